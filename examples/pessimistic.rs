@@ -28,7 +28,7 @@ async fn main() {
     let value1: Value = b"value1".to_vec();
     let key2: Key = b"key02".to_vec().into();
     let value2: Value = b"value2".to_vec();
-    let mut txn0 = client
+    let txn0 = client
         .begin_optimistic()
         .await
         .expect("Could not begin a transaction");
@@ -37,7 +37,7 @@ async fn main() {
     }
     txn0.commit().await.expect("Could not commit");
     drop(txn0);
-    let mut txn1 = client
+    let txn1 = client
         .begin_pessimistic()
         .await
         .expect("Could not begin a transaction");
@@ -49,7 +49,7 @@ async fn main() {
     println!("{:?}", (&key1, value));
     {
         // another txn cannot write to the locked key
-        let mut txn2 = client
+        let txn2 = client
             .begin_with_options(TransactionOptions::new_optimistic().no_resolve_locks())
             .await
             .expect("Could not begin a transaction");
@@ -62,7 +62,7 @@ async fn main() {
     let value3: Value = b"value3".to_vec();
     txn1.put(key1.clone(), value3).await.unwrap();
     txn1.commit().await.unwrap();
-    let mut txn3 = client
+    let txn3 = client
         .begin_optimistic()
         .await
         .expect("Could not begin a transaction");
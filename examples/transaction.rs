@@ -6,7 +6,7 @@ use crate::common::parse_args;
 use tikv_client::{BoundRange, Config, Key, KvPair, TransactionClient as Client, Value};
 
 async fn puts(client: &Client, pairs: impl IntoIterator<Item = impl Into<KvPair>>) {
-    let mut txn = client
+    let txn = client
         .begin_optimistic()
         .await
         .expect("Could not begin a transaction");
@@ -18,7 +18,7 @@ async fn puts(client: &Client, pairs: impl IntoIterator<Item = impl Into<KvPair>
 }
 
 async fn get(client: &Client, key: Key) -> Option<Value> {
-    let mut txn = client
+    let txn = client
         .begin_optimistic()
         .await
         .expect("Could not begin a transaction");
@@ -30,7 +30,7 @@ async fn get(client: &Client, key: Key) -> Option<Value> {
 }
 
 async fn key_exists(client: &Client, key: Key) -> bool {
-    let mut txn = client
+    let txn = client
         .begin_optimistic()
         .await
         .expect("Could not begin a transaction");
@@ -45,7 +45,7 @@ async fn key_exists(client: &Client, key: Key) -> bool {
 }
 
 async fn scan(client: &Client, range: impl Into<BoundRange>, limit: u32) {
-    let mut txn = client
+    let txn = client
         .begin_optimistic()
         .await
         .expect("Could not begin a transaction");
@@ -57,7 +57,7 @@ async fn scan(client: &Client, range: impl Into<BoundRange>, limit: u32) {
 }
 
 async fn dels(client: &Client, keys: impl IntoIterator<Item = Key>) {
-    let mut txn = client
+    let txn = client
         .begin_optimistic()
         .await
         .expect("Could not begin a transaction");
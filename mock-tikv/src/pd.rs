@@ -139,11 +139,15 @@ impl Pd for MockPd {
 
     fn get_all_stores(
         &mut self,
-        _ctx: ::grpcio::RpcContext,
-        _req: GetAllStoresRequest,
-        _sink: ::grpcio::UnarySink<GetAllStoresResponse>,
+        ctx: ::grpcio::RpcContext,
+        req: GetAllStoresRequest,
+        sink: ::grpcio::UnarySink<GetAllStoresResponse>,
     ) {
-        todo!()
+        let resp = GetAllStoresResponse {
+            stores: vec![Self::store()],
+            ..Default::default()
+        };
+        spawn_unary_success!(ctx, req, resp, sink);
     }
 
     fn store_heartbeat(
@@ -271,6 +271,43 @@ impl Tikv for MockTikv {
         todo!()
     }
 
+    fn raw_compare_and_swap(
+        &mut self,
+        ctx: grpcio::RpcContext,
+        mut req: tikv_client_proto::kvrpcpb::RawCasRequest,
+        sink: grpcio::UnarySink<tikv_client_proto::kvrpcpb::RawCasResponse>,
+    ) {
+        let previous = if req.get_previous_not_exist() {
+            None
+        } else {
+            Some(req.take_previous_value())
+        };
+        let (previous, succeed) =
+            self.inner
+                .raw_compare_and_swap(req.take_key(), previous, req.take_value());
+        let mut resp = RawCasResponse::default();
+        resp.set_succeed(succeed);
+        match previous {
+            Some(value) => resp.set_previous_value(value),
+            None => resp.set_previous_not_exist(true),
+        }
+        spawn_unary_success!(ctx, req, resp, sink);
+    }
+
+    fn raw_get_key_ttl(
+        &mut self,
+        ctx: grpcio::RpcContext,
+        req: tikv_client_proto::kvrpcpb::RawGetKeyTtlRequest,
+        sink: grpcio::UnarySink<tikv_client_proto::kvrpcpb::RawGetKeyTtlResponse>,
+    ) {
+        // The mock store doesn't expire keys, so existing keys have no TTL.
+        let mut resp = RawGetKeyTtlResponse::default();
+        if self.inner.raw_get(req.get_key()).is_none() {
+            resp.set_not_found(true);
+        }
+        spawn_unary_success!(ctx, req, resp, sink);
+    }
+
     fn ver_get(
         &mut self,
         _ctx: grpcio::RpcContext,
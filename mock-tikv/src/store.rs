@@ -59,6 +59,23 @@ impl KvStore {
         );
     }
 
+    /// Put `value` if the current value of `key` is `previous`. Returns the value before the
+    /// operation and whether `value` was put.
+    pub fn raw_compare_and_swap(
+        &self,
+        key: Vec<u8>,
+        previous: Option<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> (Option<Vec<u8>>, bool) {
+        let mut data = self.data.write().unwrap();
+        let current = data.get(&key).cloned();
+        if current != previous {
+            return (current, false);
+        }
+        data.insert(key, value);
+        (current, true)
+    }
+
     pub fn raw_delete(&self, key: &[u8]) {
         let mut data = self.data.write().unwrap();
         data.remove(key);
@@ -2,8 +2,10 @@
 
 // https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/
 
+use crate::Error;
 use rand::{thread_rng, Rng};
-use std::time::Duration;
+use serde_derive::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
 
 pub const DEFAULT_REGION_BACKOFF: Backoff = Backoff::no_jitter_backoff(2, 500, 10);
 pub const OPTIMISTIC_BACKOFF: Backoff = Backoff::no_jitter_backoff(2, 500, 10);
@@ -12,7 +14,8 @@ pub const PESSIMISTIC_BACKOFF: Backoff = Backoff::no_backoff();
 /// When a request is retried, we can backoff for some time to avoid saturating the network.
 ///
 /// `Backoff` is an object which determines how long to wait for.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Backoff {
     kind: BackoffKind,
     current_attempts: u32,
@@ -182,7 +185,8 @@ impl Backoff {
 }
 
 /// The pattern for computing backoff times.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 enum BackoffKind {
     None,
     NoJitter,
@@ -191,6 +195,23 @@ enum BackoffKind {
     DecorrelatedJitter,
 }
 
+/// Why a request is retried, as reported to backoff observers, see
+/// [`RawClient::with_backoff_observer`](crate::RawClient::with_backoff_observer).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryKind {
+    /// The region information of the client was outdated, or the region was unavailable.
+    Region,
+    /// Keys were locked by other transactions.
+    Lock,
+    /// A request to PD failed and is retried after reconnecting, or reconnecting to PD failed.
+    /// The attempt counts the requests, or the reconnects after a failed request.
+    Pd,
+}
+
+/// Called before each retry of a request with why it is retried, the number of the attempt which
+/// failed (starting at 1), how long the request waits before the retry, and the error.
+pub(crate) type BackoffObserver = Arc<dyn Fn(RetryKind, u32, Duration, &Error) + Send + Sync>;
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,420 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Generating load against a cluster and measuring the latency of the client.
+//!
+//! A [`Workload`](Workload) is a mix of operations on a key space of fixed size: raw point gets
+//! and puts, raw scans, and optimistic transactions reading and writing a key. The YCSB core
+//! workloads are provided as presets. [`run`](run) loads the key space, then runs the workload
+//! from concurrent workers for a while and reports the latency percentiles of each operation.
+//!
+//! The module is enabled by the `bench` feature, which also builds the `tikv-bench` binary.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{RawClient, TransactionClient, bench::{run, BenchOptions, Workload}};
+//! # use std::time::Duration;
+//! # futures::executor::block_on(async {
+//! let raw = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let txn = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let options = BenchOptions::new(Workload::ycsb_b()).duration(Duration::from_secs(30));
+//! let report = run(raw, txn, options).await.unwrap();
+//! println!("{}", report);
+//! # });
+//! ```
+
+use crate::{RawClient, Result, TransactionClient};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The number of pairs written per request when loading the key space.
+const LOAD_BATCH_SIZE: u64 = 1000;
+
+/// An operation of a workload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operation {
+    /// A raw get of a random key.
+    Get,
+    /// A raw put of a random key.
+    Put,
+    /// A raw scan from a random key.
+    Scan,
+    /// An optimistic transaction reading and writing a random key.
+    ReadModifyWrite,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Get => "get",
+            Operation::Put => "put",
+            Operation::Scan => "scan",
+            Operation::ReadModifyWrite => "read-modify-write",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A mix of operations on a key space.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Workload {
+    /// Operations with their relative weights.
+    mix: Vec<(Operation, u32)>,
+    keys: u64,
+    value_size: usize,
+    scan_limit: u32,
+}
+
+impl Workload {
+    /// A workload of the operations of `mix`, each chosen with a probability proportional to its
+    /// weight, on 100,000 keys with values of 100 bytes.
+    pub fn new(mix: Vec<(Operation, u32)>) -> Workload {
+        assert!(
+            mix.iter().any(|(_, weight)| *weight > 0),
+            "a workload needs an operation"
+        );
+        Workload {
+            mix,
+            keys: 100_000,
+            value_size: 100,
+            scan_limit: 100,
+        }
+    }
+
+    /// YCSB workload A, update heavy: 50% gets and 50% puts.
+    pub fn ycsb_a() -> Workload {
+        Workload::new(vec![(Operation::Get, 50), (Operation::Put, 50)])
+    }
+
+    /// YCSB workload B, read mostly: 95% gets and 5% puts.
+    pub fn ycsb_b() -> Workload {
+        Workload::new(vec![(Operation::Get, 95), (Operation::Put, 5)])
+    }
+
+    /// YCSB workload C, read only.
+    pub fn ycsb_c() -> Workload {
+        Workload::new(vec![(Operation::Get, 100)])
+    }
+
+    /// YCSB workload E, short ranges: 95% scans and 5% puts.
+    pub fn ycsb_e() -> Workload {
+        Workload::new(vec![(Operation::Scan, 95), (Operation::Put, 5)])
+    }
+
+    /// YCSB workload F, read-modify-write: 50% gets and 50% transactions reading and writing a
+    /// key.
+    pub fn ycsb_f() -> Workload {
+        Workload::new(vec![(Operation::Get, 50), (Operation::ReadModifyWrite, 50)])
+    }
+
+    /// Set the number of keys of the key space.
+    pub fn keys(mut self, keys: u64) -> Workload {
+        self.keys = keys.max(1);
+        self
+    }
+
+    /// Set the size of written values in bytes.
+    pub fn value_size(mut self, size: usize) -> Workload {
+        self.value_size = size;
+        self
+    }
+
+    /// Set the number of pairs read by a scan.
+    pub fn scan_limit(mut self, limit: u32) -> Workload {
+        self.scan_limit = limit;
+        self
+    }
+
+    fn choose(&self, rng: &mut impl Rng) -> Operation {
+        let total: u32 = self.mix.iter().map(|(_, weight)| weight).sum();
+        let mut choice = rng.gen_range(0..total);
+        for (operation, weight) in &self.mix {
+            if choice < *weight {
+                return *operation;
+            }
+            choice -= weight;
+        }
+        unreachable!()
+    }
+
+    fn key(&self, index: u64) -> Vec<u8> {
+        format!("bench/{:012}", index).into_bytes()
+    }
+}
+
+/// Options of a run of a workload.
+#[derive(Clone, Debug)]
+pub struct BenchOptions {
+    workload: Workload,
+    concurrency: usize,
+    duration: Duration,
+    load: bool,
+}
+
+impl BenchOptions {
+    /// Run `workload` from 16 workers for 10 seconds, after loading the key space.
+    pub fn new(workload: Workload) -> BenchOptions {
+        BenchOptions {
+            workload,
+            concurrency: 16,
+            duration: Duration::from_secs(10),
+            load: true,
+        }
+    }
+
+    /// Set the number of workers, each running one operation at a time.
+    pub fn concurrency(mut self, concurrency: usize) -> BenchOptions {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set how long the workload runs, not including the load.
+    pub fn duration(mut self, duration: Duration) -> BenchOptions {
+        self.duration = duration;
+        self
+    }
+
+    /// Don't load the key space, e.g. because a previous run did.
+    pub fn no_load(mut self) -> BenchOptions {
+        self.load = false;
+        self
+    }
+}
+
+/// The latencies of the operations of one kind.
+#[derive(Clone, Debug, Default)]
+pub struct OperationStats {
+    latencies: Vec<Duration>,
+    errors: u64,
+}
+
+impl OperationStats {
+    /// The number of operations which succeeded.
+    pub fn count(&self) -> u64 {
+        self.latencies.len() as u64
+    }
+
+    /// The number of operations which failed.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// The latency below which `percentile` percent of successful operations completed.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::default();
+        }
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    fn record(&mut self, result: Result<()>, latency: Duration) {
+        match result {
+            Ok(()) => self.latencies.push(latency),
+            Err(e) => {
+                debug!(target: "tikv_client::bench", error = %e, "benchmark operation failed");
+                self.errors += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: OperationStats) {
+        self.latencies.extend(other.latencies);
+        self.errors += other.errors;
+    }
+}
+
+/// The outcome of [`run`](run).
+#[derive(Clone, Debug)]
+pub struct Report {
+    elapsed: Duration,
+    operations: BTreeMap<Operation, OperationStats>,
+}
+
+impl Report {
+    /// How long the workload ran.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The statistics of each operation of the workload which ran at least once.
+    pub fn operations(&self) -> &BTreeMap<Operation, OperationStats> {
+        &self.operations
+    }
+
+    /// Successful operations per second.
+    pub fn throughput(&self) -> f64 {
+        let count: u64 = self.operations.values().map(OperationStats::count).sum();
+        count as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:.1} ops/s over {:.1}s",
+            self.throughput(),
+            self.elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "{:<18} {:>10} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            "operation", "count", "errors", "p50", "p95", "p99", "max"
+        )?;
+        for (operation, stats) in &self.operations {
+            writeln!(
+                f,
+                "{:<18} {:>10} {:>8} {:>10?} {:>10?} {:>10?} {:>10?}",
+                operation.to_string(),
+                stats.count(),
+                stats.errors(),
+                stats.percentile(50.0),
+                stats.percentile(95.0),
+                stats.percentile(99.0),
+                stats.percentile(100.0)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Load the key space of the workload of `options` unless disabled, then run the workload with
+/// `raw` and `txn` and report the latencies of its operations.
+///
+/// Failed operations are counted rather than failing the run; only a failed load fails it.
+pub async fn run(raw: RawClient, txn: TransactionClient, options: BenchOptions) -> Result<Report> {
+    if options.load {
+        load(&raw, &options.workload).await?;
+    }
+
+    let start = Instant::now();
+    let deadline = start + options.duration;
+    let workers = (0..options.concurrency).map(|worker| {
+        let worker = Worker {
+            raw: raw.clone(),
+            txn: txn.clone(),
+            workload: options.workload.clone(),
+            rng: StdRng::seed_from_u64(worker as u64),
+        };
+        tokio::spawn(worker.run(deadline))
+    });
+    let mut operations = BTreeMap::new();
+    for stats in futures::future::join_all(workers).await {
+        let stats = stats.expect("benchmark worker panicked");
+        for (operation, stats) in stats {
+            operations
+                .entry(operation)
+                .or_insert_with(OperationStats::default)
+                .merge(stats);
+        }
+    }
+    for stats in operations.values_mut() {
+        stats.latencies.sort_unstable();
+    }
+    Ok(Report {
+        elapsed: start.elapsed(),
+        operations,
+    })
+}
+
+async fn load(raw: &RawClient, workload: &Workload) -> Result<()> {
+    let value = vec![b'v'; workload.value_size];
+    let mut start = 0;
+    while start < workload.keys {
+        let end = (start + LOAD_BATCH_SIZE).min(workload.keys);
+        raw.batch_put((start..end).map(|i| (workload.key(i), value.clone())))
+            .await?;
+        start = end;
+    }
+    Ok(())
+}
+
+struct Worker {
+    raw: RawClient,
+    txn: TransactionClient,
+    workload: Workload,
+    rng: StdRng,
+}
+
+impl Worker {
+    async fn run(mut self, deadline: Instant) -> BTreeMap<Operation, OperationStats> {
+        let mut stats: BTreeMap<Operation, OperationStats> = BTreeMap::new();
+        while Instant::now() < deadline {
+            let operation = self.workload.choose(&mut self.rng);
+            let key = self.workload.key(self.rng.gen_range(0..self.workload.keys));
+            let start = Instant::now();
+            let result = self.execute(operation, key).await;
+            stats
+                .entry(operation)
+                .or_default()
+                .record(result, start.elapsed());
+        }
+        stats
+    }
+
+    async fn execute(&self, operation: Operation, key: Vec<u8>) -> Result<()> {
+        let value = vec![b'v'; self.workload.value_size];
+        match operation {
+            Operation::Get => self.raw.get(key).await.map(drop),
+            Operation::Put => self.raw.put(key, value).await,
+            Operation::Scan => self
+                .raw
+                .scan(key.., self.workload.scan_limit)
+                .await
+                .map(drop),
+            Operation::ReadModifyWrite => {
+                let txn = self.txn.begin_optimistic().await?;
+                let result = async {
+                    txn.get(key.clone()).await?;
+                    txn.put(key, value).await
+                }
+                .await;
+                match result {
+                    Ok(()) => txn.commit().await.map(drop),
+                    Err(e) => {
+                        txn.rollback().await?;
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_choose() {
+        let workload = Workload::new(vec![(Operation::Get, 0), (Operation::Put, 1)]);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!((0..100).all(|_| workload.choose(&mut rng) == Operation::Put));
+
+        let workload = Workload::ycsb_a();
+        let gets = (0..1000)
+            .filter(|_| workload.choose(&mut rng) == Operation::Get)
+            .count();
+        assert!(gets > 400 && gets < 600);
+    }
+
+    #[test]
+    fn test_percentile() {
+        let stats = OperationStats {
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+            errors: 0,
+        };
+        assert_eq!(stats.percentile(50.0), Duration::from_millis(50));
+        assert_eq!(stats.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(stats.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(stats.percentile(0.0), Duration::from_millis(1));
+        assert_eq!(
+            OperationStats::default().percentile(50.0),
+            Duration::default()
+        );
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Runs a workload of the `bench` module against a cluster and prints the latencies.
+//!
+//! ```text
+//! tikv-bench --pd 127.0.0.1:2379 --workload b --duration 30 --concurrency 64
+//! ```
+
+use clap::{crate_version, App, Arg};
+use std::time::Duration;
+use tikv_client::{
+    bench::{run, BenchOptions, Workload},
+    Config, RawClient, Result, TransactionClient,
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let matches = App::new("tikv-bench")
+        .version(crate_version!())
+        .author("The TiKV Project Authors")
+        .about("Generates load against a TiKV cluster and reports latency percentiles")
+        .arg(
+            Arg::with_name("pd")
+                .long("pd")
+                .value_name("PD_URL")
+                .help("Sets PD endpoints, separated by `,`")
+                .takes_value(true)
+                .multiple(true)
+                .value_delimiter(",")
+                .default_value("localhost:2379"),
+        )
+        .arg(
+            Arg::with_name("ca")
+                .long("ca")
+                .value_name("CA_PATH")
+                .help("Sets the CA. Must be used with --cert and --key")
+                .takes_value(true)
+                .requires("cert"),
+        )
+        .arg(
+            Arg::with_name("cert")
+                .long("cert")
+                .value_name("CERT_PATH")
+                .help("Sets the certificate. Must be used with --ca and --key")
+                .takes_value(true)
+                .requires("key"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .value_name("KEY_PATH")
+                .help("Sets the private key. Must be used with --ca and --cert")
+                .takes_value(true)
+                .requires("ca"),
+        )
+        .arg(
+            Arg::with_name("workload")
+                .long("workload")
+                .help("Sets the YCSB workload")
+                .possible_values(&["a", "b", "c", "e", "f"])
+                .default_value("a"),
+        )
+        .arg(
+            Arg::with_name("keys")
+                .long("keys")
+                .help("Sets the number of keys")
+                .default_value("100000"),
+        )
+        .arg(
+            Arg::with_name("value-size")
+                .long("value-size")
+                .help("Sets the size of values in bytes")
+                .default_value("100"),
+        )
+        .arg(
+            Arg::with_name("scan-limit")
+                .long("scan-limit")
+                .help("Sets the number of pairs read by a scan")
+                .default_value("100"),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .help("Sets the number of concurrent workers")
+                .default_value("16"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .long("duration")
+                .help("Sets how long the workload runs in seconds")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("no-load")
+                .long("no-load")
+                .help("Doesn't load the keys before running the workload"),
+        )
+        .get_matches();
+
+    let number = |name: &str| -> u64 {
+        let value = matches.value_of(name).unwrap();
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("--{} must be a number, got {:?}", name, value))
+    };
+    let workload = match matches.value_of("workload").unwrap() {
+        "a" => Workload::ycsb_a(),
+        "b" => Workload::ycsb_b(),
+        "c" => Workload::ycsb_c(),
+        "e" => Workload::ycsb_e(),
+        _ => Workload::ycsb_f(),
+    }
+    .keys(number("keys"))
+    .value_size(number("value-size") as usize)
+    .scan_limit(number("scan-limit") as u32);
+    let mut options = BenchOptions::new(workload)
+        .concurrency(number("concurrency") as usize)
+        .duration(Duration::from_secs(number("duration")));
+    if matches.is_present("no-load") {
+        options = options.no_load();
+    }
+
+    let pd: Vec<String> = matches.values_of("pd").unwrap().map(String::from).collect();
+    let config = match (
+        matches.value_of("ca"),
+        matches.value_of("cert"),
+        matches.value_of("key"),
+    ) {
+        (Some(ca), Some(cert), Some(key)) => Config::default().with_security(ca, cert, key),
+        _ => Config::default(),
+    };
+    let raw = RawClient::new_with_config(pd.clone(), config.clone()).await?;
+    let txn = TransactionClient::new_with_config(pd, config).await?;
+    let report = run(raw, txn, options).await?;
+    print!("{}", report);
+    Ok(())
+}
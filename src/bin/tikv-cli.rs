@@ -0,0 +1,289 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A command line tool for reading, writing and administering a cluster, built on the public API
+//! of the client.
+//!
+//! Keys and values are given as UTF-8 strings, and printed as UTF-8 where possible. An empty
+//! start or end key leaves that end of a range unbounded.
+//!
+//! ```text
+//! tikv-cli --pd 127.0.0.1:2379 raw put hello world
+//! tikv-cli --pd 127.0.0.1:2379 txn scan a z --limit 10
+//! tikv-cli --pd 127.0.0.1:2379 scan-locks
+//! tikv-cli --pd 127.0.0.1:2379 cluster
+//! ```
+
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
+use tikv_client::{
+    BoundRange, Config, Key, KvPair, RawClient, Result, TimestampExt, TransactionClient, Value,
+};
+
+const DEFAULT_LIMIT: &str = "100";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let key = || Arg::with_name("key").required(true);
+    let value = || Arg::with_name("value").required(true);
+    let range = || {
+        [
+            Arg::with_name("start").default_value(""),
+            Arg::with_name("end").default_value(""),
+        ]
+    };
+    let limit = || {
+        Arg::with_name("limit")
+            .long("limit")
+            .takes_value(true)
+            .default_value(DEFAULT_LIMIT)
+    };
+    let kv_commands = |name: &'static str, about: &'static str| {
+        SubCommand::with_name(name)
+            .about(about)
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("get").arg(key()))
+            .subcommand(SubCommand::with_name("put").arg(key()).arg(value()))
+            .subcommand(SubCommand::with_name("delete").arg(key()))
+            .subcommand(SubCommand::with_name("scan").args(&range()).arg(limit()))
+    };
+
+    let matches = App::new("tikv-cli")
+        .version(crate_version!())
+        .author("The TiKV Project Authors")
+        .about("Reads, writes and administers a TiKV cluster")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("pd")
+                .long("pd")
+                .value_name("PD_URL")
+                .help("Sets PD endpoints, separated by `,`")
+                .takes_value(true)
+                .multiple(true)
+                .value_delimiter(",")
+                .default_value("localhost:2379"),
+        )
+        .arg(
+            Arg::with_name("ca")
+                .long("ca")
+                .value_name("CA_PATH")
+                .help("Sets the CA. Must be used with --cert and --key")
+                .takes_value(true)
+                .requires("cert"),
+        )
+        .arg(
+            Arg::with_name("cert")
+                .long("cert")
+                .value_name("CERT_PATH")
+                .help("Sets the certificate. Must be used with --ca and --key")
+                .takes_value(true)
+                .requires("key"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .value_name("KEY_PATH")
+                .help("Sets the private key. Must be used with --ca and --cert")
+                .takes_value(true)
+                .requires("ca"),
+        )
+        .subcommand(kv_commands("raw", "Raw key-value operations"))
+        .subcommand(kv_commands("txn", "Transactional key-value operations"))
+        .subcommand(
+            SubCommand::with_name("scan-locks")
+                .about("Lists the locks of transactions started before now")
+                .args(&range())
+                .arg(limit()),
+        )
+        .subcommand(
+            SubCommand::with_name("resolve-locks")
+                .about("Resolves the expired locks of transactions started before now")
+                .args(&range()),
+        )
+        .subcommand(
+            SubCommand::with_name("split")
+                .about("Splits regions so that each key starts a region")
+                .arg(key().multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("checksum")
+                .about("Computes the checksum of a range at the current timestamp")
+                .args(&range()),
+        )
+        .subcommand(
+            SubCommand::with_name("cluster")
+                .about("Shows the stores, hot regions and current timestamp of the cluster"),
+        )
+        .get_matches();
+
+    let pd: Vec<String> = matches.values_of("pd").unwrap().map(String::from).collect();
+    let config = match (
+        matches.value_of("ca"),
+        matches.value_of("cert"),
+        matches.value_of("key"),
+    ) {
+        (Some(ca), Some(cert), Some(key)) => Config::default().with_security(ca, cert, key),
+        _ => Config::default(),
+    };
+
+    match matches.subcommand() {
+        ("raw", Some(matches)) => {
+            let client = RawClient::new_with_config(pd, config).await?;
+            raw(&client, matches).await
+        }
+        ("txn", Some(matches)) => {
+            let client = TransactionClient::new_with_config(pd, config).await?;
+            txn(&client, matches).await
+        }
+        (command, Some(matches)) => {
+            let client = TransactionClient::new_with_config(pd, config).await?;
+            admin(&client, command, matches).await
+        }
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+async fn raw(client: &RawClient, matches: &ArgMatches<'_>) -> Result<()> {
+    match matches.subcommand() {
+        ("get", Some(args)) => print_value(client.get(key_arg(args, "key")).await?),
+        ("put", Some(args)) => {
+            client
+                .put(key_arg(args, "key"), args.value_of("value").unwrap())
+                .await?
+        }
+        ("delete", Some(args)) => client.delete(key_arg(args, "key")).await?,
+        ("scan", Some(args)) => print_pairs(client.scan(range_arg(args), limit_arg(args)).await?),
+        _ => unreachable!("a subcommand is required"),
+    }
+    Ok(())
+}
+
+async fn txn(client: &TransactionClient, matches: &ArgMatches<'_>) -> Result<()> {
+    let txn = client.begin_optimistic().await?;
+    let result = match matches.subcommand() {
+        ("get", Some(args)) => txn.get(key_arg(args, "key")).await.map(print_value),
+        ("put", Some(args)) => {
+            txn.put(key_arg(args, "key"), args.value_of("value").unwrap())
+                .await
+        }
+        ("delete", Some(args)) => txn.delete(key_arg(args, "key")).await,
+        ("scan", Some(args)) => txn
+            .scan(range_arg(args), limit_arg(args))
+            .await
+            .map(|pairs| print_pairs(pairs.collect())),
+        _ => unreachable!("a subcommand is required"),
+    };
+    match result {
+        Ok(()) => txn.commit().await.map(drop),
+        Err(e) => {
+            txn.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+async fn admin(client: &TransactionClient, command: &str, args: &ArgMatches<'_>) -> Result<()> {
+    match command {
+        "scan-locks" => {
+            let now = client.current_timestamp().await?;
+            for lock in client
+                .scan_locks(now, range_arg(args), limit_arg(args))
+                .await?
+            {
+                println!(
+                    "{}\tprimary {}\tstart_ts {}\tttl {}ms",
+                    display((&lock.key()).into()),
+                    display((&lock.primary_key()).into()),
+                    lock.start_timestamp().version(),
+                    lock.ttl()
+                );
+            }
+        }
+        "resolve-locks" => {
+            let now = client.current_timestamp().await?;
+            let locks = client.scan_locks(now, range_arg(args), u32::MAX).await?;
+            let count = locks.len();
+            let resolved = client.resolve_locks(locks).await?;
+            println!(
+                "found {} locks, {}",
+                count,
+                if resolved {
+                    "all resolved"
+                } else {
+                    "some still alive"
+                }
+            );
+        }
+        "split" => {
+            let keys: Vec<String> = args.values_of("key").unwrap().map(String::from).collect();
+            client.split_region(keys).await?;
+        }
+        "checksum" => {
+            let now = client.current_timestamp().await?;
+            let checksum = client.checksum(range_arg(args), now).await?;
+            println!(
+                "crc64_xor {:#x}\tkvs {}\tbytes {}",
+                checksum.crc64_xor, checksum.total_kvs, checksum.total_bytes
+            );
+        }
+        "cluster" => {
+            println!(
+                "current timestamp {}",
+                client.current_timestamp().await?.version()
+            );
+            for store in client.stores().await? {
+                let labels: Vec<String> = store
+                    .labels()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect();
+                println!(
+                    "store {}\t{}\t{}\t{}\t{}",
+                    store.id(),
+                    store.address(),
+                    store.version(),
+                    if store.is_up() { "up" } else { "down" },
+                    labels.join(",")
+                );
+            }
+            for region in client.hot_regions().await? {
+                println!("hot region {}\t{}", region.region_id(), region);
+            }
+        }
+        _ => unreachable!("unknown subcommand {}", command),
+    }
+    Ok(())
+}
+
+fn key_arg(args: &ArgMatches<'_>, name: &str) -> Key {
+    args.value_of(name).unwrap().to_owned().into()
+}
+
+fn range_arg(args: &ArgMatches<'_>) -> BoundRange {
+    let start = key_arg(args, "start");
+    match args.value_of("end").unwrap() {
+        "" => BoundRange::range_from(start),
+        end => (start..Key::from(end.to_owned())).into(),
+    }
+}
+
+fn limit_arg(args: &ArgMatches<'_>) -> u32 {
+    let limit = args.value_of("limit").unwrap();
+    limit
+        .parse()
+        .unwrap_or_else(|_| panic!("--limit must be a number, got {:?}", limit))
+}
+
+fn display(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn print_value(value: Option<Value>) {
+    match value {
+        Some(value) => println!("{}", display(&value)),
+        None => println!("(not found)"),
+    }
+}
+
+fn print_pairs(pairs: Vec<KvPair>) {
+    for pair in pairs {
+        println!("{}\t{}", display(pair.key().into()), display(pair.value()));
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::{config::Config, pd::PdRpcClient, Result};
+use std::sync::Arc;
+
+/// A handle to a TiKV cluster which can be shared between clients.
+///
+/// Clients created from the same `Cluster` with
+/// [`RawClient::from_cluster`](crate::RawClient::from_cluster) or
+/// [`TransactionClient::from_cluster`](crate::TransactionClient::from_cluster) reuse its PD
+/// connection (including the timestamp oracle stream) and its connections to TiKV stores,
+/// instead of establishing their own.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use tikv_client::{Cluster, RawClient, TransactionClient};
+/// # use futures::prelude::*;
+/// # futures::executor::block_on(async {
+/// let cluster = Cluster::connect(vec!["192.168.0.100"]).await.unwrap();
+/// let raw = RawClient::from_cluster(&cluster);
+/// let txn = TransactionClient::from_cluster(&cluster);
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct Cluster {
+    pub(crate) rpc: Arc<PdRpcClient>,
+}
+
+impl Cluster {
+    /// Connect to a TiKV cluster.
+    ///
+    /// It's important to **include more than one PD endpoint** (include all, if possible!)
+    /// This helps avoid having a *single point of failure*.
+    pub async fn connect<S: Into<String>>(pd_endpoints: Vec<S>) -> Result<Cluster> {
+        Self::connect_with_config(pd_endpoints, Config::default()).await
+    }
+
+    /// Connect to a TiKV cluster with a custom [`Config`](Config).
+    pub async fn connect_with_config<S: Into<String>>(
+        pd_endpoints: Vec<S>,
+        config: Config,
+    ) -> Result<Cluster> {
+        let pd_endpoints: Vec<String> = pd_endpoints.into_iter().map(Into::into).collect();
+        let rpc = Arc::new(PdRpcClient::connect(&pd_endpoints, &config, false).await?);
+        Ok(Cluster { rpc })
+    }
+}
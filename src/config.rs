@@ -1,5 +1,9 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
+use crate::{
+    backoff::{DEFAULT_REGION_BACKOFF, OPTIMISTIC_BACKOFF, PESSIMISTIC_BACKOFF},
+    Backoff, Features, RetryOptions,
+};
 use serde_derive::{Deserialize, Serialize};
 use std::{path::PathBuf, time::Duration};
 
@@ -27,9 +31,53 @@ pub struct Config {
     pub cert_path: Option<PathBuf>,
     pub key_path: Option<PathBuf>,
     pub timeout: Duration,
+    /// Overrides the features detected from the cluster version when set.
+    pub features: Option<Features>,
+    /// A domain name whose SRV records are resolved to PD endpoints when set.
+    pub pd_srv: Option<String>,
+    /// The TLS identity used to connect to PD, instead of the one used to connect to TiKV.
+    pub pd_ca_path: Option<PathBuf>,
+    pub pd_cert_path: Option<PathBuf>,
+    pub pd_key_path: Option<PathBuf>,
+    /// A token sent to PD as a bearer token in the `authorization` metadata of every request.
+    pub pd_token: Option<String>,
+    /// Tags every request to TiKV, so TiKV can attribute load to the application.
+    pub request_source: Option<String>,
+    /// The resource group every request to TiKV is charged to, instead of the default group.
+    pub resource_group: Option<String>,
+    /// Whether to annotate the errors of requests to regions PD reports as hot.
+    pub hot_region_diagnostics: bool,
+    /// How requests retry when the region information of the client is outdated, unless
+    /// overridden by their options.
+    pub region_backoff: Backoff,
+    /// How requests retry when keys are locked, once the locks are resolved, unless overridden
+    /// by their options.
+    pub lock_backoff: Backoff,
+    /// How pessimistic transactions retry when keys are locked, unless overridden by their
+    /// options.
+    pub pessimistic_lock_backoff: Backoff,
+    /// How many times a request to PD is sent, reconnecting to PD after each failure. At least
+    /// once, even if set to 0.
+    pub pd_max_retries: usize,
+    /// How many times the client tries to reconnect to PD after a failed request. At least once,
+    /// even if set to 0.
+    pub pd_max_reconnects: usize,
+    /// The minimum interval between reconnections to PD.
+    pub pd_reconnect_interval: Duration,
+    /// The timeout of each request to PD, and of connecting to PD. Falls back to `timeout`.
+    pub pd_timeout: Option<Duration>,
+    /// How long to wait for each timestamp from PD. Falls back to `pd_timeout`.
+    pub pd_tso_timeout: Option<Duration>,
+    /// Whether to connect to PD on the first request, rather than when the client is created.
+    pub lazy_connect: bool,
 }
 
 const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+// FIXME: these numbers and how they are used are all just cargo-culted in, there
+// may be more optimal values.
+pub(crate) const DEFAULT_PD_MAX_RETRIES: usize = 10;
+pub(crate) const DEFAULT_PD_MAX_RECONNECTS: usize = 3;
+pub(crate) const DEFAULT_PD_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
 
 impl Default for Config {
     fn default() -> Self {
@@ -38,6 +86,24 @@ impl Default for Config {
             cert_path: None,
             key_path: None,
             timeout: DEFAULT_REQUEST_TIMEOUT,
+            features: None,
+            pd_srv: None,
+            pd_ca_path: None,
+            pd_cert_path: None,
+            pd_key_path: None,
+            pd_token: None,
+            request_source: None,
+            resource_group: None,
+            hot_region_diagnostics: false,
+            region_backoff: DEFAULT_REGION_BACKOFF,
+            lock_backoff: OPTIMISTIC_BACKOFF,
+            pessimistic_lock_backoff: PESSIMISTIC_BACKOFF,
+            pd_max_retries: DEFAULT_PD_MAX_RETRIES,
+            pd_max_reconnects: DEFAULT_PD_MAX_RECONNECTS,
+            pd_reconnect_interval: DEFAULT_PD_RECONNECT_INTERVAL,
+            pd_timeout: None,
+            pd_tso_timeout: None,
+            lazy_connect: false,
         }
     }
 }
@@ -68,6 +134,8 @@ impl Config {
 
     /// Set the timeout for the [`Config`](Config).
     ///
+    /// It applies to each request to TiKV, and to PD unless set otherwise by
+    /// [`with_pd_timeouts`](Config::with_pd_timeouts).
     ///
     /// # Examples
     /// ```rust
@@ -79,4 +147,258 @@ impl Config {
         self.timeout = timeout;
         self
     }
+
+    /// Set the optional features the client may use, instead of detecting them from the
+    /// versions of the TiKV stores when connecting.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::{Config, Features};
+    /// let features = Features {
+    ///     async_commit: false,
+    ///     ..Features::default()
+    /// };
+    /// let config = Config::default().with_features(features);
+    /// ```
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.features = Some(features);
+        self
+    }
+
+    /// Discover PD endpoints from the SRV records of a domain name, e.g. as published by a
+    /// service registry such as Consul.
+    ///
+    /// The records are resolved when connecting, and the endpoints found are used alongside any
+    /// endpoints passed to the client. They are resolved again whenever the client fails to
+    /// reconnect to the PD cluster, so PD nodes can be replaced without reconfiguring the client.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// let config = Config::default().with_pd_srv("pd.service.consul");
+    /// ```
+    pub fn with_pd_srv(mut self, name: impl Into<String>) -> Self {
+        self.pd_srv = Some(name.into());
+        self
+    }
+
+    /// Set the certificate authority, certificate, and key locations used to connect to PD.
+    ///
+    /// By default, PD is connected to with the same TLS identity as TiKV, set by
+    /// [`with_security`](Config::with_security). Set a distinct identity for clusters whose
+    /// control plane and data plane trust different clients.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// let config = Config::default()
+    ///     .with_security("root.ca", "tikv.cert", "tikv.key")
+    ///     .with_pd_security("root.ca", "pd.cert", "pd.key");
+    /// ```
+    pub fn with_pd_security(
+        mut self,
+        ca_path: impl Into<PathBuf>,
+        cert_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+    ) -> Self {
+        self.pd_ca_path = Some(ca_path.into());
+        self.pd_cert_path = Some(cert_path.into());
+        self.pd_key_path = Some(key_path.into());
+        self
+    }
+
+    /// Set a token to authenticate to PD with. It is sent with every request to PD as
+    /// `authorization: Bearer <token>` gRPC metadata, and never sent to TiKV.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// let config = Config::default().with_pd_token("secret");
+    /// ```
+    pub fn with_pd_token(mut self, token: impl Into<String>) -> Self {
+        self.pd_token = Some(token.into());
+        self
+    }
+
+    /// Set the source of the requests of the client, e.g. the name of the application or job.
+    ///
+    /// Every request to TiKV carries the tag in the `request_source` of its context, which TiKV
+    /// reports with its statistics so dashboards can attribute load to the source. The tag can be
+    /// overridden for some operations with `with_request_source` of the clients.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// let config = Config::default().with_request_source("billing-batch");
+    /// ```
+    pub fn with_request_source(mut self, source: impl Into<String>) -> Self {
+        self.request_source = Some(source.into());
+        self
+    }
+
+    /// Charge the requests of the client to a resource group created in PD, e.g. with
+    /// [`TransactionClient::create_resource_group`](crate::TransactionClient::create_resource_group).
+    ///
+    /// Every request to TiKV carries the name of the group in its context, and TiKV throttles and
+    /// schedules it by the request units of the group. The group can be overridden for some
+    /// operations with `with_resource_group` of the clients.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// let config = Config::default().with_resource_group("tenant_a");
+    /// ```
+    pub fn with_resource_group(mut self, group: impl Into<String>) -> Self {
+        self.resource_group = Some(group.into());
+        self
+    }
+
+    /// Annotate the errors of requests to regions PD reports as hot.
+    ///
+    /// When a request to a region fails, the hot regions reported by PD are looked up, and if the
+    /// region is hot the error is wrapped in an [`Error::HotRegion`](crate::Error::HotRegion)
+    /// describing its load. Hot regions are fetched from the HTTP API of PD in the background, at
+    /// most once a minute, so errors are annotated with the regions fetched last.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// let config = Config::default().with_hot_region_diagnostics();
+    /// ```
+    pub fn with_hot_region_diagnostics(mut self) -> Self {
+        self.hot_region_diagnostics = true;
+        self
+    }
+
+    /// Set how requests retry on outdated regions and locked keys, unless overridden by their
+    /// options, e.g. by [`TransactionOptions::retry_options`](crate::TransactionOptions::retry_options).
+    ///
+    /// The lock backoff doesn't apply to pessimistic transactions, see
+    /// [`with_pessimistic_lock_backoff`](Config::with_pessimistic_lock_backoff).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::{Backoff, Config, RetryOptions};
+    /// let config = Config::default().with_retry_options(RetryOptions::new(
+    ///     Backoff::full_jitter_backoff(2, 1000, 20),
+    ///     Backoff::no_jitter_backoff(10, 2000, 5),
+    /// ));
+    /// ```
+    pub fn with_retry_options(mut self, options: RetryOptions) -> Self {
+        self.region_backoff = options.region_backoff;
+        self.lock_backoff = options.lock_backoff;
+        self
+    }
+
+    /// Set how pessimistic transactions retry when keys are locked, unless overridden by their
+    /// options. By default, they don't.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::{Backoff, Config};
+    /// let config =
+    ///     Config::default().with_pessimistic_lock_backoff(Backoff::no_jitter_backoff(2, 500, 10));
+    /// ```
+    pub fn with_pessimistic_lock_backoff(mut self, backoff: Backoff) -> Self {
+        self.pessimistic_lock_backoff = backoff;
+        self
+    }
+
+    /// Set how requests to PD are retried: each is sent at most `max_retries` times, and after
+    /// each failure the client tries to reconnect to PD at most `max_reconnects` times, at least
+    /// `reconnect_interval` apart (default: 10 times, 3 times and 1 second). The delay between
+    /// reconnects doubles after each one, up to 16 times the interval, with random jitter.
+    ///
+    /// Clients connected to the same PD endpoints with the same PD settings share their PD
+    /// connections, and reconnect together.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// # use std::time::Duration;
+    /// let config = Config::default().with_pd_retries(20, 5, Duration::from_millis(500));
+    /// ```
+    pub fn with_pd_retries(
+        mut self,
+        max_retries: usize,
+        max_reconnects: usize,
+        reconnect_interval: Duration,
+    ) -> Self {
+        assert!(
+            max_retries > 0 && max_reconnects > 0,
+            "PD requests must be sent and reconnected at least once"
+        );
+        self.pd_max_retries = max_retries;
+        self.pd_max_reconnects = max_reconnects;
+        self.pd_reconnect_interval = reconnect_interval;
+        self
+    }
+
+    /// Set the timeouts of requests to PD, independently of the timeout of requests to TiKV set by
+    /// [`timeout`](Config::timeout): each request for regions, stores or the GC safepoint, and
+    /// each connection attempt, fails after `timeout`, and each request for a timestamp after
+    /// `tso_timeout`. A request which times out is retried as set by
+    /// [`with_pd_retries`](Config::with_pd_retries), so a stalled PD fails operations after a
+    /// bounded time rather than blocking them.
+    ///
+    /// By default, requests to PD use the timeout of requests to TiKV.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// # use std::time::Duration;
+    /// let config = Config::default()
+    ///     .with_pd_timeouts(Duration::from_millis(500), Duration::from_millis(200));
+    /// ```
+    pub fn with_pd_timeouts(mut self, timeout: Duration, tso_timeout: Duration) -> Self {
+        self.pd_timeout = Some(timeout);
+        self.pd_tso_timeout = Some(tso_timeout);
+        self
+    }
+
+    /// Connect to PD on the first request rather than when the client is created.
+    ///
+    /// By default, creating a client connects to PD and fetches the cluster ID, so a client
+    /// configured with unreachable or wrong endpoints fails to be created. A lazy client is
+    /// created without contacting the cluster, e.g. so an application can start before its
+    /// cluster, and its first request fails instead if PD can't be reached. A failed connection
+    /// is tried again by the next request.
+    ///
+    /// As the cluster isn't contacted, the features of a lazy client aren't detected from the
+    /// cluster version: they are those set with [`with_features`](Config::with_features), or the
+    /// defaults. In particular, a lazy client assumes API V1, so clients of API V2 clusters must
+    /// set [`api_v2`](crate::Features::api_v2) explicitly.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Config;
+    /// let config = Config::default().with_lazy_connect();
+    /// ```
+    pub fn with_lazy_connect(mut self) -> Self {
+        self.lazy_connect = true;
+        self
+    }
+
+    /// The timeout of requests to PD, other than for timestamps.
+    pub(crate) fn pd_timeout(&self) -> Duration {
+        self.pd_timeout.unwrap_or(self.timeout)
+    }
+
+    /// The timeout of requests to PD for timestamps.
+    pub(crate) fn pd_tso_timeout(&self) -> Duration {
+        self.pd_tso_timeout.unwrap_or_else(|| self.pd_timeout())
+    }
+
+    /// The retry options of requests which don't set their own, of pessimistic transactions if
+    /// `pessimistic` is set.
+    pub(crate) fn retry_options(&self, pessimistic: bool) -> RetryOptions {
+        RetryOptions {
+            region_backoff: self.region_backoff.clone(),
+            lock_backoff: if pessimistic {
+                self.pessimistic_lock_backoff.clone()
+            } else {
+                self.lock_backoff.clone()
+            },
+        }
+    }
 }
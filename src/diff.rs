@@ -0,0 +1,228 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Listing the keys changed by transactions between two timestamps.
+//!
+//! [`changed_keys`](changed_keys) scans the write column family of a range, where TiKV records a
+//! write for every version of a key committed by a transaction, and returns the keys whose last
+//! write committed between the two timestamps was a put or a delete. This allows exporting a range
+//! incrementally, e.g. after a full [`export`](crate::export), without running CDC: the values of
+//! the keys changed can be read from a snapshot at the later timestamp, and the keys deleted
+//! removed from the copy.
+//!
+//! The write column family is scanned by a [`RawClient`](RawClient) of the transactional cluster,
+//! which must not be set to a keyspace or codec, as keys are scanned in their MVCC encoding. The
+//! earlier timestamp must not fall behind the GC safepoint of the cluster until the scan is done,
+//! or the writes between the timestamps may have been garbage collected.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{RawClient, TransactionClient, diff::{changed_keys, ChangeKind, DiffOptions}};
+//! # futures::executor::block_on(async {
+//! let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let raw = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let from = client.current_timestamp().await.unwrap();
+//! // ...
+//! let to = client.current_timestamp().await.unwrap();
+//! let range = "user".to_owned().."uses".to_owned();
+//! for change in changed_keys(&raw, range, from, to, DiffOptions::new()).await.unwrap() {
+//!     match change.kind() {
+//!         ChangeKind::Put => println!("{} was written", change.key()),
+//!         ChangeKind::Delete => println!("{} was deleted", change.key()),
+//!     }
+//! }
+//! # });
+//! ```
+
+use crate::{
+    kv::codec::keys::{decode_bytes, decode_u64},
+    replication::key_after,
+    BoundRange, ColumnFamily, Error, Key, KvPair, RawClient, Result, Timestamp, TimestampExt,
+};
+
+const DEFAULT_BATCH_SIZE: u32 = 1024;
+
+/// How a key was changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Put,
+    Delete,
+}
+
+/// The last change of a key between two timestamps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    key: Key,
+    kind: ChangeKind,
+    commit_ts: Timestamp,
+}
+
+impl Change {
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// The commit timestamp of the transaction which made the change.
+    pub fn commit_ts(&self) -> &Timestamp {
+        &self.commit_ts
+    }
+}
+
+/// Options for [`changed_keys`](changed_keys).
+#[derive(Clone, Debug)]
+pub struct DiffOptions {
+    batch_size: u32,
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        DiffOptions::new()
+    }
+}
+
+impl DiffOptions {
+    /// Scan writes in pages of 1024.
+    pub fn new() -> DiffOptions {
+        DiffOptions {
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// The number of writes scanned at a time.
+    pub fn batch_size(mut self, batch_size: u32) -> DiffOptions {
+        assert!(batch_size > 0, "batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+}
+
+/// The keys of `range` changed by transactions committed after `from` and up to `to`, in key
+/// order, each with its last change. See the [module documentation](self).
+pub async fn changed_keys(
+    client: &RawClient,
+    range: impl Into<BoundRange>,
+    from: Timestamp,
+    to: Timestamp,
+    options: DiffOptions,
+) -> Result<Vec<Change>> {
+    let client = client.with_cf(ColumnFamily::Write);
+    let (start, end) = range.into().into_keys();
+    let mut start = start.to_encoded();
+    let end = end.map(|end| end.to_encoded());
+    let mut changes = Vec::new();
+    let mut last_key = None;
+    loop {
+        let page = client
+            .scan((start, end.clone()), options.batch_size)
+            .await?;
+        for pair in &page {
+            if let Some(change) = write_change(pair, &from, &to, &mut last_key)? {
+                changes.push(change);
+            }
+        }
+        // A page shorter than the batch size is the last one.
+        match page.last() {
+            Some(pair) if page.len() == options.batch_size as usize => {
+                start = key_after(pair.key())
+            }
+            _ => break,
+        }
+    }
+    Ok(changes)
+}
+
+/// The change recorded by a pair of the write column family, if it is the last change of its key
+/// committed after `from` and up to `to`.
+///
+/// Writes are scanned in key order, and the versions of a key from the latest, so the first change
+/// in the window is the last one. `last_key` is the key of the last change found.
+fn write_change(
+    pair: &KvPair,
+    from: &Timestamp,
+    to: &Timestamp,
+    last_key: &mut Option<Key>,
+) -> Result<Option<Change>> {
+    // The key of a write is the encoded user key followed by the bitwise negation of the commit
+    // timestamp, so that later versions come first.
+    let encoded: &[u8] = pair.key().into();
+    let (key, len) = decode_bytes(encoded)?;
+    let commit_ts = !decode_u64(&encoded[len..])?;
+    if commit_ts <= from.version() || commit_ts > to.version() {
+        return Ok(None);
+    }
+    let key = Key::from(key);
+    if last_key.as_ref() == Some(&key) {
+        return Ok(None);
+    }
+    // The value of a write starts with its type. Lock and rollback records don't change the key.
+    let kind = match pair.value().first() {
+        Some(b'P') => ChangeKind::Put,
+        Some(b'D') => ChangeKind::Delete,
+        Some(b'L') | Some(b'R') => return Ok(None),
+        _ => {
+            return Err(Error::CodecError {
+                message: format!("unknown type of write of key {}", key),
+            })
+        }
+    };
+    *last_key = Some(key.clone());
+    Ok(Some(Change {
+        key,
+        kind,
+        commit_ts: Timestamp::from_version(commit_ts),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kv::codec::keys::{encode_bytes, encode_u64};
+
+    fn write(key: &[u8], commit_ts: u64, kind: u8) -> KvPair {
+        let mut encoded = encode_bytes(key);
+        encoded.extend_from_slice(&encode_u64(!commit_ts));
+        // The start timestamp follows the type, as a varint.
+        KvPair::new(encoded, vec![kind, 1])
+    }
+
+    #[test]
+    fn test_write_change() {
+        let writes = [
+            write(b"a", 12, b'P'),
+            write(b"a", 8, b'D'),
+            write(b"b", 11, b'L'),
+            write(b"b", 9, b'D'),
+            write(b"b", 7, b'P'),
+            write(b"c", 5, b'P'),
+            write(b"d", 9, b'R'),
+        ];
+        let from = Timestamp::from_version(5);
+        let to = Timestamp::from_version(10);
+        let mut last_key = None;
+        let changes: Vec<Change> = writes
+            .iter()
+            .filter_map(|pair| write_change(pair, &from, &to, &mut last_key).unwrap())
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                Change {
+                    key: b"a".to_vec().into(),
+                    kind: ChangeKind::Delete,
+                    commit_ts: Timestamp::from_version(8),
+                },
+                Change {
+                    key: b"b".to_vec().into(),
+                    kind: ChangeKind::Delete,
+                    commit_ts: Timestamp::from_version(9),
+                },
+            ]
+        );
+
+        let invalid = write(b"a", 6, b'X');
+        assert!(write_change(&invalid, &from, &to, &mut None).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Exporting a snapshot of a range, as a light-weight logical backup.
+//!
+//! [`export_range`](export_range) scans the key-value pairs of a range at a fixed timestamp, in
+//! pages, and writes them to an [`ExportSink`](ExportSink) in key order. A [`FileSink`](FileSink)
+//! writes them to a local file, as newline-delimited JSON or CSV, with keys and values in
+//! lowercase hexadecimal:
+//!
+//! ```text
+//! {"key":"6b6579","value":"76616c7565"}
+//! ```
+//!
+//! ```text
+//! key,value
+//! 6b6579,76616c7565
+//! ```
+//!
+//! Files written by a `FileSink` can be restored with the [`import`](crate::import) module.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{TransactionClient, export::{export_range, ExportOptions, FileSink, Format}};
+//! # futures::executor::block_on(async {
+//! let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let mut sink = FileSink::create("users.ndjson", Format::Ndjson).unwrap();
+//! let range = "user".to_owned().."uses".to_owned();
+//! let summary = export_range(&client, range, &mut sink, ExportOptions::new())
+//!     .await
+//!     .unwrap();
+//! println!("exported {} pairs at {:?}", summary.exported(), summary.timestamp());
+//! # });
+//! ```
+
+use crate::{
+    replication::{hold_safepoint, key_after, release_safepoint, scan_page},
+    BoundRange, KvPair, Result, Timestamp, TransactionClient,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+const DEFAULT_BATCH_SIZE: u32 = 1024;
+
+/// A destination of exported pairs.
+pub trait ExportSink {
+    /// Write a page of pairs. Pages are written in key order.
+    fn write(&mut self, pairs: &[KvPair]) -> Result<()>;
+
+    /// Called once all pairs have been written.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The format of the pairs written by a [`FileSink`](FileSink).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object per line, with `key` and `value` fields.
+    Ndjson,
+    /// A `key,value` header, then one pair per line.
+    Csv,
+}
+
+/// Writes pairs to a file, or any other writer, in a [`Format`](Format).
+pub struct FileSink<W: Write> {
+    writer: W,
+    format: Format,
+    started: bool,
+}
+
+impl FileSink<BufWriter<File>> {
+    /// Create a file at `path`, truncating it if it exists.
+    pub fn create(path: impl AsRef<Path>, format: Format) -> Result<Self> {
+        Ok(FileSink::new(BufWriter::new(File::create(path)?), format))
+    }
+}
+
+impl<W: Write> FileSink<W> {
+    pub fn new(writer: W, format: Format) -> FileSink<W> {
+        FileSink {
+            writer,
+            format,
+            started: false,
+        }
+    }
+
+    /// The underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            if self.format == Format::Csv {
+                self.writer.write_all(b"key,value\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> ExportSink for FileSink<W> {
+    fn write(&mut self, pairs: &[KvPair]) -> Result<()> {
+        self.start()?;
+        for pair in pairs {
+            let key = to_hex(pair.key().into());
+            let value = to_hex(pair.value());
+            match self.format {
+                Format::Ndjson => {
+                    writeln!(self.writer, r#"{{"key":"{}","value":"{}"}}"#, key, value)?
+                }
+                Format::Csv => writeln!(self.writer, "{},{}", key, value)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.start()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// What an export wrote.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportSummary {
+    timestamp: Timestamp,
+    exported: u64,
+}
+
+impl ExportSummary {
+    /// The timestamp of the snapshot exported.
+    pub fn timestamp(&self) -> &Timestamp {
+        &self.timestamp
+    }
+
+    /// The number of pairs exported.
+    pub fn exported(&self) -> u64 {
+        self.exported
+    }
+}
+
+/// Options for [`export_range`](export_range).
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    batch_size: u32,
+    timestamp: Option<Timestamp>,
+    hold_safepoint: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions::new()
+    }
+}
+
+impl ExportOptions {
+    /// Export a snapshot at the current timestamp, in pages of 1024 pairs, holding back GC.
+    pub fn new() -> ExportOptions {
+        ExportOptions {
+            batch_size: DEFAULT_BATCH_SIZE,
+            timestamp: None,
+            hold_safepoint: true,
+        }
+    }
+
+    /// The number of pairs scanned and written at a time.
+    pub fn batch_size(mut self, batch_size: u32) -> ExportOptions {
+        assert!(batch_size > 0, "batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Export the snapshot at `timestamp` rather than at the current timestamp.
+    ///
+    /// The timestamp must not have fallen behind the GC safepoint of the cluster when the export
+    /// starts. GC is then held back until it is done, unless
+    /// [`hold_safepoint`](ExportOptions::hold_safepoint) is disabled.
+    pub fn timestamp(mut self, timestamp: Timestamp) -> ExportOptions {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Whether to register a service safepoint for the duration of the export, so GC doesn't
+    /// remove the versions of the snapshot while it is exported. Enabled by default; disable it if
+    /// PD doesn't allow the client to register safepoints.
+    ///
+    /// See [`TransactionClient::hold_safepoint`](TransactionClient::hold_safepoint).
+    pub fn hold_safepoint(mut self, hold: bool) -> ExportOptions {
+        self.hold_safepoint = hold;
+        self
+    }
+}
+
+/// Write the pairs of `range` in a snapshot of `client` to `sink`, in key order.
+///
+/// The sink is written to between scans, so a sink doing blocking I/O blocks the task for that
+/// time.
+pub async fn export_range(
+    client: &TransactionClient,
+    range: impl Into<BoundRange>,
+    sink: &mut impl ExportSink,
+    options: ExportOptions,
+) -> Result<ExportSummary> {
+    let timestamp = match options.timestamp {
+        Some(timestamp) => timestamp,
+        None => client.current_timestamp().await?,
+    };
+    let mut summary = ExportSummary {
+        timestamp,
+        exported: 0,
+    };
+    let safepoint =
+        hold_safepoint(client, "export", &summary.timestamp, options.hold_safepoint).await?;
+    let (mut start, end) = range.into().into_keys();
+    loop {
+        let page = scan_page(
+            client,
+            &summary.timestamp,
+            start,
+            end.clone(),
+            options.batch_size,
+        )
+        .await?;
+        sink.write(&page)?;
+        summary.exported += page.len() as u64;
+        // A page shorter than the batch size is the last one.
+        match page.last() {
+            Some(pair) if page.len() == options.batch_size as usize => {
+                start = key_after(pair.key())
+            }
+            _ => break,
+        }
+    }
+    sink.finish()?;
+    release_safepoint(safepoint).await;
+    Ok(summary)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push(DIGITS[(byte >> 4) as usize] as char);
+        hex.push(DIGITS[(byte & 0xf) as usize] as char);
+    }
+    hex
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_sink() {
+        let pairs = vec![
+            KvPair::new(b"key".to_vec(), b"value".to_vec()),
+            KvPair::new(vec![0, 255], vec![]),
+        ];
+
+        let mut sink = FileSink::new(Vec::new(), Format::Ndjson);
+        sink.write(&pairs[..1]).unwrap();
+        sink.write(&pairs[1..]).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "{\"key\":\"6b6579\",\"value\":\"76616c7565\"}\n{\"key\":\"00ff\",\"value\":\"\"}\n"
+        );
+
+        let mut sink = FileSink::new(Vec::new(), Format::Csv);
+        sink.write(&pairs).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "key,value\n6b6579,76616c7565\n00ff,\n"
+        );
+
+        // An empty export still has a header.
+        let mut sink = FileSink::new(Vec::new(), Format::Csv);
+        sink.finish().unwrap();
+        assert_eq!(sink.into_inner(), b"key,value\n");
+    }
+}
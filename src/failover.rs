@@ -0,0 +1,375 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Failing raw requests over from a primary cluster to a standby cluster.
+//!
+//! A [`FailoverClient`](FailoverClient) sends requests to the primary cluster until a number of
+//! requests in a row fail because the cluster is unavailable (e.g. gRPC errors, timeouts or
+//! exhausted retries). It then fails over: reads, and writes if enabled, are sent to the standby
+//! cluster, and the primary cluster is probed in the background. Once enough probes in a row
+//! succeed, requests are sent to the primary cluster again. A request failing over is retried on
+//! the standby cluster.
+//!
+//! The client doesn't copy data between the clusters, which is left to replication (e.g.
+//! [`copy_range`](crate::replication::copy_range) or TiCDC). Reads from the standby cluster may
+//! return stale data, and writes failed over to it are not copied back to the primary cluster.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{RawClient, failover::{FailoverClient, FailoverOptions}};
+//! # futures::executor::block_on(async {
+//! let primary = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let standby = RawClient::new(vec!["192.168.1.100"]).await.unwrap();
+//! let client = FailoverClient::new(primary, standby, FailoverOptions::new());
+//! let value = client.get("TiKV".to_owned()).await.unwrap();
+//! // Any request can be made through the client.
+//! let pairs = client
+//!     .read(|client| async move { client.scan("a".to_owned()..="z".to_owned(), 10).await })
+//!     .await
+//!     .unwrap();
+//! # });
+//! ```
+
+use crate::{BoundRange, Error, Key, KvPair, RawClient, Result, Value};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+
+const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+const DEFAULT_RECOVERY_THRESHOLD: u32 = 3;
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When a [`FailoverClient`](FailoverClient) fails over and back.
+#[derive(Clone, Debug)]
+pub struct FailoverOptions {
+    failure_threshold: u32,
+    recovery_threshold: u32,
+    probe_interval: Duration,
+    probe_key: Key,
+    fail_over_writes: bool,
+}
+
+impl Default for FailoverOptions {
+    fn default() -> FailoverOptions {
+        FailoverOptions::new()
+    }
+}
+
+impl FailoverOptions {
+    /// Fail reads over after 3 failed requests in a row, and back after 3 successful probes in a
+    /// row, probing every second.
+    pub fn new() -> FailoverOptions {
+        FailoverOptions {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            recovery_threshold: DEFAULT_RECOVERY_THRESHOLD,
+            probe_interval: DEFAULT_PROBE_INTERVAL,
+            probe_key: Key::EMPTY,
+            fail_over_writes: false,
+        }
+    }
+
+    /// How many requests to the primary cluster must fail in a row to fail over.
+    pub fn failure_threshold(mut self, threshold: u32) -> FailoverOptions {
+        assert!(threshold > 0, "failure threshold must be positive");
+        self.failure_threshold = threshold;
+        self
+    }
+
+    /// How many probes of the primary cluster must succeed in a row to fail back.
+    pub fn recovery_threshold(mut self, threshold: u32) -> FailoverOptions {
+        assert!(threshold > 0, "recovery threshold must be positive");
+        self.recovery_threshold = threshold;
+        self
+    }
+
+    /// How often the primary cluster is probed while failed over.
+    pub fn probe_interval(mut self, interval: Duration) -> FailoverOptions {
+        self.probe_interval = interval;
+        self
+    }
+
+    /// The key read to probe the primary cluster, the empty key by default.
+    pub fn probe_key(mut self, key: impl Into<Key>) -> FailoverOptions {
+        self.probe_key = key.into();
+        self
+    }
+
+    /// Also send writes to the standby cluster while failed over. By default, writes are only
+    /// sent to the primary cluster, and fail while it is unavailable.
+    pub fn fail_over_writes(mut self) -> FailoverOptions {
+        self.fail_over_writes = true;
+        self
+    }
+}
+
+/// A raw client for a primary and a standby cluster, see the [module documentation](self).
+///
+/// Cloning the client is cheap, and clones share whether they are failed over.
+#[derive(Clone)]
+pub struct FailoverClient {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    primary: RawClient,
+    standby: RawClient,
+    options: FailoverOptions,
+    health: Health,
+}
+
+impl FailoverClient {
+    pub fn new(primary: RawClient, standby: RawClient, options: FailoverOptions) -> FailoverClient {
+        FailoverClient {
+            inner: Arc::new(Inner {
+                primary,
+                standby,
+                options,
+                health: Health::default(),
+            }),
+        }
+    }
+
+    /// Whether requests are currently failed over to the standby cluster.
+    pub fn is_failed_over(&self) -> bool {
+        self.inner.health.is_failed_over()
+    }
+
+    /// Make a read with `f`, on the standby cluster while failed over.
+    pub async fn read<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn(RawClient) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.run(f, true).await
+    }
+
+    /// Make a write with `f`, on the standby cluster while failed over if
+    /// [`fail_over_writes`](FailoverOptions::fail_over_writes) is set.
+    pub async fn write<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn(RawClient) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.run(f, self.inner.options.fail_over_writes).await
+    }
+
+    /// Like [`RawClient::get`](RawClient::get).
+    pub async fn get(&self, key: impl Into<Key>) -> Result<Option<Value>> {
+        let key = key.into();
+        self.read(|client| {
+            let key = key.clone();
+            async move { client.get(key).await }
+        })
+        .await
+    }
+
+    /// Like [`RawClient::batch_get`](RawClient::batch_get).
+    pub async fn batch_get(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<KvPair>> {
+        let keys: Vec<Key> = keys.into_iter().map(Into::into).collect();
+        self.read(|client| {
+            let keys = keys.clone();
+            async move { client.batch_get(keys).await }
+        })
+        .await
+    }
+
+    /// Like [`RawClient::scan`](RawClient::scan).
+    pub async fn scan(&self, range: impl Into<BoundRange>, limit: u32) -> Result<Vec<KvPair>> {
+        let range = range.into();
+        self.read(|client| {
+            let range = range.clone();
+            async move { client.scan(range, limit).await }
+        })
+        .await
+    }
+
+    /// Like [`RawClient::put`](RawClient::put).
+    pub async fn put(&self, key: impl Into<Key>, value: impl Into<Value>) -> Result<()> {
+        let (key, value) = (key.into(), value.into());
+        self.write(|client| {
+            let (key, value) = (key.clone(), value.clone());
+            async move { client.put(key, value).await }
+        })
+        .await
+    }
+
+    /// Like [`RawClient::batch_put`](RawClient::batch_put).
+    pub async fn batch_put(
+        &self,
+        pairs: impl IntoIterator<Item = impl Into<KvPair>>,
+    ) -> Result<()> {
+        let pairs: Vec<KvPair> = pairs.into_iter().map(Into::into).collect();
+        self.write(|client| {
+            let pairs = pairs.clone();
+            async move { client.batch_put(pairs).await }
+        })
+        .await
+    }
+
+    /// Like [`RawClient::delete`](RawClient::delete).
+    pub async fn delete(&self, key: impl Into<Key>) -> Result<()> {
+        let key = key.into();
+        self.write(|client| {
+            let key = key.clone();
+            async move { client.delete(key).await }
+        })
+        .await
+    }
+
+    async fn run<T, F, Fut>(&self, f: F, may_fail_over: bool) -> Result<T>
+    where
+        F: Fn(RawClient) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let inner = &self.inner;
+        if may_fail_over && inner.health.is_failed_over() {
+            return f(inner.standby.clone()).await;
+        }
+        match f(inner.primary.clone()).await {
+            Ok(value) => {
+                inner.health.on_success();
+                Ok(value)
+            }
+            Err(e) if is_unavailable(&e) => {
+                if inner.health.on_failure(inner.options.failure_threshold) {
+                    warn!(
+                        target: "tikv_client::raw",
+                        error = %e,
+                        "primary cluster is unavailable, failing over to the standby cluster"
+                    );
+                    spawn_probe(Arc::downgrade(inner));
+                }
+                if may_fail_over && inner.health.is_failed_over() {
+                    f(inner.standby.clone()).await
+                } else {
+                    Err(e)
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Probe the primary cluster until it recovers and fail back, or until the client is dropped.
+fn spawn_probe(inner: Weak<Inner>) {
+    crate::util::task::spawn("failover-probe", async move {
+        let mut successes = 0;
+        loop {
+            let interval = match inner.upgrade() {
+                Some(inner) => inner.options.probe_interval,
+                None => return,
+            };
+            tokio::time::sleep(interval).await;
+            let inner = match inner.upgrade() {
+                Some(inner) => inner,
+                None => return,
+            };
+            match inner.primary.get(inner.options.probe_key.clone()).await {
+                Ok(_) => {
+                    successes += 1;
+                    if successes >= inner.options.recovery_threshold {
+                        info!(
+                            target: "tikv_client::raw",
+                            "primary cluster recovered, failing back"
+                        );
+                        inner.health.recover();
+                        return;
+                    }
+                }
+                Err(e) => {
+                    debug!(target: "tikv_client::raw", error = %e, "probe of primary cluster failed");
+                    successes = 0;
+                }
+            }
+        }
+    });
+}
+
+/// Whether the primary cluster is failed over, and the requests which failed in a row before.
+#[derive(Default)]
+struct Health {
+    failed_over: AtomicBool,
+    failures: AtomicU32,
+}
+
+impl Health {
+    fn is_failed_over(&self) -> bool {
+        self.failed_over.load(Ordering::SeqCst)
+    }
+
+    fn on_success(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+    }
+
+    /// Record a failure. Returns whether it fails over, i.e. it reaches `threshold` and the
+    /// cluster wasn't already failed over.
+    fn on_failure(&self, threshold: u32) -> bool {
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        failures >= threshold && !self.failed_over.swap(true, Ordering::SeqCst)
+    }
+
+    fn recover(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        self.failed_over.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Whether `error` shows the cluster to be unavailable, rather than the request to be invalid.
+fn is_unavailable(error: &Error) -> bool {
+    match error {
+        Error::Grpc(_)
+        | Error::OperationTimeout(_)
+        | Error::BackoffExhausted { .. }
+        | Error::RegionError(_)
+        | Error::LeaderNotFound { .. } => true,
+        Error::HotRegion { source, .. } => is_unavailable(source),
+        Error::MultipleErrors(errors) => errors.iter().any(is_unavailable),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let health = Health::default();
+        assert!(!health.on_failure(3));
+        assert!(!health.on_failure(3));
+        // A success resets the count.
+        health.on_success();
+        assert!(!health.on_failure(3));
+        assert!(!health.on_failure(3));
+        assert!(!health.is_failed_over());
+        assert!(health.on_failure(3));
+        assert!(health.is_failed_over());
+        // Only the first failure past the threshold fails over.
+        assert!(!health.on_failure(3));
+        health.recover();
+        assert!(!health.is_failed_over());
+        assert!(!health.on_failure(3));
+    }
+
+    #[test]
+    fn test_is_unavailable() {
+        assert!(is_unavailable(&Error::OperationTimeout(
+            Duration::from_secs(1)
+        )));
+        assert!(is_unavailable(&Error::MultipleErrors(vec![
+            Error::DuplicateKeyInsertion,
+            Error::LeaderNotFound { region_id: 1 },
+        ])));
+        assert!(!is_unavailable(&Error::DuplicateKeyInsertion));
+        assert!(!is_unavailable(&Error::CodecError {
+            message: "invalid".to_owned()
+        }));
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Gating of optional protocol features by the version of the TiKV cluster.
+
+use serde_derive::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use tikv_client_proto::metapb;
+
+/// The version of a TiKV store, as reported to PD (e.g. `5.0.1`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClusterVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl ClusterVersion {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> ClusterVersion {
+        ClusterVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// The lowest version among the given stores, ignoring tombstones and stores whose version
+    /// can't be parsed. Returns `None` if no store reports a usable version.
+    pub fn min_of_stores(stores: &[metapb::Store]) -> Option<ClusterVersion> {
+        stores
+            .iter()
+            .filter(|store| store.state != metapb::StoreState::Tombstone as i32)
+            .filter_map(|store| store.version.parse().ok())
+            .min()
+    }
+}
+
+impl FromStr for ClusterVersion {
+    type Err = ();
+
+    /// Parses `major.minor.patch`, tolerating a leading `v` and any pre-release or build suffix.
+    fn from_str(s: &str) -> Result<ClusterVersion, ()> {
+        let s = s.trim().trim_start_matches('v');
+        let s = s.split(['-', '+']).next().unwrap_or_default();
+        let mut parts = s.split('.').map(|part| part.parse::<u64>().map_err(|_| ()));
+        let major = parts.next().ok_or(())??;
+        let minor = parts.next().unwrap_or(Ok(0))?;
+        let patch = parts.next().unwrap_or(Ok(0))?;
+        Ok(ClusterVersion::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for ClusterVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The version from which TiKV supports async commit and 1PC.
+const ASYNC_COMMIT_VERSION: ClusterVersion = ClusterVersion::new(5, 0, 0);
+/// The version from which TiKV supports atomic compare and swap of raw keys.
+const RAW_CAS_VERSION: ClusterVersion = ClusterVersion::new(5, 0, 0);
+/// The version from which TiKV can be configured with API V2.
+pub(crate) const API_V2_VERSION: ClusterVersion = ClusterVersion::new(6, 1, 0);
+
+/// Optional protocol features which the client may use.
+///
+/// By default, features are detected from the versions of the TiKV stores when the client
+/// connects. Use [`Config::with_features`](crate::Config::with_features) to override detection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Features {
+    /// Async commit transactions.
+    pub async_commit: bool,
+    /// One-phase commit (1PC) transactions.
+    pub one_pc: bool,
+    /// Atomic compare and swap of raw keys, which the raw client's
+    /// [`compare_and_swap`](crate::RawClient::compare_and_swap) and the operations built on it
+    /// depend on.
+    pub raw_cas: bool,
+    /// The cluster is configured with API V2 (`storage.api-version = 2`), so raw keys are
+    /// prefixed with their mode and keyspace. Raw clients apply the prefix transparently, while
+    /// transactional clients don't support such clusters and fail with
+    /// [`UnsupportedFeature`](crate::Error::UnsupportedFeature).
+    ///
+    /// Detected from the configuration of a TiKV store, read from the HTTP API of its status
+    /// address, if the cluster is recent enough to support API V2. Creating a client fails if no
+    /// store answers, rather than guessing; set the features with
+    /// [`Config::with_features`](crate::Config::with_features) to skip detection. Lazy clients
+    /// don't detect it, and assume API V1 unless told otherwise.
+    pub api_v2: bool,
+}
+
+impl Default for Features {
+    fn default() -> Features {
+        Features {
+            async_commit: true,
+            one_pc: true,
+            raw_cas: true,
+            api_v2: false,
+        }
+    }
+}
+
+impl Features {
+    /// The features supported by a cluster of the given version. An unknown version enables
+    /// every feature, except API V2 which depends on the configuration of the cluster.
+    pub fn for_version(version: Option<ClusterVersion>) -> Features {
+        match version {
+            Some(version) => Features {
+                async_commit: version >= ASYNC_COMMIT_VERSION,
+                one_pc: version >= ASYNC_COMMIT_VERSION,
+                raw_cas: version >= RAW_CAS_VERSION,
+                api_v2: false,
+            },
+            None => Features::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!("5.0.1".parse(), Ok(ClusterVersion::new(5, 0, 1)));
+        assert_eq!("v4.0.12".parse(), Ok(ClusterVersion::new(4, 0, 12)));
+        assert_eq!("5.1.0-alpha".parse(), Ok(ClusterVersion::new(5, 1, 0)));
+        assert_eq!("5".parse(), Ok(ClusterVersion::new(5, 0, 0)));
+        assert_eq!("".parse::<ClusterVersion>(), Err(()));
+        assert_eq!("five".parse::<ClusterVersion>(), Err(()));
+    }
+
+    #[test]
+    fn test_features_for_stores() {
+        let store = |version: &str| metapb::Store {
+            version: version.to_owned(),
+            ..Default::default()
+        };
+        let stores = vec![store("5.0.1"), store("4.0.12"), store("")];
+        let version = ClusterVersion::min_of_stores(&stores);
+        assert_eq!(version, Some(ClusterVersion::new(4, 0, 12)));
+        assert!(!Features::for_version(version).async_commit);
+        assert_eq!(
+            Features::for_version(ClusterVersion::min_of_stores(&stores[..1])),
+            Features::default()
+        );
+        assert_eq!(Features::for_version(None), Features::default());
+    }
+
+    #[test]
+    fn test_feature_versions() {
+        let features = |major, minor, patch| {
+            Features::for_version(Some(ClusterVersion::new(major, minor, patch)))
+        };
+        assert!(!features(4, 0, 12).async_commit);
+        assert!(!features(4, 0, 12).one_pc);
+        assert!(!features(4, 0, 12).raw_cas);
+        assert!(features(5, 0, 0).async_commit);
+        assert!(features(5, 0, 0).one_pc);
+        assert!(features(5, 0, 0).raw_cas);
+        assert!(!features(6, 5, 0).api_v2);
+        assert!(Features::default().raw_cas);
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Restoring data exported by the [`export`](crate::export) module.
+//!
+//! [`restore`](restore) reads pairs written by a [`FileSink`](crate::export::FileSink), in
+//! either format, and writes them in batches, each in its own transaction. An unfinished restore
+//! is partially visible. Restores can be limited to a range, and throttled so they don't starve
+//! the cluster.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{TransactionClient, import::{restore_file, RestoreOptions}};
+//! # futures::executor::block_on(async {
+//! let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let options = RestoreOptions::new()
+//!     .range("user".to_owned().."uses".to_owned())
+//!     .pairs_per_second(10_000);
+//! let summary = restore_file(&client, "users.ndjson", options).await.unwrap();
+//! println!("restored {} pairs", summary.restored());
+//! # });
+//! ```
+
+use crate::{
+    export::Format, replication::write_page, BoundRange, Key, KvPair, Result, TransactionClient,
+};
+use futures_timer::Delay;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::RangeBounds,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tikv_client_common::internal_err;
+
+const DEFAULT_BATCH_SIZE: usize = 1024;
+
+/// What a restore wrote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RestoreSummary {
+    restored: u64,
+    skipped: u64,
+}
+
+impl RestoreSummary {
+    /// The number of pairs written.
+    pub fn restored(&self) -> u64 {
+        self.restored
+    }
+
+    /// The number of pairs read which were out of the range restored.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+/// Options for [`restore`](restore).
+#[derive(Clone, Debug)]
+pub struct RestoreOptions {
+    batch_size: usize,
+    range: Option<BoundRange>,
+    pairs_per_second: Option<u64>,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> RestoreOptions {
+        RestoreOptions::new()
+    }
+}
+
+impl RestoreOptions {
+    /// Restore all pairs, unthrottled, in batches of 1024 pairs.
+    pub fn new() -> RestoreOptions {
+        RestoreOptions {
+            batch_size: DEFAULT_BATCH_SIZE,
+            range: None,
+            pairs_per_second: None,
+        }
+    }
+
+    /// The number of pairs written in each transaction.
+    pub fn batch_size(mut self, batch_size: usize) -> RestoreOptions {
+        assert!(batch_size > 0, "batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Only restore the pairs in `range`.
+    pub fn range(mut self, range: impl Into<BoundRange>) -> RestoreOptions {
+        self.range = Some(range.into());
+        self
+    }
+
+    /// Write at most `pairs_per_second` pairs per second on average.
+    pub fn pairs_per_second(mut self, pairs_per_second: u64) -> RestoreOptions {
+        assert!(pairs_per_second > 0, "rate must be positive");
+        self.pairs_per_second = Some(pairs_per_second);
+        self
+    }
+}
+
+/// Restore the pairs of the file at `path` into `client`.
+pub async fn restore_file(
+    client: &TransactionClient,
+    path: impl AsRef<Path>,
+    options: RestoreOptions,
+) -> Result<RestoreSummary> {
+    restore(client, BufReader::new(File::open(path)?), options).await
+}
+
+/// Restore the pairs read from `reader` into `client`. The format is detected from the first
+/// line.
+///
+/// Pairs are written with `put`, overwriting the values of keys in `client`. Reading blocks the
+/// task, so readers should be local files or in memory.
+pub async fn restore(
+    client: &TransactionClient,
+    reader: impl BufRead,
+    options: RestoreOptions,
+) -> Result<RestoreSummary> {
+    let start = Instant::now();
+    let mut summary = RestoreSummary {
+        restored: 0,
+        skipped: 0,
+    };
+    let mut format = None;
+    let mut batch = Vec::with_capacity(options.batch_size);
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| internal_err!("failed to read line {}: {}", i + 1, e))?;
+        if line.is_empty() {
+            continue;
+        }
+        if format.is_none() && line == "key,value" {
+            format = Some(Format::Csv);
+            continue;
+        }
+        let format = *format.get_or_insert(Format::Ndjson);
+        let pair = parse_line(&line, format)
+            .ok_or_else(|| internal_err!("malformed line {}: {}", i + 1, line))?;
+        match &options.range {
+            Some(range) if !range.contains(pair.key()) => summary.skipped += 1,
+            _ => batch.push(pair),
+        }
+        if batch.len() == options.batch_size {
+            write_batch(client, &mut batch, &mut summary, start, &options).await?;
+        }
+    }
+    write_batch(client, &mut batch, &mut summary, start, &options).await?;
+    Ok(summary)
+}
+
+/// Write the pairs of `batch`, then wait until the rate of pairs written since `start` is within
+/// the limit.
+async fn write_batch(
+    client: &TransactionClient,
+    batch: &mut Vec<KvPair>,
+    summary: &mut RestoreSummary,
+    start: Instant,
+    options: &RestoreOptions,
+) -> Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    summary.restored += batch.len() as u64;
+    write_page(client, std::mem::take(batch)).await?;
+    if let Some(rate) = options.pairs_per_second {
+        let due = Duration::from_secs_f64(summary.restored as f64 / rate as f64);
+        if let Some(wait) = due.checked_sub(start.elapsed()) {
+            Delay::new(wait).await;
+        }
+    }
+    Ok(())
+}
+
+fn parse_line(line: &str, format: Format) -> Option<KvPair> {
+    let (key, value) = match format {
+        Format::Csv => {
+            let mut fields = line.split(',');
+            let pair = (fields.next()?, fields.next()?);
+            if fields.next().is_some() {
+                return None;
+            }
+            pair
+        }
+        Format::Ndjson => (json_field(line, "key")?, json_field(line, "value")?),
+    };
+    Some(KvPair(Key::from(from_hex(key)?), from_hex(value)?))
+}
+
+/// The string value of a field of a flat JSON object of hexadecimal strings.
+fn json_field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("\"{}\":\"", name);
+    let start = line.find(&pattern)? + pattern.len();
+    let len = line[start..].find('"')?;
+    Some(&line[start..start + len])
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|digits| match digits {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let pair = KvPair(Key::from(b"key".to_vec()), b"value".to_vec());
+        assert_eq!(
+            parse_line(r#"{"key":"6b6579","value":"76616c7565"}"#, Format::Ndjson),
+            Some(pair.clone())
+        );
+        assert_eq!(
+            parse_line(r#"{"value":"76616c7565", "key":"6b6579"}"#, Format::Ndjson),
+            Some(pair.clone())
+        );
+        assert_eq!(parse_line("6b6579,76616c7565", Format::Csv), Some(pair));
+        assert_eq!(
+            parse_line("00FF,", Format::Csv),
+            Some(KvPair(Key::from(vec![0, 255]), vec![]))
+        );
+
+        assert_eq!(parse_line("6b6579", Format::Csv), None);
+        assert_eq!(parse_line("6b6579,7,1", Format::Csv), None);
+        assert_eq!(parse_line("6b657,76", Format::Csv), None);
+        assert_eq!(parse_line("zz,76", Format::Csv), None);
+        assert_eq!(parse_line("+f,76", Format::Csv), None);
+        assert_eq!(parse_line(r#"{"key":"6b6579"}"#, Format::Ndjson), None);
+    }
+}
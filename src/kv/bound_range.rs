@@ -24,9 +24,13 @@ use super::Key;
 /// **Minimum key**: there is the minimum key: empty key. So a range may not be unbounded below.
 /// The unbounded lower bound in a [`Range`](Range) will be converted to an empty key.
 ///
-/// **Maximum key**: There is no limit of the maximum key. When an empty key is used as the upper bound, it means upper unbounded.
+/// **Maximum key**: There is no limit of the maximum key. When an empty key is used as the exclusive upper bound, it means upper unbounded.
 /// The unbounded upper bound in a [`Range`](Range). The range covering all keys is just `Key::EMPTY..`.
 ///
+/// **Bounds**: The bounds of a range are kept as given, e.g. `(Bound::Excluded(a), Bound::Included(b))`.
+/// As TiKV takes an inclusive start key and an exclusive end key, an excluded start or an included
+/// end is sent as the successor of the key, i.e. the key followed by a zero byte.
+///
 /// **But, you should not need to worry about all this:** Most functions which operate
 /// on ranges will accept any types which implement `Into<BoundRange>`.
 /// Common range types like `a..b`, `a..=b` has implemented `Into<BoundRange>`where `a` and `b`
@@ -68,9 +72,7 @@ pub struct BoundRange {
 }
 
 impl BoundRange {
-    /// Create a new BoundRange.
-    ///
-    /// The caller must ensure that `from` is not `Unbounded`.
+    /// Create a new BoundRange. An unbounded start is the empty key.
     fn new(from: Bound<Key>, to: Bound<Key>) -> BoundRange {
         BoundRange { from, to }
     }
@@ -92,6 +94,7 @@ impl BoundRange {
     /// # Examples
     /// ```rust
     /// use tikv_client::{BoundRange, Key, IntoOwnedRange};
+    /// use std::ops::Bound;
     /// // Exclusive
     /// let range = "a".."z";
     /// assert_eq!(
@@ -122,6 +125,18 @@ impl BoundRange {
     ///     BoundRange::from(range.into_owned()).into_keys(),
     ///     (Key::from("".to_owned()), Some(Key::from("z\0".to_owned()))),
     /// );
+    /// // Left exclusive right inclusive
+    /// let range = (Bound::Excluded("a"), Bound::Included("z"));
+    /// assert_eq!(
+    ///     range.into_owned().into_keys(),
+    ///     (Key::from("a\0".to_owned()), Some(Key::from("z\0".to_owned()))),
+    /// );
+    /// // Only the empty key
+    /// let range = ..="";
+    /// assert_eq!(
+    ///     BoundRange::from(range.into_owned()).into_keys(),
+    ///     (Key::from("".to_owned()), Some(Key::from("\0".to_owned()))),
+    /// );
     /// // Full range
     /// let range = ..;
     /// assert_eq!(
@@ -148,6 +163,175 @@ impl BoundRange {
         };
         (start, end)
     }
+
+    /// Whether the range contains no key.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::{BoundRange, Key, IntoOwnedRange};
+    /// assert!(BoundRange::from(("b".."a").into_owned()).is_empty());
+    /// assert!(BoundRange::from(("a".."a").into_owned()).is_empty());
+    /// assert!(!BoundRange::from(("a"..="a").into_owned()).is_empty());
+    /// // An empty end key is unbounded.
+    /// assert!(!BoundRange::from(("a".."").into_owned()).is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        let (start, end) = self.start_end();
+        matches!(end, Some(end) if start >= end)
+    }
+
+    /// The keys in both ranges, or `None` if there are none.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::{BoundRange, Key, IntoOwnedRange};
+    /// let range = BoundRange::from(("a".."m").into_owned());
+    /// assert_eq!(
+    ///     range.intersection(&("k"..="z").into_owned()),
+    ///     Some(("k".."m").into_owned().into()),
+    /// );
+    /// assert_eq!(range.intersection(&("m"..).into_owned()), None);
+    /// ```
+    pub fn intersection(&self, other: &BoundRange) -> Option<BoundRange> {
+        let (start, end) = self.start_end();
+        let (other_start, other_end) = other.start_end();
+        let from = if start >= other_start {
+            self.from.clone()
+        } else {
+            other.from.clone()
+        };
+        let to = if end_le(&end, &other_end) {
+            self.to.clone()
+        } else {
+            other.to.clone()
+        };
+        Some(BoundRange::new(from, to)).filter(|range| !range.is_empty())
+    }
+
+    /// The keys in either range, or `None` if the ranges neither overlap nor are adjacent, so
+    /// that their keys don't make up a single range.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::{BoundRange, Key, IntoOwnedRange};
+    /// let range = BoundRange::from(("a".."m").into_owned());
+    /// assert_eq!(
+    ///     range.union(&("m"..="z").into_owned()),
+    ///     Some(("a"..="z").into_owned().into()),
+    /// );
+    /// assert_eq!(range.union(&("n"..).into_owned()), None);
+    /// ```
+    pub fn union(&self, other: &BoundRange) -> Option<BoundRange> {
+        if other.is_empty() {
+            return Some(self.clone());
+        }
+        if self.is_empty() {
+            return Some(other.clone());
+        }
+        let (start, end) = self.start_end();
+        let (other_start, other_end) = other.start_end();
+        if !end_le(&Some(other_start.clone()), &end) || !end_le(&Some(start.clone()), &other_end) {
+            return None;
+        }
+        let from = if start <= other_start {
+            self.from.clone()
+        } else {
+            other.from.clone()
+        };
+        let to = if end_le(&other_end, &end) {
+            self.to.clone()
+        } else {
+            other.to.clone()
+        };
+        Some(BoundRange::new(from, to))
+    }
+
+    /// The keys in `self` but not in `other`: none, one or two ranges, in ascending order.
+    ///
+    /// E.g. the sub-ranges of a job which remain to be done once `other` is done.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::{BoundRange, Key, IntoOwnedRange};
+    /// # use std::ops::Bound;
+    /// let range = BoundRange::from(("a".."z").into_owned());
+    /// let after_m: BoundRange = (
+    ///     Bound::Excluded(Key::from("m".to_owned())),
+    ///     Bound::Excluded(Key::from("z".to_owned())),
+    /// )
+    ///     .into();
+    /// assert_eq!(
+    ///     range.difference(&("k"..="m").into_owned()),
+    ///     vec![("a".."k").into_owned(), after_m],
+    /// );
+    /// assert!(range.difference(&("a"..).into_owned()).is_empty());
+    /// ```
+    pub fn difference(&self, other: &BoundRange) -> Vec<BoundRange> {
+        if other.is_empty() || self.intersection(other).is_none() {
+            return if self.is_empty() {
+                Vec::new()
+            } else {
+                vec![self.clone()]
+            };
+        }
+        let before = match &other.from {
+            // Nothing is before the smallest key, and an empty exclusive end would be unbounded.
+            Bound::Included(start) if start.is_empty() => None,
+            Bound::Included(start) => Some(Bound::Excluded(start.clone())),
+            Bound::Excluded(start) => Some(Bound::Included(start.clone())),
+            Bound::Unbounded => None,
+        };
+        let after = match other.end_bound() {
+            Bound::Included(end) => Some(Bound::Excluded(end.clone())),
+            Bound::Excluded(end) => Some(Bound::Included(end.clone())),
+            Bound::Unbounded => None,
+        };
+        before
+            .and_then(|to| self.intersection(&BoundRange::new(Bound::Unbounded, to)))
+            .into_iter()
+            .chain(
+                after.and_then(|from| self.intersection(&BoundRange::new(from, Bound::Unbounded))),
+            )
+            .collect()
+    }
+
+    /// The inclusive start key and the exclusive end key of the range, `None` if it is unbounded
+    /// above.
+    fn start_end(&self) -> (Key, Option<Key>) {
+        let start = match &self.from {
+            Bound::Included(start) => start.clone(),
+            Bound::Excluded(start) => {
+                let mut start = start.clone();
+                start.push_zero();
+                start
+            }
+            Bound::Unbounded => Key::EMPTY,
+        };
+        let end = match self.end_bound() {
+            Bound::Included(end) => {
+                let mut end = end.clone();
+                end.push_zero();
+                Some(end)
+            }
+            Bound::Excluded(end) => Some(end.clone()),
+            Bound::Unbounded => None,
+        };
+        (start, end)
+    }
+
+    /// Split the range into consecutive ranges at `boundaries`, which must be in ascending order
+    /// and inside the range. The first range keeps the start bound of `self`, the last one its
+    /// end bound.
+    pub(crate) fn split_at(self, boundaries: Vec<Key>) -> Vec<BoundRange> {
+        let mut ranges = Vec::with_capacity(boundaries.len() + 1);
+        let mut from = self.from;
+        for boundary in boundaries {
+            ranges.push(BoundRange::new(from, Bound::Excluded(boundary.clone())));
+            from = Bound::Included(boundary);
+        }
+        ranges.push(BoundRange::new(from, self.to));
+        ranges
+    }
 }
 
 impl RangeBounds<Key> for BoundRange {
@@ -161,13 +345,7 @@ impl RangeBounds<Key> for BoundRange {
 
     fn end_bound(&self) -> Bound<&Key> {
         match &self.to {
-            Bound::Included(t) => {
-                if t.is_empty() {
-                    Bound::Unbounded
-                } else {
-                    Bound::Included(t)
-                }
-            }
+            Bound::Included(t) => Bound::Included(t),
             Bound::Excluded(t) => {
                 if t.is_empty() {
                     Bound::Unbounded
@@ -235,14 +413,14 @@ impl<T: Into<Key>> From<(T, Option<T>)> for BoundRange {
             Some(to) => to.into().into_upper_bound(),
         };
 
-        BoundRange::new(other.0.into().into_lower_bound(), to)
+        BoundRange::new(Bound::Included(other.0.into()), to)
     }
 }
 
 impl<T: Into<Key>> From<(T, T)> for BoundRange {
     fn from(other: (T, T)) -> BoundRange {
         BoundRange::new(
-            other.0.into().into_lower_bound(),
+            Bound::Included(other.0.into()),
             other.1.into().into_upper_bound(),
         )
     }
@@ -271,7 +449,7 @@ impl From<kvrpcpb::KeyRange> for BoundRange {
     fn from(range: kvrpcpb::KeyRange) -> Self {
         let start_key = Key::from(range.start_key);
         let end_key = Key::from(range.end_key);
-        BoundRange::new(start_key.into_lower_bound(), end_key.into_upper_bound())
+        BoundRange::new(Bound::Included(start_key), end_key.into_upper_bound())
     }
 }
 
@@ -298,6 +476,8 @@ impl From<kvrpcpb::KeyRange> for BoundRange {
 /// let r4: BoundRange = (&k1, &k2).into_owned();
 /// let r5: BoundRange = (&k1, None).into_owned();
 /// let r6: BoundRange = (&k1, Some(&k2)).into_owned();
+///
+/// let r7: BoundRange = (Bound::Excluded("s"), Bound::Included("e")).into_owned();
 /// ```
 pub trait IntoOwnedRange {
     /// Transform a borrowed range of some form into an owned `BoundRange`.
@@ -348,6 +528,21 @@ impl<T: Into<Key> + Borrow<U>, U: ToOwned<Owned = T> + ?Sized> IntoOwnedRange
     }
 }
 
+impl<T: Into<Key> + Borrow<U> + Eq, U: ToOwned<Owned = T> + ?Sized> IntoOwnedRange
+    for (Bound<&U>, Bound<&U>)
+{
+    fn into_owned(self) -> BoundRange {
+        fn to_owned<U: ToOwned + ?Sized>(bound: Bound<&U>) -> Bound<U::Owned> {
+            match bound {
+                Bound::Included(u) => Bound::Included(u.to_owned()),
+                Bound::Excluded(u) => Bound::Excluded(u.to_owned()),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        }
+        From::from((to_owned(self.0), to_owned(self.1)))
+    }
+}
+
 impl IntoOwnedRange for RangeFull {
     fn into_owned(self) -> BoundRange {
         From::from(self)
@@ -373,3 +568,36 @@ fn convert_to_bound_key<K: Into<Key>>(b: Bound<K>) -> Bound<Key> {
         Bound::Unbounded => Bound::Unbounded,
     }
 }
+
+/// Whether the exclusive end key `a` is at most `b`, `None` being unbounded.
+fn end_le(a: &Option<Key>, b: &Option<Key>) -> bool {
+    match (a, b) {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some(a), Some(b)) => a <= b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::IntoOwnedRange;
+
+    #[test]
+    fn test_difference_from_start() {
+        let range: BoundRange = ("a".."z").into_owned();
+        assert_eq!(
+            range.difference(&("".."m").into_owned()),
+            vec![("m".."z").into_owned()]
+        );
+        assert_eq!(
+            range.difference(&("a".."m").into_owned()),
+            vec![("m".."z").into_owned()]
+        );
+        assert_eq!(
+            range.difference(&(.."m").into_owned()),
+            vec![("m".."z").into_owned()]
+        );
+        assert!(range.difference(&("".."z").into_owned()).is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Order-preserving encodings for building keys out of typed parts.
+//!
+//! TiKV orders keys by comparing their bytes. The encodings in this module are memcomparable:
+//! comparing encoded values byte-wise gives the same order as comparing the original values.
+//! This makes range scans over keys made of several parts (e.g. a user id followed by a
+//! timestamp) return entries in the expected order.
+//!
+//! * Unsigned integers are encoded big-endian.
+//! * Signed integers are encoded big-endian with the sign bit flipped.
+//! * Floats are encoded so that their IEEE 754 total order is preserved.
+//! * Byte strings and strings use the [memcomparable format], so a string part never compares
+//!   against the part following a shorter string.
+//!
+//! # Examples
+//! ```rust
+//! # use tikv_client::codec::keys::{CompositeKeyBuilder, CompositeKeyDecoder};
+//! let key = CompositeKeyBuilder::new()
+//!     .push_str("user")
+//!     .push_u64(42)
+//!     .push_i64(-7)
+//!     .build();
+//!
+//! let mut decoder = CompositeKeyDecoder::new(&key);
+//! assert_eq!(decoder.read_string().unwrap(), "user");
+//! assert_eq!(decoder.read_u64().unwrap(), 42);
+//! assert_eq!(decoder.read_i64().unwrap(), -7);
+//! assert!(decoder.is_empty());
+//! ```
+//!
+//! [memcomparable format]: https://github.com/facebook/mysql-5.6/wiki/MyRocks-record-format#memcomparable-format
+
+use super::{max_encoded_bytes_size, BytesEncoder, ENC_ASC_PADDING, ENC_GROUP_SIZE, ENC_MARKER};
+use crate::{Key, Result};
+use std::convert::TryInto;
+use tikv_client_common::internal_err;
+
+const SIGN_MASK: u64 = 1 << 63;
+
+pub fn encode_u64(v: u64) -> [u8; 8] {
+    v.to_be_bytes()
+}
+
+pub fn decode_u64(data: &[u8]) -> Result<u64> {
+    let bytes: [u8; 8] = data
+        .try_into()
+        .map_err(|_| internal_err!("expected 8 bytes, got {}", data.len()))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+pub fn encode_i64(v: i64) -> [u8; 8] {
+    encode_u64(v as u64 ^ SIGN_MASK)
+}
+
+pub fn decode_i64(data: &[u8]) -> Result<i64> {
+    Ok((decode_u64(data)? ^ SIGN_MASK) as i64)
+}
+
+pub fn encode_f64(v: f64) -> [u8; 8] {
+    let bits = v.to_bits();
+    let bits = if bits & SIGN_MASK == 0 {
+        bits | SIGN_MASK
+    } else {
+        !bits
+    };
+    encode_u64(bits)
+}
+
+pub fn decode_f64(data: &[u8]) -> Result<f64> {
+    let bits = decode_u64(data)?;
+    let bits = if bits & SIGN_MASK == 0 {
+        !bits
+    } else {
+        bits & !SIGN_MASK
+    };
+    Ok(f64::from_bits(bits))
+}
+
+pub fn encode_bytes(v: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(max_encoded_bytes_size(v.len()));
+    encoded.encode_bytes(v, false).unwrap();
+    encoded
+}
+
+/// Decode a byte string at the start of `data`, returning it and the number of bytes read.
+pub fn decode_bytes(data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut decoded = Vec::new();
+    let mut offset = 0;
+    loop {
+        let group = data
+            .get(offset..offset + ENC_GROUP_SIZE + 1)
+            .ok_or_else(|| internal_err!("unexpected EOF, original key = {:?}", data))?;
+        offset += ENC_GROUP_SIZE + 1;
+        let pad_size = (ENC_MARKER - group[ENC_GROUP_SIZE]) as usize;
+        if pad_size > ENC_GROUP_SIZE {
+            return Err(internal_err!("invalid key padding"));
+        }
+        let (value, padding) = group[..ENC_GROUP_SIZE].split_at(ENC_GROUP_SIZE - pad_size);
+        if padding != &ENC_ASC_PADDING[..pad_size] {
+            return Err(internal_err!("invalid key padding"));
+        }
+        decoded.extend_from_slice(value);
+        if pad_size > 0 {
+            return Ok((decoded, offset));
+        }
+    }
+}
+
+/// Builds a key out of several parts, each encoded so that keys sort by their parts in order.
+#[derive(Clone, Debug, Default)]
+pub struct CompositeKeyBuilder {
+    buf: Vec<u8>,
+}
+
+impl CompositeKeyBuilder {
+    pub fn new() -> CompositeKeyBuilder {
+        CompositeKeyBuilder::default()
+    }
+
+    /// Start from raw bytes (e.g. a table prefix) which are not encoded.
+    pub fn with_prefix(prefix: impl Into<Vec<u8>>) -> CompositeKeyBuilder {
+        CompositeKeyBuilder { buf: prefix.into() }
+    }
+
+    pub fn push_u64(mut self, v: u64) -> Self {
+        self.buf.extend_from_slice(&encode_u64(v));
+        self
+    }
+
+    pub fn push_i64(mut self, v: i64) -> Self {
+        self.buf.extend_from_slice(&encode_i64(v));
+        self
+    }
+
+    pub fn push_f64(mut self, v: f64) -> Self {
+        self.buf.extend_from_slice(&encode_f64(v));
+        self
+    }
+
+    pub fn push_bytes(mut self, v: &[u8]) -> Self {
+        self.buf.encode_bytes(v, false).unwrap();
+        self
+    }
+
+    pub fn push_str(self, v: &str) -> Self {
+        self.push_bytes(v.as_bytes())
+    }
+
+    pub fn build(self) -> Key {
+        self.buf.into()
+    }
+}
+
+/// Reads the parts of a key built by [`CompositeKeyBuilder`](CompositeKeyBuilder), in the order
+/// they were pushed.
+#[derive(Clone, Debug)]
+pub struct CompositeKeyDecoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> CompositeKeyDecoder<'a> {
+    pub fn new(key: &'a Key) -> CompositeKeyDecoder<'a> {
+        CompositeKeyDecoder { data: &key.0 }
+    }
+
+    /// Skip raw bytes (e.g. a prefix given to
+    /// [`CompositeKeyBuilder::with_prefix`](CompositeKeyBuilder::with_prefix)).
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.take(len).map(|_| ())
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        decode_u64(self.take(8)?)
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64> {
+        decode_i64(self.take(8)?)
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64> {
+        decode_f64(self.take(8)?)
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        let (bytes, len) = decode_bytes(self.data)?;
+        self.data = &self.data[len..];
+        Ok(bytes)
+    }
+
+    pub fn read_string(&mut self) -> Result<String> {
+        String::from_utf8(self.read_bytes()?).map_err(|e| internal_err!("invalid utf8: {}", e))
+    }
+
+    /// The bytes which have not been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(internal_err!(
+                "unexpected EOF, expected {} bytes, {} remaining",
+                len,
+                self.data.len()
+            ));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_integer_order() {
+        let ints = [i64::MIN, -1000, -1, 0, 1, 1000, i64::MAX];
+        for pair in ints.windows(2) {
+            assert!(encode_i64(pair[0]) < encode_i64(pair[1]));
+        }
+        for v in ints.iter() {
+            assert_eq!(decode_i64(&encode_i64(*v)).unwrap(), *v);
+        }
+        assert!(encode_u64(255) < encode_u64(256));
+        assert!(decode_u64(&[0; 7]).is_err());
+    }
+
+    #[test]
+    fn test_float_order() {
+        let floats = [
+            f64::NEG_INFINITY,
+            -1e10,
+            -1.5,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.5,
+            1e10,
+            f64::INFINITY,
+        ];
+        for pair in floats.windows(2) {
+            assert!(encode_f64(pair[0]) < encode_f64(pair[1]));
+        }
+        for v in floats.iter() {
+            assert_eq!(decode_f64(&encode_f64(*v)).unwrap().to_bits(), v.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_composite_key() {
+        let key = |s: &str, n: u64| CompositeKeyBuilder::new().push_str(s).push_u64(n).build();
+        // A shorter string sorts first regardless of the following parts.
+        assert!(key("a", u64::MAX) < key("ab", 0));
+        assert!(key("ab", 1) < key("ab", 2));
+        assert!(key("abcdefgh", 1) < key("abcdefgh\0", 0));
+
+        let key = CompositeKeyBuilder::with_prefix(b"t".to_vec())
+            .push_bytes(b"abcdefghij")
+            .push_f64(-2.5)
+            .build();
+        let mut decoder = CompositeKeyDecoder::new(&key);
+        decoder.skip(1).unwrap();
+        assert_eq!(decoder.read_bytes().unwrap(), b"abcdefghij".to_vec());
+        assert_eq!(decoder.read_f64().unwrap(), -2.5);
+        assert!(decoder.is_empty());
+        assert!(decoder.read_u64().is_err());
+
+        let mut decoder = CompositeKeyDecoder::new(&key);
+        assert!(decoder.read_bytes().is_err());
+    }
+}
@@ -1,8 +1,13 @@
+//! Encodings of keys and values.
+
 use std::{io::Write, ptr};
 use tikv_client_common::internal_err;
 
 use crate::Result;
 
+pub mod keys;
+pub mod value;
+
 const ENC_GROUP_SIZE: usize = 8;
 const ENC_MARKER: u8 = 0xff;
 const ENC_ASC_PADDING: [u8; ENC_GROUP_SIZE] = [0; ENC_GROUP_SIZE];
@@ -11,11 +16,11 @@ const ENC_DESC_PADDING: [u8; ENC_GROUP_SIZE] = [!0; ENC_GROUP_SIZE];
 /// Returns the maximum encoded bytes size.
 ///
 /// Duplicate from components/tikv_util/src/codec/bytes.rs.
-pub fn max_encoded_bytes_size(n: usize) -> usize {
+pub(crate) fn max_encoded_bytes_size(n: usize) -> usize {
     (n / ENC_GROUP_SIZE + 1) * (ENC_GROUP_SIZE + 1)
 }
 
-pub trait BytesEncoder: Write {
+pub(crate) trait BytesEncoder: Write {
     /// Refer: <https://github.com/facebook/mysql-5.6/wiki/MyRocks-record-format#memcomparable-format>
     ///
     /// Duplicate from components/tikv_util/src/codec/bytes.rs.
@@ -70,7 +75,7 @@ fn adjust_bytes_order<'a>(bs: &'a [u8], desc: bool, buf: &'a mut [u8]) -> &'a [u
 /// Decodes bytes which are encoded by `encode_bytes` before just in place without malloc.
 ///
 /// Duplicate from components/tikv_util/src/codec/bytes.rs.
-pub fn decode_bytes_in_place(data: &mut Vec<u8>, desc: bool) -> Result<()> {
+pub(crate) fn decode_bytes_in_place(data: &mut Vec<u8>, desc: bool) -> Result<()> {
     if data.is_empty() {
         return Ok(());
     }
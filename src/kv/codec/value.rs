@@ -0,0 +1,531 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Transparent transformations of stored values.
+//!
+//! A [`ValueCodec`](ValueCodec) is applied to every value written by a client configured with
+//! it, and reversed on every value read, e.g. by
+//! [`RawClient::with_value_codec`](crate::RawClient::with_value_codec) or
+//! [`TransactionOptions::value_codec`](crate::TransactionOptions::value_codec). Codecs can be
+//! stacked by using a tuple `(A, B)`, which applies `A` then `B` on write, and reverses `B` then
+//! `A` on read.
+//!
+//! Compression and encryption can be combined, e.g. `(Compression::new(..), Encryption::new(..))`
+//! compresses values before encrypting them.
+//!
+//! A [`Checksum`](Checksum) should be applied last, so it covers the value as stored.
+//!
+//! **Warning:** values written with a codec can only be read back by clients using the same
+//! codec.
+
+use crate::{Error, KvPair, Result, Value};
+use std::{convert::TryInto, fmt, sync::Arc};
+
+/// Encode a counter as used by [`RawClient::incr`](crate::RawClient::incr): an 8-byte
+/// big-endian signed integer.
+pub fn encode_counter(counter: i64) -> Value {
+    counter.to_be_bytes().to_vec()
+}
+
+/// Decode a counter encoded by [`encode_counter`](encode_counter).
+pub fn decode_counter(value: &[u8]) -> Result<i64> {
+    let bytes: [u8; 8] = value.try_into().map_err(|_| Error::CodecError {
+        message: format!("expected an 8-byte counter, got {} bytes", value.len()),
+    })?;
+    Ok(i64::from_be_bytes(bytes))
+}
+
+/// Transforms values before they are written to TiKV, and reverses the transformation after
+/// they are read.
+pub trait ValueCodec: fmt::Debug + Send + Sync + 'static {
+    fn encode(&self, value: Value) -> Result<Value>;
+
+    fn decode(&self, value: Value) -> Result<Value>;
+}
+
+impl<A: ValueCodec, B: ValueCodec> ValueCodec for (A, B) {
+    fn encode(&self, value: Value) -> Result<Value> {
+        self.1.encode(self.0.encode(value)?)
+    }
+
+    fn decode(&self, value: Value) -> Result<Value> {
+        self.0.decode(self.1.decode(value)?)
+    }
+}
+
+/// The value codec of a client, if it has one.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SharedValueCodec(Option<Arc<dyn ValueCodec>>);
+
+impl SharedValueCodec {
+    pub fn new(codec: impl ValueCodec) -> SharedValueCodec {
+        SharedValueCodec(Some(Arc::new(codec)))
+    }
+
+    pub fn encode(&self, value: Value) -> Result<Value> {
+        match &self.0 {
+            Some(codec) => codec.encode(value),
+            None => Ok(value),
+        }
+    }
+
+    pub fn decode(&self, value: Value) -> Result<Value> {
+        match &self.0 {
+            Some(codec) => codec.decode(value),
+            None => Ok(value),
+        }
+    }
+
+    pub fn encode_pair(&self, pair: KvPair) -> Result<KvPair> {
+        Ok(KvPair(pair.0, self.encode(pair.1)?))
+    }
+
+    pub fn decode_pair(&self, pair: KvPair) -> Result<KvPair> {
+        Ok(KvPair(pair.0, self.decode(pair.1)?))
+    }
+
+    pub fn decode_pairs(&self, pairs: impl IntoIterator<Item = KvPair>) -> Result<Vec<KvPair>> {
+        pairs
+            .into_iter()
+            .map(|pair| self.decode_pair(pair))
+            .collect()
+    }
+}
+
+impl PartialEq for SharedValueCodec {
+    fn eq(&self, other: &SharedValueCodec) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "lz4_flex",
+    feature = "zstd",
+    feature = "aes-gcm",
+    feature = "crc32fast",
+    feature = "twox-hash"
+))]
+fn codec_error(e: impl fmt::Display) -> Error {
+    Error::CodecError {
+        message: e.to_string(),
+    }
+}
+
+#[cfg(any(feature = "lz4_flex", feature = "zstd"))]
+const UNCOMPRESSED: u8 = 0;
+#[cfg(feature = "lz4_flex")]
+const LZ4: u8 = 1;
+#[cfg(feature = "zstd")]
+const ZSTD: u8 = 2;
+
+/// A compression algorithm used by [`Compression`](Compression).
+#[cfg(any(feature = "lz4_flex", feature = "zstd"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    /// LZ4, requires the `lz4_flex` feature.
+    #[cfg(feature = "lz4_flex")]
+    Lz4,
+    /// Zstandard at the given level, requires the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+/// Compresses values of at least `threshold` bytes.
+///
+/// Each value is prefixed by a one byte header recording how it was compressed. Values smaller
+/// than the threshold, or which don't get smaller when compressed, are stored uncompressed.
+/// Empty values are stored and read as is.
+#[cfg(any(feature = "lz4_flex", feature = "zstd"))]
+#[derive(Clone, Debug)]
+pub struct Compression {
+    algorithm: CompressionAlgorithm,
+    threshold: usize,
+}
+
+#[cfg(any(feature = "lz4_flex", feature = "zstd"))]
+impl Compression {
+    pub fn new(algorithm: CompressionAlgorithm, threshold: usize) -> Compression {
+        Compression {
+            algorithm,
+            threshold,
+        }
+    }
+
+    fn compress(&self, value: &[u8]) -> Result<(u8, Vec<u8>)> {
+        match self.algorithm {
+            #[cfg(feature = "lz4_flex")]
+            CompressionAlgorithm::Lz4 => Ok((LZ4, lz4_flex::compress_prepend_size(value))),
+            #[cfg(feature = "zstd")]
+            CompressionAlgorithm::Zstd(level) => zstd::encode_all(value, level)
+                .map(|compressed| (ZSTD, compressed))
+                .map_err(codec_error),
+        }
+    }
+}
+
+#[cfg(any(feature = "lz4_flex", feature = "zstd"))]
+impl ValueCodec for Compression {
+    fn encode(&self, value: Value) -> Result<Value> {
+        if value.is_empty() {
+            return Ok(value);
+        }
+        if value.len() >= self.threshold {
+            let (header, compressed) = self.compress(&value)?;
+            if compressed.len() < value.len() {
+                let mut encoded = Vec::with_capacity(compressed.len() + 1);
+                encoded.push(header);
+                encoded.extend_from_slice(&compressed);
+                return Ok(encoded);
+            }
+        }
+        let mut encoded = Vec::with_capacity(value.len() + 1);
+        encoded.push(UNCOMPRESSED);
+        encoded.extend_from_slice(&value);
+        Ok(encoded)
+    }
+
+    fn decode(&self, mut value: Value) -> Result<Value> {
+        if value.is_empty() {
+            return Ok(value);
+        }
+        match value[0] {
+            UNCOMPRESSED => {
+                value.remove(0);
+                Ok(value)
+            }
+            #[cfg(feature = "lz4_flex")]
+            LZ4 => lz4_flex::decompress_size_prepended(&value[1..]).map_err(codec_error),
+            #[cfg(feature = "zstd")]
+            ZSTD => zstd::decode_all(&value[1..]).map_err(codec_error),
+            header => Err(codec_error(format!(
+                "unknown compression header {}",
+                header
+            ))),
+        }
+    }
+}
+
+/// Provides the keys used by [`Encryption`](Encryption).
+///
+/// Keys are identified by an id which is stored with each encrypted value, so keys can be
+/// rotated: new values are encrypted with the current key, while values encrypted with older
+/// keys can still be read as long as the provider returns them.
+#[cfg(feature = "aes-gcm")]
+pub trait KeyProvider: fmt::Debug + Send + Sync + 'static {
+    /// The id of the key used to encrypt new values.
+    fn current_key_id(&self) -> u32;
+
+    /// The 256-bit key with the given id, or `None` if the key is unknown.
+    fn key(&self, key_id: u32) -> Option<[u8; 32]>;
+}
+
+#[cfg(feature = "aes-gcm")]
+const KEY_ID_LEN: usize = 4;
+#[cfg(feature = "aes-gcm")]
+const NONCE_LEN: usize = 12;
+
+/// Encrypts values with AES-256-GCM, using keys from a [`KeyProvider`](KeyProvider).
+///
+/// An encrypted value consists of the big-endian `u32` key id, a random 96-bit nonce, then the
+/// ciphertext and authentication tag. The key id is authenticated along with the value. Empty
+/// values are stored and read as is.
+///
+/// Requires the `aes-gcm` feature.
+#[cfg(feature = "aes-gcm")]
+#[derive(Clone, Debug)]
+pub struct Encryption<P: KeyProvider> {
+    provider: P,
+}
+
+#[cfg(feature = "aes-gcm")]
+impl<P: KeyProvider> Encryption<P> {
+    pub fn new(provider: P) -> Encryption<P> {
+        Encryption { provider }
+    }
+
+    fn cipher(&self, key_id: u32) -> Result<aes_gcm::Aes256Gcm> {
+        use aes_gcm::aead::NewAead;
+
+        let key = self
+            .provider
+            .key(key_id)
+            .ok_or_else(|| codec_error(format!("unknown encryption key {}", key_id)))?;
+        Ok(aes_gcm::Aes256Gcm::new(&key.into()))
+    }
+}
+
+#[cfg(feature = "aes-gcm")]
+impl<P: KeyProvider> ValueCodec for Encryption<P> {
+    fn encode(&self, value: Value) -> Result<Value> {
+        use aes_gcm::aead::{Aead, Payload};
+        use rand::Rng;
+
+        if value.is_empty() {
+            return Ok(value);
+        }
+        let key_id = self.provider.current_key_id();
+        let header = key_id.to_be_bytes();
+        let nonce: [u8; NONCE_LEN] = rand::thread_rng().gen();
+        let ciphertext = self
+            .cipher(key_id)?
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &value,
+                    aad: &header,
+                },
+            )
+            .map_err(|_| codec_error("failed to encrypt value"))?;
+
+        let mut encoded = Vec::with_capacity(KEY_ID_LEN + NONCE_LEN + ciphertext.len());
+        encoded.extend_from_slice(&header);
+        encoded.extend_from_slice(&nonce);
+        encoded.extend_from_slice(&ciphertext);
+        Ok(encoded)
+    }
+
+    fn decode(&self, value: Value) -> Result<Value> {
+        use aes_gcm::aead::{Aead, Payload};
+        use std::convert::TryInto;
+
+        if value.is_empty() {
+            return Ok(value);
+        }
+        if value.len() < KEY_ID_LEN + NONCE_LEN {
+            return Err(codec_error("encrypted value is too short"));
+        }
+        let (header, rest) = value.split_at(KEY_ID_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let key_id = u32::from_be_bytes(header.try_into().unwrap());
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().unwrap();
+        self.cipher(key_id)?
+            .decrypt(
+                &nonce.into(),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .map_err(|_| codec_error("failed to decrypt value"))
+    }
+}
+
+/// A checksum algorithm used by [`Checksum`](Checksum).
+#[cfg(any(feature = "crc32fast", feature = "twox-hash"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// CRC32 (4 bytes), requires the `crc32fast` feature.
+    #[cfg(feature = "crc32fast")]
+    Crc32,
+    /// xxHash64 (8 bytes), requires the `twox-hash` feature.
+    #[cfg(feature = "twox-hash")]
+    XxHash64,
+}
+
+#[cfg(any(feature = "crc32fast", feature = "twox-hash"))]
+impl ChecksumAlgorithm {
+    fn len(self) -> usize {
+        match self {
+            #[cfg(feature = "crc32fast")]
+            ChecksumAlgorithm::Crc32 => 4,
+            #[cfg(feature = "twox-hash")]
+            ChecksumAlgorithm::XxHash64 => 8,
+        }
+    }
+
+    fn checksum(self, value: &[u8]) -> u64 {
+        match self {
+            #[cfg(feature = "crc32fast")]
+            ChecksumAlgorithm::Crc32 => crc32fast::hash(value) as u64,
+            #[cfg(feature = "twox-hash")]
+            ChecksumAlgorithm::XxHash64 => {
+                use std::hash::Hasher;
+
+                let mut hasher = twox_hash::XxHash64::with_seed(0);
+                hasher.write(value);
+                hasher.finish()
+            }
+        }
+    }
+}
+
+/// Appends a checksum to values when they are written, and verifies it when they are read.
+///
+/// A value whose checksum does not match is reported as
+/// [`Error::CorruptedValue`](crate::Error::CorruptedValue). The checksum is stored big-endian
+/// after the value. Empty values are stored and read as is.
+#[cfg(any(feature = "crc32fast", feature = "twox-hash"))]
+#[derive(Clone, Debug)]
+pub struct Checksum {
+    algorithm: ChecksumAlgorithm,
+}
+
+#[cfg(any(feature = "crc32fast", feature = "twox-hash"))]
+impl Checksum {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Checksum {
+        Checksum { algorithm }
+    }
+}
+
+#[cfg(any(feature = "crc32fast", feature = "twox-hash"))]
+impl ValueCodec for Checksum {
+    fn encode(&self, mut value: Value) -> Result<Value> {
+        if value.is_empty() {
+            return Ok(value);
+        }
+        let checksum = self.algorithm.checksum(&value).to_be_bytes();
+        value.extend_from_slice(&checksum[8 - self.algorithm.len()..]);
+        Ok(value)
+    }
+
+    fn decode(&self, mut value: Value) -> Result<Value> {
+        if value.is_empty() {
+            return Ok(value);
+        }
+        let len = self.algorithm.len();
+        if value.len() <= len {
+            return Err(codec_error("value is too short to hold a checksum"));
+        }
+        let stored = value.split_off(value.len() - len);
+        let mut expected = [0; 8];
+        expected[8 - len..].copy_from_slice(&stored);
+        let expected = u64::from_be_bytes(expected);
+        let actual = self.algorithm.checksum(&value);
+        if expected != actual {
+            return Err(Error::CorruptedValue { expected, actual });
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Suffix(u8);
+
+    impl ValueCodec for Suffix {
+        fn encode(&self, mut value: Value) -> Result<Value> {
+            value.push(self.0);
+            Ok(value)
+        }
+
+        fn decode(&self, mut value: Value) -> Result<Value> {
+            match value.pop() {
+                Some(b) if b == self.0 => Ok(value),
+                _ => Err(Error::CodecError {
+                    message: "bad suffix".to_owned(),
+                }),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stacked_codecs() {
+        let codec = SharedValueCodec::new((Suffix(1), Suffix(2)));
+        let encoded = codec.encode(b"v".to_vec()).unwrap();
+        assert_eq!(encoded, b"v\x01\x02".to_vec());
+        assert_eq!(codec.decode(encoded).unwrap(), b"v".to_vec());
+        assert!(codec.decode(b"v\x02\x01".to_vec()).is_err());
+
+        let none = SharedValueCodec::default();
+        assert_eq!(none.encode(b"v".to_vec()).unwrap(), b"v".to_vec());
+        assert_eq!(none, SharedValueCodec::default());
+        assert_ne!(none, codec);
+        assert_eq!(codec, codec.clone());
+    }
+
+    #[cfg(any(feature = "lz4_flex", feature = "zstd"))]
+    #[test]
+    fn test_compression() {
+        let mut algorithms = Vec::new();
+        #[cfg(feature = "lz4_flex")]
+        algorithms.push(CompressionAlgorithm::Lz4);
+        #[cfg(feature = "zstd")]
+        algorithms.push(CompressionAlgorithm::Zstd(3));
+
+        for algorithm in algorithms {
+            let codec = Compression::new(algorithm, 16);
+            let large = b"value".repeat(100);
+            let encoded = codec.encode(large.clone()).unwrap();
+            assert!(encoded.len() < large.len());
+            assert_ne!(encoded[0], UNCOMPRESSED);
+            assert_eq!(codec.decode(encoded).unwrap(), large);
+
+            let small = b"value".to_vec();
+            let encoded = codec.encode(small.clone()).unwrap();
+            assert_eq!(encoded[0], UNCOMPRESSED);
+            assert_eq!(codec.decode(encoded).unwrap(), small);
+
+            assert!(codec.encode(Vec::new()).unwrap().is_empty());
+            assert!(codec.decode(vec![42, 1, 2]).is_err());
+        }
+    }
+
+    #[cfg(feature = "aes-gcm")]
+    #[test]
+    fn test_encryption() {
+        #[derive(Debug)]
+        struct Keys(u32);
+
+        impl KeyProvider for Keys {
+            fn current_key_id(&self) -> u32 {
+                self.0
+            }
+
+            fn key(&self, key_id: u32) -> Option<[u8; 32]> {
+                if key_id <= 2 {
+                    Some([key_id as u8; 32])
+                } else {
+                    None
+                }
+            }
+        }
+
+        let codec = Encryption::new(Keys(1));
+        let value = b"secret".to_vec();
+        let encoded = codec.encode(value.clone()).unwrap();
+        assert_eq!(encoded[..KEY_ID_LEN], 1u32.to_be_bytes());
+        assert_ne!(encoded, codec.encode(value.clone()).unwrap());
+        // Values encrypted with an older key can be read after rotation.
+        let rotated = Encryption::new(Keys(2));
+        assert_eq!(rotated.decode(encoded.clone()).unwrap(), value);
+        assert_eq!(
+            rotated.encode(value.clone()).unwrap()[..KEY_ID_LEN],
+            2u32.to_be_bytes()
+        );
+
+        let mut tampered = encoded.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(codec.decode(tampered).is_err());
+        let mut unknown_key = encoded;
+        unknown_key[..KEY_ID_LEN].copy_from_slice(&3u32.to_be_bytes());
+        assert!(codec.decode(unknown_key).is_err());
+        assert!(codec.encode(Vec::new()).unwrap().is_empty());
+    }
+
+    #[cfg(all(feature = "crc32fast", feature = "twox-hash"))]
+    #[test]
+    fn test_checksum() {
+        for algorithm in [ChecksumAlgorithm::Crc32, ChecksumAlgorithm::XxHash64] {
+            let codec = Checksum::new(algorithm);
+            let value = b"value".to_vec();
+            let encoded = codec.encode(value.clone()).unwrap();
+            assert_eq!(encoded.len(), value.len() + algorithm.len());
+            assert_eq!(codec.decode(encoded.clone()).unwrap(), value);
+
+            let mut corrupted = encoded;
+            corrupted[0] ^= 1;
+            assert!(matches!(
+                codec.decode(corrupted),
+                Err(Error::CorruptedValue { .. })
+            ));
+            assert!(codec.decode(vec![1]).is_err());
+            assert!(codec.encode(Vec::new()).unwrap().is_empty());
+        }
+    }
+}
@@ -1,13 +1,17 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use super::HexRepr;
-use crate::kv::codec::{self, BytesEncoder};
+use super::{decode_hex, encode_hex, HexRepr};
+use crate::{
+    kv::codec::{self, BytesEncoder},
+    Error, Result,
+};
 #[allow(unused_imports)]
 #[cfg(test)]
 use proptest::{arbitrary::any_with, collection::size_range};
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 use std::{fmt, ops::Bound, u8};
+use tikv_client_common::EscapedBytes;
 use tikv_client_proto::kvrpcpb;
 
 const _PROPTEST_KEY_MAX: usize = 1024 * 2; // 2 KB
@@ -82,12 +86,6 @@ impl Key {
         self.0.is_empty()
     }
 
-    /// Return whether the last byte of key is 0.
-    #[inline]
-    pub(super) fn zero_terminated(&self) -> bool {
-        self.0.last().map(|i| *i == 0).unwrap_or(false)
-    }
-
     /// Push a zero to the end of the key.
     ///
     /// Extending a zero makes the new key the smallest key that is greater than than the original one, i.e. the succeeder.
@@ -96,26 +94,43 @@ impl Key {
         self.0.push(0)
     }
 
-    /// Convert the key to a lower bound. The key is treated as inclusive.
+    /// Convert the key to an upper bound. The key is treated as exclusive, and the empty key as
+    /// unbounded.
     #[inline]
-    pub(super) fn into_lower_bound(mut self) -> Bound<Key> {
-        if self.zero_terminated() {
-            self.0.pop().unwrap();
-            Bound::Excluded(self)
+    pub(super) fn into_upper_bound(self) -> Bound<Key> {
+        if self.is_empty() {
+            Bound::Unbounded
         } else {
-            Bound::Included(self)
+            Bound::Excluded(self)
         }
     }
 
-    /// Convert the key to an upper bound. The key is treated as exclusive.
-    #[inline]
-    pub(super) fn into_upper_bound(mut self) -> Bound<Key> {
-        if self.zero_terminated() {
-            self.0.pop().unwrap();
-            Bound::Included(self)
-        } else {
-            Bound::Excluded(self)
-        }
+    /// The key as a lowercase hex string.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Key;
+    /// let key = Key::from(vec![b'k', 0, 0xff]);
+    /// assert_eq!(key.to_hex(), "6b00ff");
+    /// ```
+    pub fn to_hex(&self) -> String {
+        encode_hex(&self.0)
+    }
+
+    /// Parse a key from a hex string, such as one returned by [`to_hex`](Key::to_hex). Both
+    /// lowercase and uppercase digits are accepted.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use tikv_client::Key;
+    /// let key = Key::from_hex("6B00ff").unwrap();
+    /// assert_eq!(key, Key::from(vec![b'k', 0, 0xff]));
+    /// assert!(Key::from_hex("6b0").is_err());
+    /// ```
+    pub fn from_hex(hex: &str) -> Result<Key> {
+        decode_hex(hex).map(Key).ok_or_else(|| Error::CodecError {
+            message: format!("invalid hex key {:?}", hex),
+        })
     }
 
     /// Return the MVCC-encoded representation of the key.
@@ -174,3 +189,18 @@ impl fmt::Debug for Key {
         write!(f, "Key({})", HexRepr(&self.0))
     }
 }
+
+/// Displays the key with printable ASCII characters as is and other bytes escaped, e.g.
+/// `user\x00\x01`. Error messages show keys the same way.
+///
+/// # Examples
+/// ```rust
+/// # use tikv_client::Key;
+/// let key = Key::from(b"user\x00\x01".to_vec());
+/// assert_eq!(key.to_string(), r"user\x00\x01");
+/// ```
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", EscapedBytes(&self.0))
+    }
+}
@@ -3,7 +3,7 @@
 use super::{HexRepr, Key, Value};
 #[cfg(test)]
 use proptest_derive::Arbitrary;
-use std::{fmt, str};
+use std::{collections::HashMap, fmt, str};
 use tikv_client_proto::kvrpcpb;
 
 /// A key/value pair.
@@ -118,6 +118,16 @@ impl From<KvPair> for kvrpcpb::KvPair {
     }
 }
 
+/// The value of each key in `keys`, in order, looked up in `pairs`; `None` if the key has no
+/// pair.
+pub(crate) fn align_to_keys(
+    keys: &[Key],
+    pairs: impl IntoIterator<Item = KvPair>,
+) -> Vec<Option<Value>> {
+    let values: HashMap<Key, Value> = pairs.into_iter().map(Into::into).collect();
+    keys.iter().map(|key| values.get(key).cloned()).collect()
+}
+
 impl AsRef<Key> for KvPair {
     fn as_ref(&self) -> &Key {
         &self.0
@@ -139,3 +149,30 @@ impl fmt::Debug for KvPair {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_align_to_keys() {
+        let keys: Vec<Key> = vec![
+            b"c".to_vec().into(),
+            b"a".to_vec().into(),
+            b"c".to_vec().into(),
+        ];
+        let pairs = vec![
+            KvPair::new(b"a".to_vec(), b"1".to_vec()),
+            KvPair::new(b"c".to_vec(), b"3".to_vec()),
+        ];
+        assert_eq!(
+            align_to_keys(&keys, pairs),
+            vec![
+                Some(b"3".to_vec()),
+                Some(b"1".to_vec()),
+                Some(b"3".to_vec())
+            ]
+        );
+        assert_eq!(align_to_keys(&keys[..1], Vec::new()), vec![None]);
+    }
+}
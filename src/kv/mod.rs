@@ -5,11 +5,16 @@ mod bound_range;
 pub mod codec;
 mod key;
 mod kvpair;
+mod prefix;
+#[cfg(feature = "serde")]
+pub mod serialize;
 mod value;
 
 pub use bound_range::{BoundRange, IntoOwnedRange};
 pub use key::Key;
+pub(crate) use kvpair::align_to_keys;
 pub use kvpair::KvPair;
+pub(crate) use prefix::Prefix;
 pub use value::Value;
 
 struct HexRepr<'a>(pub &'a [u8]);
@@ -22,3 +27,20 @@ impl<'a> fmt::Display for HexRepr<'a> {
         Ok(())
     }
 }
+
+/// Lowercase hex digits of the bytes.
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The bytes of hex digits, in either case.
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some(digit(*high)? << 4 | digit(*low)?),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,148 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use super::{BoundRange, Key, KvPair};
+use std::ops::{Bound, RangeBounds};
+
+/// A byte prefix which is transparently prepended to keys sent to TiKV and stripped from keys
+/// received from TiKV.
+///
+/// An empty prefix leaves keys untouched.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Prefix(Vec<u8>);
+
+impl Prefix {
+    pub fn new(prefix: impl Into<Vec<u8>>) -> Prefix {
+        Prefix(prefix.into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The prefix `self` followed by `other`.
+    pub fn join(&self, other: &Prefix) -> Prefix {
+        let mut prefix = self.0.clone();
+        prefix.extend_from_slice(&other.0);
+        Prefix(prefix)
+    }
+
+    pub fn encode_key(&self, key: Key) -> Key {
+        if self.is_empty() {
+            return key;
+        }
+        let mut encoded = Vec::with_capacity(self.0.len() + key.0.len());
+        encoded.extend_from_slice(&self.0);
+        encoded.extend_from_slice(&key.0);
+        Key(encoded)
+    }
+
+    pub fn encode_pair(&self, pair: KvPair) -> KvPair {
+        KvPair(self.encode_key(pair.0), pair.1)
+    }
+
+    /// Map a range of unprefixed keys to the range of prefixed keys. Unbounded ends are
+    /// limited to the keys starting with the prefix.
+    pub fn encode_range(&self, range: BoundRange) -> BoundRange {
+        if self.is_empty() {
+            return range;
+        }
+        let start = match range.start_bound() {
+            Bound::Included(key) => Bound::Included(self.encode_key(key.clone())),
+            Bound::Excluded(key) => Bound::Excluded(self.encode_key(key.clone())),
+            Bound::Unbounded => Bound::Included(Key(self.0.clone())),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => Bound::Included(self.encode_key(key.clone())),
+            Bound::Excluded(key) => Bound::Excluded(self.encode_key(key.clone())),
+            Bound::Unbounded => match self.successor() {
+                Some(end) => Bound::Excluded(end),
+                None => Bound::Unbounded,
+            },
+        };
+        (start, end).into()
+    }
+
+    /// Strip the prefix from a key returned by TiKV. Keys without the prefix are returned as is.
+    pub fn decode_key(&self, mut key: Key) -> Key {
+        if !self.is_empty() && key.0.starts_with(&self.0) {
+            key.0.drain(..self.0.len());
+        }
+        key
+    }
+
+    /// Like [`decode_key`](Prefix::decode_key), borrowing the key.
+    pub fn strip<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        key.strip_prefix(self.0.as_slice()).unwrap_or(key)
+    }
+
+    pub fn decode_pair(&self, pair: KvPair) -> KvPair {
+        KvPair(self.decode_key(pair.0), pair.1)
+    }
+
+    /// The smallest key greater than every key starting with the prefix, or `None` if there is
+    /// no such key.
+    fn successor(&self) -> Option<Key> {
+        let mut end = self.0.clone();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return Some(Key(end));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prefix_keys() {
+        let prefix = Prefix::new(b"ns/".to_vec());
+        let key = prefix.encode_key(b"k1".to_vec().into());
+        assert_eq!(key, Key::from(b"ns/k1".to_vec()));
+        assert_eq!(prefix.decode_key(key), Key::from(b"k1".to_vec()));
+        assert_eq!(prefix.strip(b"ns/k1"), b"k1");
+        assert_eq!(prefix.strip(b"k1"), b"k1");
+        assert_eq!(
+            Prefix::default().encode_key(b"k1".to_vec().into()),
+            Key::from(b"k1".to_vec())
+        );
+        assert_eq!(
+            prefix.join(&Prefix::new(b"a/".to_vec())),
+            Prefix::new(b"ns/a/".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_prefix_range() {
+        let prefix = Prefix::new(b"ns".to_vec());
+        assert_eq!(
+            prefix.encode_range(BoundRange::from(..)).into_keys(),
+            (Key::from(b"ns".to_vec()), Some(Key::from(b"nt".to_vec())))
+        );
+        assert_eq!(
+            prefix
+                .encode_range(BoundRange::from(b"a".to_vec()..=b"b".to_vec()))
+                .into_keys(),
+            (
+                Key::from(b"nsa".to_vec()),
+                Some(Key::from(b"nsb\0".to_vec()))
+            )
+        );
+
+        let prefix = Prefix::new(vec![1, u8::MAX]);
+        assert_eq!(
+            prefix
+                .encode_range(BoundRange::range_from(vec![3].into()))
+                .into_keys(),
+            (Key::from(vec![1, u8::MAX, 3]), Some(Key::from(vec![2])))
+        );
+        let prefix = Prefix::new(vec![u8::MAX]);
+        assert_eq!(
+            prefix.encode_range(BoundRange::from(..)).into_keys(),
+            (Key::from(vec![u8::MAX]), None)
+        );
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! `serde` support for keys, values and timestamps.
+//!
+//! [`Key`](crate::Key) and [`KvPair`](crate::KvPair) implement `Serialize` and `Deserialize`.
+//! Keys and values are serialized as bytes by binary formats such as bincode, and as hex strings by
+//! human-readable formats such as JSON. Fields can be serialized differently with
+//! `#[serde(with = "...")]` and the modules below:
+//!
+//! - [`hex`](hex) and [`base64`](self::base64) serialize keys and values (`Vec<u8>`) as hex or
+//!   base64 strings, in every format.
+//! - [`timestamp`](timestamp) serializes a [`Timestamp`](crate::Timestamp) as its version.
+//!
+//! This module requires the `serde` feature.
+//!
+//! # Examples
+//! ```rust
+//! # use tikv_client::{Key, Timestamp, Value};
+//! # use serde_derive::{Deserialize, Serialize};
+//! #[derive(Serialize, Deserialize)]
+//! struct Checkpoint {
+//!     last_key: Key,
+//!     #[serde(with = "tikv_client::serialize::base64")]
+//!     last_value: Value,
+//!     #[serde(with = "tikv_client::serialize::timestamp")]
+//!     read_at: Timestamp,
+//! }
+//! ```
+
+use super::{decode_hex, encode_hex};
+use crate::{Key, KvPair, Timestamp, TimestampExt};
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeStruct,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_derive::Deserialize;
+use std::fmt;
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(<&[u8]>::from(self), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Key, D::Error> {
+        deserialize_bytes(deserializer).map(Key::from)
+    }
+}
+
+impl Serialize for KvPair {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut pair = serializer.serialize_struct("KvPair", 2)?;
+        pair.serialize_field("key", self.key())?;
+        pair.serialize_field("value", &Bytes(self.value()))?;
+        pair.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for KvPair {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<KvPair, D::Error> {
+        #[derive(Deserialize)]
+        struct Pair {
+            key: Key,
+            value: Key,
+        }
+        let pair = Pair::deserialize(deserializer)?;
+        Ok(KvPair(pair.key, pair.value.into()))
+    }
+}
+
+/// The types [`hex`](hex) and [`base64`](self::base64) serialize: keys and values.
+pub trait AsBytes {
+    fn as_bytes(&self) -> &[u8];
+}
+
+impl AsBytes for Key {
+    fn as_bytes(&self) -> &[u8] {
+        self.into()
+    }
+}
+
+impl AsBytes for Vec<u8> {
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Bytes serialized like a key.
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(self.0, serializer)
+    }
+}
+
+fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&encode_hex(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor(decode_hex))
+    } else {
+        deserializer.deserialize_byte_buf(BytesVisitor(decode_hex))
+    }
+}
+
+/// Visits bytes, or a string decoded by the function.
+struct BytesVisitor(fn(&str) -> Option<Vec<u8>>);
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("bytes or an encoded string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+        (self.0)(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Serialize keys and values as hex strings, with `#[serde(with = "tikv_client::serialize::hex")]`.
+pub mod hex {
+    use super::*;
+
+    pub fn serialize<T: AsBytes, S: Serializer>(
+        bytes: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_hex(bytes.as_bytes()))
+    }
+
+    pub fn deserialize<'de, T: From<Vec<u8>>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        deserializer
+            .deserialize_str(BytesVisitor(decode_hex))
+            .map(T::from)
+    }
+}
+
+/// Serialize keys and values as base64 strings (with padding), with
+/// `#[serde(with = "tikv_client::serialize::base64")]`.
+pub mod base64 {
+    use super::*;
+    use ::base64::{engine::general_purpose::STANDARD, Engine};
+
+    pub fn serialize<T: AsBytes, S: Serializer>(
+        bytes: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes.as_bytes()))
+    }
+
+    pub fn deserialize<'de, T: From<Vec<u8>>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        deserializer
+            .deserialize_str(BytesVisitor(|s| STANDARD.decode(s).ok()))
+            .map(T::from)
+    }
+}
+
+/// Serialize a timestamp as its version, with
+/// `#[serde(with = "tikv_client::serialize::timestamp")]`.
+pub mod timestamp {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &Timestamp,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(timestamp.version())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Timestamp, D::Error> {
+        u64::deserialize(deserializer).map(Timestamp::from_version)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Value;
+    use serde_derive::Serialize;
+
+    #[test]
+    fn test_key_serde() {
+        let key = Key::from(vec![0, 1, 0xab, 0xff]);
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(json, "\"0001abff\"");
+        assert_eq!(serde_json::from_str::<Key>(&json).unwrap(), key);
+        assert_eq!(serde_json::from_str::<Key>("\"0001ABFF\"").unwrap(), key);
+        assert!(serde_json::from_str::<Key>("\"0001a\"").is_err());
+        assert!(serde_json::from_str::<Key>("\"zz\"").is_err());
+        assert!(serde_json::from_str::<Key>("\"+f\"").is_err());
+
+        let pair = KvPair::new(key, b"v".to_vec());
+        let json = serde_json::to_string(&pair).unwrap();
+        assert_eq!(json, r#"{"key":"0001abff","value":"76"}"#);
+        assert_eq!(serde_json::from_str::<KvPair>(&json).unwrap(), pair);
+    }
+
+    #[test]
+    fn test_serialize_with() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Record {
+            #[serde(with = "hex")]
+            key: Key,
+            #[serde(with = "base64")]
+            value: Value,
+            #[serde(with = "timestamp")]
+            ts: Timestamp,
+        }
+        let record = Record {
+            key: b"k".to_vec().into(),
+            value: b"value".to_vec(),
+            ts: Timestamp::from_version(1 << 20),
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(json, r#"{"key":"6b","value":"dmFsdWU=","ts":1048576}"#);
+        assert_eq!(serde_json::from_str::<Record>(&json).unwrap(), record);
+    }
+}
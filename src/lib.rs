@@ -73,6 +73,23 @@
 //! ```
 //!
 //! At this point, you should seek the documentation in the related API modules.
+//!
+//! ## Logging
+//!
+//! The client emits [`tracing`](https://docs.rs/tracing) events with fields such as `region_id`,
+//! `store_id` and `error`, under a target per subsystem: `tikv_client::pd`,
+//! `tikv_client::store`, `tikv_client::txn`, `tikv_client::raw` and `tikv_client::recipes`.
+//! Without a `tracing` subscriber, events are forwarded to the [`log`](https://docs.rs/log)
+//! crate with the same targets.
+//!
+//! ## Background tasks
+//!
+//! The client runs background tasks, such as the stream of timestamps from PD, the heartbeats of
+//! transactions and the commit of their secondary keys. Each runs in a `task` span named after it,
+//! under the `tikv_client::task` target, and [`running_tasks`] lists those running, e.g. to check
+//! that none is left once the clients are dropped. With the `tokio-console` feature, and building
+//! with `RUSTFLAGS="--cfg tokio_unstable"`, the tasks are also named in
+//! [tokio-console](https://github.com/tokio-rs/console).
 
 #[macro_use]
 mod request;
@@ -81,15 +98,28 @@ mod request;
 mod transaction;
 
 mod backoff;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod cluster;
 mod compat;
 mod config;
+pub mod diff;
+pub mod export;
+pub mod failover;
+mod features;
+pub mod import;
 mod kv;
+pub mod mirror;
 mod pd;
 mod raw;
+pub mod recipes;
 mod region;
+pub mod replication;
 mod stats;
 mod store;
 mod timestamp;
+#[cfg(feature = "typed")]
+pub mod typed;
 mod util;
 
 #[cfg(test)]
@@ -98,25 +128,44 @@ mod mock;
 mod proptests;
 
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
 #[doc(inline)]
-pub use crate::backoff::Backoff;
+pub use crate::backoff::{Backoff, RetryKind};
+#[doc(inline)]
+pub use crate::cluster::Cluster;
+#[doc(inline)]
+pub use crate::features::{ClusterVersion, Features};
+#[doc(inline)]
+pub use crate::kv::codec;
+#[cfg(feature = "serde")]
+#[doc(inline)]
+pub use crate::kv::serialize;
 #[doc(inline)]
 pub use crate::kv::{BoundRange, IntoOwnedRange, Key, KvPair, Value};
 #[doc(inline)]
-pub use crate::raw::{lowering::*, Client as RawClient, ColumnFamily};
+pub use crate::pd::{HotRegion, HotRegionKind, Keyspace, KeyspaceState, ResourceGroup, StoreInfo};
+#[doc(inline)]
+pub use crate::raw::{
+    lowering::*, Client as RawClient, ColumnFamily, RangeStats, RawMutation, ScanStream, WatchEvent,
+};
 #[doc(inline)]
-pub use crate::request::RetryOptions;
+pub use crate::request::{CancellationToken, Options, Priority, RetryOptions};
 #[doc(inline)]
 pub use crate::timestamp::{Timestamp, TimestampExt};
 #[doc(inline)]
 pub use crate::transaction::{
-    lowering::*, CheckLevel, Client as TransactionClient, Snapshot, Transaction, TransactionOptions,
+    lowering::*, CheckLevel, Checksum, Client as TransactionClient, CommitMode, CommitStatus,
+    LockInfo, ServiceSafepoint, Snapshot, Transaction, TransactionEvent, TransactionOptions,
+    WriterId,
 };
 #[doc(inline)]
 pub use config::Config;
 #[doc(inline)]
 pub use region::{Region, RegionId, RegionVerId, StoreId};
 #[doc(inline)]
-pub use tikv_client_common::{security::SecurityManager, Error, Result};
+pub use tikv_client_common::tasks::{running_tasks, TaskInfo};
+#[doc(inline)]
+pub use tikv_client_common::{security::SecurityManager, Attempt, Error, ErrorCode, Result};
+#[doc(inline)]
+pub use tikv_client_store::StoreStats;
@@ -0,0 +1,254 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Comparing raw reads against a second cluster.
+//!
+//! A [`MirrorClient`](MirrorClient) makes reads on a primary cluster and returns their results as
+//! is. A sample of the reads is made again on a mirror cluster in the background, e.g. the
+//! cluster data is being migrated to, and the results are compared. Reads returning different
+//! pairs, or failing on the mirror cluster, are reported to a callback. Writes are only made on
+//! the primary cluster.
+//!
+//! As the mirror read is made after the primary read, writes in between may be reported as
+//! mismatches, as may pairs not yet copied to the mirror cluster.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{RawClient, mirror::{MirrorClient, MirrorOptions}};
+//! # futures::executor::block_on(async {
+//! let primary = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let mirror = RawClient::new(vec!["192.168.1.100"]).await.unwrap();
+//! let options = MirrorOptions::new()
+//!     .sample_rate(0.1)
+//!     .on_mismatch(|mismatch| eprintln!("mismatch: {:?}", mismatch.read()));
+//! let client = MirrorClient::new(primary, mirror, options);
+//! let value = client.get("TiKV".to_owned()).await.unwrap();
+//! # });
+//! ```
+
+use crate::{BoundRange, Key, KvPair, RawClient, Result, Value};
+use rand::{thread_rng, Rng};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+/// Options for a [`MirrorClient`](MirrorClient).
+#[derive(Clone)]
+pub struct MirrorOptions {
+    sample_rate: f64,
+    max_in_flight: usize,
+    on_mismatch: Option<Arc<dyn Fn(&Mismatch) + Send + Sync>>,
+}
+
+impl Default for MirrorOptions {
+    fn default() -> MirrorOptions {
+        MirrorOptions::new()
+    }
+}
+
+impl MirrorOptions {
+    /// Mirror every read, comparing up to 64 reads at a time. Mismatches are logged.
+    pub fn new() -> MirrorOptions {
+        MirrorOptions {
+            sample_rate: 1.0,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            on_mismatch: None,
+        }
+    }
+
+    /// The fraction of reads to mirror, between 0 and 1.
+    pub fn sample_rate(mut self, rate: f64) -> MirrorOptions {
+        assert!(
+            (0.0..=1.0).contains(&rate),
+            "sample rate must be between 0 and 1"
+        );
+        self.sample_rate = rate;
+        self
+    }
+
+    /// The number of mirror reads made at a time. Reads sampled while as many are in flight are
+    /// not mirrored, so that a slow mirror cluster doesn't pile up requests.
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> MirrorOptions {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Call `f` with every mismatch, from a background task.
+    pub fn on_mismatch(mut self, f: impl Fn(&Mismatch) + Send + Sync + 'static) -> MirrorOptions {
+        self.on_mismatch = Some(Arc::new(f));
+        self
+    }
+}
+
+/// A read made on both clusters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MirrorRead {
+    Get(Key),
+    BatchGet(Vec<Key>),
+    Scan(BoundRange, u32),
+}
+
+/// A read whose results differ between the clusters.
+#[derive(Debug)]
+pub struct Mismatch {
+    read: MirrorRead,
+    primary: Vec<KvPair>,
+    mirror: Result<Vec<KvPair>>,
+}
+
+impl Mismatch {
+    pub fn read(&self) -> &MirrorRead {
+        &self.read
+    }
+
+    /// The pairs read from the primary cluster, sorted by key.
+    pub fn primary(&self) -> &[KvPair] {
+        &self.primary
+    }
+
+    /// The pairs read from the mirror cluster, sorted by key, or the error of the read.
+    pub fn mirror(&self) -> &Result<Vec<KvPair>> {
+        &self.mirror
+    }
+}
+
+/// A raw client reading from a primary cluster and comparing with a mirror cluster, see the
+/// [module documentation](self).
+#[derive(Clone)]
+pub struct MirrorClient {
+    primary: RawClient,
+    mirror: RawClient,
+    options: MirrorOptions,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl MirrorClient {
+    pub fn new(primary: RawClient, mirror: RawClient, options: MirrorOptions) -> MirrorClient {
+        MirrorClient {
+            primary,
+            mirror,
+            options,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The client of the primary cluster, e.g. to write.
+    pub fn primary(&self) -> &RawClient {
+        &self.primary
+    }
+
+    /// Like [`RawClient::get`](RawClient::get).
+    pub async fn get(&self, key: impl Into<Key>) -> Result<Option<Value>> {
+        let key = key.into();
+        let value = self.primary.get(key.clone()).await?;
+        if self.sample() {
+            let primary = value
+                .iter()
+                .map(|value| KvPair::new(key.clone(), value.clone()))
+                .collect();
+            self.compare(MirrorRead::Get(key), primary);
+        }
+        Ok(value)
+    }
+
+    /// Like [`RawClient::batch_get`](RawClient::batch_get).
+    pub async fn batch_get(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<KvPair>> {
+        let keys: Vec<Key> = keys.into_iter().map(Into::into).collect();
+        let pairs = self.primary.batch_get(keys.clone()).await?;
+        if self.sample() {
+            self.compare(MirrorRead::BatchGet(keys), pairs.clone());
+        }
+        Ok(pairs)
+    }
+
+    /// Like [`RawClient::scan`](RawClient::scan).
+    pub async fn scan(&self, range: impl Into<BoundRange>, limit: u32) -> Result<Vec<KvPair>> {
+        let range = range.into();
+        let pairs = self.primary.scan(range.clone(), limit).await?;
+        if self.sample() {
+            self.compare(MirrorRead::Scan(range, limit), pairs.clone());
+        }
+        Ok(pairs)
+    }
+
+    fn sample(&self) -> bool {
+        self.options.sample_rate >= 1.0 || thread_rng().gen::<f64>() < self.options.sample_rate
+    }
+
+    /// Make `read` on the mirror cluster in the background and compare with the `primary` pairs.
+    fn compare(&self, read: MirrorRead, primary: Vec<KvPair>) {
+        if self.in_flight.fetch_add(1, Ordering::SeqCst) >= self.options.max_in_flight {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            return;
+        }
+        let mirror = self.mirror.clone();
+        let on_mismatch = self.options.on_mismatch.clone();
+        let in_flight = self.in_flight.clone();
+        crate::util::task::spawn("mirror-read", async move {
+            let result = match &read {
+                MirrorRead::Get(key) => mirror.get(key.clone()).await.map(|value| {
+                    value
+                        .into_iter()
+                        .map(|value| KvPair::new(key.clone(), value))
+                        .collect()
+                }),
+                MirrorRead::BatchGet(keys) => mirror.batch_get(keys.clone()).await,
+                MirrorRead::Scan(range, limit) => mirror.scan(range.clone(), *limit).await,
+            };
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            let primary = sorted(primary);
+            let result = result.map(sorted);
+            if matches!(&result, Ok(pairs) if *pairs == primary) {
+                return;
+            }
+            let mismatch = Mismatch {
+                read,
+                primary,
+                mirror: result,
+            };
+            match on_mismatch {
+                Some(f) => f(&mismatch),
+                None => warn!(
+                    target: "tikv_client::raw",
+                    read = ?mismatch.read,
+                    "mirror read mismatch"
+                ),
+            }
+        });
+    }
+}
+
+/// Sort pairs by key, as batch gets return them in no particular order.
+fn sorted(mut pairs: Vec<KvPair>) -> Vec<KvPair> {
+    pairs.sort_by(|a, b| a.key().cmp(b.key()));
+    pairs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sorted() {
+        let pair = |k: &str, v: &str| KvPair::new(k.to_owned(), v.to_owned());
+        assert_eq!(
+            sorted(vec![pair("b", "2"), pair("a", "1")]),
+            sorted(vec![pair("a", "1"), pair("b", "2")])
+        );
+        assert_ne!(
+            sorted(vec![pair("b", "2"), pair("a", "1")]),
+            sorted(vec![pair("a", "1"), pair("b", "3")])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_sample_rate() {
+        let _ = MirrorOptions::new().sample_rate(1.5);
+    }
+}
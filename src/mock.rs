@@ -6,15 +6,16 @@
 //! the system, in particular without requiring a TiKV or PD server, or RPC layer.
 
 use crate::{
+    backoff::BackoffObserver,
     pd::{PdClient, PdRpcClient, RetryClient},
     store::Store,
     Config, Error, Key, Region, RegionId, Result, Timestamp,
 };
 use async_trait::async_trait;
 use derive_new::new;
-use std::{any::Any, sync::Arc};
+use std::{any::Any, sync::Arc, time::Duration};
 use tikv_client_proto::metapb;
-use tikv_client_store::{KvClient, KvConnect, Request};
+use tikv_client_store::{KvClient, KvConnect, Request, RequestMetadata};
 
 /// Create a `PdRpcClient` with it's internals replaced with mocks so that the
 /// client can be tested without doing any RPC calls.
@@ -24,12 +25,12 @@ pub async fn pd_rpc_client() -> PdRpcClient<MockKvConnect, MockCluster> {
         &config,
         |_, _| MockKvConnect,
         |e, sm| {
-            futures::future::ok(RetryClient::new_with_cluster(
+            futures::future::ok(Arc::new(RetryClient::new_with_cluster(
                 e,
                 sm,
                 config.timeout,
                 MockCluster,
-            ))
+            )))
         },
         false,
     )
@@ -62,11 +63,17 @@ pub struct MockCluster;
 #[derive(new)]
 pub struct MockPdClient {
     client: MockKvClient,
+    #[new(default)]
+    timestamp: Timestamp,
+    #[new(default)]
+    backoff_observer: Option<BackoffObserver>,
+    #[new(default)]
+    safepoint: u64,
 }
 
 #[async_trait]
 impl KvClient for MockKvClient {
-    async fn dispatch(&self, req: &dyn Request) -> Result<Box<dyn Any>> {
+    async fn dispatch(&self, req: &dyn Request, _: &RequestMetadata) -> Result<Box<dyn Any>> {
         match &self.dispatch {
             Some(f) => f(req.as_any()),
             None => panic!("no dispatch hook set"),
@@ -89,9 +96,30 @@ impl MockPdClient {
     pub fn default() -> MockPdClient {
         MockPdClient {
             client: MockKvClient::default(),
+            timestamp: Timestamp::default(),
+            backoff_observer: None,
+            safepoint: 0,
         }
     }
 
+    /// Report the retries of requests to `observer`.
+    pub fn with_backoff_observer(mut self, observer: BackoffObserver) -> MockPdClient {
+        self.backoff_observer = Some(observer);
+        self
+    }
+
+    /// Make PD return `safepoint` as the GC safepoint.
+    pub fn with_safepoint(mut self, safepoint: u64) -> MockPdClient {
+        self.safepoint = safepoint;
+        self
+    }
+
+    /// Make PD return `timestamp` as the current timestamp.
+    pub fn with_timestamp(mut self, timestamp: Timestamp) -> MockPdClient {
+        self.timestamp = timestamp;
+        self
+    }
+
     pub fn region1() -> Region {
         let mut region = Region::default();
         region.region.id = 1;
@@ -147,17 +175,40 @@ impl PdClient for MockPdClient {
     }
 
     async fn get_timestamp(self: Arc<Self>) -> Result<Timestamp> {
-        Ok(Timestamp::default())
+        Ok(self.timestamp.clone())
+    }
+
+    async fn get_safepoint(self: Arc<Self>) -> Result<u64> {
+        Ok(self.safepoint)
     }
 
     async fn update_safepoint(self: Arc<Self>, _safepoint: u64) -> Result<bool> {
         unimplemented!()
     }
+
+    async fn update_service_safepoint(
+        self: Arc<Self>,
+        _service_id: String,
+        _ttl: Duration,
+        safepoint: u64,
+    ) -> Result<u64> {
+        Ok(self.safepoint.max(safepoint))
+    }
+
+    fn backoff_observer(&self) -> Option<BackoffObserver> {
+        self.backoff_observer.clone()
+    }
 }
 
 pub fn mock_store() -> Store {
     Store {
         region: Region::default(),
         client: Arc::new(MockKvClient::new("foo".to_owned(), None)),
+        request_source: None,
+        resource_group: None,
+        replica: None,
+        priority: None,
+        isolation_level: None,
+        api_version: None,
     }
 }
@@ -0,0 +1,266 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! The regions and stores of a cluster, as last loaded from PD.
+//!
+//! Regions are keyed by their keys as PD reports them, i.e. encoded in the transactional API.
+//! Entries are invalidated by the errors of the requests sent with them, each kind of region
+//! error invalidating only what it shows to be outdated, and expire after a while so that
+//! changes which no request notices are picked up eventually.
+
+use crate::{Region, RegionId, StoreId};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use tikv_client_proto::{errorpb, metapb};
+
+// How long regions and stores are cached before they are loaded from PD again.
+const CACHE_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Default)]
+struct Entries {
+    // The regions by their start key, which don't overlap.
+    regions: BTreeMap<Vec<u8>, (Region, Instant)>,
+    // The start keys of the regions, by region ID.
+    ids: HashMap<RegionId, Vec<u8>>,
+    stores: HashMap<StoreId, (metapb::Store, Instant)>,
+}
+
+impl Entries {
+    fn remove_region(&mut self, id: RegionId) -> Option<Region> {
+        let start_key = self.ids.remove(&id)?;
+        self.regions.remove(&start_key).map(|(region, _)| region)
+    }
+}
+
+/// The regions and stores of a cluster, as last loaded from PD.
+#[derive(Default)]
+pub struct RegionCache {
+    entries: RwLock<Entries>,
+}
+
+impl RegionCache {
+    /// The cached region containing `key`, encoded as in PD.
+    pub fn region_for_key(&self, key: &[u8]) -> Option<Region> {
+        let entries = self.entries.read().unwrap();
+        let (region, loaded) = entries.regions.range(..=key.to_vec()).next_back()?.1;
+        let end_key = region.region.get_end_key();
+        if (end_key.is_empty() || key < end_key) && loaded.elapsed() < CACHE_TTL {
+            Some(region.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn region_for_id(&self, id: RegionId) -> Option<Region> {
+        let entries = self.entries.read().unwrap();
+        let (region, loaded) = entries.regions.get(entries.ids.get(&id)?)?;
+        if loaded.elapsed() < CACHE_TTL {
+            Some(region.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Cache `region`, replacing the cached regions it overlaps.
+    pub fn add_region(&self, region: Region) {
+        let mut entries = self.entries.write().unwrap();
+        let start_key = region.region.get_start_key().to_vec();
+        let end_key = region.region.get_end_key().to_vec();
+        let before_end = if end_key.is_empty() {
+            entries.regions.range::<Vec<u8>, _>(..)
+        } else {
+            entries.regions.range(..end_key)
+        };
+        // Cached regions don't overlap, so those ending after the start of the region are the
+        // last ones starting before its end.
+        let overlapping: Vec<RegionId> = before_end
+            .rev()
+            .take_while(|(_, (cached, _))| {
+                let cached_end = cached.region.get_end_key();
+                cached_end.is_empty() || cached_end > start_key.as_slice()
+            })
+            .map(|(_, (cached, _))| cached.id())
+            .collect();
+        for id in overlapping {
+            entries.remove_region(id);
+        }
+        entries.remove_region(region.id());
+        entries.ids.insert(region.id(), start_key.clone());
+        entries.regions.insert(start_key, (region, Instant::now()));
+    }
+
+    pub fn invalidate_region(&self, id: RegionId) {
+        self.entries.write().unwrap().remove_region(id);
+    }
+
+    /// Set the leader of a cached region. Returns whether the region is cached and has the new
+    /// leader among its peers.
+    pub fn update_leader(&self, id: RegionId, leader: metapb::Peer) -> bool {
+        let mut entries = self.entries.write().unwrap();
+        let start_key = match entries.ids.get(&id) {
+            Some(start_key) => start_key.clone(),
+            None => return false,
+        };
+        match entries.regions.get_mut(&start_key) {
+            Some((region, _)) if region.region.get_peers().contains(&leader) => {
+                region.leader = Some(leader);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn store(&self, id: StoreId) -> Option<metapb::Store> {
+        match self.entries.read().unwrap().stores.get(&id) {
+            Some((store, loaded)) if loaded.elapsed() < CACHE_TTL => Some(store.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn add_store(&self, store: metapb::Store) {
+        self.entries
+            .write()
+            .unwrap()
+            .stores
+            .insert(store.get_id(), (store, Instant::now()));
+    }
+
+    pub fn invalidate_store(&self, id: StoreId) {
+        self.entries.write().unwrap().stores.remove(&id);
+    }
+
+    /// Invalidate what `error`, returned by a request to `region_id`, shows to be outdated.
+    ///
+    /// - `NotLeader` only updates the leader of the region, or reloads the region if the new
+    ///   leader is unknown.
+    /// - `StoreNotMatch` reloads the store the request was sent to.
+    /// - Errors of a busy or lagging store leave the cache as is, as the region is up to date.
+    /// - Other errors, e.g. `EpochNotMatch` after a split or merge, reload the region.
+    pub fn on_region_error(&self, region_id: RegionId, error: &errorpb::Error) {
+        if error.has_not_leader() {
+            let not_leader = error.get_not_leader();
+            if !not_leader.has_leader()
+                || !self.update_leader(region_id, not_leader.get_leader().clone())
+            {
+                self.invalidate_region(region_id);
+            }
+        } else if error.has_store_not_match() {
+            self.invalidate_store(error.get_store_not_match().get_request_store_id());
+        } else if !(error.has_server_is_busy()
+            || error.has_stale_command()
+            || error.has_raft_entry_too_large()
+            || error.has_max_timestamp_not_synced()
+            || error.has_read_index_not_ready()
+            || error.has_proposal_in_merging_mode())
+        {
+            self.invalidate_region(region_id);
+        }
+    }
+
+    /// Invalidate a region and the store of its leader after a request to it could not be sent,
+    /// as the leader may have moved away from a failed store.
+    pub fn on_send_error(&self, region_id: RegionId) {
+        let region = self.entries.write().unwrap().remove_region(region_id);
+        if let Some(store_id) = region.and_then(|region| region.get_store_id().ok()) {
+            self.invalidate_store(store_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn region(id: RegionId, start: &[u8], end: &[u8], leader_store: StoreId) -> Region {
+        let mut region = metapb::Region::default();
+        region.set_id(id);
+        region.set_start_key(start.to_vec());
+        region.set_end_key(end.to_vec());
+        let peers: Vec<metapb::Peer> = (1..=3)
+            .map(|store_id| {
+                let mut peer = metapb::Peer::default();
+                peer.set_id(id * 10 + store_id);
+                peer.set_store_id(store_id);
+                peer
+            })
+            .collect();
+        let leader = peers[leader_store as usize - 1].clone();
+        region.set_peers(peers);
+        Region::new(region, Some(leader))
+    }
+
+    #[test]
+    fn test_region_for_key() {
+        let cache = RegionCache::default();
+        cache.add_region(region(1, b"", b"b", 1));
+        cache.add_region(region(2, b"b", b"d", 1));
+        cache.add_region(region(3, b"f", b"", 1));
+        assert_eq!(cache.region_for_key(b"").unwrap().id(), 1);
+        assert_eq!(cache.region_for_key(b"a").unwrap().id(), 1);
+        assert_eq!(cache.region_for_key(b"b").unwrap().id(), 2);
+        assert!(cache.region_for_key(b"d").is_none());
+        assert_eq!(cache.region_for_key(b"z").unwrap().id(), 3);
+        assert_eq!(
+            cache.region_for_id(2).unwrap().start_key(),
+            b"b".to_vec().into()
+        );
+
+        // Region 2 merged into region 1, which replaces both.
+        cache.add_region(region(1, b"", b"c", 1));
+        assert!(cache.region_for_id(2).is_none());
+        assert_eq!(cache.region_for_key(b"b").unwrap().id(), 1);
+        assert!(cache.region_for_key(b"c").is_none());
+        assert_eq!(cache.region_for_key(b"z").unwrap().id(), 3);
+
+        // Region 3 split.
+        cache.add_region(region(4, b"g", b"", 1));
+        assert!(cache.region_for_id(3).is_none());
+        assert!(cache.region_for_key(b"f").is_none());
+        assert_eq!(cache.region_for_key(b"z").unwrap().id(), 4);
+    }
+
+    #[test]
+    fn test_on_region_error() {
+        let cache = RegionCache::default();
+        cache.add_region(region(1, b"", b"b", 1));
+        cache.add_region(region(2, b"b", b"", 1));
+        let mut store = metapb::Store::default();
+        store.set_id(1);
+        cache.add_store(store);
+
+        // The new leader is updated in place.
+        let mut error = errorpb::Error::default();
+        error
+            .mut_not_leader()
+            .set_leader(region(1, b"", b"b", 2).leader.unwrap());
+        cache.on_region_error(1, &error);
+        assert_eq!(cache.region_for_id(1).unwrap().get_store_id().unwrap(), 2);
+
+        // A busy store doesn't invalidate anything.
+        let mut error = errorpb::Error::default();
+        error.mut_server_is_busy().set_reason("busy".to_owned());
+        cache.on_region_error(1, &error);
+        assert!(cache.region_for_id(1).is_some());
+
+        let mut error = errorpb::Error::default();
+        error.mut_store_not_match().set_request_store_id(1);
+        cache.on_region_error(2, &error);
+        assert!(cache.store(1).is_none());
+        assert!(cache.region_for_id(2).is_some());
+
+        // Only the region of the request is reloaded.
+        let mut error = errorpb::Error::default();
+        error.mut_epoch_not_match();
+        cache.on_region_error(2, &error);
+        assert!(cache.region_for_id(2).is_none());
+        assert!(cache.region_for_id(1).is_some());
+
+        // An unknown leader reloads the region.
+        let mut error = errorpb::Error::default();
+        error.mut_not_leader().set_region_id(1);
+        cache.on_region_error(1, &error);
+        assert!(cache.region_for_id(1).is_none());
+    }
+}
@@ -1,20 +1,31 @@
 // Copyright 2018 TiKV Project Authors. Licensed under Apache-2.0.
 
 use crate::{
-    compat::stream_fn, kv::codec, pd::RetryClient, store::Store, BoundRange, Config, Key, Region,
-    RegionId, Result, SecurityManager, Timestamp,
+    backoff::BackoffObserver,
+    compat::stream_fn,
+    features::API_V2_VERSION,
+    kv::codec,
+    pd::{
+        hot, http::HttpClient, keyspace, resource_group, status, HotRegion, HotRegionCache,
+        Keyspace, RegionCache, RequestUnitRecorder, ResourceGroup, RetryClient,
+    },
+    store::Store,
+    BoundRange, ClusterVersion, ColumnFamily, Config, Error, Features, Key, Options, Region,
+    RegionId, Result, RetryOptions, SecurityManager, StoreId, Timestamp,
 };
 use async_trait::async_trait;
-use futures::{prelude::*, stream::BoxStream};
+use futures::{future, prelude::*, stream::BoxStream};
 use grpcio::{EnvBuilder, Environment};
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
     thread,
+    time::Duration,
 };
+use tikv_client_common::internal_err;
 use tikv_client_pd::Cluster;
-use tikv_client_proto::kvrpcpb;
-use tikv_client_store::{KvClient, KvConnect, TikvConnect};
+use tikv_client_proto::{cdcpb::ChangeDataClient, debugpb, kvrpcpb, metapb};
+use tikv_client_store::{KvClient, KvConnect, RequestMetadata, StoreStats, TikvConnect};
 
 const CQ_COUNT: usize = 1;
 const CLIENT_PREFIX: &str = "tikv-client";
@@ -51,8 +62,71 @@ pub trait PdClient: Send + Sync + 'static {
 
     async fn get_timestamp(self: Arc<Self>) -> Result<Timestamp>;
 
+    /// The GC safepoint of the cluster: versions before it may have been garbage collected.
+    async fn get_safepoint(self: Arc<Self>) -> Result<u64>;
+
     async fn update_safepoint(self: Arc<Self>, safepoint: u64) -> Result<bool>;
 
+    /// Register the safepoint of a service for `ttl`, holding back GC until it expires, or remove
+    /// the registration if `ttl` is zero. Returns the minimum safepoint of all services.
+    async fn update_service_safepoint(
+        self: Arc<Self>,
+        service_id: String,
+        ttl: Duration,
+        safepoint: u64,
+    ) -> Result<u64>;
+
+    /// The optional features supported by the cluster.
+    fn features(&self) -> Features {
+        Features::default()
+    }
+
+    /// The gRPC metadata sent with every request to TiKV.
+    fn metadata(&self) -> RequestMetadata {
+        RequestMetadata::default()
+    }
+
+    /// The hot regions to annotate the errors of requests with, if enabled.
+    fn hot_region_cache(&self) -> Option<Arc<HotRegionCache>> {
+        None
+    }
+
+    /// The cached regions to invalidate when requests fail, if regions are cached.
+    fn region_cache(&self) -> Option<Arc<RegionCache>> {
+        None
+    }
+
+    /// The callback to report the retries of requests to, if any.
+    fn backoff_observer(&self) -> Option<BackoffObserver> {
+        None
+    }
+
+    /// The recorder of the request units consumed by requests, if any.
+    fn request_unit_recorder(&self) -> Option<RequestUnitRecorder> {
+        None
+    }
+
+    /// The retry options of requests which don't set their own.
+    fn retry_options(&self) -> RetryOptions {
+        RetryOptions::default_optimistic()
+    }
+
+    /// The retry options of pessimistic transactions which don't set their own.
+    fn pessimistic_retry_options(&self) -> RetryOptions {
+        RetryOptions::default_pessimistic()
+    }
+
+    /// A client sending every request to the leaders of regions, for requests which can't be
+    /// served by replicas.
+    fn leader_client(self: Arc<Self>) -> Arc<Self> {
+        self
+    }
+
+    /// A client sending the requests of an operation with `options`.
+    fn with_options(self: Arc<Self>, _options: &Options) -> Arc<Self> {
+        self
+    }
+
     /// In transactional API, `key` is in raw format
     async fn store_for_key(self: Arc<Self>, key: &Key) -> Result<Store> {
         let region = self.region_for_key(key).await?;
@@ -64,6 +138,33 @@ pub trait PdClient: Send + Sync + 'static {
         self.map_region_to_store(region).await
     }
 
+    /// The region boundaries splitting `range` into at most `max_chunks` chunks of consecutive
+    /// regions, with as even a number of regions per chunk as possible.
+    ///
+    /// In transactional API, `range` and the returned keys are in raw format.
+    async fn region_aligned_boundaries(
+        &self,
+        range: BoundRange,
+        max_chunks: usize,
+    ) -> Result<Vec<Key>> {
+        let (mut key, end) = range.into_keys();
+        // The ends of the regions of the range, but the last.
+        let mut region_ends = Vec::new();
+        loop {
+            let region_end = self.region_for_key(&key).await?.end_key();
+            if region_end.is_empty()
+                || end
+                    .as_ref()
+                    .is_some_and(|end| !end.is_empty() && *end <= region_end)
+            {
+                break;
+            }
+            region_ends.push(region_end.clone());
+            key = region_end;
+        }
+        Ok(chunk_boundaries(region_ends, max_chunks))
+    }
+
     fn group_keys_by_region<K, K2>(
         self: Arc<Self>,
         keys: impl Iterator<Item = K> + Send + Sync + 'static,
@@ -123,6 +224,32 @@ pub trait PdClient: Send + Sync + 'static {
         .boxed()
     }
 
+    /// Load the regions of `ranges` and connect to the stores of their leaders, so the first
+    /// requests to them don't wait for either. Returns the number of stores connected to.
+    ///
+    /// In transactional API, `ranges` are in raw format.
+    async fn warmup(self: Arc<Self>, ranges: Vec<BoundRange>) -> Result<usize> {
+        let mut clients = HashMap::new();
+        for range in ranges {
+            let mut stores = self.clone().stores_for_range(range);
+            while let Some(store) = stores.try_next().await? {
+                clients
+                    .entry(store.region.get_store_id()?)
+                    .or_insert(store.client);
+            }
+        }
+        let connected = future::join_all(clients.iter().map(|(store_id, client)| async move {
+            (*store_id, client.wait_for_connected().await)
+        }))
+        .await;
+        for (store_id, connected) in &connected {
+            if !connected {
+                return Err(internal_err!("failed to connect to store {}", store_id));
+            }
+        }
+        Ok(connected.len())
+    }
+
     /// Returns a Stream which iterates over the contexts for ranges in the same region.
     fn group_ranges_by_region(
         self: Arc<Self>,
@@ -203,59 +330,481 @@ pub struct PdRpcClient<KvC: KvConnect + Send + Sync + 'static = TikvConnect, Cl
     kv_connect: KvC,
     kv_client_cache: Arc<RwLock<HashMap<String, KvC::KvClient>>>,
     enable_codec: bool,
+    features: Features,
+    metadata: RequestMetadata,
+    request_source: Option<String>,
+    // The resource group requests to TiKV are charged to, if not the default.
+    resource_group: Option<String>,
+    // The request units consumed by requests, by resource group.
+    request_units: RequestUnitRecorder,
+    // The priority of requests to TiKV, if not the default.
+    priority: Option<kvrpcpb::CommandPri>,
+    // The isolation level of reads from TiKV, if not snapshot isolation.
+    isolation_level: Option<kvrpcpb::IsolationLevel>,
+    // Whether to read from the replica of a region with the best observed latency and error rate.
+    replica_read: bool,
+    // The regions and stores last loaded from PD.
+    region_cache: Arc<RegionCache>,
+    // The hot regions to annotate errors with, if hot region diagnostics are enabled.
+    hot_region_cache: Option<Arc<HotRegionCache>>,
+    // The retry options of requests which don't set their own, and of pessimistic transactions.
+    retry_options: RetryOptions,
+    pessimistic_retry_options: RetryOptions,
+    backoff_observer: Option<BackoffObserver>,
 }
 
 #[async_trait]
-impl<KvC: KvConnect + Send + Sync + 'static> PdClient for PdRpcClient<KvC> {
+impl<KvC: KvConnect + Clone + Send + Sync + 'static> PdClient for PdRpcClient<KvC> {
     type KvClient = KvC::KvClient;
 
     async fn map_region_to_store(self: Arc<Self>, region: Region) -> Result<Store> {
+        if self.replica_read {
+            return self.map_region_to_replica(region).await;
+        }
         let store_id = region.get_store_id()?;
-        let store = self.pd.clone().get_store(store_id).await?;
+        let store = self.store(store_id).await?;
         let kv_client = self.kv_client(store.get_address())?;
-        Ok(Store::new(region, Arc::new(kv_client)))
+        let mut store = Store::new(region, Arc::new(kv_client));
+        store.request_source = self.request_source.clone();
+        store.resource_group = self.resource_group.clone();
+        store.priority = self.priority;
+        store.isolation_level = self.isolation_level;
+        store.api_version = self.api_version();
+        Ok(store)
     }
 
     async fn region_for_key(&self, key: &Key) -> Result<Region> {
         let enable_codec = self.enable_codec;
-        let key = if enable_codec {
+        let key: Vec<u8> = if enable_codec {
             key.to_encoded().into()
         } else {
             key.clone().into()
         };
-        let region = self.pd.clone().get_region(key).await?;
+        let region = match self.region_cache.region_for_key(&key) {
+            Some(region) => region,
+            None => {
+                let region = self
+                    .pd
+                    .clone()
+                    .get_region(key, self.backoff_observer.as_ref())
+                    .await?;
+                self.region_cache.add_region(region.clone());
+                region
+            }
+        };
         Self::decode_region(region, enable_codec)
     }
 
     async fn region_for_id(&self, id: RegionId) -> Result<Region> {
-        let region = self.pd.clone().get_region_by_id(id).await?;
+        let region = match self.region_cache.region_for_id(id) {
+            Some(region) => region,
+            None => {
+                let region = self
+                    .pd
+                    .clone()
+                    .get_region_by_id(id, self.backoff_observer.as_ref())
+                    .await?;
+                self.region_cache.add_region(region.clone());
+                region
+            }
+        };
         Self::decode_region(region, self.enable_codec)
     }
 
     async fn get_timestamp(self: Arc<Self>) -> Result<Timestamp> {
-        self.pd.clone().get_timestamp().await
+        self.pd
+            .clone()
+            .get_timestamp(self.backoff_observer.as_ref())
+            .await
+    }
+
+    async fn get_safepoint(self: Arc<Self>) -> Result<u64> {
+        self.pd
+            .clone()
+            .get_safepoint(self.backoff_observer.as_ref())
+            .await
     }
 
     async fn update_safepoint(self: Arc<Self>, safepoint: u64) -> Result<bool> {
-        self.pd.clone().update_safepoint(safepoint).await
+        self.pd
+            .clone()
+            .update_safepoint(safepoint, self.backoff_observer.as_ref())
+            .await
+    }
+
+    async fn update_service_safepoint(
+        self: Arc<Self>,
+        service_id: String,
+        ttl: Duration,
+        safepoint: u64,
+    ) -> Result<u64> {
+        self.pd
+            .clone()
+            .update_service_safepoint(service_id, ttl, safepoint, self.backoff_observer.as_ref())
+            .await
+    }
+
+    fn features(&self) -> Features {
+        self.features
+    }
+
+    fn metadata(&self) -> RequestMetadata {
+        self.metadata.clone()
+    }
+
+    fn hot_region_cache(&self) -> Option<Arc<HotRegionCache>> {
+        self.hot_region_cache.clone()
+    }
+
+    fn region_cache(&self) -> Option<Arc<RegionCache>> {
+        Some(self.region_cache.clone())
+    }
+
+    fn backoff_observer(&self) -> Option<BackoffObserver> {
+        self.backoff_observer.clone()
+    }
+
+    fn request_unit_recorder(&self) -> Option<RequestUnitRecorder> {
+        Some(self.request_units.clone())
+    }
+
+    fn retry_options(&self) -> RetryOptions {
+        self.retry_options.clone()
+    }
+
+    fn pessimistic_retry_options(&self) -> RetryOptions {
+        self.pessimistic_retry_options.clone()
+    }
+
+    fn leader_client(self: Arc<Self>) -> Arc<Self> {
+        if self.replica_read {
+            Arc::new(self.with_replica_read(false))
+        } else {
+            self
+        }
+    }
+
+    fn with_options(self: Arc<Self>, options: &Options) -> Arc<Self> {
+        if options.priority.is_none()
+            && options.replica_read.is_none()
+            && options.retry_options.is_none()
+            && !options.read_committed
+        {
+            return self;
+        }
+        let mut client = self.with_codec(self.enable_codec);
+        if let Some(priority) = options.priority {
+            client.priority = Some(priority.into());
+        }
+        if let Some(replica_read) = options.replica_read {
+            client.replica_read = replica_read;
+        }
+        if let Some(retry_options) = &options.retry_options {
+            client.retry_options = retry_options.clone();
+            client.pessimistic_retry_options = retry_options.clone();
+        }
+        if options.read_committed {
+            client.isolation_level = Some(kvrpcpb::IsolationLevel::Rc);
+        }
+        Arc::new(client)
     }
 }
 
+impl<KvC: KvConnect + Clone + Send + Sync + 'static> PdRpcClient<KvC> {
+    /// Map a region to the store of its replica with the best observed latency and error rate.
+    async fn map_region_to_replica(self: Arc<Self>, region: Region) -> Result<Store> {
+        let leader = region.leader.clone().ok_or(Error::LeaderNotFound {
+            region_id: region.id(),
+        })?;
+        let mut candidates = Vec::new();
+        for peer in region.region.get_peers() {
+            // Learners may be TiFlash replicas, which don't serve key-value reads.
+            if peer.get_role() == metapb::PeerRole::Learner {
+                continue;
+            }
+            match self.store_address(peer.get_store_id()).await {
+                Ok(address) => {
+                    let stats = self
+                        .kv_client_cache
+                        .read()
+                        .unwrap()
+                        .get(&address)
+                        .and_then(|client| client.stats());
+                    candidates.push((peer.clone(), address, stats.as_ref().map(replica_score)));
+                }
+                Err(e) => warn!(
+                    target: "tikv_client::pd",
+                    region_id = region.id(),
+                    store_id = peer.get_store_id(),
+                    error = %e,
+                    "failed to get store"
+                ),
+            }
+        }
+        let (peer, address) = match select_replica(&leader, candidates) {
+            Some(replica) => replica,
+            None => (
+                leader.clone(),
+                self.store_address(leader.get_store_id()).await?,
+            ),
+        };
+        let kv_client = self.kv_client(&address)?;
+        let mut store = Store::new(region, Arc::new(kv_client));
+        store.request_source = self.request_source.clone();
+        store.resource_group = self.resource_group.clone();
+        store.priority = self.priority;
+        store.isolation_level = self.isolation_level;
+        store.api_version = self.api_version();
+        if peer != leader {
+            store.replica = Some(peer);
+        }
+        Ok(store)
+    }
+
+    /// The API version of requests: raw requests to API V2 clusters must be marked as such.
+    fn api_version(&self) -> Option<kvrpcpb::ApiVersion> {
+        if self.features.api_v2 && !self.enable_codec {
+            Some(kvrpcpb::ApiVersion::V2)
+        } else {
+            None
+        }
+    }
+
+    async fn store_address(&self, store_id: StoreId) -> Result<String> {
+        Ok(self.store(store_id).await?.get_address().to_owned())
+    }
+
+    async fn store(&self, store_id: StoreId) -> Result<metapb::Store> {
+        if let Some(store) = self.region_cache.store(store_id) {
+            return Ok(store);
+        }
+        let store = self
+            .pd
+            .clone()
+            .get_store(store_id, self.backoff_observer.as_ref())
+            .await?;
+        self.region_cache.add_store(store.clone());
+        Ok(store)
+    }
+}
+
+/// Whether the cluster is configured with API V2, as read from the configuration of the first
+/// store which responds. Fails if no store does, as guessing wrong would make raw clients read and
+/// write the wrong keys.
+async fn detect_api_v2(http: &HttpClient, stores: &[metapb::Store]) -> Result<bool> {
+    let stores = stores
+        .iter()
+        .filter(|store| store.state == metapb::StoreState::Up as i32);
+    let mut last_err = None;
+    for store in stores {
+        match status::api_version(http, store).await {
+            Ok(version) => {
+                info!(target: "tikv_client::pd", version, "detected API version");
+                return Ok(version == 2);
+            }
+            Err(e) => {
+                warn!(
+                    target: "tikv_client::pd",
+                    store_id = store.id,
+                    error = %e,
+                    "failed to read API version of store"
+                );
+                last_err = Some(e);
+            }
+        }
+    }
+    let cause = last_err.map_or_else(|| "no store is up".to_owned(), |e| e.to_string());
+    Err(internal_err!(
+        "failed to detect the API version of the cluster ({}), set the features of the client \
+         with Config::with_features",
+        cause
+    ))
+}
+
+/// Choose the boundaries of at most `max_chunks` chunks among the ends of consecutive regions,
+/// giving each chunk as even a number of regions as possible.
+fn chunk_boundaries(region_ends: Vec<Key>, max_chunks: usize) -> Vec<Key> {
+    let regions = region_ends.len() + 1;
+    let chunks = max_chunks.clamp(1, regions);
+    // Chunk `i` starts with region `i * regions / chunks`, which starts at the end of the
+    // region before it.
+    (1..chunks)
+        .map(|i| region_ends[i * regions / chunks - 1].clone())
+        .collect()
+}
+
+// How much an error rate of 1 adds to the latency of a store when choosing a replica.
+const REPLICA_ERROR_PENALTY: Duration = Duration::from_secs(1);
+
+/// The latency of a store, penalized by its error rate.
+fn replica_score(stats: &StoreStats) -> Duration {
+    stats.latency() + REPLICA_ERROR_PENALTY.mul_f64(stats.error_rate())
+}
+
+/// Choose the replica with the lowest score, preferring the leader on ties. Stores without a score
+/// are chosen first, so that all stores are measured.
+fn select_replica(
+    leader: &metapb::Peer,
+    candidates: Vec<(metapb::Peer, String, Option<Duration>)>,
+) -> Option<(metapb::Peer, String)> {
+    candidates
+        .into_iter()
+        .min_by_key(|(peer, _, score)| (score.unwrap_or_default(), peer != leader))
+        .map(|(peer, address, _)| (peer, address))
+}
+
 impl PdRpcClient<TikvConnect, Cluster> {
     pub async fn connect(
         pd_endpoints: &[String],
         config: &Config,
         enable_codec: bool,
     ) -> Result<PdRpcClient> {
-        PdRpcClient::new(
+        let mut client = PdRpcClient::new(
             config,
             |env, security_mgr| TikvConnect::new(env, security_mgr, config.timeout),
             |env, security_mgr| {
-                RetryClient::connect(env, pd_endpoints, security_mgr, config.timeout)
+                RetryClient::connect_shared(env, pd_endpoints, security_mgr, config)
             },
             enable_codec,
         )
-        .await
+        .await?;
+        client.features = match config.features {
+            Some(features) => features,
+            // Detecting the features would connect to the cluster.
+            None if config.lazy_connect => Features::default(),
+            None => {
+                let stores = client.pd.clone().get_all_stores(None).await?;
+                let version = ClusterVersion::min_of_stores(&stores);
+                info!(target: "tikv_client::pd", ?version, "detected cluster version");
+                let mut features = Features::for_version(version);
+                if matches!(version, Some(version) if version >= API_V2_VERSION) {
+                    let http = HttpClient::new(
+                        client.kv_connect.security_mgr().clone(),
+                        client.pd.timeout(),
+                    )?;
+                    features.api_v2 = detect_api_v2(&http, &stores).await?;
+                }
+                features
+            }
+        };
+        if config.hot_region_diagnostics {
+            client.hot_region_cache = Some(Arc::new(HotRegionCache::new(client.pd.clone())));
+        }
+        Ok(client)
+    }
+
+    /// The regions PD currently finds hot for reads or writes.
+    pub async fn hot_regions(&self) -> Result<Vec<HotRegion>> {
+        hot::fetch(&self.pd).await
+    }
+
+    /// Create a keyspace in PD's keyspace service.
+    pub async fn create_keyspace(
+        &self,
+        name: &str,
+        config: &HashMap<String, String>,
+    ) -> Result<Keyspace> {
+        keyspace::create(&self.pd, name, config).await
+    }
+
+    /// The keyspaces registered in PD.
+    pub async fn keyspaces(&self) -> Result<Vec<Keyspace>> {
+        keyspace::list(&self.pd, self.backoff_observer.as_ref()).await
+    }
+
+    /// The keyspace registered in PD as `name`.
+    pub async fn keyspace(&self, name: &str) -> Result<Keyspace> {
+        keyspace::get(&self.pd, name, self.backoff_observer.as_ref()).await
+    }
+
+    /// Create a resource group in PD's resource manager.
+    pub async fn create_resource_group(&self, group: &ResourceGroup) -> Result<()> {
+        resource_group::create(&self.pd, group, self.backoff_observer.as_ref()).await
+    }
+
+    /// Replace the settings of a resource group in PD's resource manager.
+    pub async fn update_resource_group(&self, group: &ResourceGroup) -> Result<()> {
+        resource_group::update(&self.pd, group, self.backoff_observer.as_ref()).await
+    }
+
+    /// The resource groups registered in PD.
+    pub async fn resource_groups(&self) -> Result<Vec<ResourceGroup>> {
+        resource_group::list(&self.pd, self.backoff_observer.as_ref()).await
+    }
+
+    /// The resource group registered in PD as `name`.
+    pub async fn resource_group(&self, name: &str) -> Result<ResourceGroup> {
+        resource_group::get(&self.pd, name, self.backoff_observer.as_ref()).await
+    }
+
+    /// Delete a resource group from PD's resource manager.
+    pub async fn delete_resource_group(&self, name: &str) -> Result<()> {
+        resource_group::delete(&self.pd, name, self.backoff_observer.as_ref()).await
+    }
+
+    /// The stores of the cluster, as registered in PD.
+    pub async fn stores(&self) -> Result<Vec<StoreInfo>> {
+        let stores = self
+            .pd
+            .clone()
+            .get_all_stores(self.backoff_observer.as_ref())
+            .await?;
+        Ok(stores.into_iter().map(StoreInfo).collect())
+    }
+
+    /// Connect to the change data capture service of a store.
+    pub(crate) async fn change_data_client(&self, store_id: StoreId) -> Result<ChangeDataClient> {
+        let store = self.store(store_id).await?;
+        self.kv_connect.connect_change_data(store.get_address())
+    }
+
+    /// The approximate size of the data of `region` in `cf`, as measured by the store of its
+    /// leader.
+    pub(crate) async fn approximate_region_size(
+        &self,
+        region: &Region,
+        cf: &ColumnFamily,
+    ) -> Result<u64> {
+        let store = self.store(region.get_store_id()?).await?;
+        let client = self.kv_connect.connect_debug(store.get_address())?;
+        let mut request = debugpb::RegionSizeRequest::default();
+        request.set_region_id(region.id());
+        request.set_cfs(vec![cf.to_string()]);
+        let response = client.region_size_async(&request)?.await?;
+        Ok(response.entries.iter().map(|entry| entry.size).sum())
+    }
+}
+
+/// A TiKV store of a cluster, see [`stores`](crate::TransactionClient::stores).
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreInfo(metapb::Store);
+
+impl StoreInfo {
+    pub fn id(&self) -> StoreId {
+        self.0.id
+    }
+
+    /// The address clients connect to.
+    pub fn address(&self) -> &str {
+        &self.0.address
+    }
+
+    /// The version of TiKV the store runs.
+    pub fn version(&self) -> &str {
+        &self.0.version
+    }
+
+    /// Whether the store is up, rather than being taken offline or removed.
+    pub fn is_up(&self) -> bool {
+        self.0.get_state() == metapb::StoreState::Up
+    }
+
+    /// The labels of the store, e.g. its zone and host, as key-value pairs.
+    pub fn labels(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .labels
+            .iter()
+            .map(|label| (label.key.as_str(), label.value.as_str()))
     }
 }
 
@@ -276,7 +825,7 @@ impl<KvC: KvConnect + Send + Sync + 'static, Cl> PdRpcClient<KvC, Cl> {
         enable_codec: bool,
     ) -> Result<PdRpcClient<KvC, Cl>>
     where
-        PdFut: Future<Output = Result<RetryClient<Cl>>>,
+        PdFut: Future<Output = Result<Arc<RetryClient<Cl>>>>,
         MakeKvC: FnOnce(Arc<Environment>, Arc<SecurityManager>) -> KvC,
         MakePd: FnOnce(Arc<Environment>, Arc<SecurityManager>) -> PdFut,
     {
@@ -295,22 +844,151 @@ impl<KvC: KvConnect + Send + Sync + 'static, Cl> PdRpcClient<KvC, Cl> {
                 SecurityManager::default()
             },
         );
+        // PD may be secured with a different identity than the stores.
+        let pd_security_mgr = if let (Some(ca_path), Some(cert_path), Some(key_path)) = (
+            &config.pd_ca_path,
+            &config.pd_cert_path,
+            &config.pd_key_path,
+        ) {
+            Arc::new(SecurityManager::load(ca_path, cert_path, key_path)?)
+        } else {
+            security_mgr.clone()
+        };
 
-        let pd = Arc::new(pd(env.clone(), security_mgr.clone()).await?);
+        let pd = pd(env.clone(), pd_security_mgr).await?;
         let kv_client_cache = Default::default();
         Ok(PdRpcClient {
             pd,
             kv_client_cache,
             kv_connect: kv_connect(env, security_mgr),
             enable_codec,
+            features: config.features.unwrap_or_default(),
+            metadata: RequestMetadata::default(),
+            request_source: config.request_source.clone(),
+            resource_group: config.resource_group.clone(),
+            request_units: RequestUnitRecorder::new(config.resource_group.clone()),
+            priority: None,
+            isolation_level: None,
+            replica_read: false,
+            region_cache: Default::default(),
+            hot_region_cache: None,
+            retry_options: config.retry_options(false),
+            pessimistic_retry_options: config.retry_options(true),
+            backoff_observer: None,
         })
     }
 
+    /// Creates a client sharing the PD connection and store connections of `self`, but
+    /// with its own key encoding.
+    pub fn with_codec(&self, enable_codec: bool) -> PdRpcClient<KvC, Cl>
+    where
+        KvC: Clone,
+    {
+        PdRpcClient {
+            pd: self.pd.clone(),
+            kv_connect: self.kv_connect.clone(),
+            kv_client_cache: self.kv_client_cache.clone(),
+            enable_codec,
+            features: self.features,
+            metadata: self.metadata.clone(),
+            request_source: self.request_source.clone(),
+            resource_group: self.resource_group.clone(),
+            request_units: self.request_units.clone(),
+            priority: self.priority,
+            isolation_level: self.isolation_level,
+            replica_read: self.replica_read,
+            region_cache: self.region_cache.clone(),
+            hot_region_cache: self.hot_region_cache.clone(),
+            retry_options: self.retry_options.clone(),
+            pessimistic_retry_options: self.pessimistic_retry_options.clone(),
+            backoff_observer: self.backoff_observer.clone(),
+        }
+    }
+
+    /// Creates a client sharing the connections of `self`, which sends `metadata` with every
+    /// request to TiKV.
+    pub fn with_metadata(&self, metadata: RequestMetadata) -> PdRpcClient<KvC, Cl>
+    where
+        KvC: Clone,
+    {
+        PdRpcClient {
+            metadata,
+            ..self.with_codec(self.enable_codec)
+        }
+    }
+
+    /// Creates a client sharing the connections of `self`, which tags every request to TiKV
+    /// with `source`.
+    pub fn with_request_source(&self, source: String) -> PdRpcClient<KvC, Cl>
+    where
+        KvC: Clone,
+    {
+        PdRpcClient {
+            request_source: Some(source),
+            ..self.with_codec(self.enable_codec)
+        }
+    }
+
+    /// Creates a client sharing the connections of `self`, which charges every request to TiKV
+    /// to the resource group `group`.
+    pub fn with_resource_group(&self, group: String) -> PdRpcClient<KvC, Cl>
+    where
+        KvC: Clone,
+    {
+        PdRpcClient {
+            request_units: self.request_units.with_group(group.clone()),
+            resource_group: Some(group),
+            ..self.with_codec(self.enable_codec)
+        }
+    }
+
+    /// Creates a client sharing the connections of `self`, which reads from the replica of each
+    /// region with the best observed latency and error rate if `replica_read` is set.
+    ///
+    /// The client must only send reads, except through [`leader_client`](PdClient::leader_client).
+    pub fn with_replica_read(&self, replica_read: bool) -> PdRpcClient<KvC, Cl>
+    where
+        KvC: Clone,
+    {
+        PdRpcClient {
+            replica_read,
+            ..self.with_codec(self.enable_codec)
+        }
+    }
+
+    /// Creates a client sharing the connections of `self`, which reports the retries of its
+    /// requests to `observer`.
+    pub fn with_backoff_observer(&self, observer: BackoffObserver) -> PdRpcClient<KvC, Cl>
+    where
+        KvC: Clone,
+    {
+        PdRpcClient {
+            backoff_observer: Some(observer),
+            ..self.with_codec(self.enable_codec)
+        }
+    }
+
+    /// The request units consumed by the requests of the client, and of the clients sharing its
+    /// connections, by resource group.
+    pub fn request_units(&self) -> HashMap<String, f64> {
+        self.request_units.get()
+    }
+
+    /// The statistics of the requests sent to every store connected to, by store address.
+    pub fn store_stats(&self) -> HashMap<String, StoreStats> {
+        self.kv_client_cache
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(address, client)| Some((address.clone(), client.stats()?)))
+            .collect()
+    }
+
     fn kv_client(&self, address: &str) -> Result<KvC::KvClient> {
         if let Some(client) = self.kv_client_cache.read().unwrap().get(address) {
             return Ok(client.clone());
         };
-        info!("connect to tikv endpoint: {:?}", address);
+        info!(target: "tikv_client::store", address, "connecting to store");
         self.kv_connect.connect(address).map(|client| {
             self.kv_client_cache
                 .write()
@@ -328,6 +1006,43 @@ pub mod test {
 
     use futures::{executor, executor::block_on};
 
+    #[test]
+    fn test_chunk_boundaries() {
+        let ends: Vec<Key> = (1..10u8).map(|i| vec![i].into()).collect();
+        assert_eq!(chunk_boundaries(ends.clone(), 1), Vec::<Key>::new());
+        assert_eq!(chunk_boundaries(ends.clone(), 0), Vec::<Key>::new());
+        assert_eq!(chunk_boundaries(ends.clone(), 20), ends);
+        assert_eq!(
+            chunk_boundaries(ends.clone(), 3),
+            vec![ends[2].clone(), ends[5].clone()]
+        );
+        assert_eq!(
+            chunk_boundaries(ends.clone(), 4),
+            vec![ends[1].clone(), ends[4].clone(), ends[6].clone()]
+        );
+        assert_eq!(chunk_boundaries(Vec::new(), 4), Vec::<Key>::new());
+    }
+
+    #[test]
+    fn test_region_aligned_boundaries() {
+        let client = MockPdClient::default();
+        let range: BoundRange = (vec![1], vec![250, 250]).into();
+        let boundaries = block_on(client.region_aligned_boundaries(range.clone(), 4)).unwrap();
+        assert_eq!(boundaries, vec![Key::from(vec![10])]);
+        assert_eq!(
+            range.clone().split_at(boundaries),
+            vec![
+                BoundRange::from((vec![1], vec![10])),
+                BoundRange::from((vec![10], vec![250, 250]))
+            ]
+        );
+        let boundaries = block_on(client.region_aligned_boundaries(range, 1)).unwrap();
+        assert!(boundaries.is_empty());
+        let range: BoundRange = (vec![1], vec![10]).into();
+        let boundaries = block_on(client.region_aligned_boundaries(range, 4)).unwrap();
+        assert!(boundaries.is_empty());
+    }
+
     #[test]
     fn test_kv_client_caching() {
         let client = block_on(pd_rpc_client());
@@ -342,6 +1057,79 @@ pub mod test {
         assert_eq!(kv2.addr, kv3.addr);
     }
 
+    #[test]
+    fn test_request_source() {
+        let mut store = mock_store();
+        store.region = MockPdClient::region1();
+        assert_eq!(store.context().unwrap().get_request_source(), "");
+
+        store.request_source = Some("backfill".to_owned());
+        let context = store.context().unwrap();
+        assert_eq!(context.get_region_id(), 1);
+        assert_eq!(context.get_request_source(), "backfill");
+        assert_eq!(
+            context
+                .get_resource_control_context()
+                .get_resource_group_name(),
+            ""
+        );
+        assert_eq!(context.get_priority(), kvrpcpb::CommandPri::Normal);
+
+        store.priority = Some(kvrpcpb::CommandPri::High);
+        assert_eq!(
+            store.context().unwrap().get_priority(),
+            kvrpcpb::CommandPri::High
+        );
+        assert_eq!(
+            store.context().unwrap().get_isolation_level(),
+            kvrpcpb::IsolationLevel::Si
+        );
+
+        store.isolation_level = Some(kvrpcpb::IsolationLevel::Rc);
+        assert_eq!(
+            store.context().unwrap().get_isolation_level(),
+            kvrpcpb::IsolationLevel::Rc
+        );
+
+        store.resource_group = Some("tenant_a".to_owned());
+        assert_eq!(
+            store
+                .context()
+                .unwrap()
+                .get_resource_control_context()
+                .get_resource_group_name(),
+            "tenant_a"
+        );
+    }
+
+    #[test]
+    fn test_select_replica() {
+        let peer = |id| metapb::Peer {
+            id,
+            store_id: id,
+            ..Default::default()
+        };
+        let candidate =
+            |id, millis: Option<u64>| (peer(id), id.to_string(), millis.map(Duration::from_millis));
+        let selected = |candidates| select_replica(&peer(1), candidates).map(|(p, _)| p.id);
+
+        assert_eq!(selected(vec![]), None);
+        assert_eq!(
+            selected(vec![candidate(1, Some(10)), candidate(2, Some(5))]),
+            Some(2)
+        );
+        // The leader wins ties.
+        assert_eq!(
+            selected(vec![candidate(2, Some(5)), candidate(1, Some(5))]),
+            Some(1)
+        );
+        // Unmeasured stores are tried first.
+        assert_eq!(
+            selected(vec![candidate(1, Some(5)), candidate(3, None)]),
+            Some(3)
+        );
+    }
+
     #[test]
     fn test_group_keys_by_region() {
         let client = MockPdClient::default();
@@ -395,6 +1183,16 @@ pub mod test {
         assert!(stream.next().is_none());
     }
 
+    #[test]
+    fn test_warmup() {
+        let client = Arc::new(MockPdClient::default());
+        let range = |start: u8, end: u8| BoundRange::from((vec![start], vec![end]));
+        let warmup = |ranges| executor::block_on(client.clone().warmup(ranges)).unwrap();
+        assert_eq!(warmup(vec![range(1, 5), range(2, 8)]), 1);
+        assert_eq!(warmup(vec![range(1, 5), range(8, 12)]), 2);
+        assert_eq!(warmup(vec![]), 0);
+    }
+
     #[test]
     fn test_group_ranges_by_region() {
         let client = Arc::new(MockPdClient::default());
@@ -0,0 +1,251 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! The hot regions of a cluster, as reported by PD.
+//!
+//! PD only serves its hot region statistics over its HTTP API, which is reached with the
+//! certificates of the gRPC connection to PD if TLS is configured.
+
+use super::RetryClient;
+use crate::{Error, RegionId, Result, StoreId};
+use serde_derive::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tikv_client_common::internal_err;
+
+// How long the hot regions used to annotate errors are cached.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Whether a region is hot because of reads or writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HotRegionKind {
+    Read,
+    Write,
+}
+
+impl HotRegionKind {
+    fn path(self) -> &'static str {
+        match self {
+            HotRegionKind::Read => "/pd/api/v1/hotspot/regions/read",
+            HotRegionKind::Write => "/pd/api/v1/hotspot/regions/write",
+        }
+    }
+}
+
+/// A region PD found hot, with the flow through its leader.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HotRegion {
+    region_id: RegionId,
+    store_id: StoreId,
+    kind: HotRegionKind,
+    bytes_per_sec: f64,
+    keys_per_sec: f64,
+    hot_degree: i64,
+}
+
+impl HotRegion {
+    pub fn region_id(&self) -> RegionId {
+        self.region_id
+    }
+
+    /// The store of the leader of the region.
+    pub fn store_id(&self) -> StoreId {
+        self.store_id
+    }
+
+    pub fn kind(&self) -> HotRegionKind {
+        self.kind
+    }
+
+    /// The bytes read or written per second.
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes_per_sec
+    }
+
+    /// The keys read or written per second.
+    pub fn keys_per_sec(&self) -> f64 {
+        self.keys_per_sec
+    }
+
+    /// For how many consecutive reports to PD the region has been hot.
+    pub fn hot_degree(&self) -> i64 {
+        self.hot_degree
+    }
+}
+
+impl fmt::Display for HotRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hot on store {}, {:.0} bytes/s, {:.0} keys/s",
+            match self.kind {
+                HotRegionKind::Read => "read",
+                HotRegionKind::Write => "write",
+            },
+            self.store_id,
+            self.bytes_per_sec,
+            self.keys_per_sec
+        )
+    }
+}
+
+/// Fetch the regions PD currently finds hot for reads or writes.
+pub async fn fetch(pd: &RetryClient) -> Result<Vec<HotRegion>> {
+    let url = leader_url(pd).await?;
+    let mut regions = Vec::new();
+    for kind in [HotRegionKind::Read, HotRegionKind::Write] {
+        let report: HotRegionReport = pd.http().get(&url, kind.path()).await?;
+        regions.extend(report.into_hot_regions(kind));
+    }
+    Ok(regions)
+}
+
+/// The hot regions of a cluster, to annotate errors with. The regions are refreshed in the
+/// background at most once a minute, when an error is annotated with stale regions.
+pub struct HotRegionCache {
+    pd: Arc<RetryClient>,
+    regions: Arc<Mutex<CachedRegions>>,
+}
+
+#[derive(Default)]
+struct CachedRegions {
+    refreshed: Option<Instant>,
+    regions: HashMap<RegionId, HotRegion>,
+}
+
+impl HotRegionCache {
+    pub fn new(pd: Arc<RetryClient>) -> HotRegionCache {
+        HotRegionCache {
+            pd,
+            regions: Default::default(),
+        }
+    }
+
+    /// Annotate `error`, of a request to `region_id`, if the region is hot.
+    pub fn annotate(&self, region_id: RegionId, error: Error) -> Error {
+        match self.get(region_id) {
+            Some(region) => Error::HotRegion {
+                region_id,
+                diagnostics: region.to_string(),
+                source: Box::new(error),
+            },
+            None => error,
+        }
+    }
+
+    fn get(&self, region_id: RegionId) -> Option<HotRegion> {
+        let mut cached = self.regions.lock().unwrap();
+        let fresh =
+            matches!(cached.refreshed, Some(refreshed) if refreshed.elapsed() < REFRESH_INTERVAL);
+        if !fresh {
+            // Refresh at most once per interval, even if refreshing fails.
+            cached.refreshed = Some(Instant::now());
+            let pd = self.pd.clone();
+            let regions = self.regions.clone();
+            crate::util::task::spawn("hot-region-refresh", async move {
+                match fetch(&pd).await {
+                    Ok(hot) => {
+                        let mut by_id = HashMap::new();
+                        for region in hot {
+                            by_id.entry(region.region_id).or_insert(region);
+                        }
+                        regions.lock().unwrap().regions = by_id;
+                    }
+                    Err(e) => {
+                        warn!(target: "tikv_client::pd", error = %e, "failed to fetch hot regions")
+                    }
+                }
+            });
+        }
+        cached.regions.get(&region_id).cloned()
+    }
+}
+
+/// A hot region report of PD.
+#[derive(Deserialize)]
+struct HotRegionReport {
+    // The statistics of the regions by the store of their leader.
+    #[serde(default)]
+    as_leader: Option<HashMap<String, StoreHotRegions>>,
+}
+
+#[derive(Deserialize)]
+struct StoreHotRegions {
+    #[serde(default)]
+    statistics: Option<Vec<HotRegionStat>>,
+}
+
+#[derive(Deserialize)]
+struct HotRegionStat {
+    region_id: RegionId,
+    store_id: StoreId,
+    #[serde(default)]
+    flow_bytes: f64,
+    #[serde(default)]
+    flow_keys: f64,
+    #[serde(default)]
+    hot_degree: i64,
+}
+
+impl HotRegionReport {
+    fn into_hot_regions(self, kind: HotRegionKind) -> Vec<HotRegion> {
+        self.as_leader
+            .unwrap_or_default()
+            .into_values()
+            .flat_map(|store| store.statistics.unwrap_or_default())
+            .map(|stat| HotRegion {
+                region_id: stat.region_id,
+                store_id: stat.store_id,
+                kind,
+                bytes_per_sec: stat.flow_bytes,
+                keys_per_sec: stat.flow_keys,
+                hot_degree: stat.hot_degree,
+            })
+            .collect()
+    }
+}
+
+/// The client URL of the PD leader, whose HTTP API serves the statistics and services of PD.
+pub(super) async fn leader_url(pd: &RetryClient) -> Result<String> {
+    pd.leader_url()
+        .await
+        .ok_or_else(|| internal_err!("PD leader has no client URL"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_hot_regions() {
+        let report: HotRegionReport = serde_json::from_str(
+            r#"{"as_peer": {}, "as_leader": {"1": {"regions_count": 1, "statistics": [
+                {"store_id": 1, "region_id": 8, "hot_degree": 3, "flow_bytes": 1024.25,
+                 "flow_keys": 10}
+            ]}, "2": {"statistics": null}}}"#,
+        )
+        .unwrap();
+        let regions = report.into_hot_regions(HotRegionKind::Write);
+        assert_eq!(
+            regions,
+            vec![HotRegion {
+                region_id: 8,
+                store_id: 1,
+                kind: HotRegionKind::Write,
+                bytes_per_sec: 1024.25,
+                keys_per_sec: 10.0,
+                hot_degree: 3,
+            }]
+        );
+        assert_eq!(
+            regions[0].to_string(),
+            "write hot on store 1, 1024 bytes/s, 10 keys/s"
+        );
+
+        let report: HotRegionReport = serde_json::from_str(r#"{"as_leader": null}"#).unwrap();
+        assert!(report.into_hot_regions(HotRegionKind::Read).is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Requests to the HTTP APIs of PD and TiKV, for the statistics and configuration they don't
+//! serve over gRPC.
+
+use crate::{Result, SecurityManager};
+use reqwest::Method;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{sync::Arc, time::Duration};
+use tikv_client_common::internal_err;
+
+/// A client of the HTTP APIs of PD or TiKV, secured like the gRPC connections to them.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    security_mgr: Arc<SecurityManager>,
+}
+
+impl HttpClient {
+    pub fn new(security_mgr: Arc<SecurityManager>, timeout: Duration) -> Result<HttpClient> {
+        Ok(HttpClient {
+            client: security_mgr.http_client(timeout)?,
+            security_mgr,
+        })
+    }
+
+    /// Get `path` from the server at `addr`, and decode the JSON response.
+    pub async fn get<T: DeserializeOwned>(&self, addr: &str, path: &str) -> Result<T> {
+        self.send(Method::GET, addr, path, None::<&()>).await
+    }
+
+    /// Send a request with an optional JSON body, and decode the JSON response.
+    pub async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        addr: &str,
+        path: &str,
+        body: Option<&impl Serialize>,
+    ) -> Result<T> {
+        let url = format!("{}{}", self.security_mgr.http_url(addr), path);
+        let mut request = self.client.request(method, &url);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| internal_err!("request to {} failed: {}", url, e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(internal_err!(
+                "request to {} failed with {}: {}",
+                url,
+                status,
+                message.trim()
+            ));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| internal_err!("malformed response from {}: {}", url, e))
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! The keyspaces of a cluster, managed through the keyspace service of PD.
+//!
+//! Keyspaces are loaded and listed over the gRPC connection to PD. The keyspace service has no
+//! RPC to create keyspaces, so they are created through the HTTP API of the PD leader, which is
+//! reached with the certificates of the gRPC connection if TLS is configured.
+
+use super::{hot::leader_url, RetryClient};
+use crate::{backoff::BackoffObserver, Result};
+use reqwest::Method;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, sync::Arc};
+use tikv_client_common::internal_err;
+use tikv_client_proto::keyspacepb;
+
+const KEYSPACES_PATH: &str = "/pd/api/v2/keyspaces";
+// The number of keyspaces listed in each request.
+const PAGE_SIZE: u32 = 100;
+
+/// The state of a keyspace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyspaceState {
+    Enabled,
+    Disabled,
+    Archived,
+    Tombstone,
+}
+
+impl KeyspaceState {
+    fn parse(state: &str) -> Option<KeyspaceState> {
+        match state {
+            "ENABLED" => Some(KeyspaceState::Enabled),
+            "DISABLED" => Some(KeyspaceState::Disabled),
+            "ARCHIVED" => Some(KeyspaceState::Archived),
+            "TOMBSTONE" => Some(KeyspaceState::Tombstone),
+            _ => None,
+        }
+    }
+}
+
+impl From<keyspacepb::KeyspaceState> for KeyspaceState {
+    fn from(state: keyspacepb::KeyspaceState) -> KeyspaceState {
+        match state {
+            keyspacepb::KeyspaceState::Enabled => KeyspaceState::Enabled,
+            keyspacepb::KeyspaceState::Disabled => KeyspaceState::Disabled,
+            keyspacepb::KeyspaceState::Archived => KeyspaceState::Archived,
+            keyspacepb::KeyspaceState::Tombstone => KeyspaceState::Tombstone,
+        }
+    }
+}
+
+impl fmt::Display for KeyspaceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self {
+            KeyspaceState::Enabled => "enabled",
+            KeyspaceState::Disabled => "disabled",
+            KeyspaceState::Archived => "archived",
+            KeyspaceState::Tombstone => "tombstone",
+        };
+        f.write_str(state)
+    }
+}
+
+/// A keyspace registered in PD.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyspace {
+    id: u32,
+    name: String,
+    state: KeyspaceState,
+    created_at: i64,
+    state_changed_at: i64,
+    config: HashMap<String, String>,
+}
+
+impl Keyspace {
+    /// The ID PD assigned to the keyspace, which prefixes its keys in API V2 clusters.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> KeyspaceState {
+        self.state
+    }
+
+    /// When the keyspace was created, in seconds since the Unix epoch.
+    pub fn created_at(&self) -> i64 {
+        self.created_at
+    }
+
+    /// When the state of the keyspace last changed, in seconds since the Unix epoch.
+    pub fn state_changed_at(&self) -> i64 {
+        self.state_changed_at
+    }
+
+    pub fn config(&self) -> &HashMap<String, String> {
+        &self.config
+    }
+
+    fn from_meta(meta: keyspacepb::KeyspaceMeta) -> Result<Keyspace> {
+        let state = keyspacepb::KeyspaceState::from_i32(meta.state).ok_or_else(|| {
+            internal_err!("keyspace {} has unknown state {}", meta.name, meta.state)
+        })?;
+        Ok(Keyspace {
+            id: meta.id,
+            name: meta.name,
+            state: state.into(),
+            created_at: meta.created_at,
+            state_changed_at: meta.state_changed_at,
+            config: meta.config,
+        })
+    }
+}
+
+/// Create a keyspace named `name` with `config`.
+pub async fn create(
+    pd: &RetryClient,
+    name: &str,
+    config: &HashMap<String, String>,
+) -> Result<Keyspace> {
+    let url = leader_url(pd).await?;
+    let request = CreateKeyspaceRequest { name, config };
+    let keyspace: KeyspaceMeta = pd
+        .http()
+        .send(Method::POST, &url, KEYSPACES_PATH, Some(&request))
+        .await?;
+    keyspace.try_into_keyspace()
+}
+
+/// The keyspace named `name`.
+pub async fn get(
+    pd: &Arc<RetryClient>,
+    name: &str,
+    observer: Option<&BackoffObserver>,
+) -> Result<Keyspace> {
+    let meta = pd.clone().load_keyspace(name.to_owned(), observer).await?;
+    Keyspace::from_meta(meta)
+}
+
+/// All keyspaces of the cluster, in the order of their IDs.
+pub async fn list(
+    pd: &Arc<RetryClient>,
+    observer: Option<&BackoffObserver>,
+) -> Result<Vec<Keyspace>> {
+    let mut keyspaces = Vec::new();
+    let mut start_id = 0;
+    loop {
+        let page = pd
+            .clone()
+            .get_all_keyspaces(start_id, PAGE_SIZE, observer)
+            .await?;
+        let last_page = page.len() < PAGE_SIZE as usize;
+        for meta in page {
+            keyspaces.push(Keyspace::from_meta(meta)?);
+        }
+        match keyspaces.last().and_then(|last| last.id.checked_add(1)) {
+            Some(next_id) if !last_page && next_id > start_id => start_id = next_id,
+            _ => return Ok(keyspaces),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CreateKeyspaceRequest<'a> {
+    name: &'a str,
+    config: &'a HashMap<String, String>,
+}
+
+/// A keyspace as reported by the HTTP API of PD.
+#[derive(Deserialize)]
+struct KeyspaceMeta {
+    #[serde(default)]
+    id: u32,
+    name: String,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    created_at: i64,
+    #[serde(default)]
+    state_changed_at: i64,
+    #[serde(default)]
+    config: Option<HashMap<String, String>>,
+}
+
+impl KeyspaceMeta {
+    fn try_into_keyspace(self) -> Result<Keyspace> {
+        let state = KeyspaceState::parse(&self.state).ok_or_else(|| {
+            internal_err!("keyspace {} has unknown state {:?}", self.name, self.state)
+        })?;
+        Ok(Keyspace {
+            id: self.id,
+            name: self.name,
+            state,
+            created_at: self.created_at,
+            state_changed_at: self.state_changed_at,
+            config: self.config.unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keyspace_from_meta() {
+        let mut meta = keyspacepb::KeyspaceMeta::default();
+        meta.set_id(1);
+        meta.set_name("tenant_a".to_owned());
+        meta.state = keyspacepb::KeyspaceState::Archived as i32;
+        meta.set_created_at(1_690_000_000);
+        meta.set_state_changed_at(1_690_000_001);
+        meta.mut_config()
+            .insert("gc_life_time".to_owned(), "600".to_owned());
+        let keyspace = Keyspace::from_meta(meta.clone()).unwrap();
+        assert_eq!(keyspace.id(), 1);
+        assert_eq!(keyspace.name(), "tenant_a");
+        assert_eq!(keyspace.state(), KeyspaceState::Archived);
+        assert_eq!(keyspace.created_at(), 1_690_000_000);
+        assert_eq!(keyspace.state_changed_at(), 1_690_000_001);
+        assert_eq!(
+            keyspace.config().get("gc_life_time").map(String::as_str),
+            Some("600")
+        );
+
+        meta.state = 42;
+        assert!(Keyspace::from_meta(meta).is_err());
+    }
+
+    #[test]
+    fn test_parse_created_keyspace() {
+        let created: KeyspaceMeta = serde_json::from_str(
+            r#"{"id": 2, "name": "tenant_b", "state": "ENABLED", "created_at": 1690000002,
+                "state_changed_at": 1690000003}"#,
+        )
+        .unwrap();
+        let keyspace = created.try_into_keyspace().unwrap();
+        assert_eq!(keyspace.id(), 2);
+        assert_eq!(keyspace.state(), KeyspaceState::Enabled);
+        assert!(keyspace.config().is_empty());
+
+        let invalid: KeyspaceMeta =
+            serde_json::from_str(r#"{"id": 1, "name": "a", "state": "GONE"}"#).unwrap();
+        assert!(invalid.try_into_keyspace().is_err());
+    }
+}
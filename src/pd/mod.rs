@@ -1,5 +1,16 @@
+mod cache;
 mod client;
+mod hot;
+mod http;
+mod keyspace;
+mod resource_group;
 mod retry;
+mod srv;
+mod status;
 
-pub use client::{PdClient, PdRpcClient};
+pub use cache::RegionCache;
+pub use client::{PdClient, PdRpcClient, StoreInfo};
+pub use hot::{HotRegion, HotRegionCache, HotRegionKind};
+pub use keyspace::{Keyspace, KeyspaceState};
+pub use resource_group::{RequestUnitRecorder, ResourceGroup};
 pub use retry::RetryClient;
@@ -0,0 +1,275 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! The resource groups of a cluster, managed through the resource manager of PD.
+//!
+//! Requests are charged to a resource group in request units (RU), which abstract the CPU, IO and
+//! network they use. TiKV throttles the requests of each group to the rate of RU configured for
+//! it. The resource manager is served over the gRPC connection to the PD leader.
+
+use super::RetryClient;
+use crate::{backoff::BackoffObserver, Result};
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tikv_client_proto::{kvrpcpb, resource_manager};
+
+const MEDIUM_PRIORITY: u32 = 8;
+// The group requests are charged to when they don't name one.
+const DEFAULT_GROUP: &str = "default";
+
+/// A resource group, limiting the rate of request units of the requests charged to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceGroup {
+    name: String,
+    ru_per_sec: u64,
+    burst_limit: i64,
+    priority: u32,
+}
+
+impl ResourceGroup {
+    /// A group named `name` refilled with `ru_per_sec` request units per second, at medium
+    /// priority, which can't burst above its rate.
+    pub fn new(name: impl Into<String>, ru_per_sec: u64) -> ResourceGroup {
+        ResourceGroup {
+            name: name.into(),
+            ru_per_sec,
+            burst_limit: 0,
+            priority: MEDIUM_PRIORITY,
+        }
+    }
+
+    /// The number of request units the group can accumulate while idle and use in a burst, or
+    /// `-1` to let the group use spare resources without limit.
+    pub fn with_burst_limit(mut self, burst_limit: i64) -> ResourceGroup {
+        self.burst_limit = burst_limit;
+        self
+    }
+
+    /// The priority of the requests of the group when resources are contended: 1 is low, 8
+    /// medium and 16 high.
+    pub fn with_priority(mut self, priority: u32) -> ResourceGroup {
+        assert!(priority <= 16, "priority of a resource group is at most 16");
+        self.priority = priority;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ru_per_sec(&self) -> u64 {
+        self.ru_per_sec
+    }
+
+    pub fn burst_limit(&self) -> i64 {
+        self.burst_limit
+    }
+
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn to_proto(&self) -> resource_manager::ResourceGroup {
+        let mut group = resource_manager::ResourceGroup::default();
+        group.set_name(self.name.clone());
+        // Resource groups are limited by request units, rather than by raw resources.
+        group.mode = resource_manager::GroupMode::RuMode as i32;
+        group.set_priority(self.priority);
+        let limits = group.mut_r_u_settings().mut_r_u().mut_settings();
+        limits.set_fill_rate(self.ru_per_sec);
+        limits.set_burst_limit(self.burst_limit);
+        group
+    }
+}
+
+impl From<resource_manager::ResourceGroup> for ResourceGroup {
+    fn from(mut group: resource_manager::ResourceGroup) -> ResourceGroup {
+        let limits = group.get_r_u_settings().get_r_u().get_settings();
+        ResourceGroup {
+            ru_per_sec: limits.get_fill_rate(),
+            burst_limit: limits.get_burst_limit(),
+            // Versions of PD without priorities report none.
+            priority: match group.get_priority() {
+                0 => MEDIUM_PRIORITY,
+                priority => priority,
+            },
+            name: group.take_name(),
+        }
+    }
+}
+
+/// Create the resource group `group`.
+pub async fn create(
+    pd: &Arc<RetryClient>,
+    group: &ResourceGroup,
+    observer: Option<&BackoffObserver>,
+) -> Result<()> {
+    pd.clone()
+        .put_resource_group(group.to_proto(), false, observer)
+        .await
+}
+
+/// Replace the settings of the existing resource group named like `group`.
+pub async fn update(
+    pd: &Arc<RetryClient>,
+    group: &ResourceGroup,
+    observer: Option<&BackoffObserver>,
+) -> Result<()> {
+    pd.clone()
+        .put_resource_group(group.to_proto(), true, observer)
+        .await
+}
+
+/// The resource group named `name`.
+pub async fn get(
+    pd: &Arc<RetryClient>,
+    name: &str,
+    observer: Option<&BackoffObserver>,
+) -> Result<ResourceGroup> {
+    let group = pd
+        .clone()
+        .get_resource_group(name.to_owned(), observer)
+        .await?;
+    Ok(group.into())
+}
+
+/// All resource groups of the cluster.
+pub async fn list(
+    pd: &Arc<RetryClient>,
+    observer: Option<&BackoffObserver>,
+) -> Result<Vec<ResourceGroup>> {
+    let groups = pd.clone().list_resource_groups(observer).await?;
+    Ok(groups.into_iter().map(Into::into).collect())
+}
+
+/// Delete the resource group named `name`. Requests charged to it fall back to the default
+/// group.
+pub async fn delete(
+    pd: &Arc<RetryClient>,
+    name: &str,
+    observer: Option<&BackoffObserver>,
+) -> Result<()> {
+    pd.clone()
+        .delete_resource_group(name.to_owned(), observer)
+        .await
+}
+
+/// Accumulates the request units TiKV reports consumed by the requests of a client, by resource
+/// group. Shared by the clients derived from the client.
+#[derive(Clone, Debug)]
+pub struct RequestUnitRecorder {
+    group: String,
+    consumed: Arc<Mutex<HashMap<String, f64>>>,
+}
+
+impl RequestUnitRecorder {
+    /// A recorder charging requests to `group`, or to the default group.
+    pub fn new(group: Option<String>) -> RequestUnitRecorder {
+        RequestUnitRecorder {
+            group: group.unwrap_or_else(|| DEFAULT_GROUP.to_owned()),
+            consumed: Default::default(),
+        }
+    }
+
+    /// A recorder sharing the counters of `self`, which charges requests to `group`.
+    pub fn with_group(&self, group: String) -> RequestUnitRecorder {
+        RequestUnitRecorder {
+            group,
+            consumed: self.consumed.clone(),
+        }
+    }
+
+    /// Record the request units reported by `response`, if any.
+    pub fn record(&self, response: &dyn Any) {
+        let units = request_units(response);
+        if units > 0.0 {
+            *self
+                .consumed
+                .lock()
+                .unwrap()
+                .entry(self.group.clone())
+                .or_default() += units;
+        }
+    }
+
+    /// The request units consumed so far, by resource group.
+    pub fn get(&self) -> HashMap<String, f64> {
+        self.consumed.lock().unwrap().clone()
+    }
+}
+
+/// The request units TiKV reports consumed by a request in its response. Only the responses
+/// carrying execution details report them.
+fn request_units(response: &dyn Any) -> f64 {
+    macro_rules! exec_details {
+        ($($type:ty),*) => {
+            $(
+                if let Some(response) = response.downcast_ref::<$type>() {
+                    return response
+                        .exec_details_v2
+                        .as_ref()
+                        .map_or(0.0, |details| details.ru_v2);
+                }
+            )*
+        };
+    }
+    exec_details!(
+        kvrpcpb::GetResponse,
+        kvrpcpb::BatchGetResponse,
+        kvrpcpb::ScanResponse,
+        kvrpcpb::PrewriteResponse,
+        kvrpcpb::CommitResponse,
+        kvrpcpb::PessimisticLockResponse
+    );
+    0.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resource_group_proto() {
+        let group = ResourceGroup::new("tenant_a", 2000)
+            .with_burst_limit(-1)
+            .with_priority(16);
+        let proto = group.to_proto();
+        assert_eq!(proto.mode, resource_manager::GroupMode::RuMode as i32);
+        assert_eq!(ResourceGroup::from(proto), group);
+
+        let mut proto = resource_manager::ResourceGroup::default();
+        proto.set_name("default".to_owned());
+        proto
+            .mut_r_u_settings()
+            .mut_r_u()
+            .mut_settings()
+            .set_fill_rate(1000);
+        assert_eq!(
+            ResourceGroup::from(proto),
+            ResourceGroup::new("default", 1000)
+        );
+    }
+
+    #[test]
+    fn test_record_request_units() {
+        let response = |ru_v2| kvrpcpb::GetResponse {
+            exec_details_v2: Some(kvrpcpb::ExecDetailsV2 {
+                ru_v2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let recorder = RequestUnitRecorder::new(None);
+        let tenant_a = recorder.with_group("tenant_a".to_owned());
+        recorder.record(&response(1.5));
+        tenant_a.record(&response(2.0));
+        tenant_a.record(&response(0.5));
+        tenant_a.record(&kvrpcpb::RawGetResponse::default());
+        let consumed = recorder.get();
+        assert_eq!(consumed.len(), 2);
+        assert_eq!(consumed["default"], 1.5);
+        assert_eq!(consumed["tenant_a"], 2.5);
+    }
+}
@@ -2,34 +2,89 @@
 
 //! A utility module for managing and retrying PD requests.
 
-use crate::{stats::pd_stats, Error, Region, RegionId, Result, SecurityManager, StoreId};
+use super::{http::HttpClient, srv};
+use crate::{
+    backoff::BackoffObserver,
+    config::{DEFAULT_PD_MAX_RECONNECTS, DEFAULT_PD_MAX_RETRIES, DEFAULT_PD_RECONNECT_INTERVAL},
+    stats::pd_stats,
+    Backoff, Config, Error, Region, RegionId, Result, RetryKind, SecurityManager, StoreId,
+};
 use async_trait::async_trait;
+use futures::{
+    future::{self, Either},
+    prelude::*,
+};
 use futures_timer::Delay;
 use grpcio::Environment;
 use std::{
+    collections::HashMap,
     fmt,
-    sync::Arc,
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
     time::{Duration, Instant},
 };
+use tikv_client_common::internal_err;
 use tikv_client_pd::{Cluster, Connection};
 use tikv_client_proto::{
-    metapb,
+    keyspacepb, metapb,
     pdpb::{self, Timestamp},
+    resource_manager,
 };
 use tokio::sync::RwLock;
 
-// FIXME: these numbers and how they are used are all just cargo-culted in, there
-// may be more optimal values.
-const RECONNECT_INTERVAL_SEC: u64 = 1;
-const MAX_REQUEST_COUNT: usize = 3;
-const LEADER_CHANGE_RETRY: usize = 10;
+// The longest delay between reconnects, in multiples of the reconnect interval.
+const MAX_RECONNECT_DELAY_FACTOR: u32 = 16;
+
+/// How requests to PD are retried, see [`Config::with_pd_retries`](Config::with_pd_retries).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct RetryLimits {
+    max_retries: usize,
+    max_reconnects: usize,
+    reconnect_interval: Duration,
+}
+
+impl RetryLimits {
+    /// The delays between the reconnects after a failed request: at least the reconnect interval,
+    /// doubling after each reconnect, with jitter so that clients which lost PD at the same time
+    /// don't all reconnect at the same time.
+    fn reconnect_backoff(&self) -> Backoff {
+        let interval_ms = self.reconnect_interval.as_millis() as u64;
+        if interval_ms == 0 {
+            return Backoff::no_jitter_backoff(0, 0, u32::MAX);
+        }
+        // Equal jitter waits between half the delay and the delay.
+        Backoff::equal_jitter_backoff(
+            interval_ms * 2,
+            interval_ms * MAX_RECONNECT_DELAY_FACTOR as u64,
+            u32::MAX,
+        )
+    }
+}
+
+impl Default for RetryLimits {
+    fn default() -> RetryLimits {
+        RetryLimits {
+            max_retries: DEFAULT_PD_MAX_RETRIES,
+            max_reconnects: DEFAULT_PD_MAX_RECONNECTS,
+            reconnect_interval: DEFAULT_PD_RECONNECT_INTERVAL,
+        }
+    }
+}
 
 /// Client for communication with a PD cluster. Has the facility to reconnect to the cluster.
 pub struct RetryClient<Cl = Cluster> {
-    // Tuple is the cluster and the time of the cluster's last reconnect.
-    cluster: RwLock<(Cl, Instant)>,
+    // Tuple is the cluster and the time of the cluster's last reconnect. The cluster is `None`
+    // until the first request when connecting lazily.
+    cluster: RwLock<(Option<Cl>, Instant)>,
     connection: Connection,
+    // The client of the HTTP API of PD, for the services PD doesn't serve over gRPC.
+    http: HttpClient,
     timeout: Duration,
+    tso_timeout: Duration,
+    // The endpoints given, and the name whose SRV records are resolved to more endpoints.
+    endpoints: Vec<String>,
+    pd_srv: Option<String>,
+    limits: RetryLimits,
 }
 
 #[cfg(test)]
@@ -40,71 +95,251 @@ impl<Cl> RetryClient<Cl> {
         timeout: Duration,
         cluster: Cl,
     ) -> RetryClient<Cl> {
+        let http = HttpClient::new(security_mgr.clone(), timeout).unwrap();
         let connection = Connection::new(env, security_mgr);
         RetryClient {
-            cluster: RwLock::new((cluster, Instant::now())),
+            cluster: RwLock::new((Some(cluster), Instant::now())),
             connection,
+            http,
             timeout,
+            tso_timeout: timeout,
+            endpoints: Vec::new(),
+            pd_srv: None,
+            limits: RetryLimits::default(),
         }
     }
 }
 
 macro_rules! retry {
-    ($self: ident, $tag: literal, |$cluster: ident| $call: expr) => {{
+    ($self: ident, $observer: expr, $tag: literal, |$cluster: ident| $call: expr) => {{
+        let observer: Option<&BackoffObserver> = $observer;
         let stats = pd_stats($tag);
-        let mut last_err = Ok(());
-        for _ in 0..LEADER_CHANGE_RETRY {
+        let limits = $self.limits();
+        $self.ensure_connected().await?;
+        let mut last_err = None;
+        for attempt in 1..=limits.max_retries {
             // use the block here to drop the guard of the read lock,
             // otherwise `reconnect` will try to acquire the write lock and results in a deadlock
             let res = {
-                let $cluster = &$self.cluster.read().await.0;
-                let res = $call.await;
-                res
+                let guard = $self.cluster.read().await;
+                match &guard.0 {
+                    Some($cluster) => $call.await,
+                    None => Err(internal_err!("not connected to PD")),
+                }
             };
 
             match stats.done(res) {
                 Ok(r) => return Ok(r),
-                Err(e) => last_err = Err(e),
+                // Reconnecting can't bring back the cluster, and retrying may reach the other one.
+                Err(e @ Error::ClusterIdMismatch { .. }) => return Err(e),
+                Err(e) => {
+                    if attempt < limits.max_retries {
+                        observe_retry(observer, attempt, Duration::ZERO, &e);
+                    }
+                    last_err = Some(e);
+                }
             }
 
-            let mut reconnect_count = MAX_REQUEST_COUNT;
-            while let Err(e) = $self.reconnect(RECONNECT_INTERVAL_SEC).await {
-                reconnect_count -= 1;
-                if reconnect_count == 0 {
+            let mut reconnects = 0;
+            let mut backoff = limits.reconnect_backoff();
+            while let Err(e) = $self.reconnect(limits.reconnect_interval).await {
+                reconnects += 1;
+                if reconnects >= limits.max_reconnects {
                     return Err(e);
                 }
-                Delay::new(Duration::from_secs(RECONNECT_INTERVAL_SEC)).await;
+                if let Some(delay) = backoff.next_delay_duration() {
+                    observe_retry(observer, reconnects, delay, &e);
+                    Delay::new(delay).await;
+                }
             }
         }
 
-        last_err?;
-        unreachable!();
+        Err(last_err.unwrap_or_else(|| internal_err!("no request was sent to PD")))
     }};
 }
 
+/// Report a failed request to PD, or a failed reconnect, which is about to be retried after
+/// `delay` to `observer` if any. `attempt` counts the requests, or the reconnects after a request.
+fn observe_retry(
+    observer: Option<&BackoffObserver>,
+    attempt: usize,
+    delay: Duration,
+    error: &Error,
+) {
+    if let Some(observer) = observer {
+        observer(RetryKind::Pd, attempt as u32, delay, error);
+    }
+}
+
+/// The settings a PD client is connected with. Clients connected with the same settings can share
+/// their connections.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct SharingKey {
+    endpoints: Vec<String>,
+    pd_srv: Option<String>,
+    pd_token: Option<String>,
+    // The paths of the CA, certificate and key PD is connected with.
+    security: Option<(PathBuf, PathBuf, PathBuf)>,
+    timeout: Duration,
+    tso_timeout: Duration,
+    limits: RetryLimits,
+}
+
+impl SharingKey {
+    fn new(endpoints: &[String], config: &Config) -> SharingKey {
+        let pd_security = match (
+            &config.pd_ca_path,
+            &config.pd_cert_path,
+            &config.pd_key_path,
+        ) {
+            (Some(ca), Some(cert), Some(key)) => Some((ca.clone(), cert.clone(), key.clone())),
+            _ => None,
+        };
+        let security =
+            pd_security.or_else(
+                || match (&config.ca_path, &config.cert_path, &config.key_path) {
+                    (Some(ca), Some(cert), Some(key)) => {
+                        Some((ca.clone(), cert.clone(), key.clone()))
+                    }
+                    _ => None,
+                },
+            );
+        let mut endpoints = endpoints.to_vec();
+        endpoints.sort();
+        endpoints.dedup();
+        SharingKey {
+            endpoints,
+            pd_srv: config.pd_srv.clone(),
+            pd_token: config.pd_token.clone(),
+            security,
+            timeout: config.pd_timeout(),
+            tso_timeout: config.pd_tso_timeout(),
+            limits: RetryLimits::from(config),
+        }
+    }
+}
+
+impl From<&Config> for RetryLimits {
+    fn from(config: &Config) -> RetryLimits {
+        RetryLimits {
+            // The fields of the config can be set without the checks of `with_pd_retries`.
+            max_retries: config.pd_max_retries.max(1),
+            max_reconnects: config.pd_max_reconnects.max(1),
+            reconnect_interval: config.pd_reconnect_interval,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    // The PD clients in use, by the settings they were connected with.
+    static ref SHARED_CLIENTS: Mutex<HashMap<SharingKey, Weak<RetryClient>>> = Default::default();
+}
+
 impl RetryClient<Cluster> {
+    /// A client connected to the PD cluster of `endpoints`, shared with the other clients in use
+    /// which were connected with the same endpoints and PD settings.
+    ///
+    /// Sharing a client shares its gRPC channels and timestamp oracle stream, and makes the
+    /// clients reconnect once, rather than each on its own, when PD fails over.
+    pub async fn connect_shared(
+        env: Arc<Environment>,
+        endpoints: &[String],
+        security_mgr: Arc<SecurityManager>,
+        config: &Config,
+    ) -> Result<Arc<RetryClient>> {
+        let key = SharingKey::new(endpoints, config);
+        let client = match shared_client(&key) {
+            Some(client) => client,
+            None => {
+                let client =
+                    Arc::new(RetryClient::connect(env, endpoints, security_mgr, config).await?);
+                let mut shared = SHARED_CLIENTS.lock().unwrap();
+                shared.retain(|_, client| client.strong_count() > 0);
+                // Another client may have connected in the meantime, keep only one of them.
+                match shared.get(&key).and_then(Weak::upgrade) {
+                    Some(existing) => existing,
+                    None => {
+                        shared.insert(key, Arc::downgrade(&client));
+                        client
+                    }
+                }
+            }
+        };
+        // The client shared may have been connected lazily and not be connected yet.
+        if !config.lazy_connect {
+            client.ensure_connected().await?;
+        }
+        Ok(client)
+    }
+
+    /// A client connected to the PD cluster of `endpoints`, or which connects on its first
+    /// request if `config` asks to connect lazily.
     pub async fn connect(
         env: Arc<Environment>,
         endpoints: &[String],
         security_mgr: Arc<SecurityManager>,
-        timeout: Duration,
+        config: &Config,
     ) -> Result<RetryClient> {
-        let connection = Connection::new(env, security_mgr);
-        let cluster = RwLock::new((
-            connection.connect_cluster(endpoints, timeout).await?,
-            Instant::now(),
-        ));
-        Ok(RetryClient {
-            cluster,
+        let http = HttpClient::new(security_mgr.clone(), config.pd_timeout())?;
+        let mut connection = Connection::new(env, security_mgr);
+        if let Some(token) = &config.pd_token {
+            connection = connection.with_token(token.clone());
+        }
+        let client = RetryClient {
+            cluster: RwLock::new((None, Instant::now())),
             connection,
-            timeout,
-        })
+            http,
+            timeout: config.pd_timeout(),
+            tso_timeout: config.pd_tso_timeout(),
+            endpoints: endpoints.to_vec(),
+            pd_srv: config.pd_srv.clone(),
+            limits: RetryLimits::from(config),
+        };
+        if !config.lazy_connect {
+            client.ensure_connected().await?;
+        }
+        Ok(client)
+    }
+
+    /// Connect to the PD cluster of the endpoints, resolving them again.
+    async fn connect_cluster(&self) -> Result<Cluster> {
+        let endpoints =
+            resolve_endpoints(&self.endpoints, self.pd_srv.as_deref(), self.timeout).await?;
+        self.connection
+            .connect_cluster(&endpoints, self.timeout)
+            .await
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The client of the HTTP API of PD.
+    pub(super) fn http(&self) -> &HttpClient {
+        &self.http
+    }
+
+    /// A client URL of the current leader.
+    pub async fn leader_url(&self) -> Option<String> {
+        self.ensure_connected().await.ok()?;
+        self.cluster
+            .read()
+            .await
+            .0
+            .as_ref()?
+            .leader_urls()
+            .first()
+            .cloned()
     }
 
     // These get_* functions will try multiple times to make a request, reconnecting as necessary.
     // It does not know about encoding. Caller should take care of it.
-    pub async fn get_region(self: Arc<Self>, key: Vec<u8>) -> Result<Region> {
-        retry!(self, "get_region", |cluster| {
+    pub async fn get_region(
+        self: Arc<Self>,
+        key: Vec<u8>,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<Region> {
+        retry!(self, observer, "get_region", |cluster| {
             let key = key.clone();
             async {
                 cluster
@@ -117,8 +352,12 @@ impl RetryClient<Cluster> {
         })
     }
 
-    pub async fn get_region_by_id(self: Arc<Self>, region_id: RegionId) -> Result<Region> {
-        retry!(self, "get_region_by_id", |cluster| async {
+    pub async fn get_region_by_id(
+        self: Arc<Self>,
+        region_id: RegionId,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<Region> {
+        retry!(self, observer, "get_region_by_id", |cluster| async {
             cluster
                 .get_region_by_id(region_id, self.timeout)
                 .await
@@ -126,8 +365,12 @@ impl RetryClient<Cluster> {
         })
     }
 
-    pub async fn get_store(self: Arc<Self>, id: StoreId) -> Result<metapb::Store> {
-        retry!(self, "get_store", |cluster| async {
+    pub async fn get_store(
+        self: Arc<Self>,
+        id: StoreId,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<metapb::Store> {
+        retry!(self, observer, "get_store", |cluster| async {
             cluster
                 .get_store(id, self.timeout)
                 .await
@@ -135,9 +378,11 @@ impl RetryClient<Cluster> {
         })
     }
 
-    #[allow(dead_code)]
-    pub async fn get_all_stores(self: Arc<Self>) -> Result<Vec<metapb::Store>> {
-        retry!(self, "get_all_stores", |cluster| async {
+    pub async fn get_all_stores(
+        self: Arc<Self>,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<Vec<metapb::Store>> {
+        retry!(self, observer, "get_all_stores", |cluster| async {
             cluster
                 .get_all_stores(self.timeout)
                 .await
@@ -145,28 +390,171 @@ impl RetryClient<Cluster> {
         })
     }
 
-    pub async fn get_timestamp(self: Arc<Self>) -> Result<Timestamp> {
-        retry!(self, "get_timestamp", |cluster| cluster.get_timestamp())
+    pub async fn get_timestamp(
+        self: Arc<Self>,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<Timestamp> {
+        retry!(self, observer, "get_timestamp", |cluster| with_timeout(
+            self.tso_timeout,
+            cluster.get_timestamp()
+        ))
     }
 
-    pub async fn update_safepoint(self: Arc<Self>, safepoint: u64) -> Result<bool> {
-        retry!(self, "update_gc_safepoint", |cluster| async {
+    pub async fn get_safepoint(self: Arc<Self>, observer: Option<&BackoffObserver>) -> Result<u64> {
+        retry!(self, observer, "get_gc_safepoint", |cluster| async {
+            cluster
+                .get_safepoint(self.timeout)
+                .await
+                .map(|resp| resp.get_safe_point())
+        })
+    }
+
+    pub async fn update_safepoint(
+        self: Arc<Self>,
+        safepoint: u64,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<bool> {
+        retry!(self, observer, "update_gc_safepoint", |cluster| async {
             cluster
                 .update_safepoint(safepoint, self.timeout)
                 .await
                 .map(|resp| resp.get_new_safe_point() == safepoint)
         })
     }
+
+    pub async fn update_service_safepoint(
+        self: Arc<Self>,
+        service_id: String,
+        ttl: Duration,
+        safepoint: u64,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<u64> {
+        retry!(
+            self,
+            observer,
+            "update_service_gc_safepoint",
+            |cluster| async {
+                cluster
+                    .update_service_safepoint(
+                        service_id.clone(),
+                        ttl.as_secs() as i64,
+                        safepoint,
+                        self.timeout,
+                    )
+                    .await
+                    .map(|resp| resp.get_min_safe_point())
+            }
+        )
+    }
+
+    pub async fn load_keyspace(
+        self: Arc<Self>,
+        name: String,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<keyspacepb::KeyspaceMeta> {
+        retry!(self, observer, "load_keyspace", |cluster| async {
+            cluster
+                .load_keyspace(name.clone(), self.timeout)
+                .await
+                .map(|mut resp| resp.take_keyspace())
+        })
+    }
+
+    /// At most `limit` keyspaces, in the order of their IDs, starting at `start_id`.
+    pub async fn get_all_keyspaces(
+        self: Arc<Self>,
+        start_id: u32,
+        limit: u32,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<Vec<keyspacepb::KeyspaceMeta>> {
+        retry!(self, observer, "get_all_keyspaces", |cluster| async {
+            cluster
+                .get_all_keyspaces(start_id, limit, self.timeout)
+                .await
+                .map(|mut resp| resp.take_keyspaces())
+        })
+    }
+
+    pub async fn list_resource_groups(
+        self: Arc<Self>,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<Vec<resource_manager::ResourceGroup>> {
+        retry!(self, observer, "list_resource_groups", |cluster| async {
+            cluster
+                .list_resource_groups(self.timeout)
+                .await
+                .map(|mut resp| resp.take_groups())
+        })
+    }
+
+    pub async fn get_resource_group(
+        self: Arc<Self>,
+        name: String,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<resource_manager::ResourceGroup> {
+        retry!(self, observer, "get_resource_group", |cluster| async {
+            cluster
+                .get_resource_group(name.clone(), self.timeout)
+                .await
+                .map(|mut resp| resp.take_group())
+        })
+    }
+
+    /// Add `group`, or replace the settings of the group of the same name if it `exists`.
+    pub async fn put_resource_group(
+        self: Arc<Self>,
+        group: resource_manager::ResourceGroup,
+        exists: bool,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<()> {
+        retry!(self, observer, "put_resource_group", |cluster| async {
+            cluster
+                .put_resource_group(group.clone(), exists, self.timeout)
+                .await
+                .map(|_| ())
+        })
+    }
+
+    pub async fn delete_resource_group(
+        self: Arc<Self>,
+        name: String,
+        observer: Option<&BackoffObserver>,
+    ) -> Result<()> {
+        retry!(self, observer, "delete_resource_group", |cluster| async {
+            cluster
+                .delete_resource_group(name.clone(), self.timeout)
+                .await
+                .map(|_| ())
+        })
+    }
 }
 
 impl fmt::Debug for RetryClient {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("pd::RetryClient")
             .field("timeout", &self.timeout)
+            .field("tso_timeout", &self.tso_timeout)
             .finish()
     }
 }
 
+fn shared_client(key: &SharingKey) -> Option<Arc<RetryClient>> {
+    SHARED_CLIENTS
+        .lock()
+        .unwrap()
+        .get(key)
+        .and_then(Weak::upgrade)
+}
+
+/// Run `request`, failing if it doesn't finish within `timeout`.
+async fn with_timeout<T>(timeout: Duration, request: impl Future<Output = Result<T>>) -> Result<T> {
+    futures::pin_mut!(request);
+    match future::select(request, Delay::new(timeout)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(Error::OperationTimeout(timeout)),
+    }
+}
+
 fn region_from_response(
     resp: pdpb::GetRegionResponse,
     err: impl FnOnce() -> Error,
@@ -179,28 +567,99 @@ fn region_from_response(
 #[async_trait]
 trait Reconnect {
     type Cl;
-    async fn reconnect(&self, interval_sec: u64) -> Result<()>;
+    async fn reconnect(&self, interval: Duration) -> Result<()>;
+
+    /// Connect, unless already connected.
+    async fn ensure_connected(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn limits(&self) -> RetryLimits {
+        RetryLimits::default()
+    }
 }
 
 #[async_trait]
 impl Reconnect for RetryClient<Cluster> {
     type Cl = Cluster;
 
-    async fn reconnect(&self, interval_sec: u64) -> Result<()> {
+    fn limits(&self) -> RetryLimits {
+        self.limits
+    }
+
+    async fn ensure_connected(&self) -> Result<()> {
+        if self.cluster.read().await.0.is_some() {
+            return Ok(());
+        }
+        let mut lock = self.cluster.write().await;
+        // Another request may have connected while this one waited for the lock.
+        if lock.0.is_none() {
+            let cluster = self.connect_cluster().await?;
+            info!(target: "tikv_client::pd", cluster_id = cluster.id(), "connected to PD");
+            *lock = (Some(cluster), Instant::now());
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&self, interval: Duration) -> Result<()> {
         let reconnect_begin = Instant::now();
         let mut lock = self.cluster.write().await;
         let (cluster, last_connected) = &mut *lock;
-        // If `last_connected + interval_sec` is larger or equal than reconnect_begin,
+        let cluster = match cluster {
+            Some(cluster) => cluster,
+            None => {
+                *cluster = Some(self.connect_cluster().await?);
+                *last_connected = Instant::now();
+                return Ok(());
+            }
+        };
+        // If `last_connected + interval` is larger or equal than reconnect_begin,
         // a concurrent reconnect is just succeed when this thread trying to get write lock
-        let should_connect = reconnect_begin > *last_connected + Duration::from_secs(interval_sec);
+        let should_connect = reconnect_begin > *last_connected + interval;
         if should_connect {
-            self.connection.reconnect(cluster, self.timeout).await?;
+            if let Err(e) = self.connection.reconnect(cluster, self.timeout).await {
+                // The members known may all have been replaced, so look for new ones.
+                if self.pd_srv.is_none() {
+                    return Err(e);
+                }
+                warn!(
+                    target: "tikv_client::pd",
+                    error = %e,
+                    "failed to reconnect to PD members, resolving endpoints again"
+                );
+                let new_cluster = self.connect_cluster().await?;
+                if new_cluster.id() != cluster.id() {
+                    return Err(Error::ClusterIdMismatch {
+                        expected: cluster.id(),
+                        actual: new_cluster.id(),
+                    });
+                }
+                *cluster = new_cluster;
+            }
             *last_connected = Instant::now();
         }
         Ok(())
     }
 }
 
+/// The endpoints given, followed by those resolved from the SRV records of `pd_srv`, if any.
+async fn resolve_endpoints(
+    endpoints: &[String],
+    pd_srv: Option<&str>,
+    timeout: Duration,
+) -> Result<Vec<String>> {
+    let mut endpoints = endpoints.to_vec();
+    if let Some(name) = pd_srv {
+        for endpoint in srv::resolve(name, timeout).await? {
+            if !endpoints.contains(&endpoint) {
+                endpoints.push(endpoint);
+            }
+        }
+        info!(target: "tikv_client::pd", ?endpoints, name, "resolved PD endpoints");
+    }
+    Ok(endpoints)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -212,14 +671,14 @@ mod test {
     fn test_reconnect() {
         struct MockClient {
             reconnect_count: Mutex<usize>,
-            cluster: RwLock<((), Instant)>,
+            cluster: RwLock<(Option<()>, Instant)>,
         }
 
         #[async_trait]
         impl Reconnect for MockClient {
             type Cl = ();
 
-            async fn reconnect(&self, _: u64) -> Result<()> {
+            async fn reconnect(&self, _: Duration) -> Result<()> {
                 *self.reconnect_count.lock().unwrap() += 1;
                 // Not actually unimplemented, we just don't care about the error.
                 Err(Error::Unimplemented)
@@ -227,39 +686,232 @@ mod test {
         }
 
         async fn retry_err(client: Arc<MockClient>) -> Result<()> {
-            retry!(client, "test", |_c| ready(Err(internal_err!("whoops"))))
+            retry!(client, None, "test", |_c| ready(Err(internal_err!(
+                "whoops"
+            ))))
         }
 
         async fn retry_ok(client: Arc<MockClient>) -> Result<()> {
-            retry!(client, "test", |_c| ready(Ok::<_, Error>(())))
+            retry!(client, None, "test", |_c| ready(Ok::<_, Error>(())))
+        }
+
+        async fn retry_observed(
+            client: Arc<MockClient>,
+            observed: Arc<Mutex<Vec<(RetryKind, u32, bool)>>>,
+        ) -> Result<()> {
+            let observer: BackoffObserver = Arc::new(move |kind, attempt, delay, _| {
+                observed
+                    .lock()
+                    .unwrap()
+                    .push((kind, attempt, delay > Duration::ZERO))
+            });
+            retry!(client, Some(&observer), "test", |_c| ready(Err(
+                internal_err!("whoops")
+            )))
+        }
+
+        async fn retry_mismatch(client: Arc<MockClient>) -> Result<()> {
+            retry!(client, None, "test", |_c| ready(Err(
+                Error::ClusterIdMismatch {
+                    expected: 1,
+                    actual: 2
+                }
+            )))
         }
 
         executor::block_on(async {
             let client = Arc::new(MockClient {
                 reconnect_count: Mutex::new(0),
-                cluster: RwLock::new(((), Instant::now())),
+                cluster: RwLock::new((Some(()), Instant::now())),
             });
 
             assert!(retry_err(client.clone()).await.is_err());
-            assert_eq!(*client.reconnect_count.lock().unwrap(), MAX_REQUEST_COUNT);
+            assert_eq!(
+                *client.reconnect_count.lock().unwrap(),
+                DEFAULT_PD_MAX_RECONNECTS
+            );
+
+            // The failed request and the failed reconnects but the last are reported.
+            *client.reconnect_count.lock().unwrap() = 0;
+            let observed = Arc::new(Mutex::new(Vec::new()));
+            assert!(retry_observed(client.clone(), observed.clone())
+                .await
+                .is_err());
+            assert_eq!(
+                *observed.lock().unwrap(),
+                vec![
+                    (RetryKind::Pd, 1, false),
+                    (RetryKind::Pd, 1, true),
+                    (RetryKind::Pd, 2, true)
+                ]
+            );
 
             *client.reconnect_count.lock().unwrap() = 0;
             assert!(retry_ok(client.clone()).await.is_ok());
             assert_eq!(*client.reconnect_count.lock().unwrap(), 0);
+
+            // Requests answered by another cluster fail right away.
+            assert!(matches!(
+                retry_mismatch(client.clone()).await,
+                Err(Error::ClusterIdMismatch {
+                    expected: 1,
+                    actual: 2
+                })
+            ));
+            assert_eq!(*client.reconnect_count.lock().unwrap(), 0);
+        })
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let limits = RetryLimits {
+            reconnect_interval: Duration::from_millis(100),
+            ..RetryLimits::default()
+        };
+        let mut backoff = limits.reconnect_backoff();
+        let delays: Vec<Duration> = (0..6)
+            .map(|_| backoff.next_delay_duration().unwrap())
+            .collect();
+        assert!(delays[0] >= Duration::from_millis(100) && delays[0] < Duration::from_millis(200));
+        assert!(delays[1] >= Duration::from_millis(200) && delays[1] < Duration::from_millis(400));
+        assert!(delays
+            .iter()
+            .all(|delay| *delay < Duration::from_millis(1600)));
+
+        let limits = RetryLimits {
+            reconnect_interval: Duration::ZERO,
+            ..RetryLimits::default()
+        };
+        assert_eq!(
+            limits.reconnect_backoff().next_delay_duration(),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_sharing_key() {
+        let config = Config::default();
+        let endpoints = vec!["pd1:2379".to_owned(), "pd2:2379".to_owned()];
+        let reordered = vec!["pd2:2379".to_owned(), "pd1:2379".to_owned()];
+        assert!(SharingKey::new(&endpoints, &config) == SharingKey::new(&reordered, &config));
+        let with_token = config.clone().with_pd_token("secret");
+        assert!(SharingKey::new(&endpoints, &config) != SharingKey::new(&endpoints, &with_token));
+        let with_security = config.with_security("ca", "cert", "key");
+        assert!(
+            SharingKey::new(&endpoints, &with_security)
+                == SharingKey::new(
+                    &endpoints,
+                    &with_security.clone().with_pd_security("ca", "cert", "key")
+                )
+        );
+    }
+
+    #[test]
+    fn test_retry_limits_from_config() {
+        let config = Config {
+            pd_max_retries: 0,
+            pd_max_reconnects: 0,
+            ..Config::default()
+        };
+        let limits = RetryLimits::from(&config);
+        assert_eq!(limits.max_retries, 1);
+        assert_eq!(limits.max_reconnects, 1);
+    }
+
+    #[test]
+    fn test_with_timeout() {
+        let result = executor::block_on(with_timeout(
+            Duration::from_millis(10),
+            future::pending::<Result<()>>(),
+        ));
+        assert!(matches!(result, Err(Error::OperationTimeout(_))));
+        let result = executor::block_on(with_timeout(Duration::from_millis(10), ready(Ok(1))));
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_lazy_connect() {
+        struct MockClient {
+            connects: Mutex<usize>,
+            reachable: Mutex<bool>,
+            cluster: RwLock<(Option<()>, Instant)>,
+        }
+
+        #[async_trait]
+        impl Reconnect for MockClient {
+            type Cl = ();
+
+            async fn reconnect(&self, _: Duration) -> Result<()> {
+                Ok(())
+            }
+
+            async fn ensure_connected(&self) -> Result<()> {
+                let mut lock = self.cluster.write().await;
+                if lock.0.is_none() {
+                    *self.connects.lock().unwrap() += 1;
+                    if !*self.reachable.lock().unwrap() {
+                        return Err(internal_err!("unreachable"));
+                    }
+                    lock.0 = Some(());
+                }
+                Ok(())
+            }
+        }
+
+        async fn request(client: Arc<MockClient>, sent: Arc<Mutex<usize>>) -> Result<()> {
+            retry!(client, None, "test", |_c| {
+                *sent.lock().unwrap() += 1;
+                ready(Ok::<_, Error>(()))
+            })
+        }
+
+        executor::block_on(async {
+            let client = Arc::new(MockClient {
+                connects: Mutex::new(0),
+                reachable: Mutex::new(false),
+                cluster: RwLock::new((None, Instant::now())),
+            });
+            let sent = Arc::new(Mutex::new(0));
+
+            // The first request connects, and fails without being sent if PD is unreachable.
+            assert!(request(client.clone(), sent.clone()).await.is_err());
+            assert_eq!(*client.connects.lock().unwrap(), 1);
+            assert_eq!(*sent.lock().unwrap(), 0);
+
+            // The next request tries to connect again.
+            *client.reachable.lock().unwrap() = true;
+            assert!(request(client.clone(), sent.clone()).await.is_ok());
+            assert!(request(client.clone(), sent.clone()).await.is_ok());
+            assert_eq!(*client.connects.lock().unwrap(), 2);
+            assert_eq!(*sent.lock().unwrap(), 2);
         })
     }
 
+    #[test]
+    fn test_pd_timeouts() {
+        let endpoints = vec!["pd1:2379".to_owned()];
+        let config = Config::default().timeout(Duration::from_secs(5));
+        let key = SharingKey::new(&endpoints, &config);
+        assert_eq!(key.timeout, Duration::from_secs(5));
+        assert_eq!(key.tso_timeout, Duration::from_secs(5));
+
+        let config = config.with_pd_timeouts(Duration::from_secs(1), Duration::from_millis(100));
+        let key = SharingKey::new(&endpoints, &config);
+        assert_eq!(key.timeout, Duration::from_secs(1));
+        assert_eq!(key.tso_timeout, Duration::from_millis(100));
+    }
+
     #[test]
     fn test_retry() {
         struct MockClient {
-            cluster: RwLock<(Mutex<usize>, Instant)>,
+            cluster: RwLock<(Option<Mutex<usize>>, Instant)>,
         }
 
         #[async_trait]
         impl Reconnect for MockClient {
             type Cl = Mutex<usize>;
 
-            async fn reconnect(&self, _: u64) -> Result<()> {
+            async fn reconnect(&self, _: Duration) -> Result<()> {
                 Ok(())
             }
         }
@@ -268,7 +920,7 @@ mod test {
             client: Arc<MockClient>,
             max_retries: Arc<Mutex<usize>>,
         ) -> Result<()> {
-            retry!(client, "test", |c| {
+            retry!(client, None, "test", |c| {
                 let mut c = c.lock().unwrap();
                 *c += 1;
 
@@ -286,7 +938,7 @@ mod test {
             client: Arc<MockClient>,
             max_retries: Arc<Mutex<usize>>,
         ) -> Result<()> {
-            retry!(client, "test", |c| {
+            retry!(client, None, "test", |c| {
                 let mut c = c.lock().unwrap();
                 *c += 1;
 
@@ -302,23 +954,42 @@ mod test {
 
         executor::block_on(async {
             let client = Arc::new(MockClient {
-                cluster: RwLock::new((Mutex::new(0), Instant::now())),
+                cluster: RwLock::new((Some(Mutex::new(0)), Instant::now())),
             });
             let max_retries = Arc::new(Mutex::new(1000));
 
             assert!(retry_max_err(client.clone(), max_retries).await.is_err());
             assert_eq!(
-                *client.cluster.read().await.0.lock().unwrap(),
-                LEADER_CHANGE_RETRY
+                *client
+                    .cluster
+                    .read()
+                    .await
+                    .0
+                    .as_ref()
+                    .unwrap()
+                    .lock()
+                    .unwrap(),
+                DEFAULT_PD_MAX_RETRIES
             );
 
             let client = Arc::new(MockClient {
-                cluster: RwLock::new((Mutex::new(0), Instant::now())),
+                cluster: RwLock::new((Some(Mutex::new(0)), Instant::now())),
             });
             let max_retries = Arc::new(Mutex::new(2));
 
             assert!(retry_max_ok(client.clone(), max_retries).await.is_ok());
-            assert_eq!(*client.cluster.read().await.0.lock().unwrap(), 2);
+            assert_eq!(
+                *client
+                    .cluster
+                    .read()
+                    .await
+                    .0
+                    .as_ref()
+                    .unwrap()
+                    .lock()
+                    .unwrap(),
+                2
+            );
         })
     }
 }
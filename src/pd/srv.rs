@@ -0,0 +1,286 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Discovery of PD endpoints from DNS SRV records.
+//!
+//! Only the small part of DNS needed to look up SRV records over UDP is implemented. The name
+//! servers are read from `/etc/resolv.conf`.
+
+use crate::Result;
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tikv_client_common::internal_err;
+use tokio::net::UdpSocket;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const DNS_PORT: u16 = 53;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+// The largest response which is not truncated over UDP.
+const MAX_RESPONSE_LEN: usize = 512;
+// Bounds the labels followed when decompressing a name, so a malicious response can't loop.
+const MAX_NAME_LABELS: usize = 128;
+
+/// A target of an SRV record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Target {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    host: String,
+}
+
+/// Resolve the SRV records of `name` to `host:port` endpoints, ordered by priority, and by
+/// weight (heaviest first) within a priority.
+pub async fn resolve(name: &str, timeout: Duration) -> Result<Vec<String>> {
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u16)
+        .unwrap_or_default();
+    let query = encode_query(id, name)?;
+
+    let mut last_err = internal_err!("no name server to resolve {}", name);
+    for server in name_servers() {
+        match query_server(server, &query, timeout).await {
+            Ok(response) => match decode_response(id, &response) {
+                Ok(mut targets) => {
+                    if targets.is_empty() {
+                        return Err(internal_err!("no SRV records for {}", name));
+                    }
+                    targets.sort_by(|a, b| {
+                        a.priority
+                            .cmp(&b.priority)
+                            .then_with(|| b.weight.cmp(&a.weight))
+                    });
+                    return Ok(targets
+                        .into_iter()
+                        .map(|t| format!("{}:{}", t.host, t.port))
+                        .collect());
+                }
+                Err(e) => last_err = e,
+            },
+            Err(e) => {
+                warn!(
+                    target: "tikv_client::pd",
+                    %server,
+                    name,
+                    error = %e,
+                    "name server failed to resolve PD endpoints"
+                );
+                last_err = e;
+            }
+        }
+    }
+    Err(last_err)
+}
+
+fn name_servers() -> Vec<SocketAddr> {
+    let servers = fs::read_to_string(RESOLV_CONF)
+        .map(|conf| parse_resolv_conf(&conf))
+        .unwrap_or_default();
+    if servers.is_empty() {
+        vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DNS_PORT)]
+    } else {
+        servers
+    }
+}
+
+fn parse_resolv_conf(conf: &str) -> Vec<SocketAddr> {
+    conf.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => words.next()?.parse::<IpAddr>().ok(),
+                _ => None,
+            }
+        })
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        .collect()
+}
+
+async fn query_server(server: SocketAddr, query: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+    let local: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(query).await?;
+    let mut buf = vec![0; MAX_RESPONSE_LEN];
+    let len = tokio::time::timeout(timeout, socket.recv(&mut buf))
+        .await
+        .map_err(|_| internal_err!("timed out"))??;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn encode_query(id: u16, name: &str) -> Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(18 + name.len());
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired.
+    query.extend_from_slice(&0x0100u16.to_be_bytes());
+    // One question, no answers or other records.
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(internal_err!("invalid domain name {}", name));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+fn decode_response(id: u16, response: &[u8]) -> Result<Vec<Target>> {
+    let truncated = || internal_err!("truncated DNS response");
+    if response.len() < 12 || read_u16(response, 0)? != id {
+        return Err(internal_err!("unexpected DNS response"));
+    }
+    let flags = read_u16(response, 2)?;
+    if flags & 0x0200 != 0 {
+        return Err(internal_err!(
+            "DNS response truncated, SRV records don't fit in UDP"
+        ));
+    }
+    let rcode = flags & 0x000f;
+    if rcode != 0 {
+        return Err(internal_err!(
+            "DNS query failed with response code {}",
+            rcode
+        ));
+    }
+    let questions = read_u16(response, 4)?;
+    let answers = read_u16(response, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(response, offset)?.1 + 4;
+    }
+    let mut targets = Vec::with_capacity(answers as usize);
+    for _ in 0..answers {
+        offset = read_name(response, offset)?.1;
+        let ty = read_u16(response, offset)?;
+        let class = read_u16(response, offset + 2)?;
+        let len = read_u16(response, offset + 8)? as usize;
+        let data = offset + 10;
+        offset = data + len;
+        if offset > response.len() {
+            return Err(truncated());
+        }
+        // Answers may include the CNAME records the name was resolved through.
+        if ty != TYPE_SRV || class != CLASS_IN {
+            continue;
+        }
+        targets.push(Target {
+            priority: read_u16(response, data)?,
+            weight: read_u16(response, data + 2)?,
+            port: read_u16(response, data + 4)?,
+            host: read_name(response, data + 6)?.0,
+        });
+    }
+    Ok(targets)
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Result<u16> {
+    match buf.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(internal_err!("truncated DNS response")),
+    }
+}
+
+/// Read the possibly compressed name at `offset`, returning the name and the offset after it.
+fn read_name(buf: &[u8], mut offset: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    // The offset after the name, which is after the first pointer followed, if any.
+    let mut end = None;
+    for _ in 0..MAX_NAME_LABELS {
+        let len = *buf
+            .get(offset)
+            .ok_or_else(|| internal_err!("truncated DNS response"))? as usize;
+        if len == 0 {
+            return Ok((labels.join("."), end.unwrap_or(offset + 1)));
+        }
+        if len & 0xc0 == 0xc0 {
+            let pointer = read_u16(buf, offset)? & 0x3fff;
+            end.get_or_insert(offset + 2);
+            offset = pointer as usize;
+            continue;
+        }
+        let label = buf
+            .get(offset + 1..offset + 1 + len)
+            .ok_or_else(|| internal_err!("truncated DNS response"))?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset += 1 + len;
+    }
+    Err(internal_err!("DNS name too long"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let conf = "# comment\nsearch example.com\nnameserver 10.0.0.1\nnameserver ::1\n";
+        assert_eq!(
+            parse_resolv_conf(conf),
+            vec!["10.0.0.1:53".parse().unwrap(), "[::1]:53".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_decode_response() {
+        let query = encode_query(42, "pd.service.consul.").unwrap();
+        assert_eq!(query.len(), 12 + 19 + 4);
+
+        let mut response = query.clone();
+        // A response, with recursion desired and available, and two answers.
+        response[2..4].copy_from_slice(&0x8180u16.to_be_bytes());
+        response[6..8].copy_from_slice(&2u16.to_be_bytes());
+        let answer =
+            |response: &mut Vec<u8>, priority: u16, weight: u16, port: u16, host: &[u8]| {
+                // The name is a pointer to the question.
+                response.extend_from_slice(&[0xc0, 12]);
+                response.extend_from_slice(&TYPE_SRV.to_be_bytes());
+                response.extend_from_slice(&CLASS_IN.to_be_bytes());
+                response.extend_from_slice(&60u32.to_be_bytes());
+                response.extend_from_slice(&(6 + host.len() as u16).to_be_bytes());
+                response.extend_from_slice(&priority.to_be_bytes());
+                response.extend_from_slice(&weight.to_be_bytes());
+                response.extend_from_slice(&port.to_be_bytes());
+                response.extend_from_slice(host);
+            };
+        answer(&mut response, 1, 10, 2379, b"\x03pd1\x00");
+        // The host is `pd2` followed by a pointer to `service.consul`.
+        answer(&mut response, 0, 5, 2380, b"\x03pd2\xc0\x0f");
+
+        assert_eq!(
+            decode_response(42, &response).unwrap(),
+            vec![
+                Target {
+                    priority: 1,
+                    weight: 10,
+                    port: 2379,
+                    host: "pd1".to_owned(),
+                },
+                Target {
+                    priority: 0,
+                    weight: 5,
+                    port: 2380,
+                    host: "pd2.service.consul".to_owned(),
+                },
+            ]
+        );
+        assert!(decode_response(43, &response).is_err());
+        assert!(decode_response(42, &response[..response.len() - 1]).is_err());
+
+        // A pointer to itself.
+        assert!(read_name(&[0xc0, 0], 0).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! The configuration of TiKV stores, read from the HTTP API of their status address.
+
+use super::http::HttpClient;
+use crate::Result;
+use serde_derive::Deserialize;
+use tikv_client_common::internal_err;
+use tikv_client_proto::metapb;
+
+/// The API version TiKV uses unless `storage.api-version` is set.
+const DEFAULT_API_VERSION: u64 = 1;
+
+/// The parts of the configuration of a store the client depends on.
+#[derive(Deserialize)]
+struct StoreConfig {
+    #[serde(default)]
+    storage: StorageConfig,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct StorageConfig {
+    api_version: Option<u64>,
+}
+
+/// The `storage.api-version` the store is configured with.
+pub async fn api_version(http: &HttpClient, store: &metapb::Store) -> Result<u64> {
+    if store.status_address.is_empty() {
+        return Err(internal_err!("store {} has no status address", store.id));
+    }
+    let config: StoreConfig = http.get(&store.status_address, "/config").await?;
+    Ok(config.storage.api_version.unwrap_or(DEFAULT_API_VERSION))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_api_version() {
+        let config: StoreConfig =
+            serde_json::from_str(r#"{"storage":{"api-version":2,"enable-ttl":true}}"#).unwrap();
+        assert_eq!(config.storage.api_version, Some(2));
+        let config: StoreConfig =
+            serde_json::from_str(r#"{"storage":{"enable-ttl":false}}"#).unwrap();
+        assert_eq!(config.storage.api_version, None);
+        let config: StoreConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.storage.api_version, None);
+    }
+}
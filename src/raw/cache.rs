@@ -0,0 +1,219 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use super::cdc::Feed;
+use crate::{
+    pd::{PdClient, PdRpcClient},
+    BoundRange, Key, Result, TimestampExt, Value,
+};
+use futures::prelude::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::RangeBounds,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+const RESTART_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A bounded cache of raw values in a range, invalidated by a change data feed of the range.
+///
+/// Values are only cached and served while the feed is registered on every region of the range.
+/// Whenever the feed fails (e.g. when a region splits or its leader moves), the cache is cleared
+/// and bypassed until the feed has been registered again.
+pub(crate) struct ReadCache {
+    range: BoundRange,
+    entries: Arc<Entries>,
+    feed: JoinHandle<()>,
+}
+
+impl ReadCache {
+    /// Start the feed of `range`, which must be in prefixed keys. Must be called within a Tokio
+    /// runtime.
+    pub fn start(rpc: Arc<PdRpcClient>, range: BoundRange, capacity: usize) -> ReadCache {
+        let entries = Arc::new(Entries::new(capacity));
+        let feed = crate::util::task::spawn(
+            "raw-read-cache-feed",
+            run_feed(rpc, range.clone(), entries.clone()),
+        );
+        ReadCache {
+            range,
+            entries,
+            feed,
+        }
+    }
+
+    /// Get the value of `key` from the cache, or fetch it with `fetch` and cache it.
+    pub async fn get(
+        &self,
+        key: Key,
+        fetch: impl Future<Output = Result<Option<Value>>>,
+    ) -> Result<Option<Value>> {
+        if !self.range.contains(&key) {
+            return fetch.await;
+        }
+        let generation = match self.entries.get(&key) {
+            Ok(value) => return Ok(value),
+            Err(generation) => generation,
+        };
+        let value = fetch.await?;
+        self.entries.insert(key, value.clone(), generation);
+        Ok(value)
+    }
+}
+
+impl Drop for ReadCache {
+    fn drop(&mut self) {
+        self.feed.abort();
+    }
+}
+
+struct Entries {
+    state: Mutex<State>,
+}
+
+struct State {
+    capacity: usize,
+    values: HashMap<Key, (Option<Value>, u64)>,
+    // Cached keys by the tick of their last use, least recently used first.
+    recency: BTreeMap<u64, Key>,
+    tick: u64,
+    // Incremented by every invalidation, so values fetched before an invalidation are not cached
+    // after it.
+    generation: u64,
+    live: bool,
+}
+
+impl Entries {
+    fn new(capacity: usize) -> Entries {
+        Entries {
+            state: Mutex::new(State {
+                capacity,
+                values: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+                generation: 0,
+                live: false,
+            }),
+        }
+    }
+
+    /// The cached value of `key`, or the current generation if it isn't cached.
+    fn get(&self, key: &Key) -> std::result::Result<Option<Value>, u64> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.tick += 1;
+        match state.values.get_mut(key) {
+            Some((value, tick)) => {
+                state.recency.remove(tick);
+                *tick = state.tick;
+                state.recency.insert(state.tick, key.clone());
+                Ok(value.clone())
+            }
+            None => Err(state.generation),
+        }
+    }
+
+    /// Cache a value fetched at `generation`, unless it may have been invalidated since.
+    fn insert(&self, key: Key, value: Option<Value>, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if !state.live || state.generation != generation || state.capacity == 0 {
+            return;
+        }
+        if state.values.len() >= state.capacity {
+            if let Some((_, evicted)) = state.recency.pop_first() {
+                state.values.remove(&evicted);
+            }
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        state.recency.insert(tick, key.clone());
+        state.values.insert(key, (value, tick));
+    }
+
+    fn invalidate(&self, key: &Key) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        if let Some((_, tick)) = state.values.remove(key) {
+            state.recency.remove(&tick);
+        }
+    }
+
+    /// Drop all values, and start or stop caching.
+    fn reset(&self, live: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.values.clear();
+        state.recency.clear();
+        state.live = live;
+    }
+}
+
+async fn run_feed(rpc: Arc<PdRpcClient>, range: BoundRange, entries: Arc<Entries>) {
+    loop {
+        entries.reset(false);
+        if let Err(e) = feed(&rpc, &range, &entries).await {
+            warn!(target: "tikv_client::raw", error = %e, "change data feed of the read cache failed");
+        }
+        tokio::time::sleep(RESTART_INTERVAL).await;
+    }
+}
+
+/// Register the feed on every region of the range and invalidate changed keys until it fails.
+async fn feed(rpc: &Arc<PdRpcClient>, range: &BoundRange, entries: &Entries) -> Result<()> {
+    let checkpoint_ts = rpc.clone().get_timestamp().await?.version();
+    let mut feed = Feed::register(rpc, range, checkpoint_ts).await?;
+    let mut live = false;
+    loop {
+        for row in feed.next().await? {
+            entries.invalidate(&row.key.into());
+        }
+        if !live && feed.is_initialized() {
+            entries.reset(true);
+            live = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entries() {
+        let entries = Entries::new(2);
+        let key = |k: &str| Key::from(k.to_owned());
+
+        // Nothing is cached until the feed is registered.
+        let generation = entries.get(&key("a")).unwrap_err();
+        entries.insert(key("a"), Some(b"1".to_vec()), generation);
+        assert!(entries.get(&key("a")).is_err());
+
+        entries.reset(true);
+        let generation = entries.get(&key("a")).unwrap_err();
+        entries.insert(key("a"), Some(b"1".to_vec()), generation);
+        assert_eq!(entries.get(&key("a")), Ok(Some(b"1".to_vec())));
+
+        // A value fetched before an invalidation is not cached.
+        let generation = entries.get(&key("b")).unwrap_err();
+        entries.invalidate(&key("b"));
+        entries.insert(key("b"), None, generation);
+        assert!(entries.get(&key("b")).is_err());
+
+        let generation = entries.get(&key("b")).unwrap_err();
+        entries.insert(key("b"), None, generation);
+        assert_eq!(entries.get(&key("b")), Ok(None));
+
+        // The least recently used key is evicted.
+        entries.get(&key("a")).unwrap();
+        let generation = entries.get(&key("c")).unwrap_err();
+        entries.insert(key("c"), Some(b"3".to_vec()), generation);
+        assert!(entries.get(&key("b")).is_err());
+        assert!(entries.get(&key("a")).is_ok());
+        assert!(entries.get(&key("c")).is_ok());
+
+        entries.invalidate(&key("a"));
+        assert!(entries.get(&key("a")).is_err());
+        entries.reset(false);
+        assert!(entries.get(&key("c")).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::{
+    pd::{PdClient, PdRpcClient},
+    BoundRange, Key, Region, RegionId, Result, StoreId, Timestamp, Value,
+};
+use futures::{
+    prelude::*,
+    stream::{self, SelectAll},
+};
+use grpcio::{ClientDuplexReceiver, ClientDuplexSender, WriteFlags};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tikv_client_common::internal_err;
+use tikv_client_proto::cdcpb::{
+    self,
+    change_data_request::{self, KvApi},
+    event, ChangeDataEvent, ChangeDataRequest,
+};
+
+/// The TiCDC version the feed identifies as. Change data of raw keys needs at least this version.
+const CDC_VERSION: &str = "6.2.0";
+
+/// A change of a key, received from [`watch`](super::Client::watch).
+#[derive(Clone, Debug, PartialEq)]
+pub enum WatchEvent {
+    /// The key was set to the value.
+    Put {
+        key: Key,
+        value: Value,
+        commit_ts: Timestamp,
+    },
+    /// The key was deleted.
+    Delete { key: Key, commit_ts: Timestamp },
+}
+
+impl WatchEvent {
+    pub fn key(&self) -> &Key {
+        match self {
+            WatchEvent::Put { key, .. } | WatchEvent::Delete { key, .. } => key,
+        }
+    }
+
+    /// The timestamp the change was committed at.
+    pub fn commit_ts(&self) -> &Timestamp {
+        match self {
+            WatchEvent::Put { commit_ts, .. } | WatchEvent::Delete { commit_ts, .. } => commit_ts,
+        }
+    }
+}
+
+/// A change data feed of the raw keys in a range, registered on every region of the range.
+///
+/// A feed fails when any of its regions changes (e.g. when a region splits or its leader moves),
+/// and must then be registered again.
+pub(crate) struct Feed {
+    // The feed is deregistered when the senders are dropped.
+    _senders: Vec<ClientDuplexSender<ChangeDataRequest>>,
+    events: SelectAll<ClientDuplexReceiver<ChangeDataEvent>>,
+    uninitialized: HashSet<RegionId>,
+}
+
+impl Feed {
+    /// Register a feed of the changes in `range`, which must be in prefixed keys, committed after
+    /// `checkpoint_ts`.
+    pub async fn register(
+        rpc: &Arc<PdRpcClient>,
+        range: &BoundRange,
+        checkpoint_ts: u64,
+    ) -> Result<Feed> {
+        let (start, end) = range.clone().into_keys();
+        let mut regions: HashMap<StoreId, Vec<Region>> = HashMap::new();
+        let mut uninitialized = HashSet::new();
+        let mut next = start.clone();
+        loop {
+            let region = rpc.region_for_key(&next).await?;
+            let region_end = region.end_key();
+            uninitialized.insert(region.id());
+            regions
+                .entry(region.get_store_id()?)
+                .or_default()
+                .push(region);
+            if region_end.is_empty() || end.as_ref().is_some_and(|end| &region_end >= end) {
+                break;
+            }
+            next = region_end;
+        }
+
+        let mut senders = Vec::with_capacity(regions.len());
+        let mut receivers = Vec::with_capacity(regions.len());
+        for (store_id, regions) in regions {
+            let (mut sender, receiver) = rpc.change_data_client(store_id).await?.event_feed()?;
+            for region in regions {
+                let request = register_request(&region, &start, &end, checkpoint_ts);
+                sender.send((request, WriteFlags::default())).await?;
+            }
+            senders.push(sender);
+            receivers.push(receiver);
+        }
+        Ok(Feed {
+            _senders: senders,
+            events: stream::select_all(receivers),
+            uninitialized,
+        })
+    }
+
+    /// The rows of the next events received, or an error if the feed failed.
+    ///
+    /// Changes committed after the checkpoint but before the feed was registered are received
+    /// before the feed is initialized.
+    pub async fn next(&mut self) -> Result<Vec<event::Row>> {
+        let events = match self.events.next().await.transpose()? {
+            Some(events) => events,
+            None => return Err(internal_err!("change data feed closed")),
+        };
+        let mut rows = Vec::new();
+        for event in events.events {
+            match event.event {
+                Some(event::Event::Entries(entries)) => {
+                    for row in entries.entries {
+                        if row.get_type() == event::LogType::Initialized {
+                            self.uninitialized.remove(&event.region_id);
+                        } else {
+                            rows.push(row);
+                        }
+                    }
+                }
+                Some(event::Event::Error(e)) => {
+                    return Err(internal_err!(
+                        "change data feed of region {} failed: {:?}",
+                        event.region_id,
+                        e
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Whether every region of the feed has been initialized.
+    pub fn is_initialized(&self) -> bool {
+        self.uninitialized.is_empty()
+    }
+}
+
+fn register_request(
+    region: &Region,
+    start: &Key,
+    end: &Option<Key>,
+    checkpoint_ts: u64,
+) -> ChangeDataRequest {
+    let (region_start, region_end) = region.range();
+    let start = std::cmp::max(&region_start, start).clone();
+    let end = match end {
+        Some(end) if region_end.is_empty() || end < &region_end => end.clone(),
+        _ => region_end,
+    };
+    let region_id: RegionId = region.id();
+
+    let mut header = cdcpb::Header::default();
+    header.set_ticdc_version(CDC_VERSION.to_owned());
+    let mut request = ChangeDataRequest::default();
+    request.set_header(header);
+    request.set_region_id(region_id);
+    request.set_region_epoch(region.region.get_region_epoch().clone());
+    request.set_checkpoint_ts(checkpoint_ts);
+    request.set_start_key(start.into());
+    request.set_end_key(end.into());
+    request.set_request_id(region_id);
+    request.kv_api = KvApi::RawKv as i32;
+    request.request = Some(change_data_request::Request::Register(
+        change_data_request::Register {},
+    ));
+    request
+}
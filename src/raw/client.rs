@@ -1,18 +1,52 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use tikv_client_common::Error;
+use tikv_client_common::{internal_err, Error};
+use tikv_client_proto::{cdcpb::event, kvrpcpb};
 
 use crate::{
-    backoff::{DEFAULT_REGION_BACKOFF, OPTIMISTIC_BACKOFF},
     config::Config,
-    pd::PdRpcClient,
-    raw::lowering::*,
-    request::{Collect, Plan},
-    BoundRange, ColumnFamily, Key, KvPair, Result, Value,
+    kv::{
+        align_to_keys,
+        codec::value::{decode_counter, encode_counter, SharedValueCodec, ValueCodec},
+        Prefix,
+    },
+    pd::{PdClient, PdRpcClient},
+    raw::{
+        cache::ReadCache,
+        cdc::Feed,
+        coalesce::Coalescer,
+        conditional::{Guard, RawMutation},
+        lowering::*,
+        requests::{merge_key_ranges, pairs_by_range, truncations},
+        stats::{allocate, estimate_keys, random_key, RegionStats},
+        RangeStats, ScanStream, WatchEvent,
+    },
+    request::{Collect, CollectError, Options, Plan},
+    Backoff, BoundRange, Cluster, ColumnFamily, HotRegion, Key, Keyspace, KvPair, ResourceGroup,
+    Result, RetryKind, StoreStats, Timestamp, TimestampExt, Value,
+};
+use futures::{prelude::*, stream};
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+    time::{Duration, Instant},
+    u32,
 };
-use std::{sync::Arc, u32};
 
 const MAX_RAW_KV_SCAN_LIMIT: u32 = 10240;
+const MAX_TTL_REQUESTS_IN_FLIGHT: usize = 16;
+// The number of pairs scanned to estimate the number of pairs of a region.
+const STATS_SAMPLE_LIMIT: u32 = 64;
+// The prefix of raw keys of the default keyspace of API V2 clusters: the raw mode byte followed by
+// the keyspace ID 0.
+const API_V2_RAW_PREFIX: &[u8] = b"r\0\0\0";
+// Compare and swaps tried by `get_or_insert` while they fail without reporting a value.
+const MAX_GET_OR_INSERT_ATTEMPTS: usize = 3;
+// How long a conditional write holds its guard key, in milliseconds.
+const GUARD_LEASE_MILLIS: u64 = 10_000;
+// How long a conditional write waits between attempts while the guard is held by another writer.
+const GUARD_BACKOFF: Backoff = Backoff::no_jitter_backoff(2, 500, u32::MAX);
 
 /// The TiKV raw `Client` is used to interact with TiKV using raw requests.
 ///
@@ -24,6 +58,19 @@ const MAX_RAW_KV_SCAN_LIMIT: u32 = 10240;
 pub struct Client {
     rpc: Arc<PdRpcClient>,
     cf: Option<ColumnFamily>,
+    // The prefix of raw keys required by the API version of the cluster.
+    api: Prefix,
+    // The API prefix followed by the prefix of the keyspace.
+    keyspace: Prefix,
+    scope: Prefix,
+    // The keyspace prefix followed by the scope.
+    prefix: Prefix,
+    value_codec: SharedValueCodec,
+    sequential_scan: bool,
+    merge_batch_scan_ranges: bool,
+    // Gets of the same key in flight, if read coalescing is enabled.
+    coalescer: Option<Coalescer<(Option<ColumnFamily>, Key), Option<Value>>>,
+    cache: Option<Arc<ReadCache>>,
 }
 
 impl Client {
@@ -63,7 +110,49 @@ impl Client {
     ) -> Result<Client> {
         let pd_endpoints: Vec<String> = pd_endpoints.into_iter().map(Into::into).collect();
         let rpc = Arc::new(PdRpcClient::connect(&pd_endpoints, &config, false).await?);
-        Ok(Client { rpc, cf: None })
+        Ok(Client::from_rpc(rpc))
+    }
+
+    /// Create a raw [`Client`](Client) which shares the connections of a [`Cluster`](Cluster).
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Cluster, RawClient, TransactionClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// let cluster = Cluster::connect(vec!["192.168.0.100"]).await.unwrap();
+    /// let raw = RawClient::from_cluster(&cluster);
+    /// let txn = TransactionClient::from_cluster(&cluster);
+    /// # });
+    /// ```
+    pub fn from_cluster(cluster: &Cluster) -> Client {
+        Client::from_rpc(Arc::new(cluster.rpc.with_codec(false)))
+    }
+
+    /// A client of `rpc` with the default options.
+    ///
+    /// Raw keys of API V2 clusters are prefixed by their mode and keyspace ID: the client prepends
+    /// the prefix of the default keyspace to all keys, before any keyspace or scope set later, so
+    /// code written against API V1 keeps working.
+    fn from_rpc(rpc: Arc<PdRpcClient>) -> Client {
+        let api = if rpc.features().api_v2 {
+            Prefix::new(API_V2_RAW_PREFIX)
+        } else {
+            Prefix::default()
+        };
+        Client {
+            rpc,
+            cf: None,
+            api: api.clone(),
+            keyspace: api.clone(),
+            scope: Prefix::default(),
+            prefix: api,
+            value_codec: SharedValueCodec::default(),
+            sequential_scan: false,
+            merge_batch_scan_ranges: false,
+            coalescer: None,
+            cache: None,
+        }
     }
 
     /// Set the column family of requests.
@@ -87,366 +176,1799 @@ impl Client {
     /// ```
     pub fn with_cf(&self, cf: ColumnFamily) -> Client {
         Client {
-            rpc: self.rpc.clone(),
             cf: Some(cf),
+            ..self.clone()
         }
     }
 
-    /// Create a new 'get' request.
+    /// Set the keyspace of requests.
     ///
-    /// Once resolved this request will result in the fetching of the value associated with the
-    /// given key.
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// All keys of requests created with it are prefixed by the keyspace, and keys returned are
+    /// stripped of the prefix, so that clients of different keyspaces can not see each other's
+    /// data. The original `Client` can still be used.
     ///
-    /// Retuning `Ok(None)` indicates the key does not exist in TiKV.
+    /// The prefix is the length of the keyspace name as a big-endian `u32`, followed by the name.
+    /// A scope set by [`scoped`](Client::scoped) is kept, and applies within the new keyspace.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Value, Config, RawClient};
+    /// # use tikv_client::{Config, RawClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let key = "TiKV".to_owned();
-    /// let req = client.get(key);
-    /// let result: Option<Value> = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let tenant_a = client.with_keyspace("tenant_a");
+    /// let get_request = tenant_a.get("foo".to_owned());
     /// # });
     /// ```
-    pub async fn get(&self, key: impl Into<Key>) -> Result<Option<Value>> {
-        let request = new_raw_get_request(key.into(), self.cf.clone());
-        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .single_region()
-            .await?
-            .resolve_lock(OPTIMISTIC_BACKOFF)
-            .retry_region(DEFAULT_REGION_BACKOFF)
-            .post_process_default()
-            .plan();
-        plan.execute().await
+    pub fn with_keyspace(&self, keyspace: impl AsRef<str>) -> Client {
+        let name = keyspace.as_ref().as_bytes();
+        let mut prefix = Vec::with_capacity(4 + name.len());
+        prefix.extend_from_slice(&(name.len() as u32).to_be_bytes());
+        prefix.extend_from_slice(name);
+        let keyspace = self.api.join(&Prefix::new(prefix));
+        Client {
+            prefix: keyspace.join(&self.scope),
+            keyspace,
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'batch get' request.
+    /// Scope requests to keys beginning with `prefix`.
     ///
-    /// Once resolved this request will result in the fetching of the values associated with the
-    /// given keys.
-    ///
-    /// Non-existent entries will not appear in the result. The order of the keys is not retained in the result.
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// The prefix is prepended to all keys of requests created with it and stripped from all
+    /// keys returned. Scans and range deletions are constrained to keys with the prefix, so the
+    /// scoped client can never read or modify keys outside of it. Scoping an already scoped
+    /// client appends `prefix` to the existing scope. The original `Client` can still be used.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{KvPair, Config, RawClient};
+    /// # use tikv_client::{Config, RawClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
-    /// let req = client.batch_get(keys);
-    /// let result: Vec<KvPair> = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let users = client.scoped("users/".to_owned());
+    /// // Deletes every key beginning with "users/", and nothing else.
+    /// users.delete_range(..).await.unwrap();
     /// # });
     /// ```
-    pub async fn batch_get(
-        &self,
-        keys: impl IntoIterator<Item = impl Into<Key>>,
-    ) -> Result<Vec<KvPair>> {
-        let request = new_raw_batch_get_request(keys.into_iter().map(Into::into), self.cf.clone());
-        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .resolve_lock(OPTIMISTIC_BACKOFF)
-            .multi_region()
-            .retry_region(DEFAULT_REGION_BACKOFF)
-            .merge(Collect)
-            .plan();
-        plan.execute()
-            .await
-            .map(|r| r.into_iter().map(Into::into).collect())
+    pub fn scoped(&self, prefix: impl Into<Key>) -> Client {
+        let scope = self.scope.join(&Prefix::new(prefix.into()));
+        Client {
+            prefix: self.keyspace.join(&scope),
+            scope,
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'put' request.
+    /// Transform values with `codec`.
     ///
-    /// Once resolved this request will result in the setting of the value associated with the given key.
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Values written by requests created with it are encoded by the codec, and values read are
+    /// decoded. The original `Client` can still be used.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Key, Value, Config, RawClient};
+    /// # use tikv_client::{Config, RawClient, Result, Value, codec::value::ValueCodec};
     /// # use futures::prelude::*;
+    /// #[derive(Debug)]
+    /// struct Reversed;
+    ///
+    /// impl ValueCodec for Reversed {
+    ///     fn encode(&self, mut value: Value) -> Result<Value> {
+    ///         value.reverse();
+    ///         Ok(value)
+    ///     }
+    ///
+    ///     fn decode(&self, value: Value) -> Result<Value> {
+    ///         self.encode(value)
+    ///     }
+    /// }
+    ///
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let key = "TiKV".to_owned();
-    /// let val = "TiKV".to_owned();
-    /// let req = client.put(key, val);
-    /// let result: () = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_value_codec(Reversed);
     /// # });
     /// ```
-    pub async fn put(&self, key: impl Into<Key>, value: impl Into<Value>) -> Result<()> {
-        let request = new_raw_put_request(key.into(), value.into(), self.cf.clone());
-        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .single_region()
-            .await?
-            .resolve_lock(OPTIMISTIC_BACKOFF)
-            .retry_region(DEFAULT_REGION_BACKOFF)
-            .extract_error()
-            .plan();
-        plan.execute().await?;
-        Ok(())
+    pub fn with_value_codec(&self, codec: impl ValueCodec) -> Client {
+        Client {
+            value_codec: SharedValueCodec::new(codec),
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'batch put' request.
+    /// Scan regions one at a time, in key order.
     ///
-    /// Once resolved this request will result in the setting of the values associated with the given keys.
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// By default, a scan sends a request for `limit` pairs to every region in the range at once,
+    /// then keeps the first `limit` pairs, which over-fetches heavily for small limits over large
+    /// ranges. A client created by this function requests only the pairs still missing from each
+    /// region in turn, and stops as soon as `limit` pairs are found, at the cost of latency
+    /// growing with the number of regions visited. The original `Client` can still be used.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Result, KvPair, Key, Value, Config, RawClient, IntoOwnedRange};
+    /// # use tikv_client::{Config, RawClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let kvpair1 = ("PD".to_owned(), "Go".to_owned());
-    /// let kvpair2 = ("TiKV".to_owned(), "Rust".to_owned());
-    /// let iterable = vec![kvpair1, kvpair2];
-    /// let req = client.batch_put(iterable);
-    /// let result: () = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_sequential_scan();
+    /// let first_ten = client.scan(.., 10).await.unwrap();
     /// # });
     /// ```
-    pub async fn batch_put(
-        &self,
-        pairs: impl IntoIterator<Item = impl Into<KvPair>>,
-    ) -> Result<()> {
-        let request = new_raw_batch_put_request(pairs.into_iter().map(Into::into), self.cf.clone());
-        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .resolve_lock(OPTIMISTIC_BACKOFF)
-            .multi_region()
-            .retry_region(DEFAULT_REGION_BACKOFF)
-            .extract_error()
-            .plan();
-        plan.execute().await?;
-        Ok(())
+    pub fn with_sequential_scan(&self) -> Client {
+        Client {
+            sequential_scan: true,
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'delete' request.
+    /// Create a new client whose batch scans merge overlapping and adjacent ranges.
     ///
-    /// Once resolved this request will result in the deletion of the given key.
+    /// By default, a batch scan sends the ranges as given, so that the keys of overlapping ranges
+    /// are scanned once for each range. A client created by this function merges the ranges
+    /// before splitting them by region, so that every key is scanned once, then attributes the
+    /// pairs back to the ranges. The original `Client` can still be used.
     ///
-    /// It does not return an error if the key does not exist in TiKV.
+    /// In a region, the limit of a batch scan then applies to the merged ranges. A range whose
+    /// first pairs may have been left out because of it is scanned again on its own, so merging
+    /// suits ranges which overlap much more than they hold pairs.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Key, Config, RawClient};
+    /// # use tikv_client::{Config, RawClient, IntoOwnedRange};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let key = "TiKV".to_owned();
-    /// let req = client.delete(key);
-    /// let result: () = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_batch_scan_merging();
+    /// let ranges = vec![("a".."m").into_owned(), ("k".."z").into_owned()];
+    /// let pairs = client.batch_scan(ranges, 100).await.unwrap();
     /// # });
     /// ```
-    pub async fn delete(&self, key: impl Into<Key>) -> Result<()> {
-        let request = new_raw_delete_request(key.into(), self.cf.clone());
-        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .single_region()
-            .await?
-            .resolve_lock(OPTIMISTIC_BACKOFF)
-            .retry_region(DEFAULT_REGION_BACKOFF)
-            .extract_error()
-            .plan();
-        plan.execute().await?;
-        Ok(())
+    pub fn with_batch_scan_merging(&self) -> Client {
+        Client {
+            merge_batch_scan_ranges: true,
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'batch delete' request.
-    ///
-    /// Once resolved this request will result in the deletion of the given keys.
+    /// Send a gRPC metadata entry with every request to TiKV.
     ///
-    /// It does not return an error if some of the keys do not exist and will delete the others.
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Requests of the new client carry the entry, after any entries added before, so that
+    /// server-side logging and middleboxes can attribute traffic, e.g. to a tenant. Creating a
+    /// client is cheap, so a client can be created to attach an entry, such as a correlation id,
+    /// to a single request. The original `Client` can still be used.
     ///
     /// # Examples
     /// ```rust,no_run
     /// # use tikv_client::{Config, RawClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
-    /// let req = client.batch_delete(keys);
-    /// let result: () = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_metadata("tenant-id", "42");
+    /// let value = client
+    ///     .with_metadata("x-correlation-id", "abc")
+    ///     .get("key".to_owned())
+    ///     .await
+    ///     .unwrap();
     /// # });
     /// ```
-    pub async fn batch_delete(&self, keys: impl IntoIterator<Item = impl Into<Key>>) -> Result<()> {
-        let request =
-            new_raw_batch_delete_request(keys.into_iter().map(Into::into), self.cf.clone());
-        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .resolve_lock(OPTIMISTIC_BACKOFF)
-            .multi_region()
-            .retry_region(DEFAULT_REGION_BACKOFF)
-            .extract_error()
-            .plan();
-        plan.execute().await?;
-        Ok(())
+    pub fn with_metadata(&self, key: impl Into<String>, value: impl Into<String>) -> Client {
+        let mut metadata = self.rpc.metadata();
+        metadata.insert(key, value);
+        Client {
+            rpc: Arc::new(self.rpc.with_metadata(metadata)),
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'delete range' request.
+    /// Tag requests with a source, overriding the one set by
+    /// [`Config::with_request_source`](crate::Config::with_request_source).
     ///
-    /// Once resolved this request will result in the deletion of all keys lying in the given range.
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Every request of the new client to TiKV carries the tag, so TiKV can attribute its load to
+    /// the source. The original `Client` can still be used.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use tikv_client::{Config, RawClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let inclusive_range = "TiKV"..="TiDB";
-    /// let req = client.delete_range(inclusive_range.into_owned());
-    /// let result: () = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let backfill = client.with_request_source("backfill");
+    /// backfill.put("key".to_owned(), "value".to_owned()).await.unwrap();
     /// # });
     /// ```
-    pub async fn delete_range(&self, range: impl Into<BoundRange>) -> Result<()> {
-        let request = new_raw_delete_range_request(range.into(), self.cf.clone());
-        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .resolve_lock(OPTIMISTIC_BACKOFF)
-            .multi_region()
-            .retry_region(DEFAULT_REGION_BACKOFF)
-            .extract_error()
-            .plan();
-        plan.execute().await?;
-        Ok(())
+    pub fn with_request_source(&self, source: impl Into<String>) -> Client {
+        Client {
+            rpc: Arc::new(self.rpc.with_request_source(source.into())),
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'scan' request.
-    ///
-    /// Once resolved this request will result in a `Vec` of key-value pairs that lies in the specified range.
-    ///
-    /// If the number of eligible key-value pairs are greater than `limit`,
-    /// only the first `limit` pairs are returned, ordered by the key.
+    /// Charge requests to a resource group, overriding the one set by
+    /// [`Config::with_resource_group`](crate::Config::with_resource_group).
     ///
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Every request of the new client to TiKV is charged to the group, and throttled by its
+    /// request units. The original `Client` can still be used.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{KvPair, Config, RawClient, IntoOwnedRange};
-    /// # use futures::prelude::*;
+    /// # use tikv_client::RawClient;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let inclusive_range = "TiKV"..="TiDB";
-    /// let req = client.scan(inclusive_range.into_owned(), 2);
-    /// let result: Vec<KvPair> = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let tenant_a = client.with_resource_group("tenant_a");
+    /// tenant_a.put("key".to_owned(), "value".to_owned()).await.unwrap();
     /// # });
     /// ```
-    pub async fn scan(&self, range: impl Into<BoundRange>, limit: u32) -> Result<Vec<KvPair>> {
-        self.scan_inner(range.into(), limit, false).await
+    pub fn with_resource_group(&self, group: impl Into<String>) -> Client {
+        Client {
+            rpc: Arc::new(self.rpc.with_resource_group(group.into())),
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'scan' request that only returns the keys.
+    /// Report the retries of requests, e.g. to record them in the metrics of the application.
     ///
-    /// Once resolved this request will result in a `Vec` of keys that lies in the specified range.
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Before a request of the new client is retried, because of an outdated region or a locked
+    /// key, `observer` is called with why it is retried, the number of the attempt which failed
+    /// (starting at 1), how long the request waits before the retry and the error of the attempt.
+    /// A request which is retried many times points to a struggling cluster. The observer is
+    /// called on the task sending the request, so it should return quickly. The original
+    /// `Client` can still be used.
     ///
-    /// If the number of eligible keys are greater than `limit`,
-    /// only the first `limit` pairs are returned, ordered by the key.
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::RawClient;
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_backoff_observer(|kind, attempt, delay, error| {
+    ///         eprintln!("retrying ({:?}) attempt {} in {:?}: {}", kind, attempt, delay, error)
+    ///     });
+    /// # });
+    /// ```
+    pub fn with_backoff_observer(
+        &self,
+        observer: impl Fn(RetryKind, u32, Duration, &Error) + Send + Sync + 'static,
+    ) -> Client {
+        Client {
+            rpc: Arc::new(self.rpc.with_backoff_observer(Arc::new(observer))),
+            ..self.clone()
+        }
+    }
+
+    /// Coalesce concurrent gets of the same key.
     ///
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// While a [`get`](Client::get) of a key is in flight, further gets of that key from the new
+    /// client and its clones wait for the result of the first one instead of sending their own
+    /// request, which takes load off TiKV when many tasks read the same hot keys. Gets waiting for
+    /// a request which fails send their own request. The original `Client` can still be used, but
+    /// its gets are not coalesced.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use tikv_client::{Config, RawClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let inclusive_range = "TiKV"..="TiDB";
-    /// let req = client.scan_keys(inclusive_range.into_owned(), 2);
-    /// let result: Vec<Key> = req.await.unwrap();
+    /// let client = RawClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_read_coalescing();
+    /// let (a, b) = futures::join!(client.get("hot".to_owned()), client.get("hot".to_owned()));
     /// # });
     /// ```
-    pub async fn scan_keys(&self, range: impl Into<BoundRange>, limit: u32) -> Result<Vec<Key>> {
-        Ok(self
-            .scan_inner(range, limit, true)
-            .await?
-            .into_iter()
-            .map(KvPair::into_key)
-            .collect())
+    pub fn with_read_coalescing(&self) -> Client {
+        Client {
+            coalescer: Some(Coalescer::default()),
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'batch scan' request.
+    /// Cache the values of keys in `range`.
     ///
-    /// Once resolved this request will result in a set of scanners over the given keys.
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Gets of keys in `range` from the new client and its clones are served from a cache of at
+    /// most `capacity` keys, least recently used keys being evicted first. The cache subscribes to
+    /// the changes of the range in TiKV and drops values as soon as they are changed by any
+    /// client, so it suits hot keys which are rarely written, such as configuration. Until the
+    /// subscription is established, and whenever it has to be re-established (e.g. after a region
+    /// split), gets are sent to TiKV. The subscription is cancelled when the new client and all
+    /// its clones are dropped.
     ///
-    /// **Warning**: This method is experimental. The `each_limit` parameter does not work as expected.
-    /// It does not limit the number of results returned of each range,
-    /// instead it limits the number of results in each region of each range.
-    /// As a result, you may get **more than** `each_limit` key-value pairs for each range.
-    /// But you should not miss any entries.
+    /// Changes reach the cache asynchronously, so a get may briefly return the previous value of
+    /// a key after a write to it, even one from the same client. Only the default column family
+    /// is cached. Subscribing to the changes of raw keys needs TiKV 6.2 or later. This function
+    /// must be called within a Tokio runtime.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use tikv_client::{Config, RawClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
-    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let inclusive_range1 = "TiDB"..="TiKV";
-    /// let inclusive_range2 = "TiKV"..="TiSpark";
-    /// let iterable = vec![inclusive_range1.into_owned(), inclusive_range2.into_owned()];
-    /// let req = client.batch_scan(iterable, 2);
-    /// let result = req.await;
+    /// let client = RawClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_read_cache("config/".to_owned().."config0".to_owned(), 1000);
+    /// let timeout = client.get("config/timeout".to_owned()).await.unwrap();
     /// # });
     /// ```
-    pub async fn batch_scan(
-        &self,
-        ranges: impl IntoIterator<Item = impl Into<BoundRange>>,
-        each_limit: u32,
-    ) -> Result<Vec<KvPair>> {
-        self.batch_scan_inner(ranges, each_limit, false).await
+    pub fn with_read_cache(&self, range: impl Into<BoundRange>, capacity: usize) -> Client {
+        let range = self.prefix.encode_range(range.into());
+        Client {
+            cache: Some(Arc::new(ReadCache::start(
+                self.rpc.clone(),
+                range,
+                capacity,
+            ))),
+            ..self.clone()
+        }
     }
 
-    /// Create a new 'batch scan' request that only returns the keys.
+    /// Create a new 'get' request.
     ///
-    /// Once resolved this request will result in a set of scanners over the given keys.
+    /// Once resolved this request will result in the fetching of the value associated with the
+    /// given key.
     ///
-    /// **Warning**: This method is experimental. The `each_limit` parameter does not work as expected.
-    /// It does not limit the number of results returned of each range,
-    /// instead it limits the number of results in each region of each range.
-    /// As a result, you may get **more than** `each_limit` key-value pairs for each range.
-    /// But you should not miss any entries.
+    /// Retuning `Ok(None)` indicates the key does not exist in TiKV.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use tikv_client::{Value, Config, RawClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let inclusive_range1 = "TiDB"..="TiKV";
-    /// let inclusive_range2 = "TiKV"..="TiSpark";
-    /// let iterable = vec![inclusive_range1.into_owned(), inclusive_range2.into_owned()];
-    /// let req = client.batch_scan(iterable, 2);
-    /// let result = req.await;
+    /// let key = "TiKV".to_owned();
+    /// let req = client.get(key);
+    /// let result: Option<Value> = req.await.unwrap();
     /// # });
     /// ```
-    pub async fn batch_scan_keys(
-        &self,
-        ranges: impl IntoIterator<Item = impl Into<BoundRange>>,
-        each_limit: u32,
-    ) -> Result<Vec<Key>> {
-        Ok(self
-            .batch_scan_inner(ranges, each_limit, true)
-            .await?
-            .into_iter()
-            .map(KvPair::into_key)
-            .collect())
+    pub async fn get(&self, key: impl Into<Key>) -> Result<Option<Value>> {
+        let key = self.prefix.encode_key(key.into());
+        let value = match &self.cache {
+            Some(cache) if matches!(self.cf, None | Some(ColumnFamily::Default)) => {
+                cache.get(key.clone(), self.get_coalesced(key)).await?
+            }
+            _ => self.get_coalesced(key).await?,
+        };
+        value
+            .map(|value| self.value_codec.decode(value))
+            .transpose()
     }
 
-    async fn scan_inner(
-        &self,
-        range: impl Into<BoundRange>,
-        limit: u32,
-        key_only: bool,
-    ) -> Result<Vec<KvPair>> {
-        if limit > MAX_RAW_KV_SCAN_LIMIT {
-            return Err(Error::MaxScanLimitExceeded {
-                limit,
-                max_limit: MAX_RAW_KV_SCAN_LIMIT,
-            });
-        }
+    /// Like [`get`](Client::get), with the options of this operation.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Options, RawClient, Value};
+    /// # use std::time::Duration;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let options = Options::new().timeout(Duration::from_millis(50)).replica_read(true);
+    /// let result: Option<Value> = client.get_opt("TiKV".to_owned(), options).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn get_opt(&self, key: impl Into<Key>, options: Options) -> Result<Option<Value>> {
+        options.run(self.with_options(&options).get(key)).await
+    }
+
+    /// Create a new 'batch get' request.
+    ///
+    /// Once resolved this request will result in the fetching of the values associated with the
+    /// given keys.
+    ///
+    /// Non-existent entries will not appear in the result. The order of the keys is not retained in the result.
+    /// Use [`batch_get_ordered`](Client::batch_get_ordered) to get a result for every key, in order.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{KvPair, Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
+    /// let req = client.batch_get(keys);
+    /// let result: Vec<KvPair> = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn batch_get(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<KvPair>> {
+        let request = new_raw_batch_get_request(
+            keys.into_iter()
+                .map(|key| self.prefix.encode_key(key.into())),
+            self.cf.clone(),
+        );
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .multi_region()
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .merge(Collect)
+            .plan();
+        plan.execute()
+            .await?
+            .into_iter()
+            .map(|pair| self.value_codec.decode_pair(self.prefix.decode_pair(pair)))
+            .collect()
+    }
+
+    /// Like [`batch_get`](Client::batch_get), with the options of this operation.
+    pub async fn batch_get_opt(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+        options: Options,
+    ) -> Result<Vec<KvPair>> {
+        options
+            .run(self.with_options(&options).batch_get(keys))
+            .await
+    }
+
+    /// Create a new 'batch get' request which returns the value of every key in order.
+    ///
+    /// Once resolved this request will result in one entry per key, in the order of `keys`, so
+    /// the result can be zipped with the keys. Entries of non-existent keys are `None`.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Value, Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
+    /// let req = client.batch_get_ordered(keys);
+    /// let result: Vec<Option<Value>> = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn batch_get_ordered(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<Option<Value>>> {
+        let keys: Vec<Key> = keys.into_iter().map(Into::into).collect();
+        let pairs = self.batch_get(keys.clone()).await?;
+        Ok(align_to_keys(&keys, pairs))
+    }
+
+    /// Get the remaining time to live of `key`, in seconds.
+    ///
+    /// Returns `None` if the key does not exist, and `Some(0)` if it never expires. Keys only
+    /// expire if TiKV has TTL enabled for raw keys, which clusters with API V2 always have.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let ttl: Option<u64> = client.get_key_ttl_secs("session".to_owned()).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn get_key_ttl_secs(&self, key: impl Into<Key>) -> Result<Option<u64>> {
+        self.get_key_ttl_encoded(self.prefix.encode_key(key.into()))
+            .await
+    }
+
+    /// Get the value of `key` and its remaining time to live in seconds, where `0` means it never
+    /// expires.
+    ///
+    /// TiKV returns values and TTLs in separate responses, so both are requested concurrently.
+    /// The value is always read from TiKV, bypassing the read cache. Returns `None` if the key
+    /// does not exist, or stopped existing between the requests.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// if let Some((value, ttl)) = client.get_with_ttl("session".to_owned()).await.unwrap() {
+    ///     println!("{:?} expires in {}s", value, ttl);
+    /// }
+    /// # });
+    /// ```
+    pub async fn get_with_ttl(&self, key: impl Into<Key>) -> Result<Option<(Value, u64)>> {
+        let key = self.prefix.encode_key(key.into());
+        let (value, ttl) = future::try_join(
+            self.get_coalesced(key.clone()),
+            self.get_key_ttl_encoded(key),
+        )
+        .await?;
+        match (value, ttl) {
+            (Some(value), Some(ttl)) => Ok(Some((self.value_codec.decode(value)?, ttl))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Make `key` expire `ttl_secs` seconds from now, or never if `ttl_secs` is `0`, keeping its
+    /// value. Returns `false` if the key does not exist.
+    ///
+    /// TiKV has no request changing only the TTL of a key, and compare and swap is the only raw
+    /// write carrying a TTL. So the value is read, then written back with the new TTL by
+    /// [`compare_and_swap`](Client::compare_and_swap) if it didn't change, retrying while it is
+    /// updated concurrently, and the same requirements apply. The value is sent as stored,
+    /// without being decoded and encoded again by the value codec, so any codec works. Keys only
+    /// expire if TiKV has TTL enabled for raw keys.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// // Keep the session alive for another 30 minutes.
+    /// let alive = client
+    ///     .update_ttl("session".to_owned(), 30 * 60)
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn update_ttl(&self, key: impl Into<Key>, ttl_secs: u64) -> Result<bool> {
+        let key = self.prefix.encode_key(key.into());
+        let mut current = self.get_encoded(key.clone()).await?;
+        loop {
+            let value = match current {
+                Some(value) => value,
+                None => return Ok(false),
+            };
+            let request =
+                new_raw_cas_request(key.clone(), value.clone(), Some(value), self.cf.clone());
+            let (previous, swapped) = self.cas_with_ttl(request, ttl_secs).await?;
+            if swapped {
+                return Ok(true);
+            }
+            current = previous;
+        }
+    }
+
+    /// Create a new 'put' request.
+    ///
+    /// Once resolved this request will result in the setting of the value associated with the given key.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Value, Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let key = "TiKV".to_owned();
+    /// let val = "TiKV".to_owned();
+    /// let req = client.put(key, val);
+    /// let result: () = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn put(&self, key: impl Into<Key>, value: impl Into<Value>) -> Result<()> {
+        let request = new_raw_put_request(
+            self.prefix.encode_key(key.into()),
+            self.value_codec.encode(value.into())?,
+            self.cf.clone(),
+        );
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .single_region()
+            .await?
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .extract_error()
+            .plan();
+        plan.execute().await?;
+        Ok(())
+    }
+
+    /// Like [`put`](Client::put), with the options of this operation.
+    pub async fn put_opt(
+        &self,
+        key: impl Into<Key>,
+        value: impl Into<Value>,
+        options: Options,
+    ) -> Result<()> {
+        options
+            .run(self.with_options(&options).put(key, value))
+            .await
+    }
+
+    /// Create a new 'batch put' request.
+    ///
+    /// Once resolved this request will result in the setting of the values associated with the given keys.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Result, KvPair, Key, Value, Config, RawClient, IntoOwnedRange};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let kvpair1 = ("PD".to_owned(), "Go".to_owned());
+    /// let kvpair2 = ("TiKV".to_owned(), "Rust".to_owned());
+    /// let iterable = vec![kvpair1, kvpair2];
+    /// let req = client.batch_put(iterable);
+    /// let result: () = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn batch_put(
+        &self,
+        pairs: impl IntoIterator<Item = impl Into<KvPair>>,
+    ) -> Result<()> {
+        let pairs = pairs
+            .into_iter()
+            .map(|pair| {
+                self.value_codec
+                    .encode_pair(self.prefix.encode_pair(pair.into()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.batch_put_encoded(pairs).await
+    }
+
+    /// Like [`batch_put`](Client::batch_put), with the options of this operation.
+    pub async fn batch_put_opt(
+        &self,
+        pairs: impl IntoIterator<Item = impl Into<KvPair>>,
+        options: Options,
+    ) -> Result<()> {
+        options
+            .run(self.with_options(&options).batch_put(pairs))
+            .await
+    }
+
+    /// Create a new *atomic* 'compare and swap' request.
+    ///
+    /// Once resolved this request will set the value of `key` to `new_value` if its current value
+    /// is `previous_value`, where `None` means the key does not exist. Returns the value of the key
+    /// before the request and whether it was swapped.
+    ///
+    /// Requires a TiKV version supporting raw compare and swap (5.0 or later), otherwise fails with
+    /// [`UnsupportedFeature`](Error::UnsupportedFeature). Writes by other raw requests are
+    /// not serialized with compare and swap, so keys updated this way should only be written by
+    /// compare and swap. If the client has a value codec, `previous_value` is encoded before
+    /// being compared, so codecs which don't always encode a value the same way (e.g.
+    /// encryption) never match.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Value, Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let (previous, swapped) = client
+    ///     .compare_and_swap("leader".to_owned(), None, "node-1".to_owned())
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn compare_and_swap(
+        &self,
+        key: impl Into<Key>,
+        previous_value: impl Into<Option<Value>>,
+        new_value: impl Into<Value>,
+    ) -> Result<(Option<Value>, bool)> {
+        let previous_value = previous_value
+            .into()
+            .map(|value| self.value_codec.encode(value))
+            .transpose()?;
+        let (previous, swapped) = self
+            .compare_and_swap_encoded(
+                self.prefix.encode_key(key.into()),
+                previous_value,
+                self.value_codec.encode(new_value.into())?,
+            )
+            .await?;
+        let previous = previous
+            .map(|value| self.value_codec.decode(value))
+            .transpose()?;
+        Ok((previous, swapped))
+    }
+
+    /// Like [`compare_and_swap`](Client::compare_and_swap), with the options of this operation.
+    pub async fn compare_and_swap_opt(
+        &self,
+        key: impl Into<Key>,
+        previous_value: impl Into<Option<Value>>,
+        new_value: impl Into<Value>,
+        options: Options,
+    ) -> Result<(Option<Value>, bool)> {
+        options
+            .run(
+                self.with_options(&options)
+                    .compare_and_swap(key, previous_value, new_value),
+            )
+            .await
+    }
+
+    /// Atomically add `delta` to the counter stored at `key`, and return the new value.
+    ///
+    /// Counters are stored as encoded by [`codec::value::encode_counter`](crate::codec::value::encode_counter);
+    /// a key which does not exist counts as zero. The counter is updated by
+    /// [`compare_and_swap`](Client::compare_and_swap), retrying while it is updated concurrently,
+    /// so the same requirements apply. Returns an error if the counter would overflow.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let visits = client.incr("visits".to_owned(), 1).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn incr(&self, key: impl Into<Key>, delta: i64) -> Result<i64> {
+        let key = self.prefix.encode_key(key.into());
+        let mut previous = self.get_encoded(key.clone()).await?;
+        loop {
+            let counter = match &previous {
+                Some(value) => decode_counter(&self.value_codec.decode(value.clone())?)?,
+                None => 0,
+            };
+            let counter = counter
+                .checked_add(delta)
+                .ok_or_else(|| Error::CodecError {
+                    message: format!("counter overflow adding {} to {}", delta, counter),
+                })?;
+            let value = self.value_codec.encode(encode_counter(counter))?;
+            let (current, swapped) = self
+                .compare_and_swap_encoded(key.clone(), previous, value)
+                .await?;
+            if swapped {
+                return Ok(counter);
+            }
+            previous = current;
+        }
+    }
+
+    /// Get the value of `key`, or atomically insert `value` if the key does not exist.
+    ///
+    /// Returns the existing value, or `value` if it was inserted. Built on
+    /// [`compare_and_swap`](Client::compare_and_swap), so the same requirements apply.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let config = client
+    ///     .get_or_insert("config".to_owned(), "defaults".to_owned())
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn get_or_insert(
+        &self,
+        key: impl Into<Key>,
+        value: impl Into<Value>,
+    ) -> Result<Value> {
+        let key = key.into();
+        let value = value.into();
+        // The key may be deleted, or expire, between the check and the reply, so a failed swap
+        // can report no previous value. Try again a few times before giving up.
+        for _ in 0..MAX_GET_OR_INSERT_ATTEMPTS {
+            let (previous, swapped) = self
+                .compare_and_swap(key.clone(), None, value.clone())
+                .await?;
+            if swapped {
+                return Ok(value);
+            }
+            if let Some(previous) = previous {
+                return Ok(previous);
+            }
+        }
+        Err(internal_err!(
+            "compare and swap of {:?} failed {} times without a previous value",
+            key,
+            MAX_GET_OR_INSERT_ATTEMPTS
+        ))
+    }
+
+    /// Atomically apply `mutations` if every key of `conditions` has the expected value, where
+    /// `None` means the key does not exist. Returns whether the conditions held and the mutations
+    /// were applied.
+    ///
+    /// Conditional writes sharing the `guard` key are serialized: a write holds the guard, taken
+    /// by [`compare_and_swap`](Client::compare_and_swap), while it checks its conditions and
+    /// applies its mutations, and writes wait for the guard while it is held. The mutations are
+    /// recorded in the guard before they are applied, so that if a writer fails halfway, the next
+    /// writer finishes the write once the guard expires (after 10 seconds) and no conditional
+    /// write ever sees it partially applied. Other reads may see it partially applied, and
+    /// writes to the keys which don't go through the guard break atomicity. The same
+    /// requirements as for compare and swap apply to the guard key, and writers sharing a guard
+    /// must use the same value codec.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{RawClient, RawMutation};
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// // Move a balance between two accounts, if neither changed since it was read.
+    /// let applied = client
+    ///     .conditional_write(
+    ///         "accounts/guard".to_owned(),
+    ///         vec![
+    ///             ("accounts/a".to_owned(), Some(b"10".to_vec())),
+    ///             ("accounts/b".to_owned(), Some(b"0".to_vec())),
+    ///         ],
+    ///         vec![
+    ///             RawMutation::Put("accounts/a".to_owned().into(), b"5".to_vec()),
+    ///             RawMutation::Put("accounts/b".to_owned().into(), b"5".to_vec()),
+    ///         ],
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn conditional_write(
+        &self,
+        guard: impl Into<Key>,
+        conditions: impl IntoIterator<Item = (impl Into<Key>, Option<Value>)>,
+        mutations: impl IntoIterator<Item = RawMutation>,
+    ) -> Result<bool> {
+        let guard = self.prefix.encode_key(guard.into());
+        let (keys, expected): (Vec<Key>, Vec<Option<Value>>) = conditions
+            .into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .unzip();
+        // Mutations are recorded encoded, so that other writers can apply them as they are.
+        let mutations = mutations
+            .into_iter()
+            .map(|mutation| match mutation {
+                RawMutation::Put(key, value) => Ok(RawMutation::Put(
+                    self.prefix.encode_key(key),
+                    self.value_codec.encode(value)?,
+                )),
+                RawMutation::Delete(key) => Ok(RawMutation::Delete(self.prefix.encode_key(key))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (token, expires_at) = self.acquire_guard(guard.clone()).await?;
+        let held = Guard::Held {
+            token,
+            expires_at,
+            intent: None,
+        }
+        .encode();
+        let holds = match self.batch_get_ordered(keys).await {
+            Ok(values) => values == expected,
+            Err(e) => {
+                self.release_guard(guard, held).await?;
+                return Err(e);
+            }
+        };
+        if !holds {
+            self.release_guard(guard, held).await?;
+            return Ok(false);
+        }
+        let committing = Guard::Held {
+            token,
+            expires_at,
+            intent: Some(mutations.clone()),
+        }
+        .encode();
+        let (_, swapped) = self
+            .compare_and_swap_encoded(guard.clone(), Some(held), committing.clone())
+            .await?;
+        if !swapped {
+            return Err(Error::LockLost {
+                key: guard.into(),
+                fence_token: token,
+            });
+        }
+        self.apply_encoded(mutations).await?;
+        self.release_guard(guard, committing).await?;
+        Ok(true)
+    }
+
+    /// Create a new 'delete' request.
+    ///
+    /// Once resolved this request will result in the deletion of the given key.
+    ///
+    /// It does not return an error if the key does not exist in TiKV.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let key = "TiKV".to_owned();
+    /// let req = client.delete(key);
+    /// let result: () = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn delete(&self, key: impl Into<Key>) -> Result<()> {
+        let request = new_raw_delete_request(self.prefix.encode_key(key.into()), self.cf.clone());
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .single_region()
+            .await?
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .extract_error()
+            .plan();
+        plan.execute().await?;
+        Ok(())
+    }
+
+    /// Like [`delete`](Client::delete), with the options of this operation.
+    pub async fn delete_opt(&self, key: impl Into<Key>, options: Options) -> Result<()> {
+        options.run(self.with_options(&options).delete(key)).await
+    }
+
+    /// Create a new 'batch delete' request.
+    ///
+    /// Once resolved this request will result in the deletion of the given keys.
+    ///
+    /// It does not return an error if some of the keys do not exist and will delete the others.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
+    /// let req = client.batch_delete(keys);
+    /// let result: () = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn batch_delete(&self, keys: impl IntoIterator<Item = impl Into<Key>>) -> Result<()> {
+        self.batch_delete_encoded(
+            keys.into_iter()
+                .map(|key| self.prefix.encode_key(key.into()))
+                .collect(),
+        )
+        .await
+    }
+
+    /// Like [`batch_delete`](Client::batch_delete), with the options of this operation.
+    pub async fn batch_delete_opt(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+        options: Options,
+    ) -> Result<()> {
+        options
+            .run(self.with_options(&options).batch_delete(keys))
+            .await
+    }
+
+    /// Delete the given keys like [`batch_delete`](Client::batch_delete), returning the keys
+    /// which existed, in no particular order.
+    ///
+    /// TiKV doesn't report which keys a batch delete removed, so the keys are read before they
+    /// are deleted. A key written by another client between the read and the delete is deleted,
+    /// but not reported. Deleting the same keys again reports none of them, so a cleanup job can
+    /// be retried and still count each key once.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, RawClient};
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
+    /// let deleted: Vec<Key> = client.batch_delete_existing(keys).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn batch_delete_existing(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<Key>> {
+        let keys: Vec<Key> = keys.into_iter().map(Into::into).collect();
+        let existing = self.batch_get(keys.clone()).await?;
+        self.batch_delete(keys).await?;
+        Ok(existing.into_iter().map(|pair| pair.0).collect())
+    }
+
+    /// Create a new 'delete range' request.
+    ///
+    /// Once resolved this request will result in the deletion of all keys lying in the given range.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let inclusive_range = "TiKV"..="TiDB";
+    /// let req = client.delete_range(inclusive_range.into_owned());
+    /// let result: () = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn delete_range(&self, range: impl Into<BoundRange>) -> Result<()> {
+        let request =
+            new_raw_delete_range_request(self.prefix.encode_range(range.into()), self.cf.clone());
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .multi_region()
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .extract_error()
+            .plan();
+        plan.execute().await?;
+        Ok(())
+    }
+
+    /// Like [`delete_range`](Client::delete_range), with the options of this operation.
+    pub async fn delete_range_opt(
+        &self,
+        range: impl Into<BoundRange>,
+        options: Options,
+    ) -> Result<()> {
+        options
+            .run(self.with_options(&options).delete_range(range))
+            .await
+    }
+
+    /// Delete all keys lying in the given range in batches, as a gentler alternative to
+    /// [`delete_range`](Client::delete_range) for ranges which are in use.
+    ///
+    /// Keys are scanned and deleted `batch_size` at a time. If `rate_limit` is given, at most that
+    /// many keys are deleted per second. Keys written to the range while it is being deleted may
+    /// or may not be deleted. Once resolved this request returns the number of keys deleted.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let range = "TiDB"..="TiKV";
+    /// let req = client.delete_range_chunked(range.into_owned(), 1000, Some(10000));
+    /// let deleted: u64 = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn delete_range_chunked(
+        &self,
+        range: impl Into<BoundRange>,
+        batch_size: u32,
+        rate_limit: Option<u32>,
+    ) -> Result<u64> {
+        assert!(batch_size > 0, "batch size must be positive");
+        let range = range.into();
+        let mut start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+        let started = Instant::now();
+        let mut deleted = 0;
+        loop {
+            let keys = self
+                .scan_keys(BoundRange::from((start, end.clone())), batch_size)
+                .await?;
+            let len = keys.len();
+            start = match keys.last() {
+                Some(last) => Bound::Excluded(last.clone()),
+                None => return Ok(deleted),
+            };
+            self.batch_delete(keys).await?;
+            deleted += len as u64;
+            if len < batch_size as usize {
+                return Ok(deleted);
+            }
+            if let Some(rate_limit) = rate_limit {
+                let target = Duration::from_secs_f64(deleted as f64 / rate_limit as f64);
+                let elapsed = started.elapsed();
+                if target > elapsed {
+                    tokio::time::sleep(target - elapsed).await;
+                }
+            }
+        }
+    }
+
+    /// Create a new 'scan' request.
+    ///
+    /// Once resolved this request will result in a `Vec` of key-value pairs that lies in the specified range.
+    ///
+    /// If the number of eligible key-value pairs are greater than `limit`,
+    /// only the first `limit` pairs are returned, ordered by the key.
+    ///
+    /// Limits above TiKV's maximum of 10240 pairs per request are served by paging through the
+    /// regions of the range one at a time.
+    ///
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{KvPair, Config, RawClient, IntoOwnedRange};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let inclusive_range = "TiKV"..="TiDB";
+    /// let req = client.scan(inclusive_range.into_owned(), 2);
+    /// let result: Vec<KvPair> = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn scan(&self, range: impl Into<BoundRange>, limit: u32) -> Result<Vec<KvPair>> {
+        self.scan_inner(range.into(), limit, false).await
+    }
+
+    /// Like [`scan`](Client::scan), with the options of this operation. The values of the pairs
+    /// are empty if the options are [`key_only`](Options::key_only).
+    pub async fn scan_opt(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+        options: Options,
+    ) -> Result<Vec<KvPair>> {
+        options
+            .run(
+                self.with_options(&options)
+                    .scan_inner(range, limit, options.key_only),
+            )
+            .await
+    }
+
+    /// Call `visit` with the key and value of each pair in `range`, in order, for at most `limit`
+    /// pairs.
+    ///
+    /// Unlike [`scan`](Client::scan), pairs are not collected. The regions of the range are read
+    /// one at a time, at most 10240 pairs per request, and `visit` borrows the key and value of
+    /// each pair of the response, so only one response is held in memory at a time.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::RawClient;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let mut bytes = 0;
+    /// client
+    ///     .scan_with(.., u32::MAX, |key, value| bytes += key.len() + value.len())
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn scan_with(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+        mut visit: impl FnMut(&[u8], &[u8]) + Send,
+    ) -> Result<()> {
+        self.scan_pages(range.into(), limit, false, |kvs| {
+            for kvrpcpb::KvPair { key, value, .. } in kvs {
+                let value = self.value_codec.decode(value)?;
+                visit(self.prefix.strip(&key), &value);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// A stream of the pairs in `range`, in order, for at most `limit` pairs.
+    ///
+    /// Like [`scan_with`](Client::scan_with), the regions of the range are read one at a time, at
+    /// most 10240 pairs per request, and the next request is only sent once the pairs of the
+    /// previous one have been taken from the stream. Items are `Result`s, so the stream composes
+    /// with [`TryStreamExt`](futures::TryStreamExt), e.g. `try_chunks` to process pairs in batches.
+    ///
+    /// Polling the stream is cancellation safe: dropping a future of `next()` before it completes
+    /// keeps the request in flight, and the pairs it returns are yielded by the next poll.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::RawClient;
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let mut batches = client.scan_stream(.., u32::MAX).try_chunks(100);
+    /// while let Some(batch) = batches.try_next().await.unwrap() {
+    ///     println!("{} pairs", batch.len());
+    /// }
+    /// # });
+    /// ```
+    pub fn scan_stream(&self, range: impl Into<BoundRange>, limit: u32) -> ScanStream {
+        let (start, end) = self.prefix.encode_range(range.into()).into_keys();
+        ScanStream::new(self.clone(), start, end, limit)
+    }
+
+    /// Create a new 'scan' request that only returns the keys.
+    ///
+    /// Once resolved this request will result in a `Vec` of keys that lies in the specified range.
+    ///
+    /// If the number of eligible keys are greater than `limit`,
+    /// only the first `limit` pairs are returned, ordered by the key.
+    ///
+    /// Limits above TiKV's maximum of 10240 pairs per request are served by paging through the
+    /// regions of the range one at a time.
+    ///
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let inclusive_range = "TiKV"..="TiDB";
+    /// let req = client.scan_keys(inclusive_range.into_owned(), 2);
+    /// let result: Vec<Key> = req.await.unwrap();
+    /// # });
+    /// ```
+    pub async fn scan_keys(&self, range: impl Into<BoundRange>, limit: u32) -> Result<Vec<Key>> {
+        Ok(self
+            .scan_inner(range, limit, true)
+            .await?
+            .into_iter()
+            .map(KvPair::into_key)
+            .collect())
+    }
+
+    /// Like [`scan_keys`](Client::scan_keys), with the options of this operation.
+    pub async fn scan_keys_opt(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+        options: Options,
+    ) -> Result<Vec<Key>> {
+        options
+            .run(self.with_options(&options).scan_keys(range, limit))
+            .await
+    }
+
+    /// Scan like [`scan`](Client::scan), returning each pair with its remaining time to live in
+    /// seconds, where `0` means it never expires.
+    ///
+    /// TiKV doesn't return TTLs from scans, so the TTLs of the pairs scanned are requested
+    /// concurrently once the scan is done, at most 16 at a time. Pairs which expire or are
+    /// deleted before their TTL is read are left out.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{KvPair, Config, RawClient, IntoOwnedRange};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let sessions = client.scan_with_ttl("session".to_owned().., 10).await.unwrap();
+    /// let fresh: Vec<KvPair> = sessions
+    ///     .into_iter()
+    ///     .filter(|(_, ttl)| *ttl == 0 || *ttl > 60)
+    ///     .map(|(pair, _)| pair)
+    ///     .collect();
+    /// # });
+    /// ```
+    pub async fn scan_with_ttl(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+    ) -> Result<Vec<(KvPair, u64)>> {
+        let pairs = self.scan_inner(range.into(), limit, false).await?;
+        let ttls: Vec<Option<u64>> = stream::iter(
+            pairs
+                .iter()
+                .map(|pair| self.get_key_ttl_encoded(self.prefix.encode_key(pair.key().clone()))),
+        )
+        .buffered(MAX_TTL_REQUESTS_IN_FLIGHT)
+        .try_collect()
+        .await?;
+        Ok(pairs
+            .into_iter()
+            .zip(ttls)
+            .filter_map(|(pair, ttl)| Some((pair, ttl?)))
+            .collect())
+    }
+
+    /// Create a new 'batch scan' request.
+    ///
+    /// Once resolved this request will result in a set of scanners over the given keys.
+    ///
+    /// The first `each_limit` pairs of every range are returned, in the order the ranges are given,
+    /// with a pair repeated for every range containing it.
+    ///
+    /// If `each_limit` is above TiKV's maximum of 10240 pairs per request, the ranges are instead
+    /// scanned one at a time by paging through their regions.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let inclusive_range1 = "TiDB"..="TiKV";
+    /// let inclusive_range2 = "TiKV"..="TiSpark";
+    /// let iterable = vec![inclusive_range1.into_owned(), inclusive_range2.into_owned()];
+    /// let req = client.batch_scan(iterable, 2);
+    /// let result = req.await;
+    /// # });
+    /// ```
+    pub async fn batch_scan(
+        &self,
+        ranges: impl IntoIterator<Item = impl Into<BoundRange>>,
+        each_limit: u32,
+    ) -> Result<Vec<KvPair>> {
+        self.batch_scan_inner(ranges, each_limit, false).await
+    }
+
+    /// Create a new 'batch scan' request that only returns the keys.
+    ///
+    /// Once resolved this request will result in a set of scanners over the given keys.
+    ///
+    /// The first `each_limit` pairs of every range are returned, in the order the ranges are given,
+    /// with a pair repeated for every range containing it.
+    ///
+    /// If `each_limit` is above TiKV's maximum of 10240 pairs per request, the ranges are instead
+    /// scanned one at a time by paging through their regions.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Config, RawClient, IntoOwnedRange};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let inclusive_range1 = "TiDB"..="TiKV";
+    /// let inclusive_range2 = "TiKV"..="TiSpark";
+    /// let iterable = vec![inclusive_range1.into_owned(), inclusive_range2.into_owned()];
+    /// let req = client.batch_scan(iterable, 2);
+    /// let result = req.await;
+    /// # });
+    /// ```
+    pub async fn batch_scan_keys(
+        &self,
+        ranges: impl IntoIterator<Item = impl Into<BoundRange>>,
+        each_limit: u32,
+    ) -> Result<Vec<Key>> {
+        Ok(self
+            .batch_scan_inner(ranges, each_limit, true)
+            .await?
+            .into_iter()
+            .map(KvPair::into_key)
+            .collect())
+    }
+
+    /// Watch the changes of the keys starting with `prefix`, committed from now on.
+    ///
+    /// Returns a stream of the puts and deletes of the keys, received from the change data capture
+    /// service of TiKV. The changes of a key are received in the order they were committed, but
+    /// changes of keys in different regions are not ordered.
+    ///
+    /// The stream ends after an error, e.g. when a region of the keys splits or its leader moves.
+    /// To continue watching, call [`watch_since`](Client::watch_since) with the commit timestamp
+    /// of the last change received.
+    ///
+    /// Only changes written with API V2 are captured, which needs TiKV 6.2 or later. Watches
+    /// changes of the default column family only.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{RawClient, WatchEvent};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let mut changes = client.watch("config/".to_owned()).await.unwrap().boxed();
+    /// while let Some(change) = changes.next().await {
+    ///     match change.unwrap() {
+    ///         WatchEvent::Put { key, value, .. } => println!("{:?} = {:?}", key, value),
+    ///         WatchEvent::Delete { key, .. } => println!("{:?} deleted", key),
+    ///     }
+    /// }
+    /// # });
+    /// ```
+    pub async fn watch(
+        &self,
+        prefix: impl Into<Key>,
+    ) -> Result<impl Stream<Item = Result<WatchEvent>>> {
+        let timestamp = self.rpc.clone().get_timestamp().await?;
+        self.watch_since(prefix, timestamp).await
+    }
+
+    /// Watch the changes of the keys starting with `prefix`, committed after `timestamp`.
+    ///
+    /// Changes committed before the call are received first. `timestamp` must not be older than
+    /// the GC safepoint. See [`watch`](Client::watch) for details.
+    pub async fn watch_since(
+        &self,
+        prefix: impl Into<Key>,
+        timestamp: Timestamp,
+    ) -> Result<impl Stream<Item = Result<WatchEvent>>> {
+        let prefix = self.prefix.join(&Prefix::new(prefix.into()));
+        let range = prefix.encode_range((..).into());
+        let feed = Feed::register(&self.rpc, &range, timestamp.version()).await?;
+        let client = self.clone();
+        Ok(stream::try_unfold(
+            (feed, VecDeque::new()),
+            move |(mut feed, mut rows)| {
+                let client = client.clone();
+                async move {
+                    loop {
+                        match rows.pop_front() {
+                            Some(row) => {
+                                if let Some(event) = client.watch_event(row)? {
+                                    return Ok(Some((event, (feed, rows))));
+                                }
+                            }
+                            None => rows.extend(feed.next().await?),
+                        }
+                    }
+                }
+            },
+        ))
+    }
+
+    /// The approximate size and number of pairs of `range`.
+    ///
+    /// Sizes are TiKV's approximations of the size of each region of the range, measured by the
+    /// debug service of the leader of the region. Regions which only partly overlap the range
+    /// count in full. The number of pairs of a region is estimated from its size and the size of
+    /// its first pairs in the range, and is exact for regions with few pairs. Takes a couple of
+    /// requests per region, so is meant to plan jobs over large ranges rather than to be called
+    /// often.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let stats = client.range_stats("user".to_owned().."uses".to_owned()).await.unwrap();
+    /// println!("~{} pairs in {} regions", stats.approximate_keys(), stats.regions());
+    /// # });
+    /// ```
+    pub async fn range_stats(&self, range: impl Into<BoundRange>) -> Result<RangeStats> {
+        let regions = self
+            .region_stats(self.prefix.encode_range(range.into()))
+            .await?;
+        Ok(RangeStats::sum(&regions))
+    }
+
+    /// Sample up to `n` distinct keys of `range`, in order.
+    ///
+    /// Samples are split between the regions of the range in proportion to their approximate
+    /// sizes, as measured by [`range_stats`](Client::range_stats). Each sample is the first key
+    /// after a random key in its region, so samples are only roughly uniform over the pairs of a
+    /// region. The samples make good split keys to scan a range in parallel. Takes a scan per
+    /// sample, on top of the requests of `range_stats`.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, Key, RawClient};
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let splits: Vec<Key> = client.sample_keys("user".to_owned().., 7).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn sample_keys(&self, range: impl Into<BoundRange>, n: usize) -> Result<Vec<Key>> {
+        let regions = self
+            .region_stats(self.prefix.encode_range(range.into()))
+            .await?;
+        let sizes: Vec<u64> = regions.iter().map(|region| region.size).collect();
+        let mut keys = Vec::with_capacity(n);
+        for (region, samples) in regions.iter().zip(allocate(&sizes, n)) {
+            for _ in 0..samples {
+                let probe = random_key(&region.start, region.end.as_ref(), &mut rand::thread_rng());
+                // Wrap around to the start of the region if there is no key after the probe.
+                let key = match self.first_key(probe, region.end.clone()).await? {
+                    Some(key) => Some(key),
+                    None => {
+                        self.first_key(region.start.clone(), region.end.clone())
+                            .await?
+                    }
+                };
+                keys.extend(key);
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        Ok(keys
+            .into_iter()
+            .map(|key| self.prefix.decode_key(key))
+            .collect())
+    }
+
+    /// The regions PD currently finds hot for reads or writes, to find hotspots to split.
+    ///
+    /// Hot regions are read from the HTTP API of the PD leader, over TLS if the client is
+    /// configured with it. Errors of requests to hot regions can be annotated with
+    /// [`Config::with_hot_region_diagnostics`](crate::Config::with_hot_region_diagnostics).
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// for region in client.hot_regions().await.unwrap() {
+    ///     println!("region {} is {}", region.region_id(), region);
+    /// }
+    /// # });
+    /// ```
+    pub async fn hot_regions(&self) -> Result<Vec<HotRegion>> {
+        self.rpc.hot_regions().await
+    }
+
+    /// Create a keyspace, with the given configuration, in the keyspace service of PD, e.g. to
+    /// provision a tenant.
+    ///
+    /// Keyspaces are created through the HTTP API of the PD leader, as the keyspace service of PD
+    /// has no RPC to create them.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::RawClient;
+    /// # use std::collections::HashMap;
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let keyspace = client.create_keyspace("tenant_a", HashMap::new()).await.unwrap();
+    /// println!("created keyspace {} with ID {}", keyspace.name(), keyspace.id());
+    /// for keyspace in client.keyspaces().await.unwrap() {
+    ///     println!("keyspace {} is {}", keyspace.name(), keyspace.state());
+    /// }
+    /// # });
+    /// ```
+    pub async fn create_keyspace(
+        &self,
+        name: &str,
+        config: HashMap<String, String>,
+    ) -> Result<Keyspace> {
+        self.rpc.create_keyspace(name, &config).await
+    }
+
+    /// All keyspaces registered in PD, in the order of their IDs.
+    pub async fn keyspaces(&self) -> Result<Vec<Keyspace>> {
+        self.rpc.keyspaces().await
+    }
+
+    /// The keyspace registered in PD as `name`.
+    pub async fn keyspace(&self, name: &str) -> Result<Keyspace> {
+        self.rpc.keyspace(name).await
+    }
+
+    /// Create a resource group in the resource manager of PD, e.g. to limit the load of a tenant.
+    ///
+    /// Requests are charged to the group by
+    /// [`Config::with_resource_group`](crate::Config::with_resource_group) or
+    /// [`with_resource_group`](Client::with_resource_group).
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{RawClient, ResourceGroup};
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let group = ResourceGroup::new("tenant_a", 2000).with_burst_limit(4000);
+    /// client.create_resource_group(&group).await.unwrap();
+    /// let tenant_a = client.with_resource_group("tenant_a");
+    /// # });
+    /// ```
+    pub async fn create_resource_group(&self, group: &ResourceGroup) -> Result<()> {
+        self.rpc.create_resource_group(group).await
+    }
+
+    /// Replace the settings of the resource group named like `group`.
+    pub async fn update_resource_group(&self, group: &ResourceGroup) -> Result<()> {
+        self.rpc.update_resource_group(group).await
+    }
+
+    /// All resource groups registered in PD.
+    pub async fn resource_groups(&self) -> Result<Vec<ResourceGroup>> {
+        self.rpc.resource_groups().await
+    }
+
+    /// The resource group registered in PD as `name`.
+    pub async fn resource_group(&self, name: &str) -> Result<ResourceGroup> {
+        self.rpc.resource_group(name).await
+    }
+
+    /// Delete the resource group named `name`. Requests charged to it fall back to the default
+    /// group.
+    pub async fn delete_resource_group(&self, name: &str) -> Result<()> {
+        self.rpc.delete_resource_group(name).await
+    }
+
+    /// Load the regions of `ranges` and connect to their stores, so the first requests to them
+    /// don't wait for PD and for connections, e.g. before a latency-critical service takes
+    /// traffic. Returns the number of stores connected to.
+    ///
+    /// Fails if a store can't be connected to within the request timeout.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::RawClient;
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// client.warmup(vec!["user".to_owned().."uses".to_owned()]).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn warmup(
+        &self,
+        ranges: impl IntoIterator<Item = impl Into<BoundRange>>,
+    ) -> Result<usize> {
+        let ranges = ranges
+            .into_iter()
+            .map(|range| self.prefix.encode_range(range.into()))
+            .collect();
+        self.rpc.clone().warmup(ranges).await
+    }
+
+    /// Split `range` into at most `max_chunks` consecutive ranges made of whole regions, except
+    /// for the regions cut by the ends of `range`, with as even a number of regions per range as
+    /// possible.
+    ///
+    /// The ranges can be handed to parallel workers, each of which then reads from its own
+    /// regions. Regions may split or merge afterwards, which doesn't affect correctness.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::RawClient;
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// for range in client.split_range_by_regions(.., 8).await.unwrap() {
+    ///     let pairs = client.scan(range, 1024).await.unwrap();
+    /// }
+    /// # });
+    /// ```
+    pub async fn split_range_by_regions(
+        &self,
+        range: impl Into<BoundRange>,
+        max_chunks: usize,
+    ) -> Result<Vec<BoundRange>> {
+        let range = range.into();
+        let boundaries = self
+            .rpc
+            .region_aligned_boundaries(self.prefix.encode_range(range.clone()), max_chunks)
+            .await?;
+        Ok(range.split_at(
+            boundaries
+                .into_iter()
+                .map(|key| self.prefix.decode_key(key))
+                .collect(),
+        ))
+    }
+
+    /// The request units TiKV reports consumed by the requests of the client, and of the clients
+    /// sharing its connections, by the resource group they were charged to, e.g. to meter
+    /// tenants.
+    ///
+    /// Requests not charged to a group are counted under `default`. Only the transactional reads,
+    /// prewrites, commits and pessimistic locks report their request units.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::RawClient;
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// for (group, units) in client.request_units() {
+    ///     println!("{}: {:.1} RU", group, units);
+    /// }
+    /// # });
+    /// ```
+    pub fn request_units(&self) -> HashMap<String, f64> {
+        self.rpc.request_units()
+    }
+
+    /// The statistics of the requests sent to each TiKV store, by store address.
+    ///
+    /// Statistics are shared by all clients created from the same connections, and cover the
+    /// stores connected to so far.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, RawClient};
+    /// # futures::executor::block_on(async {
+    /// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// for (address, stats) in client.store_stats() {
+    ///     println!("{}: {:?} latency, {} errors", address, stats.latency(), stats.errors());
+    /// }
+    /// # });
+    /// ```
+    pub fn store_stats(&self) -> HashMap<String, StoreStats> {
+        self.rpc.store_stats()
+    }
+
+    async fn scan_inner(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+        key_only: bool,
+    ) -> Result<Vec<KvPair>> {
+        if self.sequential_scan || limit > MAX_RAW_KV_SCAN_LIMIT {
+            return self.scan_sequential(range.into(), limit, key_only).await;
+        }
 
-        let request = new_raw_scan_request(range.into(), limit, key_only, self.cf.clone());
+        let request = new_raw_scan_request(
+            self.prefix.encode_range(range.into()),
+            limit,
+            key_only,
+            self.cf.clone(),
+        );
         let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .resolve_lock(OPTIMISTIC_BACKOFF)
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
             .multi_region()
-            .retry_region(DEFAULT_REGION_BACKOFF)
+            .retry_region(self.rpc.retry_options().region_backoff)
             .merge(Collect)
             .plan();
-        let res = plan.execute().await;
-        res.map(|mut s| {
-            s.truncate(limit as usize);
-            s
+        let mut res = plan.execute().await?;
+        res.truncate(limit as usize);
+        self.decode_pairs(res, key_only)
+    }
+
+    /// A client sharing the connections of `self`, which sends requests with `options`.
+    fn with_options(&self, options: &Options) -> Client {
+        Client {
+            rpc: self.rpc.clone().with_options(options),
+            ..self.clone()
+        }
+    }
+
+    /// Scan the regions of `range` in order, requesting only the pairs still missing, at most
+    /// `MAX_RAW_KV_SCAN_LIMIT` at a time.
+    async fn scan_sequential(
+        &self,
+        range: BoundRange,
+        limit: u32,
+        key_only: bool,
+    ) -> Result<Vec<KvPair>> {
+        let mut pairs = Vec::new();
+        self.scan_pages(range, limit, key_only, |kvs| {
+            pairs.extend(kvs.into_iter().map(Into::into));
+            Ok(())
         })
+        .await?;
+        self.decode_pairs(pairs, key_only)
+    }
+
+    /// Scan the regions of `range` in order like [`scan_sequential`](Client::scan_sequential),
+    /// passing each page of undecoded pairs to `visit`.
+    async fn scan_pages(
+        &self,
+        range: BoundRange,
+        limit: u32,
+        key_only: bool,
+        mut visit: impl FnMut(Vec<kvrpcpb::KvPair>) -> Result<()> + Send,
+    ) -> Result<()> {
+        let (mut start, end) = self.prefix.encode_range(range).into_keys();
+        let mut visited = 0;
+        while visited < limit {
+            let (kvs, next) = self
+                .scan_page(start, end.clone(), limit - visited, key_only)
+                .await?;
+            visited += kvs.len() as u32;
+            visit(kvs)?;
+            match next {
+                Some(next) => start = next,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Scan the region containing the encoded key `start`, up to the encoded `end`, for at most
+    /// `limit` pairs and at most `MAX_RAW_KV_SCAN_LIMIT`. Returns the undecoded pairs and the key
+    /// to continue from, if the range has more pairs.
+    pub(super) async fn scan_page(
+        &self,
+        start: Key,
+        end: Option<Key>,
+        limit: u32,
+        key_only: bool,
+    ) -> Result<(Vec<kvrpcpb::KvPair>, Option<Key>)> {
+        let store = self.rpc.clone().store_for_key(&start).await?;
+        let region_end = store.region.end_key();
+        let (request_end, last_region) = match end {
+            Some(end) if region_end.is_empty() || end <= region_end => (Some(end), true),
+            _ if region_end.is_empty() => (None, true),
+            _ => (Some(region_end.clone()), false),
+        };
+        let batch_limit = limit.min(MAX_RAW_KV_SCAN_LIMIT);
+        let request = new_raw_scan_request(
+            (start, request_end).into(),
+            batch_limit,
+            key_only,
+            self.cf.clone(),
+        );
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .single_region_with_store(store)
+            .await?
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .plan();
+        let kvs = plan.execute().await?.take_kvs();
+        let next = match kvs.last() {
+            // The region may have more pairs, continue after the last one.
+            Some(last) if kvs.len() as u32 == batch_limit => {
+                let mut next = last.key.clone();
+                next.push(0);
+                Some(next.into())
+            }
+            _ if last_region => None,
+            _ => Some(region_end),
+        };
+        Ok((kvs, next))
     }
 
     async fn batch_scan_inner(
@@ -456,24 +1978,323 @@ impl Client {
         key_only: bool,
     ) -> Result<Vec<KvPair>> {
         if each_limit > MAX_RAW_KV_SCAN_LIMIT {
-            return Err(Error::MaxScanLimitExceeded {
-                limit: each_limit,
-                max_limit: MAX_RAW_KV_SCAN_LIMIT,
-            });
+            let mut pairs = Vec::new();
+            for range in ranges {
+                pairs.extend(
+                    self.scan_sequential(range.into(), each_limit, key_only)
+                        .await?,
+                );
+            }
+            return Ok(pairs);
         }
 
+        let originals: Vec<BoundRange> = ranges.into_iter().map(Into::into).collect();
+        let ranges: Vec<kvrpcpb::KeyRange> = originals
+            .iter()
+            .map(|range| self.prefix.encode_range(range.clone()).into())
+            .collect();
+        let scanned = if self.merge_batch_scan_ranges {
+            merge_key_ranges(ranges.clone())
+        } else {
+            ranges.clone()
+        };
         let request = new_raw_batch_scan_request(
-            ranges.into_iter().map(Into::into),
+            scanned.iter().cloned().map(BoundRange::from),
             each_limit,
             key_only,
             self.cf.clone(),
         );
         let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
-            .resolve_lock(OPTIMISTIC_BACKOFF)
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .multi_region()
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .merge(CollectError)
+            .plan();
+        // TiKV applies `each_limit` to every range in every region. The first pairs of a range
+        // are then among the pairs returned, unless the range was merged with others which used
+        // up the limit in a region, in which case the range is scanned again on its own.
+        let mut pairs = Vec::new();
+        let mut truncated = Vec::new();
+        for mut response in plan.execute().await? {
+            let kvs = response.take_kvs();
+            if self.merge_batch_scan_ranges {
+                truncated.extend(truncations(&scanned, &kvs, each_limit));
+            }
+            pairs.extend(kvs.into_iter().map(KvPair::from));
+        }
+        let mut res = Vec::new();
+        for (range, range_pairs) in originals
+            .into_iter()
+            .zip(pairs_by_range(pairs, &ranges, each_limit, &truncated))
+        {
+            match range_pairs {
+                Some(range_pairs) => res.extend(range_pairs),
+                None => {
+                    self.scan_pages(range, each_limit, key_only, |kvs| {
+                        res.extend(kvs.into_iter().map(Into::into));
+                        Ok(())
+                    })
+                    .await?
+                }
+            }
+        }
+        self.decode_pairs(res, key_only)
+    }
+
+    /// Strip the prefix from keys and, unless only keys were requested, decode values.
+    pub(super) fn decode_pairs(&self, pairs: Vec<KvPair>, key_only: bool) -> Result<Vec<KvPair>> {
+        let pairs = pairs.into_iter().map(|pair| self.prefix.decode_pair(pair));
+        if key_only {
+            Ok(pairs.collect())
+        } else {
+            self.value_codec.decode_pairs(pairs)
+        }
+    }
+
+    /// The change of a row of a change data feed, if it is a committed put or delete.
+    fn watch_event(&self, row: event::Row) -> Result<Option<WatchEvent>> {
+        if !matches!(
+            row.get_type(),
+            event::LogType::Committed | event::LogType::Commit
+        ) {
+            return Ok(None);
+        }
+        let op_type = row.get_op_type();
+        let key = self.prefix.decode_key(row.key.into());
+        let commit_ts = Timestamp::from_version(row.commit_ts);
+        match op_type {
+            event::row::OpType::Put => Ok(Some(WatchEvent::Put {
+                key,
+                value: self.value_codec.decode(row.value)?,
+                commit_ts,
+            })),
+            event::row::OpType::Delete => Ok(Some(WatchEvent::Delete { key, commit_ts })),
+            event::row::OpType::Unknown => Ok(None),
+        }
+    }
+
+    /// Get the value of a prefixed key, without decoding it. Concurrent gets of the key are
+    /// coalesced if read coalescing is enabled.
+    async fn get_coalesced(&self, key: Key) -> Result<Option<Value>> {
+        match &self.coalescer {
+            Some(coalescer) => {
+                let fetch = || {
+                    let client = self.clone();
+                    let key = key.clone();
+                    async move { client.get_encoded(key).await }
+                };
+                coalescer.get((self.cf.clone(), key.clone()), fetch).await
+            }
+            None => self.get_encoded(key).await,
+        }
+    }
+
+    /// Get the value of a prefixed key, without decoding it.
+    async fn get_encoded(&self, key: Key) -> Result<Option<Value>> {
+        let request = new_raw_get_request(key, self.cf.clone());
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .single_region()
+            .await?
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .post_process_default()
+            .plan();
+        plan.execute().await
+    }
+
+    /// Get the TTL of a prefixed key.
+    async fn get_key_ttl_encoded(&self, key: Key) -> Result<Option<u64>> {
+        let request = new_raw_get_key_ttl_request(key, self.cf.clone());
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .single_region()
+            .await?
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .extract_error()
+            .post_process_default()
+            .plan();
+        plan.execute().await
+    }
+
+    /// The statistics of the regions of a prefixed range, each limited to the range.
+    async fn region_stats(&self, range: BoundRange) -> Result<Vec<RegionStats>> {
+        let (start, end) = range.clone().into_keys();
+        let cf = self.cf.clone().unwrap_or(ColumnFamily::Default);
+        let mut stores = self.rpc.clone().stores_for_range(range);
+        let mut regions = Vec::new();
+        while let Some(store) = stores.next().await {
+            let store = store?;
+            let size = self.rpc.approximate_region_size(&store.region, &cf).await?;
+            let (region_start, region_end) = store.region.range();
+            let region_start = std::cmp::max(region_start, start.clone());
+            let region_end = match &end {
+                Some(end) if region_end.is_empty() || *end < region_end => Some(end.clone()),
+                _ if region_end.is_empty() => None,
+                _ => Some(region_end),
+            };
+            let request = new_raw_scan_request(
+                (region_start.clone(), region_end.clone()).into(),
+                STATS_SAMPLE_LIMIT,
+                false,
+                self.cf.clone(),
+            );
+            let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+                .single_region_with_store(store)
+                .await?
+                .resolve_lock(self.rpc.retry_options().lock_backoff)
+                .retry_region(self.rpc.retry_options().region_backoff)
+                .plan();
+            let sample: Vec<KvPair> = plan
+                .execute()
+                .await?
+                .take_kvs()
+                .into_iter()
+                .map(Into::into)
+                .collect();
+            regions.push(RegionStats {
+                start: region_start,
+                end: region_end,
+                size,
+                keys: estimate_keys(size, &sample, STATS_SAMPLE_LIMIT),
+            });
+        }
+        Ok(regions)
+    }
+
+    /// The first prefixed key in a prefixed range.
+    async fn first_key(&self, start: Key, end: Option<Key>) -> Result<Option<Key>> {
+        let request = new_raw_scan_request((start, end).into(), 1, true, self.cf.clone());
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
             .multi_region()
-            .retry_region(DEFAULT_REGION_BACKOFF)
+            .retry_region(self.rpc.retry_options().region_backoff)
             .merge(Collect)
             .plan();
+        let pairs: Vec<KvPair> = plan.execute().await?;
+        Ok(pairs.into_iter().next().map(KvPair::into_key))
+    }
+
+    /// Put prefixed pairs with encoded values.
+    async fn batch_put_encoded(&self, pairs: Vec<KvPair>) -> Result<()> {
+        let request = new_raw_batch_put_request(pairs.into_iter(), self.cf.clone());
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .multi_region()
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .extract_error()
+            .plan();
+        plan.execute().await?;
+        Ok(())
+    }
+
+    /// Delete prefixed keys.
+    async fn batch_delete_encoded(&self, keys: Vec<Key>) -> Result<()> {
+        let request = new_raw_batch_delete_request(keys.into_iter(), self.cf.clone());
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .multi_region()
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .extract_error()
+            .plan();
+        plan.execute().await?;
+        Ok(())
+    }
+
+    /// Apply the encoded mutations of a conditional write.
+    async fn apply_encoded(&self, mutations: Vec<RawMutation>) -> Result<()> {
+        let (mut puts, mut deletes) = (Vec::new(), Vec::new());
+        for mutation in mutations {
+            match mutation {
+                RawMutation::Put(key, value) => puts.push(KvPair(key, value)),
+                RawMutation::Delete(key) => deletes.push(key),
+            }
+        }
+        if !puts.is_empty() {
+            self.batch_put_encoded(puts).await?;
+        }
+        if !deletes.is_empty() {
+            self.batch_delete_encoded(deletes).await?;
+        }
+        Ok(())
+    }
+
+    /// Wait until the guard key of conditional writes is free or expired and take it, finishing
+    /// the write of an expired writer first. Returns the token and expiry of the new lease.
+    async fn acquire_guard(&self, guard: Key) -> Result<(u64, u64)> {
+        let mut backoff = GUARD_BACKOFF;
+        let mut current = self.get_encoded(guard.clone()).await?;
+        loop {
+            let now = self.rpc.clone().get_timestamp().await?.physical as u64;
+            match Guard::decode(current.as_deref())? {
+                Guard::Held { expires_at, .. } if expires_at > now => {
+                    if let Some(delay) = backoff.next_delay_duration() {
+                        tokio::time::sleep(delay).await;
+                    }
+                    current = self.get_encoded(guard.clone()).await?;
+                    continue;
+                }
+                Guard::Held {
+                    intent: Some(mutations),
+                    ..
+                } => self.apply_encoded(mutations).await?,
+                _ => {}
+            }
+            let token = rand::random();
+            let expires_at = now + GUARD_LEASE_MILLIS;
+            let held = Guard::Held {
+                token,
+                expires_at,
+                intent: None,
+            };
+            let (previous, swapped) = self
+                .compare_and_swap_encoded(guard.clone(), current, held.encode())
+                .await?;
+            if swapped {
+                return Ok((token, expires_at));
+            }
+            current = previous;
+        }
+    }
+
+    /// Free the guard key of conditional writes if it still has the value `held`.
+    async fn release_guard(&self, guard: Key, held: Value) -> Result<()> {
+        self.compare_and_swap_encoded(guard, Some(held), Guard::Free.encode())
+            .await?;
+        Ok(())
+    }
+
+    /// Compare and swap with a prefixed key and encoded values.
+    async fn compare_and_swap_encoded(
+        &self,
+        key: Key,
+        previous_value: Option<Value>,
+        new_value: Value,
+    ) -> Result<(Option<Value>, bool)> {
+        let request = new_raw_cas_request(key, new_value, previous_value, self.cf.clone());
+        self.cas_with_ttl(request, 0).await
+    }
+
+    /// Send a compare and swap request, making the key expire `ttl_secs` seconds from now if it
+    /// is swapped, or never if `ttl_secs` is `0`.
+    async fn cas_with_ttl(
+        &self,
+        mut request: kvrpcpb::RawCasRequest,
+        ttl_secs: u64,
+    ) -> Result<(Option<Value>, bool)> {
+        if !self.rpc.features().raw_cas {
+            return Err(Error::UnsupportedFeature {
+                feature: "Raw compare and swap",
+            });
+        }
+        request.set_ttl(ttl_secs);
+        let plan = crate::request::PlanBuilder::new(self.rpc.clone(), request)
+            .single_region()
+            .await?
+            .resolve_lock(self.rpc.retry_options().lock_backoff)
+            .retry_region(self.rpc.retry_options().region_backoff)
+            .extract_error()
+            .post_process_default()
+            .plan();
         plan.execute().await
     }
 }
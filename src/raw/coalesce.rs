@@ -0,0 +1,165 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::{Error, Result};
+use futures::{
+    future::{BoxFuture, Shared},
+    prelude::*,
+};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+/// Deduplicates concurrent reads of the same key into a single in-flight request.
+///
+/// The first caller for a key (the leader) starts the request; callers arriving while it is in
+/// flight wait for its result. If the request fails, the leader gets the error and each waiting
+/// caller sends its own request, so errors are never shared.
+pub(crate) struct Coalescer<K, V: Clone> {
+    in_flight: Arc<Mutex<HashMap<K, Shared<BoxFuture<'static, Option<V>>>>>>,
+}
+
+impl<K, V: Clone> Clone for Coalescer<K, V> {
+    fn clone(&self) -> Self {
+        Coalescer {
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+impl<K, V: Clone> Default for Coalescer<K, V> {
+    fn default() -> Self {
+        Coalescer {
+            in_flight: Default::default(),
+        }
+    }
+}
+
+impl<K, V> Coalescer<K, V>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub async fn get<F, Fut>(&self, key: K, fetch: F) -> Result<V>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<V>> + Send + 'static,
+    {
+        let (shared, leader_error) = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(shared) => (shared.clone(), None),
+                None => {
+                    let error: Arc<Mutex<Option<Error>>> = Default::default();
+                    let shared = Self::fetch_shared(
+                        self.in_flight.clone(),
+                        key.clone(),
+                        fetch(),
+                        error.clone(),
+                    );
+                    in_flight.insert(key, shared.clone());
+                    (shared, Some(error))
+                }
+            }
+        };
+        match (shared.await, leader_error) {
+            (Some(value), _) => Ok(value),
+            (None, Some(error)) => match error.lock().unwrap().take() {
+                Some(e) => Err(e),
+                None => unreachable!("failed request without an error"),
+            },
+            (None, None) => fetch().await,
+        }
+    }
+
+    fn fetch_shared(
+        in_flight: Arc<Mutex<HashMap<K, Shared<BoxFuture<'static, Option<V>>>>>>,
+        key: K,
+        fetch: impl Future<Output = Result<V>> + Send + 'static,
+        error: Arc<Mutex<Option<Error>>>,
+    ) -> Shared<BoxFuture<'static, Option<V>>> {
+        async move {
+            let result = fetch.await;
+            in_flight.lock().unwrap().remove(&key);
+            match result {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    *error.lock().unwrap() = Some(e);
+                    None
+                }
+            }
+        }
+        .boxed()
+        .shared()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_coalesce() {
+        let coalescer = Coalescer::<u32, u32>::default();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = futures::channel::oneshot::channel::<()>();
+        let receiver = receiver.shared();
+
+        let fetch = || {
+            let fetches = fetches.clone();
+            let receiver = receiver.clone();
+            async move {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                receiver.await.unwrap();
+                Ok(42)
+            }
+        };
+        let gets = future::join3(
+            coalescer.get(1, fetch),
+            coalescer.get(1, fetch),
+            coalescer.get(2, fetch),
+        );
+        let results = executor::block_on(future::join(gets, async {
+            sender.send(()).unwrap();
+        }))
+        .0;
+        assert_eq!(results.0.unwrap(), 42);
+        assert_eq!(results.1.unwrap(), 42);
+        assert_eq!(results.2.unwrap(), 42);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert!(coalescer.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_coalesce_error() {
+        let coalescer = Coalescer::<u32, u32>::default();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = futures::channel::oneshot::channel::<()>();
+        let receiver = receiver.shared();
+
+        let fetch = || {
+            let fetches = fetches.clone();
+            let receiver = receiver.clone();
+            async move {
+                // The first request fails, later ones succeed.
+                if fetches.fetch_add(1, Ordering::SeqCst) == 0 {
+                    receiver.await.unwrap();
+                    Err(Error::Unimplemented)
+                } else {
+                    Ok(42)
+                }
+            }
+        };
+        let gets = future::join(coalescer.get(1, fetch), coalescer.get(1, fetch));
+        let (leader, follower) = executor::block_on(future::join(gets, async {
+            sender.send(()).unwrap();
+        }))
+        .0;
+        assert!(matches!(leader, Err(Error::Unimplemented)));
+        assert_eq!(follower.unwrap(), 42);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::{Key, Result, Value};
+use std::convert::TryInto;
+use tikv_client_common::internal_err;
+
+const FREE: u8 = 0;
+const HELD: u8 = 1;
+const COMMITTING: u8 = 2;
+
+const PUT: u8 = 0;
+const DELETE: u8 = 1;
+
+/// A write applied by [`RawClient::conditional_write`](super::Client::conditional_write).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RawMutation {
+    Put(Key, Value),
+    Delete(Key),
+}
+
+/// The value of the guard key of conditional writes.
+///
+/// A writer holds the guard until `expires_at` (physical time of the timestamp oracle, in
+/// milliseconds). Once it has checked its conditions, it records its (encoded) mutations in the
+/// guard before applying them, so that a writer finding the guard expired in the middle of
+/// applying them can finish the write before taking over.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Guard {
+    Free,
+    Held {
+        token: u64,
+        expires_at: u64,
+        intent: Option<Vec<RawMutation>>,
+    },
+}
+
+impl Guard {
+    pub fn encode(&self) -> Value {
+        match self {
+            Guard::Free => vec![FREE],
+            Guard::Held {
+                token,
+                expires_at,
+                intent,
+            } => {
+                let mut value = vec![if intent.is_some() { COMMITTING } else { HELD }];
+                value.extend_from_slice(&token.to_be_bytes());
+                value.extend_from_slice(&expires_at.to_be_bytes());
+                for mutation in intent.iter().flatten() {
+                    match mutation {
+                        RawMutation::Put(key, v) => {
+                            value.push(PUT);
+                            encode_bytes(&mut value, key.into());
+                            encode_bytes(&mut value, v);
+                        }
+                        RawMutation::Delete(key) => {
+                            value.push(DELETE);
+                            encode_bytes(&mut value, key.into());
+                        }
+                    }
+                }
+                value
+            }
+        }
+    }
+
+    /// Decode the value of a guard key. A missing key is a free guard.
+    pub fn decode(value: Option<&[u8]>) -> Result<Guard> {
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(Guard::Free),
+        };
+        let invalid = || internal_err!("invalid conditional write guard {:?}", value);
+        let (tag, mut data) = value.split_first().ok_or_else(invalid)?;
+        if *tag == FREE {
+            return Ok(Guard::Free);
+        }
+        if (*tag != HELD && *tag != COMMITTING) || data.len() < 16 {
+            return Err(invalid());
+        }
+        let token = u64::from_be_bytes(data[..8].try_into().unwrap());
+        let expires_at = u64::from_be_bytes(data[8..16].try_into().unwrap());
+        data = &data[16..];
+        let intent = if *tag == COMMITTING {
+            let mut mutations = Vec::new();
+            while let Some((op, rest)) = data.split_first() {
+                data = rest;
+                let key = decode_bytes(&mut data).ok_or_else(invalid)?.to_vec();
+                mutations.push(match *op {
+                    PUT => RawMutation::Put(
+                        key.into(),
+                        decode_bytes(&mut data).ok_or_else(invalid)?.to_vec(),
+                    ),
+                    DELETE => RawMutation::Delete(key.into()),
+                    _ => return Err(invalid()),
+                });
+            }
+            Some(mutations)
+        } else if data.is_empty() {
+            None
+        } else {
+            return Err(invalid());
+        };
+        Ok(Guard::Held {
+            token,
+            expires_at,
+            intent,
+        })
+    }
+}
+
+fn encode_bytes(value: &mut Value, bytes: &[u8]) {
+    value.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    value.extend_from_slice(bytes);
+}
+
+fn decode_bytes<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    if data.len() < 4 {
+        return None;
+    }
+    let len = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+    let bytes = data.get(4..4 + len)?;
+    *data = &data[4 + len..];
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_guard_codec() {
+        let guards = vec![
+            Guard::Free,
+            Guard::Held {
+                token: 7,
+                expires_at: 1_600_000_000_000,
+                intent: None,
+            },
+            Guard::Held {
+                token: 7,
+                expires_at: 1_600_000_000_000,
+                intent: Some(vec![
+                    RawMutation::Put(b"k1".to_vec().into(), b"v1".to_vec()),
+                    RawMutation::Delete(b"k2".to_vec().into()),
+                    RawMutation::Put(b"k3".to_vec().into(), Vec::new()),
+                ]),
+            },
+            Guard::Held {
+                token: 7,
+                expires_at: 0,
+                intent: Some(Vec::new()),
+            },
+        ];
+        for guard in guards {
+            assert_eq!(Guard::decode(Some(&guard.encode())).unwrap(), guard);
+        }
+        assert_eq!(Guard::decode(None).unwrap(), Guard::Free);
+        assert!(Guard::decode(Some(b"")).is_err());
+        assert!(Guard::decode(Some(&[HELD, 1, 2])).is_err());
+        let mut truncated = Guard::Held {
+            token: 7,
+            expires_at: 0,
+            intent: Some(vec![RawMutation::Delete(b"key".to_vec().into())]),
+        }
+        .encode();
+        truncated.pop();
+        assert!(Guard::decode(Some(&truncated)).is_err());
+    }
+}
@@ -33,6 +33,22 @@ pub fn new_raw_batch_put_request(
     requests::new_raw_batch_put_request(pairs.map(Into::into).collect(), cf)
 }
 
+pub fn new_raw_cas_request(
+    key: Key,
+    value: Value,
+    previous_value: Option<Value>,
+    cf: Option<ColumnFamily>,
+) -> kvrpcpb::RawCasRequest {
+    requests::new_raw_cas_request(key.into(), value, previous_value, cf)
+}
+
+pub fn new_raw_get_key_ttl_request(
+    key: Key,
+    cf: Option<ColumnFamily>,
+) -> kvrpcpb::RawGetKeyTtlRequest {
+    requests::new_raw_get_key_ttl_request(key.into(), cf)
+}
+
 pub fn new_raw_delete_request(key: Key, cf: Option<ColumnFamily>) -> kvrpcpb::RawDeleteRequest {
     requests::new_raw_delete_request(key.into(), cf)
 }
@@ -9,13 +9,22 @@
 //!
 //! **Warning:** It is not advisable to use both raw and transactional functionality in the same keyspace.
 
-pub use self::client::Client;
+pub use self::{
+    cdc::WatchEvent, client::Client, conditional::RawMutation, scan_stream::ScanStream,
+    stats::RangeStats,
+};
 use crate::Error;
 use std::{convert::TryFrom, fmt};
 
+mod cache;
+mod cdc;
 mod client;
+mod coalesce;
+mod conditional;
 pub mod lowering;
 mod requests;
+mod scan_stream;
+mod stats;
 
 /// A [`ColumnFamily`](ColumnFamily) is an optional parameter for [`raw::Client`](Client) requests.
 ///
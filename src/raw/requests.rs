@@ -7,7 +7,7 @@ use crate::{
     store::{store_stream_for_keys, store_stream_for_ranges, Store},
     transaction::HasLocks,
     util::iter::FlatMapOkIterExt,
-    ColumnFamily, KvPair, Result, Value,
+    ColumnFamily, Key, KvPair, Result, Value,
 };
 use futures::stream::BoxStream;
 use std::sync::Arc;
@@ -126,10 +126,14 @@ impl Shardable for kvrpcpb::RawBatchPutRequest {
     }
 
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
-        self.set_context(store.region.context()?);
+        self.set_context(store.context()?);
         self.set_pairs(shard);
         Ok(())
     }
+
+    fn clear_sharded_data(&mut self) {
+        self.pairs.clear();
+    }
 }
 
 pub fn new_raw_delete_request(key: Vec<u8>, cf: Option<ColumnFamily>) -> kvrpcpb::RawDeleteRequest {
@@ -235,6 +239,96 @@ pub fn new_raw_batch_scan_request(
     req
 }
 
+/// Merge overlapping and adjacent ranges into ranges which don't overlap, ordered by start key.
+/// An empty end key is unbounded.
+pub fn merge_key_ranges(mut ranges: Vec<kvrpcpb::KeyRange>) -> Vec<kvrpcpb::KeyRange> {
+    ranges.sort_by(|a, b| a.start_key.cmp(&b.start_key));
+    let mut merged: Vec<kvrpcpb::KeyRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        if let Some(last) = merged.last_mut() {
+            if last.end_key.is_empty() {
+                break;
+            }
+            if range.start_key <= last.end_key {
+                if range.end_key.is_empty() || range.end_key > last.end_key {
+                    last.end_key = range.end_key;
+                }
+                continue;
+            }
+        }
+        merged.push(range);
+    }
+    merged
+}
+
+/// Where the scan of a region may have stopped short of the end of a range because it returned
+/// `limit` pairs: the range and the last key scanned. `ranges` must not overlap and be ordered by
+/// start key, and `kvs` are the pairs the scan of the region returned for them.
+pub fn truncations(
+    ranges: &[kvrpcpb::KeyRange],
+    mut kvs: &[kvrpcpb::KvPair],
+    limit: u32,
+) -> Vec<(kvrpcpb::KeyRange, Key)> {
+    let mut truncations = Vec::new();
+    for range in ranges {
+        let end = range.get_end_key();
+        let count = kvs.partition_point(|kv| end.is_empty() || kv.get_key() < end);
+        if count > 0 && count as u32 >= limit {
+            truncations.push((range.clone(), kvs[count - 1].get_key().to_vec().into()));
+        }
+        kvs = &kvs[count..];
+    }
+    truncations
+}
+
+/// The first `limit` pairs of each of `ranges`, from the pairs returned by the scans of the
+/// regions.
+///
+/// The scan of a region returns the first pairs of every range in the region, so the first pairs
+/// of a range are among the pairs returned, unless a scan stopped short (see
+/// [`truncations`](truncations)) before the `limit`th pair of the range. The pairs of such a range
+/// are `None`.
+pub fn pairs_by_range(
+    mut pairs: Vec<KvPair>,
+    ranges: &[kvrpcpb::KeyRange],
+    limit: u32,
+    truncations: &[(kvrpcpb::KeyRange, Key)],
+) -> Vec<Option<Vec<KvPair>>> {
+    pairs.sort_by(|a, b| a.key().cmp(b.key()));
+    // Overlapping ranges return the pairs they share once for each range.
+    pairs.dedup_by(|a, b| a.key() == b.key());
+    let before_end = |key: &[u8], end: &[u8]| end.is_empty() || key < end;
+    ranges
+        .iter()
+        .map(|range| {
+            let (start, end) = (range.get_start_key(), range.get_end_key());
+            let first = pairs.partition_point(|pair| <&[u8]>::from(pair.key()) < start);
+            let range_pairs: Vec<KvPair> = pairs[first..]
+                .iter()
+                .take_while(|pair| before_end(pair.key().into(), end))
+                .take(limit as usize)
+                .cloned()
+                .collect();
+            let last = range_pairs
+                .last()
+                .filter(|_| range_pairs.len() == limit as usize)
+                .map(|pair| <&[u8]>::from(pair.key()));
+            let incomplete = truncations.iter().any(|(scanned, at)| {
+                let at: &[u8] = at.into();
+                scanned.get_start_key() <= start
+                    && before_end(start, scanned.get_end_key())
+                    && before_end(at, end)
+                    && !matches!(last, Some(last) if at >= last)
+            });
+            if incomplete {
+                None
+            } else {
+                Some(range_pairs)
+            }
+        })
+        .collect()
+}
+
 impl KvRequest for kvrpcpb::RawBatchScanRequest {
     type Response = kvrpcpb::RawBatchScanResponse;
 }
@@ -250,7 +344,7 @@ impl Shardable for kvrpcpb::RawBatchScanRequest {
     }
 
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
-        self.set_context(store.region.context()?);
+        self.set_context(store.context()?);
         self.set_ranges(shard);
         Ok(())
     }
@@ -267,6 +361,82 @@ impl Merge<kvrpcpb::RawBatchScanResponse> for Collect {
     }
 }
 
+pub fn new_raw_cas_request(
+    key: Vec<u8>,
+    value: Vec<u8>,
+    previous_value: Option<Vec<u8>>,
+    cf: Option<ColumnFamily>,
+) -> kvrpcpb::RawCasRequest {
+    let mut req = kvrpcpb::RawCasRequest::default();
+    req.set_key(key);
+    req.set_value(value);
+    match previous_value {
+        Some(v) => req.set_previous_value(v),
+        None => req.set_previous_not_exist(true),
+    }
+    req.maybe_set_cf(cf);
+
+    req
+}
+
+impl KvRequest for kvrpcpb::RawCasRequest {
+    type Response = kvrpcpb::RawCasResponse;
+}
+
+impl SingleKey for kvrpcpb::RawCasRequest {
+    fn key(&self) -> &Vec<u8> {
+        &self.key
+    }
+}
+
+impl Process<kvrpcpb::RawCasResponse> for DefaultProcessor {
+    type Out = (Option<Value>, bool);
+
+    fn process(&self, input: Result<kvrpcpb::RawCasResponse>) -> Result<Self::Out> {
+        let mut input = input?;
+        let previous = if input.previous_not_exist {
+            None
+        } else {
+            Some(input.take_previous_value())
+        };
+        Ok((previous, input.succeed))
+    }
+}
+
+pub fn new_raw_get_key_ttl_request(
+    key: Vec<u8>,
+    cf: Option<ColumnFamily>,
+) -> kvrpcpb::RawGetKeyTtlRequest {
+    let mut req = kvrpcpb::RawGetKeyTtlRequest::default();
+    req.set_key(key);
+    req.maybe_set_cf(cf);
+
+    req
+}
+
+impl KvRequest for kvrpcpb::RawGetKeyTtlRequest {
+    type Response = kvrpcpb::RawGetKeyTtlResponse;
+}
+
+impl SingleKey for kvrpcpb::RawGetKeyTtlRequest {
+    fn key(&self) -> &Vec<u8> {
+        &self.key
+    }
+}
+
+impl Process<kvrpcpb::RawGetKeyTtlResponse> for DefaultProcessor {
+    type Out = Option<u64>;
+
+    fn process(&self, input: Result<kvrpcpb::RawGetKeyTtlResponse>) -> Result<Self::Out> {
+        let input = input?;
+        Ok(if input.not_found {
+            None
+        } else {
+            Some(input.ttl)
+        })
+    }
+}
+
 macro_rules! impl_raw_rpc_request {
     ($name: ident) => {
         impl RawRpcRequest for kvrpcpb::$name {
@@ -286,6 +456,8 @@ impl_raw_rpc_request!(RawBatchDeleteRequest);
 impl_raw_rpc_request!(RawScanRequest);
 impl_raw_rpc_request!(RawBatchScanRequest);
 impl_raw_rpc_request!(RawDeleteRangeRequest);
+impl_raw_rpc_request!(RawCasRequest);
+impl_raw_rpc_request!(RawGetKeyTtlRequest);
 
 impl HasLocks for kvrpcpb::RawGetResponse {}
 impl HasLocks for kvrpcpb::RawBatchGetResponse {}
@@ -296,6 +468,8 @@ impl HasLocks for kvrpcpb::RawBatchDeleteResponse {}
 impl HasLocks for kvrpcpb::RawScanResponse {}
 impl HasLocks for kvrpcpb::RawBatchScanResponse {}
 impl HasLocks for kvrpcpb::RawDeleteRangeResponse {}
+impl HasLocks for kvrpcpb::RawCasResponse {}
+impl HasLocks for kvrpcpb::RawGetKeyTtlResponse {}
 
 #[cfg(test)]
 mod test {
@@ -350,4 +524,91 @@ mod test {
         assert_eq!(scan.len(), 10);
         // FIXME test the keys returned.
     }
+
+    #[test]
+    fn test_merge_key_ranges() {
+        let range = |start: &[u8], end: &[u8]| {
+            let mut range = kvrpcpb::KeyRange::default();
+            range.set_start_key(start.to_vec());
+            range.set_end_key(end.to_vec());
+            range
+        };
+        assert_eq!(
+            merge_key_ranges(vec![
+                range(b"k", b"m"),
+                range(b"a", b"c"),
+                range(b"b", b"d"),
+                range(b"d", b"e"),
+                range(b"l", b"n"),
+                range(b"x", b"y"),
+            ]),
+            vec![range(b"a", b"e"), range(b"k", b"n"), range(b"x", b"y")]
+        );
+        // Unbounded ranges absorb every range after their start.
+        assert_eq!(
+            merge_key_ranges(vec![range(b"c", b""), range(b"a", b"b"), range(b"d", b"z")]),
+            vec![range(b"a", b"b"), range(b"c", b"")]
+        );
+        assert_eq!(
+            merge_key_ranges(vec![range(b"a", b"c"), range(b"", b"")]),
+            vec![range(b"", b"")]
+        );
+    }
+
+    #[test]
+    fn test_pairs_by_range() {
+        let range = |start: &[u8], end: &[u8]| {
+            let mut range = kvrpcpb::KeyRange::default();
+            range.set_start_key(start.to_vec());
+            range.set_end_key(end.to_vec());
+            range
+        };
+        let pair = |key: &[u8]| KvPair::new(key.to_vec(), key.to_vec());
+        let kv = |key: &[u8]| kvrpcpb::KvPair::from(pair(key));
+
+        // Overlapping ranges, scanned separately: the shared pair is returned twice.
+        let pairs = vec![pair(b"d"), pair(b"a"), pair(b"y"), pair(b"b"), pair(b"b")];
+        assert_eq!(
+            pairs_by_range(
+                pairs,
+                &[range(b"b", b"e"), range(b"a", b"c"), range(b"x", b"")],
+                10,
+                &[]
+            ),
+            vec![
+                Some(vec![pair(b"b"), pair(b"d")]),
+                Some(vec![pair(b"a"), pair(b"b")]),
+                Some(vec![pair(b"y")]),
+            ]
+        );
+        // The limit applies to each range across regions.
+        let pairs = vec![pair(b"a"), pair(b"b"), pair(b"m"), pair(b"n")];
+        assert_eq!(
+            pairs_by_range(pairs, &[range(b"a", b"z")], 3, &[]),
+            vec![Some(vec![pair(b"a"), pair(b"b"), pair(b"m")])]
+        );
+
+        // Ranges merged into [a, z), scanned in regions [a, k) and [k, z) with a limit of 2.
+        let merged = [range(b"a", b"z")];
+        let mut truncated = truncations(&merged, &[kv(b"a"), kv(b"b")], 2);
+        assert_eq!(truncated, vec![(range(b"a", b"z"), b"b".to_vec().into())]);
+        truncated.extend(truncations(&merged, &[kv(b"m")], 2));
+        assert_eq!(truncated.len(), 1);
+        let pairs = vec![pair(b"a"), pair(b"b"), pair(b"m")];
+        assert_eq!(
+            pairs_by_range(
+                pairs,
+                &[range(b"a", b"z"), range(b"c", b"z"), range(b"m", b"n")],
+                2,
+                &truncated
+            ),
+            vec![
+                Some(vec![pair(b"a"), pair(b"b")]),
+                // Pairs of [c, k) may have been left out.
+                None,
+                // The region boundaries are unknown, so [m, n) may be in the first region.
+                None,
+            ]
+        );
+    }
 }
@@ -0,0 +1,112 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::{raw::Client, Key, KvPair, Result};
+use futures::{
+    future::BoxFuture,
+    prelude::*,
+    ready,
+    stream::FusedStream,
+    task::{Context, Poll},
+};
+use std::{collections::VecDeque, pin::Pin};
+
+type Page = BoxFuture<'static, Result<(Vec<KvPair>, Option<Key>)>>;
+
+/// A stream of the pairs of a range, returned by [`RawClient::scan_stream`](Client::scan_stream).
+///
+/// Pairs are requested one page at a time, and the stream ends after the first error.
+#[must_use = "streams do nothing unless polled"]
+pub struct ScanStream {
+    client: Client,
+    // The encoded end of the range.
+    end: Option<Key>,
+    // The encoded key to request the next page from, `None` once the range is exhausted.
+    next: Option<Key>,
+    // The number of pairs which may still be requested.
+    remaining: u32,
+    buffer: VecDeque<KvPair>,
+    page: Option<Page>,
+}
+
+impl ScanStream {
+    pub(super) fn new(client: Client, start: Key, end: Option<Key>, limit: u32) -> ScanStream {
+        ScanStream {
+            client,
+            end,
+            next: Some(start),
+            remaining: limit,
+            buffer: VecDeque::new(),
+            page: None,
+        }
+    }
+
+    fn request_page(&mut self) -> Option<Page> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let start = self.next.take()?;
+        let client = self.client.clone();
+        let end = self.end.clone();
+        let limit = self.remaining;
+        Some(
+            async move {
+                let (kvs, next) = client.scan_page(start, end, limit, false).await?;
+                let pairs =
+                    client.decode_pairs(kvs.into_iter().map(Into::into).collect(), false)?;
+                Ok((pairs, next))
+            }
+            .boxed(),
+        )
+    }
+}
+
+impl Stream for ScanStream {
+    type Item = Result<KvPair>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(pair) = this.buffer.pop_front() {
+                return Poll::Ready(Some(Ok(pair)));
+            }
+            if this.page.is_none() {
+                this.page = this.request_page();
+            }
+            let page = match this.page.as_mut() {
+                Some(page) => page,
+                None => return Poll::Ready(None),
+            };
+            // The page is only taken once it is ready, so a poll dropped before then loses nothing.
+            let result = ready!(page.poll_unpin(cx));
+            this.page = None;
+            match result {
+                Ok((pairs, next)) => {
+                    this.remaining -= pairs.len() as u32;
+                    this.buffer.extend(pairs);
+                    this.next = next;
+                }
+                Err(e) => {
+                    this.next = None;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.buffer.len();
+        if self.page.is_none() && self.next.is_none() {
+            (buffered, Some(buffered))
+        } else {
+            (buffered, Some(buffered + self.remaining as usize))
+        }
+    }
+}
+
+impl FusedStream for ScanStream {
+    fn is_terminated(&self) -> bool {
+        self.buffer.is_empty()
+            && self.page.is_none()
+            && (self.next.is_none() || self.remaining == 0)
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::{Key, KvPair};
+use rand::Rng;
+
+/// The approximate size and number of pairs of a range, from
+/// [`range_stats`](super::Client::range_stats).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangeStats {
+    regions: usize,
+    approximate_size: u64,
+    approximate_keys: u64,
+}
+
+impl RangeStats {
+    /// The number of regions overlapping the range.
+    pub fn regions(&self) -> usize {
+        self.regions
+    }
+
+    /// The approximate size of the pairs of the range, in bytes.
+    pub fn approximate_size(&self) -> u64 {
+        self.approximate_size
+    }
+
+    /// The approximate number of pairs in the range.
+    pub fn approximate_keys(&self) -> u64 {
+        self.approximate_keys
+    }
+
+    pub(super) fn sum(regions: &[RegionStats]) -> RangeStats {
+        RangeStats {
+            regions: regions.len(),
+            approximate_size: regions.iter().map(|region| region.size).sum(),
+            approximate_keys: regions.iter().map(|region| region.keys).sum(),
+        }
+    }
+}
+
+/// The part of a region in a range, with the approximate statistics of the region.
+pub(super) struct RegionStats {
+    pub start: Key,
+    pub end: Option<Key>,
+    pub size: u64,
+    pub keys: u64,
+}
+
+/// Estimate the number of pairs of a region of `size` bytes from the first pairs of it, scanned
+/// with `limit`. A region with fewer pairs than the limit has exactly as many as were scanned.
+pub(super) fn estimate_keys(size: u64, sample: &[KvPair], limit: u32) -> u64 {
+    let scanned = sample.len() as u64;
+    if scanned < limit as u64 {
+        return scanned;
+    }
+    let bytes: u64 = sample
+        .iter()
+        .map(|pair| (<&[u8]>::from(pair.key()).len() + pair.value().len()) as u64)
+        .sum();
+    std::cmp::max(size * scanned / bytes.max(1), scanned)
+}
+
+/// Split `n` samples between regions in proportion to their sizes.
+pub(super) fn allocate(sizes: &[u64], n: usize) -> Vec<usize> {
+    if sizes.is_empty() {
+        return Vec::new();
+    }
+    let total: u64 = sizes.iter().sum();
+    if total == 0 {
+        return (0..sizes.len())
+            .map(|i| n / sizes.len() + usize::from(i < n % sizes.len()))
+            .collect();
+    }
+    let shares: Vec<u128> = sizes.iter().map(|&size| size as u128 * n as u128).collect();
+    let mut counts: Vec<usize> = shares
+        .iter()
+        .map(|share| (share / total as u128) as usize)
+        .collect();
+    // Hand the samples lost to rounding down to the largest remainders.
+    let mut by_remainder: Vec<usize> = (0..sizes.len()).collect();
+    by_remainder.sort_by_key(|&i| std::cmp::Reverse(shares[i] % total as u128));
+    let missing = n - counts.iter().sum::<usize>();
+    for &i in by_remainder.iter().take(missing) {
+        counts[i] += 1;
+    }
+    counts
+}
+
+/// A random key after `start` and before `end`, or `start` if there is none which differs from
+/// both in its first 8 bytes after their common prefix.
+pub(super) fn random_key(start: &Key, end: Option<&Key>, rng: &mut impl Rng) -> Key {
+    let start: &[u8] = start.into();
+    let end: Option<&[u8]> = end.map(Into::into).filter(|end: &&[u8]| !end.is_empty());
+    let prefix_len = match end {
+        Some(end) => start.iter().zip(end).take_while(|(a, b)| a == b).count(),
+        None => 0,
+    };
+    // The 8 bytes after the common prefix, padded with zeros.
+    let window = |key: &[u8]| {
+        let mut bytes = [0; 8];
+        for (byte, key_byte) in bytes.iter_mut().zip(&key[prefix_len..]) {
+            *byte = *key_byte;
+        }
+        u64::from_be_bytes(bytes)
+    };
+    let low = window(start);
+    let high = end.map_or(u64::MAX, window);
+    if high.saturating_sub(low) <= 1 {
+        return start.to_vec().into();
+    }
+    let mut key = start[..prefix_len].to_vec();
+    key.extend_from_slice(&rng.gen_range(low + 1..high).to_be_bytes());
+    key.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_estimate_keys() {
+        let sample = vec![KvPair::new(b"k1".to_vec(), b"v1".to_vec()); 4];
+        assert_eq!(estimate_keys(1000, &sample[..3], 4), 3);
+        assert_eq!(estimate_keys(1000, &sample, 4), 250);
+        // A region can't have fewer pairs than were scanned.
+        assert_eq!(estimate_keys(0, &sample, 4), 4);
+    }
+
+    #[test]
+    fn test_allocate() {
+        assert_eq!(allocate(&[], 3), Vec::<usize>::new());
+        assert_eq!(allocate(&[0, 0], 3), vec![2, 1]);
+        assert_eq!(allocate(&[10, 30], 4), vec![1, 3]);
+        assert_eq!(allocate(&[10, 20, 10], 3), vec![1, 1, 1]);
+        assert_eq!(allocate(&[1, 1, 1], 2).iter().sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn test_random_key() {
+        let mut rng = rand::thread_rng();
+        let cases: Vec<(Key, Option<Key>)> = vec![
+            (b"a".to_vec().into(), Some(b"b".to_vec().into())),
+            (
+                b"key1".to_vec().into(),
+                Some(b"key1\x00\x01".to_vec().into()),
+            ),
+            (b"".to_vec().into(), None),
+            (b"\xff\xff".to_vec().into(), Some(b"".to_vec().into())),
+        ];
+        for (start, end) in cases {
+            for _ in 0..100 {
+                let key = random_key(&start, end.as_ref(), &mut rng);
+                assert!(key >= start);
+                if let Some(end) = &end {
+                    assert!(end.is_empty() || &key < end);
+                }
+            }
+        }
+        // No key between the two.
+        let start: Key = b"a".to_vec().into();
+        let end: Key = b"a\x00".to_vec().into();
+        assert_eq!(random_key(&start, Some(&end), &mut rng), start);
+    }
+}
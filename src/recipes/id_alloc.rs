@@ -0,0 +1,120 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Cluster-unique, monotonically increasing IDs.
+//!
+//! The last allocated ID is stored under a counter key. Ranges of IDs are allocated by reading
+//! and advancing the counter in a pessimistic transaction, so concurrent allocators never receive
+//! overlapping ranges and every range is above all ranges allocated before it. To save round
+//! trips, an [`IdAllocator`](IdAllocator) allocates IDs in batches and hands them out locally.
+//!
+//! The counter is advanced in a pessimistic transaction rather than with a raw compare-and-swap:
+//! the counter is a transactional key, like the data the IDs are used for, and TiKV doesn't allow
+//! raw and transactional requests on the same keys. A pessimistic lock also makes contending
+//! allocators wait for each other, where compare-and-swap would have them retry in a loop.
+//!
+//! The largest ID is `u64::MAX - 1`; allocating past it fails.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{TransactionClient, recipes::id_alloc::IdAllocator};
+//! # futures::executor::block_on(async {
+//! let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let allocator = IdAllocator::new(client, "ids/order".to_owned(), 100);
+//! let id = allocator.next().await.unwrap();
+//! # });
+//! ```
+
+use crate::{codec::keys, Key, Result, TransactionClient};
+use std::ops::Range;
+use tikv_client_common::internal_err;
+use tokio::sync::Mutex;
+
+/// Allocates IDs from a counter key, `batch_size` IDs per round trip to TiKV.
+///
+/// IDs start at 1. IDs returned by one allocator are strictly increasing. Allocators sharing a
+/// key never return the same ID, but as each hands out its own batch, their IDs interleave.
+/// IDs of a batch which are not handed out before the allocator is dropped are never used.
+pub struct IdAllocator {
+    client: TransactionClient,
+    key: Key,
+    batch_size: u64,
+    batch: Mutex<Range<u64>>,
+}
+
+impl IdAllocator {
+    pub fn new(client: TransactionClient, key: impl Into<Key>, batch_size: u64) -> IdAllocator {
+        assert!(batch_size > 0, "batch size must be positive");
+        IdAllocator {
+            client,
+            key: key.into(),
+            batch_size,
+            batch: Mutex::new(0..0),
+        }
+    }
+
+    /// The next ID, allocating a new batch if the current one is used up.
+    pub async fn next(&self) -> Result<u64> {
+        let mut batch = self.batch.lock().await;
+        if batch.is_empty() {
+            *batch = self.alloc(self.batch_size).await?;
+        }
+        let id = batch.start;
+        batch.start += 1;
+        Ok(id)
+    }
+
+    /// Allocate `count` consecutive IDs directly from TiKV, bypassing the local batch.
+    pub async fn alloc(&self, count: u64) -> Result<Range<u64>> {
+        let txn = self.client.begin_pessimistic().await?;
+        let result = async {
+            let last = match txn.get_for_update(self.key.clone()).await? {
+                Some(value) => keys::decode_u64(&value)?,
+                None => 0,
+            };
+            let range = next_range(last, count)
+                .ok_or_else(|| internal_err!("IDs of {:?} are exhausted", self.key))?;
+            txn.put(self.key.clone(), keys::encode_u64(range.end - 1).to_vec())
+                .await?;
+            Ok(range)
+        }
+        .await;
+        match result {
+            Ok(range) => {
+                txn.commit().await?;
+                Ok(range)
+            }
+            Err(e) => {
+                txn.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// The `count` IDs following `last`, or `None` if they don't fit below `u64::MAX`.
+fn next_range(last: u64, count: u64) -> Option<Range<u64>> {
+    let start = last.checked_add(1)?;
+    Some(start..start.checked_add(count)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next_range() {
+        assert_eq!(next_range(0, 1), Some(1..2));
+        assert_eq!(next_range(0, 100), Some(1..101));
+        assert_eq!(next_range(100, 100), Some(101..201));
+        assert_eq!(next_range(7, 0), Some(8..8));
+    }
+
+    #[test]
+    fn test_next_range_overflow() {
+        assert_eq!(next_range(u64::MAX - 3, 2), Some(u64::MAX - 2..u64::MAX));
+        assert_eq!(next_range(u64::MAX - 2, 2), None);
+        assert_eq!(next_range(u64::MAX - 1, 1), None);
+        assert_eq!(next_range(u64::MAX, 0), None);
+        assert_eq!(next_range(0, u64::MAX), None);
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Exactly-once handling of retried requests.
+//!
+//! A caller retrying a request after a timeout can't tell whether the first attempt was applied.
+//! If every request carries an idempotency key chosen by the caller, e.g. a UUID generated once
+//! per logical request, handlers can record the key, along with their response, in the
+//! transaction making the changes of the request. A replay then finds the key and gets the
+//! recorded response instead of applying the changes again, and as the key is written in the
+//! same transaction as the changes, it is recorded if and only if they are.
+//!
+//! Keys are recorded under a prefix, with inserts, so of two attempts racing in optimistic
+//! transactions only one can commit. In pessimistic transactions, checking for a key locks it,
+//! so a concurrent attempt waits for the first one to finish and then sees its key.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{TransactionClient, recipes::idempotency::{Idempotency, Outcome}};
+//! # use futures::prelude::*;
+//! # futures::executor::block_on(async {
+//! let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let idempotency = Idempotency::new(client, "requests/".to_owned());
+//! let outcome = idempotency
+//!     .handle("3f2c9a1e".to_owned(), |txn| {
+//!         async move {
+//!             txn.put("balance/alice".to_owned(), "90").await?;
+//!             Ok(b"ok".to_vec())
+//!         }
+//!         .boxed()
+//!     })
+//!     .await
+//!     .unwrap();
+//! if let Outcome::Replayed(response) = outcome {
+//!     // ... The request was handled before, `response` is what it returned.
+//! }
+//! # });
+//! ```
+
+use crate::{kv::Prefix, Key, Result, Transaction, TransactionClient, TransactionOptions, Value};
+use futures::future::BoxFuture;
+use tikv_client_common::internal_err;
+
+// The version of the format of records, before the response. It also keeps records with empty
+// responses from being empty, which some versions of TiKV don't tell apart from missing keys.
+const RECORD_VERSION: u8 = 1;
+
+fn encode_record(response: Value) -> Value {
+    let mut record = Vec::with_capacity(1 + response.len());
+    record.push(RECORD_VERSION);
+    record.extend(response);
+    record
+}
+
+fn decode_record(mut record: Value) -> Result<Value> {
+    if record.first() != Some(&RECORD_VERSION) {
+        return Err(internal_err!("invalid idempotency record {:?}", record));
+    }
+    record.remove(0);
+    Ok(record)
+}
+
+/// Roll back a transaction which failed, keeping its error rather than the rollback's.
+async fn rollback(txn: &Transaction) {
+    if let Err(e) = txn.rollback().await {
+        warn!(
+            target: "tikv_client::recipes",
+            error = %e,
+            "failed to roll back a failed transaction"
+        );
+    }
+}
+
+/// What [`handle`](Idempotency::handle) did with a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The request was handled, and its response recorded.
+    Applied(Value),
+    /// The request was handled before, and is answered with the response recorded then.
+    Replayed(Value),
+}
+
+impl Outcome {
+    /// The response to the request, whether it was handled now or before.
+    pub fn into_response(self) -> Value {
+        match self {
+            Outcome::Applied(response) | Outcome::Replayed(response) => response,
+        }
+    }
+
+    pub fn is_replayed(&self) -> bool {
+        matches!(self, Outcome::Replayed(_))
+    }
+}
+
+/// The idempotency keys of the requests handled, shared by all clients using the same prefix.
+///
+/// Keys are kept until they are [`forget`](Idempotency::forget)ten, e.g. once callers stop
+/// retrying the requests. Keys starting with the prefix must not be used for anything else.
+pub struct Idempotency {
+    client: TransactionClient,
+    prefix: Prefix,
+}
+
+impl Idempotency {
+    pub fn new(client: TransactionClient, prefix: impl Into<Key>) -> Idempotency {
+        Idempotency {
+            client,
+            prefix: Prefix::new(prefix.into()),
+        }
+    }
+
+    /// Handle the request identified by `request_id` once.
+    ///
+    /// `handler` makes the changes of the request in a pessimistic transaction, and returns its
+    /// response, which is recorded and committed with the changes. If the request was handled
+    /// before, the handler isn't run and the recorded response is returned instead. If the
+    /// handler fails, the transaction is rolled back and nothing is recorded, so the request can
+    /// be retried.
+    pub async fn handle<F>(&self, request_id: impl Into<Key>, handler: F) -> Result<Outcome>
+    where
+        F: for<'a> FnOnce(&'a Transaction) -> BoxFuture<'a, Result<Value>>,
+    {
+        let request_id = request_id.into();
+        let txn = self.client.begin_pessimistic().await?;
+        let result = async {
+            if let Some(response) = self.check_in(&txn, request_id.clone()).await? {
+                return Ok(Outcome::Replayed(response));
+            }
+            let response = handler(&txn).await?;
+            self.record_in(&txn, request_id.clone(), response.clone())
+                .await?;
+            Ok(Outcome::Applied(response))
+        }
+        .await;
+        match result {
+            Ok(Outcome::Applied(response)) => {
+                txn.commit().await?;
+                Ok(Outcome::Applied(response))
+            }
+            Ok(replayed) => {
+                txn.rollback().await?;
+                debug!(
+                    target: "tikv_client::recipes",
+                    request_id = %request_id,
+                    "replayed request"
+                );
+                Ok(replayed)
+            }
+            Err(e) => {
+                rollback(&txn).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// The response recorded for `request_id`, as read by `txn`, if the request was handled.
+    ///
+    /// In a pessimistic transaction, the key is locked, so other transactions handling the same
+    /// request wait until `txn` ends.
+    pub async fn check_in(
+        &self,
+        txn: &Transaction,
+        request_id: impl Into<Key>,
+    ) -> Result<Option<Value>> {
+        let key = self.prefix.encode_key(request_id.into());
+        let record = if txn.is_pessimistic() {
+            txn.get_for_update(key).await?
+        } else {
+            txn.get(key).await?
+        };
+        record.map(decode_record).transpose()
+    }
+
+    /// Record in `txn` that it handles `request_id`, with its `response`.
+    ///
+    /// The key is inserted, so recording a request which was already recorded fails, at the
+    /// latest when `txn` commits. This includes requests recorded by transactions which committed
+    /// after `txn` checked for them.
+    pub async fn record_in(
+        &self,
+        txn: &Transaction,
+        request_id: impl Into<Key>,
+        response: impl Into<Value>,
+    ) -> Result<()> {
+        let key = self.prefix.encode_key(request_id.into());
+        txn.insert(key, encode_record(response.into())).await
+    }
+
+    /// The response recorded for `request_id` by the latest transaction committed, if the
+    /// request was handled.
+    pub async fn response(&self, request_id: impl Into<Key>) -> Result<Option<Value>> {
+        let timestamp = self.client.current_timestamp().await?;
+        let snapshot = self
+            .client
+            .snapshot(timestamp, TransactionOptions::new_optimistic());
+        let record = snapshot
+            .get(self.prefix.encode_key(request_id.into()))
+            .await?;
+        record.map(decode_record).transpose()
+    }
+
+    /// Delete the record of `request_id`, so that the request would be handled again.
+    pub async fn forget(&self, request_id: impl Into<Key>) -> Result<()> {
+        let txn = self.client.begin_optimistic().await?;
+        if let Err(e) = txn.delete(self.prefix.encode_key(request_id.into())).await {
+            rollback(&txn).await;
+            return Err(e);
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_codec() {
+        assert_eq!(
+            decode_record(encode_record(b"response".to_vec())).unwrap(),
+            b"response".to_vec()
+        );
+        let empty = encode_record(Vec::new());
+        assert!(!empty.is_empty());
+        assert_eq!(decode_record(empty).unwrap(), Vec::<u8>::new());
+        assert!(decode_record(Vec::new()).is_err());
+        assert!(decode_record(b"\x00response".to_vec()).is_err());
+    }
+}
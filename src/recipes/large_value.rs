@@ -0,0 +1,337 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Values larger than TiKV accepts, split into chunks stored under sub-keys.
+//!
+//! A value up to the chunk size is stored under its key, after a tag byte. A larger value is
+//! split into chunks of the chunk size, which are stored under sub-keys of its key, and its key
+//! stores a manifest recording the generation of the chunks, their number and the length of the
+//! value. Reads reassemble the chunks listed by the manifest.
+//!
+//! A write stores its chunks under a new generation before replacing the manifest, then deletes
+//! the chunks of the previous generation. In transactions, all of this is atomic. With a
+//! [`RawClient`](crate::RawClient) it is not: a read racing a write may find the chunks of the
+//! manifest it read deleted and fail, in which case it can be retried, and a write which fails
+//! part way may leave chunks which are never deleted.
+//!
+//! Keys written through [`LargeValues`] must only be read and written through it, as their
+//! values are tagged, and their sub-keys must not be used otherwise. Scans return the tagged
+//! values and the chunks.
+//!
+//! This is a helper to call explicitly, rather than an option of the clients' `put` and `get`,
+//! because it changes how values are stored: an option would make every key unreadable to
+//! clients without it, and would turn a single atomic raw write into several requests which are
+//! not. Batch requests, compare-and-swap and TTLs also can't keep their meaning for values split
+//! over several keys. So only the keys which need it are chunked, by code which knows they are.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{RawClient, recipes::large_value::LargeValues};
+//! # futures::executor::block_on(async {
+//! let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let values = LargeValues::new(1024 * 1024);
+//! values.raw_put(&client, "blob".to_owned(), vec![0; 10 * 1024 * 1024]).await.unwrap();
+//! let blob = values.raw_get(&client, "blob".to_owned()).await.unwrap();
+//! # });
+//! ```
+
+use crate::{Key, KvPair, RawClient, Result, TimestampExt, Transaction, Value};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tikv_client_common::internal_err;
+
+const INLINE_TAG: u8 = 0;
+const MANIFEST_TAG: u8 = 1;
+/// Separates a key from the generation and index of its chunks in their sub-keys.
+const CHUNK_INFIX: &[u8] = b"\xffchunk";
+
+/// The value stored under a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Record {
+    Inline(Value),
+    Chunked(Manifest),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Manifest {
+    generation: u64,
+    chunks: u32,
+    len: u64,
+}
+
+impl Record {
+    fn encode(&self) -> Value {
+        match self {
+            Record::Inline(value) => {
+                let mut record = Vec::with_capacity(value.len() + 1);
+                record.push(INLINE_TAG);
+                record.extend_from_slice(value);
+                record
+            }
+            Record::Chunked(manifest) => {
+                let mut record = Vec::with_capacity(21);
+                record.push(MANIFEST_TAG);
+                record.extend_from_slice(&manifest.generation.to_be_bytes());
+                record.extend_from_slice(&manifest.chunks.to_be_bytes());
+                record.extend_from_slice(&manifest.len.to_be_bytes());
+                record
+            }
+        }
+    }
+
+    fn decode(mut record: Value) -> Result<Record> {
+        match record.first() {
+            Some(&INLINE_TAG) => {
+                record.remove(0);
+                Ok(Record::Inline(record))
+            }
+            Some(&MANIFEST_TAG) if record.len() == 21 => Ok(Record::Chunked(Manifest {
+                generation: u64::from_be_bytes(record[1..9].try_into().unwrap()),
+                chunks: u32::from_be_bytes(record[9..13].try_into().unwrap()),
+                len: u64::from_be_bytes(record[13..].try_into().unwrap()),
+            })),
+            _ => Err(internal_err!("invalid large value record {:?}", record)),
+        }
+    }
+
+    fn manifest(&self) -> Option<&Manifest> {
+        match self {
+            Record::Inline(_) => None,
+            Record::Chunked(manifest) => Some(manifest),
+        }
+    }
+}
+
+/// Reads and writes values of any size, splitting those larger than `chunk_size` bytes into
+/// chunks.
+#[derive(Clone, Debug)]
+pub struct LargeValues {
+    chunk_size: usize,
+}
+
+impl LargeValues {
+    pub fn new(chunk_size: usize) -> LargeValues {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        LargeValues { chunk_size }
+    }
+
+    /// Write `value` under `key` with `client`.
+    pub async fn raw_put(
+        &self,
+        client: &RawClient,
+        key: impl Into<Key>,
+        value: impl Into<Value>,
+    ) -> Result<()> {
+        let key = key.into();
+        let old = self.raw_record(client, key.clone()).await?;
+        let generation = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        let (record, chunks) = self.split(&key, value.into(), generation);
+        if !chunks.is_empty() {
+            client.batch_put(chunks).await?;
+        }
+        client.put(key.clone(), record.encode()).await?;
+        let stale = stale_chunks(&key, old.as_ref(), &record);
+        if !stale.is_empty() {
+            client.batch_delete(stale).await?;
+        }
+        Ok(())
+    }
+
+    /// Read the value of `key` with `client`.
+    pub async fn raw_get(&self, client: &RawClient, key: impl Into<Key>) -> Result<Option<Value>> {
+        let key = key.into();
+        match self.raw_record(client, key.clone()).await? {
+            None => Ok(None),
+            Some(Record::Inline(value)) => Ok(Some(value)),
+            Some(Record::Chunked(manifest)) => {
+                let chunks = client.batch_get(chunk_keys(&key, &manifest)).await?;
+                assemble(&key, &manifest, chunks).map(Some)
+            }
+        }
+    }
+
+    /// Delete `key` and its chunks with `client`.
+    pub async fn raw_delete(&self, client: &RawClient, key: impl Into<Key>) -> Result<()> {
+        let key = key.into();
+        let old = self.raw_record(client, key.clone()).await?;
+        client.delete(key.clone()).await?;
+        if let Some(manifest) = old.as_ref().and_then(Record::manifest) {
+            client.batch_delete(chunk_keys(&key, manifest)).await?;
+        }
+        Ok(())
+    }
+
+    /// Write `value` under `key` in `txn`.
+    pub async fn put(
+        &self,
+        txn: &Transaction,
+        key: impl Into<Key>,
+        value: impl Into<Value>,
+    ) -> Result<()> {
+        let key = key.into();
+        let old = self.record(txn, key.clone()).await?;
+        let (record, chunks) = self.split(&key, value.into(), txn.start_timestamp().version());
+        for chunk in chunks {
+            txn.put(chunk.0, chunk.1).await?;
+        }
+        txn.put(key.clone(), record.encode()).await?;
+        for chunk_key in stale_chunks(&key, old.as_ref(), &record) {
+            txn.delete(chunk_key).await?;
+        }
+        Ok(())
+    }
+
+    /// Read the value of `key` in `txn`.
+    pub async fn get(&self, txn: &Transaction, key: impl Into<Key>) -> Result<Option<Value>> {
+        let key = key.into();
+        match self.record(txn, key.clone()).await? {
+            None => Ok(None),
+            Some(Record::Inline(value)) => Ok(Some(value)),
+            Some(Record::Chunked(manifest)) => {
+                let chunks = txn.batch_get(chunk_keys(&key, &manifest)).await?;
+                assemble(&key, &manifest, chunks.collect()).map(Some)
+            }
+        }
+    }
+
+    /// Delete `key` and its chunks in `txn`.
+    pub async fn delete(&self, txn: &Transaction, key: impl Into<Key>) -> Result<()> {
+        let key = key.into();
+        let old = self.record(txn, key.clone()).await?;
+        txn.delete(key.clone()).await?;
+        if let Some(manifest) = old.as_ref().and_then(Record::manifest) {
+            for chunk_key in chunk_keys(&key, manifest) {
+                txn.delete(chunk_key).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn raw_record(&self, client: &RawClient, key: Key) -> Result<Option<Record>> {
+        client.get(key).await?.map(Record::decode).transpose()
+    }
+
+    async fn record(&self, txn: &Transaction, key: Key) -> Result<Option<Record>> {
+        txn.get(key).await?.map(Record::decode).transpose()
+    }
+
+    /// The record of `value`, and its chunks if it is larger than the chunk size.
+    fn split(&self, key: &Key, value: Value, generation: u64) -> (Record, Vec<KvPair>) {
+        if value.len() <= self.chunk_size {
+            return (Record::Inline(value), Vec::new());
+        }
+        let manifest = Manifest {
+            generation,
+            chunks: value.len().div_ceil(self.chunk_size) as u32,
+            len: value.len() as u64,
+        };
+        let chunks = chunk_keys(key, &manifest)
+            .zip(value.chunks(self.chunk_size))
+            .map(|(chunk_key, chunk)| KvPair::new(chunk_key, chunk.to_vec()))
+            .collect();
+        (Record::Chunked(manifest), chunks)
+    }
+}
+
+fn chunk_key(key: &Key, generation: u64, index: u32) -> Key {
+    let key: &[u8] = key.into();
+    let mut chunk_key = Vec::with_capacity(key.len() + CHUNK_INFIX.len() + 12);
+    chunk_key.extend_from_slice(key);
+    chunk_key.extend_from_slice(CHUNK_INFIX);
+    chunk_key.extend_from_slice(&generation.to_be_bytes());
+    chunk_key.extend_from_slice(&index.to_be_bytes());
+    chunk_key.into()
+}
+
+fn chunk_keys<'a>(key: &'a Key, manifest: &Manifest) -> impl Iterator<Item = Key> + 'a {
+    let generation = manifest.generation;
+    (0..manifest.chunks).map(move |index| chunk_key(key, generation, index))
+}
+
+/// The chunks of `old` which are not overwritten by `new`.
+fn stale_chunks(key: &Key, old: Option<&Record>, new: &Record) -> Vec<Key> {
+    let old = match old.and_then(Record::manifest) {
+        Some(old) => old,
+        None => return Vec::new(),
+    };
+    let kept = match new.manifest() {
+        Some(new) if new.generation == old.generation => new.chunks,
+        _ => 0,
+    };
+    chunk_keys(key, old).skip(kept as usize).collect()
+}
+
+/// Concatenates the chunks of `manifest`, which may be in any order.
+fn assemble(key: &Key, manifest: &Manifest, chunks: Vec<KvPair>) -> Result<Value> {
+    let mut chunks: HashMap<Key, Value> = chunks.into_iter().map(|pair| (pair.0, pair.1)).collect();
+    let mut value = Vec::with_capacity(manifest.len as usize);
+    for chunk_key in chunk_keys(key, manifest) {
+        let chunk = chunks
+            .remove(&chunk_key)
+            .ok_or_else(|| internal_err!("chunk {:?} of {:?} is missing", chunk_key, key))?;
+        value.extend_from_slice(&chunk);
+    }
+    if value.len() as u64 != manifest.len {
+        return Err(internal_err!(
+            "chunks of {:?} have {} bytes rather than {}",
+            key,
+            value.len(),
+            manifest.len
+        ));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_codec() {
+        let inline = Record::Inline(b"value".to_vec());
+        assert_eq!(Record::decode(inline.encode()).unwrap(), inline);
+        let chunked = Record::Chunked(Manifest {
+            generation: 7,
+            chunks: 3,
+            len: 10,
+        });
+        assert_eq!(Record::decode(chunked.encode()).unwrap(), chunked);
+        assert!(Record::decode(Vec::new()).is_err());
+        assert!(Record::decode(vec![MANIFEST_TAG, 1]).is_err());
+    }
+
+    #[test]
+    fn test_split_and_assemble() {
+        let values = LargeValues::new(4);
+        let key = Key::from(b"key".to_vec());
+        let (record, chunks) = values.split(&key, b"smal".to_vec(), 1);
+        assert_eq!(record, Record::Inline(b"smal".to_vec()));
+        assert!(chunks.is_empty());
+
+        let (record, mut chunks) = values.split(&key, b"large value".to_vec(), 1);
+        let manifest = record.manifest().unwrap().clone();
+        assert_eq!(manifest.chunks, 3);
+        chunks.reverse();
+        assert_eq!(
+            assemble(&key, &manifest, chunks.clone()).unwrap(),
+            b"large value".to_vec()
+        );
+        chunks.pop();
+        assert!(assemble(&key, &manifest, chunks).is_err());
+
+        // Rewriting in the same generation keeps the chunks it overwrites.
+        let (smaller, _) = values.split(&key, b"large".to_vec(), 1);
+        assert_eq!(
+            stale_chunks(&key, Some(&record), &smaller),
+            vec![chunk_key(&key, 1, 2)]
+        );
+        let (newer, _) = values.split(&key, b"large".to_vec(), 2);
+        assert_eq!(stale_chunks(&key, Some(&record), &newer).len(), 3);
+        assert!(stale_chunks(&key, None, &newer).is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A lease-based distributed lock.
+//!
+//! A lock is a single key whose value records the current lease: a fence token and the time the
+//! lease expires. Leases are acquired, extended and released in pessimistic transactions, so
+//! concurrent owners are serialized by TiKV. Expiry times are taken from the timestamp oracle,
+//! so they don't depend on the clocks of the clients.
+//!
+//! Every acquisition increments the fence token. An owner can lose its lease without noticing
+//! (e.g. after a long pause), so resources protected by the lock should be passed the token and
+//! reject requests carrying a smaller token than the largest one they have seen.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{TransactionClient, recipes::lock::DistributedLock};
+//! # use std::time::Duration;
+//! # futures::executor::block_on(async {
+//! let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let lock = DistributedLock::new(client, "locks/leader".to_owned(), Duration::from_secs(10));
+//! let guard = lock.acquire().await.unwrap();
+//! println!("acquired with fence token {}", guard.fence_token());
+//! // ... do work.
+//! guard.release().await.unwrap();
+//! # });
+//! ```
+
+use crate::{CancellationToken, Error, Key, Result, Transaction, TransactionClient, Value};
+use std::{
+    convert::TryInto,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tikv_client_common::internal_err;
+use tokio::task::JoinHandle;
+
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The value stored under the key of a lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Lease {
+    fence_token: u64,
+    /// Physical time of the timestamp oracle, in milliseconds.
+    expires_at: u64,
+}
+
+impl Lease {
+    fn encode(&self) -> Value {
+        let mut value = Vec::with_capacity(16);
+        value.extend_from_slice(&self.fence_token.to_be_bytes());
+        value.extend_from_slice(&self.expires_at.to_be_bytes());
+        value
+    }
+
+    fn decode(value: &[u8]) -> Result<Lease> {
+        if value.len() != 16 {
+            return Err(internal_err!("invalid lock value {:?}", value));
+        }
+        Ok(Lease {
+            fence_token: u64::from_be_bytes(value[..8].try_into().unwrap()),
+            expires_at: u64::from_be_bytes(value[8..].try_into().unwrap()),
+        })
+    }
+}
+
+/// A mutex shared by all clients using the same key.
+///
+/// A lease lasts for `ttl` after it is acquired or extended. By default, the
+/// [`LockGuard`](LockGuard) returned on acquisition extends its lease in the background every
+/// third of the TTL until it is released or dropped.
+#[derive(Clone)]
+pub struct DistributedLock {
+    client: TransactionClient,
+    key: Key,
+    ttl: Duration,
+    retry_interval: Duration,
+    keep_alive: bool,
+}
+
+impl DistributedLock {
+    pub fn new(client: TransactionClient, key: impl Into<Key>, ttl: Duration) -> DistributedLock {
+        DistributedLock {
+            client,
+            key: key.into(),
+            ttl,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            keep_alive: true,
+        }
+    }
+
+    /// How long [`acquire`](DistributedLock::acquire) waits between attempts while the lock is
+    /// held by another owner.
+    pub fn retry_interval(mut self, interval: Duration) -> DistributedLock {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Don't extend leases in the background. Owners must call
+    /// [`LockGuard::keep_alive`](LockGuard::keep_alive) before their lease expires.
+    pub fn no_keep_alive(mut self) -> DistributedLock {
+        self.keep_alive = false;
+        self
+    }
+
+    /// Acquire the lock, waiting until it is released or its lease expires if it is held.
+    pub async fn acquire(&self) -> Result<LockGuard> {
+        loop {
+            if let Some(guard) = self.try_acquire().await? {
+                return Ok(guard);
+            }
+            tokio::time::sleep(self.retry_interval).await;
+        }
+    }
+
+    /// Acquire the lock if it is free, or return `None` if its lease is held by another owner.
+    pub async fn try_acquire(&self) -> Result<Option<LockGuard>> {
+        let (txn, lease, now) = self.begin().await?;
+        let fence_token = match lease {
+            Some(lease) if lease.expires_at > now => {
+                txn.rollback().await?;
+                return Ok(None);
+            }
+            Some(lease) => lease.fence_token + 1,
+            None => 1,
+        };
+        self.write(
+            txn,
+            Lease {
+                fence_token,
+                expires_at: now + self.ttl_millis(),
+            },
+        )
+        .await?;
+        debug!(target: "tikv_client::recipes", key = %self.key, fence_token, "acquired lock");
+        Ok(Some(LockGuard::new(self.clone(), fence_token)))
+    }
+
+    async fn extend(&self, fence_token: u64) -> Result<()> {
+        let (txn, lease, now) = self.begin().await?;
+        if lease.map(|lease| lease.fence_token) != Some(fence_token) {
+            txn.rollback().await?;
+            return Err(self.lost(fence_token));
+        }
+        self.write(
+            txn,
+            Lease {
+                fence_token,
+                expires_at: now + self.ttl_millis(),
+            },
+        )
+        .await
+    }
+
+    async fn release(&self, fence_token: u64) -> Result<()> {
+        let (txn, lease, _) = self.begin().await?;
+        if lease.map(|lease| lease.fence_token) != Some(fence_token) {
+            txn.rollback().await?;
+            return Err(self.lost(fence_token));
+        }
+        // The lease is expired rather than deleted so the next fence token is still larger.
+        self.write(
+            txn,
+            Lease {
+                fence_token,
+                expires_at: 0,
+            },
+        )
+        .await
+    }
+
+    /// Start a transaction and read the current lease. Also returns the current time.
+    async fn begin(&self) -> Result<(Transaction, Option<Lease>, u64)> {
+        let now = self.client.current_timestamp().await?.physical as u64;
+        let txn = self.client.begin_pessimistic().await?;
+        let lease = match txn.get_for_update(self.key.clone()).await {
+            Ok(value) => value.map(|value| Lease::decode(&value)).transpose(),
+            Err(e) => Err(e),
+        };
+        match lease {
+            Ok(lease) => Ok((txn, lease, now)),
+            Err(e) => {
+                txn.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn write(&self, txn: Transaction, lease: Lease) -> Result<()> {
+        if let Err(e) = txn.put(self.key.clone(), lease.encode()).await {
+            txn.rollback().await?;
+            return Err(e);
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    fn ttl_millis(&self) -> u64 {
+        self.ttl.as_millis() as u64
+    }
+
+    fn lost(&self, fence_token: u64) -> Error {
+        Error::LockLost {
+            key: self.key.clone().into(),
+            fence_token,
+        }
+    }
+}
+
+/// Proof of holding a [`DistributedLock`](DistributedLock).
+///
+/// Dropping the guard stops extending the lease, but does not release it: the lock stays held
+/// until the lease expires. Use [`release`](LockGuard::release) to release it immediately.
+pub struct LockGuard {
+    lock: DistributedLock,
+    fence_token: u64,
+    lost: Arc<AtomicBool>,
+    // Stops the keep-alive between extensions. The task is never aborted, as aborting it during
+    // an extension would leave the key locked by the dropped transaction.
+    stop: CancellationToken,
+    keep_alive: Option<JoinHandle<()>>,
+}
+
+impl LockGuard {
+    fn new(lock: DistributedLock, fence_token: u64) -> LockGuard {
+        let lost = Arc::new(AtomicBool::new(false));
+        let stop = CancellationToken::new();
+        let keep_alive = if lock.keep_alive {
+            let lock = lock.clone();
+            let lost = lost.clone();
+            let stop = stop.clone();
+            Some(crate::util::task::spawn("lock-keep-alive", async move {
+                loop {
+                    if tokio::time::timeout(lock.ttl / 3, stop.cancelled())
+                        .await
+                        .is_ok()
+                    {
+                        return;
+                    }
+                    match lock.extend(fence_token).await {
+                        Ok(()) => {}
+                        Err(e @ Error::LockLost { .. }) => {
+                            warn!(target: "tikv_client::recipes", error = %e, "lost lock");
+                            lost.store(true, Ordering::SeqCst);
+                            return;
+                        }
+                        Err(e) => warn!(
+                            target: "tikv_client::recipes",
+                            key = %lock.key,
+                            error = %e,
+                            "failed to extend lease of lock"
+                        ),
+                    }
+                }
+            }))
+        } else {
+            None
+        };
+        LockGuard {
+            lock,
+            fence_token,
+            lost,
+            stop,
+            keep_alive,
+        }
+    }
+
+    /// The fence token of this acquisition, larger than the tokens of all previous acquisitions.
+    pub fn fence_token(&self) -> u64 {
+        self.fence_token
+    }
+
+    /// Whether the background keep-alive found that the lock was acquired by another owner.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::SeqCst)
+    }
+
+    /// Extend the lease by the TTL of the lock.
+    ///
+    /// Returns [`Error::LockLost`](crate::Error::LockLost) if the lease expired and the lock was
+    /// acquired by another owner.
+    pub async fn keep_alive(&self) -> Result<()> {
+        let result = self.lock.extend(self.fence_token).await;
+        if let Err(Error::LockLost { .. }) = result {
+            self.lost.store(true, Ordering::SeqCst);
+        }
+        result
+    }
+
+    /// Release the lock so it can be acquired by other owners.
+    ///
+    /// Returns [`Error::LockLost`](crate::Error::LockLost) if the lock was already acquired by
+    /// another owner.
+    pub async fn release(mut self) -> Result<()> {
+        self.stop.cancel();
+        // Wait for an extension in flight, whose transaction would block the release.
+        if let Some(handle) = self.keep_alive.take() {
+            if let Err(e) = handle.await {
+                warn!(target: "tikv_client::recipes", error = %e, "lock keep-alive failed");
+            }
+        }
+        self.lock.release(self.fence_token).await
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        // The keep-alive finishes the extension in flight, if any, and stops.
+        self.stop.cancel();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lease_codec() {
+        let lease = Lease {
+            fence_token: 3,
+            expires_at: 1_600_000_000_000,
+        };
+        assert_eq!(Lease::decode(&lease.encode()).unwrap(), lease);
+        assert!(Lease::decode(b"foo").is_err());
+    }
+}
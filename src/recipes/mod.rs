@@ -0,0 +1,9 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Higher-level primitives built on top of the clients.
+
+pub mod id_alloc;
+pub mod idempotency;
+pub mod large_value;
+pub mod lock;
+pub mod queue;
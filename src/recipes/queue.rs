@@ -0,0 +1,283 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! An at-least-once work queue with visibility timeouts.
+//!
+//! Every message is a key under the prefix of the queue, ordered by the start timestamp of the
+//! transaction which enqueued it. Its value records the payload, the number of times it was
+//! dequeued and the time until which it is hidden from consumers. Dequeuing a message doesn't
+//! delete it but hides it for a visibility timeout, during which the consumer handles it and
+//! [`ack`](Queue::ack)s it. If the consumer fails before acking, the message becomes visible
+//! again once the timeout expires and is delivered to another consumer. Times are taken from the
+//! timestamp oracle, so they don't depend on the clocks of the clients.
+//!
+//! As a message may be delivered more than once, handling it should be idempotent. Messages can
+//! be enqueued in the transaction making the changes they announce with
+//! [`enqueue_in`](Queue::enqueue_in), so that they are published if and only if the transaction
+//! commits (the transactional outbox pattern).
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{TransactionClient, recipes::queue::Queue};
+//! # use std::time::Duration;
+//! # futures::executor::block_on(async {
+//! let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let queue = Queue::new(client, "queues/emails".to_owned());
+//! queue.enqueue("hello".to_owned()).await.unwrap();
+//! if let Some(message) = queue.dequeue(Duration::from_secs(30)).await.unwrap() {
+//!     // ... handle message.payload().
+//!     queue.ack(&message).await.unwrap();
+//! }
+//! # });
+//! ```
+
+use crate::{
+    codec::keys::CompositeKeyBuilder, kv::Prefix, BoundRange, Key, Options, Result, TimestampExt,
+    Transaction, TransactionClient, Value,
+};
+use std::{
+    convert::TryInto,
+    ops::Bound,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tikv_client_common::internal_err;
+
+// How many messages are read at a time when looking for a visible one.
+const SCAN_BATCH_SIZE: u32 = 64;
+// The length of the header of the value of a message: `visible_at` and `attempts`.
+const HEADER_LEN: usize = 12;
+
+/// The value stored under the key of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    /// Physical time of the timestamp oracle, in milliseconds, before which the message is
+    /// hidden.
+    visible_at: u64,
+    attempts: u32,
+    payload: Value,
+}
+
+impl Entry {
+    fn encode(&self) -> Value {
+        let mut value = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        value.extend_from_slice(&self.visible_at.to_be_bytes());
+        value.extend_from_slice(&self.attempts.to_be_bytes());
+        value.extend_from_slice(&self.payload);
+        value
+    }
+
+    fn decode(mut value: Value) -> Result<Entry> {
+        if value.len() < HEADER_LEN {
+            return Err(internal_err!("invalid queue message {:?}", value));
+        }
+        let payload = value.split_off(HEADER_LEN);
+        Ok(Entry {
+            visible_at: u64::from_be_bytes(value[..8].try_into().unwrap()),
+            attempts: u32::from_be_bytes(value[8..].try_into().unwrap()),
+            payload,
+        })
+    }
+}
+
+/// A queue shared by all clients using the same prefix.
+///
+/// Messages are dequeued roughly in the order they were enqueued. Keys starting with the prefix
+/// must not be used for anything else.
+pub struct Queue {
+    client: TransactionClient,
+    prefix: Prefix,
+    // Tells apart the messages enqueued in the same transaction.
+    seq: AtomicU64,
+}
+
+impl Queue {
+    pub fn new(client: TransactionClient, prefix: impl Into<Key>) -> Queue {
+        Queue {
+            client,
+            prefix: Prefix::new(prefix.into()),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Enqueue a message in its own transaction.
+    pub async fn enqueue(&self, payload: impl Into<Value>) -> Result<()> {
+        let txn = self.client.begin_optimistic().await?;
+        if let Err(e) = self.enqueue_in(&txn, payload).await {
+            txn.rollback().await?;
+            return Err(e);
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Enqueue a message in `txn`. The message is visible to consumers once `txn` commits, and
+    /// never if it is rolled back.
+    pub async fn enqueue_in(&self, txn: &Transaction, payload: impl Into<Value>) -> Result<()> {
+        let key = self.prefix.encode_key(
+            CompositeKeyBuilder::new()
+                .push_u64(txn.start_timestamp().version())
+                .push_u64(self.seq.fetch_add(1, Ordering::SeqCst))
+                .build(),
+        );
+        let entry = Entry {
+            visible_at: 0,
+            attempts: 0,
+            payload: payload.into(),
+        };
+        txn.insert(key, entry.encode()).await
+    }
+
+    /// Dequeue the oldest visible message, hiding it from other consumers for
+    /// `visibility_timeout`. Returns `None` if no message is visible.
+    ///
+    /// The message must be [`ack`](Queue::ack)ed within the timeout, or it is delivered again.
+    pub async fn dequeue(&self, visibility_timeout: Duration) -> Result<Option<Message>> {
+        let now = self.client.current_timestamp().await?.physical as u64;
+        let txn = self.client.begin_pessimistic().await?;
+        let result = async {
+            let mut start: BoundRange = (..).into();
+            loop {
+                // Read the latest committed entries, which may have been leased since `txn`
+                // started.
+                let pairs: Vec<_> = txn
+                    .scan_opt(
+                        self.prefix.encode_range(start),
+                        SCAN_BATCH_SIZE,
+                        Options::new().read_committed(),
+                    )
+                    .await?
+                    .collect();
+                let exhausted = pairs.len() < SCAN_BATCH_SIZE as usize;
+                let last = pairs.last().map(|pair| pair.key().clone());
+                for pair in pairs {
+                    let (key, value) = (pair.0, pair.1);
+                    if Entry::decode(value)?.visible_at > now {
+                        continue;
+                    }
+                    // Another consumer may lease the message between the scan and the lock.
+                    let entry = match txn.get_for_update(key.clone()).await? {
+                        Some(value) => Entry::decode(value)?,
+                        None => continue,
+                    };
+                    if entry.visible_at > now {
+                        continue;
+                    }
+                    let entry = Entry {
+                        visible_at: now + visibility_timeout.as_millis() as u64,
+                        attempts: entry.attempts + 1,
+                        payload: entry.payload,
+                    };
+                    txn.put(key.clone(), entry.encode()).await?;
+                    return Ok(Some(Message { key, entry }));
+                }
+                match last {
+                    Some(last) if !exhausted => {
+                        start = (
+                            Bound::Excluded(self.prefix.decode_key(last)),
+                            Bound::Unbounded,
+                        )
+                            .into()
+                    }
+                    _ => return Ok(None),
+                }
+            }
+        }
+        .await;
+        match result {
+            Ok(message) => {
+                txn.commit().await?;
+                if let Some(message) = &message {
+                    debug!(
+                        target: "tikv_client::recipes",
+                        key = %message.key,
+                        attempts = message.entry.attempts,
+                        "dequeued message"
+                    );
+                }
+                Ok(message)
+            }
+            Err(e) => {
+                txn.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Delete a handled message from the queue.
+    ///
+    /// Returns `false` if the visibility timeout of the message expired and it was dequeued
+    /// again or acked by another consumer, in which case the queue is left as is.
+    pub async fn ack(&self, message: &Message) -> Result<bool> {
+        let txn = self.client.begin_pessimistic().await?;
+        let result = async {
+            let leased = match txn.get_for_update(message.key.clone()).await? {
+                Some(value) => {
+                    let entry = Entry::decode(value)?;
+                    entry.attempts == message.entry.attempts
+                        && entry.visible_at == message.entry.visible_at
+                }
+                None => false,
+            };
+            if leased {
+                txn.delete(message.key.clone()).await?;
+            }
+            Ok(leased)
+        }
+        .await;
+        match result {
+            Ok(acked) => {
+                txn.commit().await?;
+                Ok(acked)
+            }
+            Err(e) => {
+                txn.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A message dequeued from a [`Queue`](Queue).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    key: Key,
+    entry: Entry,
+}
+
+impl Message {
+    pub fn payload(&self) -> &Value {
+        &self.entry.payload
+    }
+
+    pub fn into_payload(self) -> Value {
+        self.entry.payload
+    }
+
+    /// How many times the message was dequeued, including this time. Messages which keep
+    /// failing can be recognized by this count and moved elsewhere.
+    pub fn attempts(&self) -> u32 {
+        self.entry.attempts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entry_codec() {
+        let entry = Entry {
+            visible_at: 1_600_000_000_000,
+            attempts: 2,
+            payload: b"payload".to_vec(),
+        };
+        assert_eq!(Entry::decode(entry.encode()).unwrap(), entry);
+        let empty = Entry {
+            visible_at: 0,
+            attempts: 0,
+            payload: Vec::new(),
+        };
+        assert_eq!(Entry::decode(empty.encode()).unwrap(), empty);
+        assert!(Entry::decode(b"foo".to_vec()).is_err());
+    }
+}
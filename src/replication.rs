@@ -0,0 +1,300 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Copying data between TiKV clusters.
+//!
+//! [`copy_range`](copy_range) copies the key-value pairs of a range from a snapshot of the source
+//! cluster into the destination cluster. Pairs are scanned and written in pages, and the next page
+//! is scanned while the current one is written. Each page is written in its own transaction, so an
+//! unfinished copy is partially visible in the destination, but every value copied is from the
+//! same snapshot.
+//!
+//! After every page, the copy reports its [`CopyProgress`](CopyProgress). A copy which failed, or
+//! whose process was restarted, can be resumed from the last progress reported.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use tikv_client::{TransactionClient, replication::{copy_range, CopyOptions}};
+//! # futures::executor::block_on(async {
+//! let src = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+//! let dst = TransactionClient::new(vec!["192.168.0.200"]).await.unwrap();
+//! let options = CopyOptions::new().on_progress(|progress| {
+//!     println!("copied {} pairs", progress.copied());
+//! });
+//! let progress = copy_range(&src, &dst, "a".to_owned().."z".to_owned(), options)
+//!     .await
+//!     .unwrap();
+//! assert!(progress.is_done());
+//! # });
+//! ```
+
+use crate::{
+    BoundRange, Key, KvPair, Result, ServiceSafepoint, Timestamp, TimestampExt, TransactionClient,
+    TransactionOptions,
+};
+use futures::future;
+use rand::{thread_rng, Rng};
+use std::{sync::Arc, time::Duration};
+
+const DEFAULT_BATCH_SIZE: u32 = 1024;
+const SAFEPOINT_TTL: Duration = Duration::from_secs(300);
+
+/// How far a copy has got.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CopyProgress {
+    timestamp: Timestamp,
+    last_key: Option<Key>,
+    copied: u64,
+    done: bool,
+}
+
+impl CopyProgress {
+    /// The timestamp of the snapshot being copied.
+    pub fn timestamp(&self) -> &Timestamp {
+        &self.timestamp
+    }
+
+    /// The last key copied. All pairs of the range up to this key have been copied.
+    pub fn last_key(&self) -> Option<&Key> {
+        self.last_key.as_ref()
+    }
+
+    /// The number of pairs copied.
+    pub fn copied(&self) -> u64 {
+        self.copied
+    }
+
+    /// Whether all pairs of the range have been copied.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+/// Options for [`copy_range`](copy_range).
+#[derive(Clone)]
+pub struct CopyOptions {
+    batch_size: u32,
+    timestamp: Option<Timestamp>,
+    resume: Option<CopyProgress>,
+    hold_safepoint: bool,
+    on_progress: Option<Arc<dyn Fn(&CopyProgress) + Send + Sync>>,
+}
+
+impl Default for CopyOptions {
+    fn default() -> CopyOptions {
+        CopyOptions::new()
+    }
+}
+
+impl CopyOptions {
+    /// Copy a snapshot at the current timestamp, in pages of 1024 pairs, holding back GC of the
+    /// source cluster.
+    pub fn new() -> CopyOptions {
+        CopyOptions {
+            batch_size: DEFAULT_BATCH_SIZE,
+            timestamp: None,
+            resume: None,
+            hold_safepoint: true,
+            on_progress: None,
+        }
+    }
+
+    /// The number of pairs scanned and written at a time.
+    pub fn batch_size(mut self, batch_size: u32) -> CopyOptions {
+        assert!(batch_size > 0, "batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Copy the snapshot at `timestamp` rather than at the current timestamp.
+    ///
+    /// The timestamp must not have fallen behind the GC safepoint of the source cluster when the
+    /// copy starts. GC is then held back until it is done, unless
+    /// [`hold_safepoint`](CopyOptions::hold_safepoint) is disabled.
+    pub fn timestamp(mut self, timestamp: Timestamp) -> CopyOptions {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Continue a copy from `progress`, which must have been reported by a copy of the same range.
+    ///
+    /// The snapshot of the earlier copy is copied, so its timestamp must not have fallen behind the
+    /// GC safepoint of the source cluster.
+    pub fn resume(mut self, progress: CopyProgress) -> CopyOptions {
+        self.resume = Some(progress);
+        self
+    }
+
+    /// Whether to register a service safepoint in the source cluster for the duration of the copy,
+    /// so GC doesn't remove the versions of the snapshot while it is copied. Enabled by default;
+    /// disable it if PD doesn't allow the client to register safepoints.
+    ///
+    /// See [`TransactionClient::hold_safepoint`](TransactionClient::hold_safepoint).
+    pub fn hold_safepoint(mut self, hold: bool) -> CopyOptions {
+        self.hold_safepoint = hold;
+        self
+    }
+
+    /// Call `f` with the progress of the copy after every page written.
+    pub fn on_progress(mut self, f: impl Fn(&CopyProgress) + Send + Sync + 'static) -> CopyOptions {
+        self.on_progress = Some(Arc::new(f));
+        self
+    }
+}
+
+/// Copy the pairs of `range` from a snapshot of `src` into `dst`, returning the final progress.
+///
+/// Pairs are written with `put`, overwriting the value of a key in `dst`. Keys of `dst` in the
+/// range which are not in the snapshot of `src` are kept.
+pub async fn copy_range(
+    src: &TransactionClient,
+    dst: &TransactionClient,
+    range: impl Into<BoundRange>,
+    options: CopyOptions,
+) -> Result<CopyProgress> {
+    let CopyOptions {
+        batch_size,
+        timestamp,
+        resume,
+        hold_safepoint: hold,
+        on_progress,
+    } = options;
+    let mut progress = match resume {
+        Some(progress) => progress,
+        None => CopyProgress {
+            timestamp: match timestamp {
+                Some(timestamp) => timestamp,
+                None => src.current_timestamp().await?,
+            },
+            last_key: None,
+            copied: 0,
+            done: false,
+        },
+    };
+    if progress.done {
+        return Ok(progress);
+    }
+
+    let safepoint = hold_safepoint(src, "copy", &progress.timestamp, hold).await?;
+
+    let (start, end) = range.into().into_keys();
+    let start = match &progress.last_key {
+        Some(last_key) => key_after(last_key),
+        None => start,
+    };
+    let report = |progress: &CopyProgress| {
+        if let Some(f) = &on_progress {
+            f(progress);
+        }
+    };
+    let mut page = scan_page(src, &progress.timestamp, start, end.clone(), batch_size).await?;
+    loop {
+        let last_key = match page.last() {
+            Some(pair) => pair.key().clone(),
+            None => {
+                progress.done = true;
+                report(&progress);
+                break;
+            }
+        };
+        // A page shorter than the batch size is the last one.
+        let full = page.len() == batch_size as usize;
+        let next_page = async {
+            if full {
+                let start = key_after(&last_key);
+                scan_page(src, &progress.timestamp, start, end.clone(), batch_size).await
+            } else {
+                Ok(Vec::new())
+            }
+        };
+        let copied = page.len() as u64;
+        let ((), next_page) = future::try_join(write_page(dst, page), next_page).await?;
+
+        progress.last_key = Some(last_key);
+        progress.copied += copied;
+        progress.done = !full;
+        report(&progress);
+        if progress.done {
+            break;
+        }
+        page = next_page;
+    }
+    release_safepoint(safepoint).await;
+    Ok(progress)
+}
+
+pub(crate) async fn scan_page(
+    src: &TransactionClient,
+    timestamp: &Timestamp,
+    start: Key,
+    end: Option<Key>,
+    limit: u32,
+) -> Result<Vec<KvPair>> {
+    // A snapshot caches what it reads, so every page is scanned by a new one.
+    let snapshot = src.snapshot(timestamp.clone(), TransactionOptions::new_optimistic());
+    Ok(snapshot.scan((start, end), limit).await?.collect())
+}
+
+pub(crate) async fn write_page(dst: &TransactionClient, pairs: Vec<KvPair>) -> Result<()> {
+    let txn = dst.begin_optimistic().await?;
+    let result = async {
+        for KvPair(key, value) in pairs {
+            txn.put(key, value).await?;
+        }
+        Ok(())
+    }
+    .await;
+    match result {
+        Ok(()) => {
+            txn.commit().await?;
+            Ok(())
+        }
+        Err(e) => {
+            txn.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+/// Register a service safepoint at `timestamp` for a job, if `hold` is set, under a unique ID.
+pub(crate) async fn hold_safepoint(
+    client: &TransactionClient,
+    job: &str,
+    timestamp: &Timestamp,
+    hold: bool,
+) -> Result<Option<ServiceSafepoint>> {
+    if !hold {
+        return Ok(None);
+    }
+    let service_id = format!(
+        "tikv_client_{}_{}_{:08x}",
+        job,
+        timestamp.version(),
+        thread_rng().gen::<u32>()
+    );
+    let safepoint = client
+        .hold_safepoint(service_id, timestamp.clone(), SAFEPOINT_TTL)
+        .await?;
+    Ok(Some(safepoint))
+}
+
+/// Remove the registration of a safepoint held by a job which is done. Failing to is only logged,
+/// as the registration expires anyway.
+pub(crate) async fn release_safepoint(safepoint: Option<ServiceSafepoint>) {
+    if let Some(safepoint) = safepoint {
+        let service_id = safepoint.service_id().to_owned();
+        if let Err(e) = safepoint.release().await {
+            warn!(
+                target: "tikv_client::txn",
+                service_id = service_id.as_str(),
+                error = %e,
+                "failed to release service safepoint"
+            );
+        }
+    }
+}
+
+pub(crate) fn key_after(key: &Key) -> Key {
+    let mut key: Vec<u8> = key.clone().into();
+    key.push(0);
+    key.into()
+}
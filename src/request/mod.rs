@@ -9,14 +9,16 @@ use derive_new::new;
 use tikv_client_store::{HasError, Request};
 
 pub use self::{
+    options::{CancellationToken, Options, Priority},
     plan::{
         Collect, CollectError, DefaultProcessor, Dispatch, ExtractError, Merge, MergeResponse,
         MultiRegion, Plan, Process, ProcessResponse, ResolveLock, RetryRegion,
     },
     plan_builder::{PlanBuilder, SingleKey},
-    shard::Shardable,
+    shard::{BatchLimits, Shardable},
 };
 
+mod options;
 mod plan;
 mod plan_builder;
 #[macro_use]
@@ -195,6 +197,7 @@ mod test {
                         commit_ts_too_large: None,
                     }),
                     commit_version: 0,
+                    exec_details_v2: None,
                 }) as Box<dyn Any>)
             },
         )));
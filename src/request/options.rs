@@ -0,0 +1,275 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::{request::RetryOptions, Error, Result};
+use futures::{
+    future::{self, Either},
+    prelude::*,
+};
+use futures_timer::Delay;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tikv_client_proto::kvrpcpb;
+use tokio::sync::Notify;
+
+/// The priority TiKV schedules a request with, relative to other requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl From<Priority> for kvrpcpb::CommandPri {
+    fn from(priority: Priority) -> kvrpcpb::CommandPri {
+        match priority {
+            Priority::Low => kvrpcpb::CommandPri::Low,
+            Priority::Normal => kvrpcpb::CommandPri::Normal,
+            Priority::High => kvrpcpb::CommandPri::High,
+        }
+    }
+}
+
+/// Cancels the operations it is passed to, e.g. when the user who started them went away.
+///
+/// Operations passed a token with [`Options::cancellation`] fail with
+/// [`OperationCanceled`](Error::OperationCanceled) as soon as it is cancelled, like they do on a
+/// timeout. [`Transaction::commit_with_cancellation`](crate::Transaction::commit_with_cancellation)
+/// stops cooperatively instead, only where stopping leaves nothing behind. Clones of a token
+/// cancel the same operations.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use tikv_client::{CancellationToken, Options, RawClient};
+/// # futures::executor::block_on(async {
+/// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+/// let token = CancellationToken::new();
+/// let options = Options::new().cancellation(token.clone());
+/// let scan = client.scan_opt(.., u32::MAX, options);
+/// // ... From another task, when the result is no longer needed:
+/// token.cancel();
+/// # });
+/// ```
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<CancellationInner>,
+}
+
+#[derive(Default)]
+struct CancellationInner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel the operations passed the token, and those it will be passed to.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // Created before checking the flag, so a cancellation in between isn't missed.
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Fail with [`OperationCanceled`](Error::OperationCanceled) if the token is cancelled.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::OperationCanceled);
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &CancellationToken) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Options of a single operation, passed to the `*_opt` variants of the operations of
+/// [`RawClient`](crate::RawClient) and [`Transaction`](crate::Transaction).
+///
+/// Options which are not set keep the settings of the client, or of the transaction.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use tikv_client::{Options, Priority, RawClient};
+/// # use std::time::Duration;
+/// # futures::executor::block_on(async {
+/// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+/// let options = Options::new()
+///     .timeout(Duration::from_millis(50))
+///     .priority(Priority::High);
+/// let value = client.get_opt("key".to_owned(), options).await.unwrap();
+/// # });
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    timeout: Option<Duration>,
+    pub(crate) priority: Option<Priority>,
+    pub(crate) replica_read: Option<bool>,
+    pub(crate) key_only: bool,
+    pub(crate) retry_options: Option<RetryOptions>,
+    pub(crate) read_committed: bool,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Options {
+    /// Options keeping every setting of the client.
+    pub fn new() -> Options {
+        Options::default()
+    }
+
+    /// Fail the operation with [`OperationTimeout`](Error::OperationTimeout) if it doesn't
+    /// finish within `timeout`, including retries.
+    ///
+    /// A write which times out may or may not have been applied.
+    pub fn timeout(mut self, timeout: Duration) -> Options {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send the requests of the operation with `priority`.
+    pub fn priority(mut self, priority: Priority) -> Options {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Whether the operation reads from the replica of each region with the best observed latency
+    /// and error rate, rather than from the leader. Only applies to reads.
+    pub fn replica_read(mut self, replica_read: bool) -> Options {
+        self.replica_read = Some(replica_read);
+        self
+    }
+
+    /// Only read the keys of pairs, leaving their values empty. Only applies to scans.
+    pub fn key_only(mut self) -> Options {
+        self.key_only = true;
+        self
+    }
+
+    /// Read the latest committed data, rather than the snapshot of the transaction, like a
+    /// statement of a read-committed transaction in TiDB. Locks of transactions which are not
+    /// committed yet are ignored rather than waited for. Only applies to the reads of pessimistic
+    /// transactions.
+    ///
+    /// The values read are not cached by the transaction, and writes of the transaction are still
+    /// read back. Useful e.g. to poll a queue within a long transaction.
+    pub fn read_committed(mut self) -> Options {
+        self.read_committed = true;
+        self
+    }
+
+    /// Retry the requests of the operation with `options`.
+    pub fn retry_options(mut self, options: RetryOptions) -> Options {
+        self.retry_options = Some(options);
+        self
+    }
+
+    /// Fail the operation with [`OperationCanceled`](Error::OperationCanceled) as soon as `token`
+    /// is cancelled, dropping its requests in flight.
+    ///
+    /// Like on a timeout, a write which is cancelled may or may not have been applied.
+    pub fn cancellation(mut self, token: CancellationToken) -> Options {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Run `operation`, failing if it doesn't finish within the timeout, or if it is cancelled.
+    pub(crate) async fn run<T>(&self, operation: impl Future<Output = Result<T>>) -> Result<T> {
+        let operation = async {
+            let token = match &self.cancellation {
+                Some(token) => token,
+                None => return operation.await,
+            };
+            token.check()?;
+            futures::pin_mut!(operation);
+            let cancelled = token.cancelled();
+            futures::pin_mut!(cancelled);
+            match future::select(operation, cancelled).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => Err(Error::OperationCanceled),
+            }
+        };
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return operation.await,
+        };
+        futures::pin_mut!(operation);
+        match future::select(operation, Delay::new(timeout)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(Error::OperationTimeout(timeout)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor;
+
+    #[test]
+    fn test_run_timeout() {
+        let options = Options::new().timeout(Duration::from_millis(10));
+        let result = executor::block_on(options.run(future::pending::<Result<()>>()));
+        assert!(matches!(result, Err(Error::OperationTimeout(_))));
+
+        let result = executor::block_on(options.run(future::ready(Ok(1))));
+        assert_eq!(result.unwrap(), 1);
+        let result = executor::block_on(Options::new().run(future::ready(Ok(2))));
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_run_cancellation() {
+        let token = CancellationToken::new();
+        let options = Options::new().cancellation(token.clone());
+        let result = executor::block_on(future::join(
+            options.run(future::pending::<Result<()>>()),
+            async { token.cancel() },
+        ));
+        assert!(matches!(result.0, Err(Error::OperationCanceled)));
+
+        // Operations passed a cancelled token fail without running.
+        let result = executor::block_on(options.run(future::ready(Ok(1))));
+        assert!(matches!(result, Err(Error::OperationCanceled)));
+        let result = executor::block_on(
+            Options::new()
+                .cancellation(CancellationToken::new())
+                .run(future::ready(Ok(1))),
+        );
+        assert_eq!(result.unwrap(), 1);
+    }
+}
@@ -1,17 +1,22 @@
 // Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
 
 use crate::{
-    backoff::Backoff,
-    pd::PdClient,
-    request::{KvRequest, Shardable},
-    stats::tikv_stats,
+    backoff::{Backoff, RetryKind},
+    pd::{HotRegionCache, PdClient, RegionCache, RequestUnitRecorder},
+    request::{BatchLimits, KvRequest, Shardable},
+    stats::{observe_shard_duration, tikv_stats},
+    store::Store,
     transaction::{resolve_locks, HasLocks},
-    Error, Result,
+    Attempt, Error, RegionId, Result,
 };
 use async_trait::async_trait;
-use futures::{prelude::*, stream::StreamExt};
-use std::{marker::PhantomData, sync::Arc};
-use tikv_client_store::{HasError, HasRegionError, KvClient};
+use futures::stream::{self, StreamExt};
+use std::{
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tikv_client_store::{HasError, HasRegionError, KvClient, RequestMetadata};
 
 /// A plan for how to execute a request. A user builds up a plan with various
 /// options, then exectutes it.
@@ -22,6 +27,13 @@ pub trait Plan: Sized + Clone + Sync + Send + 'static {
 
     /// Execute the plan.
     async fn execute(&self) -> Result<Self::Result>;
+
+    /// Point the plan at the current leader of the region it was built for, after its request
+    /// failed because the leader moved. Plans which look up their regions when executed don't
+    /// need to.
+    async fn retarget_leader(&mut self, _pd_client: &Arc<impl PdClient>) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// The simplest plan which just dispatches a request to a specific kv server.
@@ -29,6 +41,14 @@ pub trait Plan: Sized + Clone + Sync + Send + 'static {
 pub struct Dispatch<Req: KvRequest> {
     pub request: Req,
     pub kv_client: Option<Arc<dyn KvClient + Send + Sync>>,
+    pub metadata: RequestMetadata,
+    /// The region the request is sent to, once it has a target.
+    pub region_id: Option<RegionId>,
+    pub hot_region_cache: Option<Arc<HotRegionCache>>,
+    /// The cached regions to invalidate when the request fails with a region error.
+    pub region_cache: Option<Arc<RegionCache>>,
+    /// Where the request units consumed by the request are recorded, if anywhere.
+    pub request_units: Option<RequestUnitRecorder>,
 }
 
 #[async_trait]
@@ -36,28 +56,74 @@ impl<Req: KvRequest> Plan for Dispatch<Req> {
     type Result = Req::Response;
 
     async fn execute(&self) -> Result<Self::Result> {
+        let kv_client = self.kv_client.as_ref().ok_or_else(|| {
+            Error::StringError(
+                "Unreachable: kv_client has not been initialised in Dispatch".to_owned(),
+            )
+        })?;
+        let limiter = kv_client.limiter();
+        let permit = match &limiter {
+            Some(limiter) => Some(limiter.acquire().await),
+            None => None,
+        };
         let stats = tikv_stats(self.request.label());
-        let result = self
-            .kv_client
-            .as_ref()
-            .ok_or_else(|| {
-                Error::StringError(
-                    "Unreachable: kv_client has not been initialised in Dispatch".to_owned(),
-                )
-            })?
-            .dispatch(&self.request)
-            .await;
-        let result = stats.done(result);
-        result.map(|r| {
-            *r.downcast()
+        let result = kv_client.dispatch(&self.request, &self.metadata).await;
+        let result = stats.done(result).map(|r| {
+            *r.downcast::<Req::Response>()
                 .expect("Downcast failed: request and response type mismatch")
-        })
+        });
+        if let Some(permit) = permit {
+            // Stores push back with `ServerIsBusy` when overloaded.
+            let busy = matches!(
+                &result,
+                Ok(response) if matches!(response.peek_region_error(), Some(e) if e.has_server_is_busy())
+            );
+            permit.release(busy);
+        }
+        if let (Ok(response), Some(recorder)) = (&result, &self.request_units) {
+            recorder.record(response);
+        }
+        if let (Some(cache), Some(region_id)) = (&self.region_cache, self.region_id) {
+            match &result {
+                Ok(response) => {
+                    if let Some(error) = response.peek_region_error() {
+                        cache.on_region_error(region_id, error);
+                    }
+                }
+                Err(Error::Grpc(_)) => cache.on_send_error(region_id),
+                Err(_) => {}
+            }
+        }
+        match (result, &self.hot_region_cache, self.region_id) {
+            (Err(e), Some(cache), Some(region_id)) => Err(cache.annotate(region_id, e)),
+            (result, _, _) => result,
+        }
+    }
+
+    async fn retarget_leader(&mut self, pd_client: &Arc<impl PdClient>) -> Result<()> {
+        if let Some(region_id) = self.region_id {
+            let region = pd_client.region_for_id(region_id).await?;
+            let store = pd_client.clone().map_region_to_store(region).await?;
+            self.request.set_context(store.context()?);
+            self.kv_client = Some(store.client);
+        }
+        Ok(())
     }
 }
 
+/// The number of shards of a multi-region plan which are executed concurrently.
+const MULTI_REGION_CONCURRENCY: usize = 16;
+
+/// Sends the shards of a request to their regions concurrently.
+///
+/// The results are in the order of the shards, i.e. of the keys or ranges of the request,
+/// whichever order the shards complete in.
+///
+/// With limits, the shard of each region is split into several requests within the limits.
 pub struct MultiRegion<P: Plan, PdC: PdClient> {
     pub(super) inner: P,
     pub pd_client: Arc<PdC>,
+    pub(super) limits: Option<BatchLimits>,
 }
 
 impl<P: Plan, PdC: PdClient> Clone for MultiRegion<P, PdC> {
@@ -65,10 +131,17 @@ impl<P: Plan, PdC: PdClient> Clone for MultiRegion<P, PdC> {
         MultiRegion {
             inner: self.inner.clone(),
             pd_client: self.pd_client.clone(),
+            limits: self.limits,
         }
     }
 }
 
+/// The result of one shard of a multi-region plan, tagged with the position of the shard.
+struct ShardResult<T> {
+    index: usize,
+    result: Result<T>,
+}
+
 #[async_trait]
 impl<P: Plan + Shardable, PdC: PdClient> Plan for MultiRegion<P, PdC>
 where
@@ -77,20 +150,54 @@ where
     type Result = Vec<Result<P::Result>>;
 
     async fn execute(&self) -> Result<Self::Result> {
-        Ok(self
+        // The request sent for each shard is a copy of the template with the data of the shard.
+        let mut template = self.inner.clone();
+        template.clear_sharded_data();
+        let template = &template;
+        let mut results: Vec<ShardResult<P::Result>> = self
             .inner
             .shards(&self.pd_client)
-            .and_then(move |(shard, store)| async move {
-                let mut clone = self.inner.clone();
-                clone.apply_shard(shard, &store)?;
-                let mut response = clone.execute().await?;
-                match response.error() {
-                    Some(e) => Err(e),
-                    None => Ok(response),
-                }
+            .flat_map(move |shard| stream::iter(self.split_shard(shard)))
+            .enumerate()
+            .map(move |(index, shard)| async move {
+                let start = Instant::now();
+                let result = match shard {
+                    Ok((shard, store)) => Self::execute_shard(template, shard, &store).await,
+                    Err(e) => Err(e),
+                };
+                observe_shard_duration(start.elapsed());
+                ShardResult { index, result }
             })
+            .buffer_unordered(MULTI_REGION_CONCURRENCY)
             .collect()
-            .await)
+            .await;
+        results.sort_unstable_by_key(|shard| shard.index);
+        Ok(results.into_iter().map(|shard| shard.result).collect())
+    }
+}
+
+impl<P: Plan + Shardable, PdC: PdClient> MultiRegion<P, PdC>
+where
+    P::Result: HasError,
+{
+    fn split_shard(&self, shard: Result<(P::Shard, Store)>) -> Vec<Result<(P::Shard, Store)>> {
+        match (shard, &self.limits) {
+            (Ok((shard, store)), Some(limits)) => P::split_shard(shard, limits)
+                .into_iter()
+                .map(|shard| Ok((shard, store.clone())))
+                .collect(),
+            (shard, _) => vec![shard],
+        }
+    }
+
+    async fn execute_shard(template: &P, shard: P::Shard, store: &Store) -> Result<P::Result> {
+        let mut clone = template.clone();
+        clone.apply_shard(shard, store)?;
+        let mut response = clone.execute().await?;
+        match response.error() {
+            Some(e) => Err(e),
+            None => Ok(response),
+        }
     }
 }
 
@@ -188,13 +295,49 @@ where
     type Result = P::Result;
 
     async fn execute(&self) -> Result<Self::Result> {
+        let start = Instant::now();
+        let mut attempts = Vec::new();
         let mut result = self.inner.execute().await?;
         let mut clone = self.clone();
+        let mut leader_hints = 0;
         while let Some(region_error) = result.region_error() {
+            let elapsed = start.elapsed();
+            // The leader a region moved to is cached from the error, so the request is sent to
+            // it right away, rather than after looking the region up again.
+            if has_leader_hint(&region_error) && leader_hints < MAX_LEADER_HINT_RETRIES {
+                leader_hints += 1;
+                attempts.push(Attempt {
+                    error: region_error,
+                    delay: Duration::ZERO,
+                    elapsed,
+                });
+                observe_backoff(&*self.pd_client, RetryKind::Region, &attempts);
+                clone.inner.retarget_leader(&self.pd_client).await?;
+                result = clone.inner.execute().await?;
+                continue;
+            }
             match clone.backoff.next_delay_duration() {
-                None => return Err(region_error),
+                None => {
+                    attempts.push(Attempt {
+                        error: region_error,
+                        delay: Duration::ZERO,
+                        elapsed,
+                    });
+                    return Err(exhausted(attempts));
+                }
                 Some(delay_duration) => {
+                    let delay_duration = retry_after(&region_error).unwrap_or(delay_duration);
+                    let not_leader = is_not_leader(&region_error);
+                    attempts.push(Attempt {
+                        error: region_error,
+                        delay: delay_duration,
+                        elapsed,
+                    });
+                    observe_backoff(&*self.pd_client, RetryKind::Region, &attempts);
                     futures_timer::Delay::new(delay_duration).await;
+                    if not_leader {
+                        clone.inner.retarget_leader(&self.pd_client).await?;
+                    }
                     result = clone.inner.execute().await?;
                 }
             }
@@ -204,6 +347,33 @@ where
     }
 }
 
+// How many times a request is retried right away on the leaders hinted by `NotLeader` errors,
+// before backing off, in case the hints go stale faster than the leader settles.
+const MAX_LEADER_HINT_RETRIES: usize = 3;
+
+// Bounds the delays suggested by stores, in case of a bogus hint.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// The delay the store suggests before retrying, if `error` carries one. It replaces the delay of
+/// the backoff, as the store knows best when it will recover.
+fn retry_after(error: &Error) -> Option<Duration> {
+    match error {
+        Error::RegionError(e) if e.get_server_is_busy().backoff_ms > 0 => {
+            Some(Duration::from_millis(e.get_server_is_busy().backoff_ms).min(MAX_RETRY_AFTER))
+        }
+        _ => None,
+    }
+}
+
+fn is_not_leader(error: &Error) -> bool {
+    matches!(error, Error::RegionError(e) if e.has_not_leader())
+}
+
+/// Whether `error` is a `NotLeader` error naming the new leader.
+fn has_leader_hint(error: &Error) -> bool {
+    matches!(error, Error::RegionError(e) if e.get_not_leader().has_leader())
+}
+
 pub struct ResolveLock<P: Plan, PdC: PdClient> {
     pub inner: P,
     pub pd_client: Arc<PdC>,
@@ -228,25 +398,42 @@ where
     type Result = P::Result;
 
     async fn execute(&self) -> Result<Self::Result> {
+        let start = Instant::now();
+        let mut attempts = Vec::new();
         let mut result = self.inner.execute().await?;
         let mut clone = self.clone();
         loop {
-            let locks = result.take_locks();
+            let mut locks = result.take_locks();
             if locks.is_empty() {
                 return Ok(result);
             }
 
             if self.backoff.is_none() {
-                return Err(Error::ResolveLockError);
+                return Err(locks.swap_remove(0).into());
             }
 
             let pd_client = self.pd_client.clone();
+            let first_lock = locks[0].clone();
             if resolve_locks(locks, pd_client.clone()).await? {
                 result = self.inner.execute().await?;
             } else {
+                let elapsed = start.elapsed();
                 match clone.backoff.next_delay_duration() {
-                    None => return Err(Error::ResolveLockError),
+                    None => {
+                        attempts.push(Attempt {
+                            error: first_lock.into(),
+                            delay: Duration::ZERO,
+                            elapsed,
+                        });
+                        return Err(exhausted(attempts));
+                    }
                     Some(delay_duration) => {
+                        attempts.push(Attempt {
+                            error: first_lock.into(),
+                            delay: delay_duration,
+                            elapsed,
+                        });
+                        observe_backoff(&*self.pd_client, RetryKind::Lock, &attempts);
                         futures_timer::Delay::new(delay_duration).await;
                         result = clone.inner.execute().await?;
                     }
@@ -254,6 +441,27 @@ where
             }
         }
     }
+
+    async fn retarget_leader(&mut self, pd_client: &Arc<impl PdClient>) -> Result<()> {
+        self.inner.retarget_leader(pd_client).await
+    }
+}
+
+/// Report the last of `attempts`, which is about to be retried, to the backoff observer if any.
+fn observe_backoff(pd_client: &impl PdClient, kind: RetryKind, attempts: &[Attempt]) {
+    if let (Some(observer), Some(attempt)) = (pd_client.backoff_observer(), attempts.last()) {
+        observer(kind, attempts.len() as u32, attempt.delay, &attempt.error);
+    }
+}
+
+/// Builds the error returned once a retrying plan runs out of attempts. If the
+/// request was never retried, the single error is returned unwrapped.
+fn exhausted(mut attempts: Vec<Attempt>) -> Error {
+    if attempts.len() == 1 {
+        attempts.pop().unwrap().error
+    } else {
+        Error::BackoffExhausted { attempts }
+    }
 }
 
 pub struct ExtractError<P: Plan> {
@@ -296,8 +504,9 @@ where
 mod test {
     use super::*;
     use crate::mock::{mock_store, MockPdClient};
-    use futures::stream::BoxStream;
-    use tikv_client_proto::kvrpcpb::BatchGetResponse;
+    use futures::stream::{self, BoxStream, TryStreamExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tikv_client_proto::kvrpcpb::{BatchGetResponse, KvPair};
 
     #[derive(Clone)]
     struct ErrPlan;
@@ -338,6 +547,7 @@ mod test {
                     pd_client: Arc::new(MockPdClient::default()),
                 },
                 pd_client: Arc::new(MockPdClient::default()),
+                limits: None,
             },
             backoff: Backoff::no_backoff(),
             pd_client: Arc::new(MockPdClient::default()),
@@ -348,4 +558,206 @@ mod test {
             .iter()
             .for_each(|r| assert!(r.is_err()));
     }
+
+    #[derive(Clone)]
+    struct RegionErrPlan;
+
+    #[async_trait]
+    impl Plan for RegionErrPlan {
+        type Result = BatchGetResponse;
+
+        async fn execute(&self) -> Result<Self::Result> {
+            Ok(BatchGetResponse {
+                region_error: Some(Default::default()),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backoff_exhausted() {
+        let plan = RetryRegion {
+            inner: RegionErrPlan,
+            backoff: Backoff::no_jitter_backoff(1, 1, 2),
+            pd_client: Arc::new(MockPdClient::default()),
+        };
+        match plan.execute().await {
+            Err(error @ Error::BackoffExhausted { .. }) => {
+                assert!(matches!(error.last_error(), Error::RegionError(_)));
+                let attempts = match error {
+                    Error::BackoffExhausted { attempts } => attempts,
+                    _ => unreachable!(),
+                };
+                assert_eq!(attempts.len(), 3);
+                assert_eq!(attempts[0].delay, Duration::from_millis(1));
+                assert_eq!(attempts[2].delay, Duration::ZERO);
+                assert!(attempts[1].elapsed <= attempts[2].elapsed);
+                assert!(attempts
+                    .iter()
+                    .all(|attempt| matches!(attempt.error, Error::RegionError(_))));
+            }
+            _ => panic!("expected BackoffExhausted"),
+        }
+
+        let plan = RetryRegion {
+            inner: RegionErrPlan,
+            backoff: Backoff::no_backoff(),
+            pd_client: Arc::new(MockPdClient::default()),
+        };
+        assert!(matches!(plan.execute().await, Err(Error::RegionError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_backoff_observer() {
+        let observed = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let observer = observed.clone();
+        let pd_client = MockPdClient::default().with_backoff_observer(Arc::new(
+            move |kind, attempt, delay, error: &Error| {
+                assert!(matches!(error, Error::RegionError(_)));
+                observer.lock().unwrap().push((kind, attempt, delay));
+            },
+        ));
+        let plan = RetryRegion {
+            inner: RegionErrPlan,
+            backoff: Backoff::no_jitter_backoff(1, 1, 2),
+            pd_client: Arc::new(pd_client),
+        };
+        assert!(plan.execute().await.is_err());
+        // The final attempt isn't retried.
+        assert_eq!(
+            *observed.lock().unwrap(),
+            vec![
+                (RetryKind::Region, 1, Duration::from_millis(1)),
+                (RetryKind::Region, 2, Duration::from_millis(1))
+            ]
+        );
+    }
+
+    /// A plan failing with `NotLeader` errors naming a new leader a number of times.
+    #[derive(Clone)]
+    struct NotLeaderPlan {
+        failures: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Plan for NotLeaderPlan {
+        type Result = BatchGetResponse;
+
+        async fn execute(&self) -> Result<Self::Result> {
+            let mut response = BatchGetResponse::default();
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                response
+                    .mut_region_error()
+                    .mut_not_leader()
+                    .mut_leader()
+                    .set_store_id(2);
+            }
+            Ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_leader_hint() {
+        let plan = |failures: usize| RetryRegion {
+            inner: NotLeaderPlan {
+                failures: Arc::new(failures.into()),
+            },
+            backoff: Backoff::no_backoff(),
+            pd_client: Arc::new(MockPdClient::default()),
+        };
+        // Retried right away without backing off.
+        assert!(plan(MAX_LEADER_HINT_RETRIES).execute().await.is_ok());
+        assert!(matches!(
+            plan(MAX_LEADER_HINT_RETRIES + 1).execute().await,
+            Err(Error::BackoffExhausted { attempts }) if attempts.len() == MAX_LEADER_HINT_RETRIES + 1
+        ));
+    }
+
+    #[derive(Clone)]
+    struct BusyPlan;
+
+    #[async_trait]
+    impl Plan for BusyPlan {
+        type Result = BatchGetResponse;
+
+        async fn execute(&self) -> Result<Self::Result> {
+            let mut response = BatchGetResponse::default();
+            response.mut_region_error().mut_server_is_busy().backoff_ms = 3;
+            Ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_after() {
+        let plan = RetryRegion {
+            inner: BusyPlan,
+            backoff: Backoff::no_jitter_backoff(100, 1000, 2),
+            pd_client: Arc::new(MockPdClient::default()),
+        };
+        match plan.execute().await {
+            Err(Error::BackoffExhausted { attempts }) => {
+                assert_eq!(attempts[0].delay, Duration::from_millis(3));
+                assert_eq!(attempts[1].delay, Duration::from_millis(3));
+            }
+            _ => panic!("expected BackoffExhausted"),
+        }
+    }
+
+    /// A plan whose later shards complete first.
+    #[derive(Clone)]
+    struct ShardedPlan {
+        shard: u8,
+    }
+
+    #[async_trait]
+    impl Plan for ShardedPlan {
+        type Result = BatchGetResponse;
+
+        async fn execute(&self) -> Result<Self::Result> {
+            futures_timer::Delay::new(Duration::from_millis(10 * (4 - self.shard) as u64)).await;
+            Ok(BatchGetResponse {
+                pairs: vec![KvPair {
+                    key: vec![self.shard],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+        }
+    }
+
+    impl Shardable for ShardedPlan {
+        type Shard = u8;
+
+        fn shards(
+            &self,
+            _: &Arc<impl crate::pd::PdClient>,
+        ) -> BoxStream<'static, crate::Result<(Self::Shard, crate::store::Store)>> {
+            stream::iter(1..=4)
+                .map(|shard| Ok((shard, mock_store())))
+                .boxed()
+        }
+
+        fn apply_shard(&mut self, shard: Self::Shard, _: &crate::store::Store) -> Result<()> {
+            self.shard = shard;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multi_region_order() {
+        let plan = MultiRegion {
+            inner: ShardedPlan { shard: 0 },
+            pd_client: Arc::new(MockPdClient::default()),
+            limits: None,
+        };
+        let keys: Vec<Vec<u8>> = plan
+            .execute()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|response| response.unwrap().pairs[0].key.clone())
+            .collect();
+        assert_eq!(keys, vec![vec![1], vec![2], vec![3], vec![4]]);
+    }
 }
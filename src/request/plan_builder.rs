@@ -4,8 +4,8 @@ use crate::{
     backoff::Backoff,
     pd::PdClient,
     request::{
-        DefaultProcessor, Dispatch, ExtractError, KvRequest, Merge, MergeResponse, MultiRegion,
-        Plan, Process, ProcessResponse, ResolveLock, RetryRegion, Shardable,
+        BatchLimits, DefaultProcessor, Dispatch, ExtractError, KvRequest, Merge, MergeResponse,
+        MultiRegion, Plan, Process, ProcessResponse, ResolveLock, RetryRegion, Shardable,
     },
     store::Store,
     transaction::HasLocks,
@@ -31,11 +31,20 @@ impl PlanBuilderPhase for Targetted {}
 
 impl<PdC: PdClient, Req: KvRequest> PlanBuilder<PdC, Dispatch<Req>, NoTarget> {
     pub fn new(pd_client: Arc<PdC>, request: Req) -> Self {
+        let metadata = pd_client.metadata();
+        let hot_region_cache = pd_client.hot_region_cache();
+        let region_cache = pd_client.region_cache();
+        let request_units = pd_client.request_unit_recorder();
         PlanBuilder {
             pd_client,
             plan: Dispatch {
                 request,
                 kv_client: None,
+                metadata,
+                region_id: None,
+                hot_region_cache,
+                region_cache,
+                request_units,
             },
             phantom: PhantomData,
         }
@@ -131,11 +140,28 @@ where
 {
     /// Split the request into shards sending a request to the region of each shard.
     pub fn multi_region(self) -> PlanBuilder<PdC, MultiRegion<P, PdC>, Targetted> {
+        self.multi_region_inner(None)
+    }
+
+    /// Split the request into shards like [`multi_region`](PlanBuilder::multi_region), further
+    /// splitting the shard of each region into requests within `limits`.
+    pub fn multi_region_with_limits(
+        self,
+        limits: BatchLimits,
+    ) -> PlanBuilder<PdC, MultiRegion<P, PdC>, Targetted> {
+        self.multi_region_inner(Some(limits))
+    }
+
+    fn multi_region_inner(
+        self,
+        limits: Option<BatchLimits>,
+    ) -> PlanBuilder<PdC, MultiRegion<P, PdC>, Targetted> {
         PlanBuilder {
             pd_client: self.pd_client.clone(),
             plan: MultiRegion {
                 inner: self.plan,
                 pd_client: self.pd_client,
+                limits,
             },
             phantom: PhantomData,
         }
@@ -179,7 +205,8 @@ fn set_single_region_store<PdC: PdClient, R: KvRequest>(
     store: Store,
     pd_client: Arc<PdC>,
 ) -> Result<PlanBuilder<PdC, Dispatch<R>, Targetted>> {
-    plan.request.set_context(store.region.context()?);
+    plan.request.set_context(store.context()?);
+    plan.region_id = Some(store.region.id());
     plan.kv_client = Some(store.client);
     Ok(PlanBuilder {
         plan,
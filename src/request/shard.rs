@@ -9,6 +9,41 @@ use crate::{
 use futures::stream::BoxStream;
 use std::sync::Arc;
 
+/// Limits on the size of a single request sent to a region, so that requests with many or
+/// large keys and values don't exceed the size limits of gRPC messages and raft entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchLimits {
+    /// The maximum number of keys in a request.
+    pub max_keys: usize,
+    /// The maximum size of the keys and values of a request in bytes. A single key and value
+    /// larger than this are sent alone.
+    pub max_bytes: usize,
+}
+
+impl BatchLimits {
+    /// Splits `items` into batches within the limits, keeping their order.
+    pub fn split<T>(&self, items: Vec<T>, size: impl Fn(&T) -> usize) -> Vec<Vec<T>> {
+        let mut batches = Vec::new();
+        let mut batch = Vec::new();
+        let mut batch_size = 0;
+        for item in items {
+            let item_size = size(&item);
+            if !batch.is_empty()
+                && (batch.len() >= self.max_keys || batch_size + item_size > self.max_bytes)
+            {
+                batches.push(std::mem::take(&mut batch));
+                batch_size = 0;
+            }
+            batch_size += item_size;
+            batch.push(item);
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        batches
+    }
+}
+
 pub trait Shardable {
     type Shard: Send;
 
@@ -18,6 +53,15 @@ pub trait Shardable {
     ) -> BoxStream<'static, Result<(Self::Shard, Store)>>;
 
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()>;
+
+    /// Splits a shard into shards within `limits`. Shards are not split by default.
+    fn split_shard(shard: Self::Shard, _limits: &BatchLimits) -> Vec<Self::Shard> {
+        vec![shard]
+    }
+
+    /// Clears the data which [`apply_shard`](Shardable::apply_shard) replaces, such as the keys
+    /// of the request, so that the copy of the request made for each shard doesn't copy them.
+    fn clear_sharded_data(&mut self) {}
 }
 
 impl<Req: KvRequest + Shardable> Shardable for Dispatch<Req> {
@@ -32,8 +76,17 @@ impl<Req: KvRequest + Shardable> Shardable for Dispatch<Req> {
 
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
         self.kv_client = Some(store.client.clone());
+        self.region_id = Some(store.region.id());
         self.request.apply_shard(shard, store)
     }
+
+    fn split_shard(shard: Self::Shard, limits: &BatchLimits) -> Vec<Self::Shard> {
+        Req::split_shard(shard, limits)
+    }
+
+    fn clear_sharded_data(&mut self) {
+        self.request.clear_sharded_data()
+    }
 }
 
 impl<P: Plan + Shardable, PdC: PdClient> Shardable for ResolveLock<P, PdC> {
@@ -49,6 +102,14 @@ impl<P: Plan + Shardable, PdC: PdClient> Shardable for ResolveLock<P, PdC> {
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
         self.inner.apply_shard(shard, store)
     }
+
+    fn split_shard(shard: Self::Shard, limits: &BatchLimits) -> Vec<Self::Shard> {
+        P::split_shard(shard, limits)
+    }
+
+    fn clear_sharded_data(&mut self) {
+        self.inner.clear_sharded_data()
+    }
 }
 
 impl<P: Plan + Shardable, PdC: PdClient> Shardable for RetryRegion<P, PdC> {
@@ -64,6 +125,14 @@ impl<P: Plan + Shardable, PdC: PdClient> Shardable for RetryRegion<P, PdC> {
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
         self.inner.apply_shard(shard, store)
     }
+
+    fn split_shard(shard: Self::Shard, limits: &BatchLimits) -> Vec<Self::Shard> {
+        P::split_shard(shard, limits)
+    }
+
+    fn clear_sharded_data(&mut self) {
+        self.inner.clear_sharded_data()
+    }
 }
 
 #[macro_export]
@@ -89,10 +158,21 @@ macro_rules! shardable_keys {
                 shard: Self::Shard,
                 store: &crate::store::Store,
             ) -> crate::Result<()> {
-                self.set_context(store.region.context()?);
+                self.set_context(store.context()?);
                 self.set_keys(shard.into_iter().map(Into::into).collect());
                 Ok(())
             }
+
+            fn split_shard(
+                shard: Self::Shard,
+                limits: &crate::request::BatchLimits,
+            ) -> Vec<Self::Shard> {
+                limits.split(shard, |key| key.len())
+            }
+
+            fn clear_sharded_data(&mut self) {
+                self.keys.clear();
+            }
         }
     };
 }
@@ -117,7 +197,7 @@ macro_rules! shardable_range {
                 shard: Self::Shard,
                 store: &crate::store::Store,
             ) -> crate::Result<()> {
-                self.set_context(store.region.context()?);
+                self.set_context(store.context()?);
 
                 self.set_start_key(shard.0.into());
                 self.set_end_key(shard.1.into());
@@ -126,3 +206,22 @@ macro_rules! shardable_range {
         }
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_batch_limits_split() {
+        let limits = BatchLimits {
+            max_keys: 3,
+            max_bytes: 10,
+        };
+        let split = limits.split(vec![4, 4, 4, 20, 1, 1, 1, 1], |size| *size);
+        assert_eq!(
+            split,
+            vec![vec![4, 4], vec![4], vec![20], vec![1, 1, 1], vec![1]]
+        );
+        assert!(limits.split(Vec::<usize>::new(), |size| *size).is_empty());
+    }
+}
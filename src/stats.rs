@@ -68,6 +68,11 @@ pub fn pd_stats(cmd: &'static str) -> RequestStats {
     )
 }
 
+/// Record how long the request to one shard of a multi-region request took, including retries.
+pub fn observe_shard_duration(duration: Duration) {
+    TIKV_SHARD_DURATION_HISTOGRAM.observe(duration_to_sec(duration));
+}
+
 #[allow(dead_code)]
 pub fn observe_tso_batch(batch_size: usize) {
     PD_TSO_BATCH_SIZE_HISTOGRAM.observe(batch_size as f64);
@@ -98,6 +103,11 @@ lazy_static::lazy_static! {
         &["type"]
     )
     .unwrap();
+    static ref TIKV_SHARD_DURATION_HISTOGRAM: Histogram = register_histogram!(
+        "tikv_shard_duration_seconds",
+        "Bucketed histogram of the duration of each region of multi-region requests"
+    )
+    .unwrap();
     static ref PD_REQUEST_DURATION_HISTOGRAM_VEC: HistogramVec = register_histogram_vec!(
         "pd_request_duration_seconds",
         "Bucketed histogram of PD requests duration",
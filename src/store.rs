@@ -3,22 +3,71 @@
 use crate::{pd::PdClient, BoundRange, Key, Region, Result};
 use derive_new::new;
 use futures::{prelude::*, stream::BoxStream};
-use std::{
-    cmp::{max, min},
-    sync::Arc,
-};
-use tikv_client_proto::kvrpcpb;
+use std::sync::Arc;
+use tikv_client_proto::{kvrpcpb, metapb};
 use tikv_client_store::{KvClient, KvConnect, TikvConnect};
 
-#[derive(new)]
+#[derive(new, Clone)]
 pub struct Store {
     pub region: Region,
     pub client: Arc<dyn KvClient + Send + Sync>,
+    /// Tags the requests sent to the store, so TiKV can attribute their load.
+    #[new(default)]
+    pub request_source: Option<String>,
+    /// The resource group the requests sent to the store are charged to, if not the default.
+    #[new(default)]
+    pub resource_group: Option<String>,
+    /// The follower or learner read from instead of the leader, if any.
+    #[new(default)]
+    pub replica: Option<metapb::Peer>,
+    /// The priority of the requests sent to the store, if not the default.
+    #[new(default)]
+    pub priority: Option<kvrpcpb::CommandPri>,
+    /// The isolation level of reads from the store, if not snapshot isolation.
+    #[new(default)]
+    pub isolation_level: Option<kvrpcpb::IsolationLevel>,
+    /// The API version of the requests sent to the store, if not V1.
+    #[new(default)]
+    pub api_version: Option<kvrpcpb::ApiVersion>,
+}
+
+impl Store {
+    /// The context of requests to the region of the store.
+    pub fn context(&self) -> Result<kvrpcpb::Context> {
+        let mut context = self.region.context()?;
+        if let Some(source) = &self.request_source {
+            context.set_request_source(source.clone());
+        }
+        if let Some(group) = &self.resource_group {
+            context
+                .mut_resource_control_context()
+                .set_resource_group_name(group.clone());
+        }
+        if let Some(replica) = &self.replica {
+            context.set_peer(replica.clone());
+            context.set_replica_read(true);
+        }
+        if let Some(priority) = self.priority {
+            context.set_priority(priority);
+        }
+        if let Some(isolation_level) = self.isolation_level {
+            context.set_isolation_level(isolation_level);
+        }
+        if let Some(api_version) = self.api_version {
+            context.set_api_version(api_version);
+        }
+        Ok(context)
+    }
 }
 
 pub trait KvConnectStore: KvConnect {
     fn connect_to_store(&self, region: Region, address: String) -> Result<Store> {
-        info!("connect to tikv endpoint: {:?}", &address);
+        info!(
+            target: "tikv_client::store",
+            region_id = region.id(),
+            address = address.as_str(),
+            "connecting to store"
+        );
         let client = self.connect(address.as_str())?;
         Ok(Store::new(region, Arc::new(client)))
     }
@@ -52,16 +101,12 @@ pub fn store_stream_for_range<PdC: PdClient>(
     range: (Vec<u8>, Vec<u8>),
     pd_client: Arc<PdC>,
 ) -> BoxStream<'static, Result<((Vec<u8>, Vec<u8>), Store)>> {
-    let bnd_range = BoundRange::from(range.clone());
+    let bnd_range = BoundRange::from(range);
     pd_client
-        .stores_for_range(bnd_range)
+        .stores_for_range(bnd_range.clone())
         .map_ok(move |store| {
-            let region_range = store.region.range();
-            let result_range = range_intersection(
-                region_range,
-                (range.0.clone().into(), range.1.clone().into()),
-            );
-            ((result_range.0.into(), result_range.1.into()), store)
+            let (start, end) = range_intersection(store.region.range(), &bnd_range);
+            ((start.into(), end.unwrap_or_default().into()), store)
         })
         .boxed()
 }
@@ -70,32 +115,25 @@ pub fn store_stream_for_range_by_start_key<PdC: PdClient>(
     start_key: Vec<u8>,
     pd_client: Arc<PdC>,
 ) -> BoxStream<'static, Result<(Vec<u8>, Store)>> {
-    let bnd_range = BoundRange::range_from(start_key.clone().into());
+    let bnd_range = BoundRange::range_from(start_key.into());
     pd_client
-        .stores_for_range(bnd_range)
+        .stores_for_range(bnd_range.clone())
         .map_ok(move |store| {
-            let region_range = store.region.range();
-            (
-                range_intersection(region_range, (start_key.clone().into(), vec![].into()))
-                    .0
-                    .into(),
-                store,
-            )
+            let (start, _) = range_intersection(store.region.range(), &bnd_range);
+            (start.into(), store)
         })
         .boxed()
 }
 
-/// The range used for request should be the intersection of `region_range` and `range`.
-fn range_intersection(region_range: (Key, Key), range: (Key, Key)) -> (Key, Key) {
-    let (lower, upper) = region_range;
-    let up = if upper.is_empty() {
-        range.1
-    } else if range.1.is_empty() {
-        upper
-    } else {
-        min(upper, range.1)
-    };
-    (max(lower, range.0), up)
+/// The range used for request should be the intersection of `region_range` and `range`, as start
+/// and end keys. The regions of a range overlap it, but an empty range is mapped to the start of
+/// the region.
+fn range_intersection(region_range: (Key, Key), range: &BoundRange) -> (Key, Option<Key>) {
+    let region_start = region_range.0.clone();
+    match BoundRange::from(region_range).intersection(range) {
+        Some(range) => range.into_keys(),
+        None => (region_start.clone(), Some(region_start)),
+    }
 }
 
 pub fn store_stream_for_ranges<PdC: PdClient>(
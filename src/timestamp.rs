@@ -4,7 +4,11 @@
 //! The lower 18 (PHYSICAL_SHIFT_BITS) bits are the logical part of the timestamp.
 //! The higher bits of the version are the physical part of the timestamp.
 
-use std::convert::TryInto;
+use std::{
+    cmp::Ordering,
+    convert::TryInto,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 pub use tikv_client_proto::pdpb::Timestamp;
 
 const PHYSICAL_SHIFT_BITS: i64 = 18;
@@ -14,6 +18,24 @@ const LOGICAL_MASK: i64 = (1 << PHYSICAL_SHIFT_BITS) - 1;
 ///
 /// Currently the only implmentation of this trait is [`Timestamp`](Timestamp) in TiKV.
 /// It contains a physical part (first 46 bits) and a logical part (last 18 bits).
+///
+/// The physical part is the wall-clock time of the timestamp oracle in milliseconds since the
+/// Unix epoch, and can be converted to and from a [`SystemTime`](SystemTime), e.g. to tell how
+/// stale a read is.
+///
+/// # Examples
+/// ```rust
+/// # use tikv_client::{Timestamp, TimestampExt};
+/// # use std::time::Duration;
+/// let ts = Timestamp::compose(1_600_000_000_000, 3);
+/// assert_eq!(ts.version(), (1_600_000_000_000 << 18) + 3);
+/// assert_eq!(Timestamp::from_version(ts.version()).decompose(), (1_600_000_000_000, 3));
+///
+/// // The timestamp of ten minutes earlier, e.g. a GC safe point.
+/// let earlier = ts.saturating_sub(Duration::from_secs(600));
+/// assert!(earlier.is_before(&ts));
+/// assert_eq!(ts.duration_since(&earlier), Duration::from_secs(600));
+/// ```
 pub trait TimestampExt: Sized {
     /// Convert the timestamp to u64.
     fn version(&self) -> u64;
@@ -21,6 +43,28 @@ pub trait TimestampExt: Sized {
     fn from_version(version: u64) -> Self;
     /// Convert u64 to an optional timestamp, where `0` represents no timestamp.
     fn try_from_version(version: u64) -> Option<Self>;
+    /// Compose a timestamp of physical time in milliseconds and a logical counter.
+    ///
+    /// Panics if `logical` doesn't fit in the 18 logical bits.
+    fn compose(physical: i64, logical: i64) -> Self;
+    /// The physical time in milliseconds and the logical counter of the timestamp.
+    fn decompose(&self) -> (i64, i64);
+    /// The physical time of the timestamp. Times before the Unix epoch are clamped to it.
+    fn to_system_time(&self) -> SystemTime;
+    /// The first timestamp of a physical time, truncated to milliseconds. Times before the Unix
+    /// epoch are clamped to it.
+    fn from_system_time(time: SystemTime) -> Self;
+    /// Compare timestamps in the order of their versions.
+    fn cmp_version(&self, other: &Self) -> Ordering;
+    /// Whether the timestamp is ordered before `other`.
+    fn is_before(&self, other: &Self) -> bool {
+        self.cmp_version(other) == Ordering::Less
+    }
+    /// The physical time elapsed from `earlier` to the timestamp, or zero if `earlier` is later.
+    fn duration_since(&self, earlier: &Self) -> Duration;
+    /// The first timestamp of the physical time `duration` before the timestamp, not earlier than
+    /// the Unix epoch.
+    fn saturating_sub(&self, duration: Duration) -> Self;
 }
 
 impl TimestampExt for Timestamp {
@@ -45,4 +89,77 @@ impl TimestampExt for Timestamp {
             Some(Self::from_version(version))
         }
     }
+
+    fn compose(physical: i64, logical: i64) -> Self {
+        assert!(
+            (0..=LOGICAL_MASK).contains(&logical),
+            "logical part {} of timestamp is out of range",
+            logical
+        );
+        Self { physical, logical }
+    }
+
+    fn decompose(&self) -> (i64, i64) {
+        (self.physical, self.logical)
+    }
+
+    fn to_system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.physical.max(0) as u64)
+    }
+
+    fn from_system_time(time: SystemTime) -> Self {
+        let physical = time
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as i64)
+            .unwrap_or(0);
+        Self::compose(physical, 0)
+    }
+
+    fn cmp_version(&self, other: &Self) -> Ordering {
+        self.decompose().cmp(&other.decompose())
+    }
+
+    fn duration_since(&self, earlier: &Self) -> Duration {
+        Duration::from_millis((self.physical - earlier.physical).max(0) as u64)
+    }
+
+    fn saturating_sub(&self, duration: Duration) -> Self {
+        let millis = duration.as_millis().min(i64::MAX as u128) as i64;
+        Self::compose(self.physical.saturating_sub(millis).max(0), 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_system_time() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_600_000_000_123);
+        let ts = Timestamp::from_system_time(time + Duration::from_micros(456));
+        assert_eq!(ts.decompose(), (1_600_000_000_123, 0));
+        assert_eq!(ts.to_system_time(), time);
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(Timestamp::from_system_time(before_epoch).version(), 0);
+        assert_eq!(
+            Timestamp::from_version(5).saturating_sub(Duration::from_secs(1)),
+            Timestamp::compose(0, 0)
+        );
+    }
+
+    #[test]
+    fn test_cmp_version() {
+        let ts = |physical, logical| Timestamp::compose(physical, logical);
+        assert!(ts(1, LOGICAL_MASK).is_before(&ts(2, 0)));
+        assert!(ts(2, 0).is_before(&ts(2, 1)));
+        assert!(!ts(2, 1).is_before(&ts(2, 1)));
+        assert_eq!(ts(1, 5).duration_since(&ts(3, 0)), Duration::ZERO);
+        assert_eq!(ts(1, 5).version(), (1 << 18) + 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_logical() {
+        let _ = Timestamp::compose(1, 1 << 18);
+    }
 }
@@ -1,8 +1,9 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use crate::{BoundRange, Key, KvPair, Result, Value};
+use super::WriterId;
+use crate::{BoundRange, Error, Key, KvPair, Result, Value};
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{hash_map::Entry, BTreeMap, HashMap},
     future::Future,
 };
 use tikv_client_proto::kvrpcpb;
@@ -12,6 +13,8 @@ use tokio::sync::{Mutex, MutexGuard};
 struct InnerBuffer {
     primary_key: Option<Key>,
     entry_map: BTreeMap<Key, BufferEntry>,
+    // The writer of each key written by a writer.
+    writers: HashMap<Key, WriterId>,
 }
 
 impl InnerBuffer {
@@ -26,6 +29,16 @@ impl InnerBuffer {
     pub fn get_primary_key_or(&mut self, key: &Key) -> &Key {
         self.primary_key.get_or_insert(key.clone())
     }
+
+    /// Fails with [`KeyFlushed`](Error::KeyFlushed) if any of `keys` was flushed.
+    fn check_not_flushed<'a>(&self, mut keys: impl Iterator<Item = &'a Key>) -> Result<()> {
+        match keys.find(|key| matches!(self.entry_map.get(key), Some(BufferEntry::Flushed))) {
+            Some(key) => Err(Error::KeyFlushed {
+                key: key.clone().into(),
+            }),
+            None => Ok(()),
+        }
+    }
 }
 
 /// A caching layer which buffers reads and writes in a transaction.
@@ -44,6 +57,48 @@ impl Buffer {
         self.mutations.lock().await.get_primary_key_or(key).clone()
     }
 
+    /// Forget the primary key, so the next locked or mutated key becomes the primary key.
+    pub async fn reset_primary_key(&self) {
+        self.mutations.lock().await.primary_key = None;
+    }
+
+    /// Fails with [`KeyFlushed`](Error::KeyFlushed) if `key` was flushed.
+    pub async fn check_not_flushed(&self, key: &Key) -> Result<()> {
+        self.mutations
+            .lock()
+            .await
+            .check_not_flushed(std::iter::once(key))
+    }
+
+    /// Record that the mutations of `keys` were flushed to TiKV, dropping their values.
+    pub async fn mark_flushed(&self, keys: impl IntoIterator<Item = Key>) {
+        let mut mutations = self.mutations.lock().await;
+        for key in keys {
+            mutations.entry_map.insert(key, BufferEntry::Flushed);
+        }
+    }
+
+    /// The keys whose mutations were flushed to TiKV.
+    pub async fn flushed_keys(&self) -> Vec<Key> {
+        self.mutations
+            .lock()
+            .await
+            .entry_map
+            .iter()
+            .filter(|(_, entry)| matches!(entry, BufferEntry::Flushed))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Whether the key is locked or mutated. In pessimistic transactions, these are the keys
+    /// holding pessimistic locks.
+    pub async fn is_locked(&self, key: &Key) -> bool {
+        matches!(
+            self.mutations.lock().await.entry_map.get(key),
+            Some(entry) if !matches!(entry, BufferEntry::Cached(_))
+        )
+    }
+
     /// Get a value from the buffer.
     /// If the returned value is None, it means the key doesn't exist in buffer yet.
     pub async fn get(&self, key: &Key) -> Option<Value> {
@@ -60,6 +115,7 @@ impl Buffer {
         F: FnOnce(Key) -> Fut,
         Fut: Future<Output = Result<Option<Value>>>,
     {
+        self.check_not_flushed(&key).await?;
         match self.get_from_mutations(&key).await {
             MutationValue::Determined(value) => Ok(value),
             MutationValue::Undetermined => {
@@ -71,6 +127,54 @@ impl Buffer {
         }
     }
 
+    /// Get a value written by the transaction. If the key isn't written, run `f` to read the
+    /// value, which isn't cached, as it may not be the value of the snapshot of the transaction.
+    pub async fn get_written_or_else<F, Fut>(&self, key: Key, f: F) -> Result<Option<Value>>
+    where
+        F: FnOnce(Key) -> Fut,
+        Fut: Future<Output = Result<Option<Value>>>,
+    {
+        self.check_not_flushed(&key).await?;
+        match self.get_written(&key).await {
+            MutationValue::Determined(value) => Ok(value),
+            MutationValue::Undetermined => f(key).await,
+        }
+    }
+
+    /// Get multiple values written by the transaction. If any keys aren't written, run `f` to
+    /// read their values, which aren't cached.
+    pub async fn batch_get_written_or_else<F, Fut>(
+        &self,
+        keys: impl Iterator<Item = Key>,
+        f: F,
+    ) -> Result<impl Iterator<Item = KvPair>>
+    where
+        F: FnOnce(Box<dyn Iterator<Item = Key>>) -> Fut,
+        Fut: Future<Output = Result<Vec<KvPair>>>,
+    {
+        let keys: Vec<Key> = keys.collect();
+        let mut written = Vec::new();
+        let mut unwritten = Vec::new();
+        {
+            let mutations = self.mutations.lock().await;
+            mutations.check_not_flushed(keys.iter())?;
+            for key in keys {
+                match mutations
+                    .entry_map
+                    .get(&key)
+                    .map(BufferEntry::get_written_value)
+                {
+                    Some(MutationValue::Determined(value)) => {
+                        written.extend(value.map(|value| KvPair(key, value)))
+                    }
+                    _ => unwritten.push(key),
+                }
+            }
+        }
+        let fetched = f(Box::new(unwritten.into_iter())).await?;
+        Ok(written.into_iter().chain(fetched))
+    }
+
     /// Get multiple values from the buffer. If any are not present, run `f` to
     /// get the missing values.
     ///
@@ -84,14 +188,17 @@ impl Buffer {
         F: FnOnce(Box<dyn Iterator<Item = Key>>) -> Fut,
         Fut: Future<Output = Result<Vec<KvPair>>>,
     {
+        let keys: Vec<Key> = keys.collect();
         let (cached_results, undetermined_keys) = {
             let mutations = self.mutations.lock().await;
+            mutations.check_not_flushed(keys.iter())?;
             // Partition the keys into those we have buffered and those we have to
             // get from the store.
             let (undetermined_keys, cached_results): (
                 Vec<(Key, MutationValue)>,
                 Vec<(Key, MutationValue)>,
             ) = keys
+                .into_iter()
                 .map(|key| {
                     let value = mutations
                         .entry_map
@@ -123,10 +230,14 @@ impl Buffer {
     }
 
     /// Run `f` to fetch entries in `range` from TiKV. Combine them with mutations in local buffer. Returns the results.
+    ///
+    /// The fetched entries are only cached if `cache` is set, i.e. unless `f` fetches keys without
+    /// values, or values other than those of the snapshot of the transaction.
     pub async fn scan_and_fetch<F, Fut>(
         &self,
         range: BoundRange,
         limit: u32,
+        cache: bool,
         f: F,
     ) -> Result<impl Iterator<Item = KvPair>>
     where
@@ -136,6 +247,7 @@ impl Buffer {
         // read from local buffer
         let mut mutations = self.mutations.lock().await;
         let mutation_range = mutations.entry_map.range(range.clone());
+        mutations.check_not_flushed(mutation_range.clone().map(|(key, _)| key))?;
 
         // fetch from TiKV
         // fetch more entries because some of them may be deleted.
@@ -165,8 +277,10 @@ impl Buffer {
         }
 
         // update local buffer
-        for (k, v) in &results {
-            Self::update_cache(&mut mutations, k.clone(), Some(v.clone()));
+        if cache {
+            for (k, v) in &results {
+                Self::update_cache(&mut mutations, k.clone(), Some(v.clone()));
+            }
         }
 
         let mut res = results
@@ -193,6 +307,32 @@ impl Buffer {
         }
     }
 
+    /// Check that `writer` may write `key`, without recording it. Fails if another writer wrote
+    /// it before.
+    pub async fn check_claim(&self, key: &Key, writer: Option<WriterId>) -> Result<()> {
+        match self.mutations.lock().await.writers.get(key) {
+            Some(owner) if Some(*owner) != writer => Err(local_write_conflict(key, *owner, writer)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record that `writer` writes `key`, or check that no writer wrote it if the write is made
+    /// without one. Fails if another writer wrote it before; the key stays with its first writer.
+    pub async fn claim(&self, key: &Key, writer: Option<WriterId>) -> Result<()> {
+        match self.mutations.lock().await.writers.entry(key.clone()) {
+            Entry::Occupied(owner) if Some(*owner.get()) != writer => {
+                Err(local_write_conflict(key, *owner.get(), writer))
+            }
+            Entry::Occupied(_) => Ok(()),
+            Entry::Vacant(entry) => {
+                if let Some(writer) = writer {
+                    entry.insert(writer);
+                }
+                Ok(())
+            }
+        }
+    }
+
     /// Insert a value into the buffer (does not write through).
     pub async fn put(&self, key: Key, value: Value) {
         self.mutations
@@ -248,6 +388,16 @@ impl Buffer {
             .unwrap_or(MutationValue::Undetermined)
     }
 
+    async fn get_written(&self, key: &Key) -> MutationValue {
+        self.mutations
+            .lock()
+            .await
+            .entry_map
+            .get(key)
+            .map(BufferEntry::get_written_value)
+            .unwrap_or(MutationValue::Undetermined)
+    }
+
     fn update_cache(buffer: &mut MutexGuard<InnerBuffer>, key: Key, value: Option<Value>) {
         match buffer.entry_map.get(&key) {
             Some(BufferEntry::Locked(None)) => {
@@ -273,6 +423,7 @@ impl Buffer {
             Some(BufferEntry::CheckNotExist) => {
                 assert!(value.is_none());
             }
+            Some(BufferEntry::Flushed) => {}
         }
     }
 }
@@ -316,13 +467,15 @@ enum BufferEntry {
     Insert(Value),
     // Key should be check not exists before.
     CheckNotExist,
+    // The mutation of the key has been prewritten by a flush, and its value dropped.
+    Flushed,
 }
 
 impl BufferEntry {
     fn to_proto_with_key(&self, key: &Key) -> Option<kvrpcpb::Mutation> {
         let mut pb = kvrpcpb::Mutation::default();
         match self {
-            BufferEntry::Cached(_) => return None,
+            BufferEntry::Cached(_) | BufferEntry::Flushed => return None,
             BufferEntry::Put(v) => {
                 pb.set_op(kvrpcpb::Op::Put);
                 pb.set_value(v.clone());
@@ -348,6 +501,17 @@ impl BufferEntry {
             BufferEntry::Locked(Some(value)) => MutationValue::Determined(value.clone()),
             BufferEntry::Insert(value) => MutationValue::Determined(Some(value.clone())),
             BufferEntry::CheckNotExist => MutationValue::Determined(None),
+            BufferEntry::Flushed => MutationValue::Undetermined,
+        }
+    }
+
+    /// The value of the entry if the transaction wrote it, ignoring the values read.
+    fn get_written_value(&self) -> MutationValue {
+        match self {
+            BufferEntry::Cached(_) | BufferEntry::Locked(_) | BufferEntry::CheckNotExist => {
+                MutationValue::Undetermined
+            }
+            _ => self.get_value(),
         }
     }
 }
@@ -370,6 +534,14 @@ impl MutationValue {
     }
 }
 
+fn local_write_conflict(key: &Key, owner: WriterId, writer: Option<WriterId>) -> Error {
+    Error::LocalWriteConflict {
+        key: key.clone().into(),
+        owner: owner.id(),
+        writer: writer.map_or(0, |writer| writer.id()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
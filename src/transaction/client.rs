@@ -1,16 +1,25 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use super::{requests::new_scan_lock_request, resolve_locks};
+use super::{
+    lowering::new_checksum_request,
+    requests::{new_scan_lock_request, new_split_region_request},
+    resolve_commit_status, resolve_locks,
+};
 use crate::{
-    backoff::{DEFAULT_REGION_BACKOFF, OPTIMISTIC_BACKOFF},
     config::Config,
+    features::Features,
     pd::{PdClient, PdRpcClient},
-    request::Plan,
+    request::{CollectError, Plan},
     timestamp::TimestampExt,
-    transaction::{Snapshot, Transaction, TransactionOptions},
-    Result,
+    transaction::{
+        hooks::{TransactionCallback, TransactionHooks},
+        Checksum, CommitStatus, LockInfo, ServiceSafepoint, Snapshot, Transaction,
+        TransactionEvent, TransactionOptions,
+    },
+    BoundRange, Cluster, Error, HotRegion, Key, Keyspace, ResourceGroup, Result, RetryKind,
+    StoreInfo, StoreStats,
 };
-use std::{mem, sync::Arc};
+use std::{collections::HashMap, mem, sync::Arc, time::Duration};
 use tikv_client_proto::{kvrpcpb, pdpb::Timestamp};
 
 const SCAN_LOCK_BATCH_SIZE: u32 = 1024; // FIXME: cargo-culted value
@@ -30,10 +39,17 @@ const SCAN_LOCK_BATCH_SIZE: u32 = 1024; // FIXME: cargo-culted value
 /// - `current_timestamp`: get the current `Timestamp`.
 /// - `snapshot`: get the [`Snapshot`](crate::transaction::Snapshot) of the database at a certain timestamp.
 /// A `Snapshot` is a read-only transaction.
+/// - `checksum`: get the [`Checksum`](crate::transaction::Checksum) of a range at a certain timestamp.
 ///
 /// The returned results of transactional requests are [`Future`](std::future::Future)s that must be awaited to execute.
+///
+/// Transactions are not supported on clusters configured with API V2: creating a client for such
+/// a cluster, or beginning a transaction with a client created from its [`Cluster`](Cluster),
+/// fails with [`UnsupportedFeature`](Error::UnsupportedFeature).
+#[derive(Clone)]
 pub struct Client {
     pd: Arc<PdRpcClient>,
+    hooks: TransactionHooks,
 }
 
 impl Client {
@@ -73,7 +89,232 @@ impl Client {
     ) -> Result<Client> {
         let pd_endpoints: Vec<String> = pd_endpoints.into_iter().map(Into::into).collect();
         let pd = Arc::new(PdRpcClient::connect(&pd_endpoints, &config, true).await?);
-        Ok(Client { pd })
+        check_features(pd.features())?;
+        Ok(Client {
+            pd,
+            hooks: TransactionHooks::default(),
+        })
+    }
+
+    /// Creates a transactional [`Client`](Client) which shares the connections of a
+    /// [`Cluster`](Cluster).
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::{Cluster, TransactionClient};
+    /// use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// let cluster = Cluster::connect(vec!["192.168.0.100"]).await.unwrap();
+    /// let client = TransactionClient::from_cluster(&cluster);
+    /// # });
+    /// ```
+    pub fn from_cluster(cluster: &Cluster) -> Client {
+        Client {
+            pd: Arc::new(cluster.rpc.with_codec(true)),
+            hooks: TransactionHooks::default(),
+        }
+    }
+
+    /// Send a gRPC metadata entry with every request to TiKV.
+    ///
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Requests of the new client, and of the transactions and snapshots it creates, carry the
+    /// entry, after any entries added before, so that server-side logging and middleboxes can
+    /// attribute traffic, e.g. to a tenant. The original `Client` can still be used.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_metadata("tenant-id", "42");
+    /// let txn = client
+    ///     .with_metadata("x-correlation-id", "abc")
+    ///     .begin_optimistic()
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub fn with_metadata(&self, key: impl Into<String>, value: impl Into<String>) -> Client {
+        let mut metadata = self.pd.metadata();
+        metadata.insert(key, value);
+        Client {
+            pd: Arc::new(self.pd.with_metadata(metadata)),
+            hooks: self.hooks.clone(),
+        }
+    }
+
+    /// Tag requests with a source, overriding the one set by
+    /// [`Config::with_request_source`](crate::Config::with_request_source).
+    ///
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Every request to TiKV of the new client, and of the transactions and snapshots it creates,
+    /// carries the tag, so TiKV can attribute its load to the source. The original `Client` can
+    /// still be used.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client
+    ///     .with_request_source("backfill")
+    ///     .begin_optimistic()
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub fn with_request_source(&self, source: impl Into<String>) -> Client {
+        Client {
+            pd: Arc::new(self.pd.with_request_source(source.into())),
+            hooks: self.hooks.clone(),
+        }
+    }
+
+    /// Charge requests to a resource group, overriding the one set by
+    /// [`Config::with_resource_group`](crate::Config::with_resource_group).
+    ///
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Every request to TiKV of the new client, and of the transactions and snapshots it creates,
+    /// is charged to the group, and throttled by its request units. The original `Client` can
+    /// still be used.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client
+    ///     .with_resource_group("tenant_a")
+    ///     .begin_optimistic()
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub fn with_resource_group(&self, group: impl Into<String>) -> Client {
+        Client {
+            pd: Arc::new(self.pd.with_resource_group(group.into())),
+            hooks: self.hooks.clone(),
+        }
+    }
+
+    /// Report the retries of requests, e.g. to record them in the metrics of the application.
+    ///
+    /// This function returns a new `Client` which shares its connections with the original one.
+    /// Before a request of the new client, or of the transactions and snapshots it creates, is
+    /// retried, `observer` is called like the one set by
+    /// [`RawClient::with_backoff_observer`](crate::RawClient::with_backoff_observer). The original
+    /// `Client` can still be used.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::{RetryKind, TransactionClient};
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .with_backoff_observer(|kind, attempt, _, _| {
+    ///         if kind == RetryKind::Lock && attempt > 5 {
+    ///             eprintln!("contended keys");
+    ///         }
+    ///     });
+    /// # });
+    /// ```
+    pub fn with_backoff_observer(
+        &self,
+        observer: impl Fn(RetryKind, u32, Duration, &Error) + Send + Sync + 'static,
+    ) -> Client {
+        Client {
+            pd: Arc::new(self.pd.with_backoff_observer(Arc::new(observer))),
+            hooks: self.hooks.clone(),
+        }
+    }
+
+    /// Call `callback` whenever a transaction of the client begins, e.g. to record it in an audit
+    /// log.
+    ///
+    /// This function returns a new `Client` which shares its connections with the original one,
+    /// with the callback replacing any set before. Callbacks are called for the transactions the
+    /// new client begins, but not for its snapshots. They are called synchronously by the
+    /// requests of the transaction, so they should be quick. The original `Client` can still be
+    /// used.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::{TimestampExt, TransactionClient};
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .on_begin(|event| eprintln!("began {}", event.start_timestamp().version()));
+    /// # });
+    /// ```
+    pub fn on_begin(&self, callback: impl Fn(&TransactionEvent) + Send + Sync + 'static) -> Client {
+        let callback: TransactionCallback = Arc::new(callback);
+        self.with_hooks(|hooks| hooks.on_begin = Some(callback))
+    }
+
+    /// Call `callback` once all keys of a transaction of the client are prewritten, before it is
+    /// committed, like [`on_begin`](Client::on_begin).
+    ///
+    /// The keys are locked, and the transaction is committed unless it then fails or is rolled
+    /// back. Transactions committed with 1PC are already committed when prewritten. Transactions
+    /// which wrote nothing aren't prewritten.
+    pub fn on_prewrite(
+        &self,
+        callback: impl Fn(&TransactionEvent) + Send + Sync + 'static,
+    ) -> Client {
+        let callback: TransactionCallback = Arc::new(callback);
+        self.with_hooks(|hooks| hooks.on_prewrite = Some(callback))
+    }
+
+    /// Call `callback` whenever a transaction of the client is committed, with its commit
+    /// timestamp, like [`on_begin`](Client::on_begin).
+    ///
+    /// The callback is called as soon as the transaction is committed, possibly before its
+    /// secondary keys are, so e.g. messages recorded by the transaction in an outbox can be
+    /// published. It is called with no commit timestamp for transactions which wrote nothing.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::{TimestampExt, TransactionClient};
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"])
+    ///     .await
+    ///     .unwrap()
+    ///     .on_commit(|event| {
+    ///         if let Some(commit_ts) = event.commit_timestamp() {
+    ///             eprintln!(
+    ///                 "committed {} keys at {}",
+    ///                 event.write_keys(),
+    ///                 commit_ts.version()
+    ///             );
+    ///         }
+    ///     });
+    /// # });
+    /// ```
+    pub fn on_commit(
+        &self,
+        callback: impl Fn(&TransactionEvent) + Send + Sync + 'static,
+    ) -> Client {
+        let callback: TransactionCallback = Arc::new(callback);
+        self.with_hooks(|hooks| hooks.on_commit = Some(callback))
+    }
+
+    /// Call `callback` whenever a transaction of the client is rolled back, like
+    /// [`on_begin`](Client::on_begin).
+    ///
+    /// This includes commits which were
+    /// [cancelled](crate::Transaction::commit_with_cancellation), but not transactions which
+    /// failed to commit or were dropped.
+    pub fn on_rollback(
+        &self,
+        callback: impl Fn(&TransactionEvent) + Send + Sync + 'static,
+    ) -> Client {
+        let callback: TransactionCallback = Arc::new(callback);
+        self.with_hooks(|hooks| hooks.on_rollback = Some(callback))
     }
 
     /// Creates a new [`Transaction`](Transaction) in optimistic mode.
@@ -97,6 +338,7 @@ impl Client {
     /// # });
     /// ```
     pub async fn begin_optimistic(&self) -> Result<Transaction> {
+        check_features(self.pd.features())?;
         let timestamp = self.current_timestamp().await?;
         Ok(self.new_transaction(timestamp, TransactionOptions::new_optimistic()))
     }
@@ -119,6 +361,7 @@ impl Client {
     /// # });
     /// ```
     pub async fn begin_pessimistic(&self) -> Result<Transaction> {
+        check_features(self.pd.features())?;
         let timestamp = self.current_timestamp().await?;
         Ok(self.new_transaction(timestamp, TransactionOptions::new_pessimistic()))
     }
@@ -141,13 +384,89 @@ impl Client {
     /// # });
     /// ```
     pub async fn begin_with_options(&self, options: TransactionOptions) -> Result<Transaction> {
+        check_features(self.pd.features())?;
         let timestamp = self.current_timestamp().await?;
         Ok(self.new_transaction(timestamp, options))
     }
 
     /// Creates a new [`Snapshot`](Snapshot) at the given [`Timestamp`](Timestamp).
     pub fn snapshot(&self, timestamp: Timestamp, options: TransactionOptions) -> Snapshot {
-        Snapshot::new(self.new_transaction(timestamp, options.read_only()))
+        let pd = if options.replica_read {
+            Arc::new(self.pd.with_replica_read(true))
+        } else {
+            self.pd.clone()
+        };
+        Snapshot::new(Transaction::new(timestamp, pd, options.read_only()))
+    }
+
+    /// Creates a new [`Snapshot`](Snapshot) at a timestamp given by the user, e.g. one recorded
+    /// earlier to read the data as of then, or to diff against a later snapshot.
+    ///
+    /// Unlike [`snapshot`](Client::snapshot), the timestamp is checked against the GC safepoint of
+    /// the cluster, and a [`TimestampBeforeSafepoint`](Error::TimestampBeforeSafepoint) error is
+    /// returned if it is older, as the versions it would read may have been garbage collected.
+    /// The snapshot is only consistent while the safepoint stays behind the timestamp, i.e. for
+    /// the GC life time of the cluster.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::{TransactionClient, TransactionOptions};
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let timestamp = client.current_timestamp().await.unwrap();
+    /// // ...
+    /// let mut snapshot = client
+    ///     .snapshot_at(timestamp, TransactionOptions::new_optimistic())
+    ///     .await
+    ///     .unwrap();
+    /// let value = snapshot.get("TiKV".to_owned()).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn snapshot_at(
+        &self,
+        timestamp: Timestamp,
+        options: TransactionOptions,
+    ) -> Result<Snapshot> {
+        check_features(self.pd.features())?;
+        let safepoint = self.pd.clone().get_safepoint().await?;
+        let version = timestamp.version();
+        if version < safepoint {
+            return Err(Error::TimestampBeforeSafepoint { version, safepoint });
+        }
+        Ok(self.snapshot(timestamp, options))
+    }
+
+    /// Keep GC from removing the versions a snapshot at `timestamp` reads, while the returned
+    /// [`ServiceSafepoint`](ServiceSafepoint) is alive, e.g. during a long scan.
+    ///
+    /// A service safepoint is registered in PD as `service_id`, with the given TTL, and refreshed
+    /// in the background. Jobs running concurrently should use distinct service IDs. Returns a
+    /// [`TimestampBeforeSafepoint`](Error::TimestampBeforeSafepoint) error if GC already passed
+    /// the timestamp.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::{TransactionClient, TransactionOptions};
+    /// use std::time::Duration;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let timestamp = client.current_timestamp().await.unwrap();
+    /// let safepoint = client
+    ///     .hold_safepoint("analytics", timestamp.clone(), Duration::from_secs(300))
+    ///     .await
+    ///     .unwrap();
+    /// let mut snapshot = client.snapshot(timestamp, TransactionOptions::new_optimistic());
+    /// // ... a long scan of the snapshot ...
+    /// safepoint.release().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn hold_safepoint(
+        &self,
+        service_id: impl Into<String>,
+        timestamp: Timestamp,
+        ttl: Duration,
+    ) -> Result<ServiceSafepoint> {
+        ServiceSafepoint::register(self.pd.clone(), service_id.into(), timestamp, ttl).await
     }
 
     /// Retrieves the current [`Timestamp`](Timestamp).
@@ -165,6 +484,259 @@ impl Client {
         self.pd.clone().get_timestamp().await
     }
 
+    /// Load the regions of `ranges` and connect to their stores, so the first requests to them
+    /// don't wait for PD and for connections, e.g. before a latency-critical service takes
+    /// traffic. Returns the number of stores connected to.
+    ///
+    /// Fails if a store can't be connected to within the request timeout.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// client.warmup(vec!["user".to_owned().."uses".to_owned()]).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn warmup(
+        &self,
+        ranges: impl IntoIterator<Item = impl Into<BoundRange>>,
+    ) -> Result<usize> {
+        let ranges = ranges.into_iter().map(Into::into).collect();
+        self.pd.clone().warmup(ranges).await
+    }
+
+    /// The regions PD currently finds hot for reads or writes, to find hotspots to split.
+    ///
+    /// Hot regions are read from the HTTP API of the PD leader, over TLS if the client is
+    /// configured with it. Errors of requests to hot regions can be annotated with
+    /// [`Config::with_hot_region_diagnostics`](crate::Config::with_hot_region_diagnostics).
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// for region in client.hot_regions().await.unwrap() {
+    ///     println!("region {} is {}", region.region_id(), region);
+    /// }
+    /// # });
+    /// ```
+    pub async fn hot_regions(&self) -> Result<Vec<HotRegion>> {
+        self.pd.hot_regions().await
+    }
+
+    /// Create a keyspace, with the given configuration, in the keyspace service of PD, e.g. to
+    /// provision a tenant.
+    ///
+    /// Keyspaces are created through the HTTP API of the PD leader, as the keyspace service of PD
+    /// has no RPC to create them.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::TransactionClient;
+    /// # use std::collections::HashMap;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let keyspace = client.create_keyspace("tenant_a", HashMap::new()).await.unwrap();
+    /// println!("created keyspace {} with ID {}", keyspace.name(), keyspace.id());
+    /// for keyspace in client.keyspaces().await.unwrap() {
+    ///     println!("keyspace {} is {}", keyspace.name(), keyspace.state());
+    /// }
+    /// # });
+    /// ```
+    pub async fn create_keyspace(
+        &self,
+        name: &str,
+        config: HashMap<String, String>,
+    ) -> Result<Keyspace> {
+        self.pd.create_keyspace(name, &config).await
+    }
+
+    /// All keyspaces registered in PD, in the order of their IDs.
+    pub async fn keyspaces(&self) -> Result<Vec<Keyspace>> {
+        self.pd.keyspaces().await
+    }
+
+    /// The keyspace registered in PD as `name`.
+    pub async fn keyspace(&self, name: &str) -> Result<Keyspace> {
+        self.pd.keyspace(name).await
+    }
+
+    /// Create a resource group in the resource manager of PD, e.g. to limit the load of a tenant.
+    ///
+    /// Requests are charged to the group by
+    /// [`Config::with_resource_group`](crate::Config::with_resource_group) or
+    /// [`with_resource_group`](Client::with_resource_group).
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{TransactionClient, ResourceGroup};
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let group = ResourceGroup::new("tenant_a", 2000).with_burst_limit(4000);
+    /// client.create_resource_group(&group).await.unwrap();
+    /// let tenant_a = client.with_resource_group("tenant_a");
+    /// # });
+    /// ```
+    pub async fn create_resource_group(&self, group: &ResourceGroup) -> Result<()> {
+        self.pd.create_resource_group(group).await
+    }
+
+    /// Replace the settings of the resource group named like `group`.
+    pub async fn update_resource_group(&self, group: &ResourceGroup) -> Result<()> {
+        self.pd.update_resource_group(group).await
+    }
+
+    /// All resource groups registered in PD.
+    pub async fn resource_groups(&self) -> Result<Vec<ResourceGroup>> {
+        self.pd.resource_groups().await
+    }
+
+    /// The resource group registered in PD as `name`.
+    pub async fn resource_group(&self, name: &str) -> Result<ResourceGroup> {
+        self.pd.resource_group(name).await
+    }
+
+    /// Delete the resource group named `name`. Requests charged to it fall back to the default
+    /// group.
+    pub async fn delete_resource_group(&self, name: &str) -> Result<()> {
+        self.pd.delete_resource_group(name).await
+    }
+
+    /// The request units TiKV reports consumed by the requests of the client, and of the clients
+    /// sharing its connections, by the resource group they were charged to, e.g. to meter
+    /// tenants.
+    ///
+    /// Requests not charged to a group are counted under `default`. Only the transactional reads,
+    /// prewrites, commits and pessimistic locks report their request units.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// for (group, units) in client.request_units() {
+    ///     println!("{}: {:.1} RU", group, units);
+    /// }
+    /// # });
+    /// ```
+    pub fn request_units(&self) -> HashMap<String, f64> {
+        self.pd.request_units()
+    }
+
+    /// The statistics of the requests sent to each TiKV store, by store address.
+    ///
+    /// Statistics are shared by all clients created from the same connections, and cover the
+    /// stores connected to so far.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// for (address, stats) in client.store_stats() {
+    ///     println!("{}: {:.2} error rate", address, stats.error_rate());
+    /// }
+    /// # });
+    /// ```
+    pub fn store_stats(&self) -> HashMap<String, StoreStats> {
+        self.pd.store_stats()
+    }
+
+    /// The stores of the cluster, as registered in PD.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// for store in client.stores().await.unwrap() {
+    ///     println!("store {} at {}", store.id(), store.address());
+    /// }
+    /// # });
+    /// ```
+    pub async fn stores(&self) -> Result<Vec<StoreInfo>> {
+        self.pd.stores().await
+    }
+
+    /// Split `range` into at most `max_chunks` consecutive ranges made of whole regions, except
+    /// for the regions cut by the ends of `range`, with as even a number of regions per range as
+    /// possible.
+    ///
+    /// The ranges can be handed to parallel workers, each of which then reads from its own
+    /// regions. Regions may split or merge afterwards, which doesn't affect correctness.
+    pub async fn split_range_by_regions(
+        &self,
+        range: impl Into<BoundRange>,
+        max_chunks: usize,
+    ) -> Result<Vec<BoundRange>> {
+        let range = range.into();
+        let boundaries = self
+            .pd
+            .region_aligned_boundaries(range.clone(), max_chunks)
+            .await?;
+        Ok(range.split_at(boundaries))
+    }
+
+    /// Split the regions containing `split_keys` so that each key starts a region.
+    ///
+    /// Splitting a large range before loading it spreads the load over several regions from the
+    /// start, rather than waiting for TiKV to split them as they grow.
+    pub async fn split_region(
+        &self,
+        split_keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<()> {
+        let req = new_split_region_request(
+            split_keys
+                .into_iter()
+                .map(|key| key.into().into())
+                .collect(),
+        );
+        let plan = crate::request::PlanBuilder::new(self.pd.clone().leader_client(), req)
+            .multi_region()
+            .retry_region(self.pd.retry_options().region_backoff)
+            .merge(CollectError)
+            .plan();
+        plan.execute().await?;
+        Ok(())
+    }
+
+    /// Scan the locks in `range` of transactions which started before `max_ts`, returning at
+    /// most `limit` locks in key order.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let now = client.current_timestamp().await.unwrap();
+    /// for lock in client.scan_locks(now, .., 100).await.unwrap() {
+    ///     println!("{:?} locked by {:?}", lock.key(), lock.start_timestamp());
+    /// }
+    /// # });
+    /// ```
+    pub async fn scan_locks(
+        &self,
+        max_ts: Timestamp,
+        range: impl Into<BoundRange>,
+        limit: u32,
+    ) -> Result<Vec<LockInfo>> {
+        let locks = self.scan_locks_inner(max_ts, range.into(), limit).await?;
+        Ok(locks.into_iter().map(LockInfo).collect())
+    }
+
+    /// Resolve `locks` whose TTL has expired, committing or rolling back the keys they lock
+    /// depending on the status of their transactions. Returns whether all of them were resolved,
+    /// i.e. none was alive.
+    pub async fn resolve_locks(&self, locks: Vec<LockInfo>) -> Result<bool> {
+        resolve_locks(
+            locks.into_iter().map(|lock| lock.0).collect(),
+            self.pd.clone(),
+        )
+        .await
+    }
+
     /// Cleans MVCC records whose timestamp is lower than the given `timestamp` in TiKV.
     ///
     /// For each key, the last mutation record (unless it's a deletion) before `safepoint` is retained.
@@ -177,19 +749,121 @@ impl Client {
     /// We omit the second step "delete ranges" which is an optimization for TiDB.
     pub async fn gc(&self, safepoint: Timestamp) -> Result<bool> {
         // scan all locks with ts <= safepoint
+        let locks = self
+            .scan_locks_inner(
+                safepoint.clone(),
+                BoundRange::range_from(Key::EMPTY),
+                u32::MAX,
+            )
+            .await?;
+
+        // resolve locks
+        resolve_locks(locks, self.pd.clone()).await?;
+
+        // update safepoint to PD
+        let res: bool = self
+            .pd
+            .clone()
+            .update_safepoint(safepoint.version())
+            .await?;
+        if !res {
+            info!(
+                target: "tikv_client::txn",
+                safepoint = safepoint.version(),
+                "new safepoint != user-specified safepoint"
+            );
+        }
+        Ok(res)
+    }
+
+    /// Determine whether the transaction started at `start_ts` with primary key `primary` was
+    /// committed, e.g. after its commit failed with an
+    /// [`UndeterminedError`](crate::Error::UndeterminedError).
+    ///
+    /// A transaction whose primary key was never prewritten, or whose locks expired, is rolled
+    /// back, so it can't be committed later and the status returned is final. A transaction whose
+    /// locks are still alive may be committed or rolled back by its client, so its status should
+    /// be resolved again once they expire.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{CommitStatus, Error, TransactionClient};
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// txn.put("TiKV".to_owned(), "Rust".to_owned()).await.unwrap();
+    /// if let Err(Error::UndeterminedError(_)) = txn.commit().await {
+    ///     let primary = txn.primary_key().await.unwrap();
+    ///     let status = client
+    ///         .resolve_commit_status(primary, txn.start_timestamp())
+    ///         .await
+    ///         .unwrap();
+    ///     println!("transaction status: {:?}", status);
+    /// }
+    /// # });
+    /// ```
+    pub async fn resolve_commit_status(
+        &self,
+        primary: impl Into<Key>,
+        start_ts: Timestamp,
+    ) -> Result<CommitStatus> {
+        resolve_commit_status(primary.into().into(), start_ts, self.pd.clone()).await
+    }
+
+    /// Compute the checksum of the key-value pairs in `range` at `timestamp`.
+    ///
+    /// TiKV computes the checksum of each region of the range from the data visible at
+    /// `timestamp`, so the checksum is consistent across the range. The same data in two clusters
+    /// has the same checksum, no matter how they are split into regions. `timestamp` must not be
+    /// older than the GC safepoint.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Checksum, TransactionClient};
+    /// # futures::executor::block_on(async {
+    /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let timestamp = client.current_timestamp().await.unwrap();
+    /// let checksum: Checksum = client
+    ///     .checksum("TiDB".to_owned().."TiKV".to_owned(), timestamp)
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn checksum(
+        &self,
+        range: impl Into<BoundRange>,
+        timestamp: Timestamp,
+    ) -> Result<Checksum> {
+        let req = new_checksum_request(range.into(), timestamp);
+        let plan = crate::request::PlanBuilder::new(self.pd.clone(), req)
+            .resolve_lock(self.pd.retry_options().lock_backoff)
+            .multi_region()
+            .retry_region(self.pd.retry_options().region_backoff)
+            .merge(crate::request::Collect)
+            .plan();
+        plan.execute().await
+    }
+
+    async fn scan_locks_inner(
+        &self,
+        max_ts: Timestamp,
+        range: BoundRange,
+        limit: u32,
+    ) -> Result<Vec<kvrpcpb::LockInfo>> {
+        let (start_key, end_key) = range.into_keys();
+        let mut start_key: Vec<u8> = start_key.into();
         let mut locks: Vec<kvrpcpb::LockInfo> = vec![];
-        let mut start_key = vec![];
-        loop {
+        while locks.len() < limit as usize {
             let req = new_scan_lock_request(
                 mem::take(&mut start_key),
-                safepoint.version(),
+                max_ts.version(),
                 SCAN_LOCK_BATCH_SIZE,
             );
 
             let plan = crate::request::PlanBuilder::new(self.pd.clone(), req)
-                .resolve_lock(OPTIMISTIC_BACKOFF)
+                .resolve_lock(self.pd.retry_options().lock_backoff)
                 .multi_region()
-                .retry_region(DEFAULT_REGION_BACKOFF)
+                .retry_region(self.pd.retry_options().region_backoff)
                 .merge(crate::request::Collect)
                 .plan();
             let res: Vec<kvrpcpb::LockInfo> = plan.execute().await?;
@@ -199,25 +873,64 @@ impl Client {
             }
             start_key = res.last().unwrap().key.clone();
             start_key.push(0);
-            locks.extend(res);
+            let in_range = |lock: &kvrpcpb::LockInfo| match &end_key {
+                Some(end_key) => Key::from(lock.key.clone()) < *end_key,
+                None => true,
+            };
+            let all_in_range = res.iter().all(in_range);
+            locks.extend(res.into_iter().filter(in_range));
+            if !all_in_range {
+                break;
+            }
         }
+        locks.truncate(limit as usize);
+        Ok(locks)
+    }
 
-        // resolve locks
-        resolve_locks(locks, self.pd.clone()).await?;
+    fn new_transaction(
+        &self,
+        timestamp: Timestamp,
+        mut options: TransactionOptions,
+    ) -> Transaction {
+        options.hooks = self.hooks.clone();
+        Transaction::new(timestamp, self.pd.clone(), options)
+    }
 
-        // update safepoint to PD
-        let res: bool = self
-            .pd
-            .clone()
-            .update_safepoint(safepoint.version())
-            .await?;
-        if !res {
-            info!("new safepoint != user-specified safepoint");
+    fn with_hooks(&self, set: impl FnOnce(&mut TransactionHooks)) -> Client {
+        let mut hooks = self.hooks.clone();
+        set(&mut hooks);
+        Client {
+            pd: self.pd.clone(),
+            hooks,
         }
-        Ok(res)
     }
+}
 
-    fn new_transaction(&self, timestamp: Timestamp, options: TransactionOptions) -> Transaction {
-        Transaction::new(timestamp, self.pd.clone(), options)
+/// Check that the cluster supports transactions. Transactional keys of API V2 clusters are
+/// prefixed with their mode and keyspace, which the transactional client doesn't apply.
+fn check_features(features: Features) -> Result<()> {
+    if features.api_v2 {
+        return Err(Error::UnsupportedFeature {
+            feature: "The transactional API on API V2 clusters",
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_features() {
+        assert!(check_features(Features::default()).is_ok());
+        let api_v2 = Features {
+            api_v2: true,
+            ..Features::default()
+        };
+        assert!(matches!(
+            check_features(api_v2),
+            Err(Error::UnsupportedFeature { .. })
+        ));
     }
 }
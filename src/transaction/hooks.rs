@@ -0,0 +1,133 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::Key;
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tikv_client_proto::{kvrpcpb, pdpb::Timestamp};
+
+/// A step in the life of a transaction, as reported to the callbacks of a
+/// [`TransactionClient`](crate::TransactionClient), see
+/// [`on_commit`](crate::TransactionClient::on_commit).
+#[derive(Clone, Debug)]
+pub struct TransactionEvent {
+    start_ts: Timestamp,
+    commit_ts: Option<Timestamp>,
+    pessimistic: bool,
+    write_keys: usize,
+    write_bytes: usize,
+    elapsed: Duration,
+}
+
+impl TransactionEvent {
+    pub(crate) fn new(start_ts: Timestamp, pessimistic: bool, began: Instant) -> TransactionEvent {
+        TransactionEvent {
+            start_ts,
+            commit_ts: None,
+            pessimistic,
+            write_keys: 0,
+            write_bytes: 0,
+            elapsed: began.elapsed(),
+        }
+    }
+
+    pub(crate) fn writes(
+        mut self,
+        mutations: &[kvrpcpb::Mutation],
+        flushed_keys: &[Key],
+    ) -> TransactionEvent {
+        self.write_keys = mutations.len() + flushed_keys.len();
+        self.write_bytes = mutations
+            .iter()
+            .map(|mutation| mutation.key.len() + mutation.value.len())
+            .chain(flushed_keys.iter().map(|key| <&[u8]>::from(key).len()))
+            .sum();
+        self
+    }
+
+    pub(crate) fn committed(mut self, commit_ts: Option<Timestamp>) -> TransactionEvent {
+        self.commit_ts = commit_ts;
+        self
+    }
+
+    /// The start timestamp of the transaction.
+    pub fn start_timestamp(&self) -> &Timestamp {
+        &self.start_ts
+    }
+
+    /// The commit timestamp of a committed transaction. Committing a transaction which wrote
+    /// nothing sends no requests, and gives it no commit timestamp.
+    pub fn commit_timestamp(&self) -> Option<&Timestamp> {
+        self.commit_ts.as_ref()
+    }
+
+    /// Whether the transaction is pessimistic rather than optimistic.
+    pub fn is_pessimistic(&self) -> bool {
+        self.pessimistic
+    }
+
+    /// The number of keys written, including those [flushed](crate::Transaction::flush).
+    pub fn write_keys(&self) -> usize {
+        self.write_keys
+    }
+
+    /// The size of the keys and values written. Only keys are counted for flushed mutations, whose
+    /// values aren't kept.
+    pub fn write_bytes(&self) -> usize {
+        self.write_bytes
+    }
+
+    /// How long ago the transaction began.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+pub(crate) type TransactionCallback = Arc<dyn Fn(&TransactionEvent) + Send + Sync>;
+
+/// The lifecycle callbacks of the transactions of a client.
+#[derive(Clone, Default)]
+pub(crate) struct TransactionHooks {
+    pub on_begin: Option<TransactionCallback>,
+    pub on_prewrite: Option<TransactionCallback>,
+    pub on_commit: Option<TransactionCallback>,
+    pub on_rollback: Option<TransactionCallback>,
+}
+
+impl TransactionHooks {
+    pub fn is_empty(&self) -> bool {
+        self.on_begin.is_none()
+            && self.on_prewrite.is_none()
+            && self.on_commit.is_none()
+            && self.on_rollback.is_none()
+    }
+}
+
+impl PartialEq for TransactionHooks {
+    fn eq(&self, other: &TransactionHooks) -> bool {
+        fn same(a: &Option<TransactionCallback>, b: &Option<TransactionCallback>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        same(&self.on_begin, &other.on_begin)
+            && same(&self.on_prewrite, &other.on_prewrite)
+            && same(&self.on_commit, &other.on_commit)
+            && same(&self.on_rollback, &other.on_rollback)
+    }
+}
+
+impl fmt::Debug for TransactionHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransactionHooks")
+            .field("on_begin", &self.on_begin.is_some())
+            .field("on_prewrite", &self.on_prewrite.is_some())
+            .field("on_commit", &self.on_commit.is_some())
+            .field("on_rollback", &self.on_rollback.is_some())
+            .finish()
+    }
+}
@@ -1,12 +1,12 @@
 // Copyright 2020 TiKV Project Authors. Licensed under Apache-2.0.
 
 use crate::{
-    backoff::{Backoff, DEFAULT_REGION_BACKOFF, OPTIMISTIC_BACKOFF},
+    backoff::Backoff,
     pd::PdClient,
-    request::Plan,
+    request::{Collect, Plan},
     timestamp::TimestampExt,
-    transaction::requests,
-    Error, RegionVerId, Result,
+    transaction::requests::{self, TransactionStatusKind},
+    Error, Key, RegionVerId, Result,
 };
 use std::{
     collections::{HashMap, HashSet},
@@ -27,6 +27,8 @@ pub async fn resolve_locks(
     locks: Vec<kvrpcpb::LockInfo>,
     pd_client: Arc<impl PdClient>,
 ) -> Result<bool> {
+    // Locks are resolved by writes, which only leaders serve.
+    let pd_client = pd_client.leader_client();
     let ts = pd_client.clone().get_timestamp().await?;
     let mut has_live_locks = false;
     let expired_locks = locks.into_iter().filter(|lock| {
@@ -62,8 +64,8 @@ pub async fn resolve_locks(
                 let plan = crate::request::PlanBuilder::new(pd_client.clone(), request)
                     .single_region()
                     .await?
-                    .resolve_lock(OPTIMISTIC_BACKOFF)
-                    .retry_region(DEFAULT_REGION_BACKOFF)
+                    .resolve_lock(pd_client.retry_options().lock_backoff)
+                    .retry_region(pd_client.retry_options().region_backoff)
                     .post_process_default()
                     .plan();
                 let commit_version = plan.execute().await?;
@@ -121,6 +123,120 @@ async fn resolve_lock_with_retry(
     Err(error.expect("no error is impossible"))
 }
 
+/// Whether a transaction was committed, see
+/// [`resolve_commit_status`](crate::TransactionClient::resolve_commit_status).
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommitStatus {
+    /// The transaction was committed at the timestamp.
+    Committed(Timestamp),
+    /// The transaction was rolled back, or never prewrote its primary key, and will never be
+    /// committed.
+    RolledBack,
+    /// The transaction holds locks which have not expired, so it may still be committed or
+    /// rolled back by its client. Its locks expire within `ttl` milliseconds, unless extended.
+    Locked { ttl: u64 },
+}
+
+/// A lock left on a key by a transaction which is not committed or rolled back yet, see
+/// [`scan_locks`](crate::TransactionClient::scan_locks).
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockInfo(pub(crate) kvrpcpb::LockInfo);
+
+impl LockInfo {
+    /// The locked key.
+    pub fn key(&self) -> Key {
+        self.0.key.clone().into()
+    }
+
+    /// The primary key of the transaction holding the lock.
+    pub fn primary_key(&self) -> Key {
+        self.0.primary_lock.clone().into()
+    }
+
+    /// The start timestamp of the transaction holding the lock.
+    pub fn start_timestamp(&self) -> Timestamp {
+        Timestamp::from_version(self.0.lock_version)
+    }
+
+    /// The TTL of the lock in milliseconds, since the physical time of the start timestamp.
+    pub fn ttl(&self) -> u64 {
+        self.0.lock_ttl
+    }
+}
+
+/// Determine whether the transaction started at `start_ts` with primary key `primary` was
+/// committed, rolling it back if it can no longer commit.
+pub async fn resolve_commit_status(
+    primary: Vec<u8>,
+    start_ts: Timestamp,
+    pd_client: Arc<impl PdClient>,
+) -> Result<CommitStatus> {
+    // Checking the status may roll the transaction back, which only leaders serve.
+    let pd_client = pd_client.leader_client();
+    let current_ts = pd_client.clone().get_timestamp().await?;
+    let request = requests::new_check_txn_status_request(
+        primary,
+        start_ts.version(),
+        0,
+        current_ts.version(),
+        true,
+    );
+    let plan = crate::request::PlanBuilder::new(pd_client.clone(), request)
+        .single_region()
+        .await?
+        .retry_region(pd_client.retry_options().region_backoff)
+        .extract_error()
+        .post_process_default()
+        .plan();
+    let status: requests::TransactionStatus = plan.execute().await?;
+    debug!(
+        target: "tikv_client::txn",
+        start_ts = start_ts.version(),
+        kind = ?status.kind,
+        action = ?status.action,
+        "checked transaction status"
+    );
+    let (ttl, lock) = match status.kind {
+        TransactionStatusKind::Committed(commit_ts) => {
+            return Ok(CommitStatus::Committed(commit_ts))
+        }
+        TransactionStatusKind::RolledBack => return Ok(CommitStatus::RolledBack),
+        TransactionStatusKind::Locked(ttl, lock) => (ttl, lock),
+    };
+    let elapsed = current_ts.physical - start_ts.physical;
+    if !lock.use_async_commit || elapsed < ttl as i64 {
+        return Ok(CommitStatus::Locked {
+            ttl: (ttl as i64 - elapsed).max(0) as u64,
+        });
+    }
+
+    // An async commit transaction is committed iff all its keys were prewritten. Checking the
+    // secondary keys rolls back the ones which were not.
+    let mut keys = lock.secondaries.clone();
+    keys.sort();
+    let request = requests::new_check_secondary_locks_request(keys, start_ts.version());
+    let plan = crate::request::PlanBuilder::new(pd_client.clone(), request)
+        .multi_region()
+        .retry_region(pd_client.retry_options().region_backoff)
+        .merge(Collect)
+        .plan();
+    let secondaries = plan.execute().await?;
+    if let Some(commit_ts) = secondaries.commit_ts {
+        return Ok(CommitStatus::Committed(commit_ts));
+    }
+    if secondaries.locks.len() < lock.secondaries.len() {
+        return Ok(CommitStatus::RolledBack);
+    }
+    let commit_ts = secondaries
+        .locks
+        .values()
+        .map(|lock| lock.min_commit_ts)
+        .chain(std::iter::once(lock.min_commit_ts))
+        .max()
+        .unwrap();
+    Ok(CommitStatus::Committed(Timestamp::from_version(commit_ts)))
+}
+
 pub trait HasLocks {
     fn take_locks(&mut self) -> Vec<kvrpcpb::LockInfo> {
         Vec::new()
@@ -163,4 +279,66 @@ mod tests {
         executor::block_on(resolve_lock_with_retry(&key, 3, 4, client))
             .expect_err("should return error");
     }
+
+    #[tokio::test]
+    async fn test_resolve_commit_status() {
+        // The status of a transaction started at 0 with primary key `primary`, locked for 10ms,
+        // whose secondary key `secondary` was prewritten if `prewritten`.
+        async fn status(primary: u8, prewritten: bool, now: i64) -> CommitStatus {
+            let pd_client =
+                MockPdClient::new(MockKvClient::with_dispatch_hook(move |req: &dyn Any| {
+                    if let Some(req) = req.downcast_ref::<kvrpcpb::CheckTxnStatusRequest>() {
+                        assert!(req.rollback_if_not_exist);
+                        let resp = match req.primary_key[0] {
+                            1 => kvrpcpb::CheckTxnStatusResponse {
+                                commit_version: 5,
+                                ..Default::default()
+                            },
+                            2 => kvrpcpb::CheckTxnStatusResponse::default(),
+                            _ => kvrpcpb::CheckTxnStatusResponse {
+                                lock_ttl: 10,
+                                lock_info: Some(kvrpcpb::LockInfo {
+                                    use_async_commit: true,
+                                    secondaries: vec![vec![12]],
+                                    min_commit_ts: 7,
+                                    ..Default::default()
+                                }),
+                                ..Default::default()
+                            },
+                        };
+                        return Ok(Box::new(resp) as Box<dyn Any>);
+                    }
+                    let mut resp = kvrpcpb::CheckSecondaryLocksResponse::default();
+                    if prewritten {
+                        resp.locks.push(kvrpcpb::LockInfo {
+                            key: vec![12],
+                            min_commit_ts: 9,
+                            ..Default::default()
+                        });
+                    }
+                    Ok(Box::new(resp) as Box<dyn Any>)
+                }))
+                .with_timestamp(Timestamp {
+                    physical: now,
+                    ..Default::default()
+                });
+            resolve_commit_status(vec![primary], Timestamp::default(), Arc::new(pd_client))
+                .await
+                .unwrap()
+        }
+
+        assert_eq!(
+            status(1, false, 0).await,
+            CommitStatus::Committed(Timestamp::from_version(5))
+        );
+        assert_eq!(status(2, false, 0).await, CommitStatus::RolledBack);
+        assert_eq!(status(3, true, 4).await, CommitStatus::Locked { ttl: 6 });
+        // Once the locks of an async commit transaction expired, it's committed iff all its keys
+        // were prewritten.
+        assert_eq!(
+            status(3, true, 10).await,
+            CommitStatus::Committed(Timestamp::from_version(9))
+        );
+        assert_eq!(status(3, false, 10).await, CommitStatus::RolledBack);
+    }
 }
@@ -5,7 +5,7 @@
 /// generated protobuf code, then calls the low-level ctor functions in the requests module.
 use crate::{timestamp::TimestampExt, transaction::requests, BoundRange, Key};
 use std::iter::Iterator;
-use tikv_client_proto::{kvrpcpb, pdpb::Timestamp};
+use tikv_client_proto::{coprocessor, kvrpcpb, pdpb::Timestamp};
 
 pub fn new_get_request(key: Key, timestamp: Timestamp) -> kvrpcpb::GetRequest {
     requests::new_get_request(key.into(), timestamp.version())
@@ -34,6 +34,27 @@ pub fn new_scan_request(
     )
 }
 
+pub fn new_batch_scan_request(
+    ranges: impl Iterator<Item = BoundRange>,
+    timestamp: Timestamp,
+    each_limit: u32,
+) -> requests::BatchScanRequest {
+    requests::new_batch_scan_request(
+        ranges.map(Into::into).collect(),
+        timestamp.version(),
+        each_limit,
+    )
+}
+
+pub fn new_checksum_request(range: BoundRange, timestamp: Timestamp) -> coprocessor::Request {
+    let (start_key, end_key) = range.into_keys();
+    requests::new_checksum_request(
+        start_key.into(),
+        end_key.unwrap_or_default().into(),
+        timestamp.version(),
+    )
+}
+
 pub fn new_resolve_lock_request(
     start_version: Timestamp,
     commit_version: Timestamp,
@@ -145,3 +166,28 @@ pub fn new_heart_beat_request(
 ) -> kvrpcpb::TxnHeartBeatRequest {
     requests::new_heart_beat_request(start_ts.version(), primary_lock.into(), ttl)
 }
+
+pub fn new_check_txn_status_request(
+    primary_key: Key,
+    lock_ts: Timestamp,
+    current_ts: Timestamp,
+    rollback_if_not_exist: bool,
+) -> kvrpcpb::CheckTxnStatusRequest {
+    requests::new_check_txn_status_request(
+        primary_key.into(),
+        lock_ts.version(),
+        0,
+        current_ts.version(),
+        rollback_if_not_exist,
+    )
+}
+
+pub fn new_check_secondary_locks_request(
+    keys: impl Iterator<Item = Key>,
+    start_version: Timestamp,
+) -> kvrpcpb::CheckSecondaryLocksRequest {
+    requests::new_check_secondary_locks_request(
+        keys.map(Into::into).collect(),
+        start_version.version(),
+    )
+}
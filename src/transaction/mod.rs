@@ -9,16 +9,22 @@
 //! **Warning:** It is not advisable to use both raw and transactional functionality in the same keyspace.
 
 pub use client::Client;
-pub(crate) use lock::{resolve_locks, HasLocks};
+pub use hooks::TransactionEvent;
+pub(crate) use lock::{resolve_commit_status, resolve_locks, HasLocks};
+pub use lock::{CommitStatus, LockInfo};
+pub use requests::Checksum;
+pub use safepoint::ServiceSafepoint;
 pub use snapshot::Snapshot;
-pub use transaction::{CheckLevel, Transaction, TransactionOptions};
+pub use transaction::{CheckLevel, CommitMode, Transaction, TransactionOptions, WriterId};
 
 mod buffer;
 mod client;
+mod hooks;
 pub mod lowering;
 #[macro_use]
 mod requests;
 mod lock;
+mod safepoint;
 mod snapshot;
 #[allow(clippy::module_inception)]
 mod transaction;
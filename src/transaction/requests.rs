@@ -2,16 +2,24 @@
 
 use crate::{
     pd::PdClient,
-    request::{Collect, DefaultProcessor, KvRequest, Merge, Process, Shardable, SingleKey},
-    store::{store_stream_for_keys, store_stream_for_range_by_start_key, Store},
+    request::{
+        BatchLimits, Collect, DefaultProcessor, KvRequest, Merge, Process, Shardable, SingleKey,
+    },
+    store::{
+        store_stream_for_keys, store_stream_for_range, store_stream_for_range_by_start_key, Store,
+    },
     timestamp::TimestampExt,
     transaction::HasLocks,
     util::iter::FlatMapOkIterExt,
     Key, KvPair, Result, Value,
 };
-use futures::stream::BoxStream;
-use std::{collections::HashMap, iter, sync::Arc};
-use tikv_client_proto::{kvrpcpb, pdpb::Timestamp};
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use grpcio::CallOption;
+use std::{any::Any, collections::HashMap, iter, sync::Arc};
+use tikv_client_common::internal_err;
+use tikv_client_proto::{coprocessor, kvrpcpb, pdpb::Timestamp, tikvpb::TikvClient, tipb};
+use tikv_client_store::Request;
 
 // implement HasLocks for a response type that has a `pairs` field,
 // where locks can be extracted from both the `pairs` and `error` fields
@@ -141,6 +149,76 @@ impl Merge<kvrpcpb::ScanResponse> for Collect {
     }
 }
 
+/// Scans of several ranges at the same timestamp, sent as one `ScanRequest` per range and region.
+///
+/// `each_limit` applies to every scan sent, so a range spanning several regions may return more
+/// than `each_limit` pairs in total.
+#[derive(Clone)]
+pub struct BatchScanRequest {
+    ranges: Vec<kvrpcpb::KeyRange>,
+    scan: kvrpcpb::ScanRequest,
+}
+
+pub fn new_batch_scan_request(
+    ranges: Vec<kvrpcpb::KeyRange>,
+    timestamp: u64,
+    each_limit: u32,
+) -> BatchScanRequest {
+    BatchScanRequest {
+        ranges,
+        scan: new_scan_request(Vec::new(), Vec::new(), timestamp, each_limit, false),
+    }
+}
+
+#[async_trait]
+impl Request for BatchScanRequest {
+    async fn dispatch(&self, client: &TikvClient, options: CallOption) -> Result<Box<dyn Any>> {
+        self.scan.dispatch(client, options).await
+    }
+
+    fn label(&self) -> &'static str {
+        self.scan.label()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self.scan.as_any()
+    }
+
+    fn set_context(&mut self, context: kvrpcpb::Context) {
+        self.scan.set_context(context);
+    }
+}
+
+impl KvRequest for BatchScanRequest {
+    type Response = kvrpcpb::ScanResponse;
+}
+
+impl Shardable for BatchScanRequest {
+    type Shard = (Vec<u8>, Vec<u8>);
+
+    fn shards(
+        &self,
+        pd_client: &Arc<impl PdClient>,
+    ) -> BoxStream<'static, Result<(Self::Shard, Store)>> {
+        let pd_client = pd_client.clone();
+        stream::iter(self.ranges.clone())
+            .flat_map(move |mut range| {
+                store_stream_for_range(
+                    (range.take_start_key(), range.take_end_key()),
+                    pd_client.clone(),
+                )
+            })
+            .boxed()
+    }
+
+    fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
+        self.scan.set_context(store.context()?);
+        self.scan.set_start_key(shard.0);
+        self.scan.set_end_key(shard.1);
+        Ok(())
+    }
+}
+
 pub fn new_resolve_lock_request(
     start_version: u64,
     commit_version: u64,
@@ -230,21 +308,29 @@ impl Shardable for kvrpcpb::PrewriteRequest {
     }
 
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
-        self.set_context(store.region.context()?);
-
-        // Only need to set secondary keys if we're sending the primary key.
-        if self.use_async_commit && !self.mutations.iter().any(|m| m.key == self.primary_lock) {
-            self.set_secondaries(vec![]);
-        }
+        self.set_context(store.context()?);
 
         // Only if there is only one request to send
-        if self.try_one_pc && self.mutations.len() != self.secondaries.len() + 1 {
+        if self.try_one_pc && shard.len() != self.secondaries.len() + 1 {
             self.set_try_one_pc(false);
         }
 
+        // Only need to set secondary keys if we're sending the primary key.
+        if self.use_async_commit && !shard.iter().any(|m| m.key == self.primary_lock) {
+            self.set_secondaries(vec![]);
+        }
+
         self.set_mutations(shard);
         Ok(())
     }
+
+    fn split_shard(shard: Self::Shard, limits: &BatchLimits) -> Vec<Self::Shard> {
+        limits.split(shard, |m| m.key.len() + m.value.len())
+    }
+
+    fn clear_sharded_data(&mut self) {
+        self.mutations.clear();
+    }
 }
 
 impl HasLocks for kvrpcpb::PrewriteResponse {
@@ -353,10 +439,14 @@ impl Shardable for kvrpcpb::PessimisticLockRequest {
     }
 
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
-        self.set_context(store.region.context()?);
+        self.set_context(store.context()?);
         self.set_mutations(shard);
         Ok(())
     }
+
+    fn clear_sharded_data(&mut self) {
+        self.mutations.clear();
+    }
 }
 
 impl Merge<kvrpcpb::PessimisticLockResponse> for Collect {
@@ -422,7 +512,7 @@ impl Shardable for kvrpcpb::ScanLockRequest {
     }
 
     fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
-        self.set_context(store.region.context()?);
+        self.set_context(store.context()?);
         self.set_start_key(shard);
         Ok(())
     }
@@ -469,6 +559,22 @@ impl Process<kvrpcpb::TxnHeartBeatResponse> for DefaultProcessor {
     }
 }
 
+pub fn new_check_txn_status_request(
+    primary_key: Vec<u8>,
+    lock_ts: u64,
+    caller_start_ts: u64,
+    current_ts: u64,
+    rollback_if_not_exist: bool,
+) -> kvrpcpb::CheckTxnStatusRequest {
+    let mut req = kvrpcpb::CheckTxnStatusRequest::default();
+    req.set_primary_key(primary_key);
+    req.set_lock_ts(lock_ts);
+    req.set_caller_start_ts(caller_start_ts);
+    req.set_current_ts(current_ts);
+    req.set_rollback_if_not_exist(rollback_if_not_exist);
+    req
+}
+
 impl KvRequest for kvrpcpb::CheckTxnStatusRequest {
     type Response = kvrpcpb::CheckTxnStatusResponse;
 }
@@ -520,6 +626,16 @@ impl From<(u64, u64, Option<kvrpcpb::LockInfo>)> for TransactionStatusKind {
     }
 }
 
+pub fn new_check_secondary_locks_request(
+    keys: Vec<Vec<u8>>,
+    start_version: u64,
+) -> kvrpcpb::CheckSecondaryLocksRequest {
+    let mut req = kvrpcpb::CheckSecondaryLocksRequest::default();
+    req.set_keys(keys);
+    req.set_start_version(start_version);
+    req
+}
+
 impl KvRequest for kvrpcpb::CheckSecondaryLocksRequest {
     type Response = kvrpcpb::CheckSecondaryLocksResponse;
 }
@@ -560,6 +676,120 @@ pub struct SecondaryLocksStatus {
     pub commit_ts: Option<Timestamp>,
 }
 
+pub fn new_split_region_request(split_keys: Vec<Vec<u8>>) -> kvrpcpb::SplitRegionRequest {
+    let mut req = kvrpcpb::SplitRegionRequest::default();
+    req.set_split_keys(split_keys);
+    req
+}
+
+impl KvRequest for kvrpcpb::SplitRegionRequest {
+    type Response = kvrpcpb::SplitRegionResponse;
+}
+
+impl Shardable for kvrpcpb::SplitRegionRequest {
+    type Shard = Vec<Vec<u8>>;
+
+    fn shards(
+        &self,
+        pd_client: &Arc<impl PdClient>,
+    ) -> BoxStream<'static, Result<(Self::Shard, Store)>> {
+        let mut keys = self.split_keys.clone();
+        keys.sort();
+        keys.dedup();
+        store_stream_for_keys(keys.into_iter(), pd_client.clone())
+    }
+
+    fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
+        self.set_context(store.context()?);
+        self.set_split_keys(shard);
+        Ok(())
+    }
+}
+
+impl HasLocks for kvrpcpb::SplitRegionResponse {}
+
+/// The coprocessor request type of checksum requests.
+const REQ_TYPE_CHECKSUM: i64 = 105;
+
+pub fn new_checksum_request(
+    start_key: Vec<u8>,
+    end_key: Vec<u8>,
+    timestamp: u64,
+) -> coprocessor::Request {
+    let checksum = tipb::ChecksumRequest {
+        scan_on: Some(tipb::ChecksumScanOn::Table as i32),
+        algorithm: Some(tipb::ChecksumAlgorithm::Crc64Xor as i32),
+        ..Default::default()
+    };
+    let mut range = coprocessor::KeyRange::default();
+    range.set_start(start_key);
+    range.set_end(end_key);
+
+    let mut req = coprocessor::Request::default();
+    req.set_tp(REQ_TYPE_CHECKSUM);
+    req.set_data(
+        protobuf::Message::write_to_bytes(&checksum)
+            .expect("encoding a message into a vector cannot fail"),
+    );
+    req.set_start_ts(timestamp);
+    req.set_ranges(vec![range]);
+    req
+}
+
+impl KvRequest for coprocessor::Request {
+    type Response = coprocessor::Response;
+}
+
+// Only checksum requests are sent to the coprocessor, and they have a single range.
+impl Shardable for coprocessor::Request {
+    type Shard = (Vec<u8>, Vec<u8>);
+
+    fn shards(
+        &self,
+        pd_client: &Arc<impl PdClient>,
+    ) -> BoxStream<'static, Result<(Self::Shard, Store)>> {
+        let range = &self.ranges[0];
+        store_stream_for_range((range.start.clone(), range.end.clone()), pd_client.clone())
+    }
+
+    fn apply_shard(&mut self, shard: Self::Shard, store: &Store) -> Result<()> {
+        self.set_context(store.context()?);
+        let mut range = coprocessor::KeyRange::default();
+        range.set_start(shard.0);
+        range.set_end(shard.1);
+        self.set_ranges(vec![range]);
+        Ok(())
+    }
+}
+
+/// The checksum of the key-value pairs in a range, computed by TiKV.
+///
+/// `crc64_xor` is the XOR of the CRC64 of every key-value pair, so it doesn't depend on how the
+/// range is split into regions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Checksum {
+    pub crc64_xor: u64,
+    pub total_kvs: u64,
+    pub total_bytes: u64,
+}
+
+impl Merge<coprocessor::Response> for Collect {
+    type Out = Checksum;
+
+    fn merge(&self, input: Vec<Result<coprocessor::Response>>) -> Result<Self::Out> {
+        let mut result = Checksum::default();
+        for resp in input {
+            let mut checksum = tipb::ChecksumResponse::default();
+            protobuf::Message::merge_from_bytes(&mut checksum, resp?.get_data())
+                .map_err(|e| internal_err!("invalid checksum response: {}", e))?;
+            result.crc64_xor ^= checksum.get_checksum();
+            result.total_kvs += checksum.get_total_kvs();
+            result.total_bytes += checksum.get_total_bytes();
+        }
+        Ok(result)
+    }
+}
+
 impl HasLocks for kvrpcpb::PessimisticRollbackResponse {
     fn take_locks(&mut self) -> Vec<kvrpcpb::LockInfo> {
         self.errors
@@ -587,5 +817,11 @@ error_locks!(kvrpcpb::BatchRollbackResponse);
 error_locks!(kvrpcpb::TxnHeartBeatResponse);
 error_locks!(kvrpcpb::CheckTxnStatusResponse);
 error_locks!(kvrpcpb::CheckSecondaryLocksResponse);
+impl HasLocks for coprocessor::Response {
+    fn take_locks(&mut self) -> Vec<kvrpcpb::LockInfo> {
+        self.locked.take().into_iter().collect()
+    }
+}
+
 impl HasLocks for kvrpcpb::CleanupResponse {}
 impl HasLocks for kvrpcpb::ScanLockResponse {}
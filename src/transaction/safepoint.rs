@@ -0,0 +1,143 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::{
+    pd::{PdClient, PdRpcClient},
+    Error, Result, Timestamp, TimestampExt,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+/// A service safepoint registered in PD, which keeps GC from removing the versions a snapshot at
+/// its timestamp reads. Created by
+/// [`TransactionClient::hold_safepoint`](crate::TransactionClient::hold_safepoint).
+///
+/// The registration expires after its TTL, and is refreshed in the background every third of it
+/// while the `ServiceSafepoint` is alive. Dropping it stops refreshing, so GC is held back until
+/// the registration expires. Use [`release`](ServiceSafepoint::release) to let GC proceed
+/// immediately.
+pub struct ServiceSafepoint<PdC: PdClient = PdRpcClient> {
+    pd: Arc<PdC>,
+    service_id: String,
+    timestamp: Timestamp,
+    refresh: Option<JoinHandle<()>>,
+}
+
+impl<PdC: PdClient> ServiceSafepoint<PdC> {
+    pub(crate) async fn register(
+        pd: Arc<PdC>,
+        service_id: String,
+        timestamp: Timestamp,
+        ttl: Duration,
+    ) -> Result<ServiceSafepoint<PdC>> {
+        assert!(
+            ttl >= Duration::from_secs(1),
+            "TTL of a service safepoint must be at least a second"
+        );
+        let version = timestamp.version();
+        let min_safepoint = pd
+            .clone()
+            .update_service_safepoint(service_id.clone(), ttl, version)
+            .await?;
+        // PD doesn't register a safepoint behind the one GC already reached.
+        if min_safepoint > version {
+            return Err(Error::TimestampBeforeSafepoint {
+                version,
+                safepoint: min_safepoint,
+            });
+        }
+
+        let refresh = {
+            let pd = pd.clone();
+            let service_id = service_id.clone();
+            crate::util::task::spawn("service-safepoint-refresh", async move {
+                loop {
+                    tokio::time::sleep(ttl / 3).await;
+                    if let Err(e) = pd
+                        .clone()
+                        .update_service_safepoint(service_id.clone(), ttl, version)
+                        .await
+                    {
+                        warn!(
+                            target: "tikv_client::txn",
+                            service_id = service_id.as_str(),
+                            error = %e,
+                            "failed to refresh service safepoint"
+                        );
+                    }
+                }
+            })
+        };
+        Ok(ServiceSafepoint {
+            pd,
+            service_id,
+            timestamp,
+            refresh: Some(refresh),
+        })
+    }
+
+    /// The ID the safepoint is registered with in PD.
+    pub fn service_id(&self) -> &str {
+        &self.service_id
+    }
+
+    /// The timestamp whose versions are kept from GC.
+    pub fn timestamp(&self) -> &Timestamp {
+        &self.timestamp
+    }
+
+    /// Remove the registration, letting GC proceed past the timestamp.
+    pub async fn release(mut self) -> Result<()> {
+        if let Some(handle) = self.refresh.take() {
+            handle.abort();
+        }
+        self.pd
+            .clone()
+            .update_service_safepoint(
+                self.service_id.clone(),
+                Duration::ZERO,
+                self.timestamp.version(),
+            )
+            .await
+            .map(|_| ())
+    }
+}
+
+impl<PdC: PdClient> Drop for ServiceSafepoint<PdC> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.refresh.take() {
+            handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockPdClient;
+
+    #[tokio::test]
+    async fn test_register() {
+        let pd = Arc::new(MockPdClient::default().with_safepoint(10));
+        let ttl = Duration::from_secs(60);
+        let safepoint = ServiceSafepoint::register(
+            pd.clone(),
+            "job".to_owned(),
+            Timestamp::from_version(10),
+            ttl,
+        )
+        .await
+        .unwrap();
+        assert_eq!(safepoint.service_id(), "job");
+        safepoint.release().await.unwrap();
+
+        let result =
+            ServiceSafepoint::register(pd, "job".to_owned(), Timestamp::from_version(9), ttl).await;
+        assert!(matches!(
+            result,
+            Err(Error::TimestampBeforeSafepoint {
+                version: 9,
+                safepoint: 10
+            })
+        ));
+    }
+}
@@ -1,6 +1,6 @@
 // Copyright 2019 TiKV Project Authors. Licensed under Apache-2.0.
 
-use crate::{BoundRange, Key, KvPair, Result, Transaction, Value};
+use crate::{BoundRange, Key, KvPair, Options, Result, Transaction, Value};
 use derive_new::new;
 use futures::stream::BoxStream;
 use std::ops::RangeBounds;
@@ -23,6 +23,11 @@ impl Snapshot {
         self.transaction.get(key).await
     }
 
+    /// Get the value associated with the given key, with the options of this operation.
+    pub async fn get_opt(&self, key: impl Into<Key>, options: Options) -> Result<Option<Value>> {
+        self.transaction.get_opt(key, options).await
+    }
+
     /// Check whether the key exists.
     pub async fn key_exists(&self, key: impl Into<Key>) -> Result<bool> {
         self.transaction.key_exists(key).await
@@ -36,6 +41,15 @@ impl Snapshot {
         self.transaction.batch_get(keys).await
     }
 
+    /// Get the values associated with the given keys, with the options of this operation.
+    pub async fn batch_get_opt(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+        options: Options,
+    ) -> Result<impl Iterator<Item = KvPair>> {
+        self.transaction.batch_get_opt(keys, options).await
+    }
+
     /// Scan a range, return at most `limit` key-value pairs that lying in the range.
     pub async fn scan(
         &self,
@@ -45,6 +59,17 @@ impl Snapshot {
         self.transaction.scan(range, limit).await
     }
 
+    /// Scan a range with the options of this operation, return at most `limit` key-value pairs
+    /// that lying in the range.
+    pub async fn scan_opt(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+        options: Options,
+    ) -> Result<impl Iterator<Item = KvPair>> {
+        self.transaction.scan_opt(range, limit, options).await
+    }
+
     /// Scan a range, return at most `limit` keys that lying in the range.
     pub async fn scan_keys(
         &self,
@@ -54,6 +79,43 @@ impl Snapshot {
         self.transaction.scan_keys(range, limit).await
     }
 
+    /// Scan a range with the options of this operation, return at most `limit` keys that lying
+    /// in the range.
+    pub async fn scan_keys_opt(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+        options: Options,
+    ) -> Result<impl Iterator<Item = Key>> {
+        self.transaction.scan_keys_opt(range, limit, options).await
+    }
+
+    /// Scan several ranges, return at most `each_limit` key-value pairs of each range.
+    ///
+    /// All ranges are scanned in one request per region and range. The pairs of each range are
+    /// ordered by key, and ranges are in the order given.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{KvPair, TransactionClient, TransactionOptions};
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let snapshot = client.snapshot(
+    ///     client.current_timestamp().await.unwrap(),
+    ///     TransactionOptions::default(),
+    /// );
+    /// let ranges = vec!["TiDB".to_owned().."TiKV".to_owned(), "TiSpark".to_owned().."TiUP".to_owned()];
+    /// let result: Vec<KvPair> = snapshot.batch_scan(ranges, 10).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn batch_scan(
+        &self,
+        ranges: impl IntoIterator<Item = impl Into<BoundRange>>,
+        each_limit: u32,
+    ) -> Result<Vec<KvPair>> {
+        self.transaction.batch_scan(ranges, each_limit).await
+    }
+
     /// Unimplemented. Similar to scan, but in the reverse direction.
     #[allow(dead_code)]
     fn scan_reverse(&self, range: impl RangeBounds<Key>) -> BoxStream<Result<KvPair>> {
@@ -2,18 +2,41 @@
 
 use crate::{
     backoff::Backoff,
+    kv::{
+        align_to_keys,
+        codec::value::{SharedValueCodec, ValueCodec},
+    },
     pd::{PdClient, PdRpcClient},
-    request::{Collect, CollectError, Plan, PlanBuilder, RetryOptions},
+    request::{
+        BatchLimits, CancellationToken, Collect, CollectError, Options, Plan, PlanBuilder,
+        RetryOptions,
+    },
     timestamp::TimestampExt,
-    transaction::{buffer::Buffer, lowering::*},
+    transaction::{
+        buffer::Buffer,
+        hooks::{TransactionCallback, TransactionEvent, TransactionHooks},
+        lowering::*,
+    },
     BoundRange, Error, Key, KvPair, Result, Value,
 };
 use derive_new::new;
 use fail::fail_point;
 use futures::{prelude::*, stream::BoxStream};
-use std::{iter, ops::RangeBounds, sync::Arc};
+use std::{
+    iter,
+    ops::{Bound, RangeBounds},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 use tikv_client_proto::{kvrpcpb, pdpb::Timestamp};
-use tokio::{sync::RwLock, time::Duration};
+use tokio::{
+    sync::{RwLock, RwLockReadGuard},
+    task::JoinHandle,
+    time::Duration,
+};
 
 /// An undo-able set of actions on the dataset.
 ///
@@ -34,6 +57,22 @@ use tokio::{sync::RwLock, time::Duration};
 /// For details, the [SIG-Transaction](https://github.com/tikv/sig-transaction)
 /// provides materials explaining designs and implementations of multiple features in TiKV transactions.
 ///
+/// # Sharing
+///
+/// A `Transaction` is a cheap handle: clones share the same transaction, and can be sent to
+/// other tasks. Every operation takes `&self`, and each takes effect atomically with respect to
+/// the others:
+///
+/// * Reads see the writes of all handles which completed before the read started.
+/// * Concurrent writes to different keys don't block each other, except while they lock keys in
+///   a pessimistic transaction. Concurrent writes to the same key are applied in some order, the
+///   last one winning; use [`put_as`](Transaction::put_as) and friends to detect them instead.
+/// * [`commit`](Transaction::commit) and [`rollback`](Transaction::rollback) wait for the writes
+///   in progress, which are included in the commit. Any operation started after them fails with
+///   [`OperationAfterCommitError`](Error::OperationAfterCommitError), on every handle.
+///
+/// The drop check of [`CheckLevel`](CheckLevel) applies when the last handle is dropped.
+///
 /// # Examples
 /// ```rust,no_run
 /// use tikv_client::{Config, TransactionClient};
@@ -41,15 +80,37 @@ use tokio::{sync::RwLock, time::Duration};
 /// # futures::executor::block_on(async {
 /// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
 /// let txn = client.begin_optimistic().await.unwrap();
+/// let handle = txn.clone();
+/// tokio::spawn(async move { handle.put("TiKV".to_owned(), "Rust".to_owned()).await })
+///     .await
+///     .unwrap()
+///     .unwrap();
+/// txn.commit().await.unwrap();
 /// # });
 /// ```
 pub struct Transaction<PdC: PdClient = PdRpcClient> {
+    inner: Arc<TransactionInner<PdC>>,
+}
+
+/// The state shared by the handles of a transaction.
+struct TransactionInner<PdC: PdClient> {
     status: Arc<RwLock<TransactionStatus>>,
     timestamp: Timestamp,
     buffer: Buffer,
     rpc: Arc<PdC>,
     options: TransactionOptions,
-    is_heartbeat_started: bool,
+    // The latest `for_update_ts` of pessimistic locks, which replaces the one of `options.kind`.
+    for_update_ts: AtomicU64,
+    is_heartbeat_started: AtomicBool,
+    began: Instant,
+}
+
+impl<PdC: PdClient> Clone for Transaction<PdC> {
+    fn clone(&self) -> Self {
+        Transaction {
+            inner: self.inner.clone(),
+        }
+    }
 }
 
 impl<PdC: PdClient> Transaction<PdC> {
@@ -63,14 +124,38 @@ impl<PdC: PdClient> Transaction<PdC> {
         } else {
             TransactionStatus::Active
         };
-        Transaction {
-            status: Arc::new(RwLock::new(status)),
-            timestamp,
-            buffer: Default::default(),
-            rpc,
-            options,
-            is_heartbeat_started: false,
+        let (for_update_ts, retry_options) = match &options.kind {
+            TransactionKind::Optimistic => (0, rpc.retry_options()),
+            TransactionKind::Pessimistic(for_update_ts) => {
+                (for_update_ts.version(), rpc.pessimistic_retry_options())
+            }
+        };
+        let options = options.default_retry_options(retry_options);
+        let txn = Transaction {
+            inner: Arc::new(TransactionInner {
+                status: Arc::new(RwLock::new(status)),
+                timestamp,
+                buffer: Default::default(),
+                rpc,
+                options,
+                for_update_ts: AtomicU64::new(for_update_ts),
+                is_heartbeat_started: AtomicBool::new(false),
+                began: Instant::now(),
+            }),
+        };
+        if let Some(on_begin) = &txn.inner.options.hooks.on_begin {
+            on_begin(&txn.event());
         }
+        txn
+    }
+
+    /// Describe the transaction to its lifecycle callbacks.
+    fn event(&self) -> TransactionEvent {
+        TransactionEvent::new(
+            self.inner.timestamp.clone(),
+            self.is_pessimistic(),
+            self.inner.began,
+        )
     }
 
     /// Create a new 'get' request
@@ -86,7 +171,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
     /// let key = "TiKV".to_owned();
     /// let result: Option<Value> = txn.get(key).await.unwrap();
     /// // Finish the transaction...
@@ -94,25 +179,56 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # });
     /// ```
     pub async fn get(&self, key: impl Into<Key>) -> Result<Option<Value>> {
-        self.check_allow_operation().await?;
-        let timestamp = self.timestamp.clone();
-        let rpc = self.rpc.clone();
-        let key = key.into();
-        let retry_options = self.options.retry_options.clone();
+        self.get_opt(key, Options::new()).await
+    }
 
-        self.buffer
-            .get_or_else(key, |key| async move {
-                let request = new_get_request(key, timestamp);
-                let plan = PlanBuilder::new(rpc, request)
-                    .single_region()
-                    .await?
-                    .resolve_lock(retry_options.lock_backoff)
-                    .retry_region(retry_options.region_backoff)
-                    .post_process_default()
-                    .plan();
-                plan.execute().await
-            })
-            .await
+    /// Like [`get`](Transaction::get), with the options of this operation. Values already read
+    /// or written by the transaction are returned from its buffer, ignoring the options.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Options, Priority, Value, TransactionClient};
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// let options = Options::new().priority(Priority::Low);
+    /// let result: Option<Value> = txn.get_opt("TiKV".to_owned(), options).await.unwrap();
+    /// // Finish the transaction...
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn get_opt(&self, key: impl Into<Key>, options: Options) -> Result<Option<Value>> {
+        self.get_encoded(key.into(), options)
+            .await?
+            .map(|value| self.inner.options.value_codec.decode(value))
+            .transpose()
+    }
+
+    /// Like [`get_opt`](Transaction::get_opt), returning the value as encoded by the value codec.
+    async fn get_encoded(&self, key: Key, options: Options) -> Result<Option<Value>> {
+        self.check_allow_operation().await?;
+        let timestamp = self.read_timestamp(&options).await?;
+        let rpc = self.inner.rpc.clone().with_options(&options);
+        let retry_options = self.retry_options(&options);
+
+        let fetch = |key| async move {
+            let request = new_get_request(key, timestamp);
+            let plan = PlanBuilder::new(rpc, request)
+                .single_region()
+                .await?
+                .resolve_lock(retry_options.lock_backoff)
+                .retry_region(retry_options.region_backoff)
+                .post_process_default()
+                .plan();
+            plan.execute().await
+        };
+        if options.read_committed {
+            options
+                .run(self.inner.buffer.get_written_or_else(key, fetch))
+                .await
+        } else {
+            options.run(self.inner.buffer.get_or_else(key, fetch)).await
+        }
     }
 
     /// Create a `get for udpate` request.
@@ -137,7 +253,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_pessimistic().await.unwrap();
+    /// let txn = client.begin_pessimistic().await.unwrap();
     /// let key = "TiKV".to_owned();
     /// let result: Value = txn.get_for_update(key).await.unwrap().unwrap();
     /// // now the key "TiKV" is locked, other transactions cannot modify it
@@ -145,15 +261,19 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// txn.commit().await.unwrap();
     /// # });
     /// ```
-    pub async fn get_for_update(&mut self, key: impl Into<Key>) -> Result<Option<Value>> {
-        self.check_allow_operation().await?;
+    pub async fn get_for_update(&self, key: impl Into<Key>) -> Result<Option<Value>> {
+        let _status = self.check_allow_write().await?;
         if !self.is_pessimistic() {
             Err(Error::InvalidTransactionType)
         } else {
             let key = key.into();
             let mut values = self.pessimistic_lock(iter::once(key.clone()), true).await?;
             assert!(values.len() == 1);
-            Ok(values.pop().unwrap())
+            values
+                .pop()
+                .unwrap()
+                .map(|value| self.inner.options.value_codec.decode(value))
+                .transpose()
         }
     }
 
@@ -165,7 +285,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_pessimistic().await.unwrap();
+    /// let txn = client.begin_pessimistic().await.unwrap();
     /// let exists = txn.key_exists("k1".to_owned()).await.unwrap();
     /// txn.commit().await.unwrap();
     /// # });
@@ -181,6 +301,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// given keys.
     ///
     /// Non-existent entries will not appear in the result. The order of the keys is not retained in the result.
+    /// Use [`batch_get_ordered`](Transaction::batch_get_ordered) to get a result for every key, in order.
     ///
     /// # Examples
     /// ```rust,no_run
@@ -189,7 +310,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use std::collections::HashMap;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
     /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
     /// let result: HashMap<Key, Value> = txn
     ///     .batch_get(keys)
@@ -204,26 +325,80 @@ impl<PdC: PdClient> Transaction<PdC> {
     pub async fn batch_get(
         &self,
         keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<impl Iterator<Item = KvPair>> {
+        self.batch_get_opt(keys, Options::new()).await
+    }
+
+    /// Like [`batch_get`](Transaction::batch_get), with the options of this operation. Values
+    /// already read or written by the transaction are returned from its buffer, ignoring the
+    /// options.
+    pub async fn batch_get_opt(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+        options: Options,
     ) -> Result<impl Iterator<Item = KvPair>> {
         self.check_allow_operation().await?;
-        let timestamp = self.timestamp.clone();
-        let rpc = self.rpc.clone();
-        let retry_options = self.options.retry_options.clone();
-
-        self.buffer
-            .batch_get_or_else(keys.into_iter().map(|k| k.into()), move |keys| async move {
-                let request = new_batch_get_request(keys, timestamp);
-                let plan = PlanBuilder::new(rpc, request)
-                    .resolve_lock(retry_options.lock_backoff)
-                    .multi_region()
-                    .retry_region(retry_options.region_backoff)
-                    .merge(Collect)
-                    .plan();
-                plan.execute()
-                    .await
-                    .map(|r| r.into_iter().map(Into::into).collect())
-            })
-            .await
+        let timestamp = self.read_timestamp(&options).await?;
+        let rpc = self.inner.rpc.clone().with_options(&options);
+        let retry_options = self.retry_options(&options);
+
+        let keys = keys.into_iter().map(|k| k.into());
+        let fetch = move |keys| async move {
+            let request = new_batch_get_request(keys, timestamp);
+            let plan = PlanBuilder::new(rpc, request)
+                .resolve_lock(retry_options.lock_backoff)
+                .multi_region()
+                .retry_region(retry_options.region_backoff)
+                .merge(Collect)
+                .plan();
+            plan.execute()
+                .await
+                .map(|r| r.into_iter().map(Into::into).collect())
+        };
+        let pairs: Vec<KvPair> = if options.read_committed {
+            options
+                .run(self.inner.buffer.batch_get_written_or_else(keys, fetch))
+                .await?
+                .collect()
+        } else {
+            options
+                .run(self.inner.buffer.batch_get_or_else(keys, fetch))
+                .await?
+                .collect()
+        };
+        Ok(self
+            .inner
+            .options
+            .value_codec
+            .decode_pairs(pairs)?
+            .into_iter())
+    }
+
+    /// Create a new 'batch get' request which returns the value of every key in order.
+    ///
+    /// Once resolved this request will result in one entry per key, in the order of `keys`, so
+    /// the result can be zipped with the keys. Entries of non-existent keys are `None`.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Value, Config, TransactionClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
+    /// let result: Vec<Option<Value>> = txn.batch_get_ordered(keys).await.unwrap();
+    /// // Finish the transaction...
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn batch_get_ordered(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<Option<Value>>> {
+        let keys: Vec<Key> = keys.into_iter().map(Into::into).collect();
+        let pairs = self.batch_get(keys.clone()).await?;
+        Ok(align_to_keys(&keys, pairs))
     }
 
     /// Create a new 'batch get for update' request.
@@ -248,7 +423,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use std::collections::HashMap;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_pessimistic().await.unwrap();
+    /// let txn = client.begin_pessimistic().await.unwrap();
     /// let keys = vec!["TiKV".to_owned(), "TiDB".to_owned()];
     /// let result: HashMap<Key, Value> = txn
     ///     .batch_get_for_update(keys)
@@ -265,15 +440,16 @@ impl<PdC: PdClient> Transaction<PdC> {
     // See `impl KvRequest for kvrpcpb::PessimisticLockRequest` for details.
     #[allow(dead_code)]
     async fn batch_get_for_update(
-        &mut self,
+        &self,
         keys: impl IntoIterator<Item = impl Into<Key>>,
     ) -> Result<impl Iterator<Item = KvPair>> {
-        self.check_allow_operation().await?;
+        let status = self.check_allow_write().await?;
         if !self.is_pessimistic() {
             Err(Error::InvalidTransactionType)
         } else {
             let keys: Vec<Key> = keys.into_iter().map(|it| it.into()).collect();
             self.pessimistic_lock(keys.clone(), false).await?;
+            drop(status);
             self.batch_get(keys).await
         }
     }
@@ -292,7 +468,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use std::collections::HashMap;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
     /// let key1: Key = b"TiKV".to_vec().into();
     /// let key2: Key = b"TiDB".to_vec().into();
     /// let result: Vec<KvPair> = txn
@@ -309,7 +485,28 @@ impl<PdC: PdClient> Transaction<PdC> {
         range: impl Into<BoundRange>,
         limit: u32,
     ) -> Result<impl Iterator<Item = KvPair>> {
-        self.scan_inner(range, limit, false).await
+        self.scan_opt(range, limit, Options::new()).await
+    }
+
+    /// Like [`scan`](Transaction::scan), with the options of this operation. The values of the
+    /// pairs are empty if the options are [`key_only`](Options::key_only).
+    pub async fn scan_opt(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+        options: Options,
+    ) -> Result<impl Iterator<Item = KvPair>> {
+        let pairs = self.scan_inner(range, limit, &options).await?;
+        if options.key_only {
+            let keys = pairs.map(|pair| KvPair(pair.into_key(), Vec::new()));
+            return Ok(keys.collect::<Vec<_>>().into_iter());
+        }
+        Ok(self
+            .inner
+            .options
+            .value_codec
+            .decode_pairs(pairs)?
+            .into_iter())
     }
 
     /// Create a new 'scan' request that only returns the keys.
@@ -326,7 +523,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use std::collections::HashMap;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
     /// let key1: Key = b"TiKV".to_vec().into();
     /// let key2: Key = b"TiDB".to_vec().into();
     /// let result: Vec<Key> = txn
@@ -342,9 +539,19 @@ impl<PdC: PdClient> Transaction<PdC> {
         &self,
         range: impl Into<BoundRange>,
         limit: u32,
+    ) -> Result<impl Iterator<Item = Key>> {
+        self.scan_keys_opt(range, limit, Options::new()).await
+    }
+
+    /// Like [`scan_keys`](Transaction::scan_keys), with the options of this operation.
+    pub async fn scan_keys_opt(
+        &self,
+        range: impl Into<BoundRange>,
+        limit: u32,
+        options: Options,
     ) -> Result<impl Iterator<Item = Key>> {
         Ok(self
-            .scan_inner(range, limit, true)
+            .scan_inner(range, limit, &options.key_only())
             .await?
             .map(KvPair::into_key))
     }
@@ -358,13 +565,16 @@ impl<PdC: PdClient> Transaction<PdC> {
 
     /// Sets the value associated with the given key.
     ///
+    /// Fails with [`LocalWriteConflict`](Error::LocalWriteConflict) if a writer of the
+    /// transaction has written the key with [`put_as`](Transaction::put_as) and friends.
+    ///
     /// # Examples
     /// ```rust,no_run
     /// # use tikv_client::{Key, Value, Config, TransactionClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
     /// let key = "TiKV".to_owned();
     /// let val = "TiKV".to_owned();
     /// txn.put(key, val);
@@ -372,14 +582,48 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// txn.commit().await.unwrap();
     /// # });
     /// ```
-    pub async fn put(&mut self, key: impl Into<Key>, value: impl Into<Value>) -> Result<()> {
-        self.check_allow_operation().await?;
-        let key = key.into();
+    pub async fn put(&self, key: impl Into<Key>, value: impl Into<Value>) -> Result<()> {
+        self.put_by(None, key.into(), value.into()).await
+    }
+
+    /// Sets the value associated with the given key, on behalf of `writer`.
+    ///
+    /// Fails with [`LocalWriteConflict`](Error::LocalWriteConflict) if another writer of the
+    /// transaction has written the key, rather than overwriting its value. See
+    /// [`WriterId`](WriterId).
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{TransactionClient, WriterId};
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// let (alice, bob) = (WriterId::new(), WriterId::new());
+    /// txn.put_as(alice, "TiKV".to_owned(), "alice".to_owned()).await.unwrap();
+    /// // Bob can't overwrite Alice's write.
+    /// assert!(txn.put_as(bob, "TiKV".to_owned(), "bob".to_owned()).await.is_err());
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn put_as(
+        &self,
+        writer: WriterId,
+        key: impl Into<Key>,
+        value: impl Into<Value>,
+    ) -> Result<()> {
+        self.put_by(Some(writer), key.into(), value.into()).await
+    }
+
+    async fn put_by(&self, writer: Option<WriterId>, key: Key, value: Value) -> Result<()> {
+        let _status = self.check_allow_write().await?;
+        self.check_claim(&key, writer).await?;
+        let value = self.inner.options.value_codec.encode(value)?;
         if self.is_pessimistic() {
             self.pessimistic_lock(iter::once(key.clone()), false)
                 .await?;
         }
-        self.buffer.put(key, value.into()).await;
+        self.inner.buffer.claim(&key, writer).await?;
+        self.inner.buffer.put(key, value).await;
         Ok(())
     }
 
@@ -392,7 +636,7 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
     /// let key = "TiKV".to_owned();
     /// let val = "TiKV".to_owned();
     /// txn.insert(key, val);
@@ -400,23 +644,164 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// txn.commit().await.unwrap();
     /// # });
     /// ```
-    pub async fn insert(&mut self, key: impl Into<Key>, value: impl Into<Value>) -> Result<()> {
-        self.check_allow_operation().await?;
-        let key = key.into();
-        if self.buffer.get(&key).await.is_some() {
+    pub async fn insert(&self, key: impl Into<Key>, value: impl Into<Value>) -> Result<()> {
+        self.insert_by(None, key.into(), value.into()).await
+    }
+
+    /// Inserts the value associated with the given key, on behalf of `writer`.
+    ///
+    /// Fails with [`LocalWriteConflict`](Error::LocalWriteConflict) if another writer of the
+    /// transaction has written the key. See [`put_as`](Transaction::put_as).
+    pub async fn insert_as(
+        &self,
+        writer: WriterId,
+        key: impl Into<Key>,
+        value: impl Into<Value>,
+    ) -> Result<()> {
+        self.insert_by(Some(writer), key.into(), value.into()).await
+    }
+
+    async fn insert_by(&self, writer: Option<WriterId>, key: Key, value: Value) -> Result<()> {
+        let value = self.inner.options.value_codec.encode(value)?;
+        self.insert_encoded(writer, key, value).await
+    }
+
+    /// Like [`insert_by`](Transaction::insert_by), with a value already encoded by the value
+    /// codec.
+    async fn insert_encoded(&self, writer: Option<WriterId>, key: Key, value: Value) -> Result<()> {
+        let _status = self.check_allow_write().await?;
+        self.check_claim(&key, writer).await?;
+        if self.inner.buffer.get(&key).await.is_some() {
             return Err(Error::DuplicateKeyInsertion);
         }
         if self.is_pessimistic() {
             self.pessimistic_lock(iter::once(key.clone()), false)
                 .await?;
         }
-        self.buffer.insert(key, value.into()).await;
+        self.inner.buffer.claim(&key, writer).await?;
+        self.inner.buffer.insert(key, value).await;
         Ok(())
     }
 
+    /// Gets the value of the given key, or inserts `value` if the key does not exist.
+    ///
+    /// Returns the existing value, or `value` if it was inserted. In a pessimistic transaction the
+    /// key is locked when it is read. Otherwise, the write is an insert, so the commit fails if the
+    /// key is created by another transaction in the meantime.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Key, Value, Config, TransactionClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// let config = txn
+    ///     .get_or_insert("config".to_owned(), "defaults".to_owned())
+    ///     .await
+    ///     .unwrap();
+    /// // Finish the transaction...
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn get_or_insert(
+        &self,
+        key: impl Into<Key>,
+        value: impl Into<Value>,
+    ) -> Result<Value> {
+        let key = key.into();
+        let existing = if self.is_pessimistic() {
+            self.get_for_update(key.clone()).await?
+        } else {
+            self.get(key.clone()).await?
+        };
+        match existing {
+            Some(existing) => Ok(existing),
+            None => {
+                let value = value.into();
+                self.insert(key, value.clone()).await?;
+                Ok(value)
+            }
+        }
+    }
+
+    /// Copies the value of `src` to `dst`, which must not exist. Returns whether `src` exists; if
+    /// it doesn't, nothing is written.
+    ///
+    /// `src` is locked along with the read, so the commit fails if another transaction writes it
+    /// in the meantime. `dst` is written as an insert, so the commit also fails if it is created
+    /// by another transaction, and the copy fails with
+    /// [`DuplicateKeyInsertion`](Error::DuplicateKeyInsertion) if the transaction has read or
+    /// written a value of it. The value is copied as stored, without being decoded and encoded
+    /// again by the value codec of the transaction.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// let copied = txn
+    ///     .copy_key("user/1".to_owned(), "archive/user/1".to_owned())
+    ///     .await
+    ///     .unwrap();
+    /// // Finish the transaction...
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn copy_key(&self, src: impl Into<Key>, dst: impl Into<Key>) -> Result<bool> {
+        let src = src.into();
+        let value = if self.is_pessimistic() {
+            let _status = self.check_allow_write().await?;
+            self.pessimistic_lock(iter::once(src), true)
+                .await?
+                .pop()
+                .flatten()
+        } else {
+            let value = self.get_encoded(src.clone(), Options::new()).await?;
+            self.lock_keys(iter::once(src)).await?;
+            value
+        };
+        match value {
+            Some(value) => {
+                self.insert_encoded(None, dst.into(), value).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Moves the value of `src` to `dst`, which must not exist, deleting `src`. Returns whether
+    /// `src` exists; if it doesn't, nothing is written.
+    ///
+    /// See [`copy_key`](Transaction::copy_key) for how the keys are checked.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
+    /// let txn = client.begin_pessimistic().await.unwrap();
+    /// txn.move_key("tmp/upload".to_owned(), "files/report".to_owned())
+    ///     .await
+    ///     .unwrap();
+    /// // Finish the transaction...
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn move_key(&self, src: impl Into<Key>, dst: impl Into<Key>) -> Result<bool> {
+        let src = src.into();
+        if !self.copy_key(src.clone(), dst).await? {
+            return Ok(false);
+        }
+        self.delete_by(None, src).await?;
+        Ok(true)
+    }
+
     /// Deletes the given key.
     ///
-    /// Deleting a non-existent key will not result in an error.
+    /// Deleting a non-existent key will not result in an error. Like [`put`](Transaction::put),
+    /// fails if a writer of the transaction has written the key.
     ///
     /// # Examples
     /// ```rust,no_run
@@ -424,21 +809,34 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100", "192.168.0.101"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
     /// let key = "TiKV".to_owned();
     /// txn.delete(key);
     /// // Finish the transaction...
     /// txn.commit().await.unwrap();
     /// # });
     /// ```
-    pub async fn delete(&mut self, key: impl Into<Key>) -> Result<()> {
-        self.check_allow_operation().await?;
-        let key = key.into();
+    pub async fn delete(&self, key: impl Into<Key>) -> Result<()> {
+        self.delete_by(None, key.into()).await
+    }
+
+    /// Deletes the given key, on behalf of `writer`.
+    ///
+    /// Fails with [`LocalWriteConflict`](Error::LocalWriteConflict) if another writer of the
+    /// transaction has written the key. See [`put_as`](Transaction::put_as).
+    pub async fn delete_as(&self, writer: WriterId, key: impl Into<Key>) -> Result<()> {
+        self.delete_by(Some(writer), key.into()).await
+    }
+
+    async fn delete_by(&self, writer: Option<WriterId>, key: Key) -> Result<()> {
+        let _status = self.check_allow_write().await?;
+        self.check_claim(&key, writer).await?;
         if self.is_pessimistic() {
             self.pessimistic_lock(iter::once(key.clone()), false)
                 .await?;
         }
-        self.buffer.delete(key).await;
+        self.inner.buffer.claim(&key, writer).await?;
+        self.inner.buffer.delete(key).await;
         Ok(())
     }
 
@@ -450,7 +848,8 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// It's useful to avoid *write skew* anomaly.
     ///
     /// In pessimistic mode, it is similar to [`batch_get_for_update`](Transaction::batch_get_for_update),
-    /// except that it does not read values.
+    /// except that it does not read values. Use [`lock_keys_with_values`](Transaction::lock_keys_with_values)
+    /// to also read them.
     ///
     /// # Examples
     /// ```rust,no_run
@@ -458,21 +857,20 @@ impl<PdC: PdClient> Transaction<PdC> {
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
     /// txn.lock_keys(vec!["TiKV".to_owned(), "Rust".to_owned()]);
     /// // ... Do some actions.
     /// txn.commit().await.unwrap();
     /// # });
     /// ```
-    pub async fn lock_keys(
-        &mut self,
-        keys: impl IntoIterator<Item = impl Into<Key>>,
-    ) -> Result<()> {
-        self.check_allow_operation().await?;
-        match self.options.kind {
+    pub async fn lock_keys(&self, keys: impl IntoIterator<Item = impl Into<Key>>) -> Result<()> {
+        let _status = self.check_allow_write().await?;
+        match self.inner.options.kind {
             TransactionKind::Optimistic => {
                 for key in keys {
-                    self.buffer.lock(key.into()).await;
+                    let key = key.into();
+                    self.inner.buffer.check_not_flushed(&key).await?;
+                    self.inner.buffer.lock(key).await;
                 }
             }
             TransactionKind::Pessimistic(_) => {
@@ -483,112 +881,488 @@ impl<PdC: PdClient> Transaction<PdC> {
         Ok(())
     }
 
-    /// Commits the actions of the transaction. On success, we return the commit timestamp (or None
-    /// if there was nothing to commit).
+    /// The timestamp the transaction started at, which identifies it.
+    pub fn start_timestamp(&self) -> Timestamp {
+        self.inner.timestamp.clone()
+    }
+
+    /// The primary key of the transaction, if it has locked or written any key.
+    ///
+    /// With [`start_timestamp`](Transaction::start_timestamp), identifies the transaction to
+    /// [`resolve_commit_status`](crate::TransactionClient::resolve_commit_status).
+    pub async fn primary_key(&self) -> Option<Key> {
+        self.inner.buffer.get_primary_key().await
+    }
+
+    /// Make `key` the primary key of the transaction.
+    ///
+    /// The primary key holds the state of the transaction: it is committed first, and other
+    /// transactions finding locks of the transaction check its status on the primary key. By
+    /// default, the first key locked or written by the transaction becomes the primary key.
+    /// Choosing a small key in a region with little load (an "anchor" key) can reduce commit
+    /// latency and make lock resolution cheaper.
+    ///
+    /// The key is locked like by [`lock_keys`](Transaction::lock_keys), so this must be called
+    /// before any key is locked or written. Returns
+    /// [`Error::PrimaryKeyAlreadyChosen`](crate::Error::PrimaryKeyAlreadyChosen) otherwise,
+    /// unless `key` is already the primary key.
     ///
     /// # Examples
     /// ```rust,no_run
-    /// # use tikv_client::{Config, Timestamp, TransactionClient};
+    /// # use tikv_client::{Config, TransactionClient};
     /// # use futures::prelude::*;
     /// # futures::executor::block_on(async {
     /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
-    /// let mut txn = client.begin_optimistic().await.unwrap();
-    /// // ... Do some actions.
-    /// let req = txn.commit();
-    /// let result: Timestamp = req.await.unwrap().unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// txn.set_primary_key("anchor".to_owned()).await.unwrap();
+    /// txn.put("TiKV".to_owned(), "Rust".to_owned()).await.unwrap();
+    /// txn.commit().await.unwrap();
     /// # });
     /// ```
-    pub async fn commit(&mut self) -> Result<Option<Timestamp>> {
-        {
-            let mut status = self.status.write().await;
-            if !matches!(
-                *status,
-                TransactionStatus::StartedCommit | TransactionStatus::Active
-            ) {
-                return Err(Error::OperationAfterCommitError);
+    pub async fn set_primary_key(&self, key: impl Into<Key>) -> Result<()> {
+        let status = self.check_allow_write().await?;
+        let key = key.into();
+        match self.inner.buffer.get_primary_key().await {
+            Some(primary_key) if primary_key == key => Ok(()),
+            Some(primary_key) => Err(Error::PrimaryKeyAlreadyChosen {
+                key: primary_key.into(),
+            }),
+            None => {
+                drop(status);
+                self.lock_keys(iter::once(key)).await
             }
-            *status = TransactionStatus::StartedCommit;
-        }
-
-        let primary_key = self.buffer.get_primary_key().await;
-        let mutations = self.buffer.to_proto_mutations().await;
-        if mutations.is_empty() {
-            assert!(primary_key.is_none());
-            return Ok(None);
-        }
-
-        self.start_auto_heartbeat().await;
-
-        let res = Committer::new(
-            primary_key,
-            mutations,
-            self.timestamp.clone(),
-            self.rpc.clone(),
-            self.options.clone(),
-        )
-        .commit()
-        .await;
-
-        if res.is_ok() {
-            let mut status = self.status.write().await;
-            *status = TransactionStatus::Committed;
         }
-        res
     }
 
-    /// Rollback the transaction.
+    /// Lock the given keys like [`lock_keys`](Transaction::lock_keys), and return the value of
+    /// every key in order.
     ///
-    /// If it succeeds, all mutations made by this transaciton will not take effect.
-    pub async fn rollback(&mut self) -> Result<()> {
-        {
-            let status = self.status.read().await;
-            if !matches!(
-                *status,
-                TransactionStatus::StartedRollback | TransactionStatus::Active
-            ) {
-                return Err(Error::OperationAfterCommitError);
+    /// In pessimistic mode, the values are returned by the same requests which lock the keys,
+    /// saving a round of gets in read-modify-write flows. Like
+    /// [`get_for_update`](Transaction::get_for_update), they are the latest values rather than
+    /// the values at the start of the transaction. In optimistic mode, the values are read at the
+    /// start of the transaction, as by [`batch_get_ordered`](Transaction::batch_get_ordered).
+    ///
+    /// Entries of non-existent keys are `None`.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, TransactionClient, Value};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client.begin_pessimistic().await.unwrap();
+    /// let values: Vec<Option<Value>> = txn
+    ///     .lock_keys_with_values(vec!["TiKV".to_owned(), "Rust".to_owned()])
+    ///     .await
+    ///     .unwrap();
+    /// // ... Do some actions.
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn lock_keys_with_values(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<Option<Value>>> {
+        let status = self.check_allow_write().await?;
+        let keys: Vec<Key> = keys.into_iter().map(Into::into).collect();
+        match self.inner.options.kind {
+            TransactionKind::Optimistic => {
+                for key in &keys {
+                    self.inner.buffer.lock(key.clone()).await;
+                }
+                drop(status);
+                self.batch_get_ordered(keys).await
             }
+            TransactionKind::Pessimistic(_) if keys.is_empty() => Ok(Vec::new()),
+            TransactionKind::Pessimistic(_) => self
+                .pessimistic_lock(keys, true)
+                .await?
+                .into_iter()
+                .map(|value| {
+                    value
+                        .map(|value| self.inner.options.value_codec.decode(value))
+                        .transpose()
+                })
+                .collect(),
         }
+    }
 
-        {
-            let mut status = self.status.write().await;
-            *status = TransactionStatus::StartedRollback;
-        }
-
-        let primary_key = self.buffer.get_primary_key().await;
-        let mutations = self.buffer.to_proto_mutations().await;
+    /// Commits the actions of the transaction. On success, we return the commit timestamp (or None
+    /// if there was nothing to commit).
+    ///
+    /// The transaction is committed once its primary key is. By default, this returns as soon as
+    /// the primary key is committed, and the other keys are committed in a background task. Until
+    /// they are, reading them may need extra requests to resolve their locks. With
+    /// [`wait_for_secondaries`](TransactionOptions::wait_for_secondaries), this also waits for the
+    /// other keys. Use [`commit_detached`](Transaction::commit_detached) to wait for them later.
+    ///
+    /// If the transaction was not committed because of a conflict with another transaction, the
+    /// error is [retryable](Error::is_retryable_transaction) and the transaction may be retried
+    /// from the start. If the response which would have told whether it was committed was lost,
+    /// the error is an [`UndeterminedError`](Error::UndeterminedError), and the transaction must
+    /// not be retried blindly.
+    ///
+    /// The commit runs in a background task, so dropping the returned future doesn't interrupt it
+    /// midway. Use [`commit_with_cancellation`](Transaction::commit_with_cancellation) to cancel
+    /// it.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, Timestamp, TransactionClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// // ... Do some actions.
+    /// let req = txn.commit();
+    /// let result: Timestamp = req.await.unwrap().unwrap();
+    /// # });
+    /// ```
+    pub async fn commit(&self) -> Result<Option<Timestamp>> {
+        self.commit_and_wait(None).await
+    }
+
+    /// Commits the transaction like [`commit`](Transaction::commit), unless `token` is cancelled
+    /// first.
+    ///
+    /// Cancellation is cooperative: it is only honoured before the primary key is committed, in
+    /// which case the keys prewritten are rolled back, the transaction is rolled back, and this
+    /// fails with [`OperationCanceled`](Error::OperationCanceled). Once the primary key is
+    /// committed, the transaction is committed regardless of the token. Transactions committed
+    /// with async commit or 1PC are committed by their prewrite, so the token is only checked
+    /// before.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{CancellationToken, TransactionClient};
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// // ... Do some actions.
+    /// let token = CancellationToken::new();
+    /// let commit = txn.commit_with_cancellation(token.clone());
+    /// // ... From another task, e.g. when shutting down:
+    /// token.cancel();
+    /// # });
+    /// ```
+    pub async fn commit_with_cancellation(
+        &self,
+        token: CancellationToken,
+    ) -> Result<Option<Timestamp>> {
+        self.commit_and_wait(Some(token)).await
+    }
+
+    async fn commit_and_wait(
+        &self,
+        cancellation: Option<CancellationToken>,
+    ) -> Result<Option<Timestamp>> {
+        let (commit_ts, secondaries) = self.spawn_commit(cancellation).await?;
+        if let (true, Some(secondaries)) = (self.inner.options.wait_for_secondaries, secondaries) {
+            // Failures are logged by the task. The transaction is committed regardless, and the
+            // remaining locks are resolved by readers.
+            let _ = secondaries.await;
+        }
+        Ok(commit_ts)
+    }
+
+    /// Commits the actions of the transaction like [`commit`](Transaction::commit), returning
+    /// as soon as the primary key is committed.
+    ///
+    /// Also returns the handle of the background task committing the other keys, if any. The
+    /// transaction is committed even if the task fails, but the locks of the keys it failed to
+    /// commit are left for readers to resolve.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{Config, Timestamp, TransactionClient};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// // ... Do some actions.
+    /// let (commit_ts, secondaries) = txn.commit_detached().await.unwrap();
+    /// // ... Respond to the user.
+    /// if let Some(secondaries) = secondaries {
+    ///     secondaries.await.unwrap().unwrap();
+    /// }
+    /// # });
+    /// ```
+    pub async fn commit_detached(
+        &self,
+    ) -> Result<(Option<Timestamp>, Option<JoinHandle<Result<()>>>)> {
+        self.spawn_commit(None).await
+    }
+
+    /// Commit in a background task, so the commit isn't interrupted midway, leaving locks behind,
+    /// if the caller drops its future.
+    async fn spawn_commit(
+        &self,
+        cancellation: Option<CancellationToken>,
+    ) -> Result<(Option<Timestamp>, Option<JoinHandle<Result<()>>>)> {
+        let txn = self.clone();
+        crate::util::task::run_detached("txn-commit", async move {
+            txn.commit_inner(cancellation).await
+        })
+        .await
+    }
+
+    async fn commit_inner(
+        &self,
+        cancellation: Option<CancellationToken>,
+    ) -> Result<(Option<Timestamp>, Option<JoinHandle<Result<()>>>)> {
+        {
+            let mut status = self.inner.status.write().await;
+            if !matches!(
+                *status,
+                TransactionStatus::StartedCommit | TransactionStatus::Active
+            ) {
+                return Err(Error::OperationAfterCommitError);
+            }
+            *status = TransactionStatus::StartedCommit;
+        }
+
+        let primary_key = self.inner.buffer.get_primary_key().await;
+        let mutations = self.inner.buffer.to_proto_mutations().await;
+        let flushed_keys = self.inner.buffer.flushed_keys().await;
+        let hooks = &self.inner.options.hooks;
+        let event = if hooks.is_empty() {
+            None
+        } else {
+            Some(self.event().writes(&mutations, &flushed_keys))
+        };
+        if mutations.is_empty() && flushed_keys.is_empty() {
+            assert!(primary_key.is_none());
+            if let (Some(on_commit), Some(event)) = (&hooks.on_commit, event) {
+                on_commit(&event);
+            }
+            return Ok((None, None));
+        }
+
+        self.start_auto_heartbeat().await;
+
+        let mut options = self.committer_options();
+        if !flushed_keys.is_empty() {
+            // Flushed keys are prewritten without the secondaries async commit needs.
+            options.async_commit = false;
+            options.try_one_pc = false;
+        }
+        let features = self.inner.rpc.features();
+        if options.async_commit && !features.async_commit {
+            debug!(
+                target: "tikv_client::txn",
+                "async commit is not supported by the cluster, falling back to 2PC"
+            );
+            options.async_commit = false;
+        }
+        if options.try_one_pc && !features.one_pc {
+            debug!(
+                target: "tikv_client::txn",
+                "1PC is not supported by the cluster, falling back to 2PC"
+            );
+            options.try_one_pc = false;
+        }
+
+        let res = Committer::new(
+            primary_key,
+            mutations,
+            self.inner.timestamp.clone(),
+            self.inner.rpc.clone(),
+            options,
+        )
+        .flushed_keys(flushed_keys)
+        .cancellation(cancellation)
+        .on_prewrite(hooks.on_prewrite.clone().zip(event.clone()))
+        .commit()
+        .await;
+
+        match &res {
+            Ok((commit_ts, _)) => {
+                *self.inner.status.write().await = TransactionStatus::Committed;
+                if let (Some(on_commit), Some(event)) = (&hooks.on_commit, event) {
+                    on_commit(&event.committed(commit_ts.clone()));
+                }
+            }
+            // The committer rolled the transaction back.
+            Err(Error::OperationCanceled) => {
+                *self.inner.status.write().await = TransactionStatus::Rolledback;
+                if let (Some(on_rollback), Some(event)) = (&hooks.on_rollback, event) {
+                    on_rollback(&event);
+                }
+            }
+            Err(_) => {}
+        }
+        res
+    }
+
+    /// Rollback the transaction.
+    ///
+    /// If it succeeds, all mutations made by this transaciton will not take effect.
+    ///
+    /// Like commits, rollbacks run in a background task and complete even if the returned future
+    /// is dropped.
+    pub async fn rollback(&self) -> Result<()> {
+        let txn = self.clone();
+        crate::util::task::run_detached("txn-rollback", async move { txn.rollback_inner().await })
+            .await
+    }
+
+    async fn rollback_inner(&self) -> Result<()> {
+        {
+            let mut status = self.inner.status.write().await;
+            if !matches!(
+                *status,
+                TransactionStatus::StartedRollback | TransactionStatus::Active
+            ) {
+                return Err(Error::OperationAfterCommitError);
+            }
+            *status = TransactionStatus::StartedRollback;
+        }
+
+        let primary_key = self.inner.buffer.get_primary_key().await;
+        let mutations = self.inner.buffer.to_proto_mutations().await;
+        let flushed_keys = self.inner.buffer.flushed_keys().await;
+        let event = self
+            .inner
+            .options
+            .hooks
+            .on_rollback
+            .as_ref()
+            .map(|on_rollback| (on_rollback, self.event().writes(&mutations, &flushed_keys)));
         let res = Committer::new(
             primary_key,
             mutations,
-            self.timestamp.clone(),
-            self.rpc.clone(),
-            self.options.clone(),
+            self.inner.timestamp.clone(),
+            self.inner.rpc.clone(),
+            self.committer_options(),
         )
+        .flushed_keys(flushed_keys)
         .rollback()
         .await;
 
         if res.is_ok() {
-            let mut status = self.status.write().await;
+            let mut status = self.inner.status.write().await;
             *status = TransactionStatus::Rolledback;
+            if let Some((on_rollback, event)) = event {
+                on_rollback(&event);
+            }
         }
         res
     }
 
+    /// Prewrite the mutations buffered so far, so that their values don't need to be kept until
+    /// the commit.
+    ///
+    /// The keys are locked in TiKV as by the first phase of the commit, which then only prewrites
+    /// the mutations made after the last flush before committing all keys. This bounds the
+    /// memory used by transactions writing more data than fits in memory: only the keys of
+    /// flushed mutations are kept. Flushed keys can't be read or written again by the
+    /// transaction, which fails with [`KeyFlushed`](Error::KeyFlushed), including scans of ranges
+    /// containing them. Transactions which flushed are committed with 2PC, even if they use
+    /// async commit or 1PC.
+    ///
+    /// The locks of flushed keys block other transactions until the transaction ends, and are
+    /// kept alive by heart beats. If the flush fails, some of the mutations may have been
+    /// prewritten, and the transaction can only be rolled back.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::TransactionClient;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let txn = client.begin_optimistic().await.unwrap();
+    /// for i in 0..1_000_000u32 {
+    ///     txn.put(i.to_be_bytes().to_vec(), vec![0; 1024]).await.unwrap();
+    ///     if i % 10_000 == 0 {
+    ///         txn.flush().await.unwrap();
+    ///     }
+    /// }
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn flush(&self) -> Result<()> {
+        // Writes wait for the flush, so that no mutation is changed while it is prewritten.
+        let mut status = self.inner.status.write().await;
+        if *status != TransactionStatus::Active {
+            return Err(Error::OperationAfterCommitError);
+        }
+        let primary_key = self.inner.buffer.get_primary_key().await;
+        let mutations = self.inner.buffer.to_proto_mutations().await;
+        if mutations.is_empty() {
+            return Ok(());
+        }
+        // The primary key is among the mutations of the first flush, so its lock exists before
+        // the locks of the secondary keys refer to it.
+        self.start_auto_heartbeat().await;
+
+        let mut options = self.committer_options();
+        options.async_commit = false;
+        options.try_one_pc = false;
+        let keys: Vec<Key> = mutations.iter().map(|m| m.key.clone().into()).collect();
+        let res = Committer::new(
+            primary_key,
+            mutations,
+            self.inner.timestamp.clone(),
+            self.inner.rpc.clone(),
+            options,
+        )
+        .prewrite()
+        .await;
+        // After a failure, the keys are rolled back like flushed ones, as some may be prewritten.
+        self.inner.buffer.mark_flushed(keys).await;
+        match res {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                *status = TransactionStatus::StartedRollback;
+                Err(e)
+            }
+        }
+    }
+
     /// Send a heart beat message to keep the transaction alive on the server and update its TTL.
     ///
-    /// Returns the TTL set on the lock by the server.
-    pub async fn send_heart_beat(&mut self) -> Result<u64> {
+    /// The locks of the transaction are extended to expire [`lock_ttl`](TransactionOptions::lock_ttl)
+    /// from now. Transactions send heart beats automatically every
+    /// [`heartbeat_interval`](TransactionOptions::heartbeat_interval) once they hold locks; with
+    /// automatic heart beats disabled by [`no_auto_hearbeat`](TransactionOptions::no_auto_hearbeat),
+    /// long transactions must call this more often than the lock TTL, or their locks may be
+    /// resolved by other transactions.
+    ///
+    /// Returns the TTL set on the lock by the server, in milliseconds since the start of the
+    /// transaction.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use tikv_client::{TransactionClient, TransactionOptions};
+    /// # use futures::prelude::*;
+    /// # futures::executor::block_on(async {
+    /// # let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+    /// let options = TransactionOptions::new_pessimistic().no_auto_hearbeat();
+    /// let txn = client.begin_with_options(options).await.unwrap();
+    /// txn.put("key".to_owned(), "value".to_owned()).await.unwrap();
+    /// // ... Do some long-running actions, sending heart beats in between.
+    /// txn.send_heart_beat().await.unwrap();
+    /// txn.commit().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn send_heart_beat(&self) -> Result<u64> {
         self.check_allow_operation().await?;
-        let primary_key = match self.buffer.get_primary_key().await {
+        let primary_key = match self.inner.buffer.get_primary_key().await {
             Some(k) => k,
             None => return Err(Error::NoPrimaryKey),
         };
-        let request = new_heart_beat_request(self.timestamp.clone(), primary_key, DEFAULT_LOCK_TTL);
-        let plan = PlanBuilder::new(self.rpc.clone(), request)
+        let ttl = heartbeat_ttl(
+            &self.inner.rpc,
+            &self.inner.timestamp,
+            self.inner.options.lock_ttl,
+        )
+        .await?;
+        let request = new_heart_beat_request(self.inner.timestamp.clone(), primary_key, ttl);
+        let plan = PlanBuilder::new(self.inner.rpc.clone(), request)
             .single_region()
             .await?
-            .resolve_lock(self.options.retry_options.lock_backoff.clone())
-            .retry_region(self.options.retry_options.region_backoff.clone())
+            .resolve_lock(self.inner.options.retry_options.lock_backoff.clone())
+            .retry_region(self.inner.options.retry_options.region_backoff.clone())
             .post_process_default()
             .plan();
         plan.execute().await
@@ -598,17 +1372,19 @@ impl<PdC: PdClient> Transaction<PdC> {
         &self,
         range: impl Into<BoundRange>,
         limit: u32,
-        key_only: bool,
+        options: &Options,
     ) -> Result<impl Iterator<Item = KvPair>> {
         self.check_allow_operation().await?;
-        let timestamp = self.timestamp.clone();
-        let rpc = self.rpc.clone();
-        let retry_options = self.options.retry_options.clone();
+        let timestamp = self.read_timestamp(options).await?;
+        let rpc = self.inner.rpc.clone().with_options(options);
+        let retry_options = self.retry_options(options);
+        let key_only = options.key_only;
 
-        self.buffer
-            .scan_and_fetch(
+        options
+            .run(self.inner.buffer.scan_and_fetch(
                 range.into(),
                 limit,
+                !key_only && !options.read_committed,
                 move |new_range, new_limit| async move {
                     let request = new_scan_request(new_range, timestamp, new_limit, key_only);
                     let plan = PlanBuilder::new(rpc, request)
@@ -621,65 +1397,219 @@ impl<PdC: PdClient> Transaction<PdC> {
                         .await
                         .map(|r| r.into_iter().map(Into::into).collect())
                 },
-            )
+            ))
             .await
     }
 
+    /// The timestamp reads with `options` are made at: the start timestamp of the transaction,
+    /// or a new one to read committed data.
+    async fn read_timestamp(&self, options: &Options) -> Result<Timestamp> {
+        if !options.read_committed {
+            return Ok(self.inner.timestamp.clone());
+        }
+        if !self.is_pessimistic() {
+            return Err(Error::InvalidTransactionType);
+        }
+        self.inner.rpc.clone().get_timestamp().await
+    }
+
+    /// The retry options of an operation with `options`.
+    fn retry_options(&self, options: &Options) -> RetryOptions {
+        options
+            .retry_options
+            .clone()
+            .unwrap_or_else(|| self.inner.options.retry_options.clone())
+    }
+
+    /// Scan several ranges in one plan, returning at most `each_limit` pairs of each range.
+    ///
+    /// The pairs of each range are ordered by key, and ranges are in the order given. Doesn't read
+    /// the local buffer, so must only be used by read-only transactions.
+    pub(crate) async fn batch_scan(
+        &self,
+        ranges: impl IntoIterator<Item = impl Into<BoundRange>>,
+        each_limit: u32,
+    ) -> Result<Vec<KvPair>> {
+        debug_assert!(self.inner.options.read_only);
+        self.check_allow_operation().await?;
+        let ranges: Vec<BoundRange> = ranges.into_iter().map(Into::into).collect();
+        let request = new_batch_scan_request(
+            ranges.iter().cloned(),
+            self.inner.timestamp.clone(),
+            each_limit,
+        );
+        let plan = PlanBuilder::new(self.inner.rpc.clone(), request)
+            .resolve_lock(self.inner.options.retry_options.lock_backoff.clone())
+            .multi_region()
+            .retry_region(self.inner.options.retry_options.region_backoff.clone())
+            .merge(Collect)
+            .plan();
+        let mut pairs = plan.execute().await?;
+
+        // Every scan reads the same snapshot, so the pairs of a range are the first `each_limit`
+        // pairs in it, wherever they were scanned. A key in overlapping ranges is scanned by each
+        // of them.
+        pairs.sort_by(|a, b| a.key().cmp(b.key()));
+        pairs.dedup_by(|a, b| a.key() == b.key());
+        let mut result = Vec::new();
+        for range in ranges {
+            let start = pairs.partition_point(|pair| match range.start_bound() {
+                Bound::Included(start) => pair.key() < start,
+                Bound::Excluded(start) => pair.key() <= start,
+                Bound::Unbounded => false,
+            });
+            result.extend(
+                pairs[start..]
+                    .iter()
+                    .take_while(|pair| range.contains(pair.key()))
+                    .take(each_limit as usize)
+                    .cloned(),
+            );
+        }
+        Ok(result)
+    }
+
     /// Pessimistically lock the keys.
     ///
     /// Once resolved it acquires a lock on the key in TiKV.
     /// The lock prevents other transactions from mutating the entry until it is released.
     ///
+    /// If `need_value` is true, returns the value of every key, in the order of `keys`.
+    ///
     /// Only valid for pessimistic transactions, panics if called on an optimistic transaction.
     async fn pessimistic_lock(
-        &mut self,
+        &self,
         keys: impl IntoIterator<Item = Key>,
         need_value: bool,
     ) -> Result<Vec<Option<Value>>> {
         assert!(
-            matches!(self.options.kind, TransactionKind::Pessimistic(_)),
+            matches!(self.inner.options.kind, TransactionKind::Pessimistic(_)),
             "`pessimistic_lock` is only valid to use with pessimistic transactions"
         );
 
         let keys: Vec<Key> = keys.into_iter().collect();
+        for key in &keys {
+            self.inner.buffer.check_not_flushed(key).await?;
+        }
         let first_key = keys[0].clone();
-        let primary_lock = self.buffer.get_primary_key_or(&first_key).await;
-        let lock_ttl = DEFAULT_LOCK_TTL;
-        let for_update_ts = self.rpc.clone().get_timestamp().await?;
-        self.options.push_for_update_ts(for_update_ts.clone());
+        let has_primary_key = self.inner.buffer.get_primary_key().await.is_some();
+        let primary_lock = self.inner.buffer.get_primary_key_or(&first_key).await;
+        let lock_ttl = self.inner.options.lock_ttl;
+        let for_update_ts = self.inner.rpc.clone().get_timestamp().await?;
+        self.inner
+            .for_update_ts
+            .fetch_max(for_update_ts.version(), Ordering::SeqCst);
         let request = new_pessimistic_lock_request(
             keys.clone().into_iter(),
             primary_lock,
-            self.timestamp.clone(),
+            self.inner.timestamp.clone(),
             lock_ttl,
-            for_update_ts,
+            for_update_ts.clone(),
             need_value,
         );
-        let plan = PlanBuilder::new(self.rpc.clone(), request)
-            .resolve_lock(self.options.retry_options.lock_backoff.clone())
+        let plan = PlanBuilder::new(self.inner.rpc.clone(), request)
+            .resolve_lock(self.inner.options.retry_options.lock_backoff.clone())
             .multi_region()
-            .retry_region(self.options.retry_options.region_backoff.clone())
+            .retry_region(self.inner.options.retry_options.region_backoff.clone())
             .merge(Collect)
             .plan();
-        let values = plan
-            .execute()
-            .await
-            .map(|r| r.into_iter().map(Into::into).collect());
+        let rollback_on_drop = RollbackLockOnDrop {
+            txn: Some(self.clone()),
+            keys: keys.clone(),
+            for_update_ts: for_update_ts.clone(),
+            has_primary_key,
+        };
+        // Values are returned in the order of the sorted keys, see `Shardable` for the request.
+        let values = plan.execute().await.map(|values: Vec<Option<Value>>| {
+            let mut sorted_keys = keys.clone();
+            sorted_keys.sort();
+            let pairs = sorted_keys
+                .into_iter()
+                .zip(values)
+                .filter_map(|(key, value)| Some(KvPair(key, value?)));
+            align_to_keys(&keys, pairs)
+        });
+        rollback_on_drop.disarm();
+        if values.is_err() {
+            self.rollback_failed_lock(keys, for_update_ts, has_primary_key)
+                .await;
+            return values;
+        }
 
         self.start_auto_heartbeat().await;
 
         for key in keys {
-            self.buffer.lock(key).await;
+            self.inner.buffer.lock(key).await;
         }
 
         values
     }
 
+    /// Roll back the pessimistic locks acquired by a failed `pessimistic_lock` of `keys`.
+    ///
+    /// Some keys may have been locked before the request failed, e.g. in other regions. Unless
+    /// rolled back, they would block other transactions until the transaction ends or their TTL
+    /// expires. Keys locked by earlier operations of the transaction stay locked.
+    async fn rollback_failed_lock(
+        &self,
+        keys: Vec<Key>,
+        for_update_ts: Timestamp,
+        has_primary_key: bool,
+    ) {
+        if !has_primary_key {
+            self.inner.buffer.reset_primary_key().await;
+        }
+        let mut new_keys = Vec::with_capacity(keys.len());
+        for key in keys {
+            if !self.inner.buffer.is_locked(&key).await {
+                new_keys.push(key);
+            }
+        }
+        if new_keys.is_empty() {
+            return;
+        }
+        let request = new_pessimistic_rollback_request(
+            new_keys.into_iter(),
+            self.inner.timestamp.clone(),
+            for_update_ts,
+        );
+        let plan = PlanBuilder::new(self.inner.rpc.clone(), request)
+            .resolve_lock(self.inner.options.retry_options.lock_backoff.clone())
+            .multi_region()
+            .retry_region(self.inner.options.retry_options.region_backoff.clone())
+            .extract_error()
+            .plan();
+        if let Err(e) = plan.execute().await {
+            warn!(
+                target: "tikv_client::txn",
+                start_ts = self.inner.timestamp.version(),
+                error = %e,
+                "failed to roll back locks of a failed pessimistic lock"
+            );
+        }
+    }
+
+    /// Check that `key` can be written by `writer`. The writer is only recorded once the write
+    /// is buffered, so a write which fails to lock its key doesn't keep it from other writers.
+    async fn check_claim(&self, key: &Key, writer: Option<WriterId>) -> Result<()> {
+        self.inner.buffer.check_not_flushed(key).await?;
+        self.inner.buffer.check_claim(key, writer).await
+    }
+
     /// Checks if the transaction can perform arbitrary operations.
     async fn check_allow_operation(&self) -> Result<()> {
-        let status = self.status.read().await;
+        self.check_allow_write().await.map(drop)
+    }
+
+    /// Checks if the transaction can perform arbitrary operations, for a write.
+    ///
+    /// Writes hold the returned guard until they are done, so commits and rollbacks wait for
+    /// them. Operations holding it must not call operations which check the status again, or
+    /// they may deadlock with a commit waiting for the guard.
+    async fn check_allow_write(&self) -> Result<RwLockReadGuard<'_, TransactionStatus>> {
+        let status = self.inner.status.read().await;
         match *status {
-            TransactionStatus::ReadOnly | TransactionStatus::Active => Ok(()),
+            TransactionStatus::ReadOnly | TransactionStatus::Active => Ok(status),
             TransactionStatus::Committed
             | TransactionStatus::Rolledback
             | TransactionStatus::StartedCommit
@@ -688,29 +1618,43 @@ impl<PdC: PdClient> Transaction<PdC> {
         }
     }
 
-    fn is_pessimistic(&self) -> bool {
-        matches!(self.options.kind, TransactionKind::Pessimistic(_))
+    /// The options to commit or roll back the transaction with.
+    fn committer_options(&self) -> TransactionOptions {
+        let mut options = self.inner.options.clone();
+        if let TransactionKind::Pessimistic(for_update_ts) = &mut options.kind {
+            *for_update_ts =
+                Timestamp::from_version(self.inner.for_update_ts.load(Ordering::SeqCst));
+        }
+        options
+    }
+
+    pub(crate) fn is_pessimistic(&self) -> bool {
+        matches!(self.inner.options.kind, TransactionKind::Pessimistic(_))
     }
 
-    async fn start_auto_heartbeat(&mut self) {
-        if !self.options.auto_heartbeat || self.is_heartbeat_started {
+    async fn start_auto_heartbeat(&self) {
+        if !self.inner.options.auto_heartbeat
+            || self.inner.is_heartbeat_started.swap(true, Ordering::SeqCst)
+        {
             return;
         }
-        self.is_heartbeat_started = true;
 
-        let status = self.status.clone();
+        let status = self.inner.status.clone();
         let primary_key = self
+            .inner
             .buffer
             .get_primary_key()
             .await
             .expect("Primary key should exist");
-        let start_ts = self.timestamp.clone();
-        let region_backoff = self.options.retry_options.region_backoff.clone();
-        let rpc = self.rpc.clone();
+        let start_ts = self.inner.timestamp.clone();
+        let region_backoff = self.inner.options.retry_options.region_backoff.clone();
+        let rpc = self.inner.rpc.clone();
+        let lock_ttl = self.inner.options.lock_ttl;
+        let interval = self.inner.options.heartbeat_interval;
 
         let heartbeat_task = async move {
             loop {
-                tokio::time::sleep(DEFAULT_HEARTBEAT_INTERVAL).await;
+                tokio::time::sleep(interval).await;
                 {
                     let status = status.read().await;
                     if matches!(
@@ -722,12 +1666,8 @@ impl<PdC: PdClient> Transaction<PdC> {
                         break;
                     }
                 }
-                let current_ts = rpc.clone().get_timestamp().await?;
-                let request = new_heart_beat_request(
-                    start_ts.clone(),
-                    primary_key.clone(),
-                    (current_ts.physical - start_ts.physical) as u64 + DEFAULT_LOCK_TTL,
-                );
+                let ttl = heartbeat_ttl(&rpc, &start_ts, lock_ttl).await?;
+                let request = new_heart_beat_request(start_ts.clone(), primary_key.clone(), ttl);
                 let plan = PlanBuilder::new(rpc.clone(), request)
                     .single_region()
                     .await?
@@ -738,15 +1678,66 @@ impl<PdC: PdClient> Transaction<PdC> {
             Ok::<(), Error>(())
         };
 
-        tokio::spawn(async {
+        crate::util::task::spawn("txn-heartbeat", async {
             if let Err(err) = heartbeat_task.await {
-                error!("Error: While sending heartbeat. {}", err);
+                error!(target: "tikv_client::txn", error = %err, "failed to send heartbeat");
             }
         });
     }
 }
 
-impl<PdC: PdClient> Drop for Transaction<PdC> {
+/// Rolls back the pessimistic locks of a `pessimistic_lock` whose future was dropped while its
+/// request was in flight, like those of a failed one. Some keys may have been locked, but they
+/// aren't recorded by the transaction, so committing or rolling it back would leave them locked.
+struct RollbackLockOnDrop<PdC: PdClient> {
+    txn: Option<Transaction<PdC>>,
+    keys: Vec<Key>,
+    for_update_ts: Timestamp,
+    has_primary_key: bool,
+}
+
+impl<PdC: PdClient> RollbackLockOnDrop<PdC> {
+    fn disarm(mut self) {
+        self.txn = None;
+    }
+}
+
+impl<PdC: PdClient> Drop for RollbackLockOnDrop<PdC> {
+    fn drop(&mut self) {
+        let txn = match self.txn.take() {
+            Some(txn) => txn,
+            None => return,
+        };
+        if tokio::runtime::Handle::try_current().is_err() {
+            warn!(
+                target: "tikv_client::txn",
+                start_ts = txn.inner.timestamp.version(),
+                "can't roll back the locks of a cancelled pessimistic lock outside of a runtime"
+            );
+            return;
+        }
+        let keys = std::mem::take(&mut self.keys);
+        let for_update_ts = self.for_update_ts.clone();
+        let has_primary_key = self.has_primary_key;
+        crate::util::task::spawn("txn-pessimistic-rollback", async move {
+            txn.rollback_failed_lock(keys, for_update_ts, has_primary_key)
+                .await
+        });
+    }
+}
+
+/// The TTL making locks of a transaction expire `lock_ttl` milliseconds from now. TTLs of locks
+/// count from the start of their transaction.
+async fn heartbeat_ttl(
+    rpc: &Arc<impl PdClient>,
+    start_ts: &Timestamp,
+    lock_ttl: u64,
+) -> Result<u64> {
+    let current_ts = rpc.clone().get_timestamp().await?;
+    Ok((current_ts.physical - start_ts.physical).max(0) as u64 + lock_ttl)
+}
+
+impl<PdC: PdClient> Drop for TransactionInner<PdC> {
     fn drop(&mut self) {
         if std::thread::panicking() {
             return;
@@ -758,7 +1749,11 @@ impl<PdC: PdClient> Drop for Transaction<PdC> {
                     panic!("Dropping an active transaction. Consider commit or rollback it.")
                 }
                 CheckLevel::Warn => {
-                    warn!("Dropping an active transaction. Consider commit or rollback it.")
+                    warn!(
+                        target: "tikv_client::txn",
+                        start_ts = self.timestamp.version(),
+                        "Dropping an active transaction. Consider commit or rollback it."
+                    )
                 }
 
                 CheckLevel::None => {}
@@ -785,14 +1780,91 @@ pub struct TransactionOptions {
     try_one_pc: bool,
     /// Try to use async commit (default is not to).
     async_commit: bool,
+    /// Transactions exceeding these limits are committed with 2PC rather than async commit.
+    async_commit_limits: AsyncCommitLimits,
+    /// Limits on the prewrite and commit requests sent to a region.
+    batch_limits: BatchLimits,
     /// Is the transaction read only? (Default is no).
     read_only: bool,
-    /// How to retry in the event of certain errors.
+    /// How to retry in the event of certain errors. Those not overridden below are replaced by
+    /// the defaults of the client when the transaction begins.
     retry_options: RetryOptions,
+    region_backoff: Option<Backoff>,
+    lock_backoff: Option<Backoff>,
     /// What to do if the transaction is dropped without an attempt to commit or rollback
     check_level: CheckLevel,
     /// Whether heartbeat will be sent automatically
     auto_heartbeat: bool,
+    /// Whether `commit` waits for secondary keys to be committed.
+    wait_for_secondaries: bool,
+    /// How often heartbeats are sent automatically.
+    heartbeat_interval: Duration,
+    /// The TTL of locks in milliseconds, extended by every heartbeat.
+    lock_ttl: u64,
+    /// Transforms values written and read by the transaction.
+    value_codec: SharedValueCodec,
+    /// Whether snapshots read from replicas rather than leaders.
+    pub(crate) replica_read: bool,
+    /// The lifecycle callbacks of the client which began the transaction.
+    pub(crate) hooks: TransactionHooks,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct AsyncCommitLimits {
+    max_keys: usize,
+    max_key_size: usize,
+    max_regions: Option<usize>,
+}
+
+impl Default for AsyncCommitLimits {
+    fn default() -> AsyncCommitLimits {
+        // The defaults of TiDB.
+        AsyncCommitLimits {
+            max_keys: 256,
+            max_key_size: 4096,
+            max_regions: None,
+        }
+    }
+}
+
+/// Identifies a writer of a transaction, such as one of several tasks sharing it.
+///
+/// Writes made with [`put_as`](Transaction::put_as), [`insert_as`](Transaction::insert_as)
+/// and [`delete_as`](Transaction::delete_as) record their writer for each key. A write to a key
+/// already written by another writer fails with
+/// [`LocalWriteConflict`](Error::LocalWriteConflict), instead of silently replacing the buffered
+/// value. Writes made without a writer, with [`put`](Transaction::put) and friends, are not
+/// tracked, so writers may overwrite them, but they fail the same way on keys written by a
+/// writer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct WriterId(u64);
+
+impl WriterId {
+    /// A new writer, distinct from all others in the process.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> WriterId {
+        static NEXT_WRITER_ID: AtomicU64 = AtomicU64::new(1);
+        WriterId(NEXT_WRITER_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// The number identifying the writer in errors.
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
+
+/// How a transaction is committed, see [`commit_mode`](TransactionOptions::commit_mode).
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum CommitMode {
+    /// Two-phase commit: prewrite all keys, then commit the primary key.
+    TwoPhase,
+    /// Async commit: the transaction is committed once all keys are prewritten, saving a round
+    /// trip.
+    AsyncCommit,
+    /// One-phase commit, if all keys are in one region.
+    OnePc,
+    /// One-phase commit if all keys are in one region, or else async commit.
+    OnePcOrAsyncCommit,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -815,10 +1887,20 @@ impl TransactionOptions {
             kind: TransactionKind::Optimistic,
             try_one_pc: false,
             async_commit: false,
+            async_commit_limits: AsyncCommitLimits::default(),
+            batch_limits: DEFAULT_BATCH_LIMITS,
             read_only: false,
             retry_options: RetryOptions::default_optimistic(),
+            region_backoff: None,
+            lock_backoff: None,
             check_level: CheckLevel::Panic,
             auto_heartbeat: true,
+            wait_for_secondaries: false,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            lock_ttl: DEFAULT_LOCK_TTL,
+            value_codec: SharedValueCodec::default(),
+            replica_read: false,
+            hooks: TransactionHooks::default(),
         }
     }
 
@@ -828,69 +1910,240 @@ impl TransactionOptions {
             kind: TransactionKind::Pessimistic(Timestamp::from_version(0)),
             try_one_pc: false,
             async_commit: false,
+            async_commit_limits: AsyncCommitLimits::default(),
+            batch_limits: DEFAULT_BATCH_LIMITS,
             read_only: false,
             retry_options: RetryOptions::default_pessimistic(),
+            region_backoff: None,
+            lock_backoff: None,
             check_level: CheckLevel::Panic,
             auto_heartbeat: true,
+            wait_for_secondaries: false,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            lock_ttl: DEFAULT_LOCK_TTL,
+            value_codec: SharedValueCodec::default(),
+            replica_read: false,
+            hooks: TransactionHooks::default(),
         }
     }
 
-    /// Try to use async commit.
-    pub fn use_async_commit(mut self) -> TransactionOptions {
-        self.async_commit = true;
-        self
+    /// Options for short optimistic transactions, such as serving requests, which should commit
+    /// with as few round trips as possible.
+    ///
+    /// Commits with 1PC if all keys are in one region, or else with async commit, falling back
+    /// to 2PC. Retries and lock resolution are those of
+    /// [`new_optimistic`](TransactionOptions::new_optimistic).
+    pub fn optimistic_low_latency() -> TransactionOptions {
+        TransactionOptions::new_optimistic().commit_mode(CommitMode::OnePcOrAsyncCommit)
     }
 
-    /// Try to use 1pc.
-    pub fn try_one_pc(mut self) -> TransactionOptions {
-        self.try_one_pc = true;
-        self
+    /// Options for long pessimistic transactions writing many keys, such as batch jobs.
+    ///
+    /// Commits with 2PC, as async commit is limited to few keys, and waits for all keys to be
+    /// committed. Locks live for 20 seconds, with a heartbeat every 10 seconds, so a slow batch
+    /// doesn't lose its locks between heartbeats.
+    pub fn pessimistic_bulk_write() -> TransactionOptions {
+        TransactionOptions::new_pessimistic()
+            .commit_mode(CommitMode::TwoPhase)
+            .wait_for_secondaries()
+            .lock_ttl(Duration::from_secs(20))
+            .heartbeat_interval(Duration::from_secs(10))
     }
 
-    /// Make the transaction read only.
-    pub fn read_only(mut self) -> TransactionOptions {
-        self.read_only = true;
+    /// Options for read-only snapshots scanning a lot of data, such as reports.
+    ///
+    /// Reads from replicas, sparing the leaders, and never locks keys.
+    pub fn analytics_read() -> TransactionOptions {
+        TransactionOptions::new_optimistic()
+            .read_only()
+            .replica_read()
+            .auto_heartbeat(false)
+    }
+
+    /// Set how the transaction is committed (default: [`CommitMode::TwoPhase`]).
+    ///
+    /// Transactions which can't be committed with 1PC or async commit fall back to 2PC. Async
+    /// commit is limited to transactions with few keys, see
+    /// [`async_commit_keys_limit`](TransactionOptions::async_commit_keys_limit),
+    /// [`async_commit_key_size_limit`](TransactionOptions::async_commit_key_size_limit) and
+    /// [`async_commit_regions_limit`](TransactionOptions::async_commit_regions_limit).
+    pub fn commit_mode(mut self, mode: CommitMode) -> TransactionOptions {
+        self.try_one_pc = matches!(mode, CommitMode::OnePc | CommitMode::OnePcOrAsyncCommit);
+        self.async_commit = matches!(
+            mode,
+            CommitMode::AsyncCommit | CommitMode::OnePcOrAsyncCommit
+        );
         self
     }
 
-    /// Don't automatically resolve locks and retry if keys are locked.
-    pub fn no_resolve_locks(mut self) -> TransactionOptions {
-        self.retry_options.lock_backoff = Backoff::no_backoff();
+    /// Try to use async commit. Same as
+    /// [`commit_mode(CommitMode::AsyncCommit)`](TransactionOptions::commit_mode), unless 1PC is
+    /// also used.
+    pub fn use_async_commit(mut self) -> TransactionOptions {
+        self.async_commit = true;
         self
     }
 
-    /// Don't automatically resolve regions with PD if we have outdated region information.
-    pub fn no_resolve_regions(mut self) -> TransactionOptions {
-        self.retry_options.region_backoff = Backoff::no_backoff();
+    /// Fall back to 2PC if the transaction writes or locks more than `limit` keys (default: 256).
+    ///
+    /// All keys are recorded in the lock of the primary key for async commit, and must be checked
+    /// when the lock is resolved.
+    pub fn async_commit_keys_limit(mut self, limit: usize) -> TransactionOptions {
+        self.async_commit_limits.max_keys = limit;
         self
     }
 
-    /// Set RetryOptions.
-    pub fn retry_options(mut self, options: RetryOptions) -> TransactionOptions {
-        self.retry_options = options;
+    /// Fall back to 2PC if the keys the transaction writes or locks total more than `limit`
+    /// bytes (default: 4096).
+    pub fn async_commit_key_size_limit(mut self, limit: usize) -> TransactionOptions {
+        self.async_commit_limits.max_key_size = limit;
         self
     }
 
-    /// Set the behavior when dropping a transaction without an attempt to commit or rollback it.
+    /// Fall back to 2PC if the keys the transaction writes or locks span more than `limit`
+    /// regions (default: no limit).
+    ///
+    /// Resolving a lock of an async commit transaction needs a request to every region of its
+    /// keys.
+    pub fn async_commit_regions_limit(mut self, limit: usize) -> TransactionOptions {
+        self.async_commit_limits.max_regions = Some(limit);
+        self
+    }
+
+    /// Send at most `limit` keys in each prewrite and commit request (default: 4096).
+    ///
+    /// The keys of each region are split into as many requests as needed.
+    pub fn batch_keys_limit(mut self, limit: usize) -> TransactionOptions {
+        self.batch_limits.max_keys = limit;
+        self
+    }
+
+    /// Send at most `limit` bytes of keys and values in each prewrite and commit request
+    /// (default: 16 KiB).
+    ///
+    /// Large requests may exceed the size limits of gRPC messages or of raft entries. A key and
+    /// value larger than `limit` is sent in a request of its own.
+    pub fn batch_size_limit(mut self, limit: usize) -> TransactionOptions {
+        self.batch_limits.max_bytes = limit;
+        self
+    }
+
+    /// Try to use 1pc. Same as [`commit_mode(CommitMode::OnePc)`](TransactionOptions::commit_mode),
+    /// unless async commit is also used.
+    pub fn try_one_pc(mut self) -> TransactionOptions {
+        self.try_one_pc = true;
+        self
+    }
+
+    /// Make the transaction read only.
+    pub fn read_only(mut self) -> TransactionOptions {
+        self.read_only = true;
+        self
+    }
+
+    /// Read from replicas rather than from the leaders of regions. Only used by snapshots.
+    ///
+    /// Each read is sent to the replica of its region, leader or follower, whose store has the
+    /// lowest observed latency and error rate (see
+    /// [`store_stats`](crate::TransactionClient::store_stats)), which can cut tail latency when
+    /// replicas are spread over distant zones. Followers check with the leader that they are up
+    /// to date before reading, so reads are still consistent. Locks are resolved on leaders.
+    pub fn replica_read(mut self) -> TransactionOptions {
+        self.replica_read = true;
+        self
+    }
+
+    /// Don't automatically resolve locks and retry if keys are locked.
+    pub fn no_resolve_locks(self) -> TransactionOptions {
+        self.lock_backoff(Backoff::no_backoff())
+    }
+
+    /// Don't automatically resolve regions with PD if we have outdated region information.
+    pub fn no_resolve_regions(self) -> TransactionOptions {
+        self.region_backoff(Backoff::no_backoff())
+    }
+
+    /// Set how to retry when keys are locked by other transactions, after resolving the locks
+    /// (default: the lock backoff of the [`Config`](crate::Config) of the client).
+    pub fn lock_backoff(mut self, backoff: Backoff) -> TransactionOptions {
+        self.retry_options.lock_backoff = backoff.clone();
+        self.lock_backoff = Some(backoff);
+        self
+    }
+
+    /// Set how to retry when region information is outdated, after updating it from PD
+    /// (default: the region backoff of the [`Config`](crate::Config) of the client).
+    pub fn region_backoff(mut self, backoff: Backoff) -> TransactionOptions {
+        self.retry_options.region_backoff = backoff.clone();
+        self.region_backoff = Some(backoff);
+        self
+    }
+
+    /// Set RetryOptions, overriding the defaults of the [`Config`](crate::Config) of the client.
+    pub fn retry_options(self, options: RetryOptions) -> TransactionOptions {
+        self.region_backoff(options.region_backoff)
+            .lock_backoff(options.lock_backoff)
+    }
+
+    /// Use `defaults` for the retry options which were not set.
+    fn default_retry_options(mut self, defaults: RetryOptions) -> TransactionOptions {
+        self.retry_options = RetryOptions {
+            region_backoff: self
+                .region_backoff
+                .clone()
+                .unwrap_or(defaults.region_backoff),
+            lock_backoff: self.lock_backoff.clone().unwrap_or(defaults.lock_backoff),
+        };
+        self
+    }
+
+    /// Set the behavior when dropping a transaction without an attempt to commit or rollback it.
     pub fn drop_check(mut self, level: CheckLevel) -> TransactionOptions {
         self.check_level = level;
         self
     }
 
-    fn push_for_update_ts(&mut self, for_update_ts: Timestamp) {
-        match &mut self.kind {
-            TransactionKind::Optimistic => unreachable!(),
-            TransactionKind::Pessimistic(old_for_update_ts) => {
-                self.kind = TransactionKind::Pessimistic(Timestamp::from_version(std::cmp::max(
-                    old_for_update_ts.version(),
-                    for_update_ts.version(),
-                )));
-            }
-        }
+    /// Don't send heartbeats automatically. Same as
+    /// [`auto_heartbeat(false)`](TransactionOptions::auto_heartbeat).
+    pub fn no_auto_hearbeat(self) -> TransactionOptions {
+        self.auto_heartbeat(false)
+    }
+
+    /// Set whether heartbeats are sent automatically (default: true). Long transactions without
+    /// them must send heartbeats with [`Transaction::send_heart_beat`](Transaction::send_heart_beat).
+    pub fn auto_heartbeat(mut self, enabled: bool) -> TransactionOptions {
+        self.auto_heartbeat = enabled;
+        self
+    }
+
+    /// Make [`Transaction::commit`](Transaction::commit) wait until all keys are committed,
+    /// rather than returning once the primary key is committed.
+    ///
+    /// This adds the latency of committing the other keys to commits, but no reader has to
+    /// resolve locks left by the transaction once it is committed.
+    pub fn wait_for_secondaries(mut self) -> TransactionOptions {
+        self.wait_for_secondaries = true;
+        self
     }
 
-    pub fn no_auto_hearbeat(mut self) -> TransactionOptions {
-        self.auto_heartbeat = false;
+    /// Set how often heartbeats are sent automatically (default: 1 second).
+    pub fn heartbeat_interval(mut self, interval: Duration) -> TransactionOptions {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// Set the TTL of locks (default: 3 seconds).
+    ///
+    /// If the transaction stops sending heartbeats, e.g. because its client crashed, its locks
+    /// can be resolved by other transactions once the TTL has passed since the last heartbeat.
+    pub fn lock_ttl(mut self, ttl: Duration) -> TransactionOptions {
+        self.lock_ttl = ttl.as_millis() as u64;
+        self
+    }
+
+    /// Transform values written and read by the transaction with `codec`.
+    pub fn value_codec(mut self, codec: impl ValueCodec) -> TransactionOptions {
+        self.value_codec = SharedValueCodec::new(codec);
         self
     }
 }
@@ -899,6 +2152,11 @@ impl TransactionOptions {
 const DEFAULT_LOCK_TTL: u64 = 3000;
 /// The default heartbeat interval.
 const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// The default limits of prewrite and commit requests, those of TiDB for the size.
+const DEFAULT_BATCH_LIMITS: BatchLimits = BatchLimits {
+    max_keys: 4096,
+    max_bytes: 16 * 1024,
+};
 
 /// A struct wrapping the details of two-phase commit protocol (2PC).
 ///
@@ -914,48 +2172,127 @@ struct Committer<PdC: PdClient = PdRpcClient> {
     start_version: Timestamp,
     rpc: Arc<PdC>,
     options: TransactionOptions,
+    // Keys prewritten by earlier flushes, which are committed or rolled back with the mutations.
+    #[new(default)]
+    flushed_keys: Vec<Key>,
     #[new(default)]
     undetermined: bool,
+    // Whether prewrite requests were sent, so a rollback must remove prewrite locks.
+    #[new(default)]
+    prewritten: bool,
+    #[new(default)]
+    cancellation: Option<CancellationToken>,
+    #[new(default)]
+    on_prewrite: Option<(TransactionCallback, TransactionEvent)>,
 }
 
 impl<PdC: PdClient> Committer<PdC> {
-    async fn commit(mut self) -> Result<Option<Timestamp>> {
-        let min_commit_ts = self.prewrite().await?;
+    fn flushed_keys(mut self, keys: Vec<Key>) -> Self {
+        self.flushed_keys = keys;
+        self
+    }
+
+    /// Roll back rather than commit if `token` is cancelled before the primary key is committed.
+    fn cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Report the transaction to a lifecycle callback once all its keys are prewritten.
+    fn on_prewrite(mut self, on_prewrite: Option<(TransactionCallback, TransactionEvent)>) -> Self {
+        self.on_prewrite = on_prewrite;
+        self
+    }
+
+    fn is_cancelled(&self) -> bool {
+        matches!(&self.cancellation, Some(token) if token.is_cancelled())
+    }
+
+    /// Roll back a commit which was cancelled.
+    async fn rollback_cancelled<T>(self) -> Result<T> {
+        debug!(
+            target: "tikv_client::txn",
+            start_ts = self.start_version.version(),
+            "commit was cancelled, rolling back"
+        );
+        self.rollback().await?;
+        Err(Error::OperationCanceled)
+    }
+
+    /// Commits the transaction, returning the commit timestamp and the task committing the
+    /// secondary keys.
+    async fn commit(mut self) -> Result<(Option<Timestamp>, Option<JoinHandle<Result<()>>>)> {
+        if self.is_cancelled() {
+            return self.rollback_cancelled().await;
+        }
+        let prewrite = if self.mutations.is_empty() {
+            // Everything was prewritten by flushes.
+            Ok(None)
+        } else {
+            self.prewritten = true;
+            self.prewrite().await
+        };
+        let min_commit_ts = match prewrite {
+            Ok(min_commit_ts) => min_commit_ts,
+            Err(e) => return Err(self.commit_error(e)),
+        };
+        if let Some((on_prewrite, event)) = &self.on_prewrite {
+            on_prewrite(event);
+        }
 
         fail_point!("after-prewrite");
 
         // If we didn't use 1pc, prewrite will set `try_one_pc` to false.
         if self.options.try_one_pc {
-            return Ok(min_commit_ts);
+            return Ok((min_commit_ts, None));
         }
 
+        // If we didn't use async commit, prewrite will set `async_commit` to false.
         let commit_ts = if self.options.async_commit {
-            // FIXME: min_commit_ts == 0 => fallback to normal 2PC
             min_commit_ts.unwrap()
         } else {
+            // The transaction isn't committed until its primary key is, so it can still be rolled
+            // back.
+            if self.is_cancelled() {
+                return self.rollback_cancelled().await;
+            }
             match self.commit_primary().await {
                 Ok(commit_ts) => commit_ts,
-                Err(e) => {
-                    return if self.undetermined {
-                        Err(Error::UndeterminedError(Box::new(e)))
-                    } else {
-                        Err(e)
-                    };
-                }
+                Err(e) => return Err(self.commit_error(e)),
             }
         };
-        tokio::spawn(self.commit_secondary(commit_ts.clone()).map(|res| {
-            if let Err(e) = res {
-                warn!("Failed to commit secondary keys: {}", e);
-            }
-        }));
-        Ok(Some(commit_ts))
+        let secondaries = crate::util::task::spawn(
+            "txn-commit-secondaries",
+            self.commit_secondary(commit_ts.clone()).inspect(|res| {
+                if let Err(e) = res {
+                    warn!(
+                        target: "tikv_client::txn",
+                        error = %e,
+                        "failed to commit secondary keys"
+                    );
+                }
+            }),
+        );
+        Ok((Some(commit_ts), Some(secondaries)))
+    }
+
+    /// Classifies an error of prewriting or committing the primary key: either the transaction
+    /// may have been committed, or it was not and may be retried, or it failed otherwise.
+    fn commit_error(&self, e: Error) -> Error {
+        if self.undetermined {
+            Error::UndeterminedError(Box::new(e))
+        } else {
+            not_committed_error(e)
+        }
     }
 
     async fn prewrite(&mut self) -> Result<Option<Timestamp>> {
+        if self.options.async_commit && !self.async_commit_allowed().await? {
+            self.options.async_commit = false;
+        }
         let primary_lock = self.primary_key.clone().unwrap();
         // FIXME: calculate TTL for big transactions
-        let lock_ttl = DEFAULT_LOCK_TTL;
+        let lock_ttl = self.options.lock_ttl;
         let mut request = match &self.options.kind {
             TransactionKind::Optimistic => new_prewrite_request(
                 self.mutations.clone(),
@@ -984,12 +2321,22 @@ impl<PdC: PdClient> Committer<PdC> {
 
         let plan = PlanBuilder::new(self.rpc.clone(), request)
             .resolve_lock(self.options.retry_options.lock_backoff.clone())
-            .multi_region()
+            .multi_region_with_limits(self.options.batch_limits)
             .retry_region(self.options.retry_options.region_backoff.clone())
             .merge(CollectError)
             .extract_error()
             .plan();
-        let response = plan.execute().await?;
+        let commits_on_prewrite = self.options.async_commit || self.options.try_one_pc;
+        let response = plan
+            .execute()
+            .inspect_err(|e| {
+                // With async commit or 1PC, the transaction may be committed by the prewrite
+                // whose response was lost.
+                if let (true, Error::Grpc(_)) = (commits_on_prewrite, e) {
+                    self.undetermined = true;
+                }
+            })
+            .await?;
 
         if self.options.try_one_pc && response.len() == 1 {
             if response[0].one_pc_commit_ts == 0 {
@@ -1001,6 +2348,16 @@ impl<PdC: PdClient> Committer<PdC> {
 
         self.options.try_one_pc = false;
 
+        // TiKV returns no min_commit_ts if it can't use async commit for a region.
+        if self.options.async_commit && response.iter().any(|r| r.min_commit_ts == 0) {
+            info!(
+                target: "tikv_client::txn",
+                start_ts = self.start_version.version(),
+                "async commit is not possible, falling back to 2PC"
+            );
+            self.options.async_commit = false;
+        }
+
         let min_commit_ts = response
             .iter()
             .map(|r| {
@@ -1013,6 +2370,43 @@ impl<PdC: PdClient> Committer<PdC> {
         Ok(min_commit_ts)
     }
 
+    /// Whether the mutations are within the limits for async commit.
+    async fn async_commit_allowed(&self) -> Result<bool> {
+        let limits = &self.options.async_commit_limits;
+        let key_size: usize = self.mutations.iter().map(|m| m.key.len()).sum();
+        if self.mutations.len() > limits.max_keys || key_size > limits.max_key_size {
+            info!(
+                target: "tikv_client::txn",
+                keys = self.mutations.len(),
+                key_size,
+                "too many keys for async commit, using 2PC"
+            );
+            return Ok(false);
+        }
+        if let Some(max_regions) = limits.max_regions {
+            let keys: Vec<Key> = self
+                .mutations
+                .iter()
+                .map(|m| m.key.clone().into())
+                .collect();
+            let regions = self
+                .rpc
+                .clone()
+                .group_keys_by_region(keys.into_iter())
+                .try_fold(0, |count, _: (_, Vec<Key>)| future::ready(Ok(count + 1)))
+                .await?;
+            if regions > max_regions {
+                info!(
+                    target: "tikv_client::txn",
+                    regions,
+                    "keys in too many regions for async commit, using 2PC"
+                );
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     /// Commits the primary key and returns the commit version
     async fn commit_primary(&mut self) -> Result<Timestamp> {
         let primary_key = self.primary_key.clone().into_iter();
@@ -1022,10 +2416,12 @@ impl<PdC: PdClient> Committer<PdC> {
             self.start_version.clone(),
             commit_version.clone(),
         );
+        // Errors are collected before being extracted to keep their kind, see `commit_error`.
         let plan = PlanBuilder::new(self.rpc.clone(), req)
             .resolve_lock(self.options.retry_options.lock_backoff.clone())
             .multi_region()
             .retry_region(self.options.retry_options.region_backoff.clone())
+            .merge(CollectError)
             .extract_error()
             .plan();
         plan.execute()
@@ -1043,25 +2439,25 @@ impl<PdC: PdClient> Committer<PdC> {
     }
 
     async fn commit_secondary(self, commit_version: Timestamp) -> Result<()> {
-        let mutations_len = self.mutations.len();
-        let primary_only = mutations_len == 1;
-        let mutations = self.mutations.into_iter();
+        let primary_only = self.mutations.len() + self.flushed_keys.len() == 1;
+        let keys = self
+            .mutations
+            .into_iter()
+            .map(|m| m.key.into())
+            .chain(self.flushed_keys);
 
         let req = if self.options.async_commit {
-            let keys = mutations.map(|m| m.key.into());
             new_commit_request(keys, self.start_version, commit_version)
         } else if primary_only {
             return Ok(());
         } else {
             let primary_key = self.primary_key.unwrap();
-            let keys = mutations
-                .map(|m| m.key.into())
-                .filter(|key| &primary_key != key);
+            let keys = keys.filter(|key| &primary_key != key);
             new_commit_request(keys, self.start_version, commit_version)
         };
         let plan = PlanBuilder::new(self.rpc, req)
             .resolve_lock(self.options.retry_options.lock_backoff)
-            .multi_region()
+            .multi_region_with_limits(self.options.batch_limits)
             .retry_region(self.options.retry_options.region_backoff)
             .extract_error()
             .plan();
@@ -1070,16 +2466,22 @@ impl<PdC: PdClient> Committer<PdC> {
     }
 
     async fn rollback(self) -> Result<()> {
-        if self.options.kind == TransactionKind::Optimistic && self.mutations.is_empty() {
+        if self.options.kind == TransactionKind::Optimistic
+            && self.mutations.is_empty()
+            && self.flushed_keys.is_empty()
+        {
             return Ok(());
         }
+        // Pessimistic rollbacks don't remove prewrite locks.
+        let prewritten = self.prewritten || !self.flushed_keys.is_empty();
         let keys = self
             .mutations
             .into_iter()
-            .map(|mutation| mutation.key.into());
+            .map(|mutation| mutation.key.into())
+            .chain(self.flushed_keys);
         match self.options.kind {
-            TransactionKind::Optimistic => {
-                let req = new_batch_rollback_request(keys, self.start_version);
+            TransactionKind::Pessimistic(for_update_ts) if !prewritten => {
+                let req = new_pessimistic_rollback_request(keys, self.start_version, for_update_ts);
                 let plan = PlanBuilder::new(self.rpc, req)
                     .resolve_lock(self.options.retry_options.lock_backoff)
                     .multi_region()
@@ -1088,8 +2490,8 @@ impl<PdC: PdClient> Committer<PdC> {
                     .plan();
                 plan.execute().await?;
             }
-            TransactionKind::Pessimistic(for_update_ts) => {
-                let req = new_pessimistic_rollback_request(keys, self.start_version, for_update_ts);
+            _ => {
+                let req = new_batch_rollback_request(keys, self.start_version);
                 let plan = PlanBuilder::new(self.rpc, req)
                     .resolve_lock(self.options.retry_options.lock_backoff)
                     .multi_region()
@@ -1121,24 +2523,61 @@ enum TransactionStatus {
     Dropped,
 }
 
+/// Converts the errors of a transaction which was not committed, but may be if retried from the
+/// start, to [`WriteConflict`](Error::WriteConflict) or
+/// [`TransactionExpired`](Error::TransactionExpired).
+fn not_committed_error(e: Error) -> Error {
+    match e {
+        Error::KeyError(kvrpcpb::KeyError {
+            txn_not_found: Some(not_found),
+            ..
+        }) => Error::TransactionExpired {
+            start_ts: not_found.start_ts,
+            key: not_found.primary_key,
+        },
+        Error::KeyError(kvrpcpb::KeyError {
+            commit_ts_expired: Some(expired),
+            ..
+        }) => Error::TransactionExpired {
+            start_ts: expired.start_ts,
+            key: expired.key,
+        },
+        Error::MultipleErrors(errors) => {
+            let mut errors: Vec<Error> = errors.into_iter().map(not_committed_error).collect();
+            match errors.iter().position(Error::is_retryable_transaction) {
+                Some(i) => errors.swap_remove(i),
+                None => Error::MultipleErrors(errors),
+            }
+        }
+        e => e,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
+        codec::value::ValueCodec,
         mock::{MockKvClient, MockPdClient},
-        Transaction, TransactionOptions,
+        transaction::{
+            hooks::{TransactionCallback, TransactionHooks},
+            transaction::{CommitMode, TransactionKind},
+        },
+        Backoff, CancellationToken, Error, Key, KvPair, Options, RetryOptions, TimestampExt,
+        Transaction, TransactionEvent, TransactionOptions, Value, WriterId,
     };
     use fail::FailScenario;
     use std::{
         any::Any,
         io,
         sync::{
-            atomic::{AtomicUsize, Ordering},
-            Arc,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
         },
+        time::Duration,
     };
     use tikv_client_proto::{kvrpcpb, pdpb::Timestamp};
 
-    #[tokio::test]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_optimistic_heartbeat() -> Result<(), io::Error> {
         let scenario = FailScenario::setup();
         fail::cfg("after-prewrite", "sleep(10000)").unwrap();
@@ -1156,12 +2595,15 @@ mod tests {
             },
         )));
         let key1 = "key1".to_owned();
-        let mut heartbeat_txn = Transaction::new(
+        let heartbeat_txn = Transaction::new(
             Timestamp::default(),
             pd_client,
             TransactionOptions::new_optimistic(),
         );
         heartbeat_txn.put(key1.clone(), "foo").await.unwrap();
+        // The commit runs in a task, whose worker thread the failpoint blocks, so start the
+        // heartbeat beforehand rather than from the commit, for it to run on another worker.
+        heartbeat_txn.start_auto_heartbeat().await;
         let heartbeat_txn_handle = tokio::task::spawn_blocking(move || {
             assert!(futures::executor::block_on(heartbeat_txn.commit()).is_ok())
         });
@@ -1195,7 +2637,7 @@ mod tests {
             },
         )));
         let key1 = "key1".to_owned();
-        let mut heartbeat_txn = Transaction::new(
+        let heartbeat_txn = Transaction::new(
             Timestamp::default(),
             pd_client,
             TransactionOptions::new_pessimistic(),
@@ -1210,4 +2652,1145 @@ mod tests {
         assert!(heartbeats.load(Ordering::SeqCst) > 1);
         Ok(())
     }
+
+    #[derive(Debug)]
+    struct Reversed;
+
+    impl ValueCodec for Reversed {
+        fn encode(&self, mut value: Value) -> crate::Result<Value> {
+            value.reverse();
+            Ok(value)
+        }
+
+        fn decode(&self, value: Value) -> crate::Result<Value> {
+            self.encode(value)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_value_codec() {
+        let prewritten = Arc::new(Mutex::new(Vec::new()));
+        let prewritten_cloned = prewritten.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(prewrite) = req.downcast_ref::<kvrpcpb::PrewriteRequest>() {
+                    prewritten_cloned.lock().unwrap().extend(
+                        prewrite
+                            .mutations
+                            .iter()
+                            .map(|mutation| mutation.value.clone()),
+                    );
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic()
+                .no_auto_hearbeat()
+                .value_codec(Reversed),
+        );
+        txn.put("key1".to_owned(), "foo").await.unwrap();
+        assert_eq!(
+            txn.get("key1".to_owned()).await.unwrap(),
+            Some(b"foo".to_vec())
+        );
+        txn.commit().await.unwrap();
+        assert_eq!(*prewritten.lock().unwrap(), vec![b"oof".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_get_or_insert() {
+        let prewritten = Arc::new(Mutex::new(Vec::new()));
+        let prewritten_cloned = prewritten.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(_get) = req.downcast_ref::<kvrpcpb::GetRequest>() {
+                    let resp = kvrpcpb::GetResponse {
+                        not_found: true,
+                        ..Default::default()
+                    };
+                    return Ok(Box::new(resp) as Box<dyn Any>);
+                } else if let Some(prewrite) = req.downcast_ref::<kvrpcpb::PrewriteRequest>() {
+                    prewritten_cloned.lock().unwrap().extend(
+                        prewrite
+                            .mutations
+                            .iter()
+                            .map(|mutation| (mutation.op, mutation.value.clone())),
+                    );
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic().no_auto_hearbeat(),
+        );
+        assert_eq!(
+            txn.get_or_insert("key1".to_owned(), "foo").await.unwrap(),
+            b"foo".to_vec()
+        );
+        assert_eq!(
+            txn.get_or_insert("key1".to_owned(), "bar").await.unwrap(),
+            b"foo".to_vec()
+        );
+        txn.commit().await.unwrap();
+        assert_eq!(
+            *prewritten.lock().unwrap(),
+            vec![(kvrpcpb::Op::Insert as i32, b"foo".to_vec())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_key() {
+        let prewritten = Arc::new(Mutex::new(Vec::new()));
+        let prewritten_cloned = prewritten.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(get) = req.downcast_ref::<kvrpcpb::GetRequest>() {
+                    let resp = if get.key == b"src" {
+                        kvrpcpb::GetResponse {
+                            value: b"oof".to_vec(),
+                            ..Default::default()
+                        }
+                    } else {
+                        kvrpcpb::GetResponse {
+                            not_found: true,
+                            ..Default::default()
+                        }
+                    };
+                    return Ok(Box::new(resp) as Box<dyn Any>);
+                } else if let Some(prewrite) = req.downcast_ref::<kvrpcpb::PrewriteRequest>() {
+                    prewritten_cloned
+                        .lock()
+                        .unwrap()
+                        .extend(prewrite.mutations.iter().map(|mutation| {
+                            (mutation.op, mutation.key.clone(), mutation.value.clone())
+                        }));
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic()
+                .no_auto_hearbeat()
+                .value_codec(Reversed),
+        );
+        assert!(!txn
+            .move_key("missing".to_owned(), "dst".to_owned())
+            .await
+            .unwrap());
+        assert!(txn
+            .move_key("src".to_owned(), "dst".to_owned())
+            .await
+            .unwrap());
+        assert_eq!(
+            txn.get("dst".to_owned()).await.unwrap(),
+            Some(b"foo".to_vec())
+        );
+        assert_eq!(txn.get("src".to_owned()).await.unwrap(), None);
+        assert!(matches!(
+            txn.copy_key("dst".to_owned(), "dst".to_owned()).await,
+            Err(Error::DuplicateKeyInsertion)
+        ));
+        txn.commit().await.unwrap();
+
+        let mut prewritten = prewritten.lock().unwrap().clone();
+        prewritten.sort();
+        assert_eq!(
+            prewritten,
+            vec![
+                (kvrpcpb::Op::Del as i32, b"src".to_vec(), vec![]),
+                // A missing source is locked too, the result depends on it.
+                (kvrpcpb::Op::Lock as i32, b"missing".to_vec(), vec![]),
+                (kvrpcpb::Op::Insert as i32, b"dst".to_vec(), b"oof".to_vec()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_committed() {
+        let versions = Arc::new(Mutex::new(Vec::new()));
+        let versions_cloned = versions.clone();
+        let pd_client = Arc::new(
+            MockPdClient::new(MockKvClient::with_dispatch_hook(move |req: &dyn Any| {
+                if let Some(get) = req.downcast_ref::<kvrpcpb::GetRequest>() {
+                    versions_cloned.lock().unwrap().push(get.version);
+                    return Ok(Box::new(kvrpcpb::GetResponse {
+                        value: b"v".to_vec(),
+                        ..Default::default()
+                    }) as Box<dyn Any>);
+                }
+                if req.is::<kvrpcpb::PessimisticLockRequest>() {
+                    return Ok(
+                        Box::new(kvrpcpb::PessimisticLockResponse::default()) as Box<dyn Any>
+                    );
+                }
+                Ok(Box::new(kvrpcpb::PessimisticRollbackResponse::default()) as Box<dyn Any>)
+            }))
+            .with_timestamp(Timestamp::from_version(42)),
+        );
+        let txn = Transaction::new(
+            Timestamp::from_version(1),
+            pd_client.clone(),
+            TransactionOptions::new_pessimistic().no_auto_hearbeat(),
+        );
+        let read_committed = || Options::new().read_committed();
+        for _ in 0..2 {
+            assert_eq!(
+                txn.get_opt("k".to_owned(), read_committed()).await.unwrap(),
+                Some(b"v".to_vec())
+            );
+        }
+        // Snapshot reads are unaffected by the committed reads before them.
+        txn.get("k".to_owned()).await.unwrap();
+        txn.get("k".to_owned()).await.unwrap();
+        assert_eq!(*versions.lock().unwrap(), vec![42, 42, 1]);
+
+        // Writes of the transaction are still read back.
+        txn.put("k".to_owned(), "w".to_owned()).await.unwrap();
+        assert_eq!(
+            txn.get_opt("k".to_owned(), read_committed()).await.unwrap(),
+            Some(b"w".to_vec())
+        );
+        assert_eq!(versions.lock().unwrap().len(), 3);
+        txn.rollback().await.unwrap();
+
+        let txn = Transaction::new(
+            Timestamp::from_version(1),
+            pd_client,
+            TransactionOptions::new_optimistic().no_auto_hearbeat(),
+        );
+        assert!(matches!(
+            txn.get_opt("k".to_owned(), read_committed()).await,
+            Err(Error::InvalidTransactionType)
+        ));
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lock_keys_with_values() {
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(lock) = req.downcast_ref::<kvrpcpb::PessimisticLockRequest>() {
+                    assert!(lock.return_values);
+                    // Every existing key has its reversed key as value.
+                    let (values, not_founds) = lock
+                        .mutations
+                        .iter()
+                        .map(|mutation| match mutation.key.as_slice() {
+                            b"missing" => (Vec::new(), true),
+                            key => (key.iter().rev().cloned().collect(), false),
+                        })
+                        .unzip();
+                    let resp = kvrpcpb::PessimisticLockResponse {
+                        values,
+                        not_founds,
+                        ..Default::default()
+                    };
+                    return Ok(Box::new(resp) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::PessimisticRollbackResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_pessimistic().no_auto_hearbeat(),
+        );
+        assert_eq!(
+            txn.lock_keys_with_values(vec!["kb".to_owned(), "missing".to_owned(), "ka".to_owned()])
+                .await
+                .unwrap(),
+            vec![Some(b"bk".to_vec()), None, Some(b"ak".to_vec())]
+        );
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan_keys() {
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(scan) = req.downcast_ref::<kvrpcpb::ScanRequest>() {
+                    assert!(scan.key_only);
+                    let pairs = ["a", "b", "c"]
+                        .iter()
+                        .map(|key| kvrpcpb::KvPair {
+                            key: key.as_bytes().to_vec(),
+                            ..Default::default()
+                        })
+                        .collect();
+                    let resp = kvrpcpb::ScanResponse {
+                        pairs,
+                        ..Default::default()
+                    };
+                    return Ok(Box::new(resp) as Box<dyn Any>);
+                } else if let Some(get) = req.downcast_ref::<kvrpcpb::GetRequest>() {
+                    let resp = kvrpcpb::GetResponse {
+                        value: get.key.iter().rev().cloned().collect(),
+                        ..Default::default()
+                    };
+                    return Ok(Box::new(resp) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::BatchRollbackResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic().no_auto_hearbeat(),
+        );
+        assert_eq!(txn.get("a".to_owned()).await.unwrap(), Some(b"a".to_vec()));
+        txn.put("d".to_owned(), "d".to_owned()).await.unwrap();
+        txn.delete("b".to_owned()).await.unwrap();
+        assert_eq!(
+            txn.scan_keys("a".to_owned().."z".to_owned(), 10)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![
+                Key::from("a".to_owned()),
+                "c".to_owned().into(),
+                "d".to_owned().into()
+            ]
+        );
+        // Values are not fetched by the scan, so they must not be cached.
+        assert_eq!(txn.get("c".to_owned()).await.unwrap(), Some(b"c".to_vec()));
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_batch_scan() {
+        let scans = Arc::new(AtomicUsize::new(0));
+        let scans_cloned = scans.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                let scan = req.downcast_ref::<kvrpcpb::ScanRequest>().unwrap();
+                scans_cloned.fetch_add(1, Ordering::SeqCst);
+                // The mock regions are split at key [10].
+                let pairs = [5u8, 6, 7, 12, 13, 14]
+                    .iter()
+                    .filter(|k| [**k].as_ref() >= scan.start_key.as_slice())
+                    .filter(|k| [**k].as_ref() < scan.end_key.as_slice())
+                    .take(scan.limit as usize)
+                    .map(|k| kvrpcpb::KvPair {
+                        key: vec![*k],
+                        value: vec![*k],
+                        ..Default::default()
+                    })
+                    .collect();
+                let resp = kvrpcpb::ScanResponse {
+                    pairs,
+                    ..Default::default()
+                };
+                Ok(Box::new(resp) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic()
+                .read_only()
+                .no_auto_hearbeat(),
+        );
+        let keys: Vec<Vec<u8>> = txn
+            .batch_scan(
+                vec![vec![6]..vec![13], vec![13]..vec![20], vec![5]..vec![8]],
+                2,
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|pair| pair.0.into())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![vec![6], vec![7], vec![13], vec![14], vec![5], vec![6]]
+        );
+        // The first range spans both regions.
+        assert_eq!(scans.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_failed_pessimistic_lock() {
+        let primary_locks = Arc::new(Mutex::new(Vec::new()));
+        let rolled_back = Arc::new(Mutex::new(Vec::new()));
+        let primary_locks_cloned = primary_locks.clone();
+        let rolled_back_cloned = rolled_back.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(lock) = req.downcast_ref::<kvrpcpb::PessimisticLockRequest>() {
+                    primary_locks_cloned
+                        .lock()
+                        .unwrap()
+                        .push(lock.primary_lock.clone());
+                    let mut resp = kvrpcpb::PessimisticLockResponse::default();
+                    if lock.mutations.iter().any(|m| m.key.starts_with(b"fail")) {
+                        resp.errors.push(kvrpcpb::KeyError {
+                            abort: "failed".to_owned(),
+                            ..Default::default()
+                        });
+                    }
+                    return Ok(Box::new(resp) as Box<dyn Any>);
+                } else if let Some(rollback) =
+                    req.downcast_ref::<kvrpcpb::PessimisticRollbackRequest>()
+                {
+                    rolled_back_cloned
+                        .lock()
+                        .unwrap()
+                        .extend(rollback.keys.iter().cloned());
+                }
+                Ok(Box::new(kvrpcpb::PessimisticRollbackResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_pessimistic().no_auto_hearbeat(),
+        );
+        assert!(txn
+            .lock_keys(vec!["fail1".to_owned(), "a".to_owned()])
+            .await
+            .is_err());
+        let mut keys = rolled_back.lock().unwrap().split_off(0);
+        keys.sort();
+        assert_eq!(keys, vec![b"a".to_vec(), b"fail1".to_vec()]);
+
+        // The failed statement doesn't choose the primary key, and only its new locks are
+        // rolled back.
+        txn.lock_keys(vec!["b".to_owned()]).await.unwrap();
+        assert!(txn
+            .lock_keys(vec!["b".to_owned(), "fail2".to_owned()])
+            .await
+            .is_err());
+        assert_eq!(*rolled_back.lock().unwrap(), vec![b"fail2".to_vec()]);
+        assert_eq!(
+            primary_locks.lock().unwrap()[1..].to_vec(),
+            vec![b"b".to_vec(); 2]
+        );
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commit_with_cancellation() {
+        let token = CancellationToken::new();
+        let token_cloned = token.clone();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_cloned = requests.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if req.downcast_ref::<kvrpcpb::PrewriteRequest>().is_some() {
+                    requests_cloned.lock().unwrap().push("prewrite");
+                    // Cancelled while prewriting.
+                    token_cloned.cancel();
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                } else if req
+                    .downcast_ref::<kvrpcpb::BatchRollbackRequest>()
+                    .is_some()
+                {
+                    requests_cloned.lock().unwrap().push("rollback");
+                    return Ok(Box::new(kvrpcpb::BatchRollbackResponse::default()) as Box<dyn Any>);
+                }
+                requests_cloned.lock().unwrap().push("commit");
+                Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client.clone(),
+            TransactionOptions::new_optimistic().no_auto_hearbeat(),
+        );
+        txn.put("key".to_owned(), "value").await.unwrap();
+        assert!(matches!(
+            txn.commit_with_cancellation(token).await,
+            Err(Error::OperationCanceled)
+        ));
+        assert_eq!(*requests.lock().unwrap(), vec!["prewrite", "rollback"]);
+        // The transaction was rolled back.
+        assert!(txn.put("key".to_owned(), "value").await.is_err());
+
+        // Nothing is sent when cancelled beforehand.
+        requests.lock().unwrap().clear();
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic().no_auto_hearbeat(),
+        );
+        txn.put("key".to_owned(), "value").await.unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(matches!(
+            txn.commit_with_cancellation(token).await,
+            Err(Error::OperationCanceled)
+        ));
+        assert!(!requests.lock().unwrap().contains(&"commit"));
+        assert!(!requests.lock().unwrap().contains(&"prewrite"));
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_hooks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let hook = |name: &'static str| {
+            let events = events.clone();
+            let callback: TransactionCallback = Arc::new(move |event: &TransactionEvent| {
+                events.lock().unwrap().push((
+                    name,
+                    event.start_timestamp().version(),
+                    event.commit_timestamp().is_some(),
+                    event.write_keys(),
+                    event.write_bytes(),
+                ))
+            });
+            Some(callback)
+        };
+        let hooks = TransactionHooks {
+            on_begin: hook("begin"),
+            on_prewrite: hook("prewrite"),
+            on_commit: hook("commit"),
+            on_rollback: hook("rollback"),
+        };
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if req.downcast_ref::<kvrpcpb::PrewriteRequest>().is_some() {
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                } else if req
+                    .downcast_ref::<kvrpcpb::BatchRollbackRequest>()
+                    .is_some()
+                {
+                    return Ok(Box::new(kvrpcpb::BatchRollbackResponse::default()) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let mut options = TransactionOptions::new_optimistic().no_auto_hearbeat();
+        options.hooks = hooks;
+
+        let txn = Transaction::new(
+            Timestamp::from_version(1),
+            pd_client.clone(),
+            options.clone(),
+        );
+        txn.put("k1".to_owned(), "v1").await.unwrap();
+        txn.put("k2".to_owned(), "v2").await.unwrap();
+        txn.commit().await.unwrap();
+        assert_eq!(
+            events.lock().unwrap().split_off(0),
+            vec![
+                ("begin", 1, false, 0, 0),
+                ("prewrite", 1, false, 2, 8),
+                ("commit", 1, true, 2, 8),
+            ]
+        );
+
+        let txn = Transaction::new(Timestamp::from_version(2), pd_client, options);
+        txn.put("k1".to_owned(), "v1").await.unwrap();
+        txn.rollback().await.unwrap();
+        assert_eq!(
+            events.lock().unwrap().split_off(0),
+            vec![("begin", 2, false, 0, 0), ("rollback", 2, false, 1, 4)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_primary_key() {
+        let prewrites = Arc::new(Mutex::new(Vec::new()));
+        let prewrites_cloned = prewrites.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(prewrite) = req.downcast_ref::<kvrpcpb::PrewriteRequest>() {
+                    prewrites_cloned.lock().unwrap().push(prewrite.clone());
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic().no_auto_hearbeat(),
+        );
+        txn.set_primary_key("anchor".to_owned()).await.unwrap();
+        txn.put("key".to_owned(), "value").await.unwrap();
+        txn.set_primary_key("anchor".to_owned()).await.unwrap();
+        assert!(matches!(
+            txn.set_primary_key("key".to_owned()).await,
+            Err(crate::Error::PrimaryKeyAlreadyChosen { key }) if key == b"anchor".to_vec()
+        ));
+        txn.commit().await.unwrap();
+
+        let prewrites = prewrites.lock().unwrap();
+        assert!(prewrites
+            .iter()
+            .all(|prewrite| prewrite.primary_lock == b"anchor".to_vec()));
+        let mut ops: Vec<_> = prewrites
+            .iter()
+            .flat_map(|prewrite| prewrite.mutations.iter())
+            .map(|mutation| (mutation.key.clone(), mutation.op))
+            .collect();
+        ops.sort();
+        assert_eq!(
+            ops,
+            vec![
+                (b"anchor".to_vec(), kvrpcpb::Op::Lock as i32),
+                (b"key".to_vec(), kvrpcpb::Op::Put as i32)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_secondaries() {
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let committed_cloned = committed.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(commit) = req.downcast_ref::<kvrpcpb::CommitRequest>() {
+                    committed_cloned
+                        .lock()
+                        .unwrap()
+                        .extend(commit.keys.iter().cloned());
+                    return Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let options = TransactionOptions::new_optimistic().no_auto_hearbeat();
+
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client.clone(),
+            options.clone().wait_for_secondaries(),
+        );
+        txn.put("key1".to_owned(), "foo").await.unwrap();
+        txn.put("key2".to_owned(), "bar").await.unwrap();
+        txn.commit().await.unwrap();
+        let mut keys = committed.lock().unwrap().split_off(0);
+        keys.sort();
+        assert_eq!(keys, vec![b"key1".to_vec(), b"key2".to_vec()]);
+
+        let txn = Transaction::new(Timestamp::default(), pd_client, options);
+        txn.put("key3".to_owned(), "foo").await.unwrap();
+        txn.put("key4".to_owned(), "bar").await.unwrap();
+        let (commit_ts, secondaries) = txn.commit_detached().await.unwrap();
+        assert!(commit_ts.is_some());
+        secondaries.unwrap().await.unwrap().unwrap();
+        let mut keys = committed.lock().unwrap().split_off(0);
+        keys.sort();
+        assert_eq!(keys, vec![b"key3".to_vec(), b"key4".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_flush() {
+        let prewritten = Arc::new(Mutex::new(Vec::new()));
+        let prewritten_cloned = prewritten.clone();
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let committed_cloned = committed.clone();
+        let rolled_back = Arc::new(Mutex::new(Vec::new()));
+        let rolled_back_cloned = rolled_back.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(prewrite) = req.downcast_ref::<kvrpcpb::PrewriteRequest>() {
+                    let keys: Vec<Vec<u8>> =
+                        prewrite.mutations.iter().map(|m| m.key.clone()).collect();
+                    prewritten_cloned
+                        .lock()
+                        .unwrap()
+                        .push((prewrite.primary_lock.clone(), keys));
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                } else if let Some(commit) = req.downcast_ref::<kvrpcpb::CommitRequest>() {
+                    committed_cloned
+                        .lock()
+                        .unwrap()
+                        .extend(commit.keys.iter().cloned());
+                    return Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>);
+                }
+                let rollback = req.downcast_ref::<kvrpcpb::BatchRollbackRequest>().unwrap();
+                rolled_back_cloned
+                    .lock()
+                    .unwrap()
+                    .extend(rollback.keys.iter().cloned());
+                Ok(Box::new(kvrpcpb::BatchRollbackResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let options = TransactionOptions::new_optimistic()
+            .no_auto_hearbeat()
+            .use_async_commit()
+            .wait_for_secondaries();
+
+        let txn = Transaction::new(Timestamp::default(), pd_client.clone(), options.clone());
+        txn.put("key1".to_owned(), "foo").await.unwrap();
+        txn.put("key2".to_owned(), "bar").await.unwrap();
+        txn.flush().await.unwrap();
+        // Nothing is left to flush.
+        txn.flush().await.unwrap();
+        assert_eq!(
+            *prewritten.lock().unwrap(),
+            vec![(b"key1".to_vec(), vec![b"key1".to_vec(), b"key2".to_vec()])]
+        );
+        assert!(matches!(
+            txn.get("key1".to_owned()).await,
+            Err(Error::KeyFlushed { .. })
+        ));
+        assert!(matches!(
+            txn.delete("key2".to_owned()).await,
+            Err(Error::KeyFlushed { .. })
+        ));
+        assert!(matches!(
+            txn.scan("key0".to_owned().."key9".to_owned(), 10).await,
+            Err(Error::KeyFlushed { .. })
+        ));
+        txn.put("key3".to_owned(), "baz").await.unwrap();
+        txn.commit().await.unwrap();
+        assert_eq!(
+            prewritten.lock().unwrap()[1],
+            (b"key1".to_vec(), vec![b"key3".to_vec()])
+        );
+        let mut keys = committed.lock().unwrap().split_off(0);
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![b"key1".to_vec(), b"key2".to_vec(), b"key3".to_vec()]
+        );
+
+        // Flushed keys are rolled back with the others.
+        let txn = Transaction::new(Timestamp::default(), pd_client, options);
+        txn.put("key1".to_owned(), "foo").await.unwrap();
+        txn.flush().await.unwrap();
+        txn.put("key2".to_owned(), "bar").await.unwrap();
+        txn.rollback().await.unwrap();
+        let mut keys = rolled_back.lock().unwrap().clone();
+        keys.sort();
+        assert_eq!(keys, vec![b"key1".to_vec(), b"key2".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_send_heart_beat() {
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(heartbeat) = req.downcast_ref::<kvrpcpb::TxnHeartBeatRequest>() {
+                    let resp = kvrpcpb::TxnHeartBeatResponse {
+                        lock_ttl: heartbeat.advise_lock_ttl,
+                        ..Default::default()
+                    };
+                    return Ok(Box::new(resp) as Box<dyn Any>);
+                } else if let Some(_lock) = req.downcast_ref::<kvrpcpb::PessimisticLockRequest>() {
+                    return Ok(
+                        Box::new(kvrpcpb::PessimisticLockResponse::default()) as Box<dyn Any>
+                    );
+                }
+                Ok(Box::new(kvrpcpb::PessimisticRollbackResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_pessimistic()
+                .no_auto_hearbeat()
+                .lock_ttl(Duration::from_secs(10)),
+        );
+        assert!(matches!(
+            txn.send_heart_beat().await,
+            Err(crate::Error::NoPrimaryKey)
+        ));
+        txn.put("key".to_owned(), "value").await.unwrap();
+        assert!(txn.send_heart_beat().await.unwrap() >= 10_000);
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_async_commit_fallback() {
+        // Returns whether the prewrite used async commit, and whether the primary key was
+        // committed separately.
+        async fn commit(options: TransactionOptions, min_commit_ts: u64) -> (bool, bool) {
+            let async_commit = Arc::new(Mutex::new(None));
+            let committed = Arc::new(Mutex::new(false));
+            let async_commit_cloned = async_commit.clone();
+            let committed_cloned = committed.clone();
+            let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+                move |req: &dyn Any| {
+                    if let Some(prewrite) = req.downcast_ref::<kvrpcpb::PrewriteRequest>() {
+                        *async_commit_cloned.lock().unwrap() = Some(prewrite.use_async_commit);
+                        let resp = kvrpcpb::PrewriteResponse {
+                            min_commit_ts,
+                            ..Default::default()
+                        };
+                        return Ok(Box::new(resp) as Box<dyn Any>);
+                    }
+                    *committed_cloned.lock().unwrap() = true;
+                    Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>)
+                },
+            )));
+            let txn = Transaction::new(Timestamp::default(), pd_client, options.no_auto_hearbeat());
+            txn.put("key1".to_owned(), "foo").await.unwrap();
+            txn.put("key2".to_owned(), "bar").await.unwrap();
+            txn.commit().await.unwrap();
+            let async_commit = async_commit.lock().unwrap().unwrap();
+            let committed = *committed.lock().unwrap();
+            (async_commit, committed)
+        }
+
+        let options = TransactionOptions::new_optimistic().use_async_commit();
+        assert_eq!(
+            commit(options.clone().async_commit_keys_limit(1), 10).await,
+            (false, true)
+        );
+        assert_eq!(
+            commit(options.clone().async_commit_key_size_limit(7), 10).await,
+            (false, true)
+        );
+        // TiKV refuses async commit.
+        assert_eq!(commit(options, 0).await, (true, true));
+    }
+
+    #[tokio::test]
+    async fn test_commit_batch_limits() {
+        let prewrites = Arc::new(Mutex::new(Vec::new()));
+        let commits = Arc::new(Mutex::new(Vec::new()));
+        let (prewrites_cloned, commits_cloned) = (prewrites.clone(), commits.clone());
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if let Some(req) = req.downcast_ref::<kvrpcpb::PrewriteRequest>() {
+                    prewrites_cloned.lock().unwrap().push(req.mutations.len());
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                }
+                if let Some(req) = req.downcast_ref::<kvrpcpb::CommitRequest>() {
+                    commits_cloned.lock().unwrap().push(req.keys.len());
+                    return Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>);
+                }
+                unreachable!()
+            },
+        )));
+        let options = TransactionOptions::new_optimistic()
+            .batch_keys_limit(4)
+            .batch_size_limit(250)
+            .wait_for_secondaries()
+            .no_auto_hearbeat();
+        let txn = Transaction::new(Timestamp::default(), pd_client, options);
+        for i in 0..10 {
+            txn.put(format!("key{}", i), vec![0u8; 100]).await.unwrap();
+        }
+        txn.commit().await.unwrap();
+
+        // Two keys and values of 104 bytes fit in 250 bytes.
+        assert_eq!(*prewrites.lock().unwrap(), vec![2, 2, 2, 2, 2]);
+        // The primary key, then the secondary keys, at most 4 at a time.
+        assert_eq!(*commits.lock().unwrap(), vec![1, 4, 4, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_commit_error_classification() {
+        #[derive(Clone, Copy)]
+        enum Failure {
+            Conflict,
+            Expired,
+            Lost,
+        }
+
+        async fn commit(options: TransactionOptions, failure: Failure) -> Error {
+            let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+                move |req: &dyn Any| {
+                    if req.downcast_ref::<kvrpcpb::PrewriteRequest>().is_some() {
+                        let mut resp = kvrpcpb::PrewriteResponse::default();
+                        match failure {
+                            Failure::Conflict => resp.errors.push(kvrpcpb::KeyError {
+                                conflict: Some(kvrpcpb::WriteConflict {
+                                    key: b"key".to_vec(),
+                                    conflict_ts: 5,
+                                    conflict_commit_ts: 6,
+                                    ..Default::default()
+                                }),
+                                ..Default::default()
+                            }),
+                            Failure::Lost => return Err(Error::Grpc(grpcio::Error::RemoteStopped)),
+                            Failure::Expired => {}
+                        }
+                        return Ok(Box::new(resp) as Box<dyn Any>);
+                    }
+                    let resp = kvrpcpb::CommitResponse {
+                        error: Some(kvrpcpb::KeyError {
+                            txn_not_found: Some(kvrpcpb::TxnNotFound {
+                                start_ts: 1,
+                                primary_key: b"key".to_vec(),
+                            }),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    };
+                    Ok(Box::new(resp) as Box<dyn Any>)
+                },
+            )));
+            let txn = Transaction::new(Timestamp::default(), pd_client, options.no_auto_hearbeat());
+            txn.put("key".to_owned(), "value").await.unwrap();
+            txn.commit().await.unwrap_err()
+        }
+
+        let options = TransactionOptions::new_optimistic();
+        let e = commit(options.clone(), Failure::Conflict).await;
+        assert!(matches!(
+            e,
+            Error::WriteConflict {
+                conflict_start_ts: 5,
+                conflict_commit_ts: 6,
+                ..
+            }
+        ));
+        assert!(e.is_retryable_transaction());
+        let e = commit(options.clone(), Failure::Expired).await;
+        assert!(matches!(e, Error::TransactionExpired { start_ts: 1, .. }));
+        assert!(e.is_retryable_transaction());
+        // A lost prewrite doesn't commit a 2PC transaction, but may commit an async one.
+        let e = commit(options.clone(), Failure::Lost).await;
+        assert!(matches!(e, Error::Grpc(_)));
+        let e = commit(options.use_async_commit(), Failure::Lost).await;
+        assert!(matches!(e, Error::UndeterminedError(_)));
+        assert!(!e.is_retryable_transaction());
+    }
+
+    #[tokio::test]
+    async fn test_local_write_conflict() {
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |_: &dyn Any| {
+                Ok(Box::new(kvrpcpb::BatchRollbackResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic(),
+        );
+        let (alice, bob) = (WriterId::new(), WriterId::new());
+        assert_ne!(alice, bob);
+
+        txn.put_as(alice, "a".to_owned(), "alice").await.unwrap();
+        txn.put_as(alice, "a".to_owned(), "alice again")
+            .await
+            .unwrap();
+        txn.delete_as(bob, "b".to_owned()).await.unwrap();
+        match txn.put_as(bob, "a".to_owned(), "bob").await {
+            Err(Error::LocalWriteConflict { key, owner, writer }) => {
+                assert_eq!(key, b"a".to_vec());
+                assert_eq!(owner, alice.id());
+                assert_eq!(writer, bob.id());
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(matches!(
+            txn.insert_as(alice, "b".to_owned(), "alice").await,
+            Err(Error::LocalWriteConflict { .. })
+        ));
+        assert_eq!(
+            txn.get("a".to_owned()).await.unwrap(),
+            Some(b"alice again".to_vec())
+        );
+
+        // Writes without a writer are not tracked, but can't overwrite keys written by a writer.
+        match txn.put("a".to_owned(), "anyone").await {
+            Err(Error::LocalWriteConflict { owner, writer, .. }) => {
+                assert_eq!(owner, alice.id());
+                assert_eq!(writer, 0);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(matches!(
+            txn.delete("b".to_owned()).await,
+            Err(Error::LocalWriteConflict { .. })
+        ));
+        assert_eq!(
+            txn.get("a".to_owned()).await.unwrap(),
+            Some(b"alice again".to_vec())
+        );
+        txn.put("c".to_owned(), "anyone").await.unwrap();
+        txn.put("c".to_owned(), "anyone again").await.unwrap();
+        txn.put_as(bob, "c".to_owned(), "bob").await.unwrap();
+        assert!(txn.put("c".to_owned(), "anyone").await.is_err());
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_lock_leaves_key_unclaimed() {
+        let fail = Arc::new(AtomicBool::new(true));
+        let fail_cloned = fail.clone();
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if req.is::<kvrpcpb::PessimisticLockRequest>() {
+                    let mut resp = kvrpcpb::PessimisticLockResponse::default();
+                    if fail_cloned.load(Ordering::SeqCst) {
+                        resp.errors.push(kvrpcpb::KeyError {
+                            abort: "failed".to_owned(),
+                            ..Default::default()
+                        });
+                    }
+                    return Ok(Box::new(resp) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::PessimisticRollbackResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_pessimistic().no_auto_hearbeat(),
+        );
+        let (alice, bob) = (WriterId::new(), WriterId::new());
+
+        assert!(txn.put_as(alice, "a".to_owned(), "alice").await.is_err());
+        assert!(txn.insert_as(alice, "b".to_owned(), "alice").await.is_err());
+        assert!(txn.delete_as(alice, "c".to_owned()).await.is_err());
+
+        // Alice wrote nothing, so Bob can write the keys.
+        fail.store(false, Ordering::SeqCst);
+        txn.put_as(bob, "a".to_owned(), "bob").await.unwrap();
+        txn.insert_as(bob, "b".to_owned(), "bob").await.unwrap();
+        txn.delete_as(bob, "c".to_owned()).await.unwrap();
+        assert!(matches!(
+            txn.put_as(alice, "a".to_owned(), "alice").await,
+            Err(Error::LocalWriteConflict { .. })
+        ));
+        assert_eq!(
+            txn.get("a".to_owned()).await.unwrap(),
+            Some(b"bob".to_vec())
+        );
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shared_transaction() {
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if req.downcast_ref::<kvrpcpb::PrewriteRequest>().is_some() {
+                    return Ok(Box::new(kvrpcpb::PrewriteResponse::default()) as Box<dyn Any>);
+                }
+                Ok(Box::new(kvrpcpb::CommitResponse::default()) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic(),
+        );
+        let tasks: Vec<_> = (0..4)
+            .map(|i| {
+                let txn = txn.clone();
+                tokio::spawn(async move { txn.put(format!("key{}", i), "value").await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        for i in 0..4 {
+            assert_eq!(
+                txn.get(format!("key{}", i)).await.unwrap(),
+                Some(b"value".to_vec())
+            );
+        }
+
+        let other = txn.clone();
+        txn.commit().await.unwrap();
+        // The commit ends the transaction for every handle.
+        assert!(matches!(
+            other.put("key4".to_owned(), "value").await,
+            Err(Error::OperationAfterCommitError)
+        ));
+    }
+
+    #[test]
+    fn test_options_presets() {
+        let options = TransactionOptions::optimistic_low_latency();
+        assert_eq!(options.kind, TransactionKind::Optimistic);
+        assert!(options.try_one_pc && options.async_commit);
+
+        let options = TransactionOptions::pessimistic_bulk_write();
+        assert!(matches!(options.kind, TransactionKind::Pessimistic(_)));
+        assert!(!options.try_one_pc && !options.async_commit);
+        assert!(options.wait_for_secondaries);
+        assert_eq!(options.lock_ttl, 20000);
+
+        let options = TransactionOptions::analytics_read();
+        assert!(options.read_only && options.replica_read);
+
+        let options = TransactionOptions::optimistic_low_latency()
+            .commit_mode(CommitMode::AsyncCommit)
+            .auto_heartbeat(false)
+            .lock_backoff(Backoff::no_backoff());
+        assert!(!options.try_one_pc && options.async_commit);
+        assert!(!options.auto_heartbeat);
+        assert_eq!(
+            options,
+            TransactionOptions::new_optimistic()
+                .use_async_commit()
+                .no_auto_hearbeat()
+                .no_resolve_locks()
+        );
+    }
+
+    #[test]
+    fn test_default_retry_options() {
+        let defaults = RetryOptions::new(
+            Backoff::no_jitter_backoff(1, 2, 3),
+            Backoff::no_jitter_backoff(4, 5, 6),
+        );
+        let options = TransactionOptions::new_pessimistic().default_retry_options(defaults.clone());
+        assert_eq!(options.retry_options, defaults);
+
+        let options = TransactionOptions::new_optimistic()
+            .no_resolve_locks()
+            .default_retry_options(defaults.clone());
+        assert_eq!(options.retry_options.lock_backoff, Backoff::no_backoff());
+        assert_eq!(
+            options.retry_options.region_backoff,
+            defaults.region_backoff
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scan_opt_key_only() {
+        let pd_client = Arc::new(MockPdClient::new(MockKvClient::with_dispatch_hook(
+            move |req: &dyn Any| {
+                if req
+                    .downcast_ref::<kvrpcpb::BatchRollbackRequest>()
+                    .is_some()
+                {
+                    return Ok(Box::new(kvrpcpb::BatchRollbackResponse::default()) as Box<dyn Any>);
+                }
+                let req = req.downcast_ref::<kvrpcpb::ScanRequest>().unwrap();
+                assert!(req.key_only);
+                let resp = kvrpcpb::ScanResponse {
+                    pairs: vec![kvrpcpb::KvPair {
+                        key: b"b".to_vec(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                };
+                Ok(Box::new(resp) as Box<dyn Any>)
+            },
+        )));
+        let txn = Transaction::new(
+            Timestamp::default(),
+            pd_client,
+            TransactionOptions::new_optimistic(),
+        );
+        txn.put("a".to_owned(), "value").await.unwrap();
+        let options = Options::new()
+            .key_only()
+            .retry_options(RetryOptions::none())
+            .timeout(Duration::from_secs(10));
+        let pairs: Vec<KvPair> = txn
+            .scan_opt("a".to_owned().."c".to_owned(), 10, options)
+            .await
+            .unwrap()
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                KvPair(b"a".to_vec().into(), Vec::new()),
+                KvPair(b"b".to_vec().into(), Vec::new()),
+            ]
+        );
+        txn.rollback().await.unwrap();
+    }
 }
@@ -0,0 +1,293 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! Typed access to values.
+//!
+//! A [`Codec`](Codec) converts between values of a Rust type and the bytes stored in TiKV.
+//! [`TypedRawClient`](TypedRawClient) and [`TypedTransaction`](TypedTransaction) use a codec to
+//! read and write typed values, so applications don't need to (de)serialize values by hand.
+//!
+//! This module requires the `typed` feature. The codecs are enabled by the `serde_json`
+//! ([`Json`](Json)), `bincode` ([`Bincode`](Bincode)) and `prost` ([`Protobuf`](Protobuf))
+//! features.
+
+use crate::{BoundRange, Error, Key, KvPair, RawClient, Result, Transaction, Value};
+use std::marker::PhantomData;
+
+/// Converts between values of type `T` and their serialized form.
+pub trait Codec<T>: Clone + Send + Sync + 'static {
+    fn encode(&self, value: &T) -> Result<Value>;
+
+    fn decode(&self, value: &[u8]) -> Result<T>;
+
+    /// Decode the value of a key-value pair, keeping its key.
+    fn decode_pair(&self, pair: KvPair) -> Result<(Key, T)> {
+        let value = self.decode(pair.value())?;
+        Ok((pair.into_key(), value))
+    }
+}
+
+fn codec_error(e: impl std::fmt::Display) -> Error {
+    Error::CodecError {
+        message: e.to_string(),
+    }
+}
+
+/// Encodes values as JSON using `serde`.
+#[cfg(feature = "serde_json")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+#[cfg(feature = "serde_json")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Codec<T> for Json {
+    fn encode(&self, value: &T) -> Result<Value> {
+        serde_json::to_vec(value).map_err(codec_error)
+    }
+
+    fn decode(&self, value: &[u8]) -> Result<T> {
+        serde_json::from_slice(value).map_err(codec_error)
+    }
+}
+
+/// Encodes values with `bincode` using `serde`.
+#[cfg(feature = "bincode")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Codec<T> for Bincode {
+    fn encode(&self, value: &T) -> Result<Value> {
+        bincode::serialize(value).map_err(codec_error)
+    }
+
+    fn decode(&self, value: &[u8]) -> Result<T> {
+        bincode::deserialize(value).map_err(codec_error)
+    }
+}
+
+/// Encodes protobuf messages using `prost`.
+#[cfg(feature = "prost")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Protobuf;
+
+#[cfg(feature = "prost")]
+impl<T: prost::Message + Default> Codec<T> for Protobuf {
+    fn encode(&self, value: &T) -> Result<Value> {
+        let mut buf = Vec::with_capacity(value.encoded_len());
+        value.encode(&mut buf).map_err(codec_error)?;
+        Ok(buf)
+    }
+
+    fn decode(&self, value: &[u8]) -> Result<T> {
+        T::decode(value).map_err(codec_error)
+    }
+}
+
+/// A raw client which reads and writes values of type `T`, encoded by the codec `C`.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use tikv_client::{RawClient, typed::{Json, TypedRawClient}};
+/// # use futures::prelude::*;
+/// # futures::executor::block_on(async {
+/// let client = RawClient::new(vec!["192.168.0.100"]).await.unwrap();
+/// let client = TypedRawClient::<Vec<String>, _>::new(client, Json);
+/// client.put("tags".to_owned(), &vec!["a".to_owned()]).await.unwrap();
+/// let tags: Option<Vec<String>> = client.get("tags".to_owned()).await.unwrap();
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct TypedRawClient<T, C: Codec<T>> {
+    client: RawClient,
+    codec: C,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T, C: Codec<T>> TypedRawClient<T, C> {
+    pub fn new(client: RawClient, codec: C) -> TypedRawClient<T, C> {
+        TypedRawClient {
+            client,
+            codec,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The underlying untyped client.
+    pub fn raw(&self) -> &RawClient {
+        &self.client
+    }
+
+    pub async fn get(&self, key: impl Into<Key>) -> Result<Option<T>> {
+        self.client
+            .get(key)
+            .await?
+            .map(|value| self.codec.decode(&value))
+            .transpose()
+    }
+
+    /// Non-existent entries will not appear in the result. The order of the keys is not retained
+    /// in the result.
+    pub async fn batch_get(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<(Key, T)>> {
+        self.client
+            .batch_get(keys)
+            .await?
+            .into_iter()
+            .map(|pair| self.codec.decode_pair(pair))
+            .collect()
+    }
+
+    pub async fn put(&self, key: impl Into<Key>, value: &T) -> Result<()> {
+        self.client.put(key, self.codec.encode(value)?).await
+    }
+
+    pub async fn batch_put(
+        &self,
+        pairs: impl IntoIterator<Item = (impl Into<Key>, T)>,
+    ) -> Result<()> {
+        let pairs = pairs
+            .into_iter()
+            .map(|(key, value)| Ok(KvPair::new(key, self.codec.encode(&value)?)))
+            .collect::<Result<Vec<_>>>()?;
+        self.client.batch_put(pairs).await
+    }
+
+    pub async fn delete(&self, key: impl Into<Key>) -> Result<()> {
+        self.client.delete(key).await
+    }
+
+    pub async fn scan(&self, range: impl Into<BoundRange>, limit: u32) -> Result<Vec<(Key, T)>> {
+        self.client
+            .scan(range, limit)
+            .await?
+            .into_iter()
+            .map(|pair| self.codec.decode_pair(pair))
+            .collect()
+    }
+}
+
+/// A view of a [`Transaction`](Transaction) which reads and writes values of type `T`, encoded
+/// by the codec `C`.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use tikv_client::{TransactionClient, typed::{Json, TypedTransaction}};
+/// # use futures::prelude::*;
+/// # futures::executor::block_on(async {
+/// let client = TransactionClient::new(vec!["192.168.0.100"]).await.unwrap();
+/// let txn = client.begin_optimistic().await.unwrap();
+/// let typed = TypedTransaction::<u64, _>::new(&txn, Json);
+/// let count = typed.get("count".to_owned()).await.unwrap().unwrap_or(0);
+/// typed.put("count".to_owned(), &(count + 1)).await.unwrap();
+/// txn.commit().await.unwrap();
+/// # });
+/// ```
+pub struct TypedTransaction<'a, T, C: Codec<T>> {
+    txn: &'a Transaction,
+    codec: C,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<'a, T, C: Codec<T>> TypedTransaction<'a, T, C> {
+    pub fn new(txn: &'a Transaction, codec: C) -> TypedTransaction<'a, T, C> {
+        TypedTransaction {
+            txn,
+            codec,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub async fn get(&self, key: impl Into<Key>) -> Result<Option<T>> {
+        self.txn
+            .get(key)
+            .await?
+            .map(|value| self.codec.decode(&value))
+            .transpose()
+    }
+
+    pub async fn get_for_update(&self, key: impl Into<Key>) -> Result<Option<T>> {
+        self.txn
+            .get_for_update(key)
+            .await?
+            .map(|value| self.codec.decode(&value))
+            .transpose()
+    }
+
+    pub async fn batch_get(
+        &self,
+        keys: impl IntoIterator<Item = impl Into<Key>>,
+    ) -> Result<Vec<(Key, T)>> {
+        self.txn
+            .batch_get(keys)
+            .await?
+            .map(|pair| self.codec.decode_pair(pair))
+            .collect()
+    }
+
+    pub async fn scan(&self, range: impl Into<BoundRange>, limit: u32) -> Result<Vec<(Key, T)>> {
+        self.txn
+            .scan(range, limit)
+            .await?
+            .map(|pair| self.codec.decode_pair(pair))
+            .collect()
+    }
+
+    pub async fn put(&self, key: impl Into<Key>, value: &T) -> Result<()> {
+        let value = self.codec.encode(value)?;
+        self.txn.put(key, value).await
+    }
+
+    pub async fn insert(&self, key: impl Into<Key>, value: &T) -> Result<()> {
+        let value = self.codec.encode(value)?;
+        self.txn.insert(key, value).await
+    }
+
+    pub async fn delete(&self, key: impl Into<Key>) -> Result<()> {
+        self.txn.delete(key).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_json_codec() {
+        let value = vec!["a".to_owned(), "b".to_owned()];
+        let encoded = Json.encode(&value).unwrap();
+        assert_eq!(encoded, br#"["a","b"]"#.to_vec());
+        let decoded: Vec<String> = Json.decode(&encoded).unwrap();
+        assert_eq!(decoded, value);
+        assert!(matches!(
+            Codec::<Vec<String>>::decode(&Json, b"{"),
+            Err(Error::CodecError { .. })
+        ));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_bincode_codec() {
+        let value = (42u64, "a".to_owned());
+        let encoded = Bincode.encode(&value).unwrap();
+        let (key, decoded): (Key, (u64, String)) = Bincode
+            .decode_pair(KvPair::new(b"k".to_vec(), encoded))
+            .unwrap();
+        assert_eq!(key, Key::from(b"k".to_vec()));
+        assert_eq!(decoded, value);
+    }
+
+    #[cfg(feature = "prost")]
+    #[test]
+    fn test_protobuf_codec() {
+        let value = tikv_client_proto::kvrpcpb::KvPair {
+            key: b"k".to_vec(),
+            value: b"v".to_vec(),
+            ..Default::default()
+        };
+        let encoded = Protobuf.encode(&value).unwrap();
+        let decoded: tikv_client_proto::kvrpcpb::KvPair = Protobuf.decode(&encoded).unwrap();
+        assert_eq!(decoded, value);
+    }
+}
@@ -1,3 +1,4 @@
 // Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
 
 pub mod iter;
+pub mod task;
@@ -0,0 +1,82 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::Future;
+use tikv_client_common::tasks::TaskGuard;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+/// Spawn a background task named `name` on the Tokio runtime, registered in
+/// [`running_tasks`](crate::running_tasks) while it runs and instrumented with a `task` span
+/// carrying its name.
+///
+/// With the `tokio-console` feature, the task is also named in tokio-console.
+pub fn spawn<F>(name: &'static str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let guard = TaskGuard::register(name);
+    let future = async move {
+        let _guard = guard;
+        future.await
+    }
+    .instrument(info_span!(target: "tikv_client::task", "task", name));
+    #[cfg(feature = "tokio-console")]
+    {
+        tokio::task::Builder::new()
+            .name(name)
+            .spawn(future)
+            .expect("failed to spawn task")
+    }
+    #[cfg(not(feature = "tokio-console"))]
+    {
+        tokio::spawn(future)
+    }
+}
+
+/// Run `future` in a background task named `name` and wait for it, so it runs to completion even
+/// if the returned future is dropped.
+pub async fn run_detached<F>(name: &'static str, future: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match spawn(name, future).await {
+        Ok(output) => output,
+        Err(e) => match e.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            // Only when the runtime shuts down, which drops the caller too.
+            Err(e) => panic!("task {} didn't finish: {}", name, e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::running_tasks;
+
+    #[tokio::test]
+    async fn test_spawn() {
+        let (sender, receiver) = futures::channel::oneshot::channel::<()>();
+        let task = spawn("test-spawn", async {
+            let _ = receiver.await;
+        });
+        let running = || {
+            running_tasks()
+                .iter()
+                .any(|task| task.name() == "test-spawn")
+        };
+        assert!(running());
+        sender.send(()).unwrap();
+        task.await.unwrap();
+        assert!(!running());
+
+        // Aborted tasks are unregistered too.
+        let task = spawn("test-spawn", futures::future::pending::<()>());
+        assert!(running());
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert!(!running());
+    }
+}
@@ -17,12 +17,12 @@ async fn optimistic_heartbeat() -> Result<()> {
     let key2 = "key2".to_owned();
     let client = TransactionClient::new(pd_addrs()).await?;
 
-    let mut heartbeat_txn = client
+    let heartbeat_txn = client
         .begin_with_options(TransactionOptions::new_optimistic())
         .await?;
     heartbeat_txn.put(key1.clone(), "foo").await.unwrap();
 
-    let mut txn_without_heartbeat = client
+    let txn_without_heartbeat = client
         .begin_with_options(TransactionOptions::new_optimistic().no_auto_hearbeat())
         .await?;
     txn_without_heartbeat
@@ -41,7 +41,7 @@ async fn optimistic_heartbeat() -> Result<()> {
     fail::cfg("after-prewrite", "off").unwrap();
 
     // use other txns to check these locks
-    let mut t3 = client
+    let t3 = client
         .begin_with_options(
             TransactionOptions::new_optimistic()
                 .no_resolve_locks()
@@ -51,7 +51,7 @@ async fn optimistic_heartbeat() -> Result<()> {
     t3.put(key1.clone(), "gee").await?;
     assert!(t3.commit().await.is_err());
 
-    let mut t4 = client.begin_optimistic().await?;
+    let t4 = client.begin_optimistic().await?;
     t4.put(key2.clone(), "geee").await?;
     t4.commit().await?;
 
@@ -9,9 +9,22 @@ use std::{
     collections::{HashMap, HashSet},
     convert::TryInto,
     iter,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 use tikv_client::{
-    Key, KvPair, RawClient, Result, Transaction, TransactionClient, TransactionOptions, Value,
+    diff::{changed_keys, ChangeKind, DiffOptions},
+    export::{export_range, ExportOptions, FileSink, Format},
+    import::{restore, RestoreOptions},
+    recipes::{
+        id_alloc::IdAllocator,
+        idempotency::{Idempotency, Outcome},
+        lock::DistributedLock,
+        queue::Queue,
+    },
+    replication::{copy_range, CopyOptions},
+    BoundRange, Config, Error, Key, KvPair, RawClient, Result, Timestamp, TimestampExt,
+    Transaction, TransactionClient, TransactionOptions, Value, WatchEvent,
 };
 
 // Parameters used in test
@@ -43,7 +56,7 @@ async fn crud() -> Result<()> {
     clear_tikv().await;
 
     let client = TransactionClient::new(pd_addrs()).await?;
-    let mut txn = client.begin_optimistic().await?;
+    let txn = client.begin_optimistic().await?;
 
     // Get non-existent keys
     assert!(txn.get("foo".to_owned()).await?.is_none());
@@ -79,7 +92,7 @@ async fn crud() -> Result<()> {
     txn.commit().await?;
 
     // Read from TiKV then update and delete
-    let mut txn = client.begin_optimistic().await?;
+    let txn = client.begin_optimistic().await?;
     assert_eq!(
         txn.get("foo".to_owned()).await?,
         Some("bar".to_owned().into())
@@ -128,16 +141,16 @@ async fn insert_duplicate_keys() -> Result<()> {
 
     let client = TransactionClient::new(pd_addrs()).await?;
     // Initialize TiKV store with {foo => bar}
-    let mut txn = client.begin_optimistic().await?;
+    let txn = client.begin_optimistic().await?;
     txn.put("foo".to_owned(), "bar".to_owned()).await?;
     txn.commit().await?;
     // Try insert foo again
-    let mut txn = client.begin_optimistic().await?;
+    let txn = client.begin_optimistic().await?;
     txn.insert("foo".to_owned(), "foo".to_owned()).await?;
     assert!(txn.commit().await.is_err());
 
     // Delete-your-writes
-    let mut txn = client.begin_optimistic().await?;
+    let txn = client.begin_optimistic().await?;
     txn.insert("foo".to_owned(), "foo".to_owned()).await?;
     txn.delete("foo".to_owned()).await?;
     assert!(txn.commit().await.is_err());
@@ -151,7 +164,7 @@ async fn pessimistic() -> Result<()> {
     clear_tikv().await;
 
     let client = TransactionClient::new(pd_addrs()).await?;
-    let mut txn = client.begin_pessimistic().await?;
+    let txn = client.begin_pessimistic().await?;
     txn.put("foo".to_owned(), "foo".to_owned()).await.unwrap();
 
     let ttl = txn.send_heart_beat().await.unwrap();
@@ -233,13 +246,13 @@ async fn txn_write_million() -> Result<()> {
         .map(|u| u.to_be_bytes().to_vec())
         .take(2usize.pow(NUM_BITS_KEY_PER_TXN))
         .collect::<Vec<_>>(); // each txn puts 2 ^ 12 keys. 12 = 25 - 13
-        let mut txn = client.begin_optimistic().await?;
+        let txn = client.begin_optimistic().await?;
         for (k, v) in keys.iter().zip(iter::repeat(1u32.to_be_bytes().to_vec())) {
             txn.put(k.clone(), v).await?;
         }
         txn.commit().await?;
 
-        let mut txn = client.begin_optimistic().await?;
+        let txn = client.begin_optimistic().await?;
         let res = txn.batch_get(keys).await?;
         assert_eq!(res.count(), 2usize.pow(NUM_BITS_KEY_PER_TXN));
         txn.commit().await?;
@@ -286,6 +299,13 @@ async fn txn_write_million() -> Result<()> {
 
     assert_eq!(sum, 2usize.pow(NUM_BITS_KEY_PER_TXN + NUM_BITS_TXN));
 
+    // the same ranges in one batch scan
+    let mut ranges = vec![BoundRange::from(vec![]..keys[0].clone())];
+    ranges.extend(keys.windows(2).map(|w| (w[0].clone()..w[1].clone()).into()));
+    ranges.push((keys[keys.len() - 1].clone()..).into());
+    let res = snapshot.batch_scan(ranges, limit).await?;
+    assert_eq!(res.len(), sum);
+
     Ok(())
 }
 
@@ -297,7 +317,7 @@ async fn txn_bank_transfer() -> Result<()> {
     let mut rng = thread_rng();
 
     let people = gen_u32_keys(NUM_PEOPLE, &mut rng);
-    let mut txn = client
+    let txn = client
         .begin_with_options(TransactionOptions::new_optimistic().try_one_pc())
         .await?;
     let mut sum: u32 = 0;
@@ -310,7 +330,7 @@ async fn txn_bank_transfer() -> Result<()> {
 
     // transfer
     for _ in 0..NUM_TRNASFER {
-        let mut txn = client
+        let txn = client
             .begin_with_options(TransactionOptions::new_optimistic().use_async_commit())
             .await?;
         let chosen_people = people.iter().choose_multiple(&mut rng, 2);
@@ -334,7 +354,7 @@ async fn txn_bank_transfer() -> Result<()> {
 
     // check
     let mut new_sum = 0;
-    let mut txn = client.begin_optimistic().await?;
+    let txn = client.begin_optimistic().await?;
     for person in people.iter() {
         new_sum += get_txn_u32(&txn, person.clone()).await?;
     }
@@ -478,8 +498,21 @@ async fn raw_req() -> Result<()> {
 async fn test_update_safepoint() -> Result<()> {
     clear_tikv().await;
     let client = TransactionClient::new(pd_addrs()).await?;
-    let res = client.gc(client.current_timestamp().await?).await?;
+    let safepoint = client.current_timestamp().await?;
+    let res = client.gc(safepoint.clone()).await?;
     assert!(res);
+
+    // Snapshots can be taken at the safepoint, but not before it.
+    let options = TransactionOptions::new_optimistic();
+    assert!(client
+        .snapshot_at(safepoint.clone(), options.clone())
+        .await
+        .is_ok());
+    let before = Timestamp::from_version(safepoint.version() - 1);
+    assert!(matches!(
+        client.snapshot_at(before, options).await,
+        Err(Error::TimestampBeforeSafepoint { .. })
+    ));
     Ok(())
 }
 
@@ -528,12 +561,15 @@ async fn raw_write_million() -> Result<()> {
 
     // test batch_scan
     for batch_num in 1..4 {
-        let _ = client
+        let res = client
             .batch_scan(iter::repeat(vec![]..).take(batch_num), limit)
             .await?;
-        // FIXME: `each_limit` parameter does no work as expected.
-        // It limits the entries on each region of each rangqe, instead of each range.
-        // assert_eq!(res.len(), limit as usize * batch_num);
+        assert_eq!(res.len(), limit as usize * batch_num);
+        let res = client
+            .with_batch_scan_merging()
+            .batch_scan(iter::repeat(vec![]..).take(batch_num), limit)
+            .await?;
+        assert_eq!(res.len(), limit as usize * batch_num);
     }
 
     Ok(())
@@ -545,7 +581,7 @@ async fn pessimistic_rollback() -> Result<()> {
     clear_tikv().await;
     let client =
         TransactionClient::new_with_config(vec!["127.0.0.1:2379"], Default::default()).await?;
-    let mut preload_txn = client.begin_optimistic().await?;
+    let preload_txn = client.begin_optimistic().await?;
     let key1 = vec![1];
     let value = key1.clone();
 
@@ -553,7 +589,7 @@ async fn pessimistic_rollback() -> Result<()> {
     preload_txn.commit().await?;
 
     for _ in 0..100 {
-        let mut txn = client.begin_pessimistic().await?;
+        let txn = client.begin_pessimistic().await?;
         let result = txn.get_for_update(key1.clone()).await;
         txn.rollback().await?;
         result?;
@@ -571,6 +607,178 @@ async fn pessimistic_rollback() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn txn_checksum() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+
+    let txn = client.begin_optimistic().await?;
+    for i in 0..100u32 {
+        txn.put(i.to_be_bytes().to_vec(), b"value".to_vec()).await?;
+    }
+    txn.commit().await?;
+
+    let before = client.current_timestamp().await?;
+    let checksum = client.checksum(vec![].., before.clone()).await?;
+    assert_eq!(checksum.total_kvs, 100);
+    assert_ne!(checksum.crc64_xor, 0);
+    // The checksum of a range is the XOR of the checksums of its parts.
+    let split = 42u32.to_be_bytes().to_vec();
+    let left = client.checksum(..split.clone(), before.clone()).await?;
+    let right = client.checksum(split.., before.clone()).await?;
+    assert_eq!(left.crc64_xor ^ right.crc64_xor, checksum.crc64_xor);
+    assert_eq!(left.total_kvs + right.total_kvs, 100);
+
+    let txn = client.begin_optimistic().await?;
+    txn.put(0u32.to_be_bytes().to_vec(), b"other value".to_vec())
+        .await?;
+    txn.commit().await?;
+    let after = client.current_timestamp().await?;
+    assert_ne!(client.checksum(vec![].., after).await?, checksum);
+    assert_eq!(client.checksum(vec![].., before).await?, checksum);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn txn_export() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+
+    let txn = client.begin_optimistic().await?;
+    for i in 0..5u8 {
+        txn.put(vec![i], vec![i, i]).await?;
+    }
+    txn.commit().await?;
+
+    let mut sink = FileSink::new(Vec::new(), Format::Csv);
+    let options = ExportOptions::new().batch_size(2);
+    let summary = export_range(&client, vec![1u8]..vec![4u8], &mut sink, options).await?;
+    assert_eq!(summary.exported(), 3);
+    assert_eq!(
+        String::from_utf8(sink.into_inner()).unwrap(),
+        "key,value\n01,0101\n02,0202\n03,0303\n"
+    );
+
+    clear_tikv().await;
+    let mut sink = FileSink::new(Vec::new(), Format::Ndjson);
+    let summary = export_range(&client, vec![].., &mut sink, ExportOptions::new()).await?;
+    assert_eq!(summary.exported(), 0);
+    let exported = "{\"key\":\"01\",\"value\":\"0101\"}\n{\"key\":\"02\",\"value\":\"\"}\n";
+    let options = RestoreOptions::new().batch_size(1).range(vec![2u8]..);
+    let summary = restore(&client, exported.as_bytes(), options).await?;
+    assert_eq!((summary.restored(), summary.skipped()), (1, 1));
+    let snapshot = client.snapshot(
+        client.current_timestamp().await?,
+        TransactionOptions::default(),
+    );
+    assert_eq!(
+        snapshot.scan(vec![].., 10).await?.collect::<Vec<_>>(),
+        vec![KvPair::new(vec![2u8], vec![])]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn txn_changed_keys() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+    let raw = RawClient::new(pd_addrs()).await?;
+
+    let txn = client.begin_optimistic().await?;
+    for i in 0..5u8 {
+        txn.put(vec![i], vec![i]).await?;
+    }
+    txn.commit().await?;
+    let from = client.current_timestamp().await?;
+
+    let txn = client.begin_optimistic().await?;
+    txn.put(vec![1u8], vec![10u8]).await?;
+    txn.delete(vec![3u8]).await?;
+    txn.put(vec![7u8], vec![7u8]).await?;
+    txn.commit().await?;
+    let txn = client.begin_optimistic().await?;
+    txn.delete(vec![1u8]).await?;
+    txn.commit().await?;
+    let to = client.current_timestamp().await?;
+
+    let options = DiffOptions::new().batch_size(1);
+    let changes = changed_keys(
+        &raw,
+        vec![0u8]..vec![5u8],
+        from.clone(),
+        to.clone(),
+        options,
+    )
+    .await?;
+    let changes: Vec<(Key, ChangeKind)> = changes
+        .into_iter()
+        .map(|change| (change.key().clone(), change.kind()))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (vec![1u8].into(), ChangeKind::Delete),
+            (vec![3u8].into(), ChangeKind::Delete)
+        ]
+    );
+    let changes = changed_keys(&raw, vec![].., from, to, DiffOptions::new()).await?;
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[2].kind(), ChangeKind::Put);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn txn_copy_range() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+
+    let txn = client.begin_optimistic().await?;
+    for i in 0..10u32 {
+        txn.put(i.to_be_bytes().to_vec(), b"value".to_vec()).await?;
+    }
+    txn.commit().await?;
+
+    // Copying a range onto itself leaves the data as it is.
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let reports_cloned = reports.clone();
+    let options = CopyOptions::new()
+        .batch_size(4)
+        .on_progress(move |progress| reports_cloned.lock().unwrap().push(progress.clone()));
+    let progress = copy_range(&client, &client, vec![].., options).await?;
+    assert!(progress.is_done());
+    assert_eq!(progress.copied(), 10);
+    let reports = reports.lock().unwrap().clone();
+    assert_eq!(
+        reports.iter().map(|p| p.copied()).collect::<Vec<_>>(),
+        vec![4, 8, 10]
+    );
+    assert_eq!(reports[2], progress);
+
+    let resumed = copy_range(
+        &client,
+        &client,
+        vec![]..,
+        CopyOptions::new().batch_size(4).resume(reports[0].clone()),
+    )
+    .await?;
+    assert_eq!(resumed, progress);
+
+    let snapshot = client.snapshot(
+        client.current_timestamp().await?,
+        TransactionOptions::default(),
+    );
+    assert_eq!(snapshot.scan(vec![].., 100).await?.count(), 10);
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn lock_keys() -> Result<()> {
@@ -583,8 +791,8 @@ async fn lock_keys() -> Result<()> {
     let v = b"some value".to_vec();
 
     // optimistic
-    let mut t1 = client.begin_optimistic().await?;
-    let mut t2 = client.begin_optimistic().await?;
+    let t1 = client.begin_optimistic().await?;
+    let t2 = client.begin_optimistic().await?;
     t1.lock_keys(vec![k1.clone(), k2.clone()]).await?;
     t2.put(k1.clone(), v.clone()).await?;
     t2.commit().await?;
@@ -594,8 +802,8 @@ async fn lock_keys() -> Result<()> {
     // pessimistic
     let k3 = b"key3".to_vec();
     let k4 = b"key4".to_vec();
-    let mut t3 = client.begin_pessimistic().await?;
-    let mut t4 = client.begin_pessimistic().await?;
+    let t3 = client.begin_pessimistic().await?;
+    let t4 = client.begin_pessimistic().await?;
     t3.lock_keys(vec![k3.clone(), k4.clone()]).await?;
     assert!(t4.lock_keys(vec![k3.clone(), k4.clone()]).await.is_err());
 
@@ -615,12 +823,12 @@ async fn pessimistic_heartbeat() -> Result<()> {
     let key2 = "key2".to_owned();
     let client = TransactionClient::new(pd_addrs()).await?;
 
-    let mut heartbeat_txn = client
+    let heartbeat_txn = client
         .begin_with_options(TransactionOptions::new_pessimistic())
         .await?;
     heartbeat_txn.put(key1.clone(), "foo").await.unwrap();
 
-    let mut txn_without_heartbeat = client
+    let txn_without_heartbeat = client
         .begin_with_options(TransactionOptions::new_pessimistic().no_auto_hearbeat())
         .await?;
     txn_without_heartbeat
@@ -631,12 +839,12 @@ async fn pessimistic_heartbeat() -> Result<()> {
     tokio::time::sleep(tokio::time::Duration::from_millis(5000)).await;
 
     // use other txns to check these locks
-    let mut t3 = client
+    let t3 = client
         .begin_with_options(TransactionOptions::new_optimistic().no_resolve_locks())
         .await?;
     t3.put(key1.clone(), "gee").await?;
     assert!(t3.commit().await.is_err());
-    let mut t4 = client.begin_optimistic().await?;
+    let t4 = client.begin_optimistic().await?;
     t4.put(key2.clone(), "geee").await?;
     t4.commit().await?;
 
@@ -645,6 +853,479 @@ async fn pessimistic_heartbeat() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn distributed_lock() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+    let lock = DistributedLock::new(client, "lock".to_owned(), Duration::from_secs(3));
+
+    let guard = lock.try_acquire().await?.unwrap();
+    assert_eq!(guard.fence_token(), 1);
+    assert!(lock.try_acquire().await?.is_none());
+    // The lease is kept alive past its TTL.
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    assert!(!guard.is_lost());
+    assert!(lock.try_acquire().await?.is_none());
+    guard.release().await?;
+
+    let guard = lock.acquire().await?;
+    assert_eq!(guard.fence_token(), 2);
+    drop(guard);
+    // Without keep-alive, the lease expires and the lock can be taken over.
+    let lock = lock.no_keep_alive();
+    let stale = lock.acquire().await?;
+    assert_eq!(stale.fence_token(), 3);
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    let guard = lock.try_acquire().await?.unwrap();
+    assert_eq!(guard.fence_token(), 4);
+    assert!(matches!(
+        stale.keep_alive().await,
+        Err(Error::LockLost { fence_token: 3, .. })
+    ));
+    guard.release().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn distributed_lock_release_during_extend() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+    // The lease is extended every 10ms, so releases race with extensions in flight.
+    let lock = DistributedLock::new(client, "lock".to_owned(), Duration::from_millis(30));
+    for fence_token in 1..=20 {
+        let guard = lock.try_acquire().await?.unwrap();
+        assert_eq!(guard.fence_token(), fence_token);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // Released at once, rather than once the lock of an interrupted extension expires.
+        tokio::time::timeout(Duration::from_secs(1), guard.release())
+            .await
+            .expect("release blocked by an extension")?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn id_allocator() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+    let a = IdAllocator::new(client.clone(), "ids".to_owned(), 10);
+    let b = IdAllocator::new(client, "ids".to_owned(), 10);
+
+    assert_eq!(a.next().await?, 1);
+    assert_eq!(b.next().await?, 11);
+    assert_eq!(a.next().await?, 2);
+    assert_eq!(a.alloc(5).await?, 21..26);
+
+    let mut ids = HashSet::new();
+    for _ in 0..30 {
+        assert!(ids.insert(a.next().await?));
+        assert!(ids.insert(b.next().await?));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn idempotency() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+    let idempotency = Idempotency::new(client.clone(), "requests/".to_owned());
+    fn transfer(txn: &Transaction) -> future::BoxFuture<'_, Result<Value>> {
+        async move {
+            let balance = txn.get("balance".to_owned()).await?.unwrap_or_default();
+            txn.put("balance".to_owned(), [balance, b"+".to_vec()].concat())
+                .await?;
+            Ok(b"done".to_vec())
+        }
+        .boxed()
+    }
+
+    assert_eq!(
+        idempotency.handle("a".to_owned(), transfer).await?,
+        Outcome::Applied(b"done".to_vec())
+    );
+    assert_eq!(
+        idempotency.handle("a".to_owned(), transfer).await?,
+        Outcome::Replayed(b"done".to_vec())
+    );
+    assert!(!idempotency
+        .handle("b".to_owned(), transfer)
+        .await?
+        .is_replayed());
+    let snapshot = client.snapshot(
+        client.current_timestamp().await?,
+        TransactionOptions::new_optimistic(),
+    );
+    assert_eq!(
+        snapshot.get("balance".to_owned()).await?,
+        Some(b"++".to_vec())
+    );
+
+    // A failed handler records nothing.
+    let failed = idempotency
+        .handle("c".to_owned(), |_| {
+            future::err(Error::StringError("failed".to_owned())).boxed()
+        })
+        .await;
+    assert!(failed.is_err());
+    assert_eq!(idempotency.response("c".to_owned()).await?, None);
+    assert_eq!(
+        idempotency.response("a".to_owned()).await?,
+        Some(b"done".to_vec())
+    );
+
+    // Recording a request recorded by another transaction fails.
+    let txn = client.begin_optimistic().await?;
+    idempotency
+        .record_in(&txn, "a".to_owned(), b"again".to_vec())
+        .await?;
+    assert!(txn.commit().await.is_err());
+
+    // A forgotten request is handled again, with empty responses told apart from no response.
+    idempotency.forget("a".to_owned()).await?;
+    assert_eq!(idempotency.response("a".to_owned()).await?, None);
+    assert_eq!(
+        idempotency
+            .handle("a".to_owned(), |_| future::ok(Vec::new()).boxed())
+            .await?,
+        Outcome::Applied(Vec::new())
+    );
+    assert_eq!(
+        idempotency.response("a".to_owned()).await?,
+        Some(Vec::new())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn queue() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+    let queue = Queue::new(client.clone(), "queue".to_owned());
+
+    queue.enqueue("a".to_owned()).await?;
+    // A message enqueued in a rolled back transaction is never delivered.
+    let txn = client.begin_optimistic().await?;
+    queue.enqueue_in(&txn, "rolled back".to_owned()).await?;
+    txn.rollback().await?;
+    let txn = client.begin_optimistic().await?;
+    queue.enqueue_in(&txn, "b".to_owned()).await?;
+    txn.commit().await?;
+
+    let a = queue.dequeue(Duration::from_secs(2)).await?.unwrap();
+    assert_eq!(a.payload(), b"a");
+    assert_eq!(a.attempts(), 1);
+    let b = queue.dequeue(Duration::from_secs(60)).await?.unwrap();
+    assert_eq!(b.payload(), b"b");
+    assert!(queue.dequeue(Duration::from_secs(60)).await?.is_none());
+    assert!(queue.ack(&b).await?);
+
+    // The unacked message is delivered again once its visibility timeout expires.
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let again = queue.dequeue(Duration::from_secs(60)).await?.unwrap();
+    assert_eq!(again.payload(), b"a");
+    assert_eq!(again.attempts(), 2);
+    assert!(!queue.ack(&a).await?);
+    assert!(queue.ack(&again).await?);
+    assert!(queue.dequeue(Duration::from_secs(60)).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_delete_range_chunked() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?;
+    let pairs: Vec<KvPair> = (0..25u32)
+        .map(|i| KvPair::new(i.to_be_bytes().to_vec(), b"v".to_vec()))
+        .collect();
+    client.batch_put(pairs).await?;
+
+    let deleted = client
+        .delete_range_chunked(
+            5u32.to_be_bytes().to_vec()..20u32.to_be_bytes().to_vec(),
+            4,
+            None,
+        )
+        .await?;
+    assert_eq!(deleted, 15);
+    let deleted = client.delete_range_chunked(.., 4, Some(20)).await?;
+    assert_eq!(deleted, 10);
+    assert!(client.scan(.., 100).await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_batch_delete_existing() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?;
+    client
+        .batch_put(vec![
+            KvPair::new(b"a".to_vec(), b"1".to_vec()),
+            KvPair::new(b"c".to_vec(), b"3".to_vec()),
+        ])
+        .await?;
+
+    let keys = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+    let mut deleted = client.batch_delete_existing(keys.clone()).await?;
+    deleted.sort();
+    assert_eq!(
+        deleted,
+        vec![Key::from(b"a".to_vec()), b"c".to_vec().into()]
+    );
+    assert!(client.batch_get(keys.clone()).await?.is_empty());
+    assert!(client.batch_delete_existing(keys).await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_sequential_scan() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?.with_sequential_scan();
+    let pairs: Vec<KvPair> = (0..25u32)
+        .map(|i| KvPair::new(i.to_be_bytes().to_vec(), b"v".to_vec()))
+        .collect();
+    client.batch_put(pairs.clone()).await?;
+
+    assert_eq!(client.scan(.., 10).await?, pairs[..10].to_vec());
+    assert_eq!(client.scan(.., 100).await?, pairs);
+    let keys = client
+        .scan_keys(
+            5u32.to_be_bytes().to_vec()..=7u32.to_be_bytes().to_vec(),
+            10,
+        )
+        .await?;
+    assert_eq!(keys.len(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_scan_with() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?;
+    let pairs: Vec<KvPair> = (0..25u32)
+        .map(|i| KvPair::new(i.to_be_bytes().to_vec(), b"v".to_vec()))
+        .collect();
+    client.batch_put(pairs.clone()).await?;
+
+    let mut visited = Vec::new();
+    client
+        .scan_with(.., 10, |key, value| {
+            visited.push(KvPair::new(key.to_vec(), value.to_vec()))
+        })
+        .await?;
+    assert_eq!(visited, pairs[..10].to_vec());
+    let mut count = 0;
+    client.scan_with(.., 100, |_, _| count += 1).await?;
+    assert_eq!(count, 25);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_scan_stream() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?;
+    let pairs: Vec<KvPair> = (0..25u32)
+        .map(|i| KvPair::new(i.to_be_bytes().to_vec(), b"v".to_vec()))
+        .collect();
+    client.batch_put(pairs.clone()).await?;
+
+    let stream = client.scan_stream(.., 10);
+    assert_eq!(stream.size_hint(), (0, Some(10)));
+    assert_eq!(stream.try_collect::<Vec<_>>().await?, pairs[..10].to_vec());
+    let batches: Vec<Vec<KvPair>> = client
+        .scan_stream(.., 100)
+        .try_chunks(10)
+        .try_collect()
+        .await
+        .map_err(|e| e.1)?;
+    assert_eq!(
+        batches.iter().map(Vec::len).collect::<Vec<_>>(),
+        vec![10, 10, 5]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_read_cache() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs())
+        .await?
+        .with_read_cache("config/".to_owned().."config0".to_owned(), 10);
+    let key = "config/timeout".to_owned();
+    client.put(key.clone(), b"1".to_vec()).await?;
+    assert_eq!(client.get(key.clone()).await?, Some(b"1".to_vec()));
+
+    // Changes reach the cache asynchronously.
+    client.put(key.clone(), b"2".to_vec()).await?;
+    let mut value = client.get(key.clone()).await?;
+    for _ in 0..50 {
+        if value == Some(b"2".to_vec()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        value = client.get(key.clone()).await?;
+    }
+    assert_eq!(value, Some(b"2".to_vec()));
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_watch() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?;
+    let mut changes = client.watch("config/".to_owned()).await?.boxed();
+    client.put("config/a".to_owned(), b"1".to_vec()).await?;
+    client.put("other".to_owned(), b"1".to_vec()).await?;
+    client.delete("config/a".to_owned()).await?;
+
+    let put = changes.next().await.unwrap()?;
+    assert!(
+        matches!(&put, WatchEvent::Put { key, value, .. } if key == &Key::from("config/a".to_owned()) && value == b"1")
+    );
+    let delete = changes.next().await.unwrap()?;
+    assert!(matches!(&delete, WatchEvent::Delete { key, .. } if key == put.key()));
+
+    // Changes after the put are received again.
+    let mut changes = client
+        .watch_since("config/".to_owned(), put.commit_ts().clone())
+        .await?
+        .boxed();
+    assert_eq!(changes.next().await.unwrap()?, delete);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_ttl() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?;
+    assert_eq!(client.get_key_ttl_secs("k1".to_owned()).await?, None);
+    assert_eq!(client.get_with_ttl("k1".to_owned()).await?, None);
+
+    // Keys put without a TTL never expire.
+    client.put("k1".to_owned(), "v1".to_owned()).await?;
+    client.put("k2".to_owned(), "v2".to_owned()).await?;
+    assert_eq!(client.get_key_ttl_secs("k1".to_owned()).await?, Some(0));
+    assert_eq!(
+        client.get_with_ttl("k1".to_owned()).await?,
+        Some((b"v1".to_vec(), 0))
+    );
+    assert_eq!(
+        client.scan_with_ttl("k".to_owned().., 10).await?,
+        vec![
+            (KvPair::new("k1".to_owned(), "v1".to_owned()), 0),
+            (KvPair::new("k2".to_owned(), "v2".to_owned()), 0),
+        ]
+    );
+
+    // Updating the TTL keeps the value. The TTL is only set if TiKV has TTL enabled.
+    assert!(!client.update_ttl("k0".to_owned(), 3600).await?);
+    assert!(client.update_ttl("k1".to_owned(), 3600).await?);
+    assert_eq!(client.get("k1".to_owned()).await?, Some(b"v1".to_vec()));
+    assert!(matches!(
+        client.get_key_ttl_secs("k1".to_owned()).await?,
+        Some(ttl) if ttl <= 3600
+    ));
+    assert!(client.update_ttl("k1".to_owned(), 0).await?);
+    assert_eq!(client.get_key_ttl_secs("k1".to_owned()).await?, Some(0));
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_range_stats() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?;
+    let keys: Vec<Key> = (0..10u8).map(|i| Key::from(vec![b'k', i])).collect();
+    client
+        .batch_put(keys.iter().map(|key| (key.clone(), b"v".to_vec())))
+        .await?;
+
+    // Regions with few pairs are counted exactly.
+    let stats = client.range_stats(b"k".to_vec()..b"l".to_vec()).await?;
+    assert!(stats.regions() >= 1);
+    assert_eq!(stats.approximate_keys(), 10);
+
+    let samples = client.sample_keys(b"k".to_vec()..b"l".to_vec(), 5).await?;
+    assert!(!samples.is_empty() && samples.len() <= 5);
+    assert!(samples.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(samples.iter().all(|key| keys.contains(key)));
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_hot_regions() -> Result<()> {
+    let client =
+        RawClient::new_with_config(pd_addrs(), Config::default().with_hot_region_diagnostics())
+            .await?;
+    for region in client.hot_regions().await? {
+        assert!(region.region_id() > 0);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn raw_scan_above_max_limit() -> Result<()> {
+    clear_tikv().await;
+    let client = RawClient::new(pd_addrs()).await?;
+    let pairs: Vec<KvPair> = (0..12000u32)
+        .map(|i| KvPair::new(i.to_be_bytes().to_vec(), b"v".to_vec()))
+        .collect();
+    for chunk in pairs.chunks(1000) {
+        client.batch_put(chunk.to_vec()).await?;
+    }
+
+    assert_eq!(client.scan(.., 11000).await?, pairs[..11000].to_vec());
+    assert_eq!(client.scan_keys(.., 20000).await?.len(), 12000);
+    let batch = client
+        .batch_scan(
+            vec![
+                0u32.to_be_bytes().to_vec()..100u32.to_be_bytes().to_vec(),
+                100u32.to_be_bytes().to_vec()..12000u32.to_be_bytes().to_vec(),
+            ],
+            11000,
+        )
+        .await?;
+    assert_eq!(batch.len(), 11100);
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn txn_admin() -> Result<()> {
+    clear_tikv().await;
+    let client = TransactionClient::new(pd_addrs()).await?;
+    assert!(client.stores().await?.iter().any(|store| store.is_up()));
+
+    client
+        .split_region(vec![b"m".to_vec(), b"t".to_vec()])
+        .await?;
+    let mut txn = client.begin_optimistic().await?;
+    txn.put(b"a".to_vec(), b"1".to_vec()).await?;
+    txn.put(b"z".to_vec(), b"2".to_vec()).await?;
+    txn.commit().await?;
+
+    let now = client.current_timestamp().await?;
+    let locks = client.scan_locks(now, .., 100).await?;
+    assert!(locks.is_empty());
+    assert!(client.resolve_locks(locks).await?);
+    Ok(())
+}
+
 // helper function
 async fn get_u32(client: &RawClient, key: Vec<u8>) -> Result<u32> {
     let x = client.get(key).await?.unwrap();
@@ -75,6 +75,48 @@ mod test {
             .unwrap();
         assert_eq!(res.len(), 0);
 
+        // batch_put then batch_get_ordered
+        client
+            .batch_put(vec![
+                KvPair::new("k7".to_owned(), "v7".to_owned()),
+                KvPair::new("k8".to_owned(), "v8".to_owned()),
+            ])
+            .await
+            .unwrap();
+        let res = client
+            .batch_get_ordered(vec!["k8".to_owned(), "k9".to_owned(), "k7".to_owned()])
+            .await
+            .unwrap();
+        assert_eq!(res, vec![Some(b"v8".to_vec()), None, Some(b"v7".to_vec())]);
+
+        // compare and swap
+        let res = client
+            .compare_and_swap("k5".to_owned(), None, "v5".to_owned())
+            .await
+            .unwrap();
+        assert_eq!(res, (None, true));
+        let res = client
+            .compare_and_swap("k5".to_owned(), None, "v6".to_owned())
+            .await
+            .unwrap();
+        assert_eq!(res, (Some(b"v5".to_vec()), false));
+
+        let res = client
+            .get_or_insert("k5".to_owned(), "v7".to_owned())
+            .await
+            .unwrap();
+        assert_eq!(res, b"v5".to_vec());
+        let res = client
+            .get_or_insert("k6".to_owned(), "v6".to_owned())
+            .await
+            .unwrap();
+        assert_eq!(res, b"v6".to_vec());
+
+        // counters
+        assert_eq!(client.incr("c".to_owned(), 3).await.unwrap(), 3);
+        assert_eq!(client.incr("c".to_owned(), -5).await.unwrap(), -2);
+        assert!(client.incr("k5".to_owned(), 1).await.is_err());
+
         debug!("Pass all tests");
 
         let _ = tikv_server.shutdown().await;
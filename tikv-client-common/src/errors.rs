@@ -1,78 +1,492 @@
 // Copyright 2018 TiKV Project Authors. Licensed under Apache-2.0.
 
-use std::result;
-use thiserror::Error;
+use crate::EscapedBytes;
+use std::{fmt, result, time::Duration};
 
 /// An error originating from the TiKV client or dependencies.
-#[derive(Debug, Error)]
+#[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum Error {
     /// Feature is not implemented.
-    #[error("Unimplemented feature")]
     Unimplemented,
     /// Duplicate key insertion happens.
-    #[error("Duplicate key insertion")]
     DuplicateKeyInsertion,
     /// Failed to resolve a lock
-    #[error("Failed to resolve lock")]
     ResolveLockError,
     /// Will raise this error when using a pessimistic txn only operation on an optimistic txn
-    #[error("Invalid operation for this type of transaction")]
     InvalidTransactionType,
     /// It's not allowed to perform operations in a transaction after it has been committed or rolled back.
-    #[error("Cannot read or write data after any attempt to commit or roll back the transaction")]
     OperationAfterCommitError,
     /// We tried to use 1pc for a transaction, but it didn't work. Probably should have used 2pc.
-    #[error("1PC transaction could not be committed.")]
     OnePcFailure,
     /// An operation requires a primary key, but the transaction was empty.
-    #[error("transaction has no primary key")]
     NoPrimaryKey,
+    /// The primary key of a transaction can't be changed once chosen.
+    PrimaryKeyAlreadyChosen {
+        key: Vec<u8>,
+    },
+    /// Two writers sharing a transaction wrote the same key. `writer` is `0` if the second write
+    /// was made without a writer.
+    LocalWriteConflict {
+        key: Vec<u8>,
+        owner: u64,
+        writer: u64,
+    },
+    /// The key was flushed to TiKV by `Transaction::flush`, so the transaction can no longer
+    /// read or write it.
+    KeyFlushed {
+        key: Vec<u8>,
+    },
+    /// A snapshot was requested at a timestamp before the GC safepoint of the cluster, so the
+    /// versions it would read may have been garbage collected.
+    TimestampBeforeSafepoint {
+        version: u64,
+        safepoint: u64,
+    },
+    /// PD answered for another cluster than the one the client connected to, e.g. because the
+    /// DNS records of its endpoints now point at another cluster. Nothing is sent to the other
+    /// cluster once this is detected.
+    ClusterIdMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// The operation was cancelled with its cancellation token before it finished.
+    OperationCanceled,
+    /// The TiKV cluster doesn't support the feature the operation depends on, because of its
+    /// version or its configuration. Nothing was sent to the cluster.
+    UnsupportedFeature {
+        feature: &'static str,
+    },
     /// Wraps a `std::io::Error`.
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+    Io(std::io::Error),
     /// Wraps a `grpcio::Error`.
-    #[error("gRPC error: {0}")]
-    Grpc(#[from] grpcio::Error),
+    Grpc(grpcio::Error),
     /// Represents that a futures oneshot channel was cancelled.
-    #[error("A futures oneshot channel was canceled. {0}")]
-    Canceled(#[from] futures::channel::oneshot::Canceled),
+    Canceled(futures::channel::oneshot::Canceled),
     /// Errors caused by changes of region information
-    #[error("Region error: {0:?}")]
     RegionError(tikv_client_proto::errorpb::Error),
+    /// A request to a region PD reports as hot failed.
+    HotRegion {
+        region_id: u64,
+        diagnostics: String,
+        source: Box<Error>,
+    },
     /// Whether the transaction is committed or not is undetermined
-    #[error("Whether the transaction is committed or not is undetermined")]
+    ///
+    /// The response to a request which may have committed the transaction was lost. The
+    /// transaction must not be retried blindly, as it may have been applied.
     UndeterminedError(Box<Error>),
+    /// The transaction wrote a key which was written by another transaction committed after it
+    /// started. The transaction was not committed, and may succeed if retried from the start.
+    WriteConflict {
+        key: Vec<u8>,
+        primary: Vec<u8>,
+        start_ts: u64,
+        conflict_start_ts: u64,
+        conflict_commit_ts: u64,
+    },
+    /// The locks of the transaction expired and were rolled back, or its commit timestamp was
+    /// too old, before it committed. The transaction was not committed, and may succeed if
+    /// retried from the start.
+    TransactionExpired {
+        start_ts: u64,
+        key: Vec<u8>,
+    },
     /// Wraps `tikv_client_proto::kvrpcpb::KeyError`
-    #[error("{0:?}")]
     KeyError(tikv_client_proto::kvrpcpb::KeyError),
+    /// The key is locked by another transaction and the lock could not be resolved.
+    LockConflict {
+        key: Vec<u8>,
+        lock_owner_start_ts: u64,
+        primary: Vec<u8>,
+        ttl: u64,
+    },
+    /// A deadlock was detected while acquiring a pessimistic lock.
+    ///
+    /// The transaction waiting for the lock was aborted. `wait_chain` lists the transactions
+    /// waiting for each other which formed the deadlock, if reported by TiKV.
+    Deadlock {
+        lock_ts: u64,
+        lock_key: Vec<u8>,
+        deadlock_key_hash: u64,
+        wait_chain: Vec<tikv_client_proto::deadlock::WaitForEntry>,
+    },
+    /// The lease of a distributed lock expired and the lock was acquired by another owner.
+    LockLost {
+        key: Vec<u8>,
+        fence_token: u64,
+    },
+    /// Retries were exhausted; `attempts` records every failed attempt in order.
+    BackoffExhausted {
+        attempts: Vec<Attempt>,
+    },
+    /// An operation didn't finish within the timeout set for it.
+    OperationTimeout(Duration),
     /// Multiple errors
-    #[error("Multiple errors: {0:?}")]
     MultipleErrors(Vec<Error>),
     /// Invalid ColumnFamily
-    #[error("Unsupported column family {}", _0)]
     ColumnFamilyError(String),
     /// No region is found for the given key.
-    #[error("Region is not found for key: {:?}", key)]
-    RegionForKeyNotFound { key: Vec<u8> },
+    RegionForKeyNotFound {
+        key: Vec<u8>,
+    },
     /// No region is found for the given id.
-    #[error("Region {} is not found", region_id)]
-    RegionNotFound { region_id: u64 },
+    RegionNotFound {
+        region_id: u64,
+    },
     /// No leader is found for the given id.
-    #[error("Leader of region {} is not found", region_id)]
-    LeaderNotFound { region_id: u64 },
+    LeaderNotFound {
+        region_id: u64,
+    },
     /// Scan limit exceeds the maximum
-    #[error("Limit {} exceeds max scan limit {}", limit, max_limit)]
-    MaxScanLimitExceeded { limit: u32, max_limit: u32 },
+    MaxScanLimitExceeded {
+        limit: u32,
+        max_limit: u32,
+    },
+    /// A value could not be encoded or decoded by a codec.
+    CodecError {
+        message: String,
+    },
+    /// The checksum stored with a value does not match the value read.
+    CorruptedValue {
+        expected: u64,
+        actual: u64,
+    },
     /// A string error returned by TiKV server
-    #[error("Kv error. {}", message)]
-    KvError { message: String },
-    #[error("{}", message)]
-    InternalError { message: String },
-    #[error("{0}")]
+    KvError {
+        message: String,
+    },
+    InternalError {
+        message: String,
+    },
     StringError(String),
 }
 
+impl Error {
+    /// The stable code of the error, to match on or forward to other systems.
+    ///
+    /// Errors annotated with the diagnostics of a hot region have the code of the error they
+    /// annotate.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Unimplemented => ErrorCode::Unimplemented,
+            Error::DuplicateKeyInsertion => ErrorCode::DuplicateKeyInsertion,
+            Error::ResolveLockError => ErrorCode::ResolveLock,
+            Error::InvalidTransactionType => ErrorCode::InvalidTransactionType,
+            Error::OperationAfterCommitError => ErrorCode::OperationAfterCommit,
+            Error::OnePcFailure => ErrorCode::OnePcFailure,
+            Error::NoPrimaryKey => ErrorCode::NoPrimaryKey,
+            Error::PrimaryKeyAlreadyChosen { .. } => ErrorCode::PrimaryKeyAlreadyChosen,
+            Error::LocalWriteConflict { .. } => ErrorCode::LocalWriteConflict,
+            Error::KeyFlushed { .. } => ErrorCode::KeyFlushed,
+            Error::TimestampBeforeSafepoint { .. } => ErrorCode::TimestampBeforeSafepoint,
+            Error::ClusterIdMismatch { .. } => ErrorCode::ClusterIdMismatch,
+            Error::OperationCanceled => ErrorCode::OperationCanceled,
+            Error::UnsupportedFeature { .. } => ErrorCode::UnsupportedFeature,
+            Error::Io(_) => ErrorCode::Io,
+            Error::Grpc(_) => ErrorCode::Grpc,
+            Error::Canceled(_) => ErrorCode::Canceled,
+            Error::RegionError(_) => ErrorCode::Region,
+            Error::HotRegion { source, .. } => source.code(),
+            Error::UndeterminedError(_) => ErrorCode::Undetermined,
+            Error::WriteConflict { .. } => ErrorCode::WriteConflict,
+            Error::TransactionExpired { .. } => ErrorCode::TransactionExpired,
+            Error::KeyError(_) => ErrorCode::Key,
+            Error::LockConflict { .. } => ErrorCode::LockConflict,
+            Error::Deadlock { .. } => ErrorCode::Deadlock,
+            Error::LockLost { .. } => ErrorCode::LockLost,
+            Error::BackoffExhausted { .. } => ErrorCode::BackoffExhausted,
+            Error::OperationTimeout(_) => ErrorCode::OperationTimeout,
+            Error::MultipleErrors(_) => ErrorCode::Multiple,
+            Error::ColumnFamilyError(_) => ErrorCode::ColumnFamily,
+            Error::RegionForKeyNotFound { .. } => ErrorCode::RegionForKeyNotFound,
+            Error::RegionNotFound { .. } => ErrorCode::RegionNotFound,
+            Error::LeaderNotFound { .. } => ErrorCode::LeaderNotFound,
+            Error::MaxScanLimitExceeded { .. } => ErrorCode::MaxScanLimitExceeded,
+            Error::CodecError { .. } => ErrorCode::Codec,
+            Error::CorruptedValue { .. } => ErrorCode::CorruptedValue,
+            Error::KvError { .. } => ErrorCode::Kv,
+            Error::InternalError { .. } => ErrorCode::Internal,
+            Error::StringError(_) => ErrorCode::Other,
+        }
+    }
+
+    /// The key the error is about, if any, looking through the errors wrapped by this one.
+    pub fn key(&self) -> Option<&[u8]> {
+        match self {
+            Error::PrimaryKeyAlreadyChosen { key }
+            | Error::LocalWriteConflict { key, .. }
+            | Error::KeyFlushed { key }
+            | Error::WriteConflict { key, .. }
+            | Error::TransactionExpired { key, .. }
+            | Error::LockConflict { key, .. }
+            | Error::LockLost { key, .. }
+            | Error::RegionForKeyNotFound { key } => Some(key),
+            Error::Deadlock { lock_key, .. } => Some(lock_key),
+            Error::KeyError(e) => e
+                .already_exist
+                .as_ref()
+                .map(|exist| exist.key.as_slice())
+                .or_else(|| {
+                    e.txn_not_found
+                        .as_ref()
+                        .map(|txn| txn.primary_key.as_slice())
+                }),
+            Error::HotRegion { source, .. } | Error::UndeterminedError(source) => source.key(),
+            Error::BackoffExhausted { attempts } => {
+                attempts.last().and_then(|attempt| attempt.error.key())
+            }
+            _ => None,
+        }
+    }
+
+    /// The gRPC status of the failed request, if the error is a gRPC failure, looking through the
+    /// errors wrapped by this one.
+    pub fn grpc_status(&self) -> Option<&grpcio::RpcStatus> {
+        match self {
+            Error::Grpc(grpcio::Error::RpcFailure(status)) => Some(status),
+            Error::Grpc(grpcio::Error::RpcFinished(status)) => status.as_ref(),
+            Error::HotRegion { source, .. } | Error::UndeterminedError(source) => {
+                source.grpc_status()
+            }
+            Error::BackoffExhausted { attempts } => attempts
+                .last()
+                .and_then(|attempt| attempt.error.grpc_status()),
+            _ => None,
+        }
+    }
+
+    /// Whether the transaction failed without being committed, and may succeed if retried from
+    /// the start, e.g. after a write conflict.
+    ///
+    /// Errors for which whether the transaction was committed is unknown are never retryable,
+    /// see [`UndeterminedError`](Error::UndeterminedError).
+    pub fn is_retryable_transaction(&self) -> bool {
+        matches!(
+            self.last_error(),
+            Error::WriteConflict { .. } | Error::TransactionExpired { .. }
+        )
+    }
+
+    /// The error of the last attempt if retries of the request were exhausted, or else this
+    /// error.
+    ///
+    /// Retried requests fail with [`BackoffExhausted`](Error::BackoffExhausted), so this gives
+    /// the error to match on, e.g. a [`LockConflict`](Error::LockConflict), whether the request
+    /// was retried or not. Errors annotated with the diagnostics of a hot region are looked
+    /// through as well.
+    pub fn last_error(&self) -> &Error {
+        match self {
+            Error::HotRegion { source, .. } => source.last_error(),
+            Error::BackoffExhausted { attempts } => match attempts.last() {
+                Some(attempt) => attempt.error.last_error(),
+                None => self,
+            },
+            _ => self,
+        }
+    }
+
+    /// The failed attempts of a request whose retries were exhausted, in order, or none if the
+    /// request wasn't retried. Errors annotated with the diagnostics of a hot region are looked
+    /// through.
+    pub fn attempts(&self) -> &[Attempt] {
+        match self {
+            Error::HotRegion { source, .. } => source.attempts(),
+            Error::BackoffExhausted { attempts } => attempts,
+            _ => &[],
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unimplemented => f.write_str("Unimplemented feature"),
+            Error::DuplicateKeyInsertion => f.write_str("Duplicate key insertion"),
+            Error::ResolveLockError => f.write_str("Failed to resolve lock"),
+            Error::InvalidTransactionType => {
+                f.write_str("Invalid operation for this type of transaction")
+            }
+            Error::OperationAfterCommitError => f.write_str(
+                "Cannot read or write data after any attempt to commit or roll back the transaction",
+            ),
+            Error::OnePcFailure => f.write_str("1PC transaction could not be committed."),
+            Error::NoPrimaryKey => f.write_str("transaction has no primary key"),
+            Error::PrimaryKeyAlreadyChosen { key } => write!(
+                f,
+                "transaction already has primary key {}",
+                EscapedBytes(key)
+            ),
+            Error::LocalWriteConflict { key, owner, writer } => write!(
+                f,
+                "{} wrote key {}, which was already written by writer {} of the transaction",
+                match writer {
+                    0 => "a write without a writer".to_owned(),
+                    writer => format!("writer {}", writer),
+                },
+                EscapedBytes(key),
+                owner
+            ),
+            Error::KeyFlushed { key } => write!(
+                f,
+                "Key {} was flushed and can't be accessed again in the transaction",
+                EscapedBytes(key)
+            ),
+            Error::TimestampBeforeSafepoint { version, safepoint } => write!(
+                f,
+                "Timestamp {} is before the GC safepoint {} of the cluster",
+                version, safepoint
+            ),
+            Error::ClusterIdMismatch { expected, actual } => write!(
+                f,
+                "Connected to PD cluster {}, but PD answered for cluster {}",
+                expected, actual
+            ),
+            Error::OperationCanceled => f.write_str("Operation was canceled"),
+            Error::UnsupportedFeature { feature } => {
+                write!(f, "{} is not supported by the cluster", feature)
+            }
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::Grpc(e) => write!(f, "gRPC error: {}", e),
+            Error::Canceled(e) => write!(f, "A futures oneshot channel was canceled. {}", e),
+            Error::RegionError(e) => write!(f, "Region error: {:?}", e),
+            Error::HotRegion {
+                region_id,
+                diagnostics,
+                source,
+            } => write!(f, "{} (region {} is {})", source, region_id, diagnostics),
+            Error::UndeterminedError(_) => {
+                f.write_str("Whether the transaction is committed or not is undetermined")
+            }
+            Error::WriteConflict {
+                key,
+                start_ts,
+                conflict_start_ts,
+                conflict_commit_ts,
+                ..
+            } => write!(
+                f,
+                "Write conflict on key {}: transaction {} conflicts with transaction {} committed at {}",
+                EscapedBytes(key),
+                start_ts,
+                conflict_start_ts,
+                conflict_commit_ts
+            ),
+            Error::TransactionExpired { start_ts, key } => write!(
+                f,
+                "Transaction {} expired before committing key {}",
+                start_ts,
+                EscapedBytes(key)
+            ),
+            Error::KeyError(e) => write!(f, "{:?}", e),
+            Error::LockConflict {
+                key,
+                lock_owner_start_ts,
+                primary,
+                ttl,
+            } => write!(
+                f,
+                "Key {} is locked by transaction {} (primary: {}, ttl: {})",
+                EscapedBytes(key),
+                lock_owner_start_ts,
+                EscapedBytes(primary),
+                ttl
+            ),
+            Error::Deadlock {
+                lock_ts,
+                lock_key,
+                wait_chain,
+                ..
+            } => write!(
+                f,
+                "Deadlock detected on key {}, waiting for lock of transaction {}{}",
+                EscapedBytes(lock_key),
+                lock_ts,
+                format_wait_chain(wait_chain)
+            ),
+            Error::LockLost { key, fence_token } => write!(
+                f,
+                "Lost lock on key {} held with fence token {}",
+                EscapedBytes(key),
+                fence_token
+            ),
+            Error::BackoffExhausted { attempts } => write!(
+                f,
+                "Retries exhausted after {} attempts, last error: {:?}",
+                attempts.len(),
+                attempts.last().map(|attempt| &attempt.error)
+            ),
+            Error::OperationTimeout(timeout) => {
+                write!(f, "Operation timed out after {:?}", timeout)
+            }
+            Error::MultipleErrors(errors) => write!(f, "Multiple errors: {:?}", errors),
+            Error::ColumnFamilyError(cf) => write!(f, "Unsupported column family {}", cf),
+            Error::RegionForKeyNotFound { key } => {
+                write!(f, "Region is not found for key: {}", EscapedBytes(key))
+            }
+            Error::RegionNotFound { region_id } => {
+                write!(f, "Region {} is not found", region_id)
+            }
+            Error::LeaderNotFound { region_id } => {
+                write!(f, "Leader of region {} is not found", region_id)
+            }
+            Error::MaxScanLimitExceeded { limit, max_limit } => write!(
+                f,
+                "Limit {} exceeds max scan limit {}",
+                limit, max_limit
+            ),
+            Error::CodecError { message } => write!(f, "Codec error: {}", message),
+            Error::CorruptedValue { expected, actual } => write!(
+                f,
+                "Value is corrupted: expected checksum {:#x}, got {:#x}",
+                expected, actual
+            ),
+            Error::KvError { message } => write!(f, "Kv error. {}", message),
+            Error::InternalError { message } => f.write_str(message),
+            Error::StringError(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    /// The error this one wraps. Errors wrapping other client errors return them as is, so the
+    /// chain can be walked with `downcast_ref::<Error>()`: exhausted retries lead to the error
+    /// of the last attempt, and multiple errors to the first one.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Grpc(e) => Some(e),
+            Error::Canceled(e) => Some(e),
+            Error::HotRegion { source, .. } | Error::UndeterminedError(source) => Some(&**source),
+            Error::BackoffExhausted { attempts } => attempts
+                .last()
+                .map(|attempt| &attempt.error as &(dyn std::error::Error + 'static)),
+            Error::MultipleErrors(errors) => errors
+                .first()
+                .map(|error| error as &(dyn std::error::Error + 'static)),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<grpcio::Error> for Error {
+    fn from(e: grpcio::Error) -> Error {
+        Error::Grpc(e)
+    }
+}
+
+impl From<futures::channel::oneshot::Canceled> for Error {
+    fn from(e: futures::channel::oneshot::Canceled) -> Error {
+        Error::Canceled(e)
+    }
+}
+
 impl From<tikv_client_proto::errorpb::Error> for Error {
     fn from(e: tikv_client_proto::errorpb::Error) -> Error {
         Error::RegionError(e)
@@ -80,11 +494,174 @@ impl From<tikv_client_proto::errorpb::Error> for Error {
 }
 
 impl From<tikv_client_proto::kvrpcpb::KeyError> for Error {
-    fn from(e: tikv_client_proto::kvrpcpb::KeyError) -> Error {
-        Error::KeyError(e)
+    fn from(mut e: tikv_client_proto::kvrpcpb::KeyError) -> Error {
+        if let Some(deadlock) = e.deadlock.take() {
+            Error::Deadlock {
+                lock_ts: deadlock.lock_ts,
+                lock_key: deadlock.lock_key,
+                deadlock_key_hash: deadlock.deadlock_key_hash,
+                wait_chain: deadlock.wait_chain,
+            }
+        } else if let Some(lock) = e.locked.take() {
+            lock.into()
+        } else if let Some(conflict) = e.conflict.take() {
+            Error::WriteConflict {
+                key: conflict.key,
+                primary: conflict.primary,
+                start_ts: conflict.start_ts,
+                conflict_start_ts: conflict.conflict_ts,
+                conflict_commit_ts: conflict.conflict_commit_ts,
+            }
+        } else {
+            Error::KeyError(e)
+        }
+    }
+}
+
+impl From<tikv_client_proto::kvrpcpb::LockInfo> for Error {
+    fn from(lock: tikv_client_proto::kvrpcpb::LockInfo) -> Error {
+        Error::LockConflict {
+            key: lock.key,
+            lock_owner_start_ts: lock.lock_version,
+            primary: lock.primary_lock,
+            ttl: lock.lock_ttl,
+        }
+    }
+}
+
+fn format_wait_chain(wait_chain: &[tikv_client_proto::deadlock::WaitForEntry]) -> String {
+    if wait_chain.is_empty() {
+        return String::new();
+    }
+    let entries: Vec<String> = wait_chain
+        .iter()
+        .map(|entry| {
+            format!(
+                "{} waits for {} on key {}",
+                entry.txn,
+                entry.wait_for_txn,
+                EscapedBytes(&entry.key)
+            )
+        })
+        .collect();
+    format!(" (wait chain: {})", entries.join(", "))
+}
+
+/// The stable code of an [`Error`](enum@Error), see [`Error::code`].
+///
+/// The numeric and string forms of a code never change across versions. New codes may be added,
+/// so matches on codes need a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    Unimplemented = 1,
+    DuplicateKeyInsertion = 2,
+    ResolveLock = 3,
+    InvalidTransactionType = 4,
+    OperationAfterCommit = 5,
+    OnePcFailure = 6,
+    NoPrimaryKey = 7,
+    PrimaryKeyAlreadyChosen = 8,
+    LocalWriteConflict = 9,
+    Io = 10,
+    Grpc = 11,
+    Canceled = 12,
+    Region = 13,
+    Undetermined = 14,
+    WriteConflict = 15,
+    TransactionExpired = 16,
+    Key = 17,
+    LockConflict = 18,
+    Deadlock = 19,
+    LockLost = 20,
+    BackoffExhausted = 21,
+    OperationTimeout = 22,
+    Multiple = 23,
+    ColumnFamily = 24,
+    RegionForKeyNotFound = 25,
+    RegionNotFound = 26,
+    LeaderNotFound = 27,
+    MaxScanLimitExceeded = 28,
+    Codec = 29,
+    CorruptedValue = 30,
+    Kv = 31,
+    Internal = 32,
+    Other = 33,
+    KeyFlushed = 34,
+    TimestampBeforeSafepoint = 35,
+    ClusterIdMismatch = 36,
+    OperationCanceled = 37,
+    UnsupportedFeature = 38,
+}
+
+impl ErrorCode {
+    /// The numeric form of the code.
+    pub fn as_u16(self) -> u16 {
+        self as u16
+    }
+
+    /// The string form of the code, in snake case.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Unimplemented => "unimplemented",
+            ErrorCode::DuplicateKeyInsertion => "duplicate_key_insertion",
+            ErrorCode::ResolveLock => "resolve_lock",
+            ErrorCode::InvalidTransactionType => "invalid_transaction_type",
+            ErrorCode::OperationAfterCommit => "operation_after_commit",
+            ErrorCode::OnePcFailure => "one_pc_failure",
+            ErrorCode::NoPrimaryKey => "no_primary_key",
+            ErrorCode::PrimaryKeyAlreadyChosen => "primary_key_already_chosen",
+            ErrorCode::LocalWriteConflict => "local_write_conflict",
+            ErrorCode::Io => "io",
+            ErrorCode::Grpc => "grpc",
+            ErrorCode::Canceled => "canceled",
+            ErrorCode::Region => "region",
+            ErrorCode::Undetermined => "undetermined",
+            ErrorCode::WriteConflict => "write_conflict",
+            ErrorCode::TransactionExpired => "transaction_expired",
+            ErrorCode::Key => "key",
+            ErrorCode::LockConflict => "lock_conflict",
+            ErrorCode::Deadlock => "deadlock",
+            ErrorCode::LockLost => "lock_lost",
+            ErrorCode::BackoffExhausted => "backoff_exhausted",
+            ErrorCode::OperationTimeout => "operation_timeout",
+            ErrorCode::Multiple => "multiple",
+            ErrorCode::ColumnFamily => "column_family",
+            ErrorCode::RegionForKeyNotFound => "region_for_key_not_found",
+            ErrorCode::RegionNotFound => "region_not_found",
+            ErrorCode::LeaderNotFound => "leader_not_found",
+            ErrorCode::MaxScanLimitExceeded => "max_scan_limit_exceeded",
+            ErrorCode::Codec => "codec",
+            ErrorCode::CorruptedValue => "corrupted_value",
+            ErrorCode::Kv => "kv",
+            ErrorCode::Internal => "internal",
+            ErrorCode::Other => "other",
+            ErrorCode::KeyFlushed => "key_flushed",
+            ErrorCode::TimestampBeforeSafepoint => "timestamp_before_safepoint",
+            ErrorCode::ClusterIdMismatch => "cluster_id_mismatch",
+            ErrorCode::OperationCanceled => "operation_canceled",
+            ErrorCode::UnsupportedFeature => "unsupported_feature",
+        }
     }
 }
 
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A failed attempt recorded by a retrying request plan.
+#[derive(Debug)]
+pub struct Attempt {
+    /// The error which caused the attempt to fail.
+    pub error: Error,
+    /// How long the plan waited before the next attempt (zero for the final attempt).
+    pub delay: Duration,
+    /// Time elapsed between the start of the first attempt and the failure of this one.
+    pub elapsed: Duration,
+}
+
 /// A result holding an [`Error`](enum@Error).
 pub type Result<T> = result::Result<T, Error>;
 
@@ -99,3 +676,133 @@ macro_rules! internal_err {
         internal_err!(format!($f, $($arg),+))
     });
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_error_code() {
+        assert_eq!(Error::DuplicateKeyInsertion.code().as_u16(), 2);
+        let conflict = Error::WriteConflict {
+            key: b"k".to_vec(),
+            primary: b"p".to_vec(),
+            start_ts: 1,
+            conflict_start_ts: 2,
+            conflict_commit_ts: 3,
+        };
+        let hot = Error::HotRegion {
+            region_id: 1,
+            diagnostics: "hot".to_owned(),
+            source: Box::new(conflict),
+        };
+        assert_eq!(hot.code(), ErrorCode::WriteConflict);
+        assert_eq!(hot.code().to_string(), "write_conflict");
+        assert_eq!(hot.key(), Some(&b"k"[..]));
+        let conflict = hot.source().unwrap().to_string();
+        assert!(conflict.starts_with("Write conflict on key k:"));
+
+        let locked = Error::LockConflict {
+            key: b"t\x80\x00\x01".to_vec(),
+            lock_owner_start_ts: 1,
+            primary: b"p".to_vec(),
+            ttl: 3000,
+        };
+        assert_eq!(
+            locked.to_string(),
+            r"Key t\x80\x00\x01 is locked by transaction 1 (primary: p, ttl: 3000)"
+        );
+
+        let status = grpcio::RpcStatus::new(grpcio::RpcStatusCode::UNAVAILABLE, None);
+        let undetermined =
+            Error::UndeterminedError(Box::new(grpcio::Error::RpcFailure(status).into()));
+        assert_eq!(undetermined.code(), ErrorCode::Undetermined);
+        assert_eq!(
+            undetermined.grpc_status().unwrap().status,
+            grpcio::RpcStatusCode::UNAVAILABLE
+        );
+        // The chain leads to the gRPC error.
+        let grpc = undetermined.source().unwrap().source().unwrap();
+        assert!(grpc.downcast_ref::<grpcio::Error>().is_some());
+        assert!(undetermined.key().is_none());
+
+        // Exhausted retries lead to the error of the last attempt, and on to its source.
+        let attempt = |error| Attempt {
+            error,
+            delay: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        };
+        let exhausted = Error::BackoffExhausted {
+            attempts: vec![attempt(Error::DuplicateKeyInsertion), attempt(undetermined)],
+        };
+        let last = exhausted.source().unwrap().downcast_ref::<Error>().unwrap();
+        assert!(matches!(last, Error::UndeterminedError(_)));
+        // The last error is in the chain once, as the source rather than in the message too.
+        let grpc = last.source().unwrap().source().unwrap();
+        assert!(grpc.downcast_ref::<grpcio::Error>().is_some());
+        assert!(grpc.source().is_none());
+        let multiple = Error::MultipleErrors(vec![hot, Error::DuplicateKeyInsertion]);
+        let first = multiple.source().unwrap().downcast_ref::<Error>().unwrap();
+        assert!(matches!(first, Error::HotRegion { .. }));
+        let conflict = first.source().unwrap().downcast_ref::<Error>().unwrap();
+        assert!(matches!(conflict, Error::WriteConflict { .. }));
+        assert!(conflict.source().is_none());
+    }
+
+    #[test]
+    fn test_last_error() {
+        let conflict = || Error::WriteConflict {
+            key: b"k".to_vec(),
+            primary: b"p".to_vec(),
+            start_ts: 1,
+            conflict_start_ts: 2,
+            conflict_commit_ts: 3,
+        };
+        let attempt = |error| Attempt {
+            error,
+            delay: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        };
+        assert!(conflict().is_retryable_transaction());
+        assert!(matches!(
+            conflict().last_error(),
+            Error::WriteConflict { .. }
+        ));
+
+        // The error of the last attempt decides, whatever the earlier attempts failed with.
+        let exhausted = Error::BackoffExhausted {
+            attempts: vec![attempt(Error::DuplicateKeyInsertion), attempt(conflict())],
+        };
+        assert!(exhausted.is_retryable_transaction());
+        assert!(matches!(
+            exhausted.last_error(),
+            Error::WriteConflict { start_ts: 1, .. }
+        ));
+        let exhausted = Error::BackoffExhausted {
+            attempts: vec![attempt(conflict()), attempt(Error::DuplicateKeyInsertion)],
+        };
+        assert!(!exhausted.is_retryable_transaction());
+        assert!(matches!(
+            exhausted.last_error(),
+            Error::DuplicateKeyInsertion
+        ));
+        assert_eq!(exhausted.attempts().len(), 2);
+        assert!(conflict().attempts().is_empty());
+
+        let hot = Error::HotRegion {
+            region_id: 1,
+            diagnostics: "hot".to_owned(),
+            source: Box::new(Error::BackoffExhausted {
+                attempts: vec![attempt(conflict())],
+            }),
+        };
+        assert!(hot.is_retryable_transaction());
+        let undetermined = Error::UndeterminedError(Box::new(conflict()));
+        assert!(!undetermined.is_retryable_transaction());
+        assert!(matches!(
+            undetermined.last_error(),
+            Error::UndeterminedError(_)
+        ));
+    }
+}
@@ -0,0 +1,37 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{ascii, fmt};
+
+/// Displays bytes, such as a key, with printable ASCII characters as is and other bytes escaped,
+/// e.g. `user\x00\x01`.
+///
+/// Bytes are escaped like [`std::ascii::escape_default`] does, so backslashes and quotes are
+/// escaped too.
+pub struct EscapedBytes<'a>(pub &'a [u8]);
+
+impl fmt::Display for EscapedBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0 {
+            for c in ascii::escape_default(*byte) {
+                fmt::Write::write_char(f, c as char)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_escaped_bytes() {
+        assert_eq!(EscapedBytes(b"").to_string(), "");
+        assert_eq!(EscapedBytes(b"user:1").to_string(), "user:1");
+        assert_eq!(
+            EscapedBytes(b"t\x80\x00\x01\xff\n").to_string(),
+            r"t\x80\x00\x01\xff\n"
+        );
+        assert_eq!(EscapedBytes(b"a\\\"b'").to_string(), r#"a\\\"b\'"#);
+    }
+}
@@ -1,9 +1,13 @@
 #[macro_use]
 mod errors;
+mod format;
 pub mod security;
+pub mod tasks;
 
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
 #[doc(inline)]
-pub use crate::errors::{Error, Result};
+pub use crate::errors::{Attempt, Error, ErrorCode, Result};
+#[doc(inline)]
+pub use crate::format::EscapedBytes;
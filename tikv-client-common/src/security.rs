@@ -72,7 +72,7 @@ impl SecurityManager {
     where
         Factory: FnOnce(Channel) -> Client,
     {
-        info!("connect to rpc server at endpoint: {:?}", addr);
+        info!(target: "tikv_client::security", addr, "connecting to rpc server");
 
         let addr = SCHEME_REG.replace(addr, "");
 
@@ -92,6 +92,40 @@ impl SecurityManager {
 
         Ok(factory(channel))
     }
+
+    /// The base URL of the HTTP API served at `addr`, over HTTPS if TLS is configured.
+    pub fn http_url(&self, addr: &str) -> String {
+        let addr = SCHEME_REG.replace(addr, "");
+        let scheme = if self.ca.is_empty() { "http" } else { "https" };
+        format!("{}://{}", scheme, addr.trim_end_matches('/'))
+    }
+
+    /// A client of the HTTP APIs of PD and TiKV, which presents the same certificate as gRPC
+    /// connections if TLS is configured. Requests time out after `timeout`.
+    ///
+    /// The HTTP client only accepts private keys in PKCS #8 format (`BEGIN PRIVATE KEY`), which
+    /// `openssl pkcs8 -topk8 -nocrypt` converts other formats to.
+    pub fn http_client(&self, timeout: Duration) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder().timeout(timeout);
+        if !self.ca.is_empty() {
+            let ca = reqwest::Certificate::from_pem(&self.ca)
+                .map_err(|e| internal_err!("invalid CA certificate: {}", e))?;
+            let key = load_pem_file("private key", &self.key)?;
+            let identity = reqwest::Identity::from_pkcs8_pem(&self.cert, &key).map_err(|e| {
+                internal_err!(
+                    "invalid certificate or private key, which must be in PKCS #8 format: {}",
+                    e
+                )
+            })?;
+            builder = builder
+                .tls_built_in_root_certs(false)
+                .add_root_certificate(ca)
+                .identity(identity);
+        }
+        builder
+            .build()
+            .map_err(|e| internal_err!("failed to build HTTP client: {}", e))
+    }
 }
 
 #[cfg(test)]
@@ -121,5 +155,12 @@ mod tests {
         assert_eq!(mgr.cert, vec![1]);
         let key = load_pem_file("private key", &key_path).unwrap();
         assert_eq!(key, vec![2]);
+        assert_eq!(mgr.http_url("127.0.0.1:20180"), "https://127.0.0.1:20180");
+        assert_eq!(
+            SecurityManager::default().http_url("http://127.0.0.1:2379/"),
+            "http://127.0.0.1:2379"
+        );
+        // The files aren't PEM encoded.
+        assert!(mgr.http_client(Duration::from_secs(1)).is_err());
     }
 }
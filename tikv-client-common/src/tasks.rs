@@ -0,0 +1,96 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A registry of the background tasks run by the clients, such as the timestamp oracle stream and
+//! the heartbeats of transactions.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+lazy_static::lazy_static! {
+    // The tasks running, by the order they were started in.
+    static ref TASKS: Mutex<BTreeMap<u64, TaskInfo>> = Default::default();
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A background task run by a client.
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    id: u64,
+    name: &'static str,
+    started: Instant,
+}
+
+impl TaskInfo {
+    /// A number identifying the task among those started in the process.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// What the task does, e.g. `txn-heartbeat`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// How long the task has been running.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Registers a task as running for as long as the guard lives, so it is unregistered however the
+/// task ends, including when it is aborted.
+pub struct TaskGuard {
+    id: u64,
+}
+
+impl TaskGuard {
+    pub fn register(name: &'static str) -> TaskGuard {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let task = TaskInfo {
+            id,
+            name,
+            started: Instant::now(),
+        };
+        TASKS.lock().unwrap().insert(id, task);
+        TaskGuard { id }
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        TASKS.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// The background tasks currently running, the oldest first.
+pub fn running_tasks() -> Vec<TaskInfo> {
+    TASKS.lock().unwrap().values().cloned().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let guard = TaskGuard::register("test-task");
+        let running = |id| running_tasks().iter().any(|task| task.id() == id);
+        assert!(running(guard.id));
+        let task = running_tasks()
+            .into_iter()
+            .find(|task| task.id() == guard.id)
+            .unwrap();
+        assert_eq!(task.name(), "test-task");
+
+        let id = guard.id;
+        drop(guard);
+        assert!(!running(id));
+    }
+}
@@ -1,22 +1,31 @@
 // Copyright 2018 TiKV Project Authors. Licensed under Apache-2.0.
 
-use crate::{timestamp::TimestampOracle, Error, Result, SecurityManager};
+use crate::{health::EndpointHealth, timestamp::TimestampOracle, Error, Result, SecurityManager};
 use async_trait::async_trait;
-use grpcio::{CallOption, Environment};
+use grpcio::{CallOption, Channel, Environment, MetadataBuilder};
 use std::{
     collections::HashSet,
     sync::Arc,
     time::{Duration, Instant},
 };
 use tikv_client_common::internal_err;
-use tikv_client_proto::pdpb::{self, Timestamp};
+use tikv_client_proto::{
+    keyspacepb,
+    pdpb::{self, Timestamp},
+    resource_manager,
+};
 
 /// A PD cluster.
 pub struct Cluster {
     id: u64,
     client: pdpb::PdClient,
+    // The keyspace service of the leader, on the same channel as `client`.
+    keyspace: keyspacepb::KeyspaceClient,
+    // The resource manager, served by the leader unless it is deployed on its own.
+    resource_manager: resource_manager::ResourceManagerClient,
     members: pdpb::GetMembersResponse,
     tso: TimestampOracle,
+    token: Option<String>,
 }
 
 macro_rules! pd_request {
@@ -31,6 +40,16 @@ macro_rules! pd_request {
 
 // These methods make a single attempt to make a request.
 impl Cluster {
+    /// The ID of the cluster, reported by its members.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The URLs of the leader, for clients.
+    pub fn leader_urls(&self) -> &[String] {
+        self.members.get_leader().get_client_urls()
+    }
+
     pub async fn get_region(
         &self,
         key: Vec<u8>,
@@ -38,7 +57,8 @@ impl Cluster {
     ) -> Result<pdpb::GetRegionResponse> {
         let mut req = pd_request!(self.id, pdpb::GetRegionRequest);
         req.set_region_key(key.clone());
-        req.send(&self.client, timeout).await
+        req.send(self.id, &self.client, call_option(&self.token, timeout)?)
+            .await
     }
 
     pub async fn get_region_by_id(
@@ -48,24 +68,33 @@ impl Cluster {
     ) -> Result<pdpb::GetRegionResponse> {
         let mut req = pd_request!(self.id, pdpb::GetRegionByIdRequest);
         req.set_region_id(id);
-        req.send(&self.client, timeout).await
+        req.send(self.id, &self.client, call_option(&self.token, timeout)?)
+            .await
     }
 
     pub async fn get_store(&self, id: u64, timeout: Duration) -> Result<pdpb::GetStoreResponse> {
         let mut req = pd_request!(self.id, pdpb::GetStoreRequest);
         req.set_store_id(id);
-        req.send(&self.client, timeout).await
+        req.send(self.id, &self.client, call_option(&self.token, timeout)?)
+            .await
     }
 
     pub async fn get_all_stores(&self, timeout: Duration) -> Result<pdpb::GetAllStoresResponse> {
         let req = pd_request!(self.id, pdpb::GetAllStoresRequest);
-        req.send(&self.client, timeout).await
+        req.send(self.id, &self.client, call_option(&self.token, timeout)?)
+            .await
     }
 
     pub async fn get_timestamp(&self) -> Result<Timestamp> {
         self.tso.clone().get_timestamp().await
     }
 
+    pub async fn get_safepoint(&self, timeout: Duration) -> Result<pdpb::GetGcSafePointResponse> {
+        let req = pd_request!(self.id, pdpb::GetGcSafePointRequest);
+        req.send(self.id, &self.client, call_option(&self.token, timeout)?)
+            .await
+    }
+
     pub async fn update_safepoint(
         &self,
         safepoint: u64,
@@ -73,7 +102,93 @@ impl Cluster {
     ) -> Result<pdpb::UpdateGcSafePointResponse> {
         let mut req = pd_request!(self.id, pdpb::UpdateGcSafePointRequest);
         req.set_safe_point(safepoint);
-        req.send(&self.client, timeout).await
+        req.send(self.id, &self.client, call_option(&self.token, timeout)?)
+            .await
+    }
+
+    pub async fn update_service_safepoint(
+        &self,
+        service_id: String,
+        ttl: i64,
+        safepoint: u64,
+        timeout: Duration,
+    ) -> Result<pdpb::UpdateServiceGcSafePointResponse> {
+        let mut req = pd_request!(self.id, pdpb::UpdateServiceGcSafePointRequest);
+        req.set_service_id(service_id.into_bytes());
+        req.set_ttl(ttl);
+        req.set_safe_point(safepoint);
+        req.send(self.id, &self.client, call_option(&self.token, timeout)?)
+            .await
+    }
+
+    pub async fn load_keyspace(
+        &self,
+        name: String,
+        timeout: Duration,
+    ) -> Result<keyspacepb::LoadKeyspaceResponse> {
+        let mut req = pd_request!(self.id, keyspacepb::LoadKeyspaceRequest);
+        req.set_name(name);
+        req.send(self.id, &self.keyspace, call_option(&self.token, timeout)?)
+            .await
+    }
+
+    pub async fn get_all_keyspaces(
+        &self,
+        start_id: u32,
+        limit: u32,
+        timeout: Duration,
+    ) -> Result<keyspacepb::GetAllKeyspacesResponse> {
+        let mut req = pd_request!(self.id, keyspacepb::GetAllKeyspacesRequest);
+        req.set_start_id(start_id);
+        req.set_limit(limit);
+        req.send(self.id, &self.keyspace, call_option(&self.token, timeout)?)
+            .await
+    }
+
+    pub async fn list_resource_groups(
+        &self,
+        timeout: Duration,
+    ) -> Result<resource_manager::ListResourceGroupsResponse> {
+        let req = resource_manager::ListResourceGroupsRequest::default();
+        req.send(&self.resource_manager, call_option(&self.token, timeout)?)
+            .await
+    }
+
+    pub async fn get_resource_group(
+        &self,
+        name: String,
+        timeout: Duration,
+    ) -> Result<resource_manager::GetResourceGroupResponse> {
+        let mut req = resource_manager::GetResourceGroupRequest::default();
+        req.set_resource_group_name(name);
+        req.send(&self.resource_manager, call_option(&self.token, timeout)?)
+            .await
+    }
+
+    pub async fn put_resource_group(
+        &self,
+        group: resource_manager::ResourceGroup,
+        exists: bool,
+        timeout: Duration,
+    ) -> Result<resource_manager::PutResourceGroupResponse> {
+        let mut req = PutResourceGroup {
+            exists,
+            request: resource_manager::PutResourceGroupRequest::default(),
+        };
+        req.request.set_group(group);
+        req.send(&self.resource_manager, call_option(&self.token, timeout)?)
+            .await
+    }
+
+    pub async fn delete_resource_group(
+        &self,
+        name: String,
+        timeout: Duration,
+    ) -> Result<resource_manager::DeleteResourceGroupResponse> {
+        let mut req = resource_manager::DeleteResourceGroupRequest::default();
+        req.set_resource_group_name(name);
+        req.send(&self.resource_manager, call_option(&self.token, timeout)?)
+            .await
     }
 }
 
@@ -81,11 +196,24 @@ impl Cluster {
 pub struct Connection {
     env: Arc<Environment>,
     security_mgr: Arc<SecurityManager>,
+    token: Option<String>,
+    health: EndpointHealth,
 }
 
 impl Connection {
     pub fn new(env: Arc<Environment>, security_mgr: Arc<SecurityManager>) -> Connection {
-        Connection { env, security_mgr }
+        Connection {
+            env,
+            security_mgr,
+            token: None,
+            health: EndpointHealth::default(),
+        }
+    }
+
+    /// Authenticate every request to PD with a bearer token.
+    pub fn with_token(mut self, token: impl Into<String>) -> Connection {
+        self.token = Some(token.into());
+        self
     }
 
     pub async fn connect_cluster(
@@ -94,33 +222,44 @@ impl Connection {
         timeout: Duration,
     ) -> Result<Cluster> {
         let members = self.validate_endpoints(endpoints, timeout).await?;
-        let (client, members) = self.try_connect_leader(&members, timeout).await?;
+        let (channel, members) = self.try_connect_leader(&members, timeout).await?;
         let id = members.get_header().get_cluster_id();
-        let tso = TimestampOracle::new(id, &client)?;
-        let cluster = Cluster {
-            id,
-            members,
-            client,
-            tso,
-        };
-        Ok(cluster)
+        self.new_cluster(id, channel, members)
     }
 
     // Re-establish connection with PD leader in asynchronous fashion.
     pub async fn reconnect(&self, cluster: &mut Cluster, timeout: Duration) -> Result<()> {
-        warn!("updating pd client");
+        warn!(target: "tikv_client::pd", "updating PD client");
         let start = Instant::now();
-        let (client, members) = self.try_connect_leader(&cluster.members, timeout).await?;
-        let tso = TimestampOracle::new(cluster.id, &client)?;
-        *cluster = Cluster {
-            id: cluster.id,
+        let (channel, members) = self.try_connect_leader(&cluster.members, timeout).await?;
+        *cluster = self.new_cluster(cluster.id, channel, members)?;
+
+        info!(
+            target: "tikv_client::pd",
+            elapsed = ?start.elapsed(),
+            "updating PD client done"
+        );
+        Ok(())
+    }
+
+    /// The cluster `id`, whose leader is connected through `channel`.
+    fn new_cluster(
+        &self,
+        id: u64,
+        channel: Channel,
+        members: pdpb::GetMembersResponse,
+    ) -> Result<Cluster> {
+        let client = pdpb::PdClient::new(channel.clone());
+        let tso = TimestampOracle::new(id, &client, call_option(&self.token, None)?)?;
+        Ok(Cluster {
+            id,
             client,
+            keyspace: keyspacepb::KeyspaceClient::new(channel.clone()),
+            resource_manager: resource_manager::ResourceManagerClient::new(channel),
             members,
             tso,
-        };
-
-        info!("updating PD client done, spent {:?}", start.elapsed());
-        Ok(())
+            token: self.token.clone(),
+        })
     }
 
     async fn validate_endpoints(
@@ -130,18 +269,30 @@ impl Connection {
     ) -> Result<pdpb::GetMembersResponse> {
         let mut endpoints_set = HashSet::with_capacity(endpoints.len());
 
-        let mut members = None;
-        let mut cluster_id = None;
         for ep in endpoints {
             if !endpoints_set.insert(ep) {
                 return Err(internal_err!("duplicated PD endpoint {}", ep));
             }
+        }
+
+        let mut members = None;
+        let mut cluster_id = None;
+        for ep in self.health.order(endpoints.iter().map(String::as_str)) {
+            // Don't wait for endpoints which failed lately once a healthy one answered.
+            if members.is_some() && !self.health.is_healthy(ep) {
+                continue;
+            }
 
             let (_, resp) = match self.connect(ep, timeout).await {
                 Ok(resp) => resp,
                 // Ignore failed PD node.
                 Err(e) => {
-                    warn!("PD endpoint {} failed to respond: {:?}", ep, e);
+                    warn!(
+                        target: "tikv_client::pd",
+                        endpoint = ep,
+                        error = ?e,
+                        "PD endpoint failed to respond"
+                    );
                     continue;
                 }
             };
@@ -149,13 +300,7 @@ impl Connection {
             // Check cluster ID.
             let cid = resp.get_header().get_cluster_id();
             if let Some(sample) = cluster_id {
-                if sample != cid {
-                    return Err(internal_err!(
-                        "PD response cluster_id mismatch, want {}, got {}",
-                        sample,
-                        cid
-                    ));
-                }
+                check_cluster_id(sample, resp.get_header())?;
             } else {
                 cluster_id = Some(cid);
             }
@@ -168,7 +313,7 @@ impl Connection {
 
         match members {
             Some(members) => {
-                info!("All PD endpoints are consistent: {:?}", endpoints);
+                info!(target: "tikv_client::pd", ?endpoints, "all PD endpoints are consistent");
                 Ok(members)
             }
             _ => Err(internal_err!("PD cluster failed to respond")),
@@ -179,16 +324,27 @@ impl Connection {
         &self,
         addr: &str,
         timeout: Duration,
-    ) -> Result<(pdpb::PdClient, pdpb::GetMembersResponse)> {
-        let client = self
+    ) -> Result<(Channel, pdpb::GetMembersResponse)> {
+        let channel = self
             .security_mgr
-            .connect(self.env.clone(), addr, pdpb::PdClient::new)?;
-        let option = CallOption::default().timeout(timeout);
-        let resp = client
-            .get_members_async_opt(&pdpb::GetMembersRequest::default(), option)
-            .map_err(Error::from)?
-            .await?;
-        Ok((client, resp))
+            .connect(self.env.clone(), addr, |channel| channel)?;
+        let option = call_option(&self.token, timeout)?;
+        let resp = async {
+            pdpb::PdClient::new(channel.clone())
+                .get_members_async_opt(&pdpb::GetMembersRequest::default(), option)?
+                .await
+        }
+        .await;
+        match resp {
+            Ok(resp) => {
+                self.health.succeeded(addr);
+                Ok((channel, resp))
+            }
+            Err(e) => {
+                self.health.failed(addr);
+                Err(e.into())
+            }
+        }
     }
 
     async fn try_connect(
@@ -196,10 +352,10 @@ impl Connection {
         addr: &str,
         cluster_id: u64,
         timeout: Duration,
-    ) -> Result<(pdpb::PdClient, pdpb::GetMembersResponse)> {
-        let (client, r) = self.connect(addr, timeout).await?;
+    ) -> Result<(Channel, pdpb::GetMembersResponse)> {
+        let (channel, r) = self.connect(addr, timeout).await?;
         Connection::validate_cluster_id(addr, &r, cluster_id)?;
-        Ok((client, r))
+        Ok((channel, r))
     }
 
     fn validate_cluster_id(
@@ -207,45 +363,53 @@ impl Connection {
         members: &pdpb::GetMembersResponse,
         cluster_id: u64,
     ) -> Result<()> {
-        let new_cluster_id = members.get_header().get_cluster_id();
-        if new_cluster_id != cluster_id {
-            Err(internal_err!(
-                "{} no longer belongs to cluster {}, it is in {}",
-                addr,
-                cluster_id,
-                new_cluster_id
-            ))
-        } else {
-            Ok(())
-        }
+        check_cluster_id(cluster_id, members.get_header()).map_err(|e| {
+            warn!(
+                target: "tikv_client::pd",
+                endpoint = addr,
+                error = %e,
+                "PD member belongs to another cluster"
+            );
+            e
+        })
     }
 
     async fn try_connect_leader(
         &self,
         previous: &pdpb::GetMembersResponse,
         timeout: Duration,
-    ) -> Result<(pdpb::PdClient, pdpb::GetMembersResponse)> {
+    ) -> Result<(Channel, pdpb::GetMembersResponse)> {
         let previous_leader = previous.get_leader();
         let members = previous.get_members();
         let cluster_id = previous.get_header().get_cluster_id();
 
         let mut resp = None;
-        // Try to connect to other members, then the previous leader.
-        'outer: for m in members
+        let mut mismatch = None;
+        // Try to connect to other members, the healthiest first, then the previous leader.
+        let others = members
             .iter()
             .filter(|m| *m != previous_leader)
-            .chain(Some(previous_leader))
-        {
-            for ep in m.get_client_urls() {
-                match self.try_connect(ep.as_str(), cluster_id, timeout).await {
-                    Ok((_, r)) => {
-                        resp = Some(r);
-                        break 'outer;
-                    }
-                    Err(e) => {
-                        error!("failed to connect to {}, {:?}", ep, e);
-                        continue;
-                    }
+            .flat_map(|m| m.get_client_urls().iter().map(String::as_str));
+        let endpoints = self
+            .health
+            .order(others)
+            .into_iter()
+            .chain(previous_leader.get_client_urls().iter().map(String::as_str));
+        for ep in endpoints {
+            match self.try_connect(ep, cluster_id, timeout).await {
+                Ok((_, r)) => {
+                    resp = Some(r);
+                    break;
+                }
+                Err(e @ Error::ClusterIdMismatch { .. }) => mismatch = Some(e),
+                Err(e) => {
+                    error!(
+                        target: "tikv_client::pd",
+                        endpoint = ep,
+                        error = ?e,
+                        "failed to connect to PD"
+                    );
+                    continue;
                 }
             }
         }
@@ -254,29 +418,67 @@ impl Connection {
         if let Some(resp) = resp {
             let leader = resp.get_leader();
             for ep in leader.get_client_urls() {
-                let r = self.try_connect(ep.as_str(), cluster_id, timeout).await;
-                if r.is_ok() {
-                    return r;
+                match self.try_connect(ep.as_str(), cluster_id, timeout).await {
+                    Err(e @ Error::ClusterIdMismatch { .. }) => mismatch = Some(e),
+                    r @ Ok(_) => return r,
+                    Err(_) => {}
                 }
             }
         }
 
-        Err(internal_err!("failed to connect to {:?}", members))
+        // Members found in another cluster are worse than unreachable ones, don't hide them.
+        match mismatch {
+            Some(e) => Err(e),
+            None => Err(internal_err!("failed to connect to {:?}", members)),
+        }
+    }
+}
+
+/// Check that a response from PD is from the cluster `cluster_id`.
+pub(crate) fn check_cluster_id(cluster_id: u64, header: &pdpb::ResponseHeader) -> Result<()> {
+    let actual = header.get_cluster_id();
+    if actual != cluster_id {
+        return Err(Error::ClusterIdMismatch {
+            expected: cluster_id,
+            actual,
+        });
     }
+    Ok(())
+}
+
+/// The options of a call to PD, carrying the bearer token if any.
+fn call_option(token: &Option<String>, timeout: impl Into<Option<Duration>>) -> Result<CallOption> {
+    let mut option = CallOption::default();
+    if let Some(timeout) = timeout.into() {
+        option = option.timeout(timeout);
+    }
+    if let Some(token) = token {
+        let mut headers = MetadataBuilder::new();
+        headers.add_str("authorization", &format!("Bearer {}", token))?;
+        option = option.headers(headers.build());
+    }
+    Ok(option)
 }
 
 type GrpcResult<T> = std::result::Result<T, grpcio::Error>;
 
 #[async_trait]
 trait PdMessage {
+    // The client of the service serving the request.
+    type Client: Sync;
     type Response: PdResponse;
 
-    async fn rpc(&self, client: &pdpb::PdClient, opt: CallOption) -> GrpcResult<Self::Response>;
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response>;
 
-    async fn send(&self, client: &pdpb::PdClient, timeout: Duration) -> Result<Self::Response> {
-        let option = CallOption::default().timeout(timeout);
+    async fn send(
+        &self,
+        cluster_id: u64,
+        client: &Self::Client,
+        option: CallOption,
+    ) -> Result<Self::Response> {
         let response = self.rpc(client, option).await?;
 
+        check_cluster_id(cluster_id, response.header())?;
         if response.header().has_error() {
             Err(internal_err!(response.header().get_error().get_message()))
         } else {
@@ -287,49 +489,96 @@ trait PdMessage {
 
 #[async_trait]
 impl PdMessage for pdpb::GetRegionRequest {
+    type Client = pdpb::PdClient;
     type Response = pdpb::GetRegionResponse;
 
-    async fn rpc(&self, client: &pdpb::PdClient, opt: CallOption) -> GrpcResult<Self::Response> {
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
         client.get_region_async_opt(self, opt)?.await
     }
 }
 
 #[async_trait]
 impl PdMessage for pdpb::GetRegionByIdRequest {
+    type Client = pdpb::PdClient;
     type Response = pdpb::GetRegionResponse;
 
-    async fn rpc(&self, client: &pdpb::PdClient, opt: CallOption) -> GrpcResult<Self::Response> {
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
         client.get_region_by_id_async_opt(self, opt)?.await
     }
 }
 
 #[async_trait]
 impl PdMessage for pdpb::GetStoreRequest {
+    type Client = pdpb::PdClient;
     type Response = pdpb::GetStoreResponse;
 
-    async fn rpc(&self, client: &pdpb::PdClient, opt: CallOption) -> GrpcResult<Self::Response> {
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
         client.get_store_async_opt(self, opt)?.await
     }
 }
 
 #[async_trait]
 impl PdMessage for pdpb::GetAllStoresRequest {
+    type Client = pdpb::PdClient;
     type Response = pdpb::GetAllStoresResponse;
 
-    async fn rpc(&self, client: &pdpb::PdClient, opt: CallOption) -> GrpcResult<Self::Response> {
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
         client.get_all_stores_async_opt(self, opt)?.await
     }
 }
 
+#[async_trait]
+impl PdMessage for pdpb::GetGcSafePointRequest {
+    type Client = pdpb::PdClient;
+    type Response = pdpb::GetGcSafePointResponse;
+
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
+        client.get_gc_safe_point_async_opt(self, opt)?.await
+    }
+}
+
 #[async_trait]
 impl PdMessage for pdpb::UpdateGcSafePointRequest {
+    type Client = pdpb::PdClient;
     type Response = pdpb::UpdateGcSafePointResponse;
 
-    async fn rpc(&self, client: &pdpb::PdClient, opt: CallOption) -> GrpcResult<Self::Response> {
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
         client.update_gc_safe_point_async_opt(self, opt)?.await
     }
 }
 
+#[async_trait]
+impl PdMessage for pdpb::UpdateServiceGcSafePointRequest {
+    type Client = pdpb::PdClient;
+    type Response = pdpb::UpdateServiceGcSafePointResponse;
+
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
+        client
+            .update_service_gc_safe_point_async_opt(self, opt)?
+            .await
+    }
+}
+
+#[async_trait]
+impl PdMessage for keyspacepb::LoadKeyspaceRequest {
+    type Client = keyspacepb::KeyspaceClient;
+    type Response = keyspacepb::LoadKeyspaceResponse;
+
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
+        client.load_keyspace_async_opt(self, opt)?.await
+    }
+}
+
+#[async_trait]
+impl PdMessage for keyspacepb::GetAllKeyspacesRequest {
+    type Client = keyspacepb::KeyspaceClient;
+    type Response = keyspacepb::GetAllKeyspacesResponse;
+
+    async fn rpc(&self, client: &Self::Client, opt: CallOption) -> GrpcResult<Self::Response> {
+        client.get_all_keyspaces_async_opt(self, opt)?.await
+    }
+}
+
 trait PdResponse {
     fn header(&self) -> &pdpb::ResponseHeader;
 }
@@ -352,8 +601,145 @@ impl PdResponse for pdpb::GetAllStoresResponse {
     }
 }
 
+impl PdResponse for pdpb::GetGcSafePointResponse {
+    fn header(&self) -> &pdpb::ResponseHeader {
+        self.get_header()
+    }
+}
+
 impl PdResponse for pdpb::UpdateGcSafePointResponse {
     fn header(&self) -> &pdpb::ResponseHeader {
         self.get_header()
     }
 }
+
+impl PdResponse for pdpb::UpdateServiceGcSafePointResponse {
+    fn header(&self) -> &pdpb::ResponseHeader {
+        self.get_header()
+    }
+}
+
+impl PdResponse for keyspacepb::LoadKeyspaceResponse {
+    fn header(&self) -> &pdpb::ResponseHeader {
+        self.get_header()
+    }
+}
+
+impl PdResponse for keyspacepb::GetAllKeyspacesResponse {
+    fn header(&self) -> &pdpb::ResponseHeader {
+        self.get_header()
+    }
+}
+
+/// A request to the resource manager, whose responses carry errors rather than a header.
+#[async_trait]
+trait ResourceManagerMessage {
+    type Response: ResourceManagerResponse;
+
+    async fn rpc(
+        &self,
+        client: &resource_manager::ResourceManagerClient,
+        opt: CallOption,
+    ) -> GrpcResult<Self::Response>;
+
+    async fn send(
+        &self,
+        client: &resource_manager::ResourceManagerClient,
+        option: CallOption,
+    ) -> Result<Self::Response> {
+        let response = self.rpc(client, option).await?;
+        match response.error() {
+            Some(error) => Err(internal_err!(error.get_message())),
+            None => Ok(response),
+        }
+    }
+}
+
+/// Adding a new resource group, or modifying an existing one, which take the same request.
+struct PutResourceGroup {
+    exists: bool,
+    request: resource_manager::PutResourceGroupRequest,
+}
+
+#[async_trait]
+impl ResourceManagerMessage for resource_manager::ListResourceGroupsRequest {
+    type Response = resource_manager::ListResourceGroupsResponse;
+
+    async fn rpc(
+        &self,
+        client: &resource_manager::ResourceManagerClient,
+        opt: CallOption,
+    ) -> GrpcResult<Self::Response> {
+        client.list_resource_groups_async_opt(self, opt)?.await
+    }
+}
+
+#[async_trait]
+impl ResourceManagerMessage for resource_manager::GetResourceGroupRequest {
+    type Response = resource_manager::GetResourceGroupResponse;
+
+    async fn rpc(
+        &self,
+        client: &resource_manager::ResourceManagerClient,
+        opt: CallOption,
+    ) -> GrpcResult<Self::Response> {
+        client.get_resource_group_async_opt(self, opt)?.await
+    }
+}
+
+#[async_trait]
+impl ResourceManagerMessage for PutResourceGroup {
+    type Response = resource_manager::PutResourceGroupResponse;
+
+    async fn rpc(
+        &self,
+        client: &resource_manager::ResourceManagerClient,
+        opt: CallOption,
+    ) -> GrpcResult<Self::Response> {
+        if self.exists {
+            client
+                .modify_resource_group_async_opt(&self.request, opt)?
+                .await
+        } else {
+            client
+                .add_resource_group_async_opt(&self.request, opt)?
+                .await
+        }
+    }
+}
+
+#[async_trait]
+impl ResourceManagerMessage for resource_manager::DeleteResourceGroupRequest {
+    type Response = resource_manager::DeleteResourceGroupResponse;
+
+    async fn rpc(
+        &self,
+        client: &resource_manager::ResourceManagerClient,
+        opt: CallOption,
+    ) -> GrpcResult<Self::Response> {
+        client.delete_resource_group_async_opt(self, opt)?.await
+    }
+}
+
+trait ResourceManagerResponse {
+    fn error(&self) -> Option<&resource_manager::Error>;
+}
+
+macro_rules! impl_resource_manager_response {
+    ($($type:ty),*) => {
+        $(
+            impl ResourceManagerResponse for $type {
+                fn error(&self) -> Option<&resource_manager::Error> {
+                    self.error.as_ref()
+                }
+            }
+        )*
+    };
+}
+
+impl_resource_manager_response!(
+    resource_manager::ListResourceGroupsResponse,
+    resource_manager::GetResourceGroupResponse,
+    resource_manager::PutResourceGroupResponse,
+    resource_manager::DeleteResourceGroupResponse
+);
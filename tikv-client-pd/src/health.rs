@@ -0,0 +1,106 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use rand::{thread_rng, Rng};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// How long an endpoint is avoided after failing once, doubling with each consecutive failure.
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The health of PD endpoints, so connections are attempted to the endpoints which answered last
+/// rather than always in the order they were given.
+///
+/// An endpoint which fails is avoided for a while, longer after each consecutive failure, with
+/// jitter so that clients which lost the same endpoint don't all come back to it at once. It is
+/// healthy again as soon as it answers.
+#[derive(Default)]
+pub(crate) struct EndpointHealth {
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+struct Failures {
+    count: u32,
+    // When the endpoint is worth trying again.
+    retry_at: Instant,
+}
+
+impl EndpointHealth {
+    pub(crate) fn succeeded(&self, endpoint: &str) {
+        self.failures.lock().unwrap().remove(endpoint);
+    }
+
+    pub(crate) fn failed(&self, endpoint: &str) {
+        let mut failures = self.failures.lock().unwrap();
+        let failures = failures.entry(endpoint.to_owned()).or_insert(Failures {
+            count: 0,
+            retry_at: Instant::now(),
+        });
+        failures.count += 1;
+        let backoff = BASE_BACKOFF
+            .checked_mul(1 << (failures.count - 1).min(16))
+            .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF));
+        // Equal jitter: between half the backoff and the backoff.
+        let backoff = backoff / 2 + backoff.mul_f64(thread_rng().gen::<f64>() / 2.0);
+        failures.retry_at = Instant::now() + backoff;
+    }
+
+    /// Whether `endpoint` isn't being avoided after failing.
+    pub(crate) fn is_healthy(&self, endpoint: &str) -> bool {
+        match self.failures.lock().unwrap().get(endpoint) {
+            Some(failures) => failures.retry_at <= Instant::now(),
+            None => true,
+        }
+    }
+
+    /// `endpoints` in the order they should be tried: those which never failed in the order
+    /// given, then those which failed but may be tried again, then those being avoided, the ones
+    /// which failed the least first.
+    pub(crate) fn order<'a>(&self, endpoints: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+        let failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        let mut endpoints: Vec<_> = endpoints.into_iter().collect();
+        endpoints.sort_by_key(|endpoint| {
+            failures
+                .get(*endpoint)
+                .map(|failures| (failures.retry_at > now, failures.count, failures.retry_at))
+        });
+        endpoints
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_order() {
+        let health = EndpointHealth::default();
+        let endpoints = ["pd1", "pd2", "pd3"];
+        assert_eq!(health.order(endpoints.iter().copied()), endpoints);
+
+        health.failed("pd1");
+        assert!(!health.is_healthy("pd1"));
+        assert_eq!(
+            health.order(endpoints.iter().copied()),
+            ["pd2", "pd3", "pd1"]
+        );
+
+        health.failed("pd2");
+        health.failed("pd2");
+        assert_eq!(
+            health.order(endpoints.iter().copied()),
+            ["pd3", "pd1", "pd2"]
+        );
+
+        health.succeeded("pd1");
+        assert!(health.is_healthy("pd1"));
+        assert_eq!(
+            health.order(endpoints.iter().copied()),
+            ["pd1", "pd3", "pd2"]
+        );
+    }
+}
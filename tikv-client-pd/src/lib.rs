@@ -6,7 +6,8 @@ pub use cluster::{Cluster, Connection};
 pub use tikv_client_common::{security::SecurityManager, Error, Result};
 
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
 mod cluster;
+mod health;
 mod timestamp;
@@ -11,7 +11,7 @@
 //! single `TsoRequest` to the PD server. The other future receives `TsoResponse`s from the PD
 //! server and allocates timestamps for the requests.
 
-use crate::{Error, Result};
+use crate::{cluster::check_cluster_id, Error, Result};
 use futures::{
     channel::{mpsc, oneshot},
     executor::block_on,
@@ -19,9 +19,9 @@ use futures::{
     prelude::*,
     task::{AtomicWaker, Context, Poll},
 };
-use grpcio::WriteFlags;
+use grpcio::{CallOption, WriteFlags};
 use std::{cell::RefCell, collections::VecDeque, pin::Pin, rc::Rc, thread};
-use tikv_client_common::internal_err;
+use tikv_client_common::{internal_err, tasks::TaskGuard};
 use tikv_client_proto::pdpb::*;
 
 /// It is an empirical value.
@@ -44,20 +44,27 @@ pub(crate) struct TimestampOracle {
 }
 
 impl TimestampOracle {
-    pub(crate) fn new(cluster_id: u64, pd_client: &PdClient) -> Result<TimestampOracle> {
+    pub(crate) fn new(
+        cluster_id: u64,
+        pd_client: &PdClient,
+        option: CallOption,
+    ) -> Result<TimestampOracle> {
         let (request_tx, request_rx) = mpsc::channel(MAX_BATCH_SIZE);
-        // FIXME: use tso_opt
-        let (rpc_sender, rpc_receiver) = pd_client.tso()?;
+        let (rpc_sender, rpc_receiver) = pd_client.tso_opt(option)?;
 
         // Start a background thread to handle TSO requests and responses
-        thread::spawn(move || {
-            block_on(run_tso(
-                cluster_id,
-                rpc_sender.sink_err_into(),
-                rpc_receiver.err_into(),
-                request_rx,
-            ))
-        });
+        thread::Builder::new()
+            .name("tikv-client-tso".to_owned())
+            .spawn(move || {
+                let _guard = TaskGuard::register("tso");
+                let _span = info_span!(target: "tikv_client::task", "task", name = "tso").entered();
+                block_on(run_tso(
+                    cluster_id,
+                    rpc_sender.sink_err_into(),
+                    rpc_receiver.err_into(),
+                    request_rx,
+                ))
+            })?;
 
         Ok(TimestampOracle { request_tx })
     }
@@ -107,16 +114,18 @@ async fn run_tso(
                 sending_future_waker.wake();
             }
 
+            // Timestamps of another cluster would break the ordering of transactions.
+            check_cluster_id(cluster_id, resp.get_header())?;
             allocate_timestamps(&resp, &mut pending_requests)?;
         }
         // TODO: distinguish between unexpected stream termination and expected end of test
-        info!("TSO stream terminated");
+        info!(target: "tikv_client::pd", "TSO stream terminated");
         Ok(())
     };
 
     let (send_res, recv_res): (_, Result<()>) = join!(send_requests, receive_and_handle_responses);
-    info!("TSO send termination: {:?}", send_res);
-    info!("TSO receive termination: {:?}", recv_res);
+    info!(target: "tikv_client::pd", result = ?send_res, "TSO send terminated");
+    info!(target: "tikv_client::pd", result = ?recv_res, "TSO receive terminated");
 }
 
 struct RequestGroup {
@@ -1,7 +1,10 @@
 // Copyright 2020 TiKV Project Authors. Licensed under Apache-2.0.
 
 use protos::*;
-pub use protos::{coprocessor, errorpb, kvrpcpb, metapb, mpp, pdpb, raft_serverpb, tikvpb};
+pub use protos::{
+    cdcpb, coprocessor, deadlock, debugpb, errorpb, keyspacepb, kvrpcpb, metapb, mpp, pdpb,
+    raft_serverpb, resource_manager, tikvpb, tipb,
+};
 
 #[allow(dead_code)]
 #[allow(clippy::all)]
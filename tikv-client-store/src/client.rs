@@ -1,11 +1,18 @@
 // Copyright 2020 TiKV Project Authors. Licensed under Apache-2.0.
 
-use crate::{request::Request, Result, SecurityManager};
+use crate::{
+    limiter::ConcurrencyLimiter, request::Request, stats::StatsRecorder, RequestMetadata, Result,
+    SecurityManager, StoreStats,
+};
 use async_trait::async_trait;
 use derive_new::new;
-use grpcio::{CallOption, Environment};
-use std::{any::Any, sync::Arc, time::Duration};
-use tikv_client_proto::tikvpb::TikvClient;
+use grpcio::{Channel, Environment};
+use std::{
+    any::Any,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tikv_client_proto::{cdcpb::ChangeDataClient, debugpb::DebugClient, tikvpb::TikvClient};
 
 /// A trait for connecting to TiKV stores.
 pub trait KvConnect: Sized + Send + Sync + 'static {
@@ -26,14 +33,55 @@ impl KvConnect for TikvConnect {
 
     fn connect(&self, address: &str) -> Result<KvRpcClient> {
         self.security_mgr
-            .connect(self.env.clone(), address, TikvClient::new)
-            .map(|c| KvRpcClient::new(Arc::new(c), self.timeout))
+            .connect(self.env.clone(), address, |channel| {
+                (TikvClient::new(channel.clone()), channel)
+            })
+            .map(|(c, channel)| KvRpcClient {
+                channel: Some(channel),
+                ..KvRpcClient::new(Arc::new(c), self.timeout)
+            })
+    }
+}
+
+impl TikvConnect {
+    /// Connect to the change data capture service of a TiKV store.
+    pub fn connect_change_data(&self, address: &str) -> Result<ChangeDataClient> {
+        self.security_mgr
+            .connect(self.env.clone(), address, ChangeDataClient::new)
+    }
+
+    /// Connect to the debug service of a TiKV store.
+    pub fn connect_debug(&self, address: &str) -> Result<DebugClient> {
+        self.security_mgr
+            .connect(self.env.clone(), address, DebugClient::new)
+    }
+
+    /// The TLS settings stores are connected with.
+    pub fn security_mgr(&self) -> &Arc<SecurityManager> {
+        &self.security_mgr
     }
 }
 
 #[async_trait]
 pub trait KvClient {
-    async fn dispatch(&self, req: &dyn Request) -> Result<Box<dyn Any>>;
+    async fn dispatch(&self, req: &dyn Request, metadata: &RequestMetadata)
+        -> Result<Box<dyn Any>>;
+
+    /// The statistics of the requests dispatched by the client, if it records them.
+    fn stats(&self) -> Option<StoreStats> {
+        None
+    }
+
+    /// The limiter of the requests in flight to the store, if they are limited.
+    fn limiter(&self) -> Option<Arc<ConcurrencyLimiter>> {
+        None
+    }
+
+    /// Establish the connection to the store if it isn't yet, and wait until it is connected or
+    /// the timeout of requests passes. Returns whether the store is connected.
+    async fn wait_for_connected(&self) -> bool {
+        true
+    }
 }
 
 /// This client handles requests for a single TiKV node. It converts the data
@@ -42,16 +90,43 @@ pub trait KvClient {
 pub struct KvRpcClient {
     rpc_client: Arc<TikvClient>,
     timeout: Duration,
+    #[new(default)]
+    stats: Arc<StatsRecorder>,
+    #[new(default)]
+    limiter: Arc<ConcurrencyLimiter>,
+    // The channel of the client, to wait for it to connect.
+    #[new(default)]
+    channel: Option<Channel>,
 }
 
 #[async_trait]
 impl KvClient for KvRpcClient {
-    async fn dispatch(&self, request: &dyn Request) -> Result<Box<dyn Any>> {
-        request
-            .dispatch(
-                &self.rpc_client,
-                CallOption::default().timeout(self.timeout),
-            )
-            .await
+    async fn dispatch(
+        &self,
+        request: &dyn Request,
+        metadata: &RequestMetadata,
+    ) -> Result<Box<dyn Any>> {
+        let option = metadata.call_option(self.timeout)?;
+        let start = Instant::now();
+        let result = request.dispatch(&self.rpc_client, option).await;
+        self.stats.record(start.elapsed(), result.is_ok());
+        result
+    }
+
+    fn stats(&self) -> Option<StoreStats> {
+        let mut stats = self.stats.get();
+        stats.concurrency_limit = self.limiter.limit();
+        Some(stats)
+    }
+
+    fn limiter(&self) -> Option<Arc<ConcurrencyLimiter>> {
+        Some(self.limiter.clone())
+    }
+
+    async fn wait_for_connected(&self) -> bool {
+        match &self.channel {
+            Some(channel) => channel.wait_for_connected(self.timeout).await,
+            None => true,
+        }
     }
 }
@@ -2,10 +2,15 @@
 
 use crate::Error;
 use std::fmt::Display;
-use tikv_client_proto::kvrpcpb;
+use tikv_client_proto::{coprocessor, errorpb, kvrpcpb};
 
 pub trait HasRegionError {
     fn region_error(&mut self) -> Option<Error>;
+
+    /// The region error of a response, without taking it.
+    fn peek_region_error(&self) -> Option<&errorpb::Error> {
+        None
+    }
 }
 
 pub trait HasError: HasRegionError {
@@ -22,6 +27,10 @@ macro_rules! has_region_error {
                     None
                 }
             }
+
+            fn peek_region_error(&self) -> Option<&errorpb::Error> {
+                self.region_error.as_ref()
+            }
         }
     };
 }
@@ -52,6 +61,10 @@ has_region_error!(kvrpcpb::RawBatchDeleteResponse);
 has_region_error!(kvrpcpb::RawDeleteRangeResponse);
 has_region_error!(kvrpcpb::RawScanResponse);
 has_region_error!(kvrpcpb::RawBatchScanResponse);
+has_region_error!(kvrpcpb::RawCasResponse);
+has_region_error!(kvrpcpb::RawGetKeyTtlResponse);
+has_region_error!(kvrpcpb::SplitRegionResponse);
+has_region_error!(coprocessor::Response);
 
 macro_rules! has_key_error {
     ($type:ty) => {
@@ -100,9 +113,29 @@ has_str_error!(kvrpcpb::RawBatchPutResponse);
 has_str_error!(kvrpcpb::RawDeleteResponse);
 has_str_error!(kvrpcpb::RawBatchDeleteResponse);
 has_str_error!(kvrpcpb::RawDeleteRangeResponse);
+has_str_error!(kvrpcpb::RawCasResponse);
+has_str_error!(kvrpcpb::RawGetKeyTtlResponse);
 has_str_error!(kvrpcpb::ImportResponse);
 has_str_error!(kvrpcpb::DeleteRangeResponse);
 
+impl HasError for coprocessor::Response {
+    fn error(&mut self) -> Option<Error> {
+        if self.get_other_error().is_empty() {
+            None
+        } else {
+            Some(Error::KvError {
+                message: self.take_other_error(),
+            })
+        }
+    }
+}
+
+impl HasError for kvrpcpb::SplitRegionResponse {
+    fn error(&mut self) -> Option<Error> {
+        None
+    }
+}
+
 impl HasError for kvrpcpb::ScanResponse {
     fn error(&mut self) -> Option<Error> {
         extract_errors(self.pairs.iter_mut().map(|pair| pair.error.take()))
@@ -176,6 +209,10 @@ impl<T: HasRegionError, E> HasRegionError for Result<T, E> {
     fn region_error(&mut self) -> Option<Error> {
         self.as_mut().ok().and_then(|t| t.region_error())
     }
+
+    fn peek_region_error(&self) -> Option<&errorpb::Error> {
+        self.as_ref().ok().and_then(|t| t.peek_region_error())
+    }
 }
 
 impl<T: HasRegionError> HasRegionError for Vec<T> {
@@ -188,6 +225,10 @@ impl<T: HasRegionError> HasRegionError for Vec<T> {
 
         None
     }
+
+    fn peek_region_error(&self) -> Option<&errorpb::Error> {
+        self.iter().find_map(|t| t.peek_region_error())
+    }
 }
 
 fn extract_errors(error_iter: impl Iterator<Item = Option<kvrpcpb::KeyError>>) -> Option<Error> {
@@ -205,13 +246,14 @@ fn extract_errors(error_iter: impl Iterator<Item = Option<kvrpcpb::KeyError>>) -
 mod test {
     use super::HasError;
     use tikv_client_common::{internal_err, Error};
-    use tikv_client_proto::kvrpcpb;
+    use tikv_client_proto::{deadlock::WaitForEntry, kvrpcpb};
     #[test]
     fn result_haslocks() {
         let mut resp: Result<_, Error> = Ok(kvrpcpb::CommitResponse {
             region_error: None,
             error: None,
             commit_version: 0,
+            exec_details_v2: None,
         });
         assert!(resp.error().is_none());
 
@@ -229,10 +271,77 @@ mod test {
                 commit_ts_too_large: None,
             }),
             commit_version: 0,
+            exec_details_v2: None,
         });
         assert!(resp.error().is_some());
 
         let mut resp: Result<kvrpcpb::CommitResponse, _> = Err(internal_err!("some error"));
         assert!(resp.error().is_some());
     }
+
+    #[test]
+    fn lock_conflict_error() {
+        let mut resp = kvrpcpb::CommitResponse {
+            error: Some(kvrpcpb::KeyError {
+                locked: Some(kvrpcpb::LockInfo {
+                    key: b"key".to_vec(),
+                    primary_lock: b"primary".to_vec(),
+                    lock_version: 42,
+                    lock_ttl: 3000,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match resp.error() {
+            Some(Error::LockConflict {
+                key,
+                lock_owner_start_ts,
+                primary,
+                ttl,
+            }) => {
+                assert_eq!(key, b"key".to_vec());
+                assert_eq!(lock_owner_start_ts, 42);
+                assert_eq!(primary, b"primary".to_vec());
+                assert_eq!(ttl, 3000);
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        resp.error = Some(kvrpcpb::KeyError {
+            deadlock: Some(kvrpcpb::Deadlock {
+                lock_ts: 7,
+                lock_key: b"key".to_vec(),
+                deadlock_key_hash: 1,
+                wait_chain: vec![
+                    WaitForEntry {
+                        txn: 5,
+                        wait_for_txn: 7,
+                        key_hash: 1,
+                        key: b"key".to_vec(),
+                    },
+                    WaitForEntry {
+                        txn: 7,
+                        wait_for_txn: 5,
+                        key_hash: 2,
+                        key: b"other".to_vec(),
+                    },
+                ],
+            }),
+            ..Default::default()
+        });
+        match resp.error() {
+            Some(Error::Deadlock {
+                lock_ts,
+                wait_chain,
+                ..
+            }) => {
+                assert_eq!(lock_ts, 7);
+                assert_eq!(wait_chain.len(), 2);
+                assert_eq!(wait_chain[1].key, b"other".to_vec());
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
 }
@@ -2,12 +2,18 @@
 
 mod client;
 mod errors;
+mod limiter;
+mod metadata;
 mod request;
+mod stats;
 
 #[doc(inline)]
 pub use crate::{
     client::{KvClient, KvConnect, TikvConnect},
     errors::{HasError, HasRegionError},
+    limiter::{ConcurrencyLimiter, LimiterPermit},
+    metadata::RequestMetadata,
     request::Request,
+    stats::StoreStats,
 };
 pub use tikv_client_common::{security::SecurityManager, Error, Result};
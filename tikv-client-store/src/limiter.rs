@@ -0,0 +1,178 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use futures::channel::oneshot;
+use std::{collections::VecDeque, sync::Mutex};
+
+// The limit stores start with, high enough not to throttle a store which isn't overloaded.
+const MAX_LIMIT: f64 = 1024.0;
+const MIN_LIMIT: f64 = 1.0;
+// How much the limit is cut when a store reports it is busy.
+const DECREASE_RATIO: f64 = 0.5;
+
+/// Limits the requests in flight to a store, adapting the limit to the backpressure of the store
+/// (AIMD).
+///
+/// The limit is halved when the store reports it is busy, and grows back by one for about every
+/// limit of requests which succeed, so the client backs off an overloaded store quickly and
+/// recovers as soon as the store does. Requests over the limit wait for a request in flight to
+/// finish.
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: f64,
+    in_flight: usize,
+    // Requests waiting for a slot, which is handed over when a request finishes.
+    waiters: VecDeque<oneshot::Sender<()>>,
+}
+
+impl Default for ConcurrencyLimiter {
+    fn default() -> ConcurrencyLimiter {
+        ConcurrencyLimiter {
+            state: Mutex::new(State {
+                limit: MAX_LIMIT,
+                in_flight: 0,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    /// Wait until a request can be sent within the limit.
+    pub async fn acquire(&self) -> LimiterPermit<'_> {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            if state.in_flight < state.limit as usize {
+                state.in_flight += 1;
+                return LimiterPermit { limiter: self };
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.waiters.push_back(sender);
+            receiver
+        };
+        let mut waiting = Waiting {
+            limiter: self,
+            receiver,
+        };
+        // Senders are only dropped with a slot handed over, or with the limiter, which outlives
+        // the borrow.
+        let _ = (&mut waiting.receiver).await;
+        LimiterPermit { limiter: self }
+    }
+
+    /// The current limit of requests in flight.
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit as usize
+    }
+
+    /// Release a slot, adapting the limit to whether the store reported it is busy, unless the
+    /// request was cancelled.
+    fn release(&self, busy: Option<bool>) {
+        let mut state = self.state.lock().unwrap();
+        if busy == Some(true) {
+            // Cut the limit from the number of requests actually in flight, so a store which is
+            // busy with fewer requests than the limit is relieved right away.
+            let limit = state.limit.min(state.in_flight as f64);
+            state.limit = (limit * DECREASE_RATIO).max(MIN_LIMIT);
+            log::debug!(
+                "store is busy, limiting requests in flight to {}",
+                state.limit as usize
+            );
+        } else if busy == Some(false) {
+            state.limit = (state.limit + 1.0 / state.limit).min(MAX_LIMIT);
+        }
+        // Hand the slot over to the next waiter, if the limit allows.
+        if state.in_flight <= state.limit as usize {
+            while let Some(waiter) = state.waiters.pop_front() {
+                if waiter.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        state.in_flight -= 1;
+    }
+}
+
+/// A slot for a request in flight, which is released when the permit is dropped.
+pub struct LimiterPermit<'a> {
+    limiter: &'a ConcurrencyLimiter,
+}
+
+impl LimiterPermit<'_> {
+    /// Release the slot, adapting the limit to whether the store reported it is busy.
+    pub fn release(self, busy: bool) {
+        self.limiter.release(Some(busy));
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for LimiterPermit<'_> {
+    fn drop(&mut self) {
+        // The request was cancelled, which says nothing about the store.
+        self.limiter.release(None);
+    }
+}
+
+/// Waits for a slot, giving it back if the wait is cancelled after the slot was handed over.
+struct Waiting<'a> {
+    limiter: &'a ConcurrencyLimiter,
+    receiver: oneshot::Receiver<()>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if let Ok(Some(())) = self.receiver.try_recv() {
+            drop(LimiterPermit {
+                limiter: self.limiter,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{executor::block_on, FutureExt};
+
+    #[test]
+    fn test_aimd() {
+        let limiter = ConcurrencyLimiter::default();
+        assert_eq!(limiter.limit(), 1024);
+        let permits: Vec<_> = (0..8).map(|_| block_on(limiter.acquire())).collect();
+        let mut permits = permits.into_iter();
+        permits.next().unwrap().release(true);
+        // Cut from the 8 requests in flight.
+        assert_eq!(limiter.limit(), 4);
+        permits.next().unwrap().release(true);
+        assert_eq!(limiter.limit(), 2);
+
+        // 6 requests are still in flight, over the limit.
+        let mut waiting = Box::pin(limiter.acquire());
+        assert!((&mut waiting).now_or_never().is_none());
+        for permit in permits.by_ref().take(3) {
+            permit.release(false);
+        }
+        assert!((&mut waiting).now_or_never().is_none());
+        // The limit grew past 3, so finishing one of the 3 requests in flight lets another in.
+        assert_eq!(limiter.limit(), 3);
+        permits.next().unwrap().release(false);
+        let permit = waiting.now_or_never().unwrap();
+        permit.release(false);
+        drop(permits);
+        assert_eq!(limiter.state.lock().unwrap().in_flight, 0);
+
+        // A cancelled wait doesn't leak its slot.
+        let permits: Vec<_> = (0..limiter.limit())
+            .map(|_| block_on(limiter.acquire()))
+            .collect();
+        let mut waiting = Box::pin(limiter.acquire());
+        assert!((&mut waiting).now_or_never().is_none());
+        drop(permits);
+        drop(waiting);
+        assert_eq!(limiter.state.lock().unwrap().in_flight, 0);
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use crate::Result;
+use grpcio::{CallOption, MetadataBuilder};
+use std::{sync::Arc, time::Duration};
+
+/// Custom gRPC metadata sent with requests to TiKV, e.g. a tenant or correlation id, so that
+/// server-side logging and middleboxes can attribute traffic.
+///
+/// Keys are made of ASCII letters, digits, `-`, `_` and `.`, and are sent in lowercase. Values
+/// are printable ASCII. Invalid entries fail the requests they are sent with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestMetadata {
+    entries: Arc<Vec<(String, String)>>,
+}
+
+impl RequestMetadata {
+    pub fn new() -> RequestMetadata {
+        RequestMetadata::default()
+    }
+
+    /// Add an entry. A key may be added more than once, in which case every value is sent.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        Arc::make_mut(&mut self.entries).push((key.into(), value.into()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// The options of a call carrying the metadata.
+    pub(crate) fn call_option(&self, timeout: Duration) -> Result<CallOption> {
+        let option = CallOption::default().timeout(timeout);
+        if self.is_empty() {
+            return Ok(option);
+        }
+        let mut headers = MetadataBuilder::with_capacity(self.entries.len());
+        for (key, value) in self.iter() {
+            headers.add_str(key, value)?;
+        }
+        Ok(option.headers(headers.build()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_insert() {
+        let mut metadata = RequestMetadata::new();
+        let shared = metadata.clone();
+        metadata.insert("tenant-id", "42");
+        metadata.insert("x-correlation-id", "abc");
+        assert!(shared.is_empty());
+        assert_eq!(
+            metadata.iter().collect::<Vec<_>>(),
+            vec![("tenant-id", "42"), ("x-correlation-id", "abc")]
+        );
+    }
+}
@@ -4,7 +4,7 @@ use crate::{Error, Result};
 use async_trait::async_trait;
 use grpcio::CallOption;
 use std::any::Any;
-use tikv_client_proto::{kvrpcpb, tikvpb::TikvClient};
+use tikv_client_proto::{coprocessor, kvrpcpb, tikvpb::TikvClient};
 
 #[async_trait]
 pub trait Request: Any + Sync + Send + 'static {
@@ -16,8 +16,11 @@ pub trait Request: Any + Sync + Send + 'static {
 
 macro_rules! impl_request {
     ($name: ident, $fun: ident, $label: literal) => {
+        impl_request!(kvrpcpb::$name, $fun, $label);
+    };
+    ($module: ident :: $name: ident, $fun: ident, $label: literal) => {
         #[async_trait]
-        impl Request for kvrpcpb::$name {
+        impl Request for $module::$name {
             async fn dispatch(
                 &self,
                 client: &TikvClient,
@@ -66,6 +69,16 @@ impl_request!(
     raw_delete_range_async_opt,
     "raw_delete_range"
 );
+impl_request!(
+    RawCasRequest,
+    raw_compare_and_swap_async_opt,
+    "raw_compare_and_swap"
+);
+impl_request!(
+    RawGetKeyTtlRequest,
+    raw_get_key_ttl_async_opt,
+    "raw_get_key_ttl"
+);
 impl_request!(GetRequest, kv_get_async_opt, "kv_get");
 impl_request!(ScanRequest, kv_scan_async_opt, "kv_scan");
 impl_request!(PrewriteRequest, kv_prewrite_async_opt, "kv_prewrite");
@@ -88,6 +101,7 @@ impl_request!(
     "kv_resolve_lock"
 );
 impl_request!(ScanLockRequest, kv_scan_lock_async_opt, "kv_scan_lock");
+impl_request!(SplitRegionRequest, split_region_async_opt, "split_region");
 impl_request!(
     PessimisticLockRequest,
     kv_pessimistic_lock_async_opt,
@@ -114,3 +128,4 @@ impl_request!(
     kv_delete_range_async_opt,
     "kv_delete_range"
 );
+impl_request!(coprocessor::Request, coprocessor_async_opt, "coprocessor");
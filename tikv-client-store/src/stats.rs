@@ -0,0 +1,102 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+use std::{sync::Mutex, time::Duration};
+
+// The weight of the latest request in the moving averages. Roughly the last ten requests
+// dominate the averages.
+const EWMA_WEIGHT: f64 = 0.2;
+
+/// Statistics of the requests sent to a TiKV store by a client.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StoreStats {
+    requests: u64,
+    errors: u64,
+    error_rate: f64,
+    latency: Duration,
+    pub(crate) concurrency_limit: usize,
+}
+
+impl StoreStats {
+    /// The number of requests sent.
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// The number of requests which failed to get a response. Errors reported in responses, such
+    /// as region errors, are not counted.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// The exponentially weighted moving average of the rate of requests failing, between 0 and
+    /// 1, which follows the recent health of the store.
+    pub fn error_rate(&self) -> f64 {
+        self.error_rate
+    }
+
+    /// The exponentially weighted moving average of the latency of requests.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// The limit of requests in flight to the store, which is lowered while the store reports it
+    /// is busy.
+    pub fn concurrency_limit(&self) -> usize {
+        self.concurrency_limit
+    }
+
+    fn record(&mut self, latency: Duration, ok: bool) {
+        let error = if ok { 0.0 } else { 1.0 };
+        if self.requests == 0 {
+            self.error_rate = error;
+            self.latency = latency;
+        } else {
+            self.error_rate += EWMA_WEIGHT * (error - self.error_rate);
+            self.latency = self
+                .latency
+                .mul_f64(1.0 - EWMA_WEIGHT)
+                .saturating_add(latency.mul_f64(EWMA_WEIGHT));
+        }
+        self.requests += 1;
+        if !ok {
+            self.errors += 1;
+        }
+    }
+}
+
+/// Records the statistics of a store, shared by the clones of its client.
+#[derive(Debug, Default)]
+pub(crate) struct StatsRecorder(Mutex<StoreStats>);
+
+impl StatsRecorder {
+    pub fn record(&self, latency: Duration, ok: bool) {
+        self.0.lock().unwrap().record(latency, ok);
+    }
+
+    pub fn get(&self) -> StoreStats {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let recorder = StatsRecorder::default();
+        recorder.record(Duration::from_millis(10), true);
+        let stats = recorder.get();
+        assert_eq!(stats.requests(), 1);
+        assert_eq!(stats.errors(), 0);
+        assert_eq!(stats.error_rate(), 0.0);
+        assert_eq!(stats.latency(), Duration::from_millis(10));
+
+        recorder.record(Duration::from_millis(60), false);
+        let stats = recorder.get();
+        assert_eq!(stats.requests(), 2);
+        assert_eq!(stats.errors(), 1);
+        assert!((stats.error_rate() - 0.2).abs() < 1e-9);
+        assert_eq!(stats.latency(), Duration::from_millis(20));
+    }
+}